@@ -86,9 +86,15 @@ pub enum Subcommand {
 	/// Export the state of a given block into a chain spec.
 	ExportState(sc_cli::ExportStateCmd),
 
+	/// Export a snapshot of the state of a given block.
+	ExportSnapshot(sc_cli::ExportSnapshotCmd),
+
 	/// Import blocks.
 	ImportBlocks(sc_cli::ImportBlocksCmd),
 
+	/// Seed an empty database with a state snapshot.
+	ImportSnapshot(sc_cli::ImportSnapshotCmd),
+
 	/// Remove the whole chain.
 	PurgeChain(sc_cli::PurgeChainCmd),
 
@@ -97,4 +103,11 @@ pub enum Subcommand {
 
 	/// Db meta columns information.
 	ChainInfo(sc_cli::ChainInfoCmd),
+
+	/// Check the integrity of the database.
+	CheckDb(sc_cli::CheckDbCmd),
+
+	/// Database management cli utilities.
+	#[clap(subcommand)]
+	Db(sc_cli::DbSubcommand),
 }
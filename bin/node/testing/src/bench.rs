@@ -392,6 +392,8 @@ impl BenchDb {
 			state_pruning: Some(PruningMode::ArchiveAll),
 			source: database_type.into_settings(dir.into()),
 			blocks_pruning: sc_client_db::BlocksPruning::All,
+			blocks_retention: Default::default(),
+			changes_index: false,
 		};
 		let task_executor = TaskExecutor::new();
 
@@ -22,6 +22,7 @@ use crate::{
 	blockchain::{well_known_cache_keys, Backend as BlockchainBackend},
 	UsageInfo,
 };
+use codec::{Decode, Encode};
 use parking_lot::RwLock;
 use sp_blockchain;
 use sp_consensus::BlockOrigin;
@@ -303,6 +304,22 @@ pub trait AuxStore {
 	fn get_aux(&self, key: &[u8]) -> sp_blockchain::Result<Option<Vec<u8>>>;
 }
 
+/// Provides storage for the state downloaded by a state sync.
+///
+/// The downloaded key ranges are committed as numbered chunks as they arrive, so that a state sync
+/// interrupted by a restart doesn't have to download them again. The chunks are removed once the
+/// whole state is imported.
+pub trait StateSyncStore {
+	/// Insert the chunk with the given index, replacing any previous one.
+	fn insert_state_sync_chunk(&self, index: u32, chunk: &[u8]) -> sp_blockchain::Result<()>;
+
+	/// Query the chunk with the given index.
+	fn state_sync_chunk(&self, index: u32) -> sp_blockchain::Result<Option<Vec<u8>>>;
+
+	/// Remove the chunks with an index lower than `count`.
+	fn remove_state_sync_chunks(&self, count: u32) -> sp_blockchain::Result<()>;
+}
+
 /// An `Iterator` that iterates keys in a given block under a prefix.
 pub struct KeyIterator<'a, State, Block> {
 	state: State,
@@ -431,6 +448,60 @@ pub trait StorageProvider<Block: BlockT, B: Backend<Block>> {
 		child_info: &ChildInfo,
 		key: &StorageKey,
 	) -> sp_blockchain::Result<Option<Block::Hash>>;
+
+	/// Returns the index of the storage keys modified by each block, if the backend maintains
+	/// one.
+	fn changes_index(&self) -> Option<&dyn ChangesIndex<Block>>;
+}
+
+/// Storage keys modified by a block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode)]
+pub struct BlockChanges {
+	/// Modified top-level keys.
+	pub top: Vec<Vec<u8>>,
+	/// Modified child keys, by storage key of the child trie.
+	pub children: Vec<(Vec<u8>, Vec<Vec<u8>>)>,
+}
+
+impl BlockChanges {
+	/// Whether `key` of the child trie `child_info`, or of the top-level trie, is modified.
+	pub fn contains(&self, child_info: Option<&ChildInfo>, key: &[u8]) -> bool {
+		match child_info {
+			None => self.top.iter().any(|k| k == key),
+			Some(child_info) => self
+				.children
+				.iter()
+				.filter(|(storage_key, _)| storage_key == child_info.storage_key())
+				.any(|(_, keys)| keys.iter().any(|k| k == key)),
+		}
+	}
+}
+
+/// Index of the storage keys modified by each block, speeding up historical storage queries.
+///
+/// Only the blocks imported with their state changes after the index was enabled are covered,
+/// see `indexed_from`. Keys written with their previous value may be reported as modified.
+pub trait ChangesIndex<Block: BlockT>: Send + Sync {
+	/// Number of the first block covered by the index. The blocks before it may be missing from
+	/// the index.
+	fn indexed_from(&self) -> sp_blockchain::Result<NumberFor<Block>>;
+
+	/// Keys modified by the block with the given hash, if the block is indexed. The modified
+	/// keys of blocks whose body was pruned are not kept.
+	fn block_changes(&self, hash: &Block::Hash) -> sp_blockchain::Result<Option<BlockChanges>>;
+
+	/// Numbers of the finalized blocks in `first..=last` modifying `key` of the child trie
+	/// `child_info`, or of the top-level trie, in ascending order.
+	///
+	/// This takes time proportional to the number of changes of the key after `first`. The
+	/// changes by blocks that left the block pruning window are not listed.
+	fn key_changes(
+		&self,
+		first: NumberFor<Block>,
+		last: NumberFor<Block>,
+		child_info: Option<&ChildInfo>,
+		key: &[u8],
+	) -> sp_blockchain::Result<Vec<NumberFor<Block>>>;
 }
 
 /// Client backend.
@@ -443,7 +514,7 @@ pub trait StorageProvider<Block: BlockT, B: Backend<Block>> {
 ///
 /// The same applies for live `BlockImportOperation`s: while an import operation building on a
 /// parent `P` is alive, the state for `P` should not be pruned.
-pub trait Backend<Block: BlockT>: AuxStore + Send + Sync {
+pub trait Backend<Block: BlockT>: AuxStore + StateSyncStore + Send + Sync {
 	/// Associated block insertion operation type.
 	type BlockImportOperation: BlockImportOperation<Block, State = Self::State>;
 	/// Associated blockchain backend type.
@@ -511,6 +582,11 @@ pub trait Backend<Block: BlockT>: AuxStore + Send + Sync {
 	/// Returns state backend with post-state of given block.
 	fn state_at(&self, block: BlockId<Block>) -> sp_blockchain::Result<Self::State>;
 
+	/// Returns the index of the storage keys modified by each block, if it is maintained.
+	fn changes_index(&self) -> Option<&dyn ChangesIndex<Block>> {
+		None
+	}
+
 	/// Attempts to revert the chain by `n` blocks. If `revert_finalized` is set it will attempt to
 	/// revert past any finalized block, this is unsafe and can potentially leave the node in an
 	/// inconsistent state. All blocks higher than the best block are also reverted and not counting
@@ -527,6 +603,15 @@ pub trait Backend<Block: BlockT>: AuxStore + Send + Sync {
 	/// Discard non-best, unfinalized leaf block.
 	fn remove_leaf_block(&self, hash: &Block::Hash) -> sp_blockchain::Result<()>;
 
+	/// Pin the block, so that its state and body are kept even if they would otherwise be pruned,
+	/// until [`Self::unpin_block`] has been called as many times as this function.
+	///
+	/// Only what is still available when the block is pinned is kept.
+	fn pin_block(&self, hash: Block::Hash) -> sp_blockchain::Result<()>;
+
+	/// Release a pin taken with [`Self::pin_block`].
+	fn unpin_block(&self, hash: Block::Hash);
+
 	/// Insert auxiliary data into key-value store.
 	fn insert_aux<
 		'a,
@@ -556,6 +641,9 @@ pub trait Backend<Block: BlockT>: AuxStore + Send + Sync {
 
 	/// Tells whether the backend requires full-sync mode.
 	fn requires_full_sync(&self) -> bool;
+
+	/// Tells whether the backend keeps the bodies and the states of all blocks.
+	fn is_archive(&self) -> bool;
 }
 
 /// Mark for all Backend implementations, that are making use of state data, stored locally.
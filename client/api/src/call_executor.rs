@@ -106,4 +106,16 @@ pub trait CallExecutor<B: BlockT>: RuntimeVersionOf {
 		method: &str,
 		call_data: &[u8],
 	) -> Result<(Vec<u8>, StorageProof), sp_blockchain::Error>;
+
+	/// Check a proof of the execution of the given `method` on top of the state with root
+	/// `state_root`, as produced by [`Self::prove_execution`], and return the result of the call.
+	///
+	/// The runtime code is read from the proof.
+	fn check_execution_proof(
+		&self,
+		state_root: B::Hash,
+		method: &str,
+		call_data: &[u8],
+		proof: StorageProof,
+	) -> Result<Vec<u8>, sp_blockchain::Error>;
 }
@@ -637,6 +637,7 @@ where
 	states: RwLock<HashMap<Block::Hash, InMemoryBackend<HashFor<Block>>>>,
 	blockchain: Blockchain<Block>,
 	import_lock: RwLock<()>,
+	state_sync_chunks: RwLock<HashMap<u32, Vec<u8>>>,
 }
 
 impl<Block: BlockT> Backend<Block>
@@ -649,6 +650,7 @@ where
 			states: RwLock::new(HashMap::new()),
 			blockchain: Blockchain::new(),
 			import_lock: Default::default(),
+			state_sync_chunks: Default::default(),
 		}
 	}
 }
@@ -676,6 +678,25 @@ where
 	}
 }
 
+impl<Block: BlockT> backend::StateSyncStore for Backend<Block>
+where
+	Block::Hash: Ord,
+{
+	fn insert_state_sync_chunk(&self, index: u32, chunk: &[u8]) -> sp_blockchain::Result<()> {
+		self.state_sync_chunks.write().insert(index, chunk.to_vec());
+		Ok(())
+	}
+
+	fn state_sync_chunk(&self, index: u32) -> sp_blockchain::Result<Option<Vec<u8>>> {
+		Ok(self.state_sync_chunks.read().get(&index).cloned())
+	}
+
+	fn remove_state_sync_chunks(&self, count: u32) -> sp_blockchain::Result<()> {
+		self.state_sync_chunks.write().retain(|index, _| *index >= count);
+		Ok(())
+	}
+}
+
 impl<Block: BlockT> backend::Backend<Block> for Backend<Block>
 where
 	Block::Hash: Ord,
@@ -792,6 +813,13 @@ where
 		Ok(())
 	}
 
+	fn pin_block(&self, _hash: Block::Hash) -> sp_blockchain::Result<()> {
+		// Nothing is ever pruned from the in-memory backend.
+		Ok(())
+	}
+
+	fn unpin_block(&self, _hash: Block::Hash) {}
+
 	fn get_import_lock(&self) -> &RwLock<()> {
 		&self.import_lock
 	}
@@ -799,6 +827,10 @@ where
 	fn requires_full_sync(&self) -> bool {
 		false
 	}
+
+	fn is_archive(&self) -> bool {
+		true
+	}
 }
 
 impl<Block: BlockT> backend::LocalBackend<Block> for Backend<Block> where Block::Hash: Ord {}
@@ -25,6 +25,7 @@ pub mod client;
 pub mod execution_extensions;
 pub mod in_mem;
 pub mod leaves;
+pub mod light;
 pub mod notifications;
 pub mod proof_provider;
 
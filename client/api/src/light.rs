@@ -0,0 +1,73 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Light client data fetching.
+//!
+//! A light client only stores the headers of the chain. The state is read from full nodes on
+//! demand, and the proofs they return are checked against the state roots of these headers.
+
+use futures::future::BoxFuture;
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_runtime::traits::{Block as BlockT, Header as HeaderT};
+use sp_state_machine::StorageProof;
+use std::collections::HashMap;
+
+/// Remote storage read request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteReadRequest<Header: HeaderT> {
+	/// Header of the block to read the storage at.
+	pub header: Header,
+	/// Storage keys to read.
+	pub keys: Vec<Vec<u8>>,
+}
+
+/// Remote runtime call request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteCallRequest<Header: HeaderT> {
+	/// Header of the block to call the runtime at.
+	pub header: Header,
+	/// Runtime method to call.
+	pub method: String,
+	/// Encoded call arguments.
+	pub call_data: Vec<u8>,
+}
+
+/// Reads the state from full nodes, checking the proofs they return.
+pub trait RemoteBackend<Block: BlockT>: Send + Sync {
+	/// Read storage values. The keys missing in the storage are mapped to `None`.
+	fn remote_read(
+		&self,
+		request: RemoteReadRequest<Block::Header>,
+	) -> BoxFuture<'static, ClientResult<HashMap<Vec<u8>, Option<Vec<u8>>>>>;
+
+	/// Call a runtime method and return its encoded result.
+	fn remote_call(
+		&self,
+		request: RemoteCallRequest<Block::Header>,
+	) -> BoxFuture<'static, ClientResult<Vec<u8>>>;
+}
+
+/// Check a storage read proof against the state root of `header`.
+pub fn check_read_proof<Header: HeaderT>(
+	header: &Header,
+	keys: &[Vec<u8>],
+	proof: StorageProof,
+) -> ClientResult<HashMap<Vec<u8>, Option<Vec<u8>>>> {
+	sp_state_machine::read_proof_check::<Header::Hashing, _>(*header.state_root(), proof, keys)
+		.map_err(ClientError::from_state)
+}
@@ -23,15 +23,15 @@
 use parking_lot::RwLock;
 use std::sync::Arc;
 
-use sc_rpc::SubscriptionTaskExecutor;
+use sc_rpc::{spawn_subscription_task, utils::pipe_from_stream, SubscriptionTaskExecutor};
 use sp_runtime::traits::Block as BlockT;
 
 use futures::{task::SpawnError, FutureExt, StreamExt};
 use jsonrpsee::{
-	core::{async_trait, Error as JsonRpseeError, RpcResult},
+	core::{async_trait, RpcResult},
 	proc_macros::rpc,
-	types::{error::CallError, ErrorObject, SubscriptionResult},
-	SubscriptionSink,
+	types::{ErrorObject, ErrorObjectOwned},
+	PendingSubscriptionSink,
 };
 use log::warn;
 
@@ -67,15 +67,11 @@ impl From<Error> for ErrorCode {
 	}
 }
 
-impl From<Error> for JsonRpseeError {
+impl From<Error> for ErrorObjectOwned {
 	fn from(error: Error) -> Self {
 		let message = error.to_string();
 		let code = ErrorCode::from(error);
-		JsonRpseeError::Call(CallError::Custom(ErrorObject::owned(
-			code as i32,
-			message,
-			None::<()>,
-		)))
+		ErrorObject::owned(code as i32, message, None::<()>)
 	}
 }
 
@@ -136,18 +132,13 @@ impl<Block> BeefyApiServer<notification::EncodedVersionedFinalityProof, Block::H
 where
 	Block: BlockT,
 {
-	fn subscribe_justifications(&self, mut sink: SubscriptionSink) -> SubscriptionResult {
+	fn subscribe_justifications(&self, pending: PendingSubscriptionSink) {
 		let stream = self
 			.finality_proof_stream
 			.subscribe()
 			.map(|vfp| notification::EncodedVersionedFinalityProof::new::<Block>(vfp));
 
-		let fut = async move {
-			sink.pipe_from_stream(stream).await;
-		};
-
-		self.executor.spawn("substrate-rpc-subscription", Some("rpc"), fut.boxed());
-		Ok(())
+		spawn_subscription_task(&self.executor, pipe_from_stream(pending, stream));
 	}
 
 	async fn latest_finalized(&self) -> RpcResult<Block::Hash> {
@@ -170,7 +161,7 @@ mod tests {
 	};
 	use beefy_primitives::{known_payload_ids, Payload, SignedCommitment};
 	use codec::{Decode, Encode};
-	use jsonrpsee::{types::EmptyParams, RpcModule};
+	use jsonrpsee::{core::EmptyServerParams, RpcModule};
 	use sp_runtime::traits::{BlakeTwo256, Hash};
 	use substrate_test_runtime_client::runtime::Block;
 
@@ -196,10 +187,10 @@ mod tests {
 	async fn uninitialized_rpc_handler() {
 		let (rpc, _) = setup_io_handler();
 		let request = r#"{"jsonrpc":"2.0","method":"beefy_getFinalizedHead","params":[],"id":1}"#;
-		let expected_response = r#"{"jsonrpc":"2.0","error":{"code":1,"message":"BEEFY RPC endpoint not ready"},"id":1}"#.to_string();
-		let (response, _) = rpc.raw_json_request(&request).await.unwrap();
+		let expected_response = r#"{"jsonrpc":"2.0","id":1,"error":{"code":1,"message":"BEEFY RPC endpoint not ready"}}"#.to_string();
+		let (response, _) = rpc.raw_json_request(&request, 1).await.unwrap();
 
-		assert_eq!(expected_response, response.result);
+		assert_eq!(expected_response, response.get());
 	}
 
 	#[tokio::test]
@@ -215,22 +206,22 @@ mod tests {
 		let request = r#"{"jsonrpc":"2.0","method":"beefy_getFinalizedHead","params":[],"id":1}"#;
 		let expected = "{\
 			\"jsonrpc\":\"2.0\",\
-			\"result\":\"0x2f0039e93a27221fcf657fb877a1d4f60307106113e885096cb44a461cd0afbf\",\
-			\"id\":1\
+			\"id\":1,\
+			\"result\":\"0x2f0039e93a27221fcf657fb877a1d4f60307106113e885096cb44a461cd0afbf\"\
 		}"
 		.to_string();
 		let not_ready = "{\
 			\"jsonrpc\":\"2.0\",\
-			\"error\":{\"code\":1,\"message\":\"BEEFY RPC endpoint not ready\"},\
-			\"id\":1\
+			\"id\":1,\
+			\"error\":{\"code\":1,\"message\":\"BEEFY RPC endpoint not ready\"}\
 		}"
 		.to_string();
 
 		let deadline = std::time::Instant::now() + std::time::Duration::from_secs(2);
 		while std::time::Instant::now() < deadline {
-			let (response, _) = io.raw_json_request(request).await.expect("RPC requests work");
-			if response.result != not_ready {
-				assert_eq!(response.result, expected);
+			let (response, _) = io.raw_json_request(request, 1).await.expect("RPC requests work");
+			if response.get() != not_ready {
+				assert_eq!(response.get(), expected);
 				// Success
 				return
 			}
@@ -247,7 +238,7 @@ mod tests {
 		let (rpc, _) = setup_io_handler();
 		// Subscribe call.
 		let _sub = rpc
-			.subscribe("beefy_subscribeJustifications", EmptyParams::new())
+			.subscribe_unbounded("beefy_subscribeJustifications", EmptyServerParams::new())
 			.await
 			.unwrap();
 
@@ -255,12 +246,13 @@ mod tests {
 		let (response, _) = rpc
 			.raw_json_request(
 				r#"{"jsonrpc":"2.0","method":"beefy_unsubscribeJustifications","params":["FOO"],"id":1}"#,
+				1,
 			)
 			.await
 			.unwrap();
-		let expected = r#"{"jsonrpc":"2.0","result":false,"id":1}"#;
+		let expected = r#"{"jsonrpc":"2.0","id":1,"result":false}"#;
 
-		assert_eq!(response.result, expected);
+		assert_eq!(response.get(), expected);
 	}
 
 	fn create_finality_proof() -> BeefyVersionedFinalityProof<Block> {
@@ -281,7 +273,7 @@ mod tests {
 
 		// Subscribe
 		let mut sub = rpc
-			.subscribe("beefy_subscribeJustifications", EmptyParams::new())
+			.subscribe_unbounded("beefy_subscribeJustifications", EmptyServerParams::new())
 			.await
 			.unwrap();
 
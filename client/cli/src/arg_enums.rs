@@ -307,6 +307,8 @@ pub enum SyncMode {
 	FastUnsafe,
 	/// Prove finality and download the latest state.
 	Warp,
+	/// Download and verify headers only. Read the state from full nodes on demand.
+	Light,
 }
 
 impl Into<sc_network::config::SyncMode> for SyncMode {
@@ -318,6 +320,7 @@ impl Into<sc_network::config::SyncMode> for SyncMode {
 			SyncMode::FastUnsafe =>
 				sc_network::config::SyncMode::Fast { skip_proofs: true, storage_chain_mode: false },
 			SyncMode::Warp => sc_network::config::SyncMode::Warp,
+			SyncMode::Light => sc_network::config::SyncMode::Light,
 		}
 	}
 }
@@ -0,0 +1,111 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{CliConfiguration, DatabaseParams, PruningParams, Result as CliResult, SharedParams};
+use sp_runtime::traits::Block as BlockT;
+use std::sync::Arc;
+
+/// The `check-db` command used to check the integrity of the database.
+#[derive(Debug, Clone, clap::Parser)]
+pub struct CheckDbCmd {
+	/// Rebuild the leaves, the children index and the lookup keys from the block headers if they
+	/// are found inconsistent, and remove the discarded entries of the non-canonical state
+	/// journal.
+	///
+	/// Without this flag the database is opened read-only.
+	#[clap(long)]
+	pub repair: bool,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub pruning_params: PruningParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+impl CheckDbCmd {
+	/// Run the `check-db` command
+	pub fn run<B>(&self, config: &sc_service::Configuration) -> CliResult<()>
+	where
+		B: BlockT,
+	{
+		let db_config = sc_client_db::DatabaseSettings {
+			trie_cache_maximum_size: config.trie_cache_maximum_size,
+			state_pruning: config.state_pruning.clone(),
+			source: config.database.clone(),
+			blocks_pruning: config.blocks_pruning,
+			blocks_retention: config.blocks_retention.clone(),
+			changes_index: config.changes_index,
+		};
+		// Without repairing, the database is only read. A RocksDB database is then opened as a
+		// secondary instance keeping its files in a temporary directory.
+		let secondary_dir = tempfile::tempdir()?;
+		let backend = if self.repair {
+			sc_service::new_db_backend::<B>(db_config)?
+		} else {
+			Arc::new(sc_client_db::Backend::<B>::open_read_only(db_config, secondary_dir.path())?)
+		};
+		let report = backend.check_database(self.repair)?;
+		for issue in &report.issues {
+			println!("{}", issue);
+		}
+		println!(
+			"Checked {} blocks and {} state entries, found {} issues",
+			report.blocks,
+			report.state_entries,
+			report.issues.len(),
+		);
+		match report.state_nodes {
+			Some(nodes) => println!("Checked {} state nodes for orphans", nodes),
+			None => println!("State nodes not checked for orphans"),
+		}
+
+		let remaining = report
+			.issues
+			.iter()
+			.filter(|issue| !(report.repaired && issue.is_repairable()))
+			.count();
+		if report.repaired {
+			println!("Repaired {} issues", report.issues.len() - remaining);
+		}
+		if remaining > 0 {
+			return Err(format!("{} database issues left", remaining).into())
+		}
+		Ok(())
+	}
+}
+
+impl CliConfiguration for CheckDbCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn pruning_params(&self) -> Option<&PruningParams> {
+		Some(&self.pruning_params)
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
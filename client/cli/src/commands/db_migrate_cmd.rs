@@ -0,0 +1,89 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2020-2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{arg_enums::Database, error, params::SharedParams, CliConfiguration};
+use clap::Parser;
+use sc_service::Configuration;
+use sp_runtime::traits::Block as BlockT;
+
+/// The `db migrate` command used to copy the database of a node to another backend.
+///
+/// The copy is verified once complete and the migration can be resumed by running the command
+/// again if it is interrupted. The source database is left untouched.
+#[derive(Debug, Clone, Parser)]
+pub struct DbMigrateCmd {
+	/// Database backend to migrate from.
+	#[clap(long, value_name = "DB", ignore_case = true, possible_values = Database::variants())]
+	pub from: Database,
+
+	/// Database backend to migrate to.
+	#[clap(long, value_name = "DB", ignore_case = true, possible_values = Database::variants())]
+	pub to: Database,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+}
+
+impl DbMigrateCmd {
+	/// Run the migrate command
+	pub fn run<B: BlockT>(&self, config: &Configuration) -> error::Result<()> {
+		let config_dir = config
+			.base_path
+			.as_ref()
+			.ok_or_else(|| error::Error::Input("Base path is required to migrate".into()))?
+			.config_dir(config.chain_spec.id());
+		let from = self.database_config(&config_dir, 0, self.from)?;
+		let to = self.database_config(&config_dir, 0, self.to)?;
+
+		match (from, to) {
+			#[cfg(feature = "rocksdb")]
+			(
+				sc_service::DatabaseSource::RocksDb { path: from, .. },
+				sc_service::DatabaseSource::ParityDb { path: to },
+			) => {
+				println!("Migrating {:?} to {:?}", from, to);
+				for column in sc_client_db::migrate_rocksdb_to_paritydb::<B>(&from, &to)? {
+					println!("{}", column);
+				}
+				println!("Migration complete. Start the node with `--database paritydb`.");
+				Ok(())
+			},
+			#[cfg(feature = "rocksdb")]
+			(
+				sc_service::DatabaseSource::ParityDb { path: from },
+				sc_service::DatabaseSource::RocksDb { path: to, .. },
+			) => {
+				println!("Migrating {:?} to {:?}", from, to);
+				for column in sc_client_db::migrate_paritydb_to_rocksdb::<B>(&from, &to)? {
+					println!("{}", column);
+				}
+				println!("Migration complete. Start the node with `--database rocksdb`.");
+				Ok(())
+			},
+			_ => Err(error::Error::Input(
+				"Only migrating between `rocksdb` and `paritydb` is supported".into(),
+			)),
+		}
+	}
+}
+
+impl CliConfiguration for DbMigrateCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+}
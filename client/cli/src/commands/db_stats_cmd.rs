@@ -0,0 +1,78 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{CliConfiguration, DatabaseParams, PruningParams, Result as CliResult, SharedParams};
+use clap::Parser;
+use sc_service::Configuration;
+use sp_runtime::traits::Block as BlockT;
+
+/// The `db stats` command used to print the size of the database columns.
+///
+/// Computing the size of a RocksDB column reads the whole column, while the size of a ParityDB
+/// column is the size of its files and comes without a key count.
+#[derive(Debug, Clone, Parser)]
+pub struct DbStatsCmd {
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub pruning_params: PruningParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+impl DbStatsCmd {
+	/// Run the stats command
+	pub fn run<B: BlockT>(&self, config: &Configuration) -> CliResult<()> {
+		let db_config = sc_client_db::DatabaseSettings {
+			trie_cache_maximum_size: config.trie_cache_maximum_size,
+			state_pruning: config.state_pruning.clone(),
+			source: config.database.clone(),
+			blocks_pruning: config.blocks_pruning,
+			blocks_retention: config.blocks_retention.clone(),
+			changes_index: config.changes_index,
+		};
+		let backend = sc_service::new_db_backend::<B>(db_config)?;
+
+		println!("{:<16} {:>16} {:>20}", "column", "keys", "bytes");
+		for (name, stats) in backend.column_stats() {
+			let keys = stats.and_then(|stats| stats.keys).map_or("-".into(), |k| k.to_string());
+			let bytes = stats.map_or("-".into(), |stats| stats.bytes.to_string());
+			println!("{:<16} {:>16} {:>20}", name, keys, bytes);
+		}
+		Ok(())
+	}
+}
+
+impl CliConfiguration for DbStatsCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn pruning_params(&self) -> Option<&PruningParams> {
+		Some(&self.pruning_params)
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
@@ -0,0 +1,109 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+	params::{BlockNumberOrHash, DatabaseParams, PruningParams, SharedParams},
+	CliConfiguration, Result as CliResult,
+};
+use log::info;
+use sc_client_api::{backend::Backend as BackendT, blockchain::HeaderBackend};
+use sp_runtime::traits::{Block as BlockT, NumberFor};
+use std::{
+	fmt::Debug,
+	fs,
+	io::{self, Write},
+	path::PathBuf,
+	str::FromStr,
+};
+
+/// The `export-snapshot` command used to export a snapshot of the state of a block.
+#[derive(Debug, Clone, clap::Parser)]
+pub struct ExportSnapshotCmd {
+	/// Output file name or stdout if unspecified.
+	#[clap(parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	/// Block hash or number.
+	///
+	/// Default is the last finalized block.
+	#[clap(long, value_name = "HASH or NUMBER")]
+	pub block: Option<BlockNumberOrHash>,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub pruning_params: PruningParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+impl ExportSnapshotCmd {
+	/// Run the `export-snapshot` command
+	pub fn run<B>(&self, config: &sc_service::Configuration) -> CliResult<()>
+	where
+		B: BlockT,
+		B::Hash: FromStr,
+		<B::Hash as FromStr>::Err: Debug,
+		NumberFor<B>: FromStr,
+		<NumberFor<B> as FromStr>::Err: Debug,
+	{
+		let db_config = sc_client_db::DatabaseSettings {
+			trie_cache_maximum_size: config.trie_cache_maximum_size,
+			state_pruning: config.state_pruning.clone(),
+			source: config.database.clone(),
+			blocks_pruning: config.blocks_pruning,
+			blocks_retention: config.blocks_retention.clone(),
+			changes_index: config.changes_index,
+		};
+		let backend = sc_service::new_db_backend::<B>(db_config)?;
+		let blockchain = backend.blockchain();
+		let hash = match &self.block {
+			Some(block) => blockchain.expect_block_hash_from_id(&block.parse()?)?,
+			None => blockchain.info().finalized_hash,
+		};
+
+		info!("Exporting snapshot of block {:?}...", hash);
+		let mut output: Box<dyn Write> = match &self.output {
+			Some(filename) => Box::new(io::BufWriter::new(fs::File::create(filename)?)),
+			None => Box::new(io::stdout()),
+		};
+		let nodes = backend.export_snapshot(hash, &mut output)?;
+		output.flush()?;
+		info!("Exported {} trie nodes", nodes);
+		Ok(())
+	}
+}
+
+impl CliConfiguration for ExportSnapshotCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn pruning_params(&self) -> Option<&PruningParams> {
+		Some(&self.pruning_params)
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
@@ -0,0 +1,90 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+	params::{DatabaseParams, PruningParams, SharedParams},
+	CliConfiguration, Result as CliResult,
+};
+use log::info;
+use sp_runtime::traits::Block as BlockT;
+use std::{
+	fs,
+	io::{self, Read},
+	path::PathBuf,
+};
+
+/// The `import-snapshot` command used to seed an empty database with a state snapshot.
+#[derive(Debug, Clone, clap::Parser)]
+pub struct ImportSnapshotCmd {
+	/// Input file or stdin if unspecified.
+	#[clap(parse(from_os_str))]
+	pub input: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub pruning_params: PruningParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+impl ImportSnapshotCmd {
+	/// Run the `import-snapshot` command
+	pub fn run<B>(&self, config: &sc_service::Configuration) -> CliResult<()>
+	where
+		B: BlockT,
+	{
+		let db_config = sc_client_db::DatabaseSettings {
+			trie_cache_maximum_size: config.trie_cache_maximum_size,
+			state_pruning: config.state_pruning.clone(),
+			source: config.database.clone(),
+			blocks_pruning: config.blocks_pruning,
+			blocks_retention: config.blocks_retention.clone(),
+			changes_index: config.changes_index,
+		};
+		let backend = sc_service::new_db_backend::<B>(db_config)?;
+
+		let mut input: Box<dyn Read> = match &self.input {
+			Some(filename) => Box::new(io::BufReader::new(fs::File::open(filename)?)),
+			None => Box::new(io::stdin()),
+		};
+		info!("Importing snapshot...");
+		let (number, hash) = backend.import_snapshot(&mut input)?;
+		info!("Imported snapshot of block #{} ({:?})", number, hash);
+		Ok(())
+	}
+}
+
+impl CliConfiguration for ImportSnapshotCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn pruning_params(&self) -> Option<&PruningParams> {
+		Some(&self.pruning_params)
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
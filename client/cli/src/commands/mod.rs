@@ -18,11 +18,17 @@
 mod build_spec_cmd;
 mod chain_info_cmd;
 mod check_block_cmd;
+mod check_db_cmd;
+mod db;
+mod db_migrate_cmd;
+mod db_stats_cmd;
 mod export_blocks_cmd;
+mod export_snapshot_cmd;
 mod export_state_cmd;
 mod generate;
 mod generate_node_key;
 mod import_blocks_cmd;
+mod import_snapshot_cmd;
 mod insert_key;
 mod inspect_key;
 mod inspect_node_key;
@@ -37,8 +43,11 @@ mod verify;
 
 pub use self::{
 	build_spec_cmd::BuildSpecCmd, chain_info_cmd::ChainInfoCmd, check_block_cmd::CheckBlockCmd,
-	export_blocks_cmd::ExportBlocksCmd, export_state_cmd::ExportStateCmd, generate::GenerateCmd,
-	generate_node_key::GenerateNodeKeyCmd, import_blocks_cmd::ImportBlocksCmd,
+	check_db_cmd::CheckDbCmd, db::DbSubcommand, db_migrate_cmd::DbMigrateCmd,
+	db_stats_cmd::DbStatsCmd, export_blocks_cmd::ExportBlocksCmd,
+	export_snapshot_cmd::ExportSnapshotCmd, export_state_cmd::ExportStateCmd,
+	generate::GenerateCmd, generate_node_key::GenerateNodeKeyCmd,
+	import_blocks_cmd::ImportBlocksCmd, import_snapshot_cmd::ImportSnapshotCmd,
 	insert_key::InsertKeyCmd, inspect_key::InspectKeyCmd, inspect_node_key::InspectNodeKeyCmd,
 	key::KeySubcommand, purge_chain_cmd::PurgeChainCmd, revert_cmd::RevertCmd, run_cmd::RunCmd,
 	sign::SignCmd, vanity::VanityCmd, verify::VerifyCmd,
@@ -28,11 +28,14 @@ use crate::{
 use clap::Parser;
 use regex::Regex;
 use sc_service::{
-	config::{BasePath, PrometheusConfig, TransactionPoolOptions},
+	config::{BasePath, PrometheusConfig, RateLimitConfig, TransactionPoolOptions},
 	ChainSpec, Role,
 };
 use sc_telemetry::TelemetryEndpoints;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{
+	net::{IpAddr, Ipv4Addr, SocketAddr},
+	num::NonZeroU32,
+};
 
 /// The `run` command used to run a node.
 #[derive(Debug, Clone, Parser)]
@@ -116,6 +119,32 @@ pub struct RunCmd {
 	#[clap(long)]
 	pub rpc_max_subscriptions_per_connection: Option<usize>,
 
+	/// Maximum number of calls in an RPC batch request.
+	///
+	/// Longer batches are rejected with an error. Unlimited by default.
+	#[clap(long, value_name = "COUNT")]
+	pub rpc_max_batch_len: Option<usize>,
+
+	/// Maximum number of RPC calls per minute from each IP address.
+	///
+	/// The calls exceeding it are answered with an error. Unlimited by default.
+	#[clap(long, value_name = "CALLS")]
+	pub rpc_rate_limit: Option<NonZeroU32>,
+
+	/// Maximum number of calls per minute of an RPC method, from all clients together.
+	///
+	/// This flag can be passed multiple times to limit several methods.
+	/// Expected format is 'METHOD=CALLS', e.g. `--rpc-method-rate-limit state_getKeysPaged=60`.
+	#[clap(long, value_name = "METHOD=CALLS", parse(try_from_str = parse_method_rate_limit))]
+	pub rpc_method_rate_limit: Vec<(String, NonZeroU32)>,
+
+	/// Identify the RPC clients by the `X-Forwarded-For` header for the rate limits.
+	///
+	/// Only use this behind a reverse proxy setting the header, otherwise clients can evade the
+	/// limits.
+	#[clap(long)]
+	pub rpc_rate_limit_trust_proxy_headers: bool,
+
 	/// Expose Prometheus exporter on all interfaces.
 	///
 	/// Default is local.
@@ -465,6 +494,18 @@ impl CliConfiguration for RunCmd {
 		Ok(self.rpc_max_subscriptions_per_connection)
 	}
 
+	fn rpc_max_batch_len(&self) -> Result<Option<usize>> {
+		Ok(self.rpc_max_batch_len)
+	}
+
+	fn rpc_rate_limit(&self) -> Result<RateLimitConfig> {
+		Ok(RateLimitConfig {
+			calls_per_ip: self.rpc_rate_limit,
+			calls_per_method: self.rpc_method_rate_limit.iter().cloned().collect(),
+			trust_proxy_headers: self.rpc_rate_limit_trust_proxy_headers,
+		})
+	}
+
 	fn ws_max_out_buffer_capacity(&self) -> Result<Option<usize>> {
 		Ok(self.ws_max_out_buffer_capacity)
 	}
@@ -576,6 +617,14 @@ fn parse_telemetry_endpoints(s: &str) -> std::result::Result<(String, u8), Telem
 	}
 }
 
+fn parse_method_rate_limit(s: &str) -> std::result::Result<(String, NonZeroU32), String> {
+	let (method, calls) = s
+		.split_once('=')
+		.ok_or_else(|| "Expected format is 'METHOD=CALLS'".to_string())?;
+	let calls = calls.parse().map_err(|e| format!("Invalid number of calls: {}", e))?;
+	Ok((method.to_string(), calls))
+}
+
 /// CORS setting
 ///
 /// The type is introduced to overcome `Option<Option<T>>` handling of `clap`.
@@ -638,4 +687,14 @@ mod tests {
 		assert!(is_node_name_valid("www.visit.me").is_err());
 		assert!(is_node_name_valid("email@domain").is_err());
 	}
+
+	#[test]
+	fn parses_method_rate_limits() {
+		assert_eq!(
+			parse_method_rate_limit("state_getKeysPaged=60"),
+			Ok(("state_getKeysPaged".to_string(), NonZeroU32::new(60).unwrap()))
+		);
+		assert!(parse_method_rate_limit("state_getKeysPaged").is_err());
+		assert!(parse_method_rate_limit("state_getKeysPaged=0").is_err());
+	}
 }
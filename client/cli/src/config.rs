@@ -19,8 +19,10 @@
 //! Configuration trait for a CLI based on substrate
 
 use crate::{
-	arg_enums::Database, error::Result, DatabaseParams, ImportParams, KeystoreParams,
-	NetworkParams, NodeKeyParams, OffchainWorkerParams, PruningParams, SharedParams, SubstrateCli,
+	arg_enums::Database,
+	error::{Error, Result},
+	DatabaseParams, ImportParams, KeystoreParams, NetworkParams, NodeKeyParams,
+	OffchainWorkerParams, PruningParams, SharedParams, SubstrateCli,
 };
 use log::warn;
 use names::{Generator, Name};
@@ -28,10 +30,10 @@ use sc_client_api::execution_extensions::ExecutionStrategies;
 use sc_service::{
 	config::{
 		BasePath, Configuration, DatabaseSource, KeystoreConfig, NetworkConfiguration,
-		NodeKeyConfig, OffchainWorkerConfig, PrometheusConfig, PruningMode, Role, RpcMethods,
-		TelemetryEndpoints, TransactionPoolOptions, WasmExecutionMethod,
+		NodeKeyConfig, OffchainWorkerConfig, PrometheusConfig, PruningMode, RateLimitConfig, Role,
+		RpcMethods, TelemetryEndpoints, TransactionPoolOptions, WasmExecutionMethod,
 	},
-	BlocksPruning, ChainSpec, TracingReceiver,
+	BlocksPruning, BlocksRetention, ChainSpec, TracingReceiver,
 };
 use sc_tracing::logging::LoggerBuilder;
 use std::{net::SocketAddr, path::PathBuf};
@@ -205,6 +207,21 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 		Ok(self.database_params().and_then(|x| x.database()))
 	}
 
+	/// Get the directory of the files of a read-only secondary database instance.
+	///
+	/// By default this is retrieved from `DatabaseParams` if it is available. Otherwise its `None`.
+	fn database_secondary_path(&self) -> Result<Option<PathBuf>> {
+		Ok(self.database_params().and_then(|x| x.database_secondary_path()))
+	}
+
+	/// Whether the storage keys modified by each block are indexed.
+	///
+	/// By default this is retrieved from `DatabaseParams` if it is available. Otherwise its
+	/// `false`.
+	fn storage_changes_index(&self) -> Result<bool> {
+		Ok(self.database_params().map(|x| x.storage_changes_index()).unwrap_or_default())
+	}
+
 	/// Get the database configuration object for the parameters provided
 	fn database_config(
 		&self,
@@ -215,18 +232,26 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 		let role_dir = "full";
 		let rocksdb_path = base_path.join("db").join(role_dir);
 		let paritydb_path = base_path.join("paritydb").join(role_dir);
-		Ok(match database {
+		Ok(match (database, self.database_secondary_path()?) {
+			#[cfg(feature = "rocksdb")]
+			(Database::RocksDb, Some(secondary_path)) =>
+				DatabaseSource::RocksDbSecondary { path: rocksdb_path, secondary_path, cache_size },
+			(_, Some(_)) =>
+				return Err(Error::Input(
+					"A secondary database can only be opened with `--database rocksdb`".into(),
+				)),
 			#[cfg(feature = "rocksdb")]
-			Database::RocksDb => DatabaseSource::RocksDb { path: rocksdb_path, cache_size },
-			Database::ParityDb => DatabaseSource::ParityDb { path: paritydb_path },
-			Database::ParityDbDeprecated => {
+			(Database::RocksDb, None) => DatabaseSource::RocksDb { path: rocksdb_path, cache_size },
+			(Database::ParityDb, None) => DatabaseSource::ParityDb { path: paritydb_path },
+			(Database::ParityDbDeprecated, None) => {
 				eprintln!(
 					"WARNING: \"paritydb-experimental\" database setting is deprecated and will be removed in future releases. \
 				Please update your setup to use the new value: \"paritydb\"."
 				);
 				DatabaseSource::ParityDb { path: paritydb_path }
 			},
-			Database::Auto => DatabaseSource::Auto { paritydb_path, rocksdb_path, cache_size },
+			(Database::Auto, None) =>
+				DatabaseSource::Auto { paritydb_path, rocksdb_path, cache_size },
 		})
 	}
 
@@ -258,6 +283,16 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			.unwrap_or_else(|| Ok(BlocksPruning::All))
 	}
 
+	/// Get the retention of the indexed transactions and bodies beyond the block pruning.
+	///
+	/// By default this is retrieved from `block_pruning` if it is available. Otherwise nothing
+	/// more is kept.
+	fn blocks_retention(&self) -> Result<BlocksRetention> {
+		self.pruning_params()
+			.map(|x| x.blocks_retention())
+			.unwrap_or_else(|| Ok(Default::default()))
+	}
+
 	/// Get the chain ID (string).
 	///
 	/// By default this is retrieved from `SharedParams`.
@@ -365,6 +400,18 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 		Ok(None)
 	}
 
+	/// Get maximum number of calls in a batch request.
+	fn rpc_max_batch_len(&self) -> Result<Option<usize>> {
+		Ok(None)
+	}
+
+	/// Get the call-rate limits of the RPC server.
+	///
+	/// By default no call is limited.
+	fn rpc_rate_limit(&self) -> Result<RateLimitConfig> {
+		Ok(Default::default())
+	}
+
 	/// Get maximum WS output buffer capacity.
 	fn ws_max_out_buffer_capacity(&self) -> Result<Option<usize>> {
 		Ok(None)
@@ -530,6 +577,8 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			trie_cache_maximum_size: self.trie_cache_maximum_size()?,
 			state_pruning: self.state_pruning()?,
 			blocks_pruning: self.blocks_pruning()?,
+			blocks_retention: self.blocks_retention()?,
+			changes_index: self.storage_changes_index()?,
 			wasm_method: self.wasm_method()?,
 			wasm_runtime_overrides: self.wasm_runtime_overrides(),
 			execution_strategies: self.execution_strategies(is_dev, is_validator)?,
@@ -544,6 +593,8 @@ pub trait CliConfiguration<DCV: DefaultConfigurationValues = ()>: Sized {
 			rpc_max_response_size: self.rpc_max_response_size()?,
 			rpc_id_provider: None,
 			rpc_max_subs_per_conn: self.rpc_max_subscriptions_per_connection()?,
+			rpc_max_batch_len: self.rpc_max_batch_len()?,
+			rpc_rate_limit: self.rpc_rate_limit()?,
 			ws_max_out_buffer_capacity: self.ws_max_out_buffer_capacity()?,
 			prometheus_config: self
 				.prometheus_config(DCV::prometheus_listen_port(), &chain_spec)?,
@@ -18,6 +18,7 @@
 
 use crate::arg_enums::Database;
 use clap::Args;
+use std::path::PathBuf;
 
 /// Parameters for block import.
 #[derive(Debug, Clone, PartialEq, Args)]
@@ -35,6 +36,22 @@ pub struct DatabaseParams {
 	/// Limit the memory the database cache can use.
 	#[clap(long = "db-cache", value_name = "MiB")]
 	pub database_cache_size: Option<usize>,
+
+	/// Open the RocksDB database of another node read-only, keeping the files of this secondary
+	/// instance in the given directory.
+	///
+	/// The database follows the writes of the node owning it, so that chain and state queries
+	/// can be served by a separate process. Block import is refused.
+	#[clap(long = "db-secondary", value_name = "PATH")]
+	pub database_secondary_path: Option<PathBuf>,
+
+	/// Index the storage keys modified by each block.
+	///
+	/// Speeds up the queries of storage changes over ranges of blocks, such as
+	/// `state_queryStorage` and `state_keyHistory`. Blocks imported before the index is enabled
+	/// are not covered, and the index of a block is removed with its body by `--blocks-pruning`.
+	#[clap(long)]
+	pub storage_changes_index: bool,
 }
 
 impl DatabaseParams {
@@ -47,4 +64,14 @@ impl DatabaseParams {
 	pub fn database_cache_size(&self) -> Option<usize> {
 		self.database_cache_size
 	}
+
+	/// Directory of the files of a read-only secondary database instance.
+	pub fn database_secondary_path(&self) -> Option<PathBuf> {
+		self.database_secondary_path.clone()
+	}
+
+	/// Whether the storage keys modified by each block are indexed.
+	pub fn storage_changes_index(&self) -> bool {
+		self.storage_changes_index
+	}
 }
@@ -126,7 +126,11 @@ pub struct NetworkParams {
 	#[clap(long)]
 	pub kademlia_disjoint_query_paths: bool,
 
-	/// Join the IPFS network and serve transactions over bitswap protocol.
+	/// Serve transactions over the IPFS bitswap protocol.
+	///
+	/// The node also announces the transactions it stores in the DHT of the chain, and can fetch
+	/// the ones it lacks from other peers. It doesn't join the public IPFS DHT, so IPFS nodes have
+	/// to connect to it directly.
 	#[clap(long)]
 	pub ipfs_server: bool,
 
@@ -136,6 +140,7 @@ pub struct NetworkParams {
 	/// - `fast`: Download blocks and the latest state only.
 	/// - `fast-unsafe`: Same as `fast`, but skip downloading state proofs.
 	/// - `warp`: Download the latest state and proof.
+	/// - `light`: Download and validate headers only, and read the state from full nodes.
 	#[clap(
 		long,
 		arg_enum,
@@ -18,7 +18,8 @@
 
 use crate::error;
 use clap::Args;
-use sc_service::{BlocksPruning, PruningMode};
+use sc_service::{BlocksPruning, BlocksRetention, PruningMode};
+use std::ops::RangeInclusive;
 
 /// Parameters to define the pruning mode
 #[derive(Debug, Clone, PartialEq, Args)]
@@ -28,21 +29,44 @@ pub struct PruningParams {
 	/// Default is to keep only the last 256 blocks,
 	/// otherwise, the state can be kept for all of the blocks (i.e 'archive'),
 	/// or for all of the canonical blocks (i.e 'archive-canonical').
+	///
+	/// An archive RocksDB database may be restarted with a number of blocks to keep. The state of
+	/// older blocks is then removed in the background. The number of blocks to keep may be
+	/// increased on restart, but not decreased.
 	#[clap(alias = "pruning", long, value_name = "PRUNING_MODE")]
 	pub state_pruning: Option<String>,
+	/// Additionally keep the state of every canonical block whose number is a multiple of this
+	/// interval, after it leaves the state pruning window.
+	///
+	/// Requires a numeric `--state-pruning` value and a ParityDB database.
+	#[clap(long, value_name = "INTERVAL")]
+	pub state_checkpoint_interval: Option<u32>,
 	/// Specify the number of finalized blocks to keep in the database.
 	///
-	/// Default is to keep all blocks.
+	/// Default is to keep all blocks. Headers and justifications are always kept.
 	///
 	/// NOTE: only finalized blocks are subject for removal!
 	#[clap(alias = "keep-blocks", long, value_name = "COUNT")]
 	pub blocks_pruning: Option<u32>,
+	/// Specify the number of finalized blocks to keep the indexed transactions of.
+	///
+	/// Must not be lower than `--blocks-pruning`, as indexed transactions are always kept as long
+	/// as the block bodies. Requires `--blocks-pruning`.
+	#[clap(long, value_name = "COUNT")]
+	pub transactions_pruning: Option<u32>,
+	/// Keep the bodies and indexed transactions of the finalized blocks in the given range of
+	/// block numbers, e.g. `0-999999`, regardless of `--blocks-pruning`.
+	///
+	/// May be given multiple times. Requires `--blocks-pruning`.
+	#[clap(long, value_name = "START-END", parse(try_from_str = parse_block_range))]
+	pub blocks_pruning_keep: Vec<RangeInclusive<u32>>,
 }
 
 impl PruningParams {
 	/// Get the pruning value from the parameters
 	pub fn state_pruning(&self) -> error::Result<Option<PruningMode>> {
-		self.state_pruning
+		let mode = self
+			.state_pruning
 			.as_ref()
 			.map(|s| match s.as_str() {
 				"archive" => Ok(PruningMode::ArchiveAll),
@@ -51,7 +75,20 @@ impl PruningParams {
 					.map_err(|_| error::Error::Input("Invalid pruning mode specified".to_string()))
 					.map(PruningMode::blocks_pruning),
 			})
-			.transpose()
+			.transpose()?;
+
+		match (mode, self.state_checkpoint_interval) {
+			(mode, None) => Ok(mode),
+			(Some(PruningMode::Constrained(constraints)), Some(checkpoint_interval)) =>
+				Ok(Some(PruningMode::Hybrid { constraints, checkpoint_interval })),
+			(None, Some(checkpoint_interval)) => Ok(Some(PruningMode::Hybrid {
+				constraints: Default::default(),
+				checkpoint_interval,
+			})),
+			(Some(_), Some(_)) => Err(error::Error::Input(
+				"State checkpoints can only be used with a numeric state pruning mode".to_string(),
+			)),
+		}
 	}
 
 	/// Get the block pruning value from the parameters
@@ -61,4 +98,66 @@ impl PruningParams {
 			None => BlocksPruning::All,
 		})
 	}
+
+	/// Get the retention of the indexed transactions and bodies beyond the block pruning
+	pub fn blocks_retention(&self) -> error::Result<BlocksRetention> {
+		let retention = BlocksRetention {
+			transactions: self.transactions_pruning,
+			keep_ranges: self.blocks_pruning_keep.clone(),
+		};
+		match (self.blocks_pruning, self.transactions_pruning) {
+			(None, _) if retention != BlocksRetention::default() => Err(error::Error::Input(
+				"Block retention policies require `--blocks-pruning`".to_string(),
+			)),
+			(Some(blocks), Some(transactions)) if transactions < blocks =>
+				Err(error::Error::Input(format!(
+					"`--transactions-pruning` ({}) can't be lower than `--blocks-pruning` ({})",
+					transactions, blocks,
+				))),
+			_ => Ok(retention),
+		}
+	}
+}
+
+/// Parse an inclusive range of block numbers given as `START-END`.
+fn parse_block_range(s: &str) -> std::result::Result<RangeInclusive<u32>, String> {
+	let (start, end) = s.split_once('-').ok_or_else(|| format!("Expected START-END, got {}", s))?;
+	let start: u32 = start.parse().map_err(|e| format!("Invalid range start: {}", e))?;
+	let end: u32 = end.parse().map_err(|e| format!("Invalid range end: {}", e))?;
+	if start > end {
+		return Err(format!("Range start {} is greater than its end {}", start, end))
+	}
+	Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use clap::Parser;
+
+	#[derive(Parser)]
+	struct Cli {
+		#[clap(flatten)]
+		pruning_params: PruningParams,
+	}
+
+	fn blocks_retention(args: &[&str]) -> error::Result<BlocksRetention> {
+		Cli::try_parse_from(std::iter::once("").chain(args.iter().copied()))
+			.expect("Parses pruning params")
+			.pruning_params
+			.blocks_retention()
+	}
+
+	#[test]
+	fn transactions_pruning_must_not_be_lower_than_blocks_pruning() {
+		assert!(
+			blocks_retention(&["--blocks-pruning", "10", "--transactions-pruning", "5"]).is_err()
+		);
+		assert_eq!(
+			blocks_retention(&["--blocks-pruning", "10", "--transactions-pruning", "10"]).unwrap(),
+			BlocksRetention { transactions: Some(10), keep_ranges: Vec::new() },
+		);
+		assert!(blocks_retention(&["--transactions-pruning", "10"]).is_err());
+		assert_eq!(blocks_retention(&[]).unwrap(), BlocksRetention::default());
+	}
 }
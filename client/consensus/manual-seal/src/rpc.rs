@@ -24,7 +24,7 @@ use futures::{
 	SinkExt,
 };
 use jsonrpsee::{
-	core::{async_trait, Error as JsonRpseeError, RpcResult},
+	core::{async_trait, RpcResult},
 	proc_macros::rpc,
 };
 use sc_consensus::ImportedAux;
@@ -91,7 +91,7 @@ pub struct ManualSeal<Hash> {
 }
 
 /// return type of `engine_createBlock`
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct CreatedBlock<Hash> {
 	/// hash of the created block.
 	pub hash: Hash,
@@ -124,12 +124,12 @@ impl<Hash: Send + 'static> ManualSealApiServer<Hash> for ManualSeal<Hash> {
 			sender: Some(sender),
 		};
 
-		sink.send(command).await?;
+		sink.send(command).await.map_err(Error::from)?;
 
 		match receiver.await {
 			Ok(Ok(rx)) => Ok(rx),
 			Ok(Err(e)) => Err(e.into()),
-			Err(e) => Err(JsonRpseeError::to_call_error(e)),
+			Err(e) => Err(Error::from(e).into()),
 		}
 	}
 
@@ -141,8 +141,8 @@ impl<Hash: Send + 'static> ManualSealApiServer<Hash> for ManualSeal<Hash> {
 		let mut sink = self.import_block_channel.clone();
 		let (sender, receiver) = oneshot::channel();
 		let command = EngineCommand::FinalizeBlock { hash, sender: Some(sender), justification };
-		sink.send(command).await?;
-		receiver.await.map(|_| true).map_err(|e| JsonRpseeError::to_call_error(e))
+		sink.send(command).await.map_err(Error::from)?;
+		receiver.await.map(|_| true).map_err(|e| Error::from(e).into())
 	}
 }
 
@@ -0,0 +1,149 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Removal of the state kept by a database in an archive pruning mode once it is opened with a
+//! pruning mode.
+//!
+//! The state of the blocks older than the pruning window is removed block by block: the garbage
+//! of block `n` are the trie nodes of the state of block `n - 1` that are part of neither the
+//! state of block `n` nor the state of the first block in the pruning window. Nodes are stored
+//! under their position in the trie, so a node is alive in another state if that state has the
+//! same node at the same position. States of blocks that were never finalized are not removed, nor
+//! are the nodes replaced by the blocks that were not finalized when the pruning mode was switched.
+
+use crate::{
+	apply_state_commit, columns, trie_nodes::UnsharedNodes, utils, BlockchainDb, StorageDb,
+};
+use log::{debug, info, warn};
+use parking_lot::RwLock;
+use sp_blockchain::{Backend as _, Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_database::Transaction;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, SaturatedConversion},
+};
+use std::{
+	collections::HashMap,
+	sync::{Arc, Weak},
+};
+
+/// Returns the last finalized block and the blocks imported on top of it, ordered by number, as
+/// expected by `StateDb::open_from_archive`.
+pub(crate) fn finalized_with_descendants<Block: BlockT>(
+	blockchain: &BlockchainDb<Block>,
+) -> ClientResult<((Block::Hash, u64), Vec<(Block::Hash, u64, Block::Hash)>)> {
+	let info = blockchain.info();
+	let mut descendants = HashMap::new();
+	for leaf in blockchain.leaves()? {
+		let mut branch = Vec::new();
+		let mut hash = leaf;
+		loop {
+			let header = blockchain.expect_header(BlockId::Hash(hash))?;
+			let number = *header.number();
+			if number <= info.finalized_number || descendants.contains_key(&hash) {
+				// Leaves of branches that are not descendants of the finalized block are dropped.
+				if hash == info.finalized_hash || descendants.contains_key(&hash) {
+					descendants.extend(branch);
+				}
+				break
+			}
+			branch.push((hash, (number.saturated_into::<u64>(), *header.parent_hash())));
+			hash = *header.parent_hash();
+		}
+	}
+	let mut descendants: Vec<_> = descendants
+		.into_iter()
+		.map(|(hash, (number, parent_hash))| (hash, number, parent_hash))
+		.collect();
+	descendants.sort_by_key(|(_, number, _)| *number);
+	Ok(((info.finalized_hash, info.finalized_number.saturated_into()), descendants))
+}
+
+/// Remove the garbage of the next archived block. Returns `false` if there is nothing left to
+/// remove.
+pub(crate) fn collect_next<Block: BlockT>(storage: &StorageDb<Block>) -> ClientResult<bool> {
+	let (number, window_start) = match storage.state_db.archive_gc_next() {
+		Some(next) => next,
+		None => return Ok(false),
+	};
+	let state_root = |number: u64| -> ClientResult<Vec<u8>> {
+		utils::read_header::<Block>(
+			&*storage.db,
+			columns::KEY_LOOKUP,
+			columns::HEADER,
+			BlockId::Number(number.saturated_into()),
+		)?
+		.map(|header| header.state_root().as_ref().to_vec())
+		.ok_or_else(|| ClientError::Backend(format!("Missing header of archived block {}", number)))
+	};
+
+	let mut garbage = Vec::new();
+	UnsharedNodes::<HashFor<Block>>::new(
+		&|key| storage.db.get(columns::STATE, key),
+		&mut |key, _| {
+			garbage.push(key);
+			Ok(())
+		},
+	)
+	.walk(state_root(number - 1)?, &[state_root(number)?, state_root(window_start)?])?;
+	debug!(
+		target: "db",
+		"Removing {} archived state nodes of block #{}",
+		garbage.len(),
+		number,
+	);
+
+	let commit = storage.state_db.collect_archive_garbage(number, garbage);
+	let mut transaction = Transaction::new();
+	apply_state_commit(&mut transaction, commit);
+	match storage.db.commit(transaction) {
+		Ok(()) => {
+			storage.state_db.apply_pending();
+			Ok(true)
+		},
+		Err(e) => {
+			storage.state_db.revert_pending();
+			Err(e.into())
+		},
+	}
+}
+
+/// Spawn a thread removing the archived state of `storage` in the background. The `import_lock`
+/// is held while the garbage of a block is removed.
+pub(crate) fn spawn<Block: BlockT>(storage: &Arc<StorageDb<Block>>, import_lock: Arc<RwLock<()>>) {
+	let storage: Weak<StorageDb<Block>> = Arc::downgrade(storage);
+	let spawned = std::thread::Builder::new().name("archive-gc".into()).spawn(move || {
+		while let Some(storage) = storage.upgrade() {
+			let _lock = import_lock.write();
+			match collect_next(&storage) {
+				Ok(true) => (),
+				Ok(false) => {
+					info!("Archived state removed");
+					break
+				},
+				Err(e) => {
+					warn!(target: "db", "Error removing archived state: {}", e);
+					break
+				},
+			}
+		}
+	});
+	if let Err(e) = spawned {
+		warn!(target: "db", "Error spawning archive garbage collection: {}", e);
+	}
+}
@@ -0,0 +1,340 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Index of the storage keys modified by each block.
+//!
+//! The index is kept in the `CHANGES_INDEX` column. The keys modified by a block are recorded
+//! under its lookup key when the block is imported with its state changes, and removed along with
+//! its body. When a block is finalized, it is added to the history of each key it modifies: a list
+//! of the finalized blocks modifying the key, linked from the last one. Listing the changes of a
+//! key then takes time proportional to their number.
+//!
+//! The index restarts after the finalized blocks whose changes are unknown, such as the blocks
+//! imported before the index was enabled or with a state downloaded by state sync. History
+//! entries of reverted blocks are recognized by their block hash and skipped.
+//!
+//! The history follows the block pruning: once the body of a finalized block leaves the pruning
+//! window, its history entries are removed and the index starts after it.
+
+use crate::{columns, utils, Backend, DbHash};
+use codec::{Decode, Encode};
+use sc_client_api::backend::{BlockChanges, ChangesIndex};
+use sp_blockchain::{Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::{hashing::blake2_256, storage::ChildInfo};
+use sp_database::{Database, Transaction};
+use sp_runtime::traits::{Block as BlockT, NumberFor, One};
+use sp_state_machine::{ChildStorageCollection, StorageCollection};
+use std::collections::HashMap;
+
+/// Number of the first block covered by the index.
+const START: &[u8] = b"start";
+/// Prefix of the modified keys of a block, by lookup key.
+const BLOCK_PREFIX: &[u8] = b"block";
+/// Prefix of the history entries, by key identifier and block number.
+const HISTORY_PREFIX: &[u8] = b"history";
+/// Prefix of the number of the last finalized block modifying a key, by key identifier.
+const LAST_PREFIX: &[u8] = b"last";
+
+/// Identifier of a top-level or child storage key.
+type KeyId = [u8; 32];
+
+fn key_id(child_storage_key: Option<&[u8]>, key: &[u8]) -> KeyId {
+	blake2_256(&(child_storage_key, key).encode())
+}
+
+/// Identifiers of the keys modified by a block.
+fn key_ids(changes: &BlockChanges) -> impl Iterator<Item = KeyId> + '_ {
+	let top = changes.top.iter().map(|key| key_id(None, key));
+	let children = changes.children.iter().flat_map(|(storage_key, keys)| {
+		keys.iter().map(move |key| key_id(Some(&storage_key[..]), key))
+	});
+	top.chain(children)
+}
+
+fn block_key(lookup_key: &[u8]) -> Vec<u8> {
+	[BLOCK_PREFIX, lookup_key].concat()
+}
+
+fn history_key<N: Encode>(id: &KeyId, number: N) -> Vec<u8> {
+	[HISTORY_PREFIX, &id[..], &number.encode()].concat()
+}
+
+fn last_change_key(id: &KeyId) -> Vec<u8> {
+	[LAST_PREFIX, &id[..]].concat()
+}
+
+fn read<T: Decode>(db: &dyn Database<DbHash>, key: &[u8], entry: &str) -> ClientResult<Option<T>> {
+	db.get(columns::CHANGES_INDEX, key)
+		.map(|value| {
+			T::decode(&mut &value[..]).map_err(|err| {
+				ClientError::Backend(format!("Error decoding changes index {}: {}", entry, err))
+			})
+		})
+		.transpose()
+}
+
+fn read_block_changes(
+	db: &dyn Database<DbHash>,
+	lookup_key: &[u8],
+) -> ClientResult<Option<BlockChanges>> {
+	read(db, &block_key(lookup_key), "block changes")
+}
+
+/// Read the history entry of the change of a key by block `number`: the hash of the block and
+/// the number of the previous block modifying the key.
+fn read_history<Block: BlockT>(
+	db: &dyn Database<DbHash>,
+	id: &KeyId,
+	number: NumberFor<Block>,
+) -> ClientResult<Option<(Block::Hash, Option<NumberFor<Block>>)>> {
+	read(db, &history_key(id, number), "history entry")
+}
+
+fn read_start<Block: BlockT>(db: &dyn Database<DbHash>) -> ClientResult<Option<NumberFor<Block>>> {
+	read(db, START, "start")
+}
+
+/// Start the index after `best_number` if it is enabled and not started yet, or forget where it
+/// started if it is disabled, so that it restarts when enabled again.
+pub(crate) fn init<Block: BlockT>(
+	transaction: &mut Transaction<DbHash>,
+	db: &dyn Database<DbHash>,
+	enabled: bool,
+	best_number: NumberFor<Block>,
+) -> ClientResult<()> {
+	match (enabled, read_start::<Block>(db)?) {
+		(true, None) => transaction.set_from_vec(
+			columns::CHANGES_INDEX,
+			START,
+			(best_number + One::one()).encode(),
+		),
+		(false, Some(_)) => transaction.remove(columns::CHANGES_INDEX, START),
+		_ => (),
+	}
+	Ok(())
+}
+
+/// Keys modified by the state changes of a block.
+pub(crate) fn block_changes(
+	storage: &StorageCollection,
+	child_storage: &ChildStorageCollection,
+) -> BlockChanges {
+	BlockChanges {
+		top: storage.iter().map(|(key, _)| key.clone()).collect(),
+		children: child_storage
+			.iter()
+			.map(|(storage_key, changes)| {
+				(storage_key.clone(), changes.iter().map(|(key, _)| key.clone()).collect())
+			})
+			.collect(),
+	}
+}
+
+/// Remove the modified keys recorded for a block.
+pub(crate) fn remove_block(transaction: &mut Transaction<DbHash>, lookup_key: &[u8]) {
+	transaction.remove(columns::CHANGES_INDEX, &block_key(lookup_key));
+}
+
+/// Changes to the index made by a database transaction.
+pub(crate) struct IndexUpdate<Block: BlockT> {
+	enabled: bool,
+	/// Modified keys of the blocks imported by the transaction.
+	blocks: HashMap<Block::Hash, BlockChanges>,
+	/// Last change of the keys modified by the blocks finalized by the transaction.
+	last_changes: HashMap<KeyId, NumberFor<Block>>,
+	/// First covered block, once read.
+	start: Option<NumberFor<Block>>,
+}
+
+impl<Block: BlockT> IndexUpdate<Block> {
+	pub(crate) fn new(enabled: bool) -> Self {
+		IndexUpdate { enabled, blocks: HashMap::new(), last_changes: HashMap::new(), start: None }
+	}
+
+	/// Record the keys modified by the imported block `hash`.
+	pub(crate) fn insert_block(
+		&mut self,
+		transaction: &mut Transaction<DbHash>,
+		lookup_key: &[u8],
+		hash: Block::Hash,
+		changes: BlockChanges,
+	) {
+		if self.enabled {
+			transaction.set_from_vec(
+				columns::CHANGES_INDEX,
+				&block_key(lookup_key),
+				changes.encode(),
+			);
+			self.blocks.insert(hash, changes);
+		}
+	}
+
+	/// Add the finalized block `hash` to the history of the keys it modifies. Blocks must be
+	/// finalized in order.
+	pub(crate) fn finalize_block(
+		&mut self,
+		transaction: &mut Transaction<DbHash>,
+		db: &dyn Database<DbHash>,
+		number: NumberFor<Block>,
+		hash: Block::Hash,
+	) -> ClientResult<()> {
+		if !self.enabled {
+			return Ok(())
+		}
+		let start = match self.start(db)? {
+			Some(start) => start,
+			None => return Ok(()),
+		};
+		if number < start {
+			return Ok(())
+		}
+		let changes = match self.blocks.get(&hash) {
+			Some(changes) => changes.clone(),
+			None => {
+				let lookup_key = utils::number_and_hash_to_lookup_key(number, hash)?;
+				match read_block_changes(db, &lookup_key)? {
+					Some(changes) => changes,
+					None => {
+						self.set_start(transaction, number + One::one());
+						return Ok(())
+					},
+				}
+			},
+		};
+
+		for id in key_ids(&changes) {
+			let mut previous = match self.last_changes.get(&id) {
+				Some(previous) => Some(*previous),
+				None => read(db, &last_change_key(&id), "last change")?,
+			};
+			// Skip the changes of reverted blocks.
+			while let Some(reverted) = previous.filter(|previous| *previous >= number) {
+				previous = read_history::<Block>(db, &id, reverted)?
+					.and_then(|(_, previous)| previous)
+					.filter(|previous| *previous < reverted);
+			}
+			transaction.set_from_vec(
+				columns::CHANGES_INDEX,
+				&history_key(&id, number),
+				(hash, previous).encode(),
+			);
+			self.last_changes.insert(id, number);
+		}
+		Ok(())
+	}
+
+	/// Remove the history entries of the finalized block `number`, leaving the pruning window,
+	/// and start the index after it. Blocks must be pruned in order.
+	pub(crate) fn prune_block(
+		&mut self,
+		transaction: &mut Transaction<DbHash>,
+		db: &dyn Database<DbHash>,
+		number: NumberFor<Block>,
+		hash: Block::Hash,
+	) -> ClientResult<()> {
+		if !self.enabled || self.start(db)?.map_or(true, |start| number < start) {
+			return Ok(())
+		}
+		let lookup_key = utils::number_and_hash_to_lookup_key(number, hash)?;
+		if let Some(changes) = read_block_changes(db, &lookup_key)? {
+			for id in key_ids(&changes) {
+				transaction.remove(columns::CHANGES_INDEX, &history_key(&id, number));
+				// The whole history of the key is pruned, unless it is modified again by a block
+				// finalized by this transaction.
+				if !self.last_changes.contains_key(&id) &&
+					read::<NumberFor<Block>>(db, &last_change_key(&id), "last change")? ==
+						Some(number)
+				{
+					transaction.remove(columns::CHANGES_INDEX, &last_change_key(&id));
+				}
+			}
+		}
+		self.set_start(transaction, number + One::one());
+		Ok(())
+	}
+
+	/// Write the last change of the keys modified by the finalized blocks.
+	pub(crate) fn apply(self, transaction: &mut Transaction<DbHash>) {
+		for (id, number) in self.last_changes {
+			transaction.set_from_vec(
+				columns::CHANGES_INDEX,
+				&last_change_key(&id),
+				number.encode(),
+			);
+		}
+	}
+
+	/// First covered block, or `None` if the index is not started.
+	fn start(&mut self, db: &dyn Database<DbHash>) -> ClientResult<Option<NumberFor<Block>>> {
+		if self.start.is_none() {
+			self.start = read_start::<Block>(db)?;
+		}
+		Ok(self.start)
+	}
+
+	fn set_start(&mut self, transaction: &mut Transaction<DbHash>, start: NumberFor<Block>) {
+		transaction.set_from_vec(columns::CHANGES_INDEX, START, start.encode());
+		self.start = Some(start);
+	}
+}
+
+impl<Block: BlockT> ChangesIndex<Block> for Backend<Block> {
+	fn indexed_from(&self) -> ClientResult<NumberFor<Block>> {
+		read_start::<Block>(&*self.storage.db)?
+			.ok_or_else(|| ClientError::Backend("The changes index is not started".into()))
+	}
+
+	fn block_changes(&self, hash: &Block::Hash) -> ClientResult<Option<BlockChanges>> {
+		match self.blockchain.number(*hash)? {
+			Some(number) => read_block_changes(
+				&*self.storage.db,
+				&utils::number_and_hash_to_lookup_key(number, hash)?,
+			),
+			None => Ok(None),
+		}
+	}
+
+	fn key_changes(
+		&self,
+		first: NumberFor<Block>,
+		last: NumberFor<Block>,
+		child_info: Option<&ChildInfo>,
+		key: &[u8],
+	) -> ClientResult<Vec<NumberFor<Block>>> {
+		let db = &*self.storage.db;
+		// The history of the pruned blocks is removed.
+		let first = match read_start::<Block>(db)? {
+			Some(start) => std::cmp::max(first, start),
+			None => return Ok(Vec::new()),
+		};
+		let id = key_id(child_info.map(|child_info| child_info.storage_key()), key);
+		let mut changes = Vec::new();
+		let mut next: Option<NumberFor<Block>> = read(db, &last_change_key(&id), "last change")?;
+		while let Some(number) = next.filter(|number| *number >= first) {
+			let (hash, previous) = read_history::<Block>(db, &id, number)?.ok_or_else(|| {
+				ClientError::Backend(format!("Missing changes index history entry #{}", number))
+			})?;
+			if number <= last && self.blockchain.hash(number)? == Some(hash) {
+				changes.push(number);
+			}
+			next = previous.filter(|previous| *previous < number);
+		}
+		changes.reverse();
+		Ok(changes)
+	}
+}
@@ -0,0 +1,689 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Integrity checks of the block and state data of a database.
+//!
+//! The blocks are found by following the canonical chain from the best block, the branches of the
+//! leaves back to a known block and the children index from the last finalized block. The derived
+//! indices (hash and number lookups, leaves and children) are then compared with the headers of
+//! the blocks found, and can be rebuilt from them.
+//!
+//! The journal of the non-canonical state overlay is checked as well. Its entries that can't be
+//! restored are discarded when the database is opened, repairing removes them from the database.
+//!
+//! Unless the state is archived, the nodes of the state column are checked to be part of the state
+//! of a block found, or of the last pruned block whose nodes are removed with the next one. This
+//! walks the state of every block kept and iterates the state column.
+
+use crate::{
+	children, columns, trie_nodes::UnsharedNodes, utils, utils::meta_keys, Backend, BlocksPruning,
+	DbHash, StateMetaDb, StorageDb, DB_HASH_LEN,
+};
+use codec::Decode;
+use hash_db::{HashDBRef, Hasher, Prefix};
+use sc_client_api::{backend::Backend as _, leaves::LeafSet};
+use sp_blockchain::{Backend as _, Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::storage::{well_known_keys, ChildInfo};
+use sp_database::{Database, Transaction};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{
+		Block as BlockT, HashFor, Header as HeaderT, NumberFor, One, SaturatedConversion, Zero,
+	},
+};
+use sp_state_machine::DBValue;
+use sp_trie::{trie_types::TrieDBBuilder, KeySpacedDB, Trie};
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	fmt,
+};
+
+/// An inconsistency found by `Backend::check_database`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbIssue<Block: BlockT> {
+	/// A block referenced by the `referrer` has no header.
+	MissingHeader {
+		/// Hash of the block.
+		hash: Block::Hash,
+		/// Number of the block.
+		number: NumberFor<Block>,
+		/// What references the block.
+		referrer: &'static str,
+	},
+	/// An `entry` of a block can not be decoded or does not match the block.
+	InvalidEntry {
+		/// Hash of the block.
+		hash: Block::Hash,
+		/// Name of the entry.
+		entry: &'static str,
+		/// Why the entry is invalid.
+		error: String,
+	},
+	/// The hash lookup of a block is missing or does not point to its header.
+	HashLookup {
+		/// Hash of the block.
+		hash: Block::Hash,
+		/// Number of the block.
+		number: NumberFor<Block>,
+	},
+	/// The number lookup of a canonical block is missing or points to another block.
+	NumberLookup {
+		/// Hash of the block.
+		hash: Block::Hash,
+		/// Number of the block.
+		number: NumberFor<Block>,
+		/// Block the number lookup points to.
+		found: Option<Block::Hash>,
+	},
+	/// The body of a block that is not subject to block pruning is missing.
+	MissingBody {
+		/// Hash of the block.
+		hash: Block::Hash,
+		/// Number of the block.
+		number: NumberFor<Block>,
+	},
+	/// The leaf set lacks `missing` leaves and contains `stale` ones.
+	Leaves {
+		/// Leaves missing from the leaf set.
+		missing: Vec<Block::Hash>,
+		/// Blocks of the leaf set that are not leaves.
+		stale: Vec<Block::Hash>,
+	},
+	/// The children index of a block lacks `missing` children and contains `stale` ones.
+	Children {
+		/// Hash of the block.
+		hash: Block::Hash,
+		/// Children missing from the index.
+		missing: Vec<Block::Hash>,
+		/// Blocks of the index that are not children of the block.
+		stale: Vec<Block::Hash>,
+	},
+	/// The state of a block can not be read in full.
+	MissingTrieNode {
+		/// Hash of the block.
+		hash: Block::Hash,
+		/// Number of the block.
+		number: NumberFor<Block>,
+		/// Error reading the state.
+		error: String,
+	},
+	/// Nodes of the state column are not part of the state of any block kept.
+	OrphanedTrieNodes {
+		/// Number of orphaned nodes.
+		count: u64,
+		/// Size of the orphaned nodes in bytes, including their keys.
+		bytes: u64,
+	},
+	/// An entry of the non-canonical state journal can not be restored.
+	NonCanonicalJournal {
+		/// Number of the block of the entry.
+		number: u64,
+		/// Index of the entry among the entries of the blocks with the same number.
+		index: u64,
+		/// Hash of the block of the entry, if it could be decoded.
+		hash: Option<Block::Hash>,
+		/// Why the entry can't be restored.
+		error: String,
+	},
+}
+
+impl<Block: BlockT> DbIssue<Block> {
+	/// Whether the issue is fixed by rebuilding the derived indices or removing the discarded
+	/// entries of the non-canonical state journal.
+	pub fn is_repairable(&self) -> bool {
+		matches!(
+			self,
+			DbIssue::HashLookup { .. } |
+				DbIssue::NumberLookup { .. } |
+				DbIssue::Leaves { .. } |
+				DbIssue::Children { .. } |
+				DbIssue::NonCanonicalJournal { .. }
+		)
+	}
+}
+
+impl<Block: BlockT> fmt::Display for DbIssue<Block> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			DbIssue::MissingHeader { hash, number, referrer } => write!(
+				f,
+				"Missing header of block #{} ({:?}) referenced by the {}",
+				number, hash, referrer
+			),
+			DbIssue::InvalidEntry { hash, entry, error } =>
+				write!(f, "Invalid {} of block {:?}: {}", entry, hash, error),
+			DbIssue::HashLookup { hash, number } =>
+				write!(f, "Invalid hash lookup of block #{} ({:?})", number, hash),
+			DbIssue::NumberLookup { hash, number, found } => write!(
+				f,
+				"Invalid number lookup of canonical block #{} ({:?}): found {:?}",
+				number, hash, found
+			),
+			DbIssue::MissingBody { hash, number } =>
+				write!(f, "Missing body of block #{} ({:?})", number, hash),
+			DbIssue::Leaves { missing, stale } =>
+				write!(f, "Invalid leaf set: missing {:?}, stale {:?}", missing, stale),
+			DbIssue::Children { hash, missing, stale } => write!(
+				f,
+				"Invalid children of block {:?}: missing {:?}, stale {:?}",
+				hash, missing, stale
+			),
+			DbIssue::MissingTrieNode { hash, number, error } =>
+				write!(f, "Incomplete state of block #{} ({:?}): {}", number, hash, error),
+			DbIssue::OrphanedTrieNodes { count, bytes } => write!(
+				f,
+				"{} state nodes ({} bytes) are not part of the state of any block",
+				count, bytes
+			),
+			DbIssue::NonCanonicalJournal { number, index, hash, error } => write!(
+				f,
+				"Discarded non-canonical journal entry {}.{} of block {:?}: {}",
+				number, index, hash, error
+			),
+		}
+	}
+}
+
+/// Outcome of `Backend::check_database`.
+#[derive(Debug, Clone)]
+pub struct DbCheckReport<Block: BlockT> {
+	/// Number of blocks found.
+	pub blocks: usize,
+	/// Number of storage entries read from the state of the best and finalized blocks.
+	pub state_entries: u64,
+	/// Number of nodes of the state column checked for orphans, if they were checked.
+	pub state_nodes: Option<u64>,
+	/// Inconsistencies found.
+	pub issues: Vec<DbIssue<Block>>,
+	/// Whether the derived indices were rebuilt.
+	pub repaired: bool,
+}
+
+struct BlockEntry<Block: BlockT> {
+	number: NumberFor<Block>,
+	parent_hash: Block::Hash,
+	state_root: Block::Hash,
+}
+
+struct Checker<'a, Block: BlockT> {
+	db: &'a dyn Database<DbHash>,
+	blocks: HashMap<Block::Hash, BlockEntry<Block>>,
+	issues: Vec<DbIssue<Block>>,
+}
+
+impl<'a, Block: BlockT> Checker<'a, Block> {
+	/// Read the header of a block referenced by `referrer` and check its hash lookup.
+	fn read_block(
+		&mut self,
+		number: NumberFor<Block>,
+		hash: Block::Hash,
+		referrer: &'static str,
+	) -> ClientResult<bool> {
+		if self.blocks.contains_key(&hash) {
+			return Ok(true)
+		}
+		let lookup_key = utils::number_and_hash_to_lookup_key(number, hash)?;
+		let header = match self.db.get(columns::HEADER, &lookup_key) {
+			Some(header) => header,
+			None => {
+				self.issues.push(DbIssue::MissingHeader { hash, number, referrer });
+				return Ok(false)
+			},
+		};
+		let header = match Block::Header::decode(&mut &header[..]) {
+			Ok(header) if header.hash() == hash => header,
+			Ok(_) => {
+				self.issues.push(DbIssue::InvalidEntry {
+					hash,
+					entry: "header",
+					error: "Header hash mismatch".into(),
+				});
+				return Ok(false)
+			},
+			Err(e) => {
+				self.issues.push(DbIssue::InvalidEntry {
+					hash,
+					entry: "header",
+					error: e.to_string(),
+				});
+				return Ok(false)
+			},
+		};
+		if self.db.get(columns::KEY_LOOKUP, hash.as_ref()) != Some(lookup_key) {
+			self.issues.push(DbIssue::HashLookup { hash, number });
+		}
+		self.blocks.insert(
+			hash,
+			BlockEntry {
+				number,
+				parent_hash: *header.parent_hash(),
+				state_root: *header.state_root(),
+			},
+		);
+		Ok(true)
+	}
+
+	/// Returns the block the number lookup of `number` points to.
+	fn canonical_hash(&self, number: NumberFor<Block>) -> ClientResult<Option<Block::Hash>> {
+		let key = utils::number_index_key(number)?;
+		Ok(self
+			.db
+			.get(columns::KEY_LOOKUP, &key)
+			.and_then(|lookup_key| Block::Hash::decode(&mut &lookup_key[key.len()..]).ok()))
+	}
+}
+
+pub(crate) fn check<Block: BlockT>(
+	backend: &Backend<Block>,
+	repair: bool,
+) -> ClientResult<DbCheckReport<Block>> {
+	let db = &*backend.storage.db;
+	let info = backend.blockchain.info();
+	let mut checker = Checker::<Block> { db, blocks: HashMap::new(), issues: Vec::new() };
+
+	// The canonical chain, skipping the block gap left by warp sync.
+	let mut canonical = Vec::new();
+	let mut next = Some((info.best_number, info.best_hash));
+	while let Some((number, hash)) = next.take() {
+		if !checker.read_block(number, hash, "canonical chain")? {
+			break
+		}
+		canonical.push((number, hash));
+		if number.is_zero() {
+			break
+		}
+		let parent_number = number - One::one();
+		next = match info.block_gap {
+			Some((start, end)) if start <= parent_number && parent_number <= end =>
+				if start.is_zero() {
+					None
+				} else {
+					let number = start - One::one();
+					checker.canonical_hash(number)?.map(|hash| (number, hash))
+				},
+			_ => Some((parent_number, checker.blocks[&hash].parent_hash)),
+		};
+	}
+	let mut number_lookups = Vec::new();
+	for (number, hash) in &canonical {
+		let found = checker.canonical_hash(*number)?;
+		if found != Some(*hash) {
+			checker
+				.issues
+				.push(DbIssue::NumberLookup { hash: *hash, number: *number, found });
+			number_lookups.push((*number, *hash));
+		}
+	}
+
+	// Branches of the leaves.
+	let stored_leaves = read_leaves::<Block>(db)?;
+	for (number, hash) in &stored_leaves {
+		let (mut number, mut hash) = (*number, *hash);
+		while !checker.blocks.contains_key(&hash) {
+			if !checker.read_block(number, hash, "leaf set")? || number.is_zero() {
+				break
+			}
+			hash = checker.blocks[&hash].parent_hash;
+			number -= One::one();
+		}
+	}
+
+	// Descendants of the last finalized block in the children index.
+	let mut stored_children = HashMap::new();
+	let mut queue: VecDeque<_> = [(info.finalized_number, info.finalized_hash)].into();
+	while let Some((number, hash)) = queue.pop_front() {
+		if stored_children.contains_key(&hash) || !checker.blocks.contains_key(&hash) {
+			continue
+		}
+		let children: Vec<Block::Hash> =
+			children::read_children(db, columns::META, meta_keys::CHILDREN_PREFIX, hash)?;
+		for child in &children {
+			if checker.read_block(number + One::one(), *child, "children index")? {
+				queue.push_back((number + One::one(), *child));
+			}
+		}
+		stored_children.insert(hash, children);
+	}
+
+	// Derived indices of the unfinalized blocks.
+	let mut hashes: Vec<_> =
+		checker.blocks.iter().map(|(hash, block)| (block.number, *hash)).collect();
+	hashes.sort();
+	let mut expected_children: HashMap<_, Vec<_>> = HashMap::new();
+	for (number, hash) in &hashes {
+		if *number > info.finalized_number {
+			let parent_hash = checker.blocks[hash].parent_hash;
+			expected_children.entry(parent_hash).or_default().push(*hash);
+		}
+	}
+	let mut children_updates = Vec::new();
+	for (number, hash) in &hashes {
+		if *number < info.finalized_number {
+			continue
+		}
+		let stored = match stored_children.get(hash) {
+			Some(stored) => stored.clone(),
+			None => children::read_children(db, columns::META, meta_keys::CHILDREN_PREFIX, *hash)?,
+		};
+		let expected = expected_children.get(hash).cloned().unwrap_or_default();
+		let missing = difference(&expected, &stored);
+		let stale = difference(&stored, &expected);
+		if !missing.is_empty() || !stale.is_empty() {
+			checker.issues.push(DbIssue::Children { hash: *hash, missing, stale });
+			children_updates.push((*hash, expected));
+		}
+	}
+
+	let mut expected_leaves = LeafSet::new();
+	for (number, hash) in &hashes {
+		if *number >= info.finalized_number && !expected_children.contains_key(hash) {
+			expected_leaves.import(*hash, *number, checker.blocks[hash].parent_hash);
+		}
+	}
+	let stored_leaves: Vec<_> = stored_leaves.into_iter().map(|(_, hash)| hash).collect();
+	let missing = difference(&expected_leaves.hashes(), &stored_leaves);
+	let stale = difference(&stored_leaves, &expected_leaves.hashes());
+	let rebuild_leaves = !missing.is_empty() || !stale.is_empty();
+	if rebuild_leaves {
+		checker.issues.push(DbIssue::Leaves { missing, stale });
+	}
+
+	// Bodies and justifications, which are read through the hash lookup.
+	let unreachable: HashSet<_> = checker
+		.issues
+		.iter()
+		.filter_map(|issue| match issue {
+			DbIssue::HashLookup { hash, .. } => Some(*hash),
+			_ => None,
+		})
+		.collect();
+	for (number, hash) in hashes.iter().filter(|(_, hash)| !unreachable.contains(hash)) {
+		let pruned = match backend.blocks_pruning {
+			BlocksPruning::Some(blocks_pruning) =>
+				*number + blocks_pruning.into() <= info.finalized_number &&
+					!backend.blocks_retention.is_kept(*number),
+			BlocksPruning::All => false,
+		};
+		let in_gap = info.block_gap.map_or(false, |(_, end)| *number <= end);
+		match backend.blockchain.body(BlockId::Hash(*hash)) {
+			Ok(None) if !pruned && !in_gap =>
+				checker.issues.push(DbIssue::MissingBody { hash: *hash, number: *number }),
+			Ok(_) => (),
+			Err(e) => checker.issues.push(DbIssue::InvalidEntry {
+				hash: *hash,
+				entry: "body",
+				error: e.to_string(),
+			}),
+		}
+		if let Err(e) = backend.blockchain.justifications(BlockId::Hash(*hash)) {
+			checker.issues.push(DbIssue::InvalidEntry {
+				hash: *hash,
+				entry: "justifications",
+				error: e.to_string(),
+			});
+		}
+	}
+
+	// State of the best and finalized blocks.
+	let mut state_entries = 0;
+	let mut with_state = vec![(info.finalized_number, info.finalized_hash)];
+	if info.best_hash != info.finalized_hash {
+		with_state.push((info.best_number, info.best_hash));
+	}
+	for (number, hash) in with_state {
+		let state_root = match checker.blocks.get(&hash) {
+			Some(block) if backend.have_state_at(&hash, number) => block.state_root,
+			_ => continue,
+		};
+		match read_state(&backend.storage, &state_root) {
+			Ok(entries) => state_entries += entries,
+			Err(error) => checker.issues.push(DbIssue::MissingTrieNode { hash, number, error }),
+		}
+	}
+	let state_nodes = match check_orphaned_nodes(backend, &checker)? {
+		Some((nodes, issue)) => {
+			checker.issues.extend(issue);
+			Some(nodes)
+		},
+		None => None,
+	};
+
+	// Journal of the non-canonical state overlay.
+	let mut discarded_journals = Vec::new();
+	let journal =
+		sc_state_db::inspect_non_canonical_journal::<Block::Hash, Vec<u8>, _>(&StateMetaDb(db))
+			.map_err(ClientError::from_state_db)?;
+	for entry in journal {
+		if let Some(error) = entry.error {
+			checker.issues.push(DbIssue::NonCanonicalJournal {
+				number: entry.number,
+				index: entry.index,
+				hash: entry.hash,
+				error: error.to_string(),
+			});
+			discarded_journals.push(entry.key);
+		}
+	}
+
+	let repaired = repair && checker.issues.iter().any(DbIssue::is_repairable);
+	if repaired {
+		let mut transaction = Transaction::new();
+		for key in discarded_journals {
+			transaction.remove(columns::STATE_META, &key);
+		}
+		for issue in &checker.issues {
+			if let DbIssue::HashLookup { hash, number } = issue {
+				utils::insert_hash_to_key_mapping(
+					&mut transaction,
+					columns::KEY_LOOKUP,
+					*number,
+					*hash,
+				)?;
+			}
+		}
+		for (number, hash) in number_lookups {
+			utils::insert_number_to_key_mapping(
+				&mut transaction,
+				columns::KEY_LOOKUP,
+				number,
+				hash,
+			)?;
+		}
+		for (hash, children) in children_updates {
+			if children.is_empty() {
+				children::remove_children(
+					&mut transaction,
+					columns::META,
+					meta_keys::CHILDREN_PREFIX,
+					hash,
+				);
+			} else {
+				children::write_children(
+					&mut transaction,
+					columns::META,
+					meta_keys::CHILDREN_PREFIX,
+					hash,
+					children,
+				);
+			}
+		}
+		let mut leaves = backend.blockchain.leaves.write();
+		if rebuild_leaves {
+			expected_leaves.prepare_transaction(
+				&mut transaction,
+				columns::META,
+				meta_keys::LEAF_PREFIX,
+			);
+		}
+		db.commit(transaction)?;
+		if rebuild_leaves {
+			*leaves = expected_leaves;
+		}
+	}
+
+	Ok(DbCheckReport {
+		blocks: checker.blocks.len(),
+		state_entries,
+		state_nodes,
+		issues: checker.issues,
+		repaired,
+	})
+}
+
+/// Check that the nodes of the state column are part of the state of the blocks found. Returns the
+/// number of nodes checked along with the issue of the orphaned ones, or `None` if the state is
+/// archived, if the archived state is still being removed or if the state column can't be
+/// iterated.
+fn check_orphaned_nodes<Block: BlockT>(
+	backend: &Backend<Block>,
+	checker: &Checker<Block>,
+) -> ClientResult<Option<(u64, Option<DbIssue<Block>>)>> {
+	let storage = &*backend.storage;
+	if storage.state_db.pruning_mode().is_archive() || storage.state_db.archive_gc_next().is_some()
+	{
+		return Ok(None)
+	}
+
+	let mut roots: Vec<_> = checker
+		.blocks
+		.iter()
+		.filter(|(hash, block)| backend.have_state_at(hash, block.number))
+		.map(|(_, block)| (block.number, block.state_root))
+		.collect();
+	// The nodes of the last pruned state are only removed with the next block leaving the window.
+	let last_pruned = sc_state_db::fetch_last_pruned(&StateMetaDb(&*storage.db))
+		.map_err(ClientError::from_state_db)?;
+	if let Some(number) = last_pruned {
+		let number = number.saturated_into();
+		if let Some(block) =
+			checker.canonical_hash(number)?.and_then(|hash| checker.blocks.get(&hash))
+		{
+			roots.push((number, block.state_root));
+		}
+	}
+	roots.sort();
+	roots.dedup();
+
+	// The walked keys are the keys of the nodes in databases storing them by trie position.
+	let db_key = |key: &[u8]| -> Vec<u8> {
+		if storage.prefix_keys {
+			key.to_vec()
+		} else {
+			key[key.len() - DB_HASH_LEN..].to_vec()
+		}
+	};
+	let read = |key: &[u8]| storage.state_db.get(&db_key(key), storage).ok().flatten();
+	let mut live = HashSet::new();
+	let mut previous = Vec::new();
+	for (_, root) in roots {
+		let root = root.as_ref().to_vec();
+		UnsharedNodes::<HashFor<Block>>::new(&read, &mut |key, _| {
+			live.insert(db_key(&key));
+			Ok(())
+		})
+		.walk(root.clone(), &previous)?;
+		previous = vec![root];
+	}
+
+	let (mut nodes, mut count, mut bytes) = (0, 0, 0);
+	let iterated = storage.db.iter_column(columns::STATE, &mut |key, value| {
+		let key = match key {
+			Some(key) => key.to_vec(),
+			None => <HashFor<Block> as Hasher>::hash(value).as_ref().to_vec(),
+		};
+		nodes += 1;
+		if !live.contains(&key) {
+			count += 1;
+			bytes += (key.len() + value.len()) as u64;
+		}
+		true
+	});
+	if !iterated {
+		return Ok(None)
+	}
+	Ok(Some((nodes, (count > 0).then(|| DbIssue::OrphanedTrieNodes { count, bytes }))))
+}
+
+/// Returns the leaves stored in the database along with their number.
+fn read_leaves<Block: BlockT>(
+	db: &dyn Database<DbHash>,
+) -> ClientResult<Vec<(NumberFor<Block>, Block::Hash)>> {
+	let leaves = match db.get(columns::META, meta_keys::LEAF_PREFIX) {
+		Some(leaves) => Vec::<(NumberFor<Block>, Vec<Block::Hash>)>::decode(&mut &leaves[..])
+			.map_err(|e| ClientError::Backend(format!("Error decoding leaves: {}", e)))?,
+		None => Vec::new(),
+	};
+	Ok(leaves
+		.into_iter()
+		.flat_map(|(number, hashes)| hashes.into_iter().map(move |hash| (number, hash)))
+		.collect())
+}
+
+/// Returns the items of `a` that are not in `b`.
+fn difference<T: Clone + Eq + std::hash::Hash>(a: &[T], b: &[T]) -> Vec<T> {
+	let b: HashSet<_> = b.iter().collect();
+	a.iter().filter(|item| !b.contains(item)).cloned().collect()
+}
+
+/// Read all the storage entries of the state with `root`, including child tries. Returns the
+/// number of entries.
+pub(crate) fn read_state<Block: BlockT>(
+	storage: &StorageDb<Block>,
+	root: &Block::Hash,
+) -> Result<u64, String> {
+	let nodes = StateNodes { storage, empty: <HashFor<Block> as Hasher>::hash(&[0u8]) };
+	let trie = TrieDBBuilder::<HashFor<Block>>::new(&nodes, root).build();
+	let mut entries = 0;
+	for item in trie.iter().map_err(|e| e.to_string())? {
+		let (key, value) = item.map_err(|e| e.to_string())?;
+		entries += 1;
+		if let Some(storage_key) =
+			key.strip_prefix(well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX)
+		{
+			let child_info = ChildInfo::new_default(storage_key);
+			let child_root = Block::Hash::decode(&mut &value[..])
+				.map_err(|e| format!("Invalid child trie root: {}", e))?;
+			let child_nodes = KeySpacedDB::new(&nodes, child_info.keyspace());
+			let child = TrieDBBuilder::<HashFor<Block>>::new(&child_nodes, &child_root).build();
+			for item in child.iter().map_err(|e| e.to_string())? {
+				item.map_err(|e| e.to_string())?;
+				entries += 1;
+			}
+		}
+	}
+	Ok(entries)
+}
+
+/// Trie nodes of the state database, including those of the non-canonical blocks.
+struct StateNodes<'a, Block: BlockT> {
+	storage: &'a StorageDb<Block>,
+	empty: Block::Hash,
+}
+
+impl<'a, Block: BlockT> HashDBRef<HashFor<Block>, DBValue> for StateNodes<'a, Block> {
+	fn get(&self, key: &Block::Hash, prefix: Prefix) -> Option<DBValue> {
+		if *key == self.empty {
+			return Some([0u8].to_vec())
+		}
+		sp_state_machine::Storage::get(self.storage, key, prefix).ok().flatten()
+	}
+
+	fn contains(&self, key: &Block::Hash, prefix: Prefix) -> bool {
+		HashDBRef::get(self, key, prefix).is_some()
+	}
+}
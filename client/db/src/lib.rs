@@ -32,10 +32,19 @@ pub mod offchain;
 
 pub mod bench;
 
+mod archive_gc;
+mod changes_index;
+mod check;
 mod children;
+#[cfg(any(feature = "rocksdb", test))]
+mod migration;
 mod parity_db;
 mod record_stats_state;
+#[cfg(feature = "rocksdb")]
+mod secondary;
+mod snapshot;
 mod stats;
+mod trie_nodes;
 #[cfg(any(feature = "rocksdb", test))]
 mod upgrade;
 mod utils;
@@ -43,9 +52,11 @@ mod utils;
 use linked_hash_map::LinkedHashMap;
 use log::{debug, trace, warn};
 use parking_lot::{Mutex, RwLock};
+use prometheus_endpoint::{PrometheusError, Registry};
 use std::{
 	collections::{HashMap, HashSet},
 	io,
+	ops::RangeInclusive,
 	path::{Path, PathBuf},
 	sync::Arc,
 };
@@ -63,7 +74,7 @@ use sc_client_api::{
 	utils::is_descendent_of,
 	IoInfo, MemoryInfo, MemorySize, UsageInfo,
 };
-use sc_state_db::StateDb;
+use sc_state_db::{IsPruned, StateDb};
 use sp_arithmetic::traits::Saturating;
 use sp_blockchain::{
 	well_known_cache_keys, Backend as _, CachedHeaderMetadata, Error as ClientError, HeaderBackend,
@@ -91,9 +102,12 @@ use sp_trie::{cache::SharedTrieCache, prefixed_key, MemoryDB, PrefixedMemoryDB};
 
 // Re-export the Database trait so that one can pass an implementation of it.
 pub use sc_state_db::PruningMode;
-pub use sp_database::Database;
+pub use sp_database::{ColumnStats, Database};
 
 pub use bench::BenchmarkingState;
+pub use check::{DbCheckReport, DbIssue};
+#[cfg(feature = "rocksdb")]
+pub use migration::{migrate_paritydb_to_rocksdb, migrate_rocksdb_to_paritydb, MigratedColumn};
 
 const CACHE_HEADERS: usize = 8;
 
@@ -317,6 +331,11 @@ pub struct DatabaseSettings {
 	///
 	/// NOTE: only finalized blocks are subject for removal!
 	pub blocks_pruning: BlocksPruning,
+	/// Retention of the indexed transactions and bodies beyond `blocks_pruning`.
+	pub blocks_retention: BlocksRetention,
+	/// Index the storage keys modified by each block, to look up the changes of a key without
+	/// reading the state of every block. See [`sc_client_api::backend::ChangesIndex`].
+	pub changes_index: bool,
 }
 
 /// Block pruning settings.
@@ -328,6 +347,29 @@ pub enum BlocksPruning {
 	Some(u32),
 }
 
+/// Retention of the indexed transactions and bodies of finalized blocks, applied on top of
+/// [`BlocksPruning::Some`]. Ignored when all blocks are kept.
+///
+/// Headers and justifications are never pruned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlocksRetention {
+	/// Number of recent finalized blocks to keep the indexed transactions of. Must not be lower
+	/// than the number of blocks kept by [`BlocksPruning::Some`].
+	pub transactions: Option<u32>,
+	/// Ranges of block numbers of which the canonical bodies and indexed transactions are never
+	/// pruned.
+	pub keep_ranges: Vec<RangeInclusive<u32>>,
+}
+
+impl BlocksRetention {
+	/// Whether the canonical block `number` is in one of the kept ranges.
+	pub(crate) fn is_kept<N: From<u32> + PartialOrd>(&self, number: N) -> bool {
+		self.keep_ranges
+			.iter()
+			.any(|range| N::from(*range.start()) <= number && number <= N::from(*range.end()))
+	}
+}
+
 /// Where to find the database..
 #[derive(Debug, Clone)]
 pub enum DatabaseSource {
@@ -350,6 +392,18 @@ pub enum DatabaseSource {
 		cache_size: usize,
 	},
 
+	/// Open the RocksDB database of another node at a given path, read-only. The database is
+	/// opened as a secondary instance that catches up with the writes of the node owning it.
+	#[cfg(feature = "rocksdb")]
+	RocksDbSecondary {
+		/// Path to the database.
+		path: PathBuf,
+		/// Path to the files of the secondary instance.
+		secondary_path: PathBuf,
+		/// Cache size in MiB.
+		cache_size: usize,
+	},
+
 	/// Load a ParityDb database from a given path.
 	ParityDb {
 		/// Path to the database.
@@ -377,6 +431,8 @@ impl DatabaseSource {
 			DatabaseSource::Auto { paritydb_path, .. } => Some(paritydb_path),
 			#[cfg(feature = "rocksdb")]
 			DatabaseSource::RocksDb { path, .. } => Some(path),
+			#[cfg(feature = "rocksdb")]
+			DatabaseSource::RocksDbSecondary { path, .. } => Some(path),
 			DatabaseSource::ParityDb { path } => Some(path),
 			DatabaseSource::Custom { .. } => None,
 		}
@@ -394,6 +450,11 @@ impl DatabaseSource {
 				*path = p.into();
 				true
 			},
+			#[cfg(feature = "rocksdb")]
+			DatabaseSource::RocksDbSecondary { ref mut path, .. } => {
+				*path = p.into();
+				true
+			},
 			DatabaseSource::ParityDb { ref mut path } => {
 				*path = p.into();
 				true
@@ -401,6 +462,15 @@ impl DatabaseSource {
 			DatabaseSource::Custom { .. } => false,
 		}
 	}
+
+	/// Whether the database is opened read-only.
+	pub fn is_read_only(&self) -> bool {
+		match self {
+			#[cfg(feature = "rocksdb")]
+			DatabaseSource::RocksDbSecondary { .. } => true,
+			_ => false,
+		}
+	}
 }
 
 impl std::fmt::Display for DatabaseSource {
@@ -409,6 +479,8 @@ impl std::fmt::Display for DatabaseSource {
 			DatabaseSource::Auto { .. } => "Auto",
 			#[cfg(feature = "rocksdb")]
 			DatabaseSource::RocksDb { .. } => "RocksDb",
+			#[cfg(feature = "rocksdb")]
+			DatabaseSource::RocksDbSecondary { .. } => "RocksDbSecondary",
 			DatabaseSource::ParityDb { .. } => "ParityDb",
 			DatabaseSource::Custom { .. } => "Custom",
 		};
@@ -431,6 +503,10 @@ pub(crate) mod columns {
 	/// Transactions
 	pub const TRANSACTION: u32 = 11;
 	pub const BODY_INDEX: u32 = 12;
+	/// Index of the storage keys modified by each block.
+	pub const CHANGES_INDEX: u32 = 13;
+	/// State downloaded by an unfinished state sync.
+	pub const STATE_SYNC: u32 = 14;
 }
 
 struct PendingBlock<Block: BlockT> {
@@ -442,7 +518,7 @@ struct PendingBlock<Block: BlockT> {
 }
 
 // wrapper that implements trait required for state_db
-struct StateMetaDb<'a>(&'a dyn Database<DbHash>);
+pub(crate) struct StateMetaDb<'a>(pub(crate) &'a dyn Database<DbHash>);
 
 impl<'a> sc_state_db::MetaDb for StateMetaDb<'a> {
 	type Error = sp_database::error::DatabaseError;
@@ -518,6 +594,53 @@ impl<Block: BlockT> BlockchainDb<Block> {
 		let mut meta = self.meta.write();
 		meta.block_gap = gap;
 	}
+
+	/// Indexed transactions of a block whose body was pruned before them.
+	fn retained_indexed_body(&self, id: BlockId<Block>) -> ClientResult<Option<Vec<Vec<u8>>>> {
+		let hashes = match utils::block_id_to_lookup_key(&*self.db, columns::KEY_LOOKUP, id)? {
+			Some(key) => match read_indexed_transactions(&*self.db, &key)? {
+				Some(hashes) => hashes,
+				None => return Ok(None),
+			},
+			None => return Ok(None),
+		};
+		let mut transactions = Vec::new();
+		for hash in hashes {
+			match self.db.get(columns::TRANSACTION, hash.as_ref()) {
+				Some(t) => transactions.push(t),
+				None =>
+					return Err(sp_blockchain::Error::Backend(format!(
+						"Missing indexed transaction {:?}",
+						hash
+					))),
+			}
+		}
+		Ok(Some(transactions))
+	}
+}
+
+/// Key in the META column of the indexed transactions of the block with `lookup_key`, which are
+/// kept there once the body of the block is pruned.
+fn indexed_transactions_key(lookup_key: &[u8]) -> Vec<u8> {
+	let mut key = meta_keys::INDEXED_TRANSACTIONS_PREFIX.to_vec();
+	key.extend_from_slice(lookup_key);
+	key
+}
+
+fn read_indexed_transactions(
+	db: &dyn Database<DbHash>,
+	lookup_key: &[u8],
+) -> ClientResult<Option<Vec<DbHash>>> {
+	match db.get(columns::META, &indexed_transactions_key(lookup_key)) {
+		Some(hashes) => match Decode::decode(&mut &hashes[..]) {
+			Ok(hashes) => Ok(Some(hashes)),
+			Err(err) => Err(sp_blockchain::Error::Backend(format!(
+				"Error decoding indexed transactions: {}",
+				err
+			))),
+		},
+		None => Ok(None),
+	}
 }
 
 impl<Block: BlockT> sc_client_api::blockchain::HeaderBackend<Block> for BlockchainDb<Block> {
@@ -682,7 +805,7 @@ impl<Block: BlockT> sc_client_api::blockchain::Backend<Block> for BlockchainDb<B
 	fn block_indexed_body(&self, id: BlockId<Block>) -> ClientResult<Option<Vec<Vec<u8>>>> {
 		let body = match read_db(&*self.db, columns::KEY_LOOKUP, columns::BODY_INDEX, id)? {
 			Some(body) => body,
-			None => return Ok(None),
+			None => return self.retained_indexed_body(id),
 		};
 		match Vec::<DbExtrinsic<Block>>::decode(&mut &body[..]) {
 			Ok(index) => {
@@ -756,6 +879,8 @@ pub struct BlockImportOperation<Block: BlockT> {
 	finalized_blocks: Vec<(BlockId<Block>, Option<Justification>)>,
 	set_head: Option<BlockId<Block>>,
 	commit_state: bool,
+	/// Whether the state changes of the block are unknown, as its state was set in full.
+	unknown_changes: bool,
 	index_ops: Vec<IndexOperation>,
 }
 
@@ -809,6 +934,7 @@ impl<Block: BlockT> BlockImportOperation<Block> {
 		);
 
 		self.db_updates = transaction;
+		self.unknown_changes = true;
 		Ok(root)
 	}
 }
@@ -1016,6 +1142,9 @@ impl<T: Clone> FrozenForDuration<T> {
 	}
 }
 
+/// Catches up with the writes of the node owning a database opened read-only.
+type CatchUp = Box<dyn Fn() -> io::Result<()> + Send + Sync>;
+
 /// Disk backend.
 ///
 /// Disk backend keeps data in a key-value store. In archive mode, trie nodes are kept from all
@@ -1028,10 +1157,29 @@ pub struct Backend<Block: BlockT> {
 	import_lock: Arc<RwLock<()>>,
 	is_archive: bool,
 	blocks_pruning: BlocksPruning,
+	blocks_retention: BlocksRetention,
 	io_stats: FrozenForDuration<(kvdb::IoStats, StateUsageInfo)>,
 	state_usage: Arc<StateUsageStats>,
 	genesis_state: RwLock<Option<Arc<DbGenesisStorage<Block>>>>,
 	shared_trie_cache: Option<sp_trie::cache::SharedTrieCache<HashFor<Block>>>,
+	read_only: bool,
+	catch_up: Option<CatchUp>,
+	changes_index: bool,
+	/// Blocks pinned with [`sc_client_api::Backend::pin_block`].
+	pinned_blocks: RwLock<HashMap<Block::Hash, PinnedBlock>>,
+}
+
+/// Pin references to a block, see [`sc_client_api::Backend::pin_block`].
+#[derive(Default)]
+struct PinnedBlock {
+	/// Number of pins taken on the block.
+	refs: u32,
+	/// Number of these pins which also pinned the state of the block in the state db. The state
+	/// can't be pinned any more once it is pruned.
+	state_refs: u32,
+	/// Whether the body was due to be pruned while the block was pinned, in which case it is
+	/// removed once the last pin is released.
+	prune_deferred: bool,
 }
 
 impl<Block: BlockT> Backend<Block> {
@@ -1043,6 +1191,21 @@ impl<Block: BlockT> Backend<Block> {
 
 		let db_source = &db_config.source;
 
+		#[cfg(feature = "rocksdb")]
+		if let DatabaseSource::RocksDbSecondary { path, secondary_path, cache_size } = db_source {
+			let (db, catch_up) =
+				secondary::open(path, secondary_path, DatabaseType::Full, *cache_size)?;
+			utils::check_database_type(&*db, DatabaseType::Full)?;
+			return Self::from_database(
+				db,
+				canonicalization_delay,
+				&db_config,
+				false,
+				true,
+				Some(catch_up),
+			)
+		}
+
 		let (needs_init, db) =
 			match crate::utils::open_database::<Block>(db_source, DatabaseType::Full, false) {
 				Ok(db) => (false, db),
@@ -1054,7 +1217,26 @@ impl<Block: BlockT> Backend<Block> {
 				Err(as_is) => return Err(as_is.into()),
 			};
 
-		Self::from_database(db as Arc<_>, canonicalization_delay, &db_config, needs_init)
+		Self::from_database(
+			db as Arc<_>,
+			canonicalization_delay,
+			&db_config,
+			needs_init,
+			false,
+			None,
+		)
+	}
+
+	/// Open the existing database of `db_config` read-only, to inspect it without creating,
+	/// upgrading or otherwise writing to it. A RocksDB database is opened as a secondary instance
+	/// keeping its files in `secondary_path`.
+	pub fn open_read_only(
+		db_config: DatabaseSettings,
+		secondary_path: &Path,
+	) -> ClientResult<Self> {
+		let db =
+			utils::open_database_read_only(&db_config.source, DatabaseType::Full, secondary_path)?;
+		Self::from_database(db, 0, &db_config, false, true, None)
 	}
 
 	/// Create new memory-backed client backend for tests.
@@ -1066,18 +1248,142 @@ impl<Block: BlockT> Backend<Block> {
 	/// Create new memory-backed client backend for tests.
 	#[cfg(any(test, feature = "test-helpers"))]
 	pub fn new_test_with_tx_storage(blocks_pruning: u32, canonicalization_delay: u64) -> Self {
+		Self::new_test_db(
+			PruningMode::blocks_pruning(blocks_pruning),
+			BlocksPruning::Some(blocks_pruning),
+			canonicalization_delay,
+			false,
+		)
+	}
+
+	/// Create new memory-backed client backend for tests, indexing the storage changes of blocks.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn new_test_with_changes_index(blocks_pruning: u32, canonicalization_delay: u64) -> Self {
+		Self::new_test_db(
+			PruningMode::blocks_pruning(blocks_pruning),
+			BlocksPruning::Some(blocks_pruning),
+			canonicalization_delay,
+			true,
+		)
+	}
+
+	/// Create new memory-backed client backend for tests, keeping all blocks and states.
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn new_test_archive(canonicalization_delay: u64) -> Self {
+		Self::new_test_db(
+			PruningMode::ArchiveAll,
+			BlocksPruning::All,
+			canonicalization_delay,
+			false,
+		)
+	}
+
+	#[cfg(any(test, feature = "test-helpers"))]
+	fn new_test_db(
+		state_pruning: PruningMode,
+		blocks_pruning: BlocksPruning,
+		canonicalization_delay: u64,
+		changes_index: bool,
+	) -> Self {
 		let db = kvdb_memorydb::create(crate::utils::NUM_COLUMNS);
 		let db = sp_database::as_database(db);
 		let db_setting = DatabaseSettings {
 			trie_cache_maximum_size: Some(16 * 1024 * 1024),
-			state_pruning: Some(PruningMode::blocks_pruning(blocks_pruning)),
+			state_pruning: Some(state_pruning),
 			source: DatabaseSource::Custom { db, require_create_flag: true },
-			blocks_pruning: BlocksPruning::Some(blocks_pruning),
+			blocks_pruning,
+			blocks_retention: Default::default(),
+			changes_index,
 		};
 
 		Self::new(db_setting, canonicalization_delay).expect("failed to create test-db")
 	}
 
+	/// Whether the database is opened read-only, either as a secondary instance of the database
+	/// of another node or with [`Backend::open_read_only`].
+	pub fn is_read_only(&self) -> bool {
+		self.read_only
+	}
+
+	/// Catch up with the writes of the node owning a database opened read-only, reloading the
+	/// chain metadata, the leaves and the state overlay. Does nothing for other databases.
+	pub fn catch_up_with_primary(&self) -> ClientResult<()> {
+		let catch_up = match &self.catch_up {
+			Some(catch_up) => catch_up,
+			None => return Ok(()),
+		};
+		let _lock = self.import_lock.write();
+		catch_up().map_err(|e| {
+			ClientError::Backend(format!("Error catching up with the primary database: {}", e))
+		})?;
+		let db = &*self.storage.db;
+		*self.blockchain.meta.write() = read_meta::<Block>(db, columns::HEADER)?;
+		*self.blockchain.leaves.write() =
+			LeafSet::read_from_db(db, columns::META, meta_keys::LEAF_PREFIX)?;
+		self.blockchain.header_cache.lock().clear();
+		self.storage
+			.state_db
+			.reload(&StateMetaDb(db), !db.supports_ref_counting())
+			.map_err(sp_blockchain::Error::from_state_db)?;
+		Ok(())
+	}
+
+	fn ensure_writable(&self) -> ClientResult<()> {
+		if self.is_read_only() {
+			return Err(ClientError::Backend("The database is opened read-only".into()))
+		}
+		Ok(())
+	}
+
+	/// Check the integrity of the blocks and of the state of the best and finalized blocks.
+	///
+	/// With `repair` set, the hash and number lookups, the leaf set and the children index are
+	/// rebuilt from the block headers if they are found inconsistent.
+	pub fn check_database(&self, repair: bool) -> ClientResult<DbCheckReport<Block>> {
+		if repair {
+			self.ensure_writable()?;
+		}
+		let _lock = self.import_lock.write();
+		check::check(self, repair)
+	}
+
+	/// Size statistics of the database columns, along with their name. This may read the whole
+	/// database.
+	pub fn column_stats(&self) -> Vec<(&'static str, Option<ColumnStats>)> {
+		stats::column_stats(&*self.storage.db)
+	}
+
+	/// Register Prometheus metrics of the estimated number of keys of the database columns,
+	/// sampled periodically in the background. Nothing is registered if the database can't
+	/// estimate it.
+	pub fn register_column_metrics(&self, registry: &Registry) -> Result<(), PrometheusError> {
+		stats::spawn_column_metrics(&self.storage.db, registry)
+	}
+
+	/// Write a snapshot of the state of the block with `hash`, along with its header, body and
+	/// justifications, to `output`. Returns the number of trie nodes written.
+	pub fn export_snapshot(
+		&self,
+		hash: Block::Hash,
+		output: &mut dyn io::Write,
+	) -> ClientResult<u64> {
+		snapshot::export(self, hash, output)
+	}
+
+	/// Seed an empty database with a snapshot written by `export_snapshot`.
+	///
+	/// The state of the snapshot block is checked against its state root before the block is
+	/// imported as finalized, leaving a block gap down to the genesis block. Returns the number
+	/// and hash of the snapshot block.
+	pub fn import_snapshot(
+		&self,
+		input: &mut dyn io::Read,
+	) -> ClientResult<(NumberFor<Block>, Block::Hash)> {
+		self.ensure_writable()?;
+		let _lock = self.import_lock.write();
+		snapshot::import(self, input)
+	}
+
 	/// Expose the Database that is used by this backend.
 	/// The second argument is the Column that stores the State.
 	///
@@ -1100,29 +1406,56 @@ impl<Block: BlockT> Backend<Block> {
 		canonicalization_delay: u64,
 		config: &DatabaseSettings,
 		should_init: bool,
+		read_only: bool,
+		catch_up: Option<CatchUp>,
 	) -> ClientResult<Self> {
 		let mut db_init_transaction = Transaction::new();
 
-		let requested_state_pruning = config.state_pruning.clone();
+		// The stored pruning mode can't be changed without write access.
+		let requested_state_pruning = if read_only { None } else { config.state_pruning.clone() };
 		let state_meta_db = StateMetaDb(db.as_ref());
 		let map_e = sp_blockchain::Error::from_state_db;
 
-		let (state_db_init_commit_set, state_db) = StateDb::open(
-			&state_meta_db,
-			requested_state_pruning,
-			!db.supports_ref_counting(),
-			should_init,
-		)
+		let blockchain = BlockchainDb::new(db.clone())?;
+
+		let stored_state_pruning =
+			sc_state_db::fetch_stored_pruning_mode(&state_meta_db).map_err(map_e)?;
+		let switch_from_archive = match (&requested_state_pruning, &stored_state_pruning) {
+			(Some(requested), Some(stored)) => stored.is_archive() && !requested.is_archive(),
+			_ => false,
+		};
+		let (state_db_init_commit_set, state_db) = match requested_state_pruning {
+			// The archived states could only be removed from a database keyed by trie position.
+			Some(requested) if switch_from_archive && db.supports_ref_counting() =>
+				Err(sc_state_db::StateDbError::IncompatiblePruningModes {
+					stored: stored_state_pruning.unwrap_or_default(),
+					requested,
+				}
+				.into()),
+			Some(requested) if switch_from_archive => {
+				let (finalized, unfinalized) = archive_gc::finalized_with_descendants(&blockchain)?;
+				StateDb::open_from_archive(
+					&state_meta_db,
+					requested,
+					!db.supports_ref_counting(),
+					finalized,
+					unfinalized,
+				)
+			},
+			requested =>
+				StateDb::open(&state_meta_db, requested, !db.supports_ref_counting(), should_init),
+		}
 		.map_err(map_e)?;
 
 		apply_state_commit(&mut db_init_transaction, state_db_init_commit_set);
 
 		let state_pruning_used = state_db.pruning_mode();
 		let is_archive_pruning = state_pruning_used.is_archive();
-		let blockchain = BlockchainDb::new(db.clone())?;
 
 		let storage_db =
 			StorageDb { db: db.clone(), state_db, prefix_keys: !db.supports_ref_counting() };
+		let collect_archive_garbage =
+			storage_db.prefix_keys && storage_db.state_db.archive_gc_next().is_some();
 
 		let offchain_storage = offchain::LocalStorage::new(db.clone());
 
@@ -1136,10 +1469,15 @@ impl<Block: BlockT> Backend<Block> {
 			io_stats: FrozenForDuration::new(std::time::Duration::from_secs(1)),
 			state_usage: Arc::new(StateUsageStats::new()),
 			blocks_pruning: config.blocks_pruning,
+			blocks_retention: config.blocks_retention.clone(),
 			genesis_state: RwLock::new(None),
 			shared_trie_cache: config.trie_cache_maximum_size.map(|maximum_size| {
 				SharedTrieCache::new(sp_trie::cache::CacheSize::Maximum(maximum_size))
 			}),
+			read_only,
+			catch_up,
+			changes_index: config.changes_index,
+			pinned_blocks: Default::default(),
 		};
 
 		// Older DB versions have no last state key. Check if the state is available and set it.
@@ -1160,8 +1498,22 @@ impl<Block: BlockT> Backend<Block> {
 			});
 		}
 
+		changes_index::init::<Block>(
+			&mut db_init_transaction,
+			&*db,
+			config.changes_index,
+			info.best_number,
+		)?;
+
+		if read_only {
+			return Ok(backend)
+		}
 		db.commit(db_init_transaction)?;
 
+		if collect_archive_garbage {
+			archive_gc::spawn(&backend.storage, backend.import_lock.clone());
+		}
+
 		Ok(backend)
 	}
 
@@ -1266,13 +1618,21 @@ impl<Block: BlockT> Backend<Block> {
 		last_finalized: Option<Block::Hash>,
 		justification: Option<Justification>,
 		finalization_displaced: &mut Option<FinalizationOutcome<Block::Hash, NumberFor<Block>>>,
+		index_update: &mut changes_index::IndexUpdate<Block>,
 	) -> ClientResult<MetaUpdate<Block>> {
 		// TODO: ensure best chain contains this block.
 		let number = *header.number();
 		self.ensure_sequential_finalization(header, last_finalized)?;
 		let with_state = sc_client_api::Backend::have_state_at(self, hash, number);
 
-		self.note_finalized(transaction, header, *hash, finalization_displaced, with_state)?;
+		self.note_finalized(
+			transaction,
+			header,
+			*hash,
+			finalization_displaced,
+			with_state,
+			index_update,
+		)?;
 
 		if let Some(justification) = justification {
 			transaction.set_from_vec(
@@ -1329,6 +1689,7 @@ impl<Block: BlockT> Backend<Block> {
 	fn try_commit_operation(&self, mut operation: BlockImportOperation<Block>) -> ClientResult<()> {
 		let mut transaction = Transaction::new();
 		let mut finalization_displaced_leaves = None;
+		let mut index_update = changes_index::IndexUpdate::new(self.changes_index);
 
 		operation.apply_aux(&mut transaction);
 		operation.apply_offchain(&mut transaction);
@@ -1349,6 +1710,7 @@ impl<Block: BlockT> Backend<Block> {
 				Some(last_finalized_hash),
 				justification,
 				&mut finalization_displaced_leaves,
+				&mut index_update,
 			)?);
 			last_finalized_hash = block_hash;
 			last_finalized_num = *block_header.number();
@@ -1405,7 +1767,7 @@ impl<Block: BlockT> Backend<Block> {
 				transaction.set(columns::META, meta_keys::GENESIS_HASH, hash.as_ref());
 
 				if operation.commit_state {
-					transaction.set_from_vec(columns::META, meta_keys::FINALIZED_STATE, lookup_key);
+					transaction.set(columns::META, meta_keys::FINALIZED_STATE, &lookup_key);
 				} else {
 					// When we don't want to commit the genesis state, we still preserve it in
 					// memory to bootstrap consensus. It is queried for an initial list of
@@ -1475,6 +1837,17 @@ impl<Block: BlockT> Backend<Block> {
 						sp_blockchain::Error::from_state_db(e)
 					})?;
 				apply_state_commit(&mut transaction, commit);
+				if !number.is_zero() && !operation.unknown_changes {
+					index_update.insert_block(
+						&mut transaction,
+						&lookup_key,
+						hash,
+						changes_index::block_changes(
+							&operation.storage_updates,
+							&operation.child_storage_updates,
+						),
+					);
+				}
 				if number <= last_finalized_num {
 					// Canonicalize in the db when re-importing existing blocks with state.
 					let commit = self.storage.state_db.canonicalize_block(&hash).map_err(
@@ -1526,6 +1899,7 @@ impl<Block: BlockT> Backend<Block> {
 					hash,
 					&mut finalization_displaced_leaves,
 					operation.commit_state,
+					&mut index_update,
 				)?;
 			} else {
 				// canonicalize blocks which are old enough, regardless of finality.
@@ -1633,6 +2007,7 @@ impl<Block: BlockT> Backend<Block> {
 			}
 		}
 
+		index_update.apply(&mut transaction);
 		self.storage.db.commit(transaction)?;
 
 		// Apply all in-memory state changes.
@@ -1663,6 +2038,7 @@ impl<Block: BlockT> Backend<Block> {
 		f_hash: Block::Hash,
 		displaced: &mut Option<FinalizationOutcome<Block::Hash, NumberFor<Block>>>,
 		with_state: bool,
+		index_update: &mut changes_index::IndexUpdate<Block>,
 	) -> ClientResult<()> {
 		let f_num = *f_header.number();
 
@@ -1685,9 +2061,10 @@ impl<Block: BlockT> Backend<Block> {
 				)?;
 			apply_state_commit(transaction, commit);
 		}
+		index_update.finalize_block(transaction, &*self.storage.db, f_num, f_hash)?;
 
 		let new_displaced = self.blockchain.leaves.write().finalize_height(f_num);
-		self.prune_blocks(transaction, f_num, &new_displaced)?;
+		self.prune_blocks(transaction, f_num, &new_displaced, index_update)?;
 		match displaced {
 			x @ &mut None => *x = Some(new_displaced),
 			&mut Some(ref mut displaced) => displaced.merge(new_displaced),
@@ -1701,13 +2078,30 @@ impl<Block: BlockT> Backend<Block> {
 		transaction: &mut Transaction<DbHash>,
 		finalized: NumberFor<Block>,
 		displaced: &FinalizationOutcome<Block::Hash, NumberFor<Block>>,
+		index_update: &mut changes_index::IndexUpdate<Block>,
 	) -> ClientResult<()> {
 		if let BlocksPruning::Some(blocks_pruning) = self.blocks_pruning {
+			let retention = &self.blocks_retention;
 			// Always keep the last finalized block
 			let keep = std::cmp::max(blocks_pruning, 1);
+			let keep_transactions = retention.transactions.unwrap_or(0);
+			let defer_transactions = keep_transactions > keep;
 			if finalized >= keep.into() {
 				let number = finalized.saturating_sub(keep.into());
-				self.prune_block(transaction, BlockId::<Block>::number(number))?;
+				if let Some(hash) = self.blockchain.hash(number)? {
+					index_update.prune_block(transaction, &*self.storage.db, number, hash)?;
+				}
+				if !retention.is_kept(number) {
+					self.prune_block(
+						transaction,
+						BlockId::<Block>::number(number),
+						defer_transactions,
+					)?;
+				}
+			}
+			if defer_transactions && finalized >= keep_transactions.into() {
+				let number = finalized.saturating_sub(keep_transactions.into());
+				self.prune_indexed_transactions(transaction, number)?;
 			}
 
 			// Also discard all blocks from displaced branches
@@ -1722,7 +2116,7 @@ impl<Block: BlockT> Backend<Block> {
 					let id = BlockId::<Block>::hash(hash);
 					match self.blockchain.header(id)? {
 						Some(header) => {
-							self.prune_block(transaction, id)?;
+							self.prune_block(transaction, id, false)?;
 							number = header.number().saturating_sub(One::one());
 							hash = *header.parent_hash();
 						},
@@ -1734,11 +2128,21 @@ impl<Block: BlockT> Backend<Block> {
 		Ok(())
 	}
 
+	/// Remove the body of a block. With `defer_transactions`, the indexed transactions of the
+	/// block are kept until [`Self::prune_indexed_transactions`] is called for it.
 	fn prune_block(
 		&self,
 		transaction: &mut Transaction<DbHash>,
 		id: BlockId<Block>,
+		defer_transactions: bool,
 	) -> ClientResult<()> {
+		if let Some(hash) = self.blockchain.block_hash_from_id(&id)? {
+			if let Some(pinned) = self.pinned_blocks.write().get_mut(&hash) {
+				debug!(target: "db", "Deferring removal of pinned block #{}", id);
+				pinned.prune_deferred = true;
+				return Ok(())
+			}
+		}
 		debug!(target: "db", "Removing block #{}", id);
 		utils::remove_from_db(
 			transaction,
@@ -1747,6 +2151,11 @@ impl<Block: BlockT> Backend<Block> {
 			columns::BODY,
 			id,
 		)?;
+		if let Some(key) =
+			utils::block_id_to_lookup_key(&*self.storage.db, columns::KEY_LOOKUP, id)?
+		{
+			changes_index::remove_block(transaction, &key);
+		}
 		if let Some(index) =
 			read_db(&*self.storage.db, columns::KEY_LOOKUP, columns::BODY_INDEX, id)?
 		{
@@ -1757,19 +2166,84 @@ impl<Block: BlockT> Backend<Block> {
 				columns::BODY_INDEX,
 				id,
 			)?;
-			match Vec::<DbExtrinsic<Block>>::decode(&mut &index[..]) {
-				Ok(index) =>
-					for ex in index {
-						if let DbExtrinsic::Indexed { hash, .. } = ex {
-							transaction.release(columns::TRANSACTION, hash);
-						}
-					},
+			let hashes: Vec<_> = match Vec::<DbExtrinsic<Block>>::decode(&mut &index[..]) {
+				Ok(index) => index
+					.into_iter()
+					.filter_map(|ex| match ex {
+						DbExtrinsic::Indexed { hash, .. } => Some(hash),
+						DbExtrinsic::Full(_) => None,
+					})
+					.collect(),
 				Err(err) =>
 					return Err(sp_blockchain::Error::Backend(format!(
 						"Error decoding body list: {}",
 						err
 					))),
+			};
+			if !defer_transactions {
+				for hash in hashes {
+					transaction.release(columns::TRANSACTION, hash);
+				}
+			} else if !hashes.is_empty() {
+				if let Some(key) =
+					utils::block_id_to_lookup_key(&*self.storage.db, columns::KEY_LOOKUP, id)?
+				{
+					transaction.set_from_vec(
+						columns::META,
+						&indexed_transactions_key(&key),
+						hashes.encode(),
+					);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Remove the body of a block whose pruning was deferred because it was pinned. Must be called
+	/// with the import lock held.
+	fn prune_unpinned_block(&self, hash: Block::Hash) -> ClientResult<()> {
+		let number = match self.blockchain.number(hash)? {
+			Some(number) => number,
+			None => return Ok(()),
+		};
+		// Keep the indexed transactions if they are still within their retention period, they
+		// are then removed along with the ones of the other blocks at this height.
+		let defer_transactions = match self.blocks_pruning {
+			BlocksPruning::Some(blocks_pruning) => {
+				let keep_transactions = self.blocks_retention.transactions.unwrap_or(0);
+				let finalized = self.blockchain.info().finalized_number;
+				keep_transactions > blocks_pruning &&
+					number > finalized.saturating_sub(keep_transactions.into())
+			},
+			BlocksPruning::All => false,
+		};
+		let mut transaction = Transaction::new();
+		self.prune_block(&mut transaction, BlockId::Hash(hash), defer_transactions)?;
+		self.storage.db.commit(transaction)?;
+		Ok(())
+	}
+
+	/// Release the indexed transactions of the canonical block `number`, if they were kept after
+	/// its body was pruned.
+	fn prune_indexed_transactions(
+		&self,
+		transaction: &mut Transaction<DbHash>,
+		number: NumberFor<Block>,
+	) -> ClientResult<()> {
+		let key = match utils::block_id_to_lookup_key(
+			&*self.storage.db,
+			columns::KEY_LOOKUP,
+			BlockId::<Block>::number(number),
+		)? {
+			Some(key) => key,
+			None => return Ok(()),
+		};
+		if let Some(hashes) = read_indexed_transactions(&*self.storage.db, &key)? {
+			debug!(target: "db", "Removing indexed transactions of block #{}", number);
+			for hash in hashes {
+				transaction.release(columns::TRANSACTION, hash);
 			}
+			transaction.remove(columns::META, &indexed_transactions_key(&key));
 		}
 		Ok(())
 	}
@@ -1899,6 +2373,28 @@ where
 	}
 }
 
+impl<Block: BlockT> sc_client_api::backend::StateSyncStore for Backend<Block> {
+	fn insert_state_sync_chunk(&self, index: u32, chunk: &[u8]) -> ClientResult<()> {
+		let mut transaction = Transaction::new();
+		transaction.set(columns::STATE_SYNC, &index.to_be_bytes(), chunk);
+		self.storage.db.commit(transaction)?;
+		Ok(())
+	}
+
+	fn state_sync_chunk(&self, index: u32) -> ClientResult<Option<Vec<u8>>> {
+		Ok(self.storage.db.get(columns::STATE_SYNC, &index.to_be_bytes()))
+	}
+
+	fn remove_state_sync_chunks(&self, count: u32) -> ClientResult<()> {
+		let mut transaction = Transaction::new();
+		for index in 0..count {
+			transaction.remove(columns::STATE_SYNC, &index.to_be_bytes());
+		}
+		self.storage.db.commit(transaction)?;
+		Ok(())
+	}
+}
+
 impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 	type BlockImportOperation = BlockImportOperation<Block>;
 	type Blockchain = BlockchainDb<Block>;
@@ -1917,6 +2413,7 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 			finalized_blocks: Vec::new(),
 			set_head: None,
 			commit_state: false,
+			unknown_changes: false,
 			index_ops: Default::default(),
 		})
 	}
@@ -1937,6 +2434,7 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 	}
 
 	fn commit_operation(&self, operation: Self::BlockImportOperation) -> ClientResult<()> {
+		self.ensure_writable()?;
 		let usage = operation.old_state.usage_info();
 		self.state_usage.merge_sm(usage);
 
@@ -1957,10 +2455,12 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 		block: BlockId<Block>,
 		justification: Option<Justification>,
 	) -> ClientResult<()> {
+		self.ensure_writable()?;
 		let mut transaction = Transaction::new();
 		let hash = self.blockchain.expect_block_hash_from_id(&block)?;
 		let header = self.blockchain.expect_header(block)?;
 		let mut displaced = None;
+		let mut index_update = changes_index::IndexUpdate::new(self.changes_index);
 
 		let m = self.finalize_block_with_transaction(
 			&mut transaction,
@@ -1969,7 +2469,9 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 			None,
 			justification,
 			&mut displaced,
+			&mut index_update,
 		)?;
+		index_update.apply(&mut transaction);
 		self.storage.db.commit(transaction)?;
 		self.blockchain.update_meta(m);
 		Ok(())
@@ -1980,6 +2482,7 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 		block: BlockId<Block>,
 		justification: Justification,
 	) -> ClientResult<()> {
+		self.ensure_writable()?;
 		let mut transaction: Transaction<DbHash> = Transaction::new();
 		let hash = self.blockchain.expect_block_hash_from_id(&block)?;
 		let header = self.blockchain.expect_header(block)?;
@@ -2054,11 +2557,20 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 		})
 	}
 
+	fn changes_index(&self) -> Option<&dyn sc_client_api::backend::ChangesIndex<Block>> {
+		if self.changes_index {
+			Some(self)
+		} else {
+			None
+		}
+	}
+
 	fn revert(
 		&self,
 		n: NumberFor<Block>,
 		revert_finalized: bool,
 	) -> ClientResult<(NumberFor<Block>, HashSet<Block::Hash>)> {
+		self.ensure_writable()?;
 		let mut reverted_finalized = HashSet::new();
 
 		let info = self.blockchain.info();
@@ -2152,6 +2664,10 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 						}
 						transaction.set_from_vec(columns::META, meta_keys::BEST_BLOCK, key);
 						transaction.remove(columns::KEY_LOOKUP, removed.hash().as_ref());
+						changes_index::remove_block(
+							&mut transaction,
+							&utils::number_and_hash_to_lookup_key(*removed.number(), removed_hash)?,
+						);
 						children::remove_children(
 							&mut transaction,
 							columns::META,
@@ -2196,6 +2712,7 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 	}
 
 	fn remove_leaf_block(&self, hash: &Block::Hash) -> ClientResult<()> {
+		self.ensure_writable()?;
 		let best_hash = self.blockchain.info().best_hash;
 
 		if best_hash == *hash {
@@ -2223,6 +2740,10 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 			apply_state_commit(&mut transaction, commit);
 		}
 		transaction.remove(columns::KEY_LOOKUP, hash.as_ref());
+		changes_index::remove_block(
+			&mut transaction,
+			&utils::number_and_hash_to_lookup_key(hdr.number, hash)?,
+		);
 
 		let children: Vec<_> = self
 			.blockchain()
@@ -2300,7 +2821,8 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 						block
 					)))
 				}
-				if let Ok(()) = self.storage.state_db.pin(&hash) {
+				if let Ok(()) = self.storage.state_db.pin(&hash, hdr.number.saturated_into::<u64>())
+				{
 					let root = hdr.state_root;
 					let db_state = DbStateBuilder::<Block>::new(self.storage.clone(), root)
 						.with_optional_cache(
@@ -2321,19 +2843,67 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 	}
 
 	fn have_state_at(&self, hash: &Block::Hash, number: NumberFor<Block>) -> bool {
+		let state_root_in_db = || match self.blockchain.header_metadata(*hash) {
+			Ok(header) => sp_state_machine::Storage::get(
+				self.storage.as_ref(),
+				&header.state_root,
+				(&[], None),
+			)
+			.unwrap_or(None)
+			.is_some(),
+			_ => false,
+		};
 		if self.is_archive {
-			match self.blockchain.header_metadata(*hash) {
-				Ok(header) => sp_state_machine::Storage::get(
-					self.storage.as_ref(),
-					&header.state_root,
-					(&[], None),
-				)
-				.unwrap_or(None)
-				.is_some(),
-				_ => false,
-			}
+			state_root_in_db()
 		} else {
-			!self.storage.state_db.is_pruned(hash, number.saturated_into::<u64>())
+			match self.storage.state_db.is_pruned(hash, number.saturated_into::<u64>()) {
+				IsPruned::Pruned => false,
+				IsPruned::NotPruned => true,
+				IsPruned::MaybePruned => state_root_in_db(),
+			}
+		}
+	}
+
+	fn pin_block(&self, hash: Block::Hash) -> ClientResult<()> {
+		let number = self
+			.blockchain
+			.number(hash)?
+			.ok_or_else(|| sp_blockchain::Error::UnknownBlock(format!("{}", hash)))?;
+		let state_pinned = self.storage.state_db.pin(&hash, number.saturated_into::<u64>()).is_ok();
+		let mut pinned_blocks = self.pinned_blocks.write();
+		let pinned = pinned_blocks.entry(hash).or_default();
+		pinned.refs += 1;
+		if state_pinned {
+			pinned.state_refs += 1;
+		}
+		trace!(target: "db", "Pinned block {}, {} references", hash, pinned.refs);
+		Ok(())
+	}
+
+	fn unpin_block(&self, hash: Block::Hash) {
+		let prune = {
+			let mut pinned_blocks = self.pinned_blocks.write();
+			let pinned = match pinned_blocks.get_mut(&hash) {
+				Some(pinned) => pinned,
+				None => return,
+			};
+			pinned.refs -= 1;
+			if pinned.state_refs > pinned.refs {
+				pinned.state_refs -= 1;
+				self.storage.state_db.unpin(&hash);
+			}
+			if pinned.refs > 0 {
+				return
+			}
+			trace!(target: "db", "Unpinned block {}", hash);
+			pinned_blocks.remove(&hash).map_or(false, |pinned| pinned.prune_deferred)
+		};
+		if prune {
+			// An import may be pruning the same block now that it's unpinned.
+			let _lock = self.import_lock.write();
+			if let Err(e) = self.prune_unpinned_block(hash) {
+				warn!(target: "db", "Failed to remove the body of unpinned block {}: {}", hash, e);
+			}
 		}
 	}
 
@@ -2344,9 +2914,13 @@ impl<Block: BlockT> sc_client_api::backend::Backend<Block> for Backend<Block> {
 	fn requires_full_sync(&self) -> bool {
 		matches!(
 			self.storage.state_db.pruning_mode(),
-			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical
+			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical | PruningMode::Hybrid { .. }
 		)
 	}
+
+	fn is_archive(&self) -> bool {
+		self.is_archive && matches!(self.blocks_pruning, BlocksPruning::All)
+	}
 }
 
 impl<Block: BlockT> sc_client_api::backend::LocalBackend<Block> for Backend<Block> {}
@@ -2488,6 +3062,8 @@ pub(crate) mod tests {
 				state_pruning: Some(PruningMode::blocks_pruning(1)),
 				source: DatabaseSource::Custom { db: backing, require_create_flag: false },
 				blocks_pruning: BlocksPruning::All,
+				blocks_retention: Default::default(),
+				changes_index: false,
 			},
 			0,
 		)
@@ -2580,6 +3156,395 @@ pub(crate) mod tests {
 		}
 	}
 
+	#[test]
+	fn switch_from_archive_removes_archived_state() {
+		let state_version = StateVersion::V1;
+		let db = sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS));
+		let settings = |state_pruning| DatabaseSettings {
+			trie_cache_maximum_size: Some(16 * 1024 * 1024),
+			state_pruning: Some(state_pruning),
+			source: DatabaseSource::Custom { db: db.clone(), require_create_flag: false },
+			blocks_pruning: BlocksPruning::All,
+			blocks_retention: Default::default(),
+			changes_index: false,
+		};
+		let value_node = |i: u8| {
+			let mut key = vec![0xaa];
+			key.extend_from_slice(BlakeTwo256::hash(&[i; 40]).as_ref());
+			key
+		};
+
+		let mut hashes = Vec::new();
+		{
+			let backend = Backend::<Block>::new(settings(PruningMode::ArchiveAll), 0).unwrap();
+			for i in 0..6u8 {
+				let mut op = backend.begin_operation().unwrap();
+				let mut header = Header {
+					number: i as u64,
+					parent_hash: hashes.last().cloned().unwrap_or_default(),
+					state_root: Default::default(),
+					digest: Default::default(),
+					extrinsics_root: Default::default(),
+				};
+				let storage = vec![(vec![0xaa], vec![i; 40]), (vec![0xbb, i], vec![i; 40])];
+				if i == 0 {
+					header.state_root = op
+						.old_state
+						.storage_root(
+							storage.iter().map(|(k, v)| (&k[..], Some(&v[..]))),
+							state_version,
+						)
+						.0
+						.into();
+					op.reset_storage(
+						Storage {
+							top: storage.into_iter().collect(),
+							children_default: Default::default(),
+						},
+						state_version,
+					)
+					.unwrap();
+				} else {
+					backend.begin_state_operation(&mut op, BlockId::Number(i as u64 - 1)).unwrap();
+					let (root, overlay) = op.old_state.storage_root(
+						storage.iter().map(|(k, v)| (&k[..], Some(&v[..]))),
+						state_version,
+					);
+					op.update_db_storage(overlay).unwrap();
+					header.state_root = root.into();
+					op.update_storage(
+						storage.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+						Vec::new(),
+					)
+					.unwrap();
+				}
+				hashes.push(header.hash());
+				let state = if i < 5 { NewBlockState::Final } else { NewBlockState::Best };
+				op.set_block_data(header, Some(vec![]), None, None, state).unwrap();
+				backend.commit_operation(op).unwrap();
+			}
+			assert!(backend.storage.db.get(columns::STATE, &value_node(0)).is_some());
+		}
+
+		let backend = Backend::<Block>::new(settings(PruningMode::blocks_pruning(2)), 0).unwrap();
+		assert!(backend.have_state_at(&hashes[4], 4));
+		assert!(!backend.have_state_at(&hashes[3], 3));
+		loop {
+			let _lock = backend.import_lock.write();
+			if !archive_gc::collect_next(&backend.storage).unwrap() {
+				break
+			}
+		}
+		for i in 0..4 {
+			assert!(backend.storage.db.get(columns::STATE, &value_node(i)).is_none());
+		}
+
+		{
+			let _lock = backend.import_lock.write();
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, BlockId::Hash(hashes[5])).unwrap();
+			op.mark_finalized(BlockId::Hash(hashes[5]), None).unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+		for i in 4..6 {
+			let state = backend.state_at(BlockId::Hash(hashes[i])).unwrap();
+			assert_eq!(state.storage(&[0xaa]).unwrap(), Some(vec![i as u8; 40]));
+			for j in 0..=i as u8 {
+				assert_eq!(state.storage(&[0xbb, j]).unwrap(), Some(vec![j; 40]));
+			}
+		}
+	}
+
+	#[test]
+	fn switch_from_archive_is_rejected_with_ref_counting() {
+		let dir = tempfile::tempdir().unwrap();
+		let settings = |state_pruning| DatabaseSettings {
+			trie_cache_maximum_size: None,
+			state_pruning: Some(state_pruning),
+			source: DatabaseSource::ParityDb { path: dir.path().join("db") },
+			blocks_pruning: BlocksPruning::All,
+			blocks_retention: Default::default(),
+			changes_index: false,
+		};
+		{
+			let backend = Backend::<Block>::new(settings(PruningMode::ArchiveAll), 0).unwrap();
+			insert_header(&backend, 0, Default::default(), None, Default::default());
+		}
+
+		assert!(matches!(
+			Backend::<Block>::new(settings(PruningMode::blocks_pruning(256)), 0),
+			Err(sp_blockchain::Error::StateDatabase(_))
+		));
+		let backend = Backend::<Block>::new(settings(PruningMode::ArchiveAll), 0).unwrap();
+		assert_eq!(backend.storage.state_db.pruning_mode(), PruningMode::ArchiveAll);
+	}
+
+	#[test]
+	fn column_stats_works() {
+		let backend = Backend::<Block>::new_test(1000, 100);
+		let genesis = insert_header(&backend, 0, Default::default(), None, Default::default());
+		insert_header(&backend, 1, genesis, None, Default::default());
+
+		let stats = backend.column_stats();
+		assert_eq!(stats.len(), 11);
+		let header = stats.iter().find(|(name, _)| *name == "header").unwrap().1.unwrap();
+		assert_eq!(header.keys, Some(2));
+		assert!(header.bytes > 0);
+		let aux = stats.iter().find(|(name, _)| *name == "aux").unwrap().1.unwrap();
+		assert_eq!(aux, ColumnStats { keys: Some(0), bytes: 0 });
+	}
+
+	#[cfg(feature = "rocksdb")]
+	#[test]
+	fn secondary_database_follows_primary() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("db");
+		let settings = |source| DatabaseSettings {
+			trie_cache_maximum_size: None,
+			state_pruning: Some(PruningMode::blocks_pruning(256)),
+			source,
+			blocks_pruning: BlocksPruning::Some(256),
+			blocks_retention: Default::default(),
+			changes_index: false,
+		};
+		let primary = Backend::<Block>::new(
+			settings(DatabaseSource::RocksDb { path: path.clone(), cache_size: 16 }),
+			0,
+		)
+		.unwrap();
+		let genesis = insert_header(&primary, 0, Default::default(), None, Default::default());
+
+		let secondary = Backend::<Block>::new(
+			settings(DatabaseSource::RocksDbSecondary {
+				path,
+				secondary_path: dir.path().join("secondary"),
+				cache_size: 16,
+			}),
+			0,
+		)
+		.unwrap();
+		assert!(secondary.is_read_only());
+		assert_eq!(secondary.blockchain().info().best_hash, genesis);
+
+		let block1 = insert_header(&primary, 1, genesis, None, Default::default());
+		assert_eq!(secondary.blockchain().info().best_number, 0);
+		secondary.catch_up_with_primary().unwrap();
+		assert_eq!(secondary.blockchain().info().best_hash, block1);
+		assert!(secondary.blockchain().header(BlockId::Hash(block1)).unwrap().is_some());
+
+		assert!(insert_block(&secondary, 2, block1, None, Default::default(), Vec::new(), None)
+			.is_err());
+	}
+
+	#[test]
+	fn check_database_repairs_indices() {
+		let backend = Backend::<Block>::new_test(1000, 100);
+		let genesis = insert_header(&backend, 0, Default::default(), None, Default::default());
+		let a1 = insert_header(&backend, 1, genesis, None, Default::default());
+		let b2 = insert_header(&backend, 2, a1, None, H256::from([1; 32]));
+		let a2 = insert_header(&backend, 2, a1, None, Default::default());
+		let mut children = vec![a2, b2];
+		children.sort();
+
+		let report = backend.check_database(false).unwrap();
+		assert_eq!(report.blocks, 4);
+		assert!(report.issues.is_empty());
+
+		let mut transaction = Transaction::new();
+		transaction.remove(columns::KEY_LOOKUP, a2.as_ref());
+		children::remove_children(&mut transaction, columns::META, meta_keys::CHILDREN_PREFIX, a1);
+		let mut leaves = LeafSet::new();
+		leaves.import(a2, 2, a1);
+		leaves.prepare_transaction(&mut transaction, columns::META, meta_keys::LEAF_PREFIX);
+		backend.storage.db.commit(transaction).unwrap();
+
+		let report = backend.check_database(false).unwrap();
+		assert!(!report.repaired);
+		assert_eq!(
+			report.issues,
+			vec![
+				DbIssue::HashLookup { hash: a2, number: 2 },
+				DbIssue::Children { hash: a1, missing: children.clone(), stale: vec![] },
+				DbIssue::Leaves { missing: vec![b2], stale: vec![] },
+			]
+		);
+
+		let report = backend.check_database(true).unwrap();
+		assert!(report.repaired);
+		assert_eq!(report.issues.len(), 3);
+		assert!(backend.check_database(false).unwrap().issues.is_empty());
+		assert_eq!(backend.blockchain().hash(2).unwrap(), Some(a2));
+		assert_eq!(backend.blockchain().children(a1).unwrap(), children);
+		let mut leaves = backend.blockchain().leaves().unwrap();
+		leaves.sort();
+		assert_eq!(leaves, children);
+	}
+
+	#[test]
+	fn check_database_removes_corrupted_journal_entries() {
+		let backend = Backend::<Block>::new_test(1000, 100);
+		let genesis = insert_header(&backend, 0, Default::default(), None, Default::default());
+		let block1 = insert_header(&backend, 1, genesis, None, Default::default());
+		insert_header(&backend, 2, block1, None, Default::default());
+		assert!(backend.check_database(false).unwrap().issues.is_empty());
+
+		let key = [(2u64, 0u64).encode(), b"noncanonical_journal".to_vec()].concat();
+		let mut transaction = Transaction::new();
+		transaction.set(columns::STATE_META, &key, b"ncj\x01");
+		backend.storage.db.commit(transaction).unwrap();
+
+		let report = backend.check_database(true).unwrap();
+		assert!(report.repaired);
+		assert_eq!(
+			report.issues,
+			vec![DbIssue::NonCanonicalJournal {
+				number: 2,
+				index: 0,
+				hash: None,
+				error: "Checksum mismatch".into(),
+			}]
+		);
+		assert!(backend.storage.db.get(columns::STATE_META, &key).is_none());
+	}
+
+	#[test]
+	fn check_database_finds_orphaned_nodes() {
+		let state_version = StateVersion::V1;
+		let backend = Backend::<Block>::new_test(1, 0);
+		let child_info = ChildInfo::new_default(b"child");
+		let mut parent_hash = Default::default();
+		for i in 0..4u8 {
+			let mut op = backend.begin_operation().unwrap();
+			let mut header = Header {
+				number: i as u64,
+				parent_hash,
+				state_root: Default::default(),
+				digest: Default::default(),
+				extrinsics_root: Default::default(),
+			};
+			let storage = vec![(vec![0xaa], vec![i; 40]), (vec![i], vec![i; 40])];
+			if i == 0 {
+				let child = sp_core::storage::StorageChild {
+					data: vec![(vec![1], vec![2; 40])].into_iter().collect(),
+					child_info: child_info.clone(),
+				};
+				header.state_root = op
+					.reset_storage(
+						Storage {
+							top: storage.into_iter().collect(),
+							children_default: vec![(child_info.storage_key().to_vec(), child)]
+								.into_iter()
+								.collect(),
+						},
+						state_version,
+					)
+					.unwrap();
+			} else {
+				backend.begin_state_operation(&mut op, BlockId::Hash(parent_hash)).unwrap();
+				let (root, overlay) = op.old_state.storage_root(
+					storage.iter().map(|(k, v)| (&k[..], Some(&v[..]))),
+					state_version,
+				);
+				op.update_db_storage(overlay).unwrap();
+				header.state_root = root.into();
+				op.update_storage(
+					storage.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+					Vec::new(),
+				)
+				.unwrap();
+			}
+			parent_hash = header.hash();
+			op.set_block_data(header, Some(vec![]), None, None, NewBlockState::Final)
+				.unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+
+		let report = backend.check_database(false).unwrap();
+		assert!(report.issues.is_empty());
+		let nodes = report.state_nodes.unwrap();
+		assert!(nodes > 0);
+
+		let mut transaction = Transaction::new();
+		transaction.set(columns::STATE, &[0x11; 33], &[0x22; 40]);
+		backend.storage.db.commit(transaction).unwrap();
+		let report = backend.check_database(false).unwrap();
+		assert_eq!(report.state_nodes, Some(nodes + 1));
+		assert_eq!(report.issues, vec![DbIssue::OrphanedTrieNodes { count: 1, bytes: 73 }]);
+	}
+
+	#[test]
+	fn snapshot_export_import_works() {
+		let state_version = StateVersion::V1;
+		let backend = Backend::<Block>::new_test(1000, 0);
+		let child_info = ChildInfo::new_default(b"child");
+		let mut hashes = Vec::new();
+		for i in 0..3u8 {
+			let mut op = backend.begin_operation().unwrap();
+			let mut header = Header {
+				number: i as u64,
+				parent_hash: hashes.last().cloned().unwrap_or_default(),
+				state_root: Default::default(),
+				digest: Default::default(),
+				extrinsics_root: Default::default(),
+			};
+			let storage = vec![(vec![i], vec![i; 40])];
+			if i == 0 {
+				let child = sp_core::storage::StorageChild {
+					data: vec![(vec![1], vec![2; 40])].into_iter().collect(),
+					child_info: child_info.clone(),
+				};
+				header.state_root = op
+					.reset_storage(
+						Storage {
+							top: storage.into_iter().collect(),
+							children_default: vec![(child_info.storage_key().to_vec(), child)]
+								.into_iter()
+								.collect(),
+						},
+						state_version,
+					)
+					.unwrap();
+			} else {
+				backend
+					.begin_state_operation(&mut op, BlockId::Hash(hashes[i as usize - 1]))
+					.unwrap();
+				let (root, overlay) = op.old_state.storage_root(
+					storage.iter().map(|(k, v)| (&k[..], Some(&v[..]))),
+					state_version,
+				);
+				op.update_db_storage(overlay).unwrap();
+				header.state_root = root.into();
+				op.update_storage(
+					storage.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+					Vec::new(),
+				)
+				.unwrap();
+			}
+			hashes.push(header.hash());
+			op.set_block_data(header, Some(vec![]), None, None, NewBlockState::Final)
+				.unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+
+		let mut snapshot = Vec::new();
+		assert!(backend.export_snapshot(hashes[2], &mut snapshot).unwrap() > 0);
+
+		let imported = Backend::<Block>::new_test(1000, 0);
+		assert!(imported.import_snapshot(&mut &snapshot[..snapshot.len() - 1]).is_err());
+		assert_eq!(imported.import_snapshot(&mut &snapshot[..]).unwrap(), (2, hashes[2]));
+		let info = imported.blockchain().info();
+		assert_eq!(info.genesis_hash, hashes[0]);
+		assert_eq!((info.finalized_number, info.finalized_hash), (2, hashes[2]));
+		assert_eq!(info.block_gap, Some((1, 1)));
+		let state = imported.state_at(BlockId::Hash(hashes[2])).unwrap();
+		for i in 0..3u8 {
+			assert_eq!(state.storage(&[i]).unwrap(), Some(vec![i; 40]));
+		}
+		assert_eq!(state.child_storage(&child_info, &[1]).unwrap(), Some(vec![2; 40]));
+
+		assert!(imported.import_snapshot(&mut &snapshot[..]).is_err());
+	}
+
 	#[test]
 	fn delete_only_when_negative_rc() {
 		sp_tracing::try_init_simple();
@@ -3191,6 +4156,47 @@ pub(crate) mod tests {
 		assert_eq!(Some(vec![4.into()]), bc.body(BlockId::hash(blocks[4])).unwrap());
 	}
 
+	#[test]
+	fn pinned_blocks_are_pruned_when_unpinned() {
+		let backend = Backend::<Block>::new_test_with_tx_storage(2, 0);
+		let mut blocks = Vec::new();
+		let mut prev_hash = Default::default();
+		for i in 0..5 {
+			let hash = insert_block(
+				&backend,
+				i,
+				prev_hash,
+				None,
+				Default::default(),
+				vec![i.into()],
+				None,
+			)
+			.unwrap();
+			blocks.push(hash);
+			prev_hash = hash;
+		}
+
+		backend.pin_block(blocks[1]).unwrap();
+		backend.pin_block(blocks[1]).unwrap();
+		{
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, BlockId::Hash(blocks[4])).unwrap();
+			for i in 1..5 {
+				op.mark_finalized(BlockId::Hash(blocks[i]), None).unwrap();
+			}
+			backend.commit_operation(op).unwrap();
+		}
+		let bc = backend.blockchain();
+		assert_eq!(None, bc.body(BlockId::hash(blocks[0])).unwrap());
+		assert_eq!(Some(vec![1.into()]), bc.body(BlockId::hash(blocks[1])).unwrap());
+		assert_eq!(None, bc.body(BlockId::hash(blocks[2])).unwrap());
+
+		backend.unpin_block(blocks[1]);
+		assert_eq!(Some(vec![1.into()]), bc.body(BlockId::hash(blocks[1])).unwrap());
+		backend.unpin_block(blocks[1]);
+		assert_eq!(None, bc.body(BlockId::hash(blocks[1])).unwrap());
+	}
+
 	#[test]
 	fn prune_blocks_on_finalize_with_fork() {
 		let backend = Backend::<Block>::new_test_with_tx_storage(2, 10);
@@ -3376,6 +4382,78 @@ pub(crate) mod tests {
 		}
 	}
 
+	#[test]
+	fn prune_blocks_with_retention_policy() {
+		let db = sp_database::as_database(kvdb_memorydb::create(crate::utils::NUM_COLUMNS));
+		let settings = DatabaseSettings {
+			trie_cache_maximum_size: Some(16 * 1024 * 1024),
+			state_pruning: Some(PruningMode::blocks_pruning(8)),
+			source: DatabaseSource::Custom { db, require_create_flag: true },
+			blocks_pruning: BlocksPruning::Some(2),
+			blocks_retention: BlocksRetention { transactions: Some(4), keep_ranges: vec![1..=1] },
+			changes_index: false,
+		};
+		let backend = Backend::<Block>::new(settings, 0).unwrap();
+		let mut blocks = Vec::new();
+		let mut transactions = Vec::new();
+		let mut prev_hash = Default::default();
+		for i in 0..8 {
+			let x = ExtrinsicWrapper::from(i).encode();
+			let x_hash = <HashFor<Block> as sp_core::Hasher>::hash(&x[1..]);
+			let index = vec![IndexOperation::Insert {
+				extrinsic: 0,
+				hash: x_hash.as_ref().to_vec(),
+				size: (x.len() - 1) as u32,
+			}];
+			let hash = insert_block(
+				&backend,
+				i,
+				prev_hash,
+				None,
+				Default::default(),
+				vec![i.into()],
+				Some(index),
+			)
+			.unwrap();
+			blocks.push(hash);
+			transactions.push((x_hash, x[1..].to_vec()));
+			prev_hash = hash;
+		}
+
+		{
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, BlockId::Hash(blocks[7])).unwrap();
+			for i in 1..8 {
+				op.mark_finalized(BlockId::Hash(blocks[i]), None).unwrap();
+			}
+			backend.commit_operation(op).unwrap();
+		}
+		let bc = backend.blockchain();
+		for (i, hash) in blocks.iter().enumerate() {
+			let id = BlockId::hash(*hash);
+			let (x_hash, x) = &transactions[i];
+			assert!(bc.header(id).unwrap().is_some());
+			match i {
+				// In the kept range.
+				1 => {
+					assert_eq!(bc.body(id).unwrap(), Some(vec![(i as u64).into()]));
+					assert_eq!(bc.indexed_transaction(x_hash).unwrap().as_ref(), Some(x));
+				},
+				0 | 2 | 3 => {
+					assert_eq!(bc.body(id).unwrap(), None);
+					assert_eq!(bc.indexed_transaction(x_hash).unwrap(), None);
+					assert_eq!(bc.block_indexed_body(id).unwrap(), None);
+				},
+				4 | 5 => {
+					assert_eq!(bc.body(id).unwrap(), None);
+					assert_eq!(bc.indexed_transaction(x_hash).unwrap().as_ref(), Some(x));
+					assert_eq!(bc.block_indexed_body(id).unwrap(), Some(vec![x.clone()]));
+				},
+				_ => assert_eq!(bc.body(id).unwrap(), Some(vec![(i as u64).into()])),
+			}
+		}
+	}
+
 	#[test]
 	fn remove_leaf_block_works() {
 		let backend = Backend::<Block>::new_test_with_tx_storage(2, 10);
@@ -3655,4 +4733,87 @@ pub(crate) mod tests {
 		assert_eq!(backend.blockchain().leaves().unwrap(), vec![block2]);
 		assert_eq!(backend.blockchain().info().best_hash, block2);
 	}
+
+	#[test]
+	fn changes_index_lists_key_changes() {
+		use sc_client_api::backend::{BlockChanges, ChangesIndex};
+
+		let backend = Backend::<Block>::new_test_with_changes_index(4, 10);
+		let child_info = ChildInfo::new_default(b"child");
+		let import = |number, parent_hash, extrinsics_root, top: &[&[u8]], child: &[&[u8]]| {
+			let header = Header {
+				number,
+				parent_hash,
+				state_root: BlakeTwo256::trie_root(Vec::new(), StateVersion::V1),
+				digest: Default::default(),
+				extrinsics_root,
+			};
+			let hash = header.hash();
+			let changes = |keys: &[&[u8]]| -> Vec<_> {
+				keys.iter().map(|key| (key.to_vec(), Some(vec![number as u8]))).collect()
+			};
+			let child_changes = if child.is_empty() {
+				Vec::new()
+			} else {
+				vec![(child_info.storage_key().to_vec(), changes(child))]
+			};
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, BlockId::Hash(parent_hash)).unwrap();
+			op.update_storage(changes(top), child_changes).unwrap();
+			op.set_block_data(header, Some(Vec::new()), None, None, NewBlockState::Best)
+				.unwrap();
+			backend.commit_operation(op).unwrap();
+			hash
+		};
+
+		let block0 = insert_header(&backend, 0, Default::default(), None, Default::default());
+		let block1 = import(1, block0, Default::default(), &[b"a"], &[]);
+		let block2 = import(2, block1, Default::default(), &[b"b"], &[]);
+		let block3 = import(3, block2, Default::default(), &[b"a", b"b"], &[b"c"]);
+		let block3_fork = import(3, block2, H256::from_low_u64_be(1), &[b"a", b"d"], &[]);
+		let block4 = import(4, block3, Default::default(), &[b"a"], &[]);
+		let block5 = import(5, block4, Default::default(), &[b"b"], &[]);
+
+		let index = backend.changes_index().unwrap();
+		assert_eq!(index.indexed_from().unwrap(), 1);
+		assert_eq!(
+			index.block_changes(&block3).unwrap(),
+			Some(BlockChanges {
+				top: vec![b"a".to_vec(), b"b".to_vec()],
+				children: vec![(b"child".to_vec(), vec![b"c".to_vec()])],
+			})
+		);
+
+		for hash in [block1, block2, block3, block4] {
+			backend.finalize_block(BlockId::Hash(hash), None).unwrap();
+		}
+		let key_changes = |first, last, child_info, key: &[u8]| {
+			index.key_changes(first, last, child_info, key).unwrap()
+		};
+		assert_eq!(key_changes(1, 4, None, b"a"), vec![1, 3, 4]);
+		assert_eq!(key_changes(2, 3, None, b"a"), vec![3]);
+		assert_eq!(key_changes(1, 5, None, b"b"), vec![2, 3]);
+		assert_eq!(key_changes(1, 4, Some(&child_info), b"c"), vec![3]);
+		assert_eq!(key_changes(1, 4, None, b"c"), Vec::<u64>::new());
+		// Only finalized blocks are listed.
+		assert_eq!(key_changes(1, 4, None, b"d"), Vec::<u64>::new());
+
+		// The keys modified by a block and its history entries are removed with its body.
+		assert_eq!(index.block_changes(&block3_fork).unwrap(), None);
+		backend.finalize_block(BlockId::Hash(block5), None).unwrap();
+		assert_eq!(index.block_changes(&block1).unwrap(), None);
+		assert!(index.block_changes(&block2).unwrap().is_some());
+		assert_eq!(index.indexed_from().unwrap(), 2);
+		assert_eq!(key_changes(1, 5, None, b"a"), vec![3, 4]);
+		assert_eq!(key_changes(1, 5, None, b"b"), vec![2, 3, 5]);
+		// The history entries of blocks #2 to #5.
+		let mut history_entries = 0;
+		backend.storage.db.iter_column(columns::CHANGES_INDEX, &mut |key, _| {
+			if key.map_or(false, |key| key.starts_with(b"history")) {
+				history_entries += 1;
+			}
+			true
+		});
+		assert_eq!(history_entries, 6);
+	}
 }
@@ -0,0 +1,910 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Migration of a full node database between RocksDB and ParityDB.
+//!
+//! RocksDB stores state nodes under their position in the trie followed by their hash, while
+//! ParityDB stores them under their hash with a reference count. Going to ParityDB, the positions
+//! are dropped. Going back to RocksDB, the states kept by the database are walked block by block
+//! to find the positions of their nodes, and the state journals are rebuilt from the same walks.
+//!
+//! Only the ordered ParityDB columns can be iterated. The blocks of a ParityDB database are found
+//! from the canonical number lookups and the lists of children stored in the `META` column, and
+//! its indexed transactions from the block bodies.
+
+use crate::{
+	columns,
+	parity_db::{self, ORDERED_COLUMNS},
+	trie_nodes::UnsharedNodes,
+	utils::{self, column_name, meta_keys, DatabaseType, COLUMN_META, NUM_COLUMNS},
+	Database, DbExtrinsic, DbHash, StateMetaDb, DB_HASH_LEN,
+};
+use codec::{Decode, Encode};
+use hash_db::Hasher;
+use kvdb::DBTransaction;
+use log::{debug, info};
+use sc_state_db::{JournalChanges, PruningMode};
+use sp_database::Transaction;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, UniqueSaturatedInto, Zero},
+};
+use sp_state_machine::DBValue;
+use std::{
+	collections::{BTreeMap, HashMap},
+	fmt,
+	path::Path,
+	sync::Arc,
+};
+
+/// Key of the entry in the `META` column of a migration target that tracks migration progress.
+const MIGRATION_PROGRESS: &[u8] = b"migration_progress";
+
+/// Number of source entries copied in a single target transaction.
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+/// Columns in the order they are migrated. `META` is copied last, so that an interrupted migration
+/// never leaves a target that looks like a complete database.
+const MIGRATED_COLUMNS: [u32; 13] = [
+	columns::STATE,
+	columns::STATE_META,
+	columns::KEY_LOOKUP,
+	columns::HEADER,
+	columns::BODY,
+	columns::BODY_INDEX,
+	columns::JUSTIFICATIONS,
+	columns::TRANSACTION,
+	columns::AUX,
+	columns::OFFCHAIN,
+	columns::CHANGES_INDEX,
+	columns::STATE_SYNC,
+	columns::META,
+];
+
+/// Migration progress, committed to the target together with each batch of copied entries.
+#[derive(Encode, Decode)]
+struct MigrationProgress {
+	/// Index in `MIGRATED_COLUMNS` of the column being copied.
+	column: u32,
+	/// Last source key of the column that has been copied.
+	last_key: Option<Vec<u8>>,
+}
+
+/// Summary of a migrated and verified database column.
+///
+/// The entries of the target database are read back to compute the summary. State nodes stored by
+/// RocksDB under several positions are a single ParityDB entry, which is counted once for each
+/// position when migrating to ParityDB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigratedColumn {
+	/// Column index.
+	pub column: u32,
+	/// Number of entries found in the target database.
+	pub entries: u64,
+	/// XOR of `blake2_256(key ++ value)` over all entries of the column in the target database.
+	pub digest: [u8; 32],
+}
+
+impl MigratedColumn {
+	fn new(column: u32) -> Self {
+		MigratedColumn { column, entries: 0, digest: [0; 32] }
+	}
+
+	fn add(&mut self, key: &[u8], value: &[u8]) {
+		let hash = sp_core::hashing::blake2_256(&[key, value].concat());
+		self.digest.iter_mut().zip(hash).for_each(|(d, h)| *d ^= h);
+		self.entries += 1;
+	}
+
+	/// Check the number of entries of the column in the source database.
+	fn check_entries(&self, entries: u64) -> sp_blockchain::Result<()> {
+		if entries != self.entries {
+			return Err(sp_blockchain::Error::Backend(format!(
+				"Verification failed: column {} has {} entries, {} expected",
+				column_name(self.column),
+				self.entries,
+				entries,
+			)))
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for MigratedColumn {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{}: {} entries, digest 0x{}",
+			column_name(self.column),
+			self.entries,
+			hex(&self.digest),
+		)
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn backend_err(e: impl fmt::Display) -> sp_blockchain::Error {
+	sp_blockchain::Error::Backend(e.to_string())
+}
+
+fn mismatch(column: u32, key: &[u8]) -> sp_blockchain::Error {
+	sp_blockchain::Error::Backend(format!(
+		"Verification failed: entry 0x{} of column {} does not match",
+		hex(key),
+		column_name(column),
+	))
+}
+
+fn journal_changes<Block: BlockT>(
+	key: &[u8],
+	value: &[u8],
+) -> sp_blockchain::Result<Option<JournalChanges<Block::Hash>>> {
+	sc_state_db::journal_changes::<Block::Hash>(key, value)
+		.map_err(|e| sp_blockchain::Error::Backend(format!("Error decoding state journal: {}", e)))
+}
+
+/// Returns the progress stored in the migration target, or an error if the target is an existing
+/// database.
+fn read_progress(
+	progress: Option<Vec<u8>>,
+	is_database: bool,
+	to: &Path,
+) -> sp_blockchain::Result<MigrationProgress> {
+	match progress {
+		Some(progress) => MigrationProgress::decode(&mut &progress[..]).map_err(backend_err),
+		None if is_database => Err(sp_blockchain::Error::Backend(format!(
+			"Database at {:?} already exists and is not a migration target",
+			to
+		))),
+		None => Ok(MigrationProgress { column: 0, last_key: None }),
+	}
+}
+
+/// Convert a RocksDB column entry to the ParityDB format.
+///
+/// Returns the target key, value and reference count, or `None` if the entry has no counterpart in
+/// ParityDB.
+fn rocksdb_to_paritydb_entry<Block: BlockT>(
+	source: &kvdb_rocksdb::Database,
+	column: u32,
+	key: &[u8],
+	value: &[u8],
+) -> sp_blockchain::Result<Option<(Vec<u8>, Vec<u8>, u32)>> {
+	let strip_prefix = |key: &[u8]| key[key.len().saturating_sub(DB_HASH_LEN)..].to_vec();
+	Ok(match column {
+		// RocksDB state nodes are keyed by the trie prefix and the node hash, ParityDB keeps a
+		// reference count per hash instead.
+		columns::STATE => Some((strip_prefix(key), value.to_vec(), 1)),
+		columns::STATE_META => {
+			let value = sc_state_db::map_journal_keys::<Block::Hash>(key, value, strip_prefix)
+				.map_err(|e| {
+					sp_blockchain::Error::Backend(format!("Error decoding state journal: {}", e))
+				})?
+				.unwrap_or_else(|| value.to_vec());
+			Some((key.to_vec(), value, 1))
+		},
+		// Reference counters are stored by RocksDB as separate entries, keyed by the hash followed
+		// by a zero byte.
+		columns::TRANSACTION if key.len() == DB_HASH_LEN + 1 => None,
+		columns::TRANSACTION => {
+			let counter_key = [key, &[0]].concat();
+			let references = match source.get(column, &counter_key).map_err(backend_err)? {
+				Some(counter) if counter.len() == 4 => {
+					let mut counter_data = [0; 4];
+					counter_data.copy_from_slice(&counter);
+					u32::from_le_bytes(counter_data)
+				},
+				_ => 1,
+			};
+			Some((key.to_vec(), value.to_vec(), references))
+		},
+		columns::META if key == MIGRATION_PROGRESS => None,
+		_ => Some((key.to_vec(), value.to_vec(), 1)),
+	})
+}
+
+/// Copy a full node RocksDB database at `from` into a ParityDB database at `to`, column by column.
+///
+/// Every column in `columns` is understood: state node keys lose their trie prefix, reference
+/// counts of indexed transactions are preserved and node keys stored in the state journals are
+/// rewritten. Progress is committed to the target together with the copied entries, so calling
+/// this again after an interruption resumes the migration. Once all columns are copied, every
+/// source entry is read back from the target and compared, and the ordered target columns are
+/// checked for extra entries.
+pub fn migrate_rocksdb_to_paritydb<Block: BlockT>(
+	from: &Path,
+	to: &Path,
+) -> sp_blockchain::Result<Vec<MigratedColumn>> {
+	match crate::upgrade::upgrade_db::<Block>(from, DatabaseType::Full) {
+		Ok(_) => (),
+		Err(crate::upgrade::UpgradeError::MissingDatabaseVersionFile) =>
+			return Err(sp_blockchain::Error::Backend(format!(
+				"No RocksDB database found at {:?}",
+				from
+			))),
+		Err(err) => return Err(sp_blockchain::Error::Backend(err.to_string())),
+	}
+	let mut source_config = kvdb_rocksdb::DatabaseConfig::with_columns(NUM_COLUMNS);
+	source_config.create_if_missing = false;
+	let source = kvdb_rocksdb::Database::open(&source_config, from).map_err(backend_err)?;
+	let target_db = parity_db::open_db(to, DatabaseType::Full, true, false).map_err(backend_err)?;
+	let target: Arc<dyn Database<DbHash>> = parity_db::as_database(target_db.clone(), to);
+
+	let mut progress = read_progress(
+		target.get(COLUMN_META, MIGRATION_PROGRESS),
+		target.get(COLUMN_META, meta_keys::TYPE).is_some(),
+		to,
+	)?;
+
+	while let Some(column) = MIGRATED_COLUMNS.get(progress.column as usize).cloned() {
+		info!(
+			"Migrating column {} ({} entries estimated)",
+			column_name(column),
+			source.num_keys(column).map_err(backend_err)?,
+		);
+		let mut transaction = Transaction::new();
+		let mut batch_len = 0;
+		let mut copied = 0u64;
+		for (key, value) in source.iter(column) {
+			if progress.last_key.as_ref().map_or(false, |last| key[..] <= last[..]) {
+				continue
+			}
+			if let Some((key, value, references)) =
+				rocksdb_to_paritydb_entry::<Block>(&source, column, &key, &value)?
+			{
+				for _ in 0..references {
+					transaction.set(column, &key, &value);
+				}
+			}
+			progress.last_key = Some(key.to_vec());
+			batch_len += 1;
+			if batch_len == MIGRATION_BATCH_SIZE {
+				transaction.set_from_vec(COLUMN_META, MIGRATION_PROGRESS, progress.encode());
+				target.commit(std::mem::take(&mut transaction))?;
+				copied += batch_len as u64;
+				batch_len = 0;
+				debug!("Copied {} entries of column {}", copied, column_name(column));
+			}
+		}
+		progress = MigrationProgress { column: progress.column + 1, last_key: None };
+		transaction.set_from_vec(COLUMN_META, MIGRATION_PROGRESS, progress.encode());
+		target.commit(transaction)?;
+		info!("Copied column {}", column_name(column));
+	}
+
+	let mut summary = Vec::with_capacity(MIGRATED_COLUMNS.len());
+	for column in MIGRATED_COLUMNS {
+		let mut migrated = MigratedColumn::new(column);
+		for (key, value) in source.iter(column) {
+			let (key, value, _) =
+				match rocksdb_to_paritydb_entry::<Block>(&source, column, &key, &value)? {
+					Some(entry) => entry,
+					None => continue,
+				};
+			match target.get(column, &key) {
+				Some(stored) if stored == value => migrated.add(&key, &stored),
+				_ => return Err(mismatch(column, &key)),
+			}
+		}
+		if ORDERED_COLUMNS.contains(&column) {
+			let mut entries = 0;
+			for entry in
+				parity_db::ordered_entries(&target_db, column, None).map_err(backend_err)?
+			{
+				let (key, _) = entry.map_err(backend_err)?;
+				if column != COLUMN_META || key != MIGRATION_PROGRESS {
+					entries += 1;
+				}
+			}
+			migrated.check_entries(entries)?;
+		}
+		info!("Verified {}", migrated);
+		summary.push(migrated);
+	}
+
+	let mut transaction = Transaction::new();
+	transaction.remove(COLUMN_META, MIGRATION_PROGRESS);
+	target.commit(transaction)?;
+	Ok(summary)
+}
+
+/// Copy a full node ParityDB database at `from` into a RocksDB database at `to`, column by column.
+///
+/// The states kept by the source are walked to store their nodes under their position in the
+/// trie, and the node changes of the state journals are recomputed from the same walks. Blocks
+/// are found from the canonical number lookups and the lists of children of the source, which
+/// must have been created with ordered `META`, `STATE_META`, `AUX` and `OFFCHAIN` columns.
+/// Indexed transactions are copied with one reference per block body or pruned body referencing
+/// them, transactions that are not referenced by any block are not copied.
+///
+/// The migration can be resumed like [`migrate_rocksdb_to_paritydb`]. Once all columns are
+/// copied, every target entry is read back and compared with the source, and the number of entries
+/// of the ordered source columns is checked.
+pub fn migrate_paritydb_to_rocksdb<Block: BlockT>(
+	from: &Path,
+	to: &Path,
+) -> sp_blockchain::Result<Vec<MigratedColumn>> {
+	// Columns added since the source was last opened are added to its metadata.
+	let source_db = match parity_db::open_db(from, DatabaseType::Full, false, false) {
+		Err(::parity_db::Error::InvalidConfiguration(_)) =>
+			parity_db::open_db(from, DatabaseType::Full, false, true),
+		result => result,
+	}
+	.map_err(backend_err)?;
+	let unordered = parity_db::unordered_columns(from).map_err(backend_err)?;
+	if !unordered.is_empty() {
+		return Err(sp_blockchain::Error::Backend(format!(
+			"Columns {} of the ParityDB database at {:?} are not ordered and can not be migrated",
+			unordered.into_iter().map(column_name).collect::<Vec<_>>().join(", "),
+			from,
+		)))
+	}
+	let source: Arc<dyn Database<DbHash>> = parity_db::as_database(source_db.clone(), from);
+	let mut target_config = kvdb_rocksdb::DatabaseConfig::with_columns(NUM_COLUMNS);
+	target_config.create_if_missing = true;
+	let target = kvdb_rocksdb::Database::open(&target_config, to).map_err(backend_err)?;
+	crate::upgrade::update_version(to).map_err(backend_err)?;
+
+	let progress = read_progress(
+		target.get(COLUMN_META, MIGRATION_PROGRESS).map_err(backend_err)?,
+		target.get(COLUMN_META, meta_keys::TYPE).map_err(backend_err)?.is_some(),
+		to,
+	)?;
+	let migration = ToRocksDb::<Block>::new(&source_db, &*source)?;
+	let mut batch = Batch { target: &target, transaction: DBTransaction::new(), len: 0, progress };
+	while let Some(column) = MIGRATED_COLUMNS.get(batch.progress.column as usize).cloned() {
+		info!("Migrating column {}", column_name(column));
+		match column {
+			columns::STATE => migration.copy_state(&mut batch)?,
+			columns::STATE_META => migration.copy_state_meta(&mut batch)?,
+			columns::KEY_LOOKUP =>
+				migration.copy_keys(column, migration.lookup_keys(), &mut batch)?,
+			columns::HEADER | columns::BODY | columns::BODY_INDEX | columns::JUSTIFICATIONS =>
+				migration.copy_keys(
+					column,
+					migration.blocks.keys().cloned().collect(),
+					&mut batch,
+				)?,
+			columns::TRANSACTION => migration.copy_transactions(&mut batch)?,
+			_ => migration.copy_ordered(column, &mut batch)?,
+		}
+		batch.next_column()?;
+		info!("Copied column {}", column_name(column));
+	}
+
+	let mut summary = Vec::with_capacity(MIGRATED_COLUMNS.len());
+	for column in MIGRATED_COLUMNS {
+		let mut migrated = MigratedColumn::new(column);
+		for (key, value) in target.iter(column) {
+			let expected = match column {
+				columns::META if &*key == MIGRATION_PROGRESS => continue,
+				columns::STATE => source.get(column, &key[key.len().saturating_sub(DB_HASH_LEN)..]),
+				// The node changes of the journals are recomputed and reference counters are not
+				// stored by ParityDB.
+				columns::STATE_META if journal_changes::<Block>(&key, &value)?.is_some() =>
+					Some(value.to_vec()),
+				columns::TRANSACTION if key.len() == DB_HASH_LEN + 1 => Some(value.to_vec()),
+				_ => source.get(column, &key),
+			};
+			if expected.as_deref() != Some(&value[..]) {
+				return Err(mismatch(column, &key))
+			}
+			migrated.add(&key, &value);
+		}
+		if ORDERED_COLUMNS.contains(&column) {
+			let mut entries = 0;
+			for entry in
+				parity_db::ordered_entries(&source_db, column, None).map_err(backend_err)?
+			{
+				entry.map_err(backend_err)?;
+				entries += 1;
+			}
+			migrated.check_entries(entries)?;
+		}
+		info!("Verified {}", migrated);
+		summary.push(migrated);
+	}
+
+	let mut transaction = DBTransaction::new();
+	transaction.delete(COLUMN_META, MIGRATION_PROGRESS);
+	target.write(transaction).map_err(backend_err)?;
+	Ok(summary)
+}
+
+/// Entries written to a RocksDB migration target, committed in batches together with the
+/// progress of the migration.
+///
+/// A batch may end in the middle of the entries of a source key. Those entries are written again
+/// when the migration resumes, which leaves the target unchanged.
+struct Batch<'a> {
+	target: &'a kvdb_rocksdb::Database,
+	transaction: DBTransaction,
+	len: usize,
+	progress: MigrationProgress,
+}
+
+impl<'a> Batch<'a> {
+	fn put(&mut self, column: u32, key: &[u8], value: &[u8]) -> sp_blockchain::Result<()> {
+		self.transaction.put(column, key, value);
+		self.len += 1;
+		if self.len == MIGRATION_BATCH_SIZE {
+			self.commit()?;
+		}
+		Ok(())
+	}
+
+	/// Record that the entries of the source `key` have all been added.
+	fn copied(&mut self, key: Vec<u8>) {
+		self.progress.last_key = Some(key);
+	}
+
+	/// Returns whether the entries of the source `key` have been copied already.
+	fn is_copied(&self, key: &[u8]) -> bool {
+		self.progress.last_key.as_ref().map_or(false, |last| key <= &last[..])
+	}
+
+	fn next_column(&mut self) -> sp_blockchain::Result<()> {
+		self.progress = MigrationProgress { column: self.progress.column + 1, last_key: None };
+		self.commit()
+	}
+
+	fn commit(&mut self) -> sp_blockchain::Result<()> {
+		self.transaction
+			.put_vec(COLUMN_META, MIGRATION_PROGRESS, self.progress.encode());
+		self.target.write(std::mem::take(&mut self.transaction)).map_err(backend_err)?;
+		debug!("Copied {} entries", self.len);
+		self.len = 0;
+		Ok(())
+	}
+}
+
+/// Migration from ParityDB to RocksDB.
+struct ToRocksDb<'a, Block: BlockT> {
+	source_db: &'a ::parity_db::Db,
+	source: &'a dyn Database<DbHash>,
+	/// Hashes of the source blocks by lookup key.
+	blocks: BTreeMap<Vec<u8>, Block::Hash>,
+}
+
+impl<'a, Block: BlockT> ToRocksDb<'a, Block> {
+	/// Enumerate the canonical blocks up to the best block and the blocks listed as the children
+	/// of another block.
+	fn new(
+		source_db: &'a ::parity_db::Db,
+		source: &'a dyn Database<DbHash>,
+	) -> sp_blockchain::Result<Self> {
+		let meta = utils::read_meta::<Block>(source, columns::HEADER)?;
+		let mut lookup_keys = Vec::new();
+		let best_number: u64 = meta.best_number.unique_saturated_into();
+		for number in 0..=best_number {
+			lookup_keys.extend(source.get(columns::KEY_LOOKUP, &utils::number_index_key(number)?));
+		}
+		for entry in
+			parity_db::ordered_entries(source_db, COLUMN_META, None).map_err(backend_err)?
+		{
+			let (key, value) = entry.map_err(backend_err)?;
+			if key.starts_with(meta_keys::CHILDREN_PREFIX) {
+				for child in Vec::<Block::Hash>::decode(&mut &value[..]).map_err(backend_err)? {
+					lookup_keys.extend(source.get(columns::KEY_LOOKUP, child.as_ref()));
+				}
+			}
+		}
+		let mut blocks = BTreeMap::new();
+		for lookup_key in lookup_keys {
+			let hash = Block::Hash::decode(&mut &lookup_key[4..]).map_err(backend_err)?;
+			blocks.insert(lookup_key, hash);
+		}
+		Ok(ToRocksDb { source_db, source, blocks })
+	}
+
+	/// Whether the block with `lookup_key` is canonical.
+	fn is_canonical(&self, lookup_key: &[u8]) -> bool {
+		self.source.get(columns::KEY_LOOKUP, &lookup_key[..4]).as_deref() == Some(lookup_key)
+	}
+
+	/// Returns the sorted keys of the `KEY_LOOKUP` column: the numbers of the canonical blocks and
+	/// the hashes of all blocks.
+	fn lookup_keys(&self) -> Vec<Vec<u8>> {
+		let mut keys = Vec::new();
+		for (lookup_key, hash) in &self.blocks {
+			if self.is_canonical(lookup_key) {
+				keys.push(lookup_key[..4].to_vec());
+			}
+			keys.push(hash.as_ref().to_vec());
+		}
+		keys.sort();
+		keys
+	}
+
+	fn header(&self, hash: Block::Hash) -> sp_blockchain::Result<Block::Header> {
+		utils::read_header::<Block>(
+			self.source,
+			columns::KEY_LOOKUP,
+			columns::HEADER,
+			BlockId::Hash(hash),
+		)?
+		.ok_or_else(|| sp_blockchain::Error::Backend(format!("Missing header of block {:?}", hash)))
+	}
+
+	/// Copy the entries of `column` stored under the sorted `keys`.
+	fn copy_keys(
+		&self,
+		column: u32,
+		keys: Vec<Vec<u8>>,
+		batch: &mut Batch,
+	) -> sp_blockchain::Result<()> {
+		for key in keys {
+			if batch.is_copied(&key) {
+				continue
+			}
+			if let Some(value) = self.source.get(column, &key) {
+				batch.put(column, &key, &value)?;
+			}
+			batch.copied(key);
+		}
+		Ok(())
+	}
+
+	/// Copy the entries of an ordered column.
+	fn copy_ordered(&self, column: u32, batch: &mut Batch) -> sp_blockchain::Result<()> {
+		let after = batch.progress.last_key.clone();
+		for entry in parity_db::ordered_entries(self.source_db, column, after.as_deref())
+			.map_err(backend_err)?
+		{
+			let (key, value) = entry.map_err(backend_err)?;
+			batch.put(column, &key, &value)?;
+			batch.copied(key);
+		}
+		Ok(())
+	}
+
+	/// Copy the indexed transactions with a reference counter for the block bodies and the
+	/// indexed transactions of pruned bodies referencing them.
+	fn copy_transactions(&self, batch: &mut Batch) -> sp_blockchain::Result<()> {
+		let mut references = BTreeMap::<DbHash, u32>::new();
+		for lookup_key in self.blocks.keys() {
+			if let Some(body) = self.source.get(columns::BODY_INDEX, lookup_key) {
+				let body =
+					Vec::<DbExtrinsic<Block>>::decode(&mut &body[..]).map_err(backend_err)?;
+				for extrinsic in body {
+					if let DbExtrinsic::Indexed { hash, .. } = extrinsic {
+						*references.entry(hash).or_default() += 1;
+					}
+				}
+			}
+			let key = [&meta_keys::INDEXED_TRANSACTIONS_PREFIX[..], &lookup_key[..]].concat();
+			if let Some(hashes) = self.source.get(COLUMN_META, &key) {
+				for hash in Vec::<DbHash>::decode(&mut &hashes[..]).map_err(backend_err)? {
+					*references.entry(hash).or_default() += 1;
+				}
+			}
+		}
+		for (hash, references) in references {
+			if batch.is_copied(hash.as_ref()) {
+				continue
+			}
+			if let Some(value) = self.source.get(columns::TRANSACTION, hash.as_ref()) {
+				batch.put(columns::TRANSACTION, hash.as_ref(), &value)?;
+				let counter_key = [hash.as_ref(), &[0]].concat();
+				batch.put(columns::TRANSACTION, &counter_key, &references.to_le_bytes())?;
+			}
+			batch.copied(hash.as_ref().to_vec());
+		}
+		Ok(())
+	}
+
+	/// Copy the states kept by the source, block by block in the order of their lookup keys. Only
+	/// the nodes that are not part of the state of the parent block are walked when the parent
+	/// state has been copied.
+	fn copy_state(&self, batch: &mut Batch) -> sp_blockchain::Result<()> {
+		let meta_db = StateMetaDb(self.source);
+		let mode = sc_state_db::fetch_stored_pruning_mode(&meta_db)
+			.map_err(sp_blockchain::Error::from_state_db)?;
+		let last_canonical = sc_state_db::fetch_last_canonical::<Block::Hash, _>(&meta_db)
+			.map_err(sp_blockchain::Error::from_state_db)?
+			.map(|(_, number)| number);
+		let first = match mode {
+			Some(PruningMode::Constrained(_)) => sc_state_db::fetch_last_pruned(&meta_db)
+				.map_err(sp_blockchain::Error::from_state_db)?
+				.map_or(0, |number| number + 1),
+			Some(PruningMode::Hybrid { .. }) =>
+				return Err(sp_blockchain::Error::Backend(
+					"The state of a hybrid pruning database can not be migrated".into(),
+				)),
+			_ => 0,
+		};
+		let read = |key: &[u8]| self.source.get(columns::STATE, &key[key.len() - DB_HASH_LEN..]);
+		let empty_root = HashFor::<Block>::hash(&[0]);
+
+		// State roots of the copied blocks.
+		let mut roots = HashMap::new();
+		for (lookup_key, hash) in &self.blocks {
+			let header = self.header(*hash)?;
+			let root = header.state_root().as_ref().to_vec();
+			let number: u64 = (*header.number()).unique_saturated_into();
+			let retained = if mode == Some(PruningMode::ArchiveAll) {
+				root == empty_root.as_ref() || read(&root).is_some()
+			} else {
+				self.is_canonical(lookup_key) &&
+					number >= first && last_canonical.map_or(false, |last| number <= last)
+			};
+			if !retained {
+				continue
+			}
+			let live: Vec<_> = roots.get(header.parent_hash()).cloned().into_iter().collect();
+			roots.insert(*hash, root.clone());
+			if batch.is_copied(lookup_key) {
+				continue
+			}
+			let mut nodes = 0u64;
+			UnsharedNodes::<HashFor<Block>>::new(&read, &mut |key, node| {
+				let node = node.or_else(|| read(&key)).ok_or_else(|| missing_node(&key))?;
+				nodes += 1;
+				batch.put(columns::STATE, &key, &node)
+			})
+			.complete()
+			.walk(root, &live)?;
+			debug!("Copied {} state nodes of block #{}", nodes, number);
+			batch.copied(lookup_key.clone());
+		}
+		Ok(())
+	}
+
+	/// Copy the state journals with their node changes recomputed with the trie positions of the
+	/// nodes, and the other entries as they are.
+	fn copy_state_meta(&self, batch: &mut Batch) -> sp_blockchain::Result<()> {
+		// Nodes inserted by the blocks that are not canonicalized yet are only stored in the
+		// journals.
+		let mut overlay = HashMap::<Vec<u8>, DBValue>::new();
+		for entry in parity_db::ordered_entries(self.source_db, columns::STATE_META, None)
+			.map_err(backend_err)?
+		{
+			let (key, value) = entry.map_err(backend_err)?;
+			if let Some(changes) = journal_changes::<Block>(&key, &value)? {
+				for (key, node) in changes.inserted {
+					if let Some(node) = node.filter(|node| !node.is_empty()) {
+						overlay.insert(key, node);
+					}
+				}
+			}
+		}
+		let read = |key: &[u8]| {
+			let hash = &key[key.len() - DB_HASH_LEN..];
+			overlay.get(hash).cloned().or_else(|| self.source.get(columns::STATE, hash))
+		};
+
+		let after = batch.progress.last_key.clone();
+		for entry in
+			parity_db::ordered_entries(self.source_db, columns::STATE_META, after.as_deref())
+				.map_err(backend_err)?
+		{
+			let (key, value) = entry.map_err(backend_err)?;
+			let value = match journal_changes::<Block>(&key, &value)? {
+				Some(changes) => {
+					let header = self.header(changes.hash)?;
+					let root = header.state_root().as_ref().to_vec();
+					let parent_root = if header.number().is_zero() {
+						None
+					} else {
+						Some(self.header(*header.parent_hash())?.state_root().as_ref().to_vec())
+					};
+					// Pruning journals only record the keys of the inserted nodes.
+					let with_nodes = changes.parent_hash.is_some();
+					let mut inserted = Vec::new();
+					UnsharedNodes::<HashFor<Block>>::new(&read, &mut |key, node| {
+						let node = if with_nodes {
+							Some(node.or_else(|| read(&key)).ok_or_else(|| missing_node(&key))?)
+						} else {
+							None
+						};
+						inserted.push((key, node));
+						Ok(())
+					})
+					.complete()
+					.walk(root.clone(), parent_root.as_slice())?;
+					let mut deleted = Vec::new();
+					if let Some(parent_root) = parent_root {
+						UnsharedNodes::<HashFor<Block>>::new(&read, &mut |key, _| {
+							deleted.push(key);
+							Ok(())
+						})
+						.walk(parent_root, &[root])?;
+					}
+					let changes = JournalChanges { inserted, deleted, ..changes };
+					sc_state_db::replace_journal_changes(&key, changes)
+						.expect("`key` is a journal key; qed")
+				},
+				None => value,
+			};
+			batch.put(columns::STATE_META, &key, &value)?;
+			batch.copied(key);
+		}
+		Ok(())
+	}
+}
+
+fn missing_node(key: &[u8]) -> sp_blockchain::Error {
+	sp_blockchain::Error::Backend(format!("Missing state node 0x{}", hex(key)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		tests::Block, utils::open_database, Backend, BlocksPruning, DatabaseSettings,
+		DatabaseSource,
+	};
+	use sc_client_api::backend::{Backend as _, BlockImportOperation as _, NewBlockState};
+	use sp_core::storage::Storage;
+	use sp_runtime::{testing::Header, StateVersion};
+	use sp_state_machine::Backend as _;
+
+	#[test]
+	fn migrate_rocksdb_to_paritydb_works() {
+		let base_path = tempfile::TempDir::new().unwrap();
+		let rocksdb_path = base_path.path().join("db");
+		let paritydb_path = base_path.path().join("paritydb");
+		let hash = DbHash::repeat_byte(1);
+		let prefixed_key = [&[2, 2][..], hash.as_ref()].concat();
+
+		{
+			let source = open_database::<Block>(
+				&DatabaseSource::RocksDb { path: rocksdb_path.clone(), cache_size: 128 },
+				DatabaseType::Full,
+				true,
+			)
+			.unwrap();
+			let mut transaction = Transaction::new();
+			transaction.set(columns::STATE, &prefixed_key, b"node");
+			transaction.store(columns::TRANSACTION, hash, b"indexed".to_vec());
+			transaction.reference(columns::TRANSACTION, hash);
+			transaction.set(columns::AUX, b"aux", b"value");
+			source.commit(transaction).unwrap();
+		}
+
+		let summary = migrate_rocksdb_to_paritydb::<Block>(&rocksdb_path, &paritydb_path).unwrap();
+		let entries = |column| summary.iter().find(|c| c.column == column).unwrap().entries;
+		assert_eq!(entries(columns::STATE), 1);
+		assert_eq!(entries(columns::TRANSACTION), 1);
+		assert_eq!(entries(columns::AUX), 1);
+
+		// migrating into an existing database is refused
+		assert!(migrate_rocksdb_to_paritydb::<Block>(&rocksdb_path, &paritydb_path).is_err());
+
+		let target = open_database::<Block>(
+			&DatabaseSource::ParityDb { path: paritydb_path },
+			DatabaseType::Full,
+			false,
+		)
+		.unwrap();
+		assert_eq!(target.get(columns::STATE, hash.as_ref()), Some(b"node".to_vec()));
+		assert_eq!(target.get(columns::AUX, b"aux"), Some(b"value".to_vec()));
+		assert_eq!(target.get(COLUMN_META, MIGRATION_PROGRESS), None);
+
+		// both references of the indexed transaction have been preserved
+		let mut transaction = Transaction::new();
+		transaction.release(columns::TRANSACTION, hash);
+		target.commit(transaction).unwrap();
+		assert_eq!(target.get(columns::TRANSACTION, hash.as_ref()), Some(b"indexed".to_vec()));
+		let mut transaction = Transaction::new();
+		transaction.release(columns::TRANSACTION, hash);
+		target.commit(transaction).unwrap();
+		assert_eq!(target.get(columns::TRANSACTION, hash.as_ref()), None);
+	}
+
+	#[test]
+	fn migrate_to_paritydb_and_back_works() {
+		let state_version = StateVersion::V1;
+		let base_path = tempfile::TempDir::new().unwrap();
+		let rocksdb_path = base_path.path().join("db");
+		let paritydb_path = base_path.path().join("paritydb");
+		let migrated_path = base_path.path().join("migrated");
+		let settings = |path: &Path| DatabaseSettings {
+			trie_cache_maximum_size: None,
+			state_pruning: Some(PruningMode::blocks_pruning(256)),
+			source: DatabaseSource::RocksDb { path: path.to_path_buf(), cache_size: 16 },
+			blocks_pruning: BlocksPruning::All,
+			blocks_retention: Default::default(),
+			changes_index: false,
+		};
+
+		let mut hashes = Vec::new();
+		{
+			let backend = Backend::<Block>::new(settings(&rocksdb_path), 0).unwrap();
+			for i in 0..6u8 {
+				let mut op = backend.begin_operation().unwrap();
+				let mut header = Header {
+					number: i as u64,
+					parent_hash: hashes.last().cloned().unwrap_or_default(),
+					state_root: Default::default(),
+					digest: Default::default(),
+					extrinsics_root: Default::default(),
+				};
+				let storage = vec![(vec![0xaa], vec![i; 40]), (vec![0xbb, i], vec![i; 40])];
+				if i == 0 {
+					header.state_root = op
+						.old_state
+						.storage_root(
+							storage.iter().map(|(k, v)| (&k[..], Some(&v[..]))),
+							state_version,
+						)
+						.0
+						.into();
+					op.reset_storage(
+						Storage {
+							top: storage.into_iter().collect(),
+							children_default: Default::default(),
+						},
+						state_version,
+					)
+					.unwrap();
+				} else {
+					backend.begin_state_operation(&mut op, BlockId::Number(i as u64 - 1)).unwrap();
+					let (root, overlay) = op.old_state.storage_root(
+						storage.iter().map(|(k, v)| (&k[..], Some(&v[..]))),
+						state_version,
+					);
+					op.update_db_storage(overlay).unwrap();
+					header.state_root = root.into();
+					op.update_storage(
+						storage.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+						Vec::new(),
+					)
+					.unwrap();
+				}
+				hashes.push(header.hash());
+				let state = if i < 4 { NewBlockState::Final } else { NewBlockState::Best };
+				op.set_block_data(header, Some(vec![]), None, None, state).unwrap();
+				backend.commit_operation(op).unwrap();
+			}
+		}
+
+		migrate_rocksdb_to_paritydb::<Block>(&rocksdb_path, &paritydb_path).unwrap();
+		let summary = migrate_paritydb_to_rocksdb::<Block>(&paritydb_path, &migrated_path).unwrap();
+		let entries = |column| summary.iter().find(|c| c.column == column).unwrap().entries;
+		assert_eq!(entries(columns::HEADER), 6);
+
+		// the state nodes are stored under the same keys as in the original database
+		{
+			let config = kvdb_rocksdb::DatabaseConfig::with_columns(NUM_COLUMNS);
+			let original = kvdb_rocksdb::Database::open(&config, &rocksdb_path).unwrap();
+			let migrated = kvdb_rocksdb::Database::open(&config, &migrated_path).unwrap();
+			let nodes = |db: &kvdb_rocksdb::Database| db.iter(columns::STATE).collect::<Vec<_>>();
+			assert_eq!(nodes(&migrated), nodes(&original));
+			assert_eq!(entries(columns::STATE), nodes(&original).len() as u64);
+		}
+
+		let backend = Backend::<Block>::new(settings(&migrated_path), 0).unwrap();
+		{
+			let mut op = backend.begin_operation().unwrap();
+			backend.begin_state_operation(&mut op, BlockId::Hash(hashes[5])).unwrap();
+			op.mark_finalized(BlockId::Hash(hashes[5]), None).unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+		for i in 0..6 {
+			let state = backend.state_at(BlockId::Hash(hashes[i])).unwrap();
+			assert_eq!(state.storage(&[0xaa]).unwrap(), Some(vec![i as u8; 40]));
+			for j in 0..=i as u8 {
+				assert_eq!(state.storage(&[0xbb, j]).unwrap(), Some(vec![j; 40]));
+			}
+		}
+	}
+}
@@ -20,9 +20,24 @@ use crate::{
 	utils::{DatabaseType, NUM_COLUMNS},
 };
 /// A `Database` adapter for parity-db.
-use sp_database::{error::DatabaseError, Change, ColumnId, Database, Transaction};
+use sp_database::{error::DatabaseError, Change, ColumnId, ColumnStats, Database, Transaction};
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+/// Columns using an ordered index, so they can be iterated. Databases created before these
+/// columns were ordered keep their hashed index, columns added since are ordered.
+pub(crate) const ORDERED_COLUMNS: [u32; 6] = [
+	columns::META,
+	columns::STATE_META,
+	columns::AUX,
+	columns::OFFCHAIN,
+	columns::CHANGES_INDEX,
+	columns::STATE_SYNC,
+];
 
-struct DbAdapter(parity_db::Db);
+struct DbAdapter(Arc<parity_db::Db>, PathBuf);
 
 fn handle_err<T>(result: parity_db::Result<T>) -> T {
 	match result {
@@ -35,11 +50,59 @@ fn handle_err<T>(result: parity_db::Result<T>) -> T {
 
 /// Wrap parity-db database into a trait object that implements `sp_database::Database`
 pub fn open<H: Clone + AsRef<[u8]>>(
-	path: &std::path::Path,
+	path: &Path,
 	db_type: DatabaseType,
 	create: bool,
 	upgrade: bool,
-) -> parity_db::Result<std::sync::Arc<dyn Database<H>>> {
+) -> parity_db::Result<Arc<dyn Database<H>>> {
+	Ok(as_database(open_db(path, db_type, create, upgrade)?, path))
+}
+
+/// Wrap an open parity-db database at `path` into a trait object that implements
+/// `sp_database::Database`.
+pub(crate) fn as_database<H: Clone + AsRef<[u8]>>(
+	db: Arc<parity_db::Db>,
+	path: &Path,
+) -> Arc<dyn Database<H>> {
+	Arc::new(DbAdapter(db, path.to_path_buf()))
+}
+
+/// Open the existing parity-db database at `path` read-only.
+pub(crate) fn open_read_only<H: Clone + AsRef<[u8]>>(
+	path: &Path,
+	db_type: DatabaseType,
+) -> parity_db::Result<Arc<dyn Database<H>>> {
+	let config = options(path, db_type)?;
+	Ok(as_database(Arc::new(parity_db::Db::open_read_only(&config)?), path))
+}
+
+/// Open the parity-db database at `path` with the column configuration of `db_type`.
+pub(crate) fn open_db(
+	path: &Path,
+	db_type: DatabaseType,
+	create: bool,
+	upgrade: bool,
+) -> parity_db::Result<Arc<parity_db::Db>> {
+	let config = options(path, db_type)?;
+
+	if upgrade {
+		log::info!("Upgrading database metadata.");
+		if let Some(meta) = parity_db::Options::load_metadata(path)? {
+			config.write_metadata_with_version(path, &meta.salt, Some(meta.version))?;
+		}
+	}
+
+	let db = if create {
+		parity_db::Db::open_or_create(&config)?
+	} else {
+		parity_db::Db::open(&config)?
+	};
+
+	Ok(Arc::new(db))
+}
+
+/// The column configuration of `db_type` for the database at `path`.
+fn options(path: &Path, db_type: DatabaseType) -> parity_db::Result<parity_db::Options> {
 	let mut config = parity_db::Options::with_columns(path, NUM_COLUMNS as u8);
 
 	match db_type {
@@ -70,20 +133,49 @@ pub fn open<H: Clone + AsRef<[u8]>>(
 		},
 	}
 
-	if upgrade {
-		log::info!("Upgrading database metadata.");
-		if let Some(meta) = parity_db::Options::load_metadata(path)? {
-			config.write_metadata_with_version(path, &meta.salt, Some(meta.version))?;
-		}
+	let meta = parity_db::Options::load_metadata(path)?;
+	for i in ORDERED_COLUMNS {
+		config.columns[i as usize].btree_index = meta
+			.as_ref()
+			.map_or(true, |meta| meta.columns.get(i as usize).map_or(true, |c| c.btree_index));
 	}
 
-	let db = if create {
-		parity_db::Db::open_or_create(&config)?
-	} else {
-		parity_db::Db::open(&config)?
-	};
+	Ok(config)
+}
 
-	Ok(std::sync::Arc::new(DbAdapter(db)))
+/// Returns the columns of `ORDERED_COLUMNS` that are not ordered in the database at `path`.
+#[cfg(any(feature = "rocksdb", test))]
+pub(crate) fn unordered_columns(path: &Path) -> parity_db::Result<Vec<u32>> {
+	let meta = parity_db::Options::load_metadata(path)?;
+	Ok(ORDERED_COLUMNS
+		.into_iter()
+		.filter(|i| {
+			!meta
+				.as_ref()
+				.map_or(false, |meta| meta.columns.get(*i as usize).map_or(true, |c| c.btree_index))
+		})
+		.collect())
+}
+
+/// Iterate the entries of the ordered column `col` of `db` in key order, starting after `after`.
+#[cfg(any(feature = "rocksdb", test))]
+pub(crate) fn ordered_entries<'a>(
+	db: &'a parity_db::Db,
+	col: u32,
+	after: Option<&[u8]>,
+) -> parity_db::Result<impl Iterator<Item = parity_db::Result<(Vec<u8>, Vec<u8>)>> + 'a> {
+	let mut iter = db.iter(col as u8)?;
+	match after {
+		Some(key) => iter.seek(key)?,
+		None => iter.seek_to_first()?,
+	}
+	let after = after.map(|key| key.to_vec());
+	Ok(std::iter::from_fn(move || iter.next().transpose()).filter(move |entry| {
+		match (entry, &after) {
+			(Ok((key, _)), Some(after)) => key != after,
+			_ => true,
+		}
+	}))
 }
 
 fn ref_counted_column(col: u32) -> bool {
@@ -159,4 +251,37 @@ impl<H: Clone + AsRef<[u8]>> Database<H> for DbAdapter {
 	fn sanitize_key(&self, key: &mut Vec<u8>) {
 		let _prefix = key.drain(0..key.len() - crate::DB_HASH_LEN);
 	}
+
+	fn column_stats(&self, col: ColumnId) -> Option<ColumnStats> {
+		// The index and the value tables of a column are stored in files named after it.
+		let prefixes = [format!("index_{:02}_", col), format!("table_{:02}_", col)];
+		let mut bytes = 0;
+		for entry in std::fs::read_dir(&self.1).ok()? {
+			let entry = entry.ok()?;
+			let name = entry.file_name();
+			let name = name.to_string_lossy();
+			if prefixes.iter().any(|prefix| name.starts_with(prefix.as_str())) {
+				bytes += entry.metadata().ok()?.len();
+			}
+		}
+		Some(ColumnStats { keys: None, bytes })
+	}
+
+	fn iter_column(&self, col: ColumnId, f: &mut dyn FnMut(Option<&[u8]>, &[u8]) -> bool) -> bool {
+		// Only the ordered columns can be iterated, the others are indexed by hash.
+		let mut iter = match self.0.iter(col as u8) {
+			Ok(iter) => iter,
+			Err(_) => return false,
+		};
+		let result = iter.seek(&[]).and_then(|()| {
+			while let Some((key, value)) = iter.next()? {
+				if !f(Some(&key), &value) {
+					break
+				}
+			}
+			Ok(())
+		});
+		handle_err(result);
+		true
+	}
 }
@@ -0,0 +1,101 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Read-only access to the RocksDB database of another node.
+//!
+//! The database is opened as a RocksDB secondary instance, which reads the files of a database
+//! kept open and written to by the node owning it. The secondary instance only sees the writes
+//! made up to the last time it caught up with the primary instance. As the owning node keeps
+//! pruning, reading the state or the bodies of old blocks may fail while they are being served.
+
+use crate::{
+	utils::{self, DatabaseType, OpenDbError, NUM_COLUMNS},
+	CatchUp, DbHash,
+};
+use kvdb::KeyValueDB;
+use sp_database::{error::DatabaseError, ColumnId, Database, Transaction};
+use std::{io, path::Path, sync::Arc};
+
+/// A RocksDB secondary instance, refusing all writes.
+struct SecondaryDb(Arc<kvdb_rocksdb::Database>);
+
+fn handle_err<T>(result: io::Result<T>) -> T {
+	match result {
+		Ok(r) => r,
+		Err(e) => {
+			panic!("Critical database error: {:?}", e);
+		},
+	}
+}
+
+impl Database<DbHash> for SecondaryDb {
+	fn commit(&self, _transaction: Transaction<DbHash>) -> sp_database::error::Result<()> {
+		Err(DatabaseError(Box::new(io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			"The database is opened read-only",
+		))))
+	}
+
+	fn get(&self, col: ColumnId, key: &[u8]) -> Option<Vec<u8>> {
+		handle_err(self.0.get(col, key))
+	}
+
+	fn contains(&self, col: ColumnId, key: &[u8]) -> bool {
+		handle_err(self.0.has_key(col, key))
+	}
+
+	fn estimate_num_keys(&self, col: ColumnId) -> Option<u64> {
+		self.0.num_keys(col).ok()
+	}
+
+	fn iter_column(&self, col: ColumnId, f: &mut dyn FnMut(Option<&[u8]>, &[u8]) -> bool) -> bool {
+		for (key, value) in self.0.iter(col) {
+			if !f(Some(&key), &value) {
+				break
+			}
+		}
+		true
+	}
+}
+
+/// Open the RocksDB database at `path` as a secondary instance keeping its own files in
+/// `secondary_path`. Returns the database along with the function catching up with the primary
+/// instance.
+pub(crate) fn open(
+	path: &Path,
+	secondary_path: &Path,
+	db_type: DatabaseType,
+	cache_size: usize,
+) -> Result<(Arc<dyn Database<DbHash>>, CatchUp), OpenDbError> {
+	// The database can't be upgraded without write access.
+	match crate::upgrade::check_version(path) {
+		Ok(()) => (),
+		Err(crate::upgrade::UpgradeError::MissingDatabaseVersionFile) =>
+			return Err(OpenDbError::DoesNotExist),
+		Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err.to_string()).into()),
+	}
+
+	let mut db_config = kvdb_rocksdb::DatabaseConfig::with_columns(NUM_COLUMNS);
+	db_config.secondary = Some(secondary_path.to_path_buf());
+	db_config.memory_budget = utils::rocksdb_memory_budget(path, db_type, cache_size);
+	let db = Arc::new(kvdb_rocksdb::Database::open(&db_config, path)?);
+
+	let primary = db.clone();
+	let catch_up: CatchUp = Box::new(move || primary.try_catch_up_with_primary());
+	Ok((Arc::new(SecondaryDb(db)), catch_up))
+}
@@ -0,0 +1,271 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! State snapshots used to bootstrap a database at a given block.
+//!
+//! A snapshot starts with the format version and a [`SnapshotHeader`], followed by chunks of trie
+//! nodes of the state of the snapshot block. A chunk is a list of `(prefix, node)` pairs, where
+//! `prefix` is the position of the node in the state. Nodes are stored under their prefix
+//! followed by their hash, so the keys are derived from the nodes on import. An empty chunk ends
+//! the snapshot.
+
+use crate::{check, columns, trie_nodes, utils::meta_keys, Backend};
+use codec::{Decode, Encode, IoReader};
+use hash_db::{Hasher, Prefix};
+use log::info;
+use sc_client_api::backend::{Backend as _, BlockImportOperation as _, NewBlockState};
+use sp_blockchain::{Backend as _, Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::storage::well_known_keys;
+use sp_database::Transaction;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor, Header as HeaderT, NumberFor, Zero},
+	Justifications,
+};
+use sp_state_machine::{DBValue, Storage as _};
+use std::io::{Read, Write};
+use trie_db::node::{Node, NodeHandle, Value};
+
+/// Version of the snapshot format.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Size in bytes of the trie nodes above which a chunk is written.
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Blocks of a snapshot.
+#[derive(Encode, Decode)]
+struct SnapshotHeader<Block: BlockT> {
+	/// Header of the genesis block.
+	genesis: Block::Header,
+	/// Header of the snapshot block.
+	header: Block::Header,
+	/// Body of the snapshot block, if it was not pruned.
+	body: Option<Vec<Block::Extrinsic>>,
+	/// Justifications of the snapshot block.
+	justifications: Option<Justifications>,
+}
+
+/// Write a snapshot of the state of the block with `hash` to `output`. Returns the number of trie
+/// nodes written.
+pub(crate) fn export<Block: BlockT>(
+	backend: &Backend<Block>,
+	hash: Block::Hash,
+	output: &mut dyn Write,
+) -> ClientResult<u64> {
+	let blockchain = &backend.blockchain;
+	let header = blockchain.expect_header(BlockId::Hash(hash))?;
+	// Keeps the state pinned while it is exported.
+	let _state = backend.state_at(BlockId::Hash(hash))?;
+	let snapshot = SnapshotHeader::<Block> {
+		genesis: blockchain.expect_header(BlockId::Hash(blockchain.info().genesis_hash))?,
+		body: blockchain.body(BlockId::Hash(hash))?,
+		justifications: blockchain.justifications(BlockId::Hash(hash))?,
+		header,
+	};
+	write(output, &SNAPSHOT_VERSION.encode())?;
+	write(output, &snapshot.encode())?;
+
+	let mut exporter = Exporter { backend, output, chunk: Vec::new(), chunk_size: 0, nodes: 0 };
+	let root = *snapshot.header.state_root();
+	if root != <HashFor<Block> as Hasher>::hash(&[0u8]) {
+		exporter.walk(&[], root, &mut Vec::new())?;
+	}
+	if !exporter.chunk.is_empty() {
+		exporter.flush()?;
+	}
+	exporter.flush()?;
+	Ok(exporter.nodes)
+}
+
+/// Seed an empty database with the snapshot read from `input`. Returns the number and hash of the
+/// snapshot block.
+pub(crate) fn import<Block: BlockT>(
+	backend: &Backend<Block>,
+	input: &mut dyn Read,
+) -> ClientResult<(NumberFor<Block>, Block::Hash)> {
+	if backend.storage.db.get(columns::META, meta_keys::GENESIS_HASH).is_some() {
+		return Err(ClientError::Backend(
+			"Snapshots can only be imported into an empty database".into(),
+		))
+	}
+	let mut input = IoReader(input);
+	let version = u32::decode(&mut input).map_err(decode_error)?;
+	if version != SNAPSHOT_VERSION {
+		return Err(ClientError::Backend(format!("Unsupported snapshot version {}", version)))
+	}
+	let snapshot = SnapshotHeader::<Block>::decode(&mut input).map_err(decode_error)?;
+	let number = *snapshot.header.number();
+	let hash = snapshot.header.hash();
+	if number.is_zero() || !snapshot.genesis.number().is_zero() {
+		return Err(ClientError::Backend("Invalid snapshot blocks".into()))
+	}
+
+	let mut nodes = 0;
+	loop {
+		let chunk = Vec::<(Vec<u8>, DBValue)>::decode(&mut input).map_err(decode_error)?;
+		if chunk.is_empty() {
+			break
+		}
+		let mut transaction = Transaction::new();
+		for (mut key, node) in chunk {
+			let node_hash = <HashFor<Block> as Hasher>::hash(&node);
+			if !backend.storage.prefix_keys {
+				key.clear();
+			}
+			key.extend_from_slice(node_hash.as_ref());
+			transaction.set_from_vec(columns::STATE, &key, node);
+			nodes += 1;
+		}
+		backend.storage.db.commit(transaction)?;
+	}
+	let entries = check::read_state(&backend.storage, snapshot.header.state_root())
+		.map_err(|e| ClientError::Backend(format!("Incomplete snapshot state: {}", e)))?;
+	info!(
+		target: "db",
+		"Imported {} trie nodes with {} storage entries of block #{} ({:?})",
+		nodes,
+		entries,
+		number,
+		hash,
+	);
+
+	// As with warp sync, the genesis block is imported without its state and the blocks between
+	// it and the snapshot block are left as a block gap.
+	let mut op = backend.begin_operation()?;
+	op.set_block_data(snapshot.genesis, None, None, None, NewBlockState::Final)?;
+	backend.commit_operation(op)?;
+
+	let mut op = backend.begin_operation()?;
+	op.set_block_data(
+		snapshot.header,
+		snapshot.body,
+		None,
+		snapshot.justifications,
+		NewBlockState::Final,
+	)?;
+	op.commit_state = true;
+	op.unknown_changes = true;
+	backend.commit_operation(op)?;
+	Ok((number, hash))
+}
+
+fn write(output: &mut dyn Write, data: &[u8]) -> ClientResult<()> {
+	output
+		.write_all(data)
+		.map_err(|e| ClientError::Backend(format!("Error writing snapshot: {}", e)))
+}
+
+fn decode_error(e: codec::Error) -> ClientError {
+	ClientError::Backend(format!("Error decoding snapshot: {}", e))
+}
+
+/// Writes the trie nodes of a state in chunks.
+struct Exporter<'a, Block: BlockT> {
+	backend: &'a Backend<Block>,
+	output: &'a mut dyn Write,
+	chunk: Vec<(Vec<u8>, DBValue)>,
+	chunk_size: usize,
+	nodes: u64,
+}
+
+impl<'a, Block: BlockT> Exporter<'a, Block> {
+	/// Export the nodes of the subtrie with root `hash` at nibble `path` in `keyspace`.
+	fn walk(&mut self, keyspace: &[u8], hash: Block::Hash, path: &mut Vec<u8>) -> ClientResult<()> {
+		let prefix = trie_nodes::prefix(keyspace, path);
+		let data = self.node(&hash, (&prefix.0, prefix.1))?;
+		let (partial, children, value) = match trie_nodes::decode::<HashFor<Block>>(&data)? {
+			Node::Leaf(partial, value) => (partial, None, Some(value)),
+			Node::NibbledBranch(partial, children, value) => (partial, Some(children), value),
+			_ => return Err(ClientError::Backend(format!("Unexpected trie node {:?}", hash))),
+		};
+		let depth = path.len();
+		path.extend((0..partial.len()).map(|i| partial.at(i)));
+		let children: Vec<_> = children
+			.iter()
+			.flatten()
+			.enumerate()
+			.filter_map(|(nibble, child)| match child {
+				Some(NodeHandle::Hash(hash)) => Some((nibble as u8, hash.to_vec())),
+				_ => None,
+			})
+			.collect();
+		let value = match value {
+			Some(Value::Node(hash)) => Some((true, hash.to_vec())),
+			Some(Value::Inline(value)) => Some((false, value.to_vec())),
+			None => None,
+		};
+		let (mut key, padded) = prefix;
+		key.extend(padded);
+		self.push(key, data)?;
+
+		match value {
+			Some((true, hash)) => {
+				let (key, padded) = trie_nodes::prefix(keyspace, path);
+				let hash = decode_hash::<Block>(&hash)?;
+				let data = self.node(&hash, (&key, padded))?;
+				self.push(key, data)?;
+			},
+			Some((false, child_root)) if keyspace.is_empty() && path.len() % 2 == 0 => {
+				let (key, _) = trie_nodes::prefix(&[], path);
+				if let Some(storage_key) =
+					key.strip_prefix(well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX)
+				{
+					self.walk(storage_key, decode_hash::<Block>(&child_root)?, &mut Vec::new())?;
+				}
+			},
+			_ => (),
+		}
+		for (nibble, hash) in children {
+			path.push(nibble);
+			self.walk(keyspace, decode_hash::<Block>(&hash)?, path)?;
+			path.pop();
+		}
+		path.truncate(depth);
+		Ok(())
+	}
+
+	fn node(&self, hash: &Block::Hash, prefix: Prefix) -> ClientResult<DBValue> {
+		self.backend
+			.storage
+			.get(hash, prefix)
+			.map_err(ClientError::Backend)?
+			.ok_or_else(|| ClientError::Backend(format!("Missing trie node {:?}", hash)))
+	}
+
+	fn push(&mut self, key: Vec<u8>, node: DBValue) -> ClientResult<()> {
+		self.chunk_size += key.len() + node.len();
+		self.chunk.push((key, node));
+		self.nodes += 1;
+		if self.chunk_size >= CHUNK_SIZE {
+			self.flush()?;
+		}
+		Ok(())
+	}
+
+	/// Write the current chunk, which ends the snapshot if it is empty.
+	fn flush(&mut self) -> ClientResult<()> {
+		let chunk = std::mem::take(&mut self.chunk);
+		self.chunk_size = 0;
+		write(self.output, &chunk.encode())
+	}
+}
+
+fn decode_hash<Block: BlockT>(hash: &[u8]) -> ClientResult<Block::Hash> {
+	Block::Hash::decode(&mut &hash[..])
+		.map_err(|e| ClientError::Backend(format!("Invalid trie node hash: {}", e)))
+}
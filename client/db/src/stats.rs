@@ -18,7 +18,23 @@
 
 //! Database usage statistics
 
-use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use crate::{
+	utils::{self, NUM_COLUMNS},
+	DbHash,
+};
+use log::warn;
+use prometheus_endpoint::{register, GaugeVec, Opts, PrometheusError, Registry, U64};
+use sp_database::{ColumnStats, Database};
+use std::{
+	sync::{
+		atomic::{AtomicU64, Ordering as AtomicOrdering},
+		Arc,
+	},
+	time::Duration,
+};
+
+/// Interval between two samplings of the estimated size of the database columns.
+const COLUMN_STATS_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Accumulated usage statistics for state queries.
 pub struct StateUsageStats {
@@ -143,3 +159,67 @@ impl StateUsageStats {
 		}
 	}
 }
+
+/// Size statistics of the database columns, along with their name. This may read the whole
+/// database.
+pub(crate) fn column_stats(db: &dyn Database<DbHash>) -> Vec<(&'static str, Option<ColumnStats>)> {
+	(0..NUM_COLUMNS)
+		.map(|col| (utils::column_name(col), col))
+		.filter(|(name, _)| *name != "unknown")
+		.map(|(name, col)| (name, db.column_stats(col)))
+		.collect()
+}
+
+/// Register metrics of the estimated number of keys of the database columns in `registry`, if `db`
+/// can estimate it. The metrics are sampled in the background until `db` is closed.
+pub(crate) fn spawn_column_metrics(
+	db: &Arc<dyn Database<DbHash>>,
+	registry: &Registry,
+) -> Result<(), PrometheusError> {
+	if db.estimate_num_keys(0).is_none() {
+		return Ok(())
+	}
+	let metrics = ColumnMetrics::register(registry)?;
+	let db = Arc::downgrade(db);
+	let spawned = std::thread::Builder::new().name("db-stats".into()).spawn(move || {
+		while let Some(db) = db.upgrade() {
+			metrics.update(&*db);
+			drop(db);
+			std::thread::sleep(COLUMN_STATS_INTERVAL);
+		}
+	});
+	if let Err(e) = spawned {
+		warn!(target: "db", "Error spawning database statistics sampling: {}", e);
+	}
+	Ok(())
+}
+
+/// Prometheus metrics of the estimated size of the database columns.
+struct ColumnMetrics {
+	keys: GaugeVec<U64>,
+}
+
+impl ColumnMetrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			keys: register(
+				GaugeVec::new(
+					Opts::new(
+						"substrate_database_column_keys",
+						"Estimated number of keys in the database columns",
+					),
+					&["column"],
+				)?,
+				registry,
+			)?,
+		})
+	}
+
+	fn update(&self, db: &dyn Database<DbHash>) {
+		for col in (0..NUM_COLUMNS).filter(|col| utils::column_name(*col) != "unknown") {
+			if let Some(keys) = db.estimate_num_keys(col) {
+				self.keys.with_label_values(&[utils::column_name(col)]).set(keys);
+			}
+		}
+	}
+}
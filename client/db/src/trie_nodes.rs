@@ -0,0 +1,233 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Walking the nodes of the state column.
+//!
+//! Nodes are stored under their position in the trie followed by their hash: the nodes of the
+//! main trie under the packed nibbles of their position, and the nodes of a child trie under the
+//! storage key of the child trie followed by the packed nibbles of their position in it.
+
+use hash_db::Hasher;
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_core::storage::well_known_keys;
+use sp_state_machine::DBValue;
+use std::marker::PhantomData;
+use trie_db::node::{Node, NodeHandle, Value};
+
+/// Keys of the state column a lookup in a trie may return.
+#[derive(PartialEq)]
+enum Item {
+	Node(Vec<u8>),
+	InlineValue(Vec<u8>),
+	ValueNode(Vec<u8>),
+}
+
+impl From<Value<'_>> for Item {
+	fn from(value: Value<'_>) -> Self {
+		match value {
+			Value::Inline(value) => Item::InlineValue(value.to_vec()),
+			Value::Node(hash) => Item::ValueNode(hash.to_vec()),
+		}
+	}
+}
+
+/// Walks the nodes of a trie, including its value nodes and child tries, that are not part of
+/// other tries.
+pub(crate) struct UnsharedNodes<'a, H> {
+	read: &'a dyn Fn(&[u8]) -> Option<DBValue>,
+	found: &'a mut dyn FnMut(Vec<u8>, Option<DBValue>) -> ClientResult<()>,
+	complete: bool,
+	_hasher: PhantomData<H>,
+}
+
+impl<'a, H: Hasher> UnsharedNodes<'a, H> {
+	/// Returns a walker reading the nodes stored under a key with `read` and passing the keys of
+	/// the nodes that are not part of the other tries to `found`, along with the node if it was
+	/// read. Value nodes are not read.
+	pub(crate) fn new(
+		read: &'a dyn Fn(&[u8]) -> Option<DBValue>,
+		found: &'a mut dyn FnMut(Vec<u8>, Option<DBValue>) -> ClientResult<()>,
+	) -> Self {
+		UnsharedNodes { read, found, complete: false, _hasher: PhantomData }
+	}
+
+	/// Fail on missing nodes instead of skipping them.
+	#[cfg(any(feature = "rocksdb", test))]
+	pub(crate) fn complete(mut self) -> Self {
+		self.complete = true;
+		self
+	}
+
+	/// Walk the nodes of the trie with `root` that are not part of the `live` tries.
+	pub(crate) fn walk(&mut self, root: Vec<u8>, live: &[Vec<u8>]) -> ClientResult<()> {
+		if self.complete && root == H::hash(&[0]).as_ref() {
+			// The empty trie is not stored.
+			return Ok(())
+		}
+		self.walk_node(&[], root, &mut Vec::new(), live)
+	}
+
+	/// Walk the nodes of the subtrie with root `hash` at nibble `path` that are not part of the
+	/// `live` tries with the same `keyspace`.
+	fn walk_node(
+		&mut self,
+		keyspace: &[u8],
+		hash: Vec<u8>,
+		path: &mut Vec<u8>,
+		live: &[Vec<u8>],
+	) -> ClientResult<()> {
+		for root in live {
+			if self.lookup(keyspace, root, path, false)? == Some(Item::Node(hash.clone())) {
+				return Ok(())
+			}
+		}
+		let key = node_key(keyspace, path, &hash);
+		let data = match (self.read)(&key) {
+			Some(data) => data,
+			None if self.complete => return Err(missing_node(&key)),
+			None => return Ok(()),
+		};
+		(self.found)(key, Some(data.clone()))?;
+
+		let (partial, children, value) = match decode::<H>(&data)? {
+			Node::Leaf(partial, value) => (partial, None, Some(value)),
+			Node::NibbledBranch(partial, children, value) => (partial, Some(children), value),
+			_ => return Ok(()),
+		};
+		let depth = path.len();
+		path.extend((0..partial.len()).map(|i| partial.at(i)));
+		if let Some(value) = value {
+			self.walk_value(keyspace, value, path, live)?;
+		}
+		for (nibble, child) in children.iter().flatten().enumerate() {
+			if let Some(NodeHandle::Hash(hash)) = child {
+				path.push(nibble as u8);
+				self.walk_node(keyspace, hash.to_vec(), path, live)?;
+				path.pop();
+			}
+		}
+		path.truncate(depth);
+		Ok(())
+	}
+
+	/// Walk the value node of the node with full key `path`, or the nodes of the child trie with
+	/// its root stored under `path`.
+	fn walk_value(
+		&mut self,
+		keyspace: &[u8],
+		value: Value<'_>,
+		path: &mut Vec<u8>,
+		live: &[Vec<u8>],
+	) -> ClientResult<()> {
+		let mut live_values = Vec::with_capacity(live.len());
+		for root in live {
+			live_values.push(self.lookup(keyspace, root, path, true)?);
+		}
+		match value {
+			Value::Node(hash) =>
+				if !live_values.contains(&Some(Item::ValueNode(hash.to_vec()))) {
+					(self.found)(node_key(keyspace, path, hash), None)?;
+				},
+			Value::Inline(child_root) if keyspace.is_empty() && path.len() % 2 == 0 => {
+				let (key, _) = prefix(&[], path);
+				if let Some(storage_key) =
+					key.strip_prefix(well_known_keys::DEFAULT_CHILD_STORAGE_KEY_PREFIX)
+				{
+					let live_roots: Vec<_> = live_values
+						.into_iter()
+						.filter_map(|value| match value {
+							Some(Item::InlineValue(root)) => Some(root),
+							_ => None,
+						})
+						.collect();
+					self.walk_node(storage_key, child_root.to_vec(), &mut Vec::new(), &live_roots)?;
+				}
+			},
+			Value::Inline(_) => (),
+		}
+		Ok(())
+	}
+
+	/// Returns the hash of the node at nibble `path` of the trie with `root` or, with `value`
+	/// set, the value stored under the full nibble key `path`.
+	fn lookup(
+		&self,
+		keyspace: &[u8],
+		root: &[u8],
+		path: &[u8],
+		value: bool,
+	) -> ClientResult<Option<Item>> {
+		let mut hash = root.to_vec();
+		let mut depth = 0;
+		loop {
+			if !value && depth == path.len() {
+				return Ok(Some(Item::Node(hash)))
+			}
+			let data = match (self.read)(&node_key(keyspace, &path[..depth], &hash)) {
+				Some(data) => data,
+				None => return Ok(None),
+			};
+			let (partial, children, node_value) = match decode::<H>(&data)? {
+				Node::Leaf(partial, value) => (partial, None, Some(value)),
+				Node::NibbledBranch(partial, children, value) => (partial, Some(children), value),
+				_ => return Ok(None),
+			};
+			let end = depth + partial.len();
+			if path.len() < end || (0..partial.len()).any(|i| partial.at(i) != path[depth + i]) {
+				return Ok(None)
+			}
+			if end == path.len() {
+				return Ok(if value { node_value.map(Into::into) } else { None })
+			}
+			match children.and_then(|children| children[path[end] as usize]) {
+				Some(NodeHandle::Hash(child)) => {
+					hash = child.to_vec();
+					depth = end + 1;
+				},
+				_ => return Ok(None),
+			}
+		}
+	}
+}
+
+pub(crate) fn decode<H: Hasher>(data: &[u8]) -> ClientResult<Node<'_>> {
+	<sp_trie::NodeCodec<H> as trie_db::NodeCodec>::decode(data)
+		.map_err(|e| ClientError::Backend(format!("Error decoding trie node: {:?}", e)))
+}
+
+/// Returns the bytes and the padded last nibble of a nibble `path` in `keyspace`.
+pub(crate) fn prefix(keyspace: &[u8], path: &[u8]) -> (Vec<u8>, Option<u8>) {
+	let mut bytes = keyspace.to_vec();
+	bytes.extend(path.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1]));
+	(bytes, (path.len() % 2 == 1).then(|| path[path.len() - 1] << 4))
+}
+
+/// Returns the key of the node with `hash` at nibble `path` in the state column.
+fn node_key(keyspace: &[u8], path: &[u8], hash: &[u8]) -> Vec<u8> {
+	let (mut key, padded) = prefix(keyspace, path);
+	key.extend(padded);
+	key.extend_from_slice(hash);
+	key
+}
+
+fn missing_node(key: &[u8]) -> ClientError {
+	ClientError::Backend(format!(
+		"Missing state node {}",
+		sp_core::hexdisplay::HexDisplay::from(&key)
+	))
+}
@@ -34,7 +34,7 @@ use sp_trie::DBValue;
 
 /// Number of columns in the db. Must be the same for both full && light dbs.
 /// Otherwise RocksDb will fail to open database && check its type.
-pub const NUM_COLUMNS: u32 = 13;
+pub const NUM_COLUMNS: u32 = 15;
 /// Meta column. The set of keys in the column is shared by full && light storages.
 pub const COLUMN_META: u32 = 0;
 
@@ -56,6 +56,8 @@ pub mod meta_keys {
 	pub const LEAF_PREFIX: &[u8; 4] = b"leaf";
 	/// Children prefix list key.
 	pub const CHILDREN_PREFIX: &[u8; 8] = b"children";
+	/// Prefix of the indexed transactions of blocks whose body was pruned.
+	pub const INDEXED_TRANSACTIONS_PREFIX: &[u8; 10] = b"indexed_tx";
 }
 
 /// Database metadata.
@@ -195,6 +197,9 @@ fn open_database_at<Block: BlockT>(
 		#[cfg(feature = "rocksdb")]
 		DatabaseSource::RocksDb { path, cache_size } =>
 			open_kvdb_rocksdb::<Block>(path, db_type, create, *cache_size)?,
+		#[cfg(feature = "rocksdb")]
+		DatabaseSource::RocksDbSecondary { path, secondary_path, cache_size } =>
+			crate::secondary::open(path, secondary_path, db_type, *cache_size)?.0,
 		DatabaseSource::Custom { db, require_create_flag } => {
 			if *require_create_flag && !create {
 				return Err(OpenDbError::DoesNotExist)
@@ -216,6 +221,55 @@ fn open_database_at<Block: BlockT>(
 	Ok(db)
 }
 
+/// Opens the configured database read-only, without creating or upgrading it. A RocksDB database
+/// is opened as a secondary instance keeping its files in `secondary_path`.
+pub fn open_database_read_only(
+	db_source: &DatabaseSource,
+	db_type: DatabaseType,
+	secondary_path: &Path,
+) -> OpenDbResult {
+	let db: Arc<dyn Database<DbHash>> = match &db_source {
+		DatabaseSource::ParityDb { path } => crate::parity_db::open_read_only(path, db_type)?,
+		#[cfg(feature = "rocksdb")]
+		DatabaseSource::RocksDb { path, cache_size } =>
+			crate::secondary::open(path, secondary_path, db_type, *cache_size)?.0,
+		#[cfg(feature = "rocksdb")]
+		DatabaseSource::RocksDbSecondary { path, secondary_path, cache_size } =>
+			crate::secondary::open(path, secondary_path, db_type, *cache_size)?.0,
+		DatabaseSource::Custom { db, .. } => db.clone(),
+		DatabaseSource::Auto { paritydb_path, rocksdb_path, cache_size } =>
+			match open_kvdb_rocksdb_read_only(rocksdb_path, secondary_path, db_type, *cache_size) {
+				Ok(db) => db,
+				Err(OpenDbError::NotEnabled(_)) | Err(OpenDbError::DoesNotExist) =>
+					crate::parity_db::open_read_only(paritydb_path, db_type)?,
+				Err(as_is) => return Err(as_is),
+			},
+	};
+
+	check_database_type(&*db, db_type)?;
+	Ok(db)
+}
+
+#[cfg(feature = "rocksdb")]
+fn open_kvdb_rocksdb_read_only(
+	path: &Path,
+	secondary_path: &Path,
+	db_type: DatabaseType,
+	cache_size: usize,
+) -> OpenDbResult {
+	Ok(crate::secondary::open(path, secondary_path, db_type, cache_size)?.0)
+}
+
+#[cfg(not(feature = "rocksdb"))]
+fn open_kvdb_rocksdb_read_only(
+	_path: &Path,
+	_secondary_path: &Path,
+	_db_type: DatabaseType,
+	_cache_size: usize,
+) -> OpenDbResult {
+	Err(OpenDbError::NotEnabled("with-kvdb-rocksdb"))
+}
+
 #[derive(Debug)]
 pub enum OpenDbError {
 	// constructed only when rocksdb and paritydb are disabled
@@ -311,7 +365,21 @@ fn open_kvdb_rocksdb<Block: BlockT>(
 	// and now open database assuming that it has the latest version
 	let mut db_config = kvdb_rocksdb::DatabaseConfig::with_columns(NUM_COLUMNS);
 	db_config.create_if_missing = create;
+	db_config.memory_budget = rocksdb_memory_budget(path, db_type, cache_size);
+
+	let db = kvdb_rocksdb::Database::open(&db_config, path)?;
+	// write database version only after the database is succesfully opened
+	crate::upgrade::update_version(path)?;
+	Ok(sp_database::as_database_with_estimates(db, |db, col| db.num_keys(col).ok()))
+}
 
+/// Split the RocksDB cache of `cache_size` MiB between the columns.
+#[cfg(any(feature = "rocksdb", test))]
+pub(crate) fn rocksdb_memory_budget(
+	path: &Path,
+	db_type: DatabaseType,
+	cache_size: usize,
+) -> std::collections::HashMap<u32, usize> {
 	let mut memory_budget = std::collections::HashMap::new();
 	match db_type {
 		DatabaseType::Full => {
@@ -335,12 +403,7 @@ fn open_kvdb_rocksdb<Block: BlockT>(
 			);
 		},
 	}
-	db_config.memory_budget = memory_budget;
-
-	let db = kvdb_rocksdb::Database::open(&db_config, path)?;
-	// write database version only after the database is succesfully opened
-	crate::upgrade::update_version(path)?;
-	Ok(sp_database::as_database(db))
+	memory_budget
 }
 
 #[cfg(not(any(feature = "rocksdb", test)))]
@@ -353,6 +416,26 @@ fn open_kvdb_rocksdb<Block: BlockT>(
 	Err(OpenDbError::NotEnabled("with-kvdb-rocksdb"))
 }
 
+/// Human readable name of a database column.
+pub(crate) fn column_name(column: u32) -> &'static str {
+	match column {
+		crate::columns::META => "meta",
+		crate::columns::STATE => "state",
+		crate::columns::STATE_META => "state_meta",
+		crate::columns::KEY_LOOKUP => "key_lookup",
+		crate::columns::HEADER => "header",
+		crate::columns::BODY => "body",
+		crate::columns::JUSTIFICATIONS => "justifications",
+		crate::columns::AUX => "aux",
+		crate::columns::OFFCHAIN => "offchain",
+		crate::columns::TRANSACTION => "transaction",
+		crate::columns::BODY_INDEX => "body_index",
+		crate::columns::CHANGES_INDEX => "changes_index",
+		crate::columns::STATE_SYNC => "state_sync",
+		_ => "unknown",
+	}
+}
+
 /// Check database type.
 pub fn check_database_type(
 	db: &dyn Database<DbHash>,
@@ -19,15 +19,14 @@
 //! RPC API for GRANDPA.
 #![warn(missing_docs)]
 
-use futures::{FutureExt, StreamExt};
+use futures::StreamExt;
 use log::warn;
 use std::sync::Arc;
 
 use jsonrpsee::{
 	core::{async_trait, RpcResult},
 	proc_macros::rpc,
-	types::SubscriptionResult,
-	SubscriptionSink,
+	PendingSubscriptionSink,
 };
 
 mod error;
@@ -36,7 +35,7 @@ mod notification;
 mod report;
 
 use sc_finality_grandpa::GrandpaJustificationStream;
-use sc_rpc::SubscriptionTaskExecutor;
+use sc_rpc::{spawn_subscription_task, utils::pipe_from_stream, SubscriptionTaskExecutor};
 use sp_runtime::traits::{Block as BlockT, NumberFor};
 
 use finality::{EncodedFinalityProof, RpcFinalityProofProvider};
@@ -103,19 +102,14 @@ where
 		ReportedRoundStates::from(&self.authority_set, &self.voter_state).map_err(Into::into)
 	}
 
-	fn subscribe_justifications(&self, mut sink: SubscriptionSink) -> SubscriptionResult {
+	fn subscribe_justifications(&self, pending: PendingSubscriptionSink) {
 		let stream = self.justification_stream.subscribe().map(
 			|x: sc_finality_grandpa::GrandpaJustification<Block>| {
 				JustificationNotification::from(x)
 			},
 		);
 
-		let fut = async move {
-			sink.pipe_from_stream(stream).await;
-		};
-
-		self.executor.spawn("substrate-rpc-subscription", Some("rpc"), fut.boxed());
-		Ok(())
+		spawn_subscription_task(&self.executor, pipe_from_stream(pending, stream));
 	}
 
 	async fn prove_finality(
@@ -137,10 +131,7 @@ mod tests {
 	use super::*;
 	use std::{collections::HashSet, convert::TryInto, sync::Arc};
 
-	use jsonrpsee::{
-		types::{EmptyParams, SubscriptionId},
-		RpcModule,
-	};
+	use jsonrpsee::{core::EmptyServerParams, types::SubscriptionId, RpcModule};
 	use parity_scale_codec::{Decode, Encode};
 	use sc_block_builder::{BlockBuilder, RecordProof};
 	use sc_finality_grandpa::{
@@ -281,17 +272,17 @@ mod tests {
 	#[tokio::test]
 	async fn uninitialized_rpc_handler() {
 		let (rpc, _) = setup_io_handler(EmptyVoterState);
-		let expected_response = r#"{"jsonrpc":"2.0","error":{"code":1,"message":"GRANDPA RPC endpoint not ready"},"id":0}"#.to_string();
+		let expected_response = r#"{"jsonrpc":"2.0","id":0,"error":{"code":1,"message":"GRANDPA RPC endpoint not ready"}}"#.to_string();
 		let request = r#"{"jsonrpc":"2.0","method":"grandpa_roundState","params":[],"id":0}"#;
-		let (response, _) = rpc.raw_json_request(&request).await.unwrap();
+		let (response, _) = rpc.raw_json_request(&request, 1).await.unwrap();
 
-		assert_eq!(expected_response, response.result);
+		assert_eq!(expected_response, response.get());
 	}
 
 	#[tokio::test]
 	async fn working_rpc_handler() {
 		let (rpc, _) = setup_io_handler(TestVoterState);
-		let expected_response = "{\"jsonrpc\":\"2.0\",\"result\":{\
+		let expected_response = "{\"jsonrpc\":\"2.0\",\"id\":0,\"result\":{\
 			\"setId\":1,\
 			\"best\":{\
 				\"round\":2,\"totalWeight\":100,\"thresholdWeight\":67,\
@@ -303,11 +294,11 @@ mod tests {
 				\"prevotes\":{\"currentWeight\":100,\"missing\":[]},\
 				\"precommits\":{\"currentWeight\":100,\"missing\":[]}\
 			}]\
-		},\"id\":0}".to_string();
+		}}".to_string();
 
 		let request = r#"{"jsonrpc":"2.0","method":"grandpa_roundState","params":[],"id":0}"#;
-		let (response, _) = rpc.raw_json_request(&request).await.unwrap();
-		assert_eq!(expected_response, response.result);
+		let (response, _) = rpc.raw_json_request(&request, 1).await.unwrap();
+		assert_eq!(expected_response, response.get());
 	}
 
 	#[tokio::test]
@@ -315,7 +306,7 @@ mod tests {
 		let (rpc, _) = setup_io_handler(TestVoterState);
 		// Subscribe call.
 		let _sub = rpc
-			.subscribe("grandpa_subscribeJustifications", EmptyParams::new())
+			.subscribe_unbounded("grandpa_subscribeJustifications", EmptyServerParams::new())
 			.await
 			.unwrap();
 
@@ -323,12 +314,13 @@ mod tests {
 		let (response, _) = rpc
 			.raw_json_request(
 				r#"{"jsonrpc":"2.0","method":"grandpa_unsubscribeJustifications","params":["FOO"],"id":1}"#,
+				1,
 			)
 			.await
 			.unwrap();
-		let expected = r#"{"jsonrpc":"2.0","result":false,"id":1}"#;
+		let expected = r#"{"jsonrpc":"2.0","id":1,"result":false}"#;
 
-		assert_eq!(response.result, expected);
+		assert_eq!(response.get(), expected);
 	}
 
 	fn create_justification() -> GrandpaJustification<Block> {
@@ -390,7 +382,7 @@ mod tests {
 		let (rpc, justification_sender) = setup_io_handler(TestVoterState);
 
 		let mut sub = rpc
-			.subscribe("grandpa_subscribeJustifications", EmptyParams::new())
+			.subscribe_unbounded("grandpa_subscribeJustifications", EmptyServerParams::new())
 			.await
 			.unwrap();
 
@@ -28,7 +28,10 @@ use sc_consensus::{
 	BlockImport, BlockImportParams, BoxJustificationImport, ForkChoiceStrategy, ImportResult,
 	ImportedAux,
 };
-use sc_network::config::Role;
+use sc_network::{
+	config::Role,
+	simulator::{LinkConditions, NetworkSimulator},
+};
 use sc_network_test::{
 	Block, BlockImportAdapter, FullPeerConfig, Hash, PassThroughVerifier, Peer, PeersClient,
 	PeersFullClient, TestClient, TestNetFactory,
@@ -94,6 +97,26 @@ impl GrandpaTestNet {
 
 		net
 	}
+
+	/// Create a network of authorities whose connections go through `simulator`.
+	fn new_simulated(
+		test_config: TestApi,
+		n_authority: usize,
+		simulator: &NetworkSimulator,
+	) -> Self {
+		let mut net = GrandpaTestNet { peers: Vec::with_capacity(n_authority), test_config };
+
+		for _ in 0..n_authority {
+			net.add_full_peer_with_config(FullPeerConfig {
+				notifications_protocols: vec![grandpa_protocol_name::NAME.into()],
+				is_authority: true,
+				network_simulator: Some(simulator.clone()),
+				..Default::default()
+			});
+		}
+
+		net
+	}
 }
 
 impl GrandpaTestNet {
@@ -387,6 +410,53 @@ fn finalize_3_voters_no_observers() {
 	);
 }
 
+#[test]
+fn finality_resumes_after_partition_heals() {
+	sp_tracing::try_init_simple();
+	let mut runtime = Runtime::new().unwrap();
+	let peers = &[Ed25519Keyring::Alice, Ed25519Keyring::Bob, Ed25519Keyring::Charlie];
+	let voters = make_ids(peers);
+
+	let simulator = NetworkSimulator::new(0);
+	simulator.set_default_link(LinkConditions {
+		latency: Duration::from_millis(10),
+		..Default::default()
+	});
+	let mut net = GrandpaTestNet::new_simulated(TestApi::new(voters), 3, &simulator);
+	runtime.spawn(initialize_grandpa(&mut net, peers));
+	net.peer(0).push_blocks(10, false);
+	net.block_until_sync();
+
+	let net = Arc::new(Mutex::new(net));
+	run_to_completion(&mut runtime, 10, net.clone(), peers);
+
+	// With three voters, finalizing a block takes all of them, so neither side of the partition
+	// can finalize the blocks imported in the meantime.
+	{
+		let mut net = net.lock();
+		net.partition_peers(&simulator, &[&[0], &[1, 2]]);
+		block_on(futures::future::poll_fn::<(), _>(|cx| {
+			net.poll(cx);
+			if (0..3).map(|i| net.peer(i).num_peers()).eq([0, 1, 1]) {
+				Poll::Ready(())
+			} else {
+				Poll::Pending
+			}
+		}));
+		net.peer(1).push_blocks(10, false);
+	}
+	block_until_complete(Delay::new(TEST_GOSSIP_DURATION * 4), &net, &mut runtime);
+
+	for i in 0..3 {
+		let info = net.lock().peer(i).client().info();
+		assert_eq!(info.finalized_number, 10, "Peer #{} finalized during the partition", i);
+	}
+	assert_eq!(net.lock().peer(0).client().info().best_number, 10);
+
+	simulator.heal();
+	run_to_completion(&mut runtime, 20, net.clone(), peers);
+}
+
 #[test]
 fn finalize_3_voters_1_full_observer() {
 	let mut runtime = Runtime::new().unwrap();
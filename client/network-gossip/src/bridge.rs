@@ -17,7 +17,7 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
-	state_machine::{ConsensusGossip, TopicNotification, PERIODIC_MAINTENANCE_INTERVAL},
+	state_machine::{ConsensusGossip, RateLimit, TopicNotification, PERIODIC_MAINTENANCE_INTERVAL},
 	Network, Validator,
 };
 
@@ -102,6 +102,11 @@ impl<B: BlockT> GossipEngine<B> {
 		}
 	}
 
+	/// Limit the number of messages every peer can send us. `None` removes the limit.
+	pub fn set_inbound_rate_limit(&mut self, rate_limit: Option<RateLimit>) {
+		self.state_machine.set_inbound_rate_limit(rate_limit);
+	}
+
 	pub fn report(&self, who: PeerId, reputation: ReputationChange) {
 		self.network.report_peer(who, reputation);
 	}
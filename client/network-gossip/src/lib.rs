@@ -63,7 +63,7 @@
 
 pub use self::{
 	bridge::GossipEngine,
-	state_machine::TopicNotification,
+	state_machine::{RateLimit, TopicNotification},
 	validator::{DiscardAll, MessageIntent, ValidationResult, Validator, ValidatorContext},
 };
 
@@ -21,8 +21,11 @@ use crate::{MessageIntent, Network, ValidationResult, Validator, ValidatorContex
 use ahash::AHashSet;
 use libp2p::PeerId;
 use lru::LruCache;
-use prometheus_endpoint::{register, Counter, PrometheusError, Registry, U64};
+use prometheus_endpoint::{
+	register, Counter, CounterVec, Histogram, HistogramOpts, Opts, PrometheusError, Registry, U64,
+};
 use sc_network_common::protocol::event::ObservedRole;
+use sc_utils::token_bucket::{Rate, TokenBucket};
 use sp_runtime::traits::{Block as BlockT, Hash, HashFor};
 use std::{borrow::Cow, collections::HashMap, iter, sync::Arc, time, time::Instant};
 
@@ -47,10 +50,44 @@ mod rep {
 	pub const GOSSIP_SUCCESS: Rep = Rep::new(1 << 4, "Successful gossip");
 	/// Reputation change when a peer sends us a gossip message that we already knew about.
 	pub const DUPLICATE_GOSSIP: Rep = Rep::new(-(1 << 2), "Duplicate gossip");
+	/// Reputation change when a peer sends us gossip messages faster than the rate limit.
+	pub const RATE_LIMITED: Rep = Rep::new(-(1 << 6), "Gossip rate limit exceeded");
+}
+
+/// Limit of the number of gossip messages a peer can send us, enforced with a token bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+	/// Maximum number of messages a peer can send at once.
+	pub burst: u32,
+	/// Number of messages per second a peer can send in the long run.
+	pub per_second: u32,
+}
+
+impl RateLimit {
+	fn rate(&self) -> Rate {
+		Rate { burst: self.burst, per_second: self.per_second as f64 }
+	}
 }
 
 struct PeerConsensus<H> {
 	known_messages: AHashSet<H>,
+	/// Messages the peer can still send under the rate limit. Created full on the first message
+	/// received with a rate limit.
+	rate_limit: Option<TokenBucket>,
+}
+
+impl<H> PeerConsensus<H> {
+	fn new() -> Self {
+		Self { known_messages: Default::default(), rate_limit: None }
+	}
+
+	/// Take a token for a new message. Returns `false` if the rate limit is exceeded.
+	fn try_take(&mut self, limit: &RateLimit) -> bool {
+		let (rate, now) = (limit.rate(), Instant::now());
+		self.rate_limit
+			.get_or_insert_with(|| TokenBucket::full(&rate, now))
+			.try_take(&rate, now)
+	}
 }
 
 /// Topic stream message with sender.
@@ -88,6 +125,9 @@ impl<'g, 'p, B: BlockT> ValidatorContext<B> for NetworkContext<'g, 'p, B> {
 
 	/// Send addressed message to a peer.
 	fn send_message(&mut self, who: &PeerId, message: Vec<u8>) {
+		if let Some(metrics) = &self.gossip.metrics {
+			metrics.sent_bytes.inc_by(message.len() as u64);
+		}
 		self.network.write_notification(*who, self.gossip.protocol.clone(), message);
 	}
 
@@ -104,12 +144,19 @@ fn propagate<'a, B: BlockT, I>(
 	intent: MessageIntent,
 	peers: &mut HashMap<PeerId, PeerConsensus<B::Hash>>,
 	validator: &Arc<dyn Validator<B>>,
+	metrics: Option<&Metrics>,
 )
 // (msg_hash, topic, message)
 where
 	I: Clone + IntoIterator<Item = (&'a B::Hash, &'a B::Hash, &'a Vec<u8>)>,
 {
 	let mut message_allowed = validator.message_allowed();
+	// Number of peers each message is sent to.
+	let mut fanout: HashMap<&B::Hash, u64> = messages
+		.clone()
+		.into_iter()
+		.map(|(message_hash, _, _)| (message_hash, 0))
+		.collect();
 
 	for (id, ref mut peer) in peers.iter_mut() {
 		for (message_hash, topic, message) in messages.clone() {
@@ -145,9 +192,19 @@ where
 				?message,
 				"Propagating message",
 			);
+			*fanout.entry(message_hash).or_default() += 1;
+			if let Some(metrics) = metrics {
+				metrics.sent_bytes.inc_by(message.len() as u64);
+			}
 			network.write_notification(*id, protocol.clone(), message.clone());
 		}
 	}
+
+	if let Some(metrics) = metrics {
+		for peers in fanout.values() {
+			metrics.propagation_fanout.observe(*peers as f64);
+		}
+	}
 }
 
 /// Consensus network protocol handler. Manages statements and candidate requests.
@@ -159,6 +216,7 @@ pub struct ConsensusGossip<B: BlockT> {
 	validator: Arc<dyn Validator<B>>,
 	next_broadcast: Instant,
 	metrics: Option<Metrics>,
+	rate_limit: Option<RateLimit>,
 }
 
 impl<B: BlockT> ConsensusGossip<B> {
@@ -168,7 +226,8 @@ impl<B: BlockT> ConsensusGossip<B> {
 		protocol: Cow<'static, str>,
 		metrics_registry: Option<&Registry>,
 	) -> Self {
-		let metrics = match metrics_registry.map(Metrics::register) {
+		let metrics = metrics_registry.map(|registry| Metrics::register(registry, &protocol));
+		let metrics = match metrics {
 			Some(Ok(metrics)) => Some(metrics),
 			Some(Err(e)) => {
 				tracing::debug!(target: "gossip", "Failed to register metrics: {:?}", e);
@@ -185,6 +244,16 @@ impl<B: BlockT> ConsensusGossip<B> {
 			validator,
 			next_broadcast: Instant::now() + REBROADCAST_INTERVAL,
 			metrics,
+			rate_limit: None,
+		}
+	}
+
+	/// Limit the number of messages every peer can send us. The messages exceeding the limit are
+	/// dropped, and lower the reputation of the peer.
+	pub fn set_inbound_rate_limit(&mut self, rate_limit: Option<RateLimit>) {
+		self.rate_limit = rate_limit;
+		for peer in self.peers.values_mut() {
+			peer.rate_limit = None;
 		}
 	}
 
@@ -197,7 +266,7 @@ impl<B: BlockT> ConsensusGossip<B> {
 			?role,
 			"Registering peer",
 		);
-		self.peers.insert(who, PeerConsensus { known_messages: Default::default() });
+		self.peers.insert(who, PeerConsensus::new());
 
 		let validator = self.validator.clone();
 		let mut context = NetworkContext { gossip: self, network };
@@ -260,6 +329,7 @@ impl<B: BlockT> ConsensusGossip<B> {
 			MessageIntent::PeriodicRebroadcast,
 			&mut self.peers,
 			&self.validator,
+			self.metrics.as_ref(),
 		);
 	}
 
@@ -280,6 +350,7 @@ impl<B: BlockT> ConsensusGossip<B> {
 			intent,
 			&mut self.peers,
 			&self.validator,
+			self.metrics.as_ref(),
 		);
 	}
 
@@ -341,6 +412,26 @@ impl<B: BlockT> ConsensusGossip<B> {
 		}
 
 		for message in messages {
+			if let Some(ref metrics) = self.metrics {
+				metrics.received_messages.inc();
+			}
+
+			if let (Some(limit), Some(peer)) = (&self.rate_limit, self.peers.get_mut(&who)) {
+				if !peer.try_take(limit) {
+					tracing::trace!(
+						target: "gossip",
+						%who,
+						protocol = %self.protocol,
+						"Dropped message exceeding the rate limit",
+					);
+					if let Some(ref metrics) = self.metrics {
+						metrics.rate_limited_messages.inc();
+					}
+					network.report_peer(who, rep::RATE_LIMITED);
+					continue
+				}
+			}
+
 			let message_hash = HashFor::<B>::hash(&message[..]);
 
 			if self.known_messages.contains(&message_hash) {
@@ -350,6 +441,9 @@ impl<B: BlockT> ConsensusGossip<B> {
 					protocol = %self.protocol,
 					"Ignored already known message",
 				);
+				if let Some(ref metrics) = self.metrics {
+					metrics.duplicate_messages.inc();
+				}
 				network.report_peer(who, rep::DUPLICATE_GOSSIP);
 				continue
 			}
@@ -361,6 +455,15 @@ impl<B: BlockT> ConsensusGossip<B> {
 				validator.validate(&mut context, &who, &message)
 			};
 
+			if let Some(ref metrics) = self.metrics {
+				let result = match validation {
+					ValidationResult::ProcessAndKeep(_) => "process_and_keep",
+					ValidationResult::ProcessAndDiscard(_) => "process_and_discard",
+					ValidationResult::Discard => "discard",
+				};
+				metrics.validations.with_label_values(&[result]).inc();
+			}
+
 			let (topic, keep) = match validation {
 				ValidationResult::ProcessAndKeep(topic) => (topic, true),
 				ValidationResult::ProcessAndDiscard(topic) => (topic, false),
@@ -433,6 +536,9 @@ impl<B: BlockT> ConsensusGossip<B> {
 					?entry.message,
 					"Sending topic message",
 				);
+				if let Some(ref metrics) = self.metrics {
+					metrics.sent_bytes.inc_by(entry.message.len() as u64);
+				}
 				network.write_notification(*who, self.protocol.clone(), entry.message.clone());
 			}
 		}
@@ -456,6 +562,7 @@ impl<B: BlockT> ConsensusGossip<B> {
 			intent,
 			&mut self.peers,
 			&self.validator,
+			self.metrics.as_ref(),
 		);
 	}
 
@@ -478,32 +585,86 @@ impl<B: BlockT> ConsensusGossip<B> {
 		);
 
 		peer.known_messages.insert(message_hash);
+		if let Some(ref metrics) = self.metrics {
+			metrics.sent_bytes.inc_by(message.len() as u64);
+		}
 		network.write_notification(*who, self.protocol.clone(), message);
 	}
 }
 
+/// Gossip metrics. They are labeled with the protocol name, so that the gossip engines of the
+/// different protocols can register them in the same registry.
+///
+/// They aren't broken down by topic: topics are opaque hashes, which the validators of most
+/// protocols (GRANDPA included) derive from a round or a set id. A topic label would grow without
+/// bound, and its values would mean nothing without the validator that chose them.
 struct Metrics {
 	registered_messages: Counter<U64>,
 	expired_messages: Counter<U64>,
+	received_messages: Counter<U64>,
+	duplicate_messages: Counter<U64>,
+	rate_limited_messages: Counter<U64>,
+	validations: CounterVec<U64>,
+	propagation_fanout: Histogram,
+	sent_bytes: Counter<U64>,
 }
 
 impl Metrics {
-	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+	fn register(registry: &Registry, protocol: &str) -> Result<Self, PrometheusError> {
+		let counter = |name: &str, help: &str| {
+			register(
+				Counter::with_opts(Opts::new(name, help).const_label("protocol", protocol))?,
+				registry,
+			)
+		};
+
 		Ok(Self {
-			registered_messages: register(
-				Counter::new(
-					"substrate_network_gossip_registered_messages_total",
-					"Number of registered messages by the gossip service.",
+			registered_messages: counter(
+				"substrate_network_gossip_registered_messages_total",
+				"Number of registered messages by the gossip service.",
+			)?,
+			expired_messages: counter(
+				"substrate_network_gossip_expired_messages_total",
+				"Number of expired messages by the gossip service.",
+			)?,
+			received_messages: counter(
+				"substrate_network_gossip_received_messages_total",
+				"Number of messages received from peers by the gossip service.",
+			)?,
+			duplicate_messages: counter(
+				"substrate_network_gossip_duplicate_messages_total",
+				"Number of received messages which were already known.",
+			)?,
+			rate_limited_messages: counter(
+				"substrate_network_gossip_rate_limited_messages_total",
+				"Number of received messages dropped because the peer exceeded the rate limit.",
+			)?,
+			validations: register(
+				CounterVec::new(
+					Opts::new(
+						"substrate_network_gossip_validations_total",
+						"Number of received messages by result of their validation.",
+					)
+					.const_label("protocol", protocol),
+					&["result"],
 				)?,
 				registry,
 			)?,
-			expired_messages: register(
-				Counter::new(
-					"substrate_network_gossip_expired_messages_total",
-					"Number of expired messages by the gossip service.",
+			propagation_fanout: register(
+				Histogram::with_opts(
+					HistogramOpts::new(
+						"substrate_network_gossip_propagation_fanout",
+						"Number of peers a message is sent to when it is propagated.",
+					)
+					.const_label("protocol", protocol)
+					.buckets(vec![0.0, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0]),
 				)?,
 				registry,
 			)?,
+			sent_bytes: counter(
+				"substrate_network_gossip_sent_bytes_total",
+				"Number of bytes of the messages sent to peers by the gossip service.",
+			)?,
 		})
 	}
 }
@@ -820,4 +981,23 @@ mod tests {
 			to_forward,
 		);
 	}
+
+	#[test]
+	fn on_incoming_drops_messages_exceeding_rate_limit() {
+		let mut network = NoOpNetwork::default();
+		let remote = PeerId::random();
+
+		let mut consensus = ConsensusGossip::<Block>::new(Arc::new(AllowAll), "/foo".into(), None);
+		consensus.set_inbound_rate_limit(Some(RateLimit { burst: 2, per_second: 0 }));
+		consensus.new_peer(&mut network, remote, ObservedRole::Full);
+
+		let to_forward = consensus.on_incoming(
+			&mut network,
+			remote,
+			vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]],
+		);
+
+		assert_eq!(to_forward.len(), 2);
+		assert_eq!(network.inner.lock().unwrap().peer_reports, vec![(remote, rep::RATE_LIMITED)]);
+	}
 }
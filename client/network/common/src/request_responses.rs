@@ -70,6 +70,31 @@ pub struct ProtocolConfig {
 	/// advertise support for this protocol, but any incoming request will lead to an error being
 	/// sent back.
 	pub inbound_queue: Option<mpsc::Sender<IncomingRequest>>,
+
+	/// Quota of incoming requests a single peer can send on this protocol.
+	///
+	/// The requests exceeding the quota are refused, and lower the reputation of the peer.
+	/// If this is `None`, the number of requests a peer can send is only bounded by the size of
+	/// the `inbound_queue`.
+	pub inbound_quota: Option<RequestQuota>,
+
+	/// Whether the responses on the protocol `name` are compressed with zstd.
+	///
+	/// The responses on the `fallback_names` are never compressed, so that the peers which
+	/// don't support compression can negotiate one of these instead.
+	pub compress_responses: bool,
+}
+
+/// Token-bucket quota of incoming requests per peer.
+///
+/// Every peer starts with `burst` tokens, and every request consumes one of them. The tokens are
+/// refilled at a rate of `per_second`, up to `burst`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestQuota {
+	/// Maximum number of requests a peer can send at once.
+	pub burst: u32,
+	/// Number of requests per second a peer can send in the long run.
+	pub per_second: u32,
 }
 
 /// A single request received by a peer on a request-response protocol.
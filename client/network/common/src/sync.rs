@@ -370,6 +370,9 @@ pub trait ChainSync<Block: BlockT>: Send {
 	/// Return some key metrics.
 	fn metrics(&self) -> Metrics;
 
+	/// Block download throughput of the peers we downloaded blocks from.
+	fn peer_throughput(&self) -> Vec<(PeerId, metrics::PeerThroughput)>;
+
 	/// Create implementation-specific block request.
 	fn create_opaque_block_request(&self, request: &BlockRequest<Block>) -> OpaqueBlockRequest;
 
@@ -16,6 +16,8 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use std::time::Duration;
+
 #[derive(Debug)]
 pub struct Metrics {
 	pub pending_requests: u32,
@@ -23,3 +25,16 @@ pub struct Metrics {
 	pub importing_requests: u32,
 	pub failed_requests: u32,
 }
+
+/// Measured block download throughput of a peer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerThroughput {
+	/// Average number of blocks downloaded per second.
+	pub blocks_per_second: f64,
+	/// Average number of bytes of block data downloaded per second.
+	pub bytes_per_second: f64,
+	/// Average time between a block request and its response.
+	pub latency: Duration,
+	/// Number of blocks requested at once from the peer.
+	pub request_size: u32,
+}
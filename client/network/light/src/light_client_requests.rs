@@ -20,6 +20,8 @@
 
 /// For incoming light client requests.
 pub mod handler;
+/// For outgoing light client requests.
+pub mod sender;
 
 use sc_network_common::{config::ProtocolId, request_responses::ProtocolConfig};
 
@@ -54,5 +56,7 @@ pub fn generate_protocol_config<Hash: AsRef<[u8]>>(
 		max_response_size: 16 * 1024 * 1024,
 		request_timeout: Duration::from_secs(15),
 		inbound_queue: None,
+		inbound_quota: None,
+		compress_responses: false,
 	}
 }
@@ -0,0 +1,277 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Helper for outgoing light client requests.
+//!
+//! Send the remote read and call requests of a light client to the full nodes we are syncing
+//! from with [`LightClientRequestSender`](sender::LightClientRequestSender), and check the proofs
+//! of their responses against the state roots of the local headers.
+
+use crate::schema::v1::light::{self as schema, request::Request, response::Response};
+use codec::{self, Decode, Encode};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p::PeerId;
+use log::debug;
+use parking_lot::Mutex;
+use prost::Message;
+use sc_client_api::{
+	light::{check_read_proof, RemoteBackend, RemoteCallRequest, RemoteReadRequest},
+	BlockBackend, CallExecutor, ExecutorProvider, StorageProof,
+};
+use sc_network_common::{
+	config::ProtocolId,
+	protocol::event::Event,
+	request_responses::IfDisconnected,
+	service::{NetworkEventStream, NetworkPeers, NetworkRequest},
+};
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_runtime::traits::{Block, Header};
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	marker::PhantomData,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+};
+
+const LOG_TARGET: &str = "light-client-request-sender";
+
+/// Maximum number of peers a request is sent to before giving up.
+const MAX_ATTEMPTS: usize = 3;
+
+mod rep {
+	use sc_peerset::ReputationChange as Rep;
+
+	/// Reputation change for peers which send us a response we can't decode.
+	pub const BAD_RESPONSE: Rep = Rep::new(-(1 << 12), "Invalid light client response");
+
+	/// Reputation change for peers which send us a proof that fails verification.
+	pub const BAD_PROOF: Rep = Rep::new(-(1 << 29), "Light client proof verification failed");
+}
+
+/// Sender of light client requests to the full nodes we are syncing from.
+pub struct LightClientRequestSender<B, Client> {
+	/// Blockchain client, whose executor checks the execution proofs.
+	client: Arc<Client>,
+	network: Arc<dyn NetworkRequest + Send + Sync>,
+	/// Handle to report the peers answering with invalid responses.
+	network_peers: Arc<dyn NetworkPeers + Send + Sync>,
+	protocol_name: Cow<'static, str>,
+	/// Peers we are syncing from.
+	peers: Arc<Mutex<Vec<PeerId>>>,
+	/// Index of the peer the next request is sent to first, to spread the requests.
+	next_peer: AtomicUsize,
+	_block: PhantomData<B>,
+}
+
+impl<B, Client> LightClientRequestSender<B, Client>
+where
+	B: Block,
+	Client: BlockBackend<B> + ExecutorProvider<B> + Send + Sync + 'static,
+{
+	/// Create a new [`LightClientRequestSender`].
+	///
+	/// The returned future tracks the peers the requests are sent to and must be spawned.
+	///
+	/// Fails if the genesis block, which the protocol name is derived from, is not in the database.
+	pub fn new<N>(
+		protocol_id: &ProtocolId,
+		fork_id: Option<&str>,
+		client: Arc<Client>,
+		network: Arc<N>,
+	) -> ClientResult<(Self, impl Future<Output = ()>)>
+	where
+		N: NetworkRequest + NetworkPeers + NetworkEventStream + Send + Sync + 'static,
+	{
+		let genesis_hash = client
+			.block_hash(0u32.into())?
+			.ok_or_else(|| ClientError::UnknownBlock("Genesis block".into()))?;
+		let protocol_config = super::generate_protocol_config(protocol_id, genesis_hash, fork_id);
+		let peers = Arc::new(Mutex::new(Vec::new()));
+		let track_peers = track_peers(network.event_stream(LOG_TARGET), peers.clone());
+
+		let sender = Self {
+			client,
+			network: network.clone(),
+			network_peers: network,
+			protocol_name: protocol_config.name,
+			peers,
+			next_peer: AtomicUsize::new(0),
+			_block: PhantomData::default(),
+		};
+		Ok((sender, track_peers))
+	}
+
+	/// Peers to send the next request to, in order.
+	fn select_peers(&self) -> Vec<PeerId> {
+		let peers = self.peers.lock();
+		if peers.is_empty() {
+			return Vec::new()
+		}
+		let first = self.next_peer.fetch_add(1, Ordering::Relaxed) % peers.len();
+		peers
+			.iter()
+			.cycle()
+			.skip(first)
+			.take(MAX_ATTEMPTS.min(peers.len()))
+			.copied()
+			.collect()
+	}
+
+	/// Send `request` to the selected peers in turn, until one of them answers with a response
+	/// passing `check`.
+	fn send<T, F>(&self, request: Request, check: F) -> BoxFuture<'static, ClientResult<T>>
+	where
+		T: Send + 'static,
+		F: Fn(Response) -> Result<T, CheckResponseError> + Send + 'static,
+	{
+		let network = self.network.clone();
+		let network_peers = self.network_peers.clone();
+		let protocol_name = self.protocol_name.clone();
+		let peers = self.select_peers();
+		let payload = schema::Request { request: Some(request) }.encode_to_vec();
+
+		async move {
+			if peers.is_empty() {
+				debug!(target: LOG_TARGET, "No peer to send light client request to.");
+			}
+			for peer in peers {
+				let response = match network
+					.request(
+						peer,
+						protocol_name.clone(),
+						payload.clone(),
+						IfDisconnected::ImmediateError,
+					)
+					.await
+				{
+					Ok(response) => response,
+					Err(e) => {
+						debug!(target: LOG_TARGET, "Light client request to {} failed: {}", peer, e);
+						continue
+					},
+				};
+				let checked = schema::Response::decode(&response[..])
+					.map_err(CheckResponseError::from)
+					.and_then(|response| {
+						response.response.ok_or(CheckResponseError::UnexpectedResponse)
+					})
+					.and_then(&check);
+				match checked {
+					Ok(result) => return Ok(result),
+					Err(e) => {
+						debug!(
+							target: LOG_TARGET,
+							"Invalid light client response from {}: {}",
+							peer,
+							e,
+						);
+						let reputation = match e {
+							CheckResponseError::BadProof(_) => rep::BAD_PROOF,
+							_ => rep::BAD_RESPONSE,
+						};
+						network_peers.report_peer(peer, reputation);
+					},
+				}
+			}
+			Err(ClientError::RemoteFetchFailed)
+		}
+		.boxed()
+	}
+}
+
+impl<B, Client> RemoteBackend<B> for LightClientRequestSender<B, Client>
+where
+	B: Block,
+	Client: BlockBackend<B> + ExecutorProvider<B> + Send + Sync + 'static,
+{
+	fn remote_read(
+		&self,
+		request: RemoteReadRequest<B::Header>,
+	) -> BoxFuture<'static, ClientResult<HashMap<Vec<u8>, Option<Vec<u8>>>>> {
+		let RemoteReadRequest { header, keys } = request;
+		let request = Request::RemoteReadRequest(schema::RemoteReadRequest {
+			block: header.hash().encode(),
+			keys: keys.clone(),
+		});
+
+		self.send(request, move |response| match response {
+			Response::RemoteReadResponse(response) => {
+				let proof = StorageProof::decode(&mut response.proof.as_ref())?;
+				Ok(check_read_proof(&header, &keys, proof)?)
+			},
+			_ => Err(CheckResponseError::UnexpectedResponse),
+		})
+	}
+
+	fn remote_call(
+		&self,
+		request: RemoteCallRequest<B::Header>,
+	) -> BoxFuture<'static, ClientResult<Vec<u8>>> {
+		let RemoteCallRequest { header, method, call_data } = request;
+		let request = Request::RemoteCallRequest(schema::RemoteCallRequest {
+			block: header.hash().encode(),
+			method: method.clone(),
+			data: call_data.clone(),
+		});
+
+		let client = self.client.clone();
+		self.send(request, move |response| match response {
+			Response::RemoteCallResponse(response) => {
+				let proof = StorageProof::decode(&mut response.proof.as_ref())?;
+				Ok(client.executor().check_execution_proof(
+					*header.state_root(),
+					&method,
+					&call_data,
+					proof,
+				)?)
+			},
+			_ => Err(CheckResponseError::UnexpectedResponse),
+		})
+	}
+}
+
+/// Keep `peers` up to date with the peers we are syncing from.
+async fn track_peers(
+	mut events: impl Stream<Item = Event> + Unpin,
+	peers: Arc<Mutex<Vec<PeerId>>>,
+) {
+	while let Some(event) = events.next().await {
+		match event {
+			Event::SyncConnected { remote } => peers.lock().push(remote),
+			Event::SyncDisconnected { remote } => peers.lock().retain(|peer| *peer != remote),
+			_ => {},
+		}
+	}
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CheckResponseError {
+	#[error("Failed to decode response: {0}.")]
+	DecodeProto(#[from] prost::DecodeError),
+	#[error("unexpected response")]
+	UnexpectedResponse,
+	/// Encoding or decoding of some data failed.
+	#[error("codec error: {0}")]
+	Codec(#[from] codec::Error),
+	/// The proof doesn't match the requested block.
+	#[error("bad proof: {0}")]
+	BadProof(#[from] ClientError),
+}
@@ -17,7 +17,7 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
-	bitswap::Bitswap,
+	bitswap::{Bitswap, BitswapError, BitswapEvent},
 	discovery::{DiscoveryBehaviour, DiscoveryConfig, DiscoveryOut},
 	peer_info,
 	protocol::{message::Roles, CustomMessageOutcome, NotificationsSink, Protocol},
@@ -55,7 +55,7 @@ use sp_runtime::{
 };
 use std::{
 	borrow::Cow,
-	collections::{HashSet, VecDeque},
+	collections::{HashMap, HashSet, VecDeque},
 	iter,
 	task::{Context, Poll},
 	time::Duration,
@@ -84,7 +84,7 @@ where
 	peer_info: peer_info::PeerInfoBehaviour,
 	/// Discovers nodes of the network.
 	discovery: DiscoveryBehaviour,
-	/// Bitswap server for blockchain data.
+	/// Bitswap server and client for blockchain data.
 	bitswap: Toggle<Bitswap<B, Client>>,
 	/// Generic request-response protocols.
 	request_responses: request_responses::RequestResponsesBehaviour,
@@ -267,6 +267,11 @@ where
 		self.discovery.known_peers()
 	}
 
+	/// Returns the addresses of the nodes in the Kademlia k-buckets.
+	pub fn kademlia_addresses(&mut self) -> HashMap<PeerId, Vec<Multiaddr>> {
+		self.discovery.kademlia_addresses()
+	}
+
 	/// Adds a hard-coded address for the given peer, that never expires.
 	pub fn add_known_address(&mut self, peer_id: PeerId, addr: Multiaddr) {
 		self.discovery.add_known_address(peer_id, addr)
@@ -337,6 +342,29 @@ where
 	pub fn put_value(&mut self, key: record::Key, value: Vec<u8>) {
 		self.discovery.put_value(key, value);
 	}
+
+	/// Fetch the data of `cid` over Bitswap. The data, or an error, is later sent through
+	/// `pending_response`.
+	pub fn bitswap_want(
+		&mut self,
+		cid: cid::Cid,
+		pending_response: oneshot::Sender<Result<Vec<u8>, BitswapError>>,
+	) {
+		match self.bitswap.as_mut() {
+			Some(bitswap) => bitswap.want(cid, pending_response),
+			None => {
+				let _ = pending_response.send(Err(BitswapError::Disabled));
+			},
+		}
+	}
+
+	/// Notify the Bitswap behaviour that a block has been finalized, so that the data it indexes
+	/// is announced in the DHT.
+	pub fn on_block_finalized(&mut self, hash: B::Hash) {
+		if let Some(bitswap) = self.bitswap.as_mut() {
+			bitswap.on_block_finalized(hash);
+		}
+	}
 }
 
 fn reported_roles_to_observed_role(roles: Roles) -> ObservedRole {
@@ -349,7 +377,7 @@ fn reported_roles_to_observed_role(roles: Roles) -> ObservedRole {
 	}
 }
 
-impl<B, Client> NetworkBehaviourEventProcess<void::Void> for Behaviour<B, Client>
+impl<B, Client> NetworkBehaviourEventProcess<BitswapEvent> for Behaviour<B, Client>
 where
 	B: BlockT,
 	Client: HeaderBackend<B>
@@ -360,8 +388,12 @@ where
 		+ Sync
 		+ 'static,
 {
-	fn inject_event(&mut self, event: void::Void) {
-		void::unreachable(event)
+	fn inject_event(&mut self, event: BitswapEvent) {
+		match event {
+			BitswapEvent::FindProviders(key) => self.discovery.get_providers(key),
+			BitswapEvent::StartProviding(key) => self.discovery.start_providing(key),
+			BitswapEvent::StopProviding(key) => self.discovery.stop_providing(&key),
+		}
 	}
 }
 
@@ -581,6 +613,18 @@ where
 				self.events
 					.push_back(BehaviourOut::Dht(DhtEvent::ValuePutFailed(key), duration));
 			},
+			DiscoveryOut::ProvidersFound(key, providers, duration) => {
+				debug!(
+					target: "sub-libp2p",
+					"Found {} providers of {:?} in {:?}",
+					providers.len(),
+					key,
+					duration,
+				);
+				if let Some(bitswap) = self.bitswap.as_mut() {
+					bitswap.add_providers(&key, providers);
+				}
+			},
 			DiscoveryOut::RandomKademliaStarted(protocols) =>
 				for protocol in protocols {
 					self.events.push_back(BehaviourOut::RandomKademliaStarted(protocol));
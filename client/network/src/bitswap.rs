@@ -14,43 +14,66 @@
 // You should have received a copy of the GNU General Public License
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
-//! Bitswap server for substrate.
+//! Bitswap server and client for substrate.
 //!
 //! Allows querying transactions by hash over standard bitswap protocol
 //! Only supports bitswap 1.2.0.
 //! CID is expected to reference 256-bit Blake2b transaction hash.
-
-use crate::schema::bitswap::{
-	message::{wantlist::WantType, Block as MessageBlock, BlockPresence, BlockPresenceType},
-	Message as BitswapMessage,
+//!
+//! The node can also fetch the data of any CID whose multihash it can verify from the peers it is
+//! connected to and from the providers of the CID found in the DHT, see [`Bitswap::want`]. The
+//! data indexed in the finalized blocks is announced as provided in the DHT, for as long as the
+//! node keeps it and within a bounded number of keys.
+//!
+//! The DHT is the Kademlia DHT of the chain, not the public IPFS DHT: the provider records are
+//! only found by the nodes of the chain, and IPFS nodes have to connect to the node directly.
+
+use crate::{
+	discovery::MAX_PROVIDED_KEYS,
+	schema::bitswap::{
+		message::{
+			wantlist::{Entry as WantlistEntry, WantType},
+			Block as MessageBlock, BlockPresence, BlockPresenceType, Wantlist,
+		},
+		Message as BitswapMessage,
+	},
+};
+use cid::{
+	multihash::{Code, MultihashDigest},
+	Cid, Version,
 };
-use cid::Version;
 use core::pin::Pin;
 use futures::{
+	channel::oneshot,
 	io::{AsyncRead, AsyncWrite},
-	Future,
+	Future, FutureExt,
 };
+use futures_timer::Delay;
 use libp2p::{
 	core::{
-		connection::ConnectionId, upgrade, InboundUpgrade, Multiaddr, OutboundUpgrade, PeerId,
-		UpgradeInfo,
+		connection::ConnectionId, upgrade, ConnectedPoint, InboundUpgrade, Multiaddr,
+		OutboundUpgrade, PeerId, UpgradeInfo,
 	},
+	kad::record,
 	swarm::{
-		NetworkBehaviour, NetworkBehaviourAction, NotifyHandler, OneShotHandler, PollParameters,
+		dial_opts::{DialOpts, PeerCondition},
+		IntoConnectionHandler, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
+		OneShotHandler, PollParameters,
 	},
 };
 use log::{debug, error, trace};
 use prost::Message;
 use sc_client_api::BlockBackend;
-use sp_runtime::traits::Block as BlockT;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
 use std::{
-	collections::VecDeque,
-	io,
+	collections::{HashMap, HashSet, VecDeque},
+	io, iter,
 	marker::PhantomData,
 	sync::Arc,
 	task::{Context, Poll},
+	time::{Duration, Instant},
 };
-use unsigned_varint::encode as varint_encode;
+use unsigned_varint::{decode as varint_decode, encode as varint_encode};
 
 const LOG_TARGET: &str = "bitswap";
 
@@ -64,6 +87,12 @@ const MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
 const MAX_RESPONSE_QUEUE: usize = 20;
 // Max number of blocks per wantlist
 const MAX_WANTED_BLOCKS: usize = 16;
+// Max number of CIDs we are fetching from peers at the same time.
+const MAX_PENDING_WANTS: usize = 256;
+// Time after which we give up fetching the data of a CID.
+const WANT_TIMEOUT: Duration = Duration::from_secs(60);
+// Multicodec of the raw binary data, used in the CIDs of indexed transactions.
+const RAW_CODEC: u64 = 0x55;
 
 const PROTOCOL_NAME: &[u8] = b"/ipfs/bitswap/1.2.0";
 
@@ -176,44 +205,241 @@ impl Prefix {
 		res.extend_from_slice(mh_len);
 		res
 	}
+
+	/// Decode the prefix from its encoded bytes.
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, cid::Error> {
+		let (version, rest) =
+			varint_decode::u64(bytes).map_err(|_| cid::Error::VarIntDecodeError)?;
+		let (codec, rest) = varint_decode::u64(rest).map_err(|_| cid::Error::VarIntDecodeError)?;
+		let (mh_type, rest) =
+			varint_decode::u64(rest).map_err(|_| cid::Error::VarIntDecodeError)?;
+		let (mh_len, _) = varint_decode::u64(rest).map_err(|_| cid::Error::VarIntDecodeError)?;
+		Ok(Self {
+			version: Version::try_from(version)?,
+			codec,
+			mh_type,
+			mh_len: u8::try_from(mh_len).map_err(|_| cid::Error::ParsingError)?,
+		})
+	}
+
+	/// Compute the CID of `data`, checking that its multihash is supported.
+	pub fn to_cid(&self, data: &[u8]) -> Result<Cid, cid::Error> {
+		let code = Code::try_from(self.mh_type).map_err(|_| cid::Error::ParsingError)?;
+		let hash = code.digest(data);
+		// Truncated hashes are not supported.
+		if hash.size() != self.mh_len {
+			return Err(cid::Error::ParsingError)
+		}
+		Cid::new(self.version, self.codec, hash)
+	}
+}
+
+/// Event generated by the [`Bitswap`] behaviour.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BitswapEvent {
+	/// The providers of the given key should be searched in the DHT, and passed to
+	/// [`Bitswap::add_providers`].
+	FindProviders(record::Key),
+	/// The local node should be announced in the DHT as a provider of the given key.
+	StartProviding(record::Key),
+	/// The local node no longer provides the given key.
+	StopProviding(record::Key),
+}
+
+/// CID whose data we are fetching from peers.
+struct Want {
+	/// Senders of the data to the requesters.
+	pending_responses: Vec<oneshot::Sender<Result<Vec<u8>, BitswapError>>>,
+	/// When we started fetching the data.
+	started: Instant,
 }
 
 /// Network behaviour that handles sending and receiving IPFS blocks.
-pub struct Bitswap<B, Client> {
+pub struct Bitswap<B: BlockT, Client> {
 	client: Arc<Client>,
 	ready_blocks: VecDeque<(PeerId, BitswapMessage)>,
+	/// Wantlists and cancels to send to peers.
+	outbound_messages: VecDeque<(PeerId, BitswapMessage)>,
+	/// Providers to connect to in order to send them our wantlist.
+	pending_dials: VecDeque<PeerId>,
+	pending_events: VecDeque<BitswapEvent>,
+	/// Peers we are connected to.
+	connected: HashSet<PeerId>,
+	/// CIDs we are fetching from peers.
+	wants: HashMap<Cid, Want>,
+	/// Next time to check for timed out wants.
+	next_timeout_check: Delay,
+	/// Finalized blocks whose indexed data we announce as provided in the DHT, oldest first.
+	provided: VecDeque<(B::Hash, Vec<record::Key>)>,
+	/// Number of keys in `provided`.
+	provided_keys: usize,
+	/// Maximum number of keys in `provided`, the oldest blocks are rotated out past it.
+	max_provided_keys: usize,
 	_block: PhantomData<B>,
 }
 
-impl<B, Client> Bitswap<B, Client> {
-	/// Create a new instance of the bitswap protocol handler.
-	pub fn new(client: Arc<Client>) -> Self {
-		Self { client, ready_blocks: Default::default(), _block: PhantomData::default() }
-	}
-}
-
-impl<B, Client> NetworkBehaviour for Bitswap<B, Client>
+impl<B, Client> Bitswap<B, Client>
 where
 	B: BlockT,
 	Client: BlockBackend<B> + Send + Sync + 'static,
 {
-	type ConnectionHandler = OneShotHandler<BitswapConfig, BitswapMessage, HandlerEvent>;
-	type OutEvent = void::Void;
+	/// Create a new instance of the bitswap protocol handler.
+	pub fn new(client: Arc<Client>) -> Self {
+		Self {
+			client,
+			ready_blocks: Default::default(),
+			outbound_messages: Default::default(),
+			pending_dials: Default::default(),
+			pending_events: Default::default(),
+			connected: Default::default(),
+			wants: Default::default(),
+			next_timeout_check: Delay::new(WANT_TIMEOUT),
+			provided: Default::default(),
+			provided_keys: 0,
+			max_provided_keys: MAX_PROVIDED_KEYS,
+			_block: PhantomData::default(),
+		}
+	}
 
-	fn new_handler(&mut self) -> Self::ConnectionHandler {
-		Default::default()
+	/// Fetch the data of `cid`.
+	///
+	/// The data is looked up in the local indexed transactions first, then requested from all the
+	/// peers we are connected to and from the providers of `cid` found in the DHT. The data
+	/// received from peers is checked against the multihash of `cid`, so only the CIDs whose
+	/// multihash is supported can be fetched.
+	pub fn want(
+		&mut self,
+		cid: Cid,
+		pending_response: oneshot::Sender<Result<Vec<u8>, BitswapError>>,
+	) {
+		if Code::try_from(cid.hash().code()).is_err() {
+			let _ = pending_response.send(Err(BitswapError::UnsupportedCid(cid)));
+			return
+		}
+
+		match self.local_data(&cid) {
+			Ok(Some(data)) => {
+				let _ = pending_response.send(Ok(data));
+				return
+			},
+			Ok(None) => {},
+			Err(e) => {
+				let _ = pending_response.send(Err(e));
+				return
+			},
+		}
+
+		if let Some(want) = self.wants.get_mut(&cid) {
+			want.pending_responses.push(pending_response);
+			return
+		}
+
+		if self.wants.len() >= MAX_PENDING_WANTS {
+			let _ = pending_response.send(Err(BitswapError::TooManyWants));
+			return
+		}
+
+		trace!(target: LOG_TARGET, "Fetching CID {}", cid);
+		let message = wantlist_message(iter::once(&cid), false);
+		for peer in &self.connected {
+			self.outbound_messages.push_back((*peer, message.clone()));
+		}
+		self.pending_events.push_back(BitswapEvent::FindProviders(dht_key(&cid)));
+		self.wants.insert(
+			cid,
+			Want { pending_responses: vec![pending_response], started: Instant::now() },
+		);
 	}
 
-	fn addresses_of_peer(&mut self, _peer: &PeerId) -> Vec<Multiaddr> {
-		Vec::new()
+	/// Connect to the providers of `key` found in the DHT, to send them our wantlist.
+	pub fn add_providers(&mut self, key: &record::Key, providers: HashSet<PeerId>) {
+		if !self.wants.keys().any(|cid| dht_key(cid) == *key) {
+			return
+		}
+
+		for provider in providers {
+			if !self.connected.contains(&provider) {
+				trace!(target: LOG_TARGET, "Connecting to provider {} of {:?}", provider, key);
+				self.pending_dials.push_back(provider);
+			}
+		}
 	}
 
-	fn inject_event(&mut self, peer: PeerId, _connection: ConnectionId, message: HandlerEvent) {
-		let request = match message {
-			HandlerEvent::ResponseSent => return,
-			HandlerEvent::Request(msg) => msg,
+	/// Announce the data indexed in the given finalized block as provided by the local node, and
+	/// stop announcing the data of the blocks that have been pruned since.
+	///
+	/// Only the blocks finalized while the node is running are announced. Past
+	/// `MAX_PROVIDED_KEYS` keys, the data of the oldest blocks stops being announced.
+	pub fn on_block_finalized(&mut self, hash: B::Hash) {
+		while let Some((oldest, _)) = self.provided.front() {
+			match self.client.block_indexed_body(&BlockId::Hash(*oldest)) {
+				Ok(Some(_)) => break,
+				Ok(None) => {
+					trace!(target: LOG_TARGET, "Indexed data of {} pruned", oldest);
+					self.stop_providing_oldest();
+				},
+				Err(e) => {
+					error!(target: LOG_TARGET, "Error retrieving indexed body {}: {}", oldest, e);
+					break
+				},
+			}
+		}
+
+		let indexed_body = match self.client.block_indexed_body(&BlockId::Hash(hash)) {
+			Ok(Some(indexed_body)) if !indexed_body.is_empty() => indexed_body,
+			Ok(_) => return,
+			Err(e) => {
+				error!(target: LOG_TARGET, "Error retrieving indexed body {}: {}", hash, e);
+				return
+			},
 		};
-		trace!(target: LOG_TARGET, "Received request: {:?} from {}", request, peer);
+
+		let mut keys: Vec<_> = indexed_body
+			.iter()
+			.map(|data| dht_key(&Cid::new_v1(RAW_CODEC, Code::Blake2b256.digest(data))))
+			.collect();
+		if keys.len() > self.max_provided_keys {
+			debug!(
+				target: LOG_TARGET,
+				"Only announcing {} of the {} indexed transactions of {}",
+				self.max_provided_keys,
+				keys.len(),
+				hash,
+			);
+			keys.truncate(self.max_provided_keys);
+		}
+		while self.provided_keys + keys.len() > self.max_provided_keys {
+			self.stop_providing_oldest();
+		}
+
+		for key in &keys {
+			self.pending_events.push_back(BitswapEvent::StartProviding(key.clone()));
+		}
+		self.provided_keys += keys.len();
+		self.provided.push_back((hash, keys));
+	}
+
+	/// Stop announcing the indexed data of the oldest block in `provided`.
+	fn stop_providing_oldest(&mut self) {
+		if let Some((hash, keys)) = self.provided.pop_front() {
+			trace!(target: LOG_TARGET, "Stopped announcing the indexed data of {}", hash);
+			self.provided_keys -= keys.len();
+			self.pending_events.extend(keys.into_iter().map(BitswapEvent::StopProviding));
+		}
+	}
+
+	/// Look `cid` up in the local indexed transactions.
+	fn local_data(&self, cid: &Cid) -> Result<Option<Vec<u8>>, BitswapError> {
+		if cid.hash().code() != u64::from(Code::Blake2b256) || cid.hash().size() != 32 {
+			return Ok(None)
+		}
+		let mut hash = B::Hash::default();
+		hash.as_mut().copy_from_slice(&cid.hash().digest()[0..32]);
+		Ok(self.client.indexed_transaction(&hash)?)
+	}
+
+	/// Answer the wantlist of a peer.
+	fn on_wantlist(&mut self, peer: PeerId, wantlist: Wantlist) {
 		if self.ready_blocks.len() > MAX_RESPONSE_QUEUE {
 			debug!(target: LOG_TARGET, "Ignored request: queue is full");
 			return
@@ -225,18 +451,14 @@ where
 			block_presences: Default::default(),
 			pending_bytes: 0,
 		};
-		let wantlist = match request.wantlist {
-			Some(wantlist) => wantlist,
-			None => {
-				debug!(target: LOG_TARGET, "Unexpected bitswap message from {}", peer);
-				return
-			},
-		};
 		if wantlist.entries.len() > MAX_WANTED_BLOCKS {
 			trace!(target: LOG_TARGET, "Ignored request: too many entries");
 			return
 		}
 		for entry in wantlist.entries {
+			if entry.cancel {
+				continue
+			}
 			let cid = match cid::Cid::read_bytes(entry.block.as_slice()) {
 				Ok(cid) => cid,
 				Err(e) => {
@@ -295,18 +517,174 @@ where
 		self.ready_blocks.push_back((peer, response));
 	}
 
+	/// Hand a block received from a peer over to the requesters of its CID.
+	fn on_block(&mut self, peer: PeerId, block: MessageBlock) {
+		let cid = match Prefix::from_bytes(&block.prefix).and_then(|p| p.to_cid(&block.data)) {
+			Ok(cid) => cid,
+			Err(e) => {
+				debug!(target: LOG_TARGET, "Bad block prefix from {}: {}", peer, e);
+				return
+			},
+		};
+		let want = match self.wants.remove(&cid) {
+			Some(want) => want,
+			None => {
+				trace!(target: LOG_TARGET, "Ignored unwanted block {} from {}", cid, peer);
+				return
+			},
+		};
+
+		trace!(target: LOG_TARGET, "Received CID {} from {}", cid, peer);
+		for pending_response in want.pending_responses {
+			let _ = pending_response.send(Ok(block.data.clone()));
+		}
+		let message = wantlist_message(iter::once(&cid), true);
+		for peer in &self.connected {
+			self.outbound_messages.push_back((*peer, message.clone()));
+		}
+	}
+
+	/// Fail the wants that timed out, and forget the ones nobody waits for anymore.
+	fn expire_wants(&mut self) {
+		let now = Instant::now();
+		self.wants.retain(|cid, want| {
+			if now.saturating_duration_since(want.started) >= WANT_TIMEOUT {
+				debug!(target: LOG_TARGET, "Fetching CID {} timed out", cid);
+				for pending_response in want.pending_responses.drain(..) {
+					let _ = pending_response.send(Err(BitswapError::Timeout));
+				}
+				return false
+			}
+			want.pending_responses
+				.retain(|pending_response| !pending_response.is_canceled());
+			!want.pending_responses.is_empty()
+		});
+	}
+}
+
+/// Key of the provider records of `cid` in the DHT.
+fn dht_key(cid: &Cid) -> record::Key {
+	record::Key::new(&cid.hash().to_bytes())
+}
+
+/// Build a message asking for the blocks of `cids`, or cancelling our previous request if `cancel`
+/// is `true`.
+fn wantlist_message<'a>(cids: impl Iterator<Item = &'a Cid>, cancel: bool) -> BitswapMessage {
+	BitswapMessage {
+		wantlist: Some(Wantlist {
+			entries: cids
+				.map(|cid| WantlistEntry {
+					block: cid.to_bytes(),
+					priority: 1,
+					cancel,
+					want_type: WantType::Block as i32,
+					send_dont_have: false,
+				})
+				.collect(),
+			full: false,
+		}),
+		blocks: Default::default(),
+		payload: Default::default(),
+		block_presences: Default::default(),
+		pending_bytes: 0,
+	}
+}
+
+impl<B, Client> NetworkBehaviour for Bitswap<B, Client>
+where
+	B: BlockT,
+	Client: BlockBackend<B> + Send + Sync + 'static,
+{
+	type ConnectionHandler = OneShotHandler<BitswapConfig, BitswapMessage, HandlerEvent>;
+	type OutEvent = BitswapEvent;
+
+	fn new_handler(&mut self) -> Self::ConnectionHandler {
+		Default::default()
+	}
+
+	fn addresses_of_peer(&mut self, _peer: &PeerId) -> Vec<Multiaddr> {
+		Vec::new()
+	}
+
+	fn inject_connection_established(
+		&mut self,
+		peer_id: &PeerId,
+		_: &ConnectionId,
+		_: &ConnectedPoint,
+		_: Option<&Vec<Multiaddr>>,
+		other_established: usize,
+	) {
+		if other_established != 0 {
+			return
+		}
+		self.connected.insert(*peer_id);
+		if !self.wants.is_empty() {
+			let message = wantlist_message(self.wants.keys(), false);
+			self.outbound_messages.push_back((*peer_id, message));
+		}
+	}
+
+	fn inject_connection_closed(
+		&mut self,
+		peer_id: &PeerId,
+		_: &ConnectionId,
+		_: &ConnectedPoint,
+		_: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
+		remaining_established: usize,
+	) {
+		if remaining_established == 0 {
+			self.connected.remove(peer_id);
+		}
+	}
+
+	fn inject_event(&mut self, peer: PeerId, _connection: ConnectionId, message: HandlerEvent) {
+		let message = match message {
+			HandlerEvent::ResponseSent => return,
+			HandlerEvent::Request(msg) => msg,
+		};
+		trace!(target: LOG_TARGET, "Received message: {:?} from {}", message, peer);
+		if message.wantlist.is_none() && message.payload.is_empty() {
+			debug!(target: LOG_TARGET, "Unexpected bitswap message from {}", peer);
+			return
+		}
+		if let Some(wantlist) = message.wantlist {
+			self.on_wantlist(peer, wantlist);
+		}
+		for block in message.payload {
+			self.on_block(peer, block);
+		}
+	}
+
 	fn poll(
 		&mut self,
-		_ctx: &mut Context,
+		cx: &mut Context,
 		_: &mut impl PollParameters,
 	) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
-		if let Some((peer_id, message)) = self.ready_blocks.pop_front() {
+		if self.next_timeout_check.poll_unpin(cx).is_ready() {
+			self.expire_wants();
+			self.next_timeout_check = Delay::new(Duration::from_secs(1));
+			// Register the new timer with the waker.
+			let _ = self.next_timeout_check.poll_unpin(cx);
+		}
+
+		if let Some(event) = self.pending_events.pop_front() {
+			return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
+		}
+		if let Some((peer_id, message)) =
+			self.ready_blocks.pop_front().or_else(|| self.outbound_messages.pop_front())
+		{
 			return Poll::Ready(NetworkBehaviourAction::NotifyHandler {
 				peer_id,
 				handler: NotifyHandler::Any,
 				event: message,
 			})
 		}
+		if let Some(peer_id) = self.pending_dials.pop_front() {
+			return Poll::Ready(NetworkBehaviourAction::Dial {
+				opts: DialOpts::peer_id(peer_id).condition(PeerCondition::Disconnected).build(),
+				handler: self.new_handler(),
+			})
+		}
 		Poll::Pending
 	}
 }
@@ -337,4 +715,249 @@ pub enum BitswapError {
 	/// Error sending response.
 	#[error("Failed to send response.")]
 	SendResponse,
+
+	/// The multihash of the CID isn't supported, so its data can't be checked.
+	#[error("Unsupported CID {0}.")]
+	UnsupportedCid(Cid),
+
+	/// Too many CIDs are being fetched at the same time.
+	#[error("Too many CIDs are being fetched.")]
+	TooManyWants,
+
+	/// No peer sent the data of the CID in time.
+	#[error("Timed out fetching the CID.")]
+	Timeout,
+
+	/// Bitswap isn't enabled.
+	#[error("Bitswap is disabled.")]
+	Disabled,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assert_matches::assert_matches;
+	use cid::multihash::Multihash;
+	use futures::executor::block_on;
+	use sc_block_builder::BlockBuilderProvider;
+	use sp_consensus::BlockOrigin;
+	use substrate_test_runtime::{Block, Extrinsic};
+	use substrate_test_runtime_client::{prelude::*, TestClient};
+
+	fn new_bitswap() -> (Arc<TestClient>, Bitswap<Block, TestClient>) {
+		let client = Arc::new(TestClientBuilder::with_tx_storage(u32::MAX).build());
+		(client.clone(), Bitswap::new(client))
+	}
+
+	/// Import a block indexing each of `data`, and return its hash.
+	fn import_indexed_block(
+		client: &mut Arc<TestClient>,
+		data: &[&[u8]],
+	) -> <Block as BlockT>::Hash {
+		let mut builder = client.new_block(Default::default()).unwrap();
+		for data in data {
+			builder.push(Extrinsic::Store(data.to_vec())).unwrap();
+		}
+		let block = builder.build().unwrap().block;
+		let hash = block.hash();
+		block_on(client.import(BlockOrigin::Own, block)).unwrap();
+		hash
+	}
+
+	fn raw_cid(data: &[u8]) -> Cid {
+		Cid::new_v1(RAW_CODEC, Code::Blake2b256.digest(data))
+	}
+
+	fn prefix(cid: &Cid) -> Vec<u8> {
+		Prefix {
+			version: cid.version(),
+			codec: cid.codec(),
+			mh_type: cid.hash().code(),
+			mh_len: cid.hash().size(),
+		}
+		.to_bytes()
+	}
+
+	#[test]
+	fn want_returns_local_data_without_asking_peers() {
+		let (mut client, mut bitswap) = new_bitswap();
+		import_indexed_block(&mut client, &[b"indexed data"]);
+		bitswap.connected.insert(PeerId::random());
+
+		let (tx, mut rx) = oneshot::channel();
+		bitswap.want(raw_cid(b"indexed data"), tx);
+
+		assert_matches!(rx.try_recv(), Ok(Some(Ok(data))) if data == b"indexed data");
+		assert!(bitswap.wants.is_empty());
+		assert!(bitswap.outbound_messages.is_empty());
+		assert!(bitswap.pending_events.is_empty());
+	}
+
+	#[test]
+	fn want_asks_connected_peers_and_dht_providers() {
+		let (_, mut bitswap) = new_bitswap();
+		let peer = PeerId::random();
+		bitswap.connected.insert(peer);
+		let cid = raw_cid(b"remote data");
+
+		let (tx, _rx) = oneshot::channel();
+		bitswap.want(cid, tx);
+		let (tx, _other_rx) = oneshot::channel();
+		bitswap.want(cid, tx);
+
+		assert_eq!(bitswap.wants[&cid].pending_responses.len(), 2);
+		assert_eq!(bitswap.outbound_messages.len(), 1);
+		let (to, message) = bitswap.outbound_messages.pop_front().unwrap();
+		assert_eq!(to, peer);
+		assert_eq!(message.wantlist.unwrap().entries[0].block, cid.to_bytes());
+		assert_eq!(
+			bitswap.pending_events.drain(..).collect::<Vec<_>>(),
+			vec![BitswapEvent::FindProviders(dht_key(&cid))],
+		);
+	}
+
+	#[test]
+	fn want_rejects_unsupported_cids_and_too_many_wants() {
+		let (_, mut bitswap) = new_bitswap();
+
+		let unsupported = Cid::new_v1(RAW_CODEC, Multihash::wrap(0x9999, &[0; 32]).unwrap());
+		let (tx, mut rx) = oneshot::channel();
+		bitswap.want(unsupported, tx);
+		assert_matches!(rx.try_recv(), Ok(Some(Err(BitswapError::UnsupportedCid(_)))));
+
+		let mut receivers = Vec::new();
+		for i in 0..MAX_PENDING_WANTS as u32 {
+			let (tx, rx) = oneshot::channel();
+			bitswap.want(raw_cid(&i.to_le_bytes()), tx);
+			receivers.push(rx);
+		}
+		let (tx, mut rx) = oneshot::channel();
+		bitswap.want(raw_cid(b"one too many"), tx);
+		assert_matches!(rx.try_recv(), Ok(Some(Err(BitswapError::TooManyWants))));
+		assert_eq!(bitswap.wants.len(), MAX_PENDING_WANTS);
+	}
+
+	#[test]
+	fn on_block_checks_the_data_and_answers_the_requesters() {
+		let (_, mut bitswap) = new_bitswap();
+		let peer = PeerId::random();
+		bitswap.connected.insert(peer);
+		let cid = raw_cid(b"remote data");
+		let (tx, mut rx) = oneshot::channel();
+		bitswap.want(cid, tx);
+		bitswap.outbound_messages.clear();
+
+		// Data that doesn't match the CID is ignored.
+		bitswap.on_block(peer, MessageBlock { prefix: prefix(&cid), data: b"forged".to_vec() });
+		assert_matches!(rx.try_recv(), Ok(None));
+		assert!(bitswap.wants.contains_key(&cid));
+
+		bitswap
+			.on_block(peer, MessageBlock { prefix: prefix(&cid), data: b"remote data".to_vec() });
+		assert_matches!(rx.try_recv(), Ok(Some(Ok(data))) if data == b"remote data");
+		assert!(bitswap.wants.is_empty());
+
+		// The want is cancelled on the connected peers.
+		let (to, message) = bitswap.outbound_messages.pop_front().unwrap();
+		assert_eq!(to, peer);
+		assert!(message.wantlist.unwrap().entries[0].cancel);
+	}
+
+	#[test]
+	fn add_providers_dials_the_providers_of_wanted_cids() {
+		let (_, mut bitswap) = new_bitswap();
+		let connected = PeerId::random();
+		bitswap.connected.insert(connected);
+		let cid = raw_cid(b"remote data");
+		let (tx, _rx) = oneshot::channel();
+		bitswap.want(cid, tx);
+
+		let provider = PeerId::random();
+		bitswap.add_providers(&dht_key(&raw_cid(b"other data")), HashSet::from([provider]));
+		assert!(bitswap.pending_dials.is_empty());
+
+		bitswap.add_providers(&dht_key(&cid), HashSet::from([provider, connected]));
+		assert_eq!(bitswap.pending_dials, VecDeque::from([provider]));
+	}
+
+	#[test]
+	fn expire_wants_fails_timed_out_wants_and_drops_abandoned_ones() {
+		let (_, mut bitswap) = new_bitswap();
+		let timed_out = raw_cid(b"timed out");
+		let (tx, mut timed_out_rx) = oneshot::channel();
+		bitswap.want(timed_out, tx);
+		bitswap.wants.get_mut(&timed_out).unwrap().started = Instant::now() - WANT_TIMEOUT;
+		bitswap.want(raw_cid(b"abandoned"), oneshot::channel().0);
+		let pending = raw_cid(b"pending");
+		let (tx, _pending_rx) = oneshot::channel();
+		bitswap.want(pending, tx);
+
+		bitswap.expire_wants();
+
+		assert_matches!(timed_out_rx.try_recv(), Ok(Some(Err(BitswapError::Timeout))));
+		assert_eq!(bitswap.wants.keys().collect::<Vec<_>>(), vec![&pending]);
+	}
+
+	#[test]
+	fn on_block_finalized_rotates_out_the_oldest_keys() {
+		let (mut client, mut bitswap) = new_bitswap();
+		bitswap.max_provided_keys = 3;
+		let first = import_indexed_block(&mut client, &[b"a", b"b"]);
+		let empty = import_indexed_block(&mut client, &[]);
+		let second = import_indexed_block(&mut client, &[b"c", b"d"]);
+		client.finalize_block(BlockId::Hash(second), None).unwrap();
+		let key = |data: &[u8]| dht_key(&raw_cid(data));
+
+		bitswap.on_block_finalized(first);
+		bitswap.on_block_finalized(empty);
+		assert_eq!(
+			bitswap.pending_events.drain(..).collect::<Vec<_>>(),
+			vec![BitswapEvent::StartProviding(key(b"a")), BitswapEvent::StartProviding(key(b"b"))],
+		);
+
+		// The keys of both blocks don't fit, so the ones of the oldest stop being announced.
+		bitswap.on_block_finalized(second);
+		assert_eq!(
+			bitswap.pending_events.drain(..).collect::<Vec<_>>(),
+			vec![
+				BitswapEvent::StopProviding(key(b"a")),
+				BitswapEvent::StopProviding(key(b"b")),
+				BitswapEvent::StartProviding(key(b"c")),
+				BitswapEvent::StartProviding(key(b"d")),
+			],
+		);
+		assert_eq!(bitswap.provided_keys, 2);
+	}
+
+	#[test]
+	fn prefix_roundtrip() {
+		let cid = Cid::new_v1(RAW_CODEC, Code::Blake2b256.digest(b"indexed data"));
+		let prefix = Prefix {
+			version: cid.version(),
+			codec: cid.codec(),
+			mh_type: cid.hash().code(),
+			mh_len: cid.hash().size(),
+		};
+
+		let decoded = Prefix::from_bytes(&prefix.to_bytes()).unwrap();
+		assert_eq!(decoded.version, Version::V1);
+		assert_eq!(decoded.codec, RAW_CODEC);
+		assert_eq!(decoded.mh_type, u64::from(Code::Blake2b256));
+		assert_eq!(decoded.mh_len, 32);
+		assert_eq!(decoded.to_cid(b"indexed data").unwrap(), cid);
+		assert_ne!(decoded.to_cid(b"other data").unwrap(), cid);
+	}
+
+	#[test]
+	fn prefix_rejects_truncated_hashes_and_unknown_codes() {
+		let truncated =
+			Prefix { version: Version::V1, codec: RAW_CODEC, mh_type: 0xb220, mh_len: 16 };
+		assert!(truncated.to_cid(b"data").is_err());
+
+		let unknown =
+			Prefix { version: Version::V1, codec: RAW_CODEC, mh_type: 0x9999, mh_len: 32 };
+		assert!(unknown.to_cid(b"data").is_err());
+
+		assert!(Prefix::from_bytes(&[0x80]).is_err());
+	}
 }
@@ -238,6 +238,8 @@ pub enum SyncMode {
 	},
 	/// Warp sync - verify authority set transitions and the latest state.
 	Warp,
+	/// Download and verify the headers only. The state is read from full nodes on demand.
+	Light,
 }
 
 impl SyncMode {
@@ -250,6 +252,11 @@ impl SyncMode {
 	pub fn is_fast(&self) -> bool {
 		matches!(self, Self::Fast { .. })
 	}
+
+	/// Returns if `self` is [`Self::Light`].
+	pub fn is_light(&self) -> bool {
+		matches!(self, Self::Light)
+	}
 }
 
 impl Default for SyncMode {
@@ -304,7 +311,12 @@ pub struct NetworkConfiguration {
 	/// Require iterative Kademlia DHT queries to use disjoint paths for increased resiliency in
 	/// the presence of potentially adversarial nodes.
 	pub kademlia_disjoint_query_paths: bool,
-	/// Enable serving block data over IPFS bitswap.
+	/// Enable serving block data over IPFS bitswap, fetching missing data from other peers with
+	/// [`NetworkService::bitswap_get`](crate::NetworkService::bitswap_get), and announcing the
+	/// indexed data of finalized blocks as provided in the DHT.
+	///
+	/// The provider records live in the DHT of the chain, not in the public IPFS DHT, so only the
+	/// nodes of the chain and the IPFS peers connected to the node directly can find them.
 	pub ipfs_server: bool,
 
 	/// Size of Yamux receive window of all substreams. `None` for the default (256kiB).
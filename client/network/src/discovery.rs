@@ -26,6 +26,9 @@
 //! - Bootstrap nodes. These are hard-coded node identities and addresses passed in the constructor
 //! of the `DiscoveryBehaviour`. You can also call `add_known_address` later to add an entry.
 //!
+//! - Nodes known from a previous run of the node, passed to the constructor of the
+//! `DiscoveryBehaviour` and inserted in the Kademlia k-buckets.
+//!
 //! - mDNS. Discovers nodes on the local network by broadcasting UDP packets.
 //!
 //! - Kademlia random walk. Once connected, we perform random Kademlia `FIND_NODE` requests on the
@@ -59,10 +62,10 @@ use libp2p::{
 		handler::KademliaHandlerProto,
 		record::{
 			self,
-			store::{MemoryStore, RecordStore},
+			store::{MemoryStore, MemoryStoreConfig, RecordStore},
 		},
-		GetClosestPeersError, Kademlia, KademliaBucketInserts, KademliaConfig, KademliaEvent,
-		QueryId, QueryResult, Quorum, Record,
+		GetClosestPeersError, GetProvidersError, GetProvidersOk, Kademlia, KademliaBucketInserts,
+		KademliaConfig, KademliaEvent, QueryId, QueryResult, Quorum, Record,
 	},
 	mdns::{Mdns, MdnsConfig, MdnsEvent},
 	multiaddr::Protocol,
@@ -88,6 +91,12 @@ use std::{
 /// a given address.
 const MAX_KNOWN_EXTERNAL_ADDRESSES: usize = 32;
 
+/// Maximum number of keys the local node can be a provider of in each DHT.
+///
+/// Every piece of indexed data announced over Bitswap is a separate key, so this is well above the
+/// libp2p default. Bitswap stops announcing its oldest keys to stay below it.
+pub(crate) const MAX_PROVIDED_KEYS: usize = 65536;
+
 /// `DiscoveryBehaviour` configuration.
 ///
 /// Note: In order to discover nodes or load and store values via Kademlia one has to add at least
@@ -95,6 +104,7 @@ const MAX_KNOWN_EXTERNAL_ADDRESSES: usize = 32;
 pub struct DiscoveryConfig {
 	local_peer_id: PeerId,
 	permanent_addresses: Vec<(PeerId, Multiaddr)>,
+	saved_addresses: Vec<(PeerId, Vec<Multiaddr>)>,
 	dht_random_walk: bool,
 	allow_private_ipv4: bool,
 	allow_non_globals_in_dht: bool,
@@ -110,6 +120,7 @@ impl DiscoveryConfig {
 		Self {
 			local_peer_id: local_public_key.to_peer_id(),
 			permanent_addresses: Vec::new(),
+			saved_addresses: Vec::new(),
 			dht_random_walk: true,
 			allow_private_ipv4: true,
 			allow_non_globals_in_dht: false,
@@ -135,6 +146,16 @@ impl DiscoveryConfig {
 		self
 	}
 
+	/// Set the addresses of nodes known from a previous run. Unlike permanent addresses, they
+	/// are only inserted in the k-buckets, and expire like the discovered ones.
+	pub fn with_saved_addresses<I>(&mut self, saved_addresses: I) -> &mut Self
+	where
+		I: IntoIterator<Item = (PeerId, Vec<Multiaddr>)>,
+	{
+		self.saved_addresses.extend(saved_addresses);
+		self
+	}
+
 	/// Whether the discovery behaviour should periodically perform a random
 	/// walk on the DHT to discover peers.
 	pub fn with_dht_random_walk(&mut self, value: bool) -> &mut Self {
@@ -184,6 +205,7 @@ impl DiscoveryConfig {
 		let Self {
 			local_peer_id,
 			permanent_addresses,
+			saved_addresses,
 			dht_random_walk,
 			allow_private_ipv4,
 			allow_non_globals_in_dht,
@@ -206,13 +228,25 @@ impl DiscoveryConfig {
 				config.set_kbucket_inserts(KademliaBucketInserts::Manual);
 				config.disjoint_query_paths(kademlia_disjoint_query_paths);
 
-				let store = MemoryStore::new(local_peer_id);
+				let store = MemoryStore::with_config(
+					local_peer_id,
+					MemoryStoreConfig {
+						max_provided_keys: MAX_PROVIDED_KEYS,
+						..Default::default()
+					},
+				);
 				let mut kad = Kademlia::with_config(local_peer_id, store, config);
 
 				for (peer_id, addr) in &permanent_addresses {
 					kad.add_address(peer_id, addr.clone());
 				}
 
+				for (peer_id, addrs) in &saved_addresses {
+					for addr in addrs {
+						kad.add_address(peer_id, addr.clone());
+					}
+				}
+
 				(protocol_id, kad)
 			})
 			.collect();
@@ -227,7 +261,10 @@ impl DiscoveryConfig {
 				None
 			},
 			duration_to_next_kad: Duration::from_secs(1),
-			pending_events: VecDeque::new(),
+			pending_events: saved_addresses
+				.into_iter()
+				.map(|(peer_id, _)| DiscoveryOut::Discovered(peer_id))
+				.collect(),
 			local_peer_id,
 			num_connections: 0,
 			allow_private_ipv4,
@@ -296,6 +333,24 @@ impl DiscoveryBehaviour {
 		peers
 	}
 
+	/// Returns the addresses of the nodes in the k-buckets of all the Kademlia instances.
+	pub fn kademlia_addresses(&mut self) -> HashMap<PeerId, Vec<Multiaddr>> {
+		let mut addresses = HashMap::<_, Vec<_>>::new();
+		for k in self.kademlias.values_mut() {
+			for b in k.kbuckets() {
+				for e in b.iter() {
+					let peer_addresses = addresses.entry(*e.node.key.preimage()).or_default();
+					for addr in e.node.value.iter() {
+						if !peer_addresses.contains(addr) {
+							peer_addresses.push(addr.clone());
+						}
+					}
+				}
+			}
+		}
+		addresses
+	}
+
 	/// Adds a hard-coded address for the given peer, that never expires.
 	///
 	/// This adds an entry to the parameter that was passed to `new`.
@@ -376,6 +431,35 @@ impl DiscoveryBehaviour {
 		}
 	}
 
+	/// Start announcing the local node as a provider of `key` in the DHT.
+	///
+	/// The provider record is published in the Kademlia DHT of each protocol of the chain
+	/// (`/<protocol_id>/kad`), not in the public IPFS DHT, and is republished periodically until
+	/// [`Self::stop_providing`] is called.
+	pub fn start_providing(&mut self, key: record::Key) {
+		for k in self.kademlias.values_mut() {
+			if let Err(e) = k.start_providing(key.clone()) {
+				warn!(target: "sub-libp2p", "Libp2p => Failed to start providing {:?}: {:?}", key, e);
+			}
+		}
+	}
+
+	/// Stop announcing the local node as a provider of `key`.
+	pub fn stop_providing(&mut self, key: &record::Key) {
+		for k in self.kademlias.values_mut() {
+			k.stop_providing(key);
+		}
+	}
+
+	/// Start searching the providers of `key` in the DHT.
+	///
+	/// A corresponding `ProvidersFound` event will later be generated.
+	pub fn get_providers(&mut self, key: record::Key) {
+		for k in self.kademlias.values_mut() {
+			k.get_providers(key.clone());
+		}
+	}
+
 	/// Returns the number of nodes in each Kademlia kbucket for each Kademlia instance.
 	///
 	/// Identifies Kademlia instances by their [`ProtocolId`] and kbuckets by the base 2 logarithm
@@ -492,6 +576,11 @@ pub enum DiscoveryOut {
 	/// Returning the corresponding key as well as the request duration.
 	ValuePutFailed(record::Key, Duration),
 
+	/// The search for the providers of a key has completed.
+	///
+	/// Returning the key, the providers found, which may be none, and the request duration.
+	ProvidersFound(record::Key, HashSet<PeerId>, Duration),
+
 	/// Started a random Kademlia query for each DHT identified by the given `ProtocolId`s.
 	///
 	/// Only happens if [`DiscoveryConfig::with_dht_random_walk`] has been configured to `true`.
@@ -883,6 +972,45 @@ impl NetworkBehaviour for DiscoveryBehaviour {
 								e.key(), e,
 							),
 						},
+						KademliaEvent::OutboundQueryCompleted {
+							result: QueryResult::GetProviders(res),
+							stats,
+							..
+						} => {
+							let (key, providers) = match res {
+								Ok(GetProvidersOk { key, providers, .. }) => (key, providers),
+								Err(GetProvidersError::Timeout { key, providers, .. }) => {
+									debug!(
+										target: "sub-libp2p",
+										"Libp2p => Search for providers of {:?} timed out",
+										key,
+									);
+									(key, providers)
+								},
+							};
+							let ev = DiscoveryOut::ProvidersFound(
+								key,
+								providers,
+								stats.duration().unwrap_or_default(),
+							);
+							return Poll::Ready(NetworkBehaviourAction::GenerateEvent(ev))
+						},
+						KademliaEvent::OutboundQueryCompleted {
+							result:
+								QueryResult::StartProviding(res) | QueryResult::RepublishProvider(res),
+							..
+						} => match res {
+							Ok(ok) => trace!(
+								target: "sub-libp2p",
+								"Libp2p => Provider record published: {:?}",
+								ok.key,
+							),
+							Err(e) => debug!(
+								target: "sub-libp2p",
+								"Libp2p => Publishing provider record {:?} failed with: {:?}",
+								e.key(), e,
+							),
+						},
 						// We never start any other type of query.
 						KademliaEvent::OutboundQueryCompleted { result: e, .. } => {
 							warn!(target: "sub-libp2p", "Libp2p => Unhandled Kademlia event: {:?}", e)
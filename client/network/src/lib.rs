@@ -128,7 +128,8 @@
 //! - **`/<protocol-id>/sync/2`** is a request-response protocol (see below) that lets one perform
 //! requests for information about blocks. Each request is the encoding of a `BlockRequest` and
 //! each response is the encoding of a `BlockResponse`, as defined in the `api.v1.proto` file in
-//! this source tree.
+//! this source tree. Version 3 of this protocol, named after the genesis hash of the chain, is
+//! identical except that responses are compressed with zstd.
 //! - **`/<protocol-id>/light/2`** is a request-response protocol (see below) that lets one perform
 //! light-client-related requests for information about the state. Each request is the encoding of
 //! a `light::Request` and each response is the encoding of a `light::Response`, as defined in the
@@ -247,6 +248,7 @@
 mod behaviour;
 mod discovery;
 mod peer_info;
+mod peer_store;
 mod protocol;
 mod request_responses;
 mod schema;
@@ -258,6 +260,8 @@ pub mod bitswap;
 pub mod config;
 pub mod error;
 pub mod network_state;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod simulator;
 pub mod transactions;
 
 #[doc(inline)]
@@ -281,7 +285,7 @@ pub use service::{
 	NotificationSenderReady, OutboundFailure, PublicKey,
 };
 
-pub use sc_peerset::ReputationChange;
+pub use sc_peerset::{ConnectionState, PeerScore, ReputationChange, SetId, SetMembership};
 
 /// The maximum allowed number of established connections per peer.
 ///
@@ -0,0 +1,283 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Persistence of the known nodes across restarts.
+//!
+//! The reputations of the nodes known to the peerset manager and the addresses of the nodes in
+//! the Kademlia k-buckets are periodically written to a JSON file in the network configuration
+//! directory, along with when each node was last seen. When the node starts again, the
+//! reputations are decayed for the time it was offline, and the nodes that were not seen for
+//! [`FORGET_AFTER`] are dropped.
+
+use crate::utils::interval;
+use futures::{Stream, StreamExt};
+use libp2p::{Multiaddr, PeerId};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashMap,
+	fs, io,
+	path::{Path, PathBuf},
+	pin::Pin,
+	task::{Context, Poll},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the file in the network configuration directory.
+const FILE_NAME: &str = "peers.json";
+
+/// Interval at which the known nodes are written.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Nodes that were not seen for this long are not restored.
+const FORGET_AFTER: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Content of the file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredPeers {
+	/// When the file was written, in seconds since the UNIX epoch.
+	saved_at: u64,
+	peers: Vec<StoredPeer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredPeer {
+	peer_id: String,
+	reputation: i32,
+	/// When the node was last known to the peerset manager or the k-buckets, in seconds since
+	/// the UNIX epoch.
+	last_seen: u64,
+	addresses: Vec<String>,
+}
+
+/// Nodes restored from the file.
+#[derive(Debug, Default)]
+pub(crate) struct RestoredPeers {
+	/// Decayed non-zero reputations.
+	pub reputations: Vec<(PeerId, i32)>,
+	/// Addresses of the nodes that were in the k-buckets.
+	pub addresses: Vec<(PeerId, Vec<Multiaddr>)>,
+}
+
+/// Writes the known nodes to the network configuration directory.
+pub(crate) struct PeerStore {
+	path: PathBuf,
+	/// When each node written last time was last seen.
+	last_seen: HashMap<PeerId, u64>,
+	/// Fires when the known nodes must be written.
+	next_save: Pin<Box<dyn Stream<Item = ()> + Send>>,
+}
+
+impl PeerStore {
+	/// Restore the nodes written to `dir` by a previous run.
+	///
+	/// A missing or invalid file is not an error: the node then starts with no known nodes.
+	pub(crate) fn open(dir: &Path) -> (Self, RestoredPeers) {
+		Self::open_at(dir, unix_time())
+	}
+
+	fn open_at(dir: &Path, now: u64) -> (Self, RestoredPeers) {
+		let path = dir.join(FILE_NAME);
+		let stored = match fs::read(&path) {
+			Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+				warn!(target: "sub-libp2p", "Ignoring invalid peer store {:?}: {}", path, e);
+				StoredPeers::default()
+			}),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => StoredPeers::default(),
+			Err(e) => {
+				warn!(target: "sub-libp2p", "Failed to read peer store {:?}: {}", path, e);
+				StoredPeers::default()
+			},
+		};
+
+		let offline = Duration::from_secs(now.saturating_sub(stored.saved_at));
+		let mut last_seen = HashMap::new();
+		let mut restored = RestoredPeers::default();
+		for peer in stored.peers {
+			if now.saturating_sub(peer.last_seen) > FORGET_AFTER.as_secs() {
+				continue
+			}
+			let peer_id = match peer.peer_id.parse::<PeerId>() {
+				Ok(peer_id) => peer_id,
+				Err(_) => continue,
+			};
+			let reputation = sc_peerset::decay_reputation(peer.reputation, offline);
+			if reputation != 0 {
+				restored.reputations.push((peer_id, reputation));
+			}
+			let addresses: Vec<Multiaddr> =
+				peer.addresses.iter().filter_map(|addr| addr.parse().ok()).collect();
+			if !addresses.is_empty() {
+				restored.addresses.push((peer_id, addresses));
+			}
+			last_seen.insert(peer_id, peer.last_seen);
+		}
+		debug!(
+			target: "sub-libp2p",
+			"Restored {} reputations and the addresses of {} nodes from {:?}",
+			restored.reputations.len(),
+			restored.addresses.len(),
+			path,
+		);
+
+		(PeerStore { path, last_seen, next_save: Box::pin(interval(SAVE_INTERVAL)) }, restored)
+	}
+
+	/// Returns `Poll::Ready` when the known nodes must be written.
+	pub(crate) fn poll_next_save(&mut self, cx: &mut Context) -> Poll<()> {
+		let mut ready = false;
+		// Poll until pending, so that the task is woken up for the next save.
+		while let Poll::Ready(Some(())) = self.next_save.poll_next_unpin(cx) {
+			ready = true;
+		}
+		if ready {
+			Poll::Ready(())
+		} else {
+			Poll::Pending
+		}
+	}
+
+	/// Write the reputations of the peerset manager and the addresses of the k-buckets. The nodes
+	/// in the k-buckets are seen now, the others keep the time they were last seen.
+	pub(crate) fn save(
+		&mut self,
+		reputations: Vec<(PeerId, i32)>,
+		addresses: HashMap<PeerId, Vec<Multiaddr>>,
+	) -> io::Result<()> {
+		self.save_at(reputations, addresses, unix_time())
+	}
+
+	fn save_at(
+		&mut self,
+		reputations: Vec<(PeerId, i32)>,
+		mut addresses: HashMap<PeerId, Vec<Multiaddr>>,
+		now: u64,
+	) -> io::Result<()> {
+		let mut peers = HashMap::<_, (i32, Vec<Multiaddr>)>::new();
+		for (peer_id, reputation) in reputations {
+			peers.entry(peer_id).or_default().0 = reputation;
+		}
+		for (peer_id, addrs) in addresses.drain() {
+			peers.entry(peer_id).or_default().1 = addrs;
+		}
+
+		let mut last_seen = HashMap::with_capacity(peers.len());
+		let stored = StoredPeers {
+			saved_at: now,
+			peers: peers
+				.into_iter()
+				.map(|(peer_id, (reputation, addresses))| {
+					let seen = if addresses.is_empty() {
+						self.last_seen.get(&peer_id).copied().unwrap_or(now)
+					} else {
+						now
+					};
+					last_seen.insert(peer_id, seen);
+					StoredPeer {
+						peer_id: peer_id.to_base58(),
+						reputation,
+						last_seen: seen,
+						addresses: addresses.iter().map(|addr| addr.to_string()).collect(),
+					}
+				})
+				.collect(),
+		};
+		self.last_seen = last_seen;
+
+		// Write to a temporary file first, so that the file is never left half-written.
+		let tmp_path = self.path.with_extension("json.tmp");
+		fs::write(&tmp_path, serde_json::to_vec(&stored)?)?;
+		fs::rename(&tmp_path, &self.path)
+	}
+}
+
+fn unix_time() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn restores_decayed_reputations_and_addresses() {
+		let dir = tempfile::tempdir().unwrap();
+		let banned = PeerId::random();
+		let known = PeerId::random();
+		let address: Multiaddr = "/ip4/1.2.3.4/tcp/30333".parse().unwrap();
+
+		let (mut store, restored) = PeerStore::open_at(dir.path(), 1_000);
+		assert!(restored.reputations.is_empty() && restored.addresses.is_empty());
+		store
+			.save_at(
+				vec![(banned, i32::MIN), (known, 100)],
+				vec![(known, vec![address.clone()])].into_iter().collect(),
+				1_000,
+			)
+			.unwrap();
+
+		let (_, restored) = PeerStore::open_at(dir.path(), 1_001);
+		let mut reputations = restored.reputations;
+		reputations.sort_by_key(|(_, reputation)| *reputation);
+		assert_eq!(
+			reputations,
+			vec![
+				(banned, sc_peerset::decay_reputation(i32::MIN, Duration::from_secs(1))),
+				(known, 98)
+			],
+		);
+		assert_eq!(restored.addresses, vec![(known, vec![address.clone()])]);
+
+		// The reputations are forgotten after a long time offline, and the addresses of the
+		// nodes not seen for `FORGET_AFTER`.
+		let (_, restored) = PeerStore::open_at(dir.path(), 1_000 + 3_600);
+		assert!(restored.reputations.is_empty());
+		assert_eq!(restored.addresses, vec![(known, vec![address])]);
+		let (_, restored) = PeerStore::open_at(dir.path(), 1_001 + FORGET_AFTER.as_secs());
+		assert!(restored.reputations.is_empty() && restored.addresses.is_empty());
+	}
+
+	#[test]
+	fn keeps_last_seen_of_nodes_out_of_the_k_buckets() {
+		let dir = tempfile::tempdir().unwrap();
+		let peer_id = PeerId::random();
+		let address: Multiaddr = "/ip4/1.2.3.4/tcp/30333".parse().unwrap();
+
+		let (mut store, _) = PeerStore::open_at(dir.path(), 1_000);
+		store
+			.save_at(Vec::new(), vec![(peer_id, vec![address])].into_iter().collect(), 1_000)
+			.unwrap();
+		store.save_at(vec![(peer_id, -100)], HashMap::new(), 2_000).unwrap();
+
+		let (_, restored) = PeerStore::open_at(dir.path(), 2_000);
+		assert_eq!(restored.reputations, vec![(peer_id, -100)]);
+		let (_, restored) = PeerStore::open_at(dir.path(), 1_001 + FORGET_AFTER.as_secs());
+		assert!(restored.reputations.is_empty());
+	}
+
+	#[test]
+	fn ignores_invalid_file() {
+		let dir = tempfile::tempdir().unwrap();
+		fs::write(dir.path().join(FILE_NAME), b"not json").unwrap();
+
+		let (_, restored) = PeerStore::open_at(dir.path(), 1_000);
+		assert!(restored.reputations.is_empty() && restored.addresses.is_empty());
+	}
+}
@@ -39,7 +39,10 @@ use message::{
 	Message,
 };
 use notifications::{Notifications, NotificationsOut};
-use prometheus_endpoint::{register, Gauge, GaugeVec, Opts, PrometheusError, Registry, U64};
+use prometheus_endpoint::{
+	exponential_buckets, register, Gauge, GaugeVec, Histogram, HistogramOpts, Opts,
+	PrometheusError, Registry, U64,
+};
 use sc_client_api::{BlockBackend, HeaderBackend, ProofProvider};
 use sc_consensus::import_queue::{BlockImportError, BlockImportStatus, IncomingBlock, Origin};
 use sc_network_common::{
@@ -49,6 +52,7 @@ use sc_network_common::{
 		message::{
 			BlockAnnounce, BlockAttributes, BlockData, BlockRequest, BlockResponse, BlockState,
 		},
+		metrics::PeerThroughput,
 		warp::{EncodedProof, WarpProofRequest},
 		BadPeer, ChainSync, OnBlockData, OnBlockJustification, OnStateData, OpaqueBlockRequest,
 		OpaqueBlockResponse, OpaqueStateRequest, OpaqueStateResponse, PollBlockAnnounceValidation,
@@ -128,6 +132,8 @@ struct Metrics {
 	queued_blocks: Gauge<U64>,
 	fork_targets: Gauge<U64>,
 	justifications: GaugeVec<U64>,
+	block_request_latency: Histogram,
+	block_request_throughput: Histogram,
 }
 
 impl Metrics {
@@ -156,6 +162,26 @@ impl Metrics {
 				)?;
 				register(g, r)?
 			},
+			block_request_latency: {
+				let h = Histogram::with_opts(
+					HistogramOpts::new(
+						"substrate_sync_block_request_latency",
+						"Time between a block request and its response, in seconds",
+					)
+					.buckets(exponential_buckets(0.01, 2.0, 12)?),
+				)?;
+				register(h, r)?
+			},
+			block_request_throughput: {
+				let h = Histogram::with_opts(
+					HistogramOpts::new(
+						"substrate_sync_block_request_throughput",
+						"Bytes of block responses received per second of their request",
+					)
+					.buckets(exponential_buckets(1024.0, 4.0, 10)?),
+				)?;
+				register(h, r)?
+			},
 		})
 	}
 }
@@ -220,6 +246,8 @@ struct Peer<B: BlockT> {
 	info: PeerInfo<B>,
 	/// Current request, if any. Started by emitting [`CustomMessageOutcome::BlockRequest`].
 	request: Option<(PeerRequest<B>, oneshot::Receiver<Result<Vec<u8>, RequestFailure>>)>,
+	/// When the current block request was started.
+	block_request_started: Option<time::Instant>,
 	/// Holds a set of blocks known to this peer.
 	known_blocks: LruHashSet<B::Hash>,
 }
@@ -280,6 +308,7 @@ where
 		notifications_protocols_handshakes: Vec<Vec<u8>>,
 		metrics_registry: Option<&Registry>,
 		chain_sync: Box<dyn ChainSync<B>>,
+		reputations: Vec<(PeerId, i32)>,
 	) -> error::Result<(Self, sc_peerset::PeersetHandle, Vec<(PeerId, Multiaddr)>)> {
 		let info = chain.info();
 
@@ -368,7 +397,7 @@ where
 				});
 			}
 
-			sc_peerset::Peerset::from_config(sc_peerset::PeersetConfig { sets })
+			sc_peerset::Peerset::from_config(sc_peerset::PeersetConfig { sets, reputations })
 		};
 
 		let block_announces_protocol = {
@@ -484,6 +513,26 @@ where
 		self.behaviour.peerset_debug_info()
 	}
 
+	/// Returns the non-zero reputations of the nodes known to the peerset manager.
+	pub fn peerset_reputations(&mut self) -> Vec<(PeerId, i32)> {
+		self.behaviour.peerset_reputations()
+	}
+
+	/// Returns the block download throughput of the peers we sync with.
+	pub fn sync_peer_throughput(&self) -> Vec<(PeerId, PeerThroughput)> {
+		self.chain_sync.peer_throughput()
+	}
+
+	/// Returns the scores of the nodes known to the peerset manager.
+	pub fn peerset_peer_scores(&mut self) -> Vec<sc_peerset::PeerScore> {
+		self.behaviour.peerset_peer_scores()
+	}
+
+	/// Returns the name of the notifications protocol of the given peerset set.
+	pub fn peerset_protocol_name(&self, set_id: sc_peerset::SetId) -> Option<&Cow<'static, str>> {
+		self.behaviour.set_protocol_name(set_id)
+	}
+
 	/// Returns the number of peers we're connected to.
 	pub fn num_connected_peers(&self) -> usize {
 		self.peers.len()
@@ -780,6 +829,7 @@ where
 				best_number: status.best_number,
 			},
 			request: None,
+			block_request_started: None,
 			known_blocks: LruHashSet::new(
 				NonZeroUsize::new(MAX_KNOWN_BLOCKS).expect("Constant is nonzero"),
 			),
@@ -1224,6 +1274,7 @@ fn prepare_block_request<B: BlockT>(
 
 	if let Some(ref mut peer) = peers.get_mut(&who) {
 		peer.request = Some((PeerRequest::Block(request.clone()), rx));
+		peer.block_request_started = Some(time::Instant::now());
 	}
 
 	let request = chain_sync.create_opaque_block_request(&request);
@@ -1400,6 +1451,15 @@ where
 						let (req, _) = peer.request.take().unwrap();
 						match req {
 							PeerRequest::Block(req) => {
+								if let (Some(metrics), Some(started)) =
+									(&self.metrics, peer.block_request_started.take())
+								{
+									let elapsed = started.elapsed().as_secs_f64();
+									metrics.block_request_latency.observe(elapsed);
+									metrics
+										.block_request_throughput
+										.observe(resp.len() as f64 / elapsed.max(0.001));
+								}
 								let response =
 									match self.chain_sync.decode_block_response(&resp[..]) {
 										Ok(proto) => proto,
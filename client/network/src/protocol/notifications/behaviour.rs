@@ -602,6 +602,21 @@ impl Notifications {
 		self.peerset.debug_info()
 	}
 
+	/// Returns the non-zero reputations of the nodes known to the peerset manager.
+	pub fn peerset_reputations(&mut self) -> Vec<(PeerId, i32)> {
+		self.peerset.reputations()
+	}
+
+	/// Returns the scores of the nodes known to the peerset manager.
+	pub fn peerset_peer_scores(&mut self) -> Vec<sc_peerset::PeerScore> {
+		self.peerset.peer_scores()
+	}
+
+	/// Returns the name of the notifications protocol of the given set.
+	pub fn set_protocol_name(&self, set_id: sc_peerset::SetId) -> Option<&Cow<'static, str>> {
+		self.notif_protocols.get(usize::from(set_id)).map(|protocol| &protocol.name)
+	}
+
 	/// Function that is called when the peerset wants us to connect to a peer.
 	fn peerset_report_connect(&mut self, peer_id: PeerId, set_id: sc_peerset::SetId) {
 		// If `PeerId` is unknown to us, insert an entry, start dialing, and return early.
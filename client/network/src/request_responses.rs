@@ -33,6 +33,13 @@
 //!
 //! - If provided, a ["requests processing"](ProtocolConfig::inbound_queue) channel
 //! is used to handle incoming requests.
+//!
+//! - If [enabled](ProtocolConfig::compress_responses), the responses are compressed with zstd on
+//! the main protocol name. The peers which don't support compression negotiate one of the
+//! fallback names instead, on which responses are sent uncompressed.
+//!
+//! - If provided, a [quota](ProtocolConfig::inbound_quota) limits the number of incoming requests
+//! of every peer. The requests exceeding it are refused and lower the reputation of the peer.
 
 use crate::ReputationChange;
 use futures::{
@@ -53,9 +60,10 @@ use libp2p::{
 use sc_network_common::request_responses::{
 	IfDisconnected, IncomingRequest, OutgoingResponse, ProtocolConfig, RequestFailure,
 };
+use sc_utils::token_bucket::{Rate, TokenBucket};
 use std::{
 	borrow::Cow,
-	collections::{hash_map::Entry, HashMap},
+	collections::{hash_map::Entry, HashMap, HashSet},
 	io, iter,
 	pin::Pin,
 	task::{Context, Poll},
@@ -65,6 +73,13 @@ use std::{
 pub use libp2p::request_response::{InboundFailure, OutboundFailure, RequestId};
 use sc_peerset::{PeersetHandle, BANNED_THRESHOLD};
 
+mod rep {
+	use sc_peerset::ReputationChange as Rep;
+
+	/// Reputation change when a peer exceeds its quota of requests on a protocol.
+	pub const QUOTA_EXCEEDED: Rep = Rep::new(-(1 << 12), "Request quota exceeded");
+}
+
 /// Event generated by the [`RequestResponsesBehaviour`].
 #[derive(Debug)]
 pub enum Event {
@@ -153,6 +168,14 @@ pub struct RequestResponsesBehaviour {
 	/// Pending message request, holds `MessageRequest` as a Future state to poll it
 	/// until we get a response from `Peerset`
 	message_request: Option<MessageRequest>,
+
+	/// Quota of incoming requests of the protocols which have one, with the token bucket of
+	/// every connected peer.
+	quotas: HashMap<Cow<'static, str>, (Rate, HashMap<PeerId, TokenBucket>)>,
+
+	/// Incoming requests refused because the peer exceeded its quota. Their failure, reported
+	/// later on by the [`RequestResponse`] behaviour, is ignored.
+	throttled_requests: HashSet<ProtocolRequestId>,
 }
 
 // This is a state of processing incoming request Message.
@@ -186,6 +209,7 @@ impl RequestResponsesBehaviour {
 		peerset: PeersetHandle,
 	) -> Result<Self, RegisterError> {
 		let mut protocols = HashMap::new();
+		let mut quotas = HashMap::new();
 		for protocol in list {
 			let mut cfg = RequestResponseConfig::default();
 			cfg.set_connection_keep_alive(Duration::from_secs(10));
@@ -201,6 +225,9 @@ impl RequestResponsesBehaviour {
 				GenericCodec {
 					max_request_size: protocol.max_request_size,
 					max_response_size: protocol.max_response_size,
+					compressed_protocol: protocol
+						.compress_responses
+						.then(|| protocol.name.as_bytes().to_vec()),
 				},
 				iter::once(protocol.name.as_bytes().to_vec())
 					.chain(protocol.fallback_names.iter().map(|name| name.as_bytes().to_vec()))
@@ -208,6 +235,11 @@ impl RequestResponsesBehaviour {
 				cfg,
 			);
 
+			if let Some(quota) = protocol.inbound_quota {
+				let rate = Rate { burst: quota.burst, per_second: quota.per_second as f64 };
+				quotas.insert(protocol.name.clone(), (rate, HashMap::new()));
+			}
+
 			match protocols.entry(protocol.name) {
 				Entry::Vacant(e) => e.insert((rq_rp, protocol.inbound_queue)),
 				Entry::Occupied(e) => return Err(RegisterError::DuplicateProtocol(e.key().clone())),
@@ -222,6 +254,8 @@ impl RequestResponsesBehaviour {
 			send_feedback: Default::default(),
 			peerset,
 			message_request: None,
+			quotas,
+			throttled_requests: Default::default(),
 		})
 	}
 
@@ -338,6 +372,12 @@ impl NetworkBehaviour for RequestResponsesBehaviour {
 		handler: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
 		remaining_established: usize,
 	) {
+		if remaining_established == 0 {
+			for (_, buckets) in self.quotas.values_mut() {
+				buckets.remove(peer_id);
+			}
+		}
+
 		for (p_name, event) in handler.into_iter() {
 			if let Some((proto, _)) = self.protocols.get_mut(p_name.as_str()) {
 				proto.inject_connection_closed(
@@ -604,6 +644,36 @@ impl NetworkBehaviour for RequestResponsesBehaviour {
 							message:
 								RequestResponseMessage::Request { request_id, request, channel, .. },
 						} => {
+							if let Some((rate, buckets)) = self.quotas.get_mut(protocol) {
+								let now = Instant::now();
+								let within_quota = buckets
+									.entry(peer)
+									.or_insert_with(|| TokenBucket::full(rate, now))
+									.try_take(rate, now);
+
+								if !within_quota {
+									log::debug!(
+										target: "sub-libp2p",
+										"Refusing request from {} exceeding its quota on {:?}",
+										peer,
+										protocol,
+									);
+									self.peerset.report_peer(peer, rep::QUOTA_EXCEEDED);
+
+									// Dropping the channel refuses the request.
+									drop(channel);
+									self.throttled_requests
+										.insert((protocol.clone(), request_id).into());
+
+									let out = Event::InboundRequest {
+										peer,
+										protocol: protocol.clone(),
+										result: Err(ResponseFailure::Throttled),
+									};
+									return Poll::Ready(NetworkBehaviourAction::GenerateEvent(out))
+								}
+							}
+
 							self.pending_responses_arrival_time
 								.insert((protocol.clone(), request_id).into(), Instant::now());
 
@@ -713,6 +783,14 @@ impl NetworkBehaviour for RequestResponsesBehaviour {
 						RequestResponseEvent::InboundFailure {
 							request_id, peer, error, ..
 						} => {
+							if self
+								.throttled_requests
+								.remove(&(protocol.clone(), request_id).into())
+							{
+								// Already reported when the request was refused.
+								continue
+							}
+
 							self.pending_responses_arrival_time
 								.remove(&(protocol.clone(), request_id).into());
 							self.send_feedback.remove(&(protocol.clone(), request_id).into());
@@ -775,6 +853,9 @@ pub enum ResponseFailure {
 	/// Problem on the network.
 	#[error("Problem on the network: {0}")]
 	Network(InboundFailure),
+	/// The peer exceeded its quota of requests.
+	#[error("Request quota exceeded")]
+	Throttled,
 }
 
 /// Implements the libp2p [`RequestResponseCodec`] trait. Defines how streams of bytes are turned
@@ -784,6 +865,29 @@ pub enum ResponseFailure {
 pub struct GenericCodec {
 	max_request_size: u64,
 	max_response_size: u64,
+	/// Protocol name on which the responses are compressed, if any.
+	compressed_protocol: Option<Vec<u8>>,
+}
+
+impl GenericCodec {
+	fn is_compressed(&self, protocol: &[u8]) -> bool {
+		self.compressed_protocol.as_deref() == Some(protocol)
+	}
+}
+
+/// Decompress a response, refusing it if it is larger than `max_size` once decompressed.
+fn decompress_response(compressed: &[u8], max_size: u64) -> io::Result<Vec<u8>> {
+	let decoder = zstd::stream::read::Decoder::new(compressed)?;
+	let mut buffer = Vec::new();
+	io::Read::read_to_end(&mut io::Read::take(decoder, max_size.saturating_add(1)), &mut buffer)?;
+
+	if buffer.len() as u64 > max_size {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("Decompressed response size exceeds limit: {}", max_size),
+		))
+	}
+	Ok(buffer)
 }
 
 #[async_trait::async_trait]
@@ -819,7 +923,7 @@ impl RequestResponseCodec for GenericCodec {
 
 	async fn read_response<T>(
 		&mut self,
-		_: &Self::Protocol,
+		protocol: &Self::Protocol,
 		mut io: &mut T,
 	) -> io::Result<Self::Response>
 	where
@@ -849,6 +953,10 @@ impl RequestResponseCodec for GenericCodec {
 		// Read the payload.
 		let mut buffer = vec![0; length];
 		io.read_exact(&mut buffer).await?;
+
+		if self.is_compressed(protocol) {
+			buffer = decompress_response(&buffer, self.max_response_size)?;
+		}
 		Ok(Ok(buffer))
 	}
 
@@ -877,7 +985,7 @@ impl RequestResponseCodec for GenericCodec {
 
 	async fn write_response<T>(
 		&mut self,
-		_: &Self::Protocol,
+		protocol: &Self::Protocol,
 		io: &mut T,
 		res: Self::Response,
 	) -> io::Result<()>
@@ -885,7 +993,11 @@ impl RequestResponseCodec for GenericCodec {
 		T: AsyncWrite + Unpin + Send,
 	{
 		// If `res` is an `Err`, we jump to closing the substream without writing anything on it.
-		if let Ok(res) = res {
+		if let Ok(mut res) = res {
+			if self.is_compressed(protocol) {
+				res = zstd::bulk::compress(&res, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+			}
+
 			// TODO: check the length?
 			// Write the length.
 			{
@@ -946,6 +1058,7 @@ mod tests {
 				reserved_nodes: Default::default(),
 				reserved_only: false,
 			}],
+			reputations: Vec::new(),
 		};
 
 		let (peerset, handle) = Peerset::from_config(config);
@@ -999,6 +1112,8 @@ mod tests {
 					max_response_size: 1024 * 1024,
 					request_timeout: Duration::from_secs(30),
 					inbound_queue: Some(tx),
+					inbound_quota: None,
+					compress_responses: false,
 				};
 
 				build_swarm(iter::once(protocol_config))
@@ -1100,6 +1215,8 @@ mod tests {
 					max_response_size: 8, // <-- important for the test
 					request_timeout: Duration::from_secs(30),
 					inbound_queue: Some(tx),
+					inbound_quota: None,
+					compress_responses: false,
 				};
 
 				build_swarm(iter::once(protocol_config))
@@ -1197,6 +1314,8 @@ mod tests {
 					max_response_size: 1024 * 1024,
 					request_timeout: Duration::from_secs(30),
 					inbound_queue: None,
+					inbound_quota: None,
+					compress_responses: false,
 				},
 				ProtocolConfig {
 					name: From::from(protocol_name_2),
@@ -1205,6 +1324,8 @@ mod tests {
 					max_response_size: 1024 * 1024,
 					request_timeout: Duration::from_secs(30),
 					inbound_queue: None,
+					inbound_quota: None,
+					compress_responses: false,
 				},
 			];
 
@@ -1223,6 +1344,8 @@ mod tests {
 					max_response_size: 1024 * 1024,
 					request_timeout: Duration::from_secs(30),
 					inbound_queue: Some(tx_1),
+					inbound_quota: None,
+					compress_responses: false,
 				},
 				ProtocolConfig {
 					name: From::from(protocol_name_2),
@@ -1231,6 +1354,8 @@ mod tests {
 					max_response_size: 1024 * 1024,
 					request_timeout: Duration::from_secs(30),
 					inbound_queue: Some(tx_2),
+					inbound_quota: None,
+					compress_responses: false,
 				},
 			];
 
@@ -1339,4 +1464,15 @@ mod tests {
 			assert_eq!(response_receiver_2.await.unwrap().unwrap(), b"this is a response");
 		});
 	}
+
+	#[test]
+	fn decompressed_response_size_is_limited() {
+		let response = vec![42u8; 1024];
+		let compressed = zstd::bulk::compress(&response, zstd::DEFAULT_COMPRESSION_LEVEL).unwrap();
+		assert!(compressed.len() < response.len());
+
+		assert_eq!(decompress_response(&compressed, 1024).unwrap(), response);
+		assert!(decompress_response(&compressed, 1023).is_err());
+		assert!(decompress_response(&response, 2048).is_err());
+	}
 }
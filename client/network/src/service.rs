@@ -27,15 +27,18 @@
 //! The methods of the [`NetworkService`] are implemented by sending a message over a channel,
 //! which is then processed by [`NetworkWorker::poll`].
 
+#[cfg(any(test, feature = "test-helpers"))]
+use crate::simulator::NetworkSimulator;
 use crate::{
 	behaviour::{self, Behaviour, BehaviourOut},
-	bitswap::Bitswap,
+	bitswap::{Bitswap, BitswapError},
 	config::{Params, TransportConfig},
 	discovery::DiscoveryConfig,
 	error::Error,
 	network_state::{
 		NetworkState, NotConnectedPeer as NetworkStateNotConnectedPeer, Peer as NetworkStatePeer,
 	},
+	peer_store::{PeerStore, RestoredPeers},
 	protocol::{
 		self, message::generic::Roles, NotificationsSink, NotifsHandlerError, PeerInfo, Protocol,
 		Ready,
@@ -71,9 +74,9 @@ use sc_network_common::{
 		NotificationSender as NotificationSenderT, NotificationSenderError,
 		NotificationSenderReady as NotificationSenderReadyT, Signature, SigningError,
 	},
-	sync::{SyncState, SyncStatus},
+	sync::{metrics::PeerThroughput, SyncState, SyncStatus},
 };
-use sc_peerset::PeersetHandle;
+use sc_peerset::{PeerScore, PeersetHandle, SetId};
 use sc_utils::mpsc::{tracing_unbounded, TracingUnboundedReceiver, TracingUnboundedSender};
 use sp_blockchain::{HeaderBackend, HeaderMetadata};
 use sp_runtime::traits::{Block as BlockT, NumberFor};
@@ -150,7 +153,30 @@ where
 	/// Returns a `NetworkWorker` that implements `Future` and must be regularly polled in order
 	/// for the network processing to advance. From it, you can extract a `NetworkService` using
 	/// `worker.service()`. The `NetworkService` can be shared through the codebase.
-	pub fn new(mut params: Params<B, H, Client>) -> Result<Self, Error> {
+	pub fn new(params: Params<B, H, Client>) -> Result<Self, Error> {
+		Self::build(
+			params,
+			#[cfg(any(test, feature = "test-helpers"))]
+			None,
+		)
+	}
+
+	/// Creates the network service, applying the network conditions of `simulator` to its
+	/// connections.
+	///
+	/// Only has an effect with [`TransportConfig::MemoryOnly`], see [`NetworkSimulator`].
+	#[cfg(any(test, feature = "test-helpers"))]
+	pub fn new_simulated(
+		params: Params<B, H, Client>,
+		simulator: NetworkSimulator,
+	) -> Result<Self, Error> {
+		Self::build(params, Some(simulator))
+	}
+
+	fn build(
+		mut params: Params<B, H, Client>,
+		#[cfg(any(test, feature = "test-helpers"))] simulator: Option<NetworkSimulator>,
+	) -> Result<Self, Error> {
 		// Private and public keys configuration.
 		let local_identity = params.network_config.node_key.clone().into_keypair()?;
 		let local_public = local_identity.public();
@@ -216,6 +242,15 @@ where
 			fs::create_dir_all(path)?;
 		}
 
+		// Nodes known from the previous run.
+		let (peer_store, restored_peers) = match &params.network_config.net_config_path {
+			Some(path) => {
+				let (peer_store, restored_peers) = PeerStore::open(path);
+				(Some(peer_store), restored_peers)
+			},
+			None => (None, RestoredPeers::default()),
+		};
+
 		let transactions_handler_proto = transactions::TransactionsHandlerPrototype::new(
 			params.protocol_id.clone(),
 			params
@@ -253,6 +288,7 @@ where
 				.collect(),
 			params.metrics_registry.as_ref(),
 			params.chain_sync,
+			restored_peers.reputations,
 		)?;
 
 		// List of multiaddresses that we know in the network.
@@ -298,6 +334,7 @@ where
 			let discovery_config = {
 				let mut config = DiscoveryConfig::new(local_public.clone());
 				config.with_permanent_addresses(known_addresses);
+				config.with_saved_addresses(restored_peers.addresses);
 				config.discovery_limit(
 					u64::from(params.network_config.default_peers_set.out_peers) + 15,
 				);
@@ -368,6 +405,8 @@ where
 				transport::build_transport(
 					local_identity.clone(),
 					config_mem,
+					#[cfg(any(test, feature = "test-helpers"))]
+					simulator,
 					params.network_config.yamux_window_size,
 					yamux_maximum_buffer_size,
 				)
@@ -488,6 +527,7 @@ where
 			tx_handler_controller,
 			metrics,
 			boot_node_ids,
+			peer_store,
 		})
 	}
 
@@ -574,6 +614,7 @@ where
 			.behaviour_mut()
 			.user_protocol_mut()
 			.on_block_finalized(hash, &header);
+		self.network_service.behaviour_mut().on_block_finalized(hash);
 	}
 
 	/// Inform the network service about new best imported block.
@@ -687,6 +728,27 @@ where
 		}
 	}
 
+	/// Returns the block download throughput of the peers we sync with.
+	pub fn sync_peer_throughput(&self) -> Vec<(PeerId, PeerThroughput)> {
+		self.network_service.behaviour().user_protocol().sync_peer_throughput()
+	}
+
+	/// Returns the reputations, set memberships and latest reputation changes of the peers known
+	/// to the peer set manager.
+	pub fn peer_scores(&mut self) -> Vec<PeerScore> {
+		self.network_service.behaviour_mut().user_protocol_mut().peerset_peer_scores()
+	}
+
+	/// Returns the name of the notifications protocol associated with a set of the peer set
+	/// manager, as found in [`PeerScore::sets`].
+	pub fn peerset_protocol_name(&self, set_id: SetId) -> Option<Cow<'static, str>> {
+		self.network_service
+			.behaviour()
+			.user_protocol()
+			.peerset_protocol_name(set_id)
+			.cloned()
+	}
+
 	/// Get currently connected peers.
 	pub fn peers_debug_info(&mut self) -> Vec<(PeerId, PeerInfo<B>)> {
 		self.network_service
@@ -735,6 +797,22 @@ impl<B: BlockT + 'static, H: ExHashT> NetworkService<B, H> {
 		}
 	}
 
+	/// Fetch the data of `cid` over Bitswap, from the local indexed transactions or from the
+	/// peers providing it.
+	///
+	/// Fails with [`BitswapError::Disabled`] unless the node was started with
+	/// [`NetworkConfiguration::ipfs_server`](crate::config::NetworkConfiguration::ipfs_server).
+	pub async fn bitswap_get(&self, cid: cid::Cid) -> Result<Vec<u8>, BitswapError> {
+		let (tx, rx) = oneshot::channel();
+
+		let _ = self
+			.to_worker
+			.unbounded_send(ServiceToWorkerMsg::BitswapWant { cid, pending_response: tx });
+
+		// The channel can only be closed if the network worker no longer exists.
+		rx.await.map_err(|_| BitswapError::SendResponse)?
+	}
+
 	/// Utility function to extract `PeerId` from each `Multiaddr` for peer set updates.
 	///
 	/// Returns an `Err` if one of the given addresses is invalid or contains an
@@ -1287,6 +1365,10 @@ enum ServiceToWorkerMsg<B: BlockT, H: ExHashT> {
 	NetworkState {
 		pending_response: oneshot::Sender<Result<NetworkState, RequestFailure>>,
 	},
+	BitswapWant {
+		cid: cid::Cid,
+		pending_response: oneshot::Sender<Result<Vec<u8>, BitswapError>>,
+	},
 	DisconnectPeer(PeerId, Cow<'static, str>),
 	NewBestBlockImported(B::Hash, NumberFor<B>),
 }
@@ -1332,6 +1414,8 @@ where
 	peers_notifications_sinks: Arc<Mutex<HashMap<(PeerId, Cow<'static, str>), NotificationsSink>>>,
 	/// Controller for the handler of incoming and outgoing transactions.
 	tx_handler_controller: transactions::TransactionsHandlerController<H>,
+	/// Writes the known nodes to the network configuration directory, if any.
+	peer_store: Option<PeerStore>,
 }
 
 impl<B, H, Client> Future for NetworkWorker<B, H, Client>
@@ -1476,6 +1560,8 @@ where
 				ServiceToWorkerMsg::NetworkState { pending_response } => {
 					let _ = pending_response.send(Ok(this.network_state()));
 				},
+				ServiceToWorkerMsg::BitswapWant { cid, pending_response } =>
+					this.network_service.behaviour_mut().bitswap_want(cid, pending_response),
 				ServiceToWorkerMsg::DisconnectPeer(who, protocol_name) => this
 					.network_service
 					.behaviour_mut()
@@ -1551,6 +1637,13 @@ where
 										"busy-omitted",
 									ResponseFailure::Network(InboundFailure::ConnectionClosed) =>
 										"connection-closed",
+									ResponseFailure::Throttled => {
+										metrics
+											.requests_in_throttled_total
+											.with_label_values(&[&protocol])
+											.inc();
+										continue
+									},
 								};
 
 								metrics
@@ -1946,6 +2039,16 @@ where
 
 		this.tx_handler_controller.set_gossip_enabled(!is_major_syncing);
 
+		if let Some(peer_store) = this.peer_store.as_mut() {
+			if peer_store.poll_next_save(cx).is_ready() {
+				let behaviour = this.network_service.behaviour_mut();
+				let reputations = behaviour.user_protocol_mut().peerset_reputations();
+				if let Err(e) = peer_store.save(reputations, behaviour.kademlia_addresses()) {
+					warn!(target: "sub-libp2p", "Failed to write the known nodes: {}", e);
+				}
+			}
+		}
+
 		this.is_major_syncing.store(is_major_syncing, Ordering::Relaxed);
 
 		if let Some(metrics) = this.metrics.as_ref() {
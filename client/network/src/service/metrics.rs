@@ -73,6 +73,7 @@ pub struct Metrics {
 	pub pending_connections_errors_total: CounterVec<U64>,
 	pub requests_in_failure_total: CounterVec<U64>,
 	pub requests_in_success_total: HistogramVec,
+	pub requests_in_throttled_total: CounterVec<U64>,
 	pub requests_out_failure_total: CounterVec<U64>,
 	pub requests_out_success_total: HistogramVec,
 }
@@ -233,6 +234,13 @@ impl Metrics {
 				},
 				&["protocol"]
 			)?, registry)?,
+			requests_in_throttled_total: prometheus::register(CounterVec::new(
+				Opts::new(
+					"substrate_sub_libp2p_requests_in_throttled_total",
+					"Total number of incoming requests refused because the peer exceeded its quota"
+				),
+				&["protocol"]
+			)?, registry)?,
 			requests_out_failure_total: prometheus::register(CounterVec::new(
 				Opts::new(
 					"substrate_sub_libp2p_requests_out_failure_total",
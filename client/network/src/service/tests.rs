@@ -125,6 +125,7 @@ fn build_test_full_node(
 			config::SyncMode::Fast { skip_proofs, storage_chain_mode } =>
 				sc_network_common::sync::SyncMode::LightState { skip_proofs, storage_chain_mode },
 			config::SyncMode::Warp => sc_network_common::sync::SyncMode::Warp,
+			config::SyncMode::Light => sc_network_common::sync::SyncMode::Light,
 		},
 		client.clone(),
 		Box::new(DefaultBlockAnnounceValidator),
@@ -0,0 +1,496 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Simulation of network conditions between in-memory nodes, for testing.
+//!
+//! Only available with the `test-helpers` feature.
+//!
+//! A [`NetworkSimulator`] shared by the nodes of a test network, each created with
+//! [`NetworkWorker::new_simulated`](crate::NetworkWorker::new_simulated), wraps their
+//! [`TransportConfig::MemoryOnly`](crate::config::TransportConfig::MemoryOnly) connections. It
+//! delays the data received on every connection according to the [`LinkConditions`] of the link
+//! it goes through, and cuts the connections between the nodes separated by a partition.
+//!
+//! When a connection is established, both ends exchange their [`PeerId`] to find out which link
+//! the connection goes through. As a consequence, all the nodes of a test network must use the
+//! same simulator.
+//!
+//! Time is simulated: the data crossing a link is delivered once the clock of the simulator
+//! reaches its delivery time. The clock only moves when the test advances it, with
+//! [`NetworkSimulator::advance`] or step by step with
+//! [`NetworkSimulator::advance_to_next_delivery`], so that the conditions play out the same way
+//! however fast the test runs.
+//!
+//! Lost data is modeled the way a reliable transport experiences it: the chunk of data is
+//! delivered after a retransmission delay, holding back the data that follows. Whether a chunk is
+//! lost is drawn from a random number generator seeded by the test, but the chunks themselves
+//! depend on the scheduling of the nodes.
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+	collections::{HashMap, VecDeque},
+	io,
+	pin::Pin,
+	sync::Arc,
+	task::{Context, Poll, Waker},
+	time::Duration,
+};
+
+/// Minimum delay before lost data is retransmitted.
+const MIN_RETRANSMISSION_DELAY: Duration = Duration::from_millis(200);
+
+/// Maximum number of bytes read from a connection ahead of their simulated delivery.
+const MAX_IN_FLIGHT_BYTES: usize = 1024 * 1024;
+
+/// Conditions of a simulated link, in one direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConditions {
+	/// Time it takes for data to cross the link.
+	pub latency: Duration,
+	/// Maximum throughput of the link, in bytes per second. `None` for unlimited.
+	pub bandwidth: Option<u64>,
+	/// Probability, between 0 and 1, that a chunk of data is lost and has to be retransmitted.
+	pub loss: f64,
+}
+
+impl Default for LinkConditions {
+	fn default() -> Self {
+		Self { latency: Duration::ZERO, bandwidth: None, loss: 0.0 }
+	}
+}
+
+/// Simulated network conditions between in-memory nodes. See the [module-level
+/// documentation](self).
+///
+/// Cloning a simulator returns a handle to the same simulated network.
+#[derive(Debug, Clone)]
+pub struct NetworkSimulator {
+	inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+	default_link: LinkConditions,
+	/// Conditions of the links which don't use the default ones, from the first node to the
+	/// second one.
+	links: HashMap<(PeerId, PeerId), LinkConditions>,
+	/// Group of every node, if the network is partitioned.
+	partition: Option<HashMap<PeerId, usize>>,
+	rng: StdRng,
+	/// Simulated time since the creation of the simulator.
+	now: Duration,
+	/// Time the next chunk of data in flight is delivered at, by connection id.
+	deliveries: HashMap<u64, Duration>,
+	/// Connections to wake up when the conditions change or the clock moves, by connection id.
+	wakers: HashMap<u64, Waker>,
+	next_connection_id: u64,
+}
+
+impl Inner {
+	fn link(&self, from: &PeerId, to: &PeerId) -> LinkConditions {
+		self.links.get(&(*from, *to)).copied().unwrap_or(self.default_link)
+	}
+
+	fn is_cut(&self, a: &PeerId, b: &PeerId) -> bool {
+		let group =
+			|peer: &PeerId| self.partition.as_ref().and_then(|groups| groups.get(peer)).copied();
+		self.partition.is_some() && group(a) != group(b)
+	}
+
+	fn conditions_changed(&mut self) {
+		for (_, waker) in self.wakers.drain() {
+			waker.wake();
+		}
+	}
+}
+
+impl NetworkSimulator {
+	/// Create a simulator of a perfect network, whose random decisions are drawn from `seed`.
+	pub fn new(seed: u64) -> Self {
+		Self {
+			inner: Arc::new(Mutex::new(Inner {
+				default_link: LinkConditions::default(),
+				links: HashMap::new(),
+				partition: None,
+				rng: StdRng::seed_from_u64(seed),
+				now: Duration::ZERO,
+				deliveries: HashMap::new(),
+				wakers: HashMap::new(),
+				next_connection_id: 0,
+			})),
+		}
+	}
+
+	/// Set the conditions of the links which weren't configured with [`Self::set_link`].
+	pub fn set_default_link(&self, conditions: LinkConditions) {
+		let mut inner = self.inner.lock();
+		inner.default_link = conditions;
+		inner.conditions_changed();
+	}
+
+	/// Set the conditions of the link from `from` to `to`. The link in the other direction is left
+	/// untouched.
+	pub fn set_link(&self, from: PeerId, to: PeerId, conditions: LinkConditions) {
+		let mut inner = self.inner.lock();
+		inner.links.insert((from, to), conditions);
+		inner.conditions_changed();
+	}
+
+	/// Split the network into groups of nodes unable to reach each other, replacing any previous
+	/// partition.
+	///
+	/// The nodes which aren't part of any group form an additional group together. The
+	/// connections between nodes of different groups are closed, and new ones are refused.
+	pub fn partition<G>(&self, groups: impl IntoIterator<Item = G>)
+	where
+		G: IntoIterator<Item = PeerId>,
+	{
+		let partition = groups
+			.into_iter()
+			.enumerate()
+			.flat_map(|(group, peers)| peers.into_iter().map(move |peer| (peer, group)))
+			.collect();
+
+		let mut inner = self.inner.lock();
+		inner.partition = Some(partition);
+		inner.conditions_changed();
+	}
+
+	/// Remove the partition of the network.
+	pub fn heal(&self) {
+		let mut inner = self.inner.lock();
+		inner.partition = None;
+		inner.conditions_changed();
+	}
+
+	/// Current time of the simulated network, since the creation of the simulator.
+	pub fn now(&self) -> Duration {
+		self.inner.lock().now
+	}
+
+	/// Advance the clock of the simulated network by `duration`, delivering the data due by then.
+	pub fn advance(&self, duration: Duration) {
+		let mut inner = self.inner.lock();
+		inner.now += duration;
+		inner.conditions_changed();
+	}
+
+	/// Advance the clock to the delivery time of the next chunk of data in flight, unless some
+	/// data is already due.
+	///
+	/// Returns false if there is no data in flight.
+	pub fn advance_to_next_delivery(&self) -> bool {
+		let mut inner = self.inner.lock();
+		match inner.deliveries.values().min().copied() {
+			Some(next) => {
+				if next > inner.now {
+					inner.now = next;
+					inner.conditions_changed();
+				}
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Returns true if `a` and `b` are separated by a partition.
+	pub fn is_partitioned(&self, a: &PeerId, b: &PeerId) -> bool {
+		self.inner.lock().is_cut(a, b)
+	}
+
+	/// Identify the remote end of a newly established connection, and wrap the connection in a
+	/// [`SimulatedConnection`].
+	pub(crate) async fn connect<S>(
+		self,
+		local: PeerId,
+		mut socket: S,
+	) -> io::Result<SimulatedConnection<S>>
+	where
+		S: AsyncRead + AsyncWrite + Unpin,
+	{
+		let local_bytes = local.to_bytes();
+		let length = u8::try_from(local_bytes.len())
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "PeerId too long"))?;
+		socket.write_all(&[length]).await?;
+		socket.write_all(&local_bytes).await?;
+		socket.flush().await?;
+
+		let mut length = [0];
+		socket.read_exact(&mut length).await?;
+		let mut remote_bytes = vec![0; usize::from(length[0])];
+		socket.read_exact(&mut remote_bytes).await?;
+		let remote = PeerId::from_bytes(&remote_bytes)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+		if self.is_partitioned(&local, &remote) {
+			return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "Simulated partition"))
+		}
+		Ok(SimulatedConnection::new(socket, self, local, remote))
+	}
+}
+
+/// Connection going through a [`NetworkSimulator`].
+///
+/// The data received is delivered once it has crossed the simulated link from the remote.
+pub struct SimulatedConnection<S> {
+	inner: S,
+	simulator: NetworkSimulator,
+	id: u64,
+	local: PeerId,
+	remote: PeerId,
+	/// Data read from `inner`, with the simulated time it is delivered at.
+	in_flight: VecDeque<(Duration, Vec<u8>)>,
+	in_flight_bytes: usize,
+	/// Simulated time until which the link is busy transmitting the data already in flight.
+	link_busy_until: Duration,
+	/// True if `inner` reached EOF.
+	inner_closed: bool,
+}
+
+impl<S> SimulatedConnection<S> {
+	fn new(inner: S, simulator: NetworkSimulator, local: PeerId, remote: PeerId) -> Self {
+		let (id, now) = {
+			let mut simulator = simulator.inner.lock();
+			simulator.next_connection_id += 1;
+			(simulator.next_connection_id, simulator.now)
+		};
+
+		Self {
+			inner,
+			simulator,
+			id,
+			local,
+			remote,
+			in_flight: VecDeque::new(),
+			in_flight_bytes: 0,
+			link_busy_until: now,
+			inner_closed: false,
+		}
+	}
+
+	fn check_not_cut(&self) -> io::Result<()> {
+		if self.simulator.is_partitioned(&self.local, &self.remote) {
+			return Err(io::Error::new(io::ErrorKind::ConnectionReset, "Simulated partition"))
+		}
+		Ok(())
+	}
+
+	/// Schedule the delivery of a chunk of data received from the remote.
+	fn schedule(&mut self, data: Vec<u8>) {
+		let (now, link, lost) = {
+			let mut simulator = self.simulator.inner.lock();
+			let link = simulator.link(&self.remote, &self.local);
+			let lost = link.loss > 0.0 && simulator.rng.gen_bool(link.loss.min(1.0));
+			(simulator.now, link, lost)
+		};
+
+		let transmission = link.bandwidth.map_or(Duration::ZERO, |bandwidth| {
+			Duration::from_secs_f64(data.len() as f64 / bandwidth.max(1) as f64)
+		});
+		self.link_busy_until = self.link_busy_until.max(now) + transmission;
+
+		let mut delivered_at = self.link_busy_until + link.latency;
+		if lost {
+			delivered_at += MIN_RETRANSMISSION_DELAY.max(link.latency * 2);
+		}
+		// Data is delivered in order, after the data held back by a retransmission.
+		if let Some((last, _)) = self.in_flight.back() {
+			delivered_at = delivered_at.max(*last);
+		}
+
+		self.in_flight_bytes += data.len();
+		self.in_flight.push_back((delivered_at, data));
+	}
+}
+
+impl<S> Drop for SimulatedConnection<S> {
+	fn drop(&mut self) {
+		let mut simulator = self.simulator.inner.lock();
+		simulator.deliveries.remove(&self.id);
+		simulator.wakers.remove(&self.id);
+	}
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for SimulatedConnection<S> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut [u8],
+	) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		this.check_not_cut()?;
+
+		// Read everything available from the remote, up to the in-flight limit.
+		let mut chunk = [0; 8192];
+		while !this.inner_closed && this.in_flight_bytes < MAX_IN_FLIGHT_BYTES {
+			match Pin::new(&mut this.inner).poll_read(cx, &mut chunk) {
+				Poll::Ready(Ok(0)) => this.inner_closed = true,
+				Poll::Ready(Ok(read)) => this.schedule(chunk[..read].to_vec()),
+				Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+				Poll::Pending => break,
+			}
+		}
+
+		let mut simulator = this.simulator.inner.lock();
+		let delivered_at = match this.in_flight.front() {
+			Some((delivered_at, _)) => *delivered_at,
+			None => {
+				simulator.deliveries.remove(&this.id);
+				if this.inner_closed {
+					return Poll::Ready(Ok(0))
+				}
+				simulator.wakers.insert(this.id, cx.waker().clone());
+				return Poll::Pending
+			},
+		};
+
+		if delivered_at > simulator.now {
+			simulator.deliveries.insert(this.id, delivered_at);
+			simulator.wakers.insert(this.id, cx.waker().clone());
+			return Poll::Pending
+		}
+
+		let (_, data) = this.in_flight.front_mut().expect("Checked above; qed");
+		let read = buf.len().min(data.len());
+		buf[..read].copy_from_slice(&data[..read]);
+		data.drain(..read);
+		if data.is_empty() {
+			this.in_flight.pop_front();
+		}
+		this.in_flight_bytes -= read;
+		match this.in_flight.front() {
+			Some((delivered_at, _)) => simulator.deliveries.insert(this.id, *delivered_at),
+			None => simulator.deliveries.remove(&this.id),
+		};
+		Poll::Ready(Ok(read))
+	}
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for SimulatedConnection<S> {
+	fn poll_write(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		let this = self.get_mut();
+		this.check_not_cut()?;
+		Pin::new(&mut this.inner).poll_write(cx, buf)
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+		this.check_not_cut()?;
+		Pin::new(&mut this.inner).poll_flush(cx)
+	}
+
+	fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Pin::new(&mut self.get_mut().inner).poll_close(cx)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::{executor::block_on, io::Cursor, FutureExt};
+
+	fn connection(
+		simulator: &NetworkSimulator,
+		data: &[u8],
+	) -> (SimulatedConnection<Cursor<Vec<u8>>>, PeerId, PeerId) {
+		let local = PeerId::random();
+		let remote = PeerId::random();
+		let connection =
+			SimulatedConnection::new(Cursor::new(data.to_vec()), simulator.clone(), local, remote);
+		(connection, local, remote)
+	}
+
+	/// Read everything from `connection`, advancing the clock from one delivery to the next.
+	fn read_to_end(
+		simulator: &NetworkSimulator,
+		connection: &mut SimulatedConnection<Cursor<Vec<u8>>>,
+	) -> io::Result<Vec<u8>> {
+		let mut received = Vec::new();
+		let mut read = connection.read_to_end(&mut received);
+		loop {
+			if let Some(result) = (&mut read).now_or_never() {
+				result?;
+				break
+			}
+			assert!(simulator.advance_to_next_delivery());
+		}
+		Ok(received)
+	}
+
+	#[test]
+	fn data_is_delivered_after_latency() {
+		let simulator = NetworkSimulator::new(0);
+		let (mut connection, local, remote) = connection(&simulator, b"hello");
+		let latency = Duration::from_millis(50);
+		simulator.set_link(remote, local, LinkConditions { latency, ..Default::default() });
+
+		let mut received = [0; 5];
+		assert!(connection.read(&mut received).now_or_never().is_none());
+		simulator.advance(latency - Duration::from_millis(1));
+		assert!(connection.read(&mut received).now_or_never().is_none());
+		simulator.advance(Duration::from_millis(1));
+		assert_eq!(connection.read(&mut received).now_or_never().unwrap().unwrap(), 5);
+		assert_eq!(&received, b"hello");
+	}
+
+	#[test]
+	fn bandwidth_limits_throughput() {
+		let simulator = NetworkSimulator::new(0);
+		simulator
+			.set_default_link(LinkConditions { bandwidth: Some(100 * 1024), ..Default::default() });
+		let (mut connection, _, _) = connection(&simulator, &[0; 20 * 1024]);
+
+		let received = read_to_end(&simulator, &mut connection).unwrap();
+
+		assert_eq!(received.len(), 20 * 1024);
+		assert!((simulator.now().as_secs_f64() - 0.2).abs() < 1e-6);
+		assert!(!simulator.advance_to_next_delivery());
+	}
+
+	#[test]
+	fn partition_cuts_connections_until_healed() {
+		let simulator = NetworkSimulator::new(0);
+		let (mut connection, local, remote) = connection(&simulator, b"hello");
+
+		simulator.partition(vec![vec![local]]);
+		assert!(simulator.is_partitioned(&local, &remote));
+		let error = block_on(connection.read(&mut [0; 5])).unwrap_err();
+		assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+
+		simulator.heal();
+		assert!(!simulator.is_partitioned(&local, &remote));
+		assert_eq!(read_to_end(&simulator, &mut connection).unwrap(), b"hello");
+	}
+
+	#[test]
+	fn lost_data_is_retransmitted() {
+		let simulator = NetworkSimulator::new(0);
+		simulator.set_default_link(LinkConditions { loss: 1.0, ..Default::default() });
+		let (mut connection, _, _) = connection(&simulator, b"hello");
+
+		assert_eq!(read_to_end(&simulator, &mut connection).unwrap(), b"hello");
+		assert_eq!(simulator.now(), MIN_RETRANSMISSION_DELAY);
+	}
+}
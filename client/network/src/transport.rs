@@ -16,13 +16,15 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+#[cfg(any(test, feature = "test-helpers"))]
+use crate::simulator::NetworkSimulator;
 use libp2p::{
 	bandwidth,
 	core::{
 		self,
 		either::EitherTransport,
 		muxing::StreamMuxerBox,
-		transport::{Boxed, OptionalTransport},
+		transport::{Boxed, MemoryTransport},
 		upgrade,
 	},
 	dns, identity, mplex, noise, tcp, websocket, PeerId, Transport,
@@ -36,6 +38,8 @@ pub use self::bandwidth::BandwidthSinks;
 /// If `memory_only` is true, then only communication within the same process are allowed. Only
 /// addresses with the format `/memory/...` are allowed.
 ///
+/// `simulator` applies simulated network conditions to the connections, if `memory_only` is true.
+///
 /// `yamux_window_size` is the maximum size of the Yamux receive windows. `None` to leave the
 /// default (256kiB).
 ///
@@ -49,6 +53,7 @@ pub use self::bandwidth::BandwidthSinks;
 pub fn build_transport(
 	keypair: identity::Keypair,
 	memory_only: bool,
+	#[cfg(any(test, feature = "test-helpers"))] simulator: Option<NetworkSimulator>,
 	yamux_window_size: Option<u32>,
 	yamux_maximum_buffer_size: usize,
 ) -> (Boxed<(PeerId, StreamMuxerBox)>, Arc<BandwidthSinks>) {
@@ -68,9 +73,18 @@ pub fn build_transport(
 			EitherTransport::Right(desktop_trans.map_err(dns::DnsErr::Transport))
 		})
 	} else {
-		EitherTransport::Right(OptionalTransport::some(
-			libp2p::core::transport::MemoryTransport::default(),
-		))
+		let memory = MemoryTransport::default();
+		#[cfg(any(test, feature = "test-helpers"))]
+		let memory = match simulator {
+			Some(simulator) => {
+				let local_peer_id = keypair.public().to_peer_id();
+				EitherTransport::Left(
+					memory.and_then(move |socket, _| simulator.connect(local_peer_id, socket)),
+				)
+			},
+			None => EitherTransport::Right(memory),
+		};
+		EitherTransport::Right(memory)
 	};
 
 	let (transport, bandwidth) = bandwidth::BandwidthLogging::new(transport);
@@ -0,0 +1,117 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Schema for the state and warp sync progress in the aux-db.
+//!
+//! Only the position of a state sync is kept in the aux-db. The key-values it downloaded are
+//! committed to the [`StateSyncStore`] as they arrive, one chunk per imported response.
+
+use codec::{Decode, Encode};
+use sc_client_api::backend::{AuxStore, StateSyncStore};
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_finality_grandpa::{AuthorityList, SetId};
+use sp_runtime::traits::Block as BlockT;
+
+const STATE_SYNC_KEY: &[u8] = b"sync_state_progress";
+const WARP_SYNC_KEY: &[u8] = b"sync_warp_progress";
+
+/// Progress of a state sync.
+#[derive(Encode, Decode)]
+pub(crate) struct StateSyncProgress<Header> {
+	/// Header of the block whose state is downloaded.
+	pub target: Header,
+	/// Whether the state is downloaded without proofs.
+	pub skip_proof: bool,
+	/// Last imported key of the top trie, followed by the one of the child trie being imported.
+	pub last_key: Vec<Vec<u8>>,
+	/// Number of chunks committed to the [`StateSyncStore`].
+	pub chunks: u32,
+	/// Number of imported bytes.
+	pub imported_bytes: u64,
+}
+
+/// Progress of the warp proof download: the last verified authority set and block hash.
+#[derive(Encode, Decode)]
+pub(crate) struct WarpSyncProgress<Hash> {
+	pub set_id: SetId,
+	pub authorities: AuthorityList,
+	pub last_hash: Hash,
+}
+
+fn load_decode<A: AuxStore, T: Decode>(backend: &A, key: &[u8]) -> ClientResult<Option<T>> {
+	match backend.get_aux(key)? {
+		None => Ok(None),
+		Some(t) => T::decode(&mut &t[..]).map(Some).map_err(|e| {
+			ClientError::Backend(format!("Sync progress DB is corrupted. Decode error: {}", e))
+		}),
+	}
+}
+
+/// Load the progress of a state sync.
+pub(crate) fn load_state_sync<B: BlockT, A: AuxStore>(
+	backend: &A,
+) -> ClientResult<Option<StateSyncProgress<B::Header>>> {
+	load_decode(backend, STATE_SYNC_KEY)
+}
+
+/// Start a new state sync, forgetting the chunks of any previous one.
+pub(crate) fn write_state_sync_start<Header: Encode + Decode, A: AuxStore + StateSyncStore>(
+	backend: &A,
+	progress: &StateSyncProgress<Header>,
+) -> ClientResult<()> {
+	remove_state_sync_chunks::<Header, _>(backend)?;
+	write_state_sync(backend, progress)
+}
+
+/// Write the progress of a state sync, once the chunks it counts are committed.
+pub(crate) fn write_state_sync<Header: Encode, A: AuxStore>(
+	backend: &A,
+	progress: &StateSyncProgress<Header>,
+) -> ClientResult<()> {
+	backend.insert_aux(&[(STATE_SYNC_KEY, &progress.encode()[..])], &[])
+}
+
+/// Load the progress of the warp proof download.
+pub(crate) fn load_warp_sync<B: BlockT, A: AuxStore>(
+	backend: &A,
+) -> ClientResult<Option<WarpSyncProgress<B::Hash>>> {
+	load_decode(backend, WARP_SYNC_KEY)
+}
+
+/// Write the progress of the warp proof download.
+pub(crate) fn write_warp_sync<Hash: Encode, A: AuxStore>(
+	backend: &A,
+	progress: &WarpSyncProgress<Hash>,
+) -> ClientResult<()> {
+	backend.insert_aux(&[(WARP_SYNC_KEY, &progress.encode()[..])], &[])
+}
+
+/// Forget the progress of the state and warp syncs.
+pub(crate) fn clear<Header: Decode, A: AuxStore + StateSyncStore>(backend: &A) -> ClientResult<()> {
+	remove_state_sync_chunks::<Header, _>(backend)?;
+	backend.insert_aux(&[], &[STATE_SYNC_KEY, WARP_SYNC_KEY])
+}
+
+fn remove_state_sync_chunks<Header: Decode, A: AuxStore + StateSyncStore>(
+	backend: &A,
+) -> ClientResult<()> {
+	match load_decode::<_, StateSyncProgress<Header>>(backend, STATE_SYNC_KEY)? {
+		Some(progress) => backend.remove_state_sync_chunks(progress.chunks),
+		None => Ok(()),
+	}
+}
@@ -30,7 +30,7 @@ use prost::Message;
 use sc_client_api::BlockBackend;
 use sc_network_common::{
 	config::ProtocolId,
-	request_responses::{IncomingRequest, OutgoingResponse, ProtocolConfig},
+	request_responses::{IncomingRequest, OutgoingResponse, ProtocolConfig, RequestQuota},
 	sync::message::BlockAttributes,
 };
 use sp_blockchain::HeaderBackend;
@@ -50,6 +50,14 @@ const MAX_BLOCKS_IN_RESPONSE: usize = 128;
 const MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
 const MAX_NUMBER_OF_SAME_REQUESTS_PER_PEER: usize = 2;
 
+/// Version of the protocol on which the `BlockResponse`s are compressed with zstd. The peers not
+/// supporting it fall back to the uncompressed version 2.
+const COMPRESSED_PROTOCOL_VERSION: u32 = 3;
+
+/// Quota of incoming block requests per peer. A syncing peer has at most one request in flight
+/// with us, so this leaves plenty of room for honest peers.
+const INBOUND_QUOTA: RequestQuota = RequestQuota { burst: 32, per_second: 8 };
+
 mod rep {
 	use sc_peerset::ReputationChange as Rep;
 
@@ -68,22 +76,30 @@ pub fn generate_protocol_config<Hash: AsRef<[u8]>>(
 	fork_id: Option<&str>,
 ) -> ProtocolConfig {
 	ProtocolConfig {
-		name: generate_protocol_name(genesis_hash, fork_id).into(),
-		fallback_names: std::iter::once(generate_legacy_protocol_name(protocol_id).into())
-			.collect(),
+		name: generate_protocol_name(&genesis_hash, fork_id, COMPRESSED_PROTOCOL_VERSION).into(),
+		fallback_names: vec![
+			generate_protocol_name(&genesis_hash, fork_id, 2).into(),
+			generate_legacy_protocol_name(protocol_id).into(),
+		],
 		max_request_size: 1024 * 1024,
 		max_response_size: 16 * 1024 * 1024,
 		request_timeout: Duration::from_secs(20),
 		inbound_queue: None,
+		inbound_quota: None,
+		compress_responses: true,
 	}
 }
 
-/// Generate the block protocol name from the genesis hash and fork id.
-fn generate_protocol_name<Hash: AsRef<[u8]>>(genesis_hash: Hash, fork_id: Option<&str>) -> String {
+/// Generate the block protocol name from the genesis hash, fork id and protocol version.
+fn generate_protocol_name<Hash: AsRef<[u8]>>(
+	genesis_hash: Hash,
+	fork_id: Option<&str>,
+	version: u32,
+) -> String {
 	if let Some(fork_id) = fork_id {
-		format!("/{}/{}/sync/2", hex::encode(genesis_hash), fork_id)
+		format!("/{}/{}/sync/{}", hex::encode(genesis_hash), fork_id, version)
 	} else {
-		format!("/{}/sync/2", hex::encode(genesis_hash))
+		format!("/{}/sync/{}", hex::encode(genesis_hash), version)
 	}
 }
 
@@ -162,6 +178,7 @@ where
 			fork_id,
 		);
 		protocol_config.inbound_queue = Some(tx);
+		protocol_config.inbound_quota = Some(INBOUND_QUOTA);
 
 		let seen_requests = LruCache::new(num_peer_hint * 2);
 
@@ -223,7 +223,42 @@ impl<B: BlockT> BlockCollection<B> {
 		}
 	}
 
-	pub fn clear_peer_download(&mut self, who: &PeerId) {
+	/// Returns a range being downloaded by a single peer that `is_stalled` and no further than
+	/// `peer_best`, to download it in parallel from `who`. The returned range is marked as being
+	/// downloaded.
+	pub fn stalled_blocks(
+		&mut self,
+		who: PeerId,
+		peer_best: NumberFor<B>,
+		is_stalled: impl Fn(&PeerId) -> bool,
+	) -> Option<Range<NumberFor<B>>> {
+		if self.peer_requests.contains_key(&who) {
+			return None
+		}
+		let range = self
+			.peer_requests
+			.iter()
+			.filter_map(|(peer, start)| match self.blocks.get(start) {
+				Some(&BlockRangeState::Downloading { len, downloading: 1 })
+					if *start + len <= peer_best + One::one() && is_stalled(peer) =>
+					Some(*start..*start + len),
+				_ => None,
+			})
+			.min_by_key(|range| range.start)?;
+
+		trace!(target: "sync", "Requesting stalled range {:?} from {}", range, who);
+		if let Some(BlockRangeState::Downloading { downloading, .. }) =
+			self.blocks.get_mut(&range.start)
+		{
+			*downloading += 1;
+		}
+		self.peer_requests.insert(who, range.start);
+		Some(range)
+	}
+
+	/// Clears the download of a peer. Returns `false` if its range is not being downloaded
+	/// anymore, for example because it was downloaded from another peer.
+	pub fn clear_peer_download(&mut self, who: &PeerId) -> bool {
 		if let Some(start) = self.peer_requests.remove(who) {
 			let remove = match self.blocks.get_mut(&start) {
 				Some(&mut BlockRangeState::Downloading { ref mut downloading, .. })
@@ -233,11 +268,14 @@ impl<B: BlockT> BlockCollection<B> {
 					false
 				},
 				Some(&mut BlockRangeState::Downloading { .. }) => true,
-				_ => false,
+				_ => return false,
 			};
 			if remove {
 				self.blocks.remove(&start);
 			}
+			true
+		} else {
+			false
 		}
 	}
 }
@@ -354,6 +392,34 @@ mod test {
 		);
 	}
 
+	#[test]
+	fn stalled_range_downloaded_in_parallel() {
+		let mut bc: BlockCollection<Block> = BlockCollection::new();
+		let slow = PeerId::random();
+		let fast = PeerId::random();
+		let blocks = generate_blocks(80);
+
+		assert_eq!(bc.needed_blocks(slow, 40, 150, 0, 1, 200), Some(1..41));
+		assert_eq!(bc.stalled_blocks(fast, 150, |_| false), None);
+		// The range is too far for the peer.
+		assert_eq!(bc.stalled_blocks(fast, 30, |_| true), None);
+		assert_eq!(bc.stalled_blocks(fast, 150, |peer| *peer == slow), Some(1..41));
+		// A range is downloaded from two peers at most.
+		assert_eq!(bc.stalled_blocks(PeerId::random(), 150, |_| true), None);
+
+		// The fast peer responds first, and the late response of the slow peer is ignored.
+		assert!(bc.clear_peer_download(&fast));
+		bc.insert(1, blocks[1..41].to_vec(), fast);
+		assert!(!bc.clear_peer_download(&slow));
+		assert_eq!(
+			bc.ready_blocks(1),
+			blocks[1..41]
+				.iter()
+				.map(|b| BlockData { block: b.clone(), origin: Some(fast) })
+				.collect::<Vec<_>>()
+		);
+	}
+
 	#[test]
 	fn large_gap() {
 		let mut bc: BlockCollection<Block> = BlockCollection::new();
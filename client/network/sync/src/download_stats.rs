@@ -0,0 +1,169 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Block download speed of the peers, used to size the block requests and to detect the ranges
+//! held by slow peers.
+
+use sc_network_common::sync::metrics::PeerThroughput;
+use std::time::{Duration, Instant};
+
+/// Minimum blocks to request in a single packet.
+pub(crate) const MIN_BLOCKS_TO_REQUEST: usize = 8;
+
+/// Duration of a block request that the request size is adapted to.
+const TARGET_REQUEST_DURATION: Duration = Duration::from_secs(2);
+
+/// Weight of the last request in the averages.
+const SMOOTHING: f64 = 0.3;
+
+/// A request is stalled after taking this many times its expected duration.
+const STALL_FACTOR: u32 = 4;
+
+/// A request is never stalled before this duration.
+const MIN_STALL_DURATION: Duration = Duration::from_secs(5);
+
+/// Measured block download speed of a peer.
+#[derive(Debug, Default)]
+pub(crate) struct DownloadStats {
+	/// Start of the pending request and number of requested blocks.
+	pending: Option<(Instant, usize)>,
+	/// Average blocks and bytes per second and latency, once a request completed.
+	average: Option<(f64, f64, Duration)>,
+}
+
+impl DownloadStats {
+	/// Record the start of a request of `count` blocks.
+	pub(crate) fn request_started(&mut self, now: Instant, count: usize) {
+		self.pending = Some((now, count));
+	}
+
+	/// Record the response to the pending request.
+	pub(crate) fn response_received(&mut self, now: Instant, blocks: usize, bytes: usize) {
+		let started = match self.pending.take() {
+			Some((started, _)) => started,
+			None => return,
+		};
+		let elapsed = now.saturating_duration_since(started);
+		let secs = elapsed.as_secs_f64().max(0.001);
+		let sample = (blocks as f64 / secs, bytes as f64 / secs, elapsed);
+		self.average = Some(match self.average {
+			Some((blocks_per_second, bytes_per_second, latency)) => (
+				blocks_per_second * (1.0 - SMOOTHING) + sample.0 * SMOOTHING,
+				bytes_per_second * (1.0 - SMOOTHING) + sample.1 * SMOOTHING,
+				latency.mul_f64(1.0 - SMOOTHING) + sample.2.mul_f64(SMOOTHING),
+			),
+			None => sample,
+		});
+	}
+
+	/// Forget the pending request, which failed or was cancelled.
+	pub(crate) fn request_cancelled(&mut self) {
+		self.pending = None;
+	}
+
+	/// Average blocks per second, or zero if no request completed yet.
+	pub(crate) fn blocks_per_second(&self) -> f64 {
+		self.average.map_or(0.0, |(blocks_per_second, _, _)| blocks_per_second)
+	}
+
+	/// Number of blocks to request at once, so that a request takes about
+	/// [`TARGET_REQUEST_DURATION`], between [`MIN_BLOCKS_TO_REQUEST`] and `max`.
+	pub(crate) fn request_size(&self, max: usize) -> usize {
+		match self.average {
+			Some((blocks_per_second, _, _)) => {
+				let size = blocks_per_second * TARGET_REQUEST_DURATION.as_secs_f64();
+				(size as usize).clamp(MIN_BLOCKS_TO_REQUEST, max)
+			},
+			None => max,
+		}
+	}
+
+	/// Whether the pending request takes much longer than expected from the measured speed.
+	pub(crate) fn is_stalled(&self, now: Instant) -> bool {
+		let (started, count) = match self.pending {
+			Some(pending) => pending,
+			None => return false,
+		};
+		let expected = match self.average {
+			Some((blocks_per_second, _, latency)) if blocks_per_second > 0.0 =>
+				latency.max(Duration::from_secs_f64(count as f64 / blocks_per_second)),
+			_ => Duration::ZERO,
+		};
+		now.saturating_duration_since(started) > MIN_STALL_DURATION.max(expected * STALL_FACTOR)
+	}
+
+	/// Measured throughput, once a request completed.
+	pub(crate) fn throughput(&self, max: usize) -> Option<PeerThroughput> {
+		self.average
+			.map(|(blocks_per_second, bytes_per_second, latency)| PeerThroughput {
+				blocks_per_second,
+				bytes_per_second,
+				latency,
+				request_size: self.request_size(max) as u32,
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn request_size_follows_throughput() {
+		let now = Instant::now();
+		let mut stats = DownloadStats::default();
+		assert_eq!(stats.request_size(64), 64);
+
+		stats.request_started(now, 64);
+		stats.response_received(now + Duration::from_secs(8), 64, 64 * 1024);
+		assert_eq!(stats.blocks_per_second(), 8.0);
+		assert_eq!(stats.request_size(64), 16);
+		let throughput = stats.throughput(64).unwrap();
+		assert_eq!(throughput.bytes_per_second, 8.0 * 1024.0);
+		assert_eq!(throughput.latency, Duration::from_secs(8));
+
+		let mut slow = DownloadStats::default();
+		slow.request_started(now, 64);
+		slow.response_received(now + Duration::from_secs(1), 2, 2048);
+		assert_eq!(slow.request_size(64), MIN_BLOCKS_TO_REQUEST);
+
+		for _ in 0..20 {
+			stats.request_started(now, 64);
+			stats.response_received(now + Duration::from_millis(100), 64, 64 * 1024);
+		}
+		assert_eq!(stats.request_size(64), 64);
+	}
+
+	#[test]
+	fn stalled_requests() {
+		let now = Instant::now();
+		let mut stats = DownloadStats::default();
+		stats.request_started(now, 64);
+		assert!(!stats.is_stalled(now + Duration::from_secs(1)));
+		assert!(stats.is_stalled(now + MIN_STALL_DURATION + Duration::from_secs(1)));
+
+		// 16 blocks per second, so a request of 64 blocks is expected to take 4 seconds.
+		stats.response_received(now + Duration::from_secs(4), 64, 0);
+		stats.request_started(now, 64);
+		assert!(!stats.is_stalled(now + Duration::from_secs(15)));
+		assert!(stats.is_stalled(now + Duration::from_secs(17)));
+
+		stats.request_cancelled();
+		assert!(!stats.is_stalled(now + Duration::from_secs(17)));
+	}
+}
@@ -38,6 +38,7 @@ pub mod warp_request_handler;
 
 use crate::{
 	blocks::BlockCollection,
+	download_stats::DownloadStats,
 	schema::v1::{StateRequest, StateResponse},
 	state::StateSync,
 	warp::{WarpProofImportResult, WarpSync},
@@ -48,13 +49,17 @@ use futures::{stream::FuturesUnordered, task::Poll, Future, FutureExt, StreamExt
 use libp2p::PeerId;
 use log::{debug, error, info, trace, warn};
 use prost::Message;
-use sc_client_api::{BlockBackend, ProofProvider};
+use sc_client_api::{
+	backend::{AuxStore, StateSyncStore},
+	BlockBackend, ProofProvider,
+};
 use sc_consensus::{BlockImportError, BlockImportStatus, IncomingBlock};
 use sc_network_common::sync::{
 	message::{
 		BlockAnnounce, BlockAttributes, BlockData, BlockRequest, BlockResponse, Direction,
 		FromBlock,
 	},
+	metrics::PeerThroughput,
 	warp::{EncodedProof, WarpProofRequest, WarpSyncPhase, WarpSyncProgress, WarpSyncProvider},
 	BadPeer, ChainSync as ChainSyncT, Metrics, OnBlockData, OnBlockJustification, OnStateData,
 	OpaqueBlockRequest, OpaqueBlockResponse, OpaqueStateRequest, OpaqueStateResponse, PeerInfo,
@@ -79,11 +84,15 @@ use std::{
 	ops::Range,
 	pin::Pin,
 	sync::Arc,
+	time::Instant,
 };
 
+mod aux_schema;
+mod download_stats;
 mod extra_requests;
 
-/// Maximum blocks to request in a single packet.
+/// Maximum blocks to request in a single packet. Fewer blocks are requested from the peers too slow
+/// to send them in a couple of seconds.
 const MAX_BLOCKS_TO_REQUEST: usize = 64;
 
 /// Maximum blocks to store in the import queue.
@@ -252,6 +261,8 @@ pub struct ChainSync<B: BlockT, Client> {
 	import_existing: bool,
 	/// Gap download process.
 	gap_sync: Option<GapSync<B>>,
+	/// Measured block download speed of the peers.
+	download_stats: HashMap<PeerId, DownloadStats>,
 }
 
 /// All the data we have about a Peer that we are trying to sync with
@@ -377,6 +388,8 @@ where
 		+ BlockBackend<B>
 		+ HeaderMetadata<B, Error = sp_blockchain::Error>
 		+ ProofProvider<B>
+		+ AuxStore
+		+ StateSyncStore
 		+ Send
 		+ Sync
 		+ 'static,
@@ -682,6 +695,8 @@ where
 		let allowed_requests = self.allowed_requests.take();
 		let max_parallel = if major_sync { 1 } else { self.max_parallel_downloads };
 		let gap_sync = &mut self.gap_sync;
+		let download_stats = &mut self.download_stats;
+		let now = Instant::now();
 		let iter = self.peers.iter_mut().filter_map(move |(id, peer)| {
 			if !peer.state.is_available() || !allowed_requests.contains(id) {
 				return None
@@ -719,8 +734,14 @@ where
 				max_parallel,
 				last_finalized,
 				best_queued,
+				download_stats,
+				now,
 			) {
 				peer.state = PeerSyncState::DownloadingNew(range.start);
+				download_stats.entry(*id).or_default().request_started(
+					now,
+					(range.end - range.start).saturated_into::<u32>() as usize,
+				);
 				trace!(
 					target: "sync",
 					"New block request for {}, (best:{}, common:{}) {:?}",
@@ -749,9 +770,16 @@ where
 					attrs,
 					sync.target,
 					sync.best_queued_number,
+					download_stats
+						.get(id)
+						.map_or(MAX_BLOCKS_TO_REQUEST, |s| s.request_size(MAX_BLOCKS_TO_REQUEST)),
 				)
 			}) {
 				peer.state = PeerSyncState::DownloadingGap(range.start);
+				download_stats.entry(*id).or_default().request_started(
+					now,
+					(range.end - range.start).saturated_into::<u32>() as usize,
+				);
 				trace!(
 					target: "sync",
 					"New gap block request for {}, (best:{}, common:{}) {:?}",
@@ -862,16 +890,39 @@ where
 			if let Some(request) = request {
 				match &mut peer.state {
 					PeerSyncState::DownloadingNew(_) => {
-						self.blocks.clear_peer_download(who);
+						if let Some(stats) = self.download_stats.get_mut(who) {
+							stats.response_received(
+								Instant::now(),
+								blocks.len(),
+								blocks.encoded_size(),
+							);
+						}
+						// The range may have been downloaded from a faster peer in the meantime.
+						let downloading = self.blocks.clear_peer_download(who);
 						peer.state = PeerSyncState::Available;
 						if let Some(start_block) =
 							validate_blocks::<B>(&blocks, who, Some(request))?
 						{
-							self.blocks.insert(start_block, blocks, *who);
+							if downloading {
+								self.blocks.insert(start_block, blocks, *who);
+							} else {
+								trace!(
+									target: "sync",
+									"Ignoring blocks from {} already downloaded from another peer",
+									who,
+								);
+							}
 						}
 						self.ready_blocks()
 					},
 					PeerSyncState::DownloadingGap(_) => {
+						if let Some(stats) = self.download_stats.get_mut(who) {
+							stats.response_received(
+								Instant::now(),
+								blocks.len(),
+								blocks.encoded_size(),
+							);
+						}
 						peer.state = PeerSyncState::Available;
 						if let Some(gap_sync) = &mut self.gap_sync {
 							gap_sync.blocks.clear_peer_download(who);
@@ -1293,6 +1344,7 @@ where
 							self.state_sync.as_ref().map_or(0, |s| s.progress().size / (1024 * 1024)),
 						);
 						self.state_sync = None;
+						self.clear_sync_checkpoint();
 						self.mode = SyncMode::Full;
 						output.extend(self.restart());
 					}
@@ -1307,6 +1359,7 @@ where
 							self.warp_sync.as_ref().map_or(0, |s| s.progress().total_bytes / (1024 * 1024)),
 						);
 						self.warp_sync = None;
+						self.clear_sync_checkpoint();
 						self.mode = SyncMode::Full;
 						output.extend(self.restart());
 					}
@@ -1361,6 +1414,7 @@ where
 					warn!(target: "sync", "💔 Error importing block {:?}: {}", hash, e.unwrap_err());
 					self.state_sync = None;
 					self.warp_sync = None;
+					self.clear_sync_checkpoint();
 					output.extend(self.restart());
 				},
 				Err(BlockImportError::Cancelled) => {},
@@ -1535,9 +1589,10 @@ where
 	fn peer_disconnected(&mut self, who: &PeerId) -> Option<OnBlockData<B>> {
 		self.blocks.clear_peer_download(who);
 		if let Some(gap_sync) = &mut self.gap_sync {
-			gap_sync.blocks.clear_peer_download(who)
+			gap_sync.blocks.clear_peer_download(who);
 		}
 		self.peers.remove(who);
+		self.download_stats.remove(who);
 		self.extra_justifications.peer_disconnected(who);
 		self.allowed_requests.set_all();
 		self.fork_targets.retain(|_, target| {
@@ -1556,6 +1611,15 @@ where
 		}
 	}
 
+	fn peer_throughput(&self) -> Vec<(PeerId, PeerThroughput)> {
+		self.download_stats
+			.iter()
+			.filter_map(|(peer_id, stats)| {
+				stats.throughput(MAX_BLOCKS_TO_REQUEST).map(|throughput| (*peer_id, throughput))
+			})
+			.collect()
+	}
+
 	/// Create implementation-specific block request.
 	fn create_opaque_block_request(&self, request: &BlockRequest<B>) -> OpaqueBlockRequest {
 		OpaqueBlockRequest(Box::new(schema::v1::BlockRequest {
@@ -1681,6 +1745,8 @@ where
 		+ BlockBackend<B>
 		+ HeaderMetadata<B, Error = sp_blockchain::Error>
 		+ ProofProvider<B>
+		+ AuxStore
+		+ StateSyncStore
 		+ Send
 		+ Sync
 		+ 'static,
@@ -1714,11 +1780,22 @@ where
 			warp_sync_provider,
 			import_existing: false,
 			gap_sync: None,
+			download_stats: HashMap::new(),
 		};
 		sync.reset_sync_start_point()?;
+		if let SyncMode::LightState { skip_proofs, .. } = sync.mode {
+			sync.state_sync = StateSync::resume(sync.client.clone(), skip_proofs);
+		}
 		Ok(sync)
 	}
 
+	/// Forget the state and warp sync progress checkpointed to resume them after a restart.
+	fn clear_sync_checkpoint(&self) {
+		if let Err(e) = aux_schema::clear::<B::Header, _>(&*self.client) {
+			warn!(target: "sync", "Failed to clear the sync checkpoint: {}", e);
+		}
+	}
+
 	/// Returns the best seen block number if we don't have that block yet, `None` otherwise.
 	fn best_seen(&self) -> Option<NumberFor<B>> {
 		let mut best_seens = self.peers.values().map(|p| p.best_number).collect::<Vec<_>>();
@@ -2041,6 +2118,9 @@ where
 	/// their state was `DownloadingJustification`) are unaffected and will stay in the same state.
 	fn restart(&mut self) -> impl Iterator<Item = Result<(PeerId, BlockRequest<B>), BadPeer>> + '_ {
 		self.blocks.clear();
+		for stats in self.download_stats.values_mut() {
+			stats.request_cancelled();
+		}
 		if let Err(e) = self.reset_sync_start_point() {
 			warn!(target: "sync", "💔  Unable to restart sync: {}", e);
 		}
@@ -2265,6 +2345,8 @@ fn peer_block_request<B: BlockT>(
 	max_parallel_downloads: u32,
 	finalized: NumberFor<B>,
 	best_num: NumberFor<B>,
+	download_stats: &HashMap<PeerId, DownloadStats>,
+	now: Instant,
 ) -> Option<(Range<NumberFor<B>>, BlockRequest<B>)> {
 	if best_num >= peer.best_number {
 		// Will be downloaded as alternative fork instead.
@@ -2276,14 +2358,26 @@ fn peer_block_request<B: BlockT>(
 			id, peer.common_number, finalized, peer.best_number, best_num,
 		);
 	}
-	let range = blocks.needed_blocks(
-		*id,
-		MAX_BLOCKS_TO_REQUEST,
-		peer.best_number,
-		peer.common_number,
-		max_parallel_downloads,
-		MAX_DOWNLOAD_AHEAD,
-	)?;
+	let stats = download_stats.get(id);
+	let blocks_per_second = stats.map_or(0.0, |s| s.blocks_per_second());
+	// Help with the ranges held for too long by slower peers first, as they block the import of
+	// all the blocks after them.
+	let stalled = blocks.stalled_blocks(*id, peer.best_number, |holder| {
+		download_stats
+			.get(holder)
+			.map_or(false, |s| s.is_stalled(now) && s.blocks_per_second() <= blocks_per_second)
+	});
+	let range = match stalled {
+		Some(range) => range,
+		None => blocks.needed_blocks(
+			*id,
+			stats.map_or(MAX_BLOCKS_TO_REQUEST, |s| s.request_size(MAX_BLOCKS_TO_REQUEST)),
+			peer.best_number,
+			peer.common_number,
+			max_parallel_downloads,
+			MAX_DOWNLOAD_AHEAD,
+		)?,
+	};
 
 	// The end is not part of the range.
 	let last = range.end.saturating_sub(One::one());
@@ -2314,10 +2408,11 @@ fn peer_gap_block_request<B: BlockT>(
 	attrs: BlockAttributes,
 	target: NumberFor<B>,
 	common_number: NumberFor<B>,
+	max_blocks: usize,
 ) -> Option<(Range<NumberFor<B>>, BlockRequest<B>)> {
 	let range = blocks.needed_blocks(
 		*id,
-		MAX_BLOCKS_TO_REQUEST,
+		max_blocks,
 		std::cmp::min(peer.best_number, target),
 		common_number,
 		1,
@@ -3345,4 +3440,36 @@ mod test {
 		let state = AncestorSearchState::<Block>::BinarySearch(1, 3);
 		assert!(handle_ancestor_search_state(&state, 2, true).is_none());
 	}
+
+	#[test]
+	fn light_sync_imports_headers_without_execution() {
+		let mut client = Arc::new(TestClientBuilder::new().build());
+		let blocks = (0..3).map(|_| build_block(&mut client, None, false)).collect::<Vec<_>>();
+
+		let mut sync = ChainSync::new(
+			SyncMode::Light,
+			Arc::new(TestClientBuilder::new().build()),
+			Box::new(DefaultBlockAnnounceValidator),
+			1,
+			None,
+		)
+		.unwrap();
+
+		let peer_id = PeerId::random();
+		let best_block = blocks.last().unwrap();
+		sync.new_peer(peer_id, best_block.hash(), *best_block.header().number())
+			.unwrap();
+
+		// Only the headers and justifications are requested.
+		let request = get_block_request(&mut sync, FromBlock::Hash(best_block.hash()), 3, &peer_id);
+		assert_eq!(request.fields, BlockAttributes::HEADER | BlockAttributes::JUSTIFICATION);
+
+		let response = create_block_response(blocks.into_iter().rev().collect());
+		let res = sync.on_block_data(&peer_id, Some(request), response).unwrap();
+		assert!(matches!(
+			res,
+			OnBlockData::Import(_, blocks)
+				if blocks.len() == 3 && blocks.iter().all(|block| block.skip_execution)
+		));
+	}
 }
@@ -17,17 +17,35 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 //! State sync support.
+//!
+//! The key-values imported from each response are committed as a chunk to the
+//! [`StateSyncStore`], and the position of the sync to the aux-db, so that a state sync
+//! interrupted by a restart resumes from the last imported key. This requires the peers to still
+//! have the state of the target block, which is the case for archive nodes and within their
+//! pruning window.
 
-use crate::schema::v1::{StateEntry, StateRequest, StateResponse};
+use crate::{
+	aux_schema::{self, StateSyncProgress},
+	schema::v1::{StateEntry, StateRequest, StateResponse},
+};
 use codec::{Decode, Encode};
-use log::debug;
-use sc_client_api::{CompactProof, ProofProvider};
+use log::{debug, warn};
+use sc_client_api::{
+	backend::{AuxStore, StateSyncStore},
+	CompactProof, ProofProvider,
+};
 use sc_consensus::ImportedState;
 use sc_network_common::sync::StateDownloadProgress;
 use smallvec::SmallVec;
 use sp_core::storage::well_known_keys;
 use sp_runtime::traits::{Block as BlockT, Header, NumberFor};
-use std::{collections::HashMap, sync::Arc};
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::Arc,
+};
+
+/// Key-values and child trie storage keys imported from a response, by trie root.
+type StateChunk = BTreeMap<Vec<u8>, (Vec<(Vec<u8>, Vec<u8>)>, Vec<Vec<u8>>)>;
 
 /// State sync state machine. Accumulates partial state data until it
 /// is ready to be imported.
@@ -41,6 +59,11 @@ pub struct StateSync<B: BlockT, Client> {
 	client: Arc<Client>,
 	imported_bytes: u64,
 	skip_proof: bool,
+	/// Number of chunks committed to the store.
+	chunks: u32,
+	/// Whether the chunks are still committed, which stops after the first failure to keep the
+	/// checkpoint consistent.
+	checkpointing: bool,
 }
 
 /// Import state chunk result.
@@ -56,10 +79,63 @@ pub enum ImportResult<B: BlockT> {
 impl<B, Client> StateSync<B, Client>
 where
 	B: BlockT,
-	Client: ProofProvider<B> + Send + Sync + 'static,
+	Client: ProofProvider<B> + AuxStore + StateSyncStore + Send + Sync + 'static,
 {
 	///  Create a new instance.
 	pub fn new(client: Arc<Client>, target: B::Header, skip_proof: bool) -> Self {
+		let sync = Self::with_target(client, target, skip_proof);
+		if let Err(e) = aux_schema::write_state_sync_start(&*sync.client, &sync.checkpoint()) {
+			warn!(target: "sync", "Failed to checkpoint state sync start: {}", e);
+		}
+		sync
+	}
+
+	/// Resume the state sync checkpointed by a previous run, if any, from its last imported key.
+	///
+	/// The checkpoint is discarded if it was made with a different `skip_proof` or if one of its
+	/// chunks can't be read.
+	pub fn resume(client: Arc<Client>, skip_proof: bool) -> Option<Self> {
+		let progress = match aux_schema::load_state_sync::<B, _>(&*client) {
+			Ok(Some(progress)) => progress,
+			Ok(None) => return None,
+			Err(e) => {
+				warn!(target: "sync", "Failed to load state sync checkpoint: {}", e);
+				return None
+			},
+		};
+		if progress.skip_proof != skip_proof {
+			debug!(target: "sync", "Discarding state sync checkpoint with other proof mode");
+			return None
+		}
+
+		let mut sync = Self::with_target(client, progress.target, skip_proof);
+		for index in 0..progress.chunks {
+			let chunk = match sync.client.state_sync_chunk(index) {
+				Ok(Some(chunk)) => StateChunk::decode(&mut &chunk[..]).map_err(|e| e.to_string()),
+				Ok(None) => Err("missing chunk".into()),
+				Err(e) => Err(e.to_string()),
+			};
+			match chunk {
+				Ok(chunk) => sync.apply_chunk(chunk),
+				Err(e) => {
+					warn!(target: "sync", "Discarding state sync checkpoint at chunk {}: {}", index, e);
+					return None
+				},
+			}
+		}
+		sync.last_key = SmallVec::from_vec(progress.last_key);
+		sync.chunks = progress.chunks;
+		sync.imported_bytes = progress.imported_bytes;
+		debug!(
+			target: "sync",
+			"Resuming state sync of {} from {} checkpointed chunks",
+			sync.target_block,
+			sync.chunks,
+		);
+		Some(sync)
+	}
+
+	fn with_target(client: Arc<Client>, target: B::Header, skip_proof: bool) -> Self {
 		Self {
 			client,
 			target_block: target.hash(),
@@ -70,19 +146,90 @@ where
 			complete: false,
 			imported_bytes: 0,
 			skip_proof,
+			chunks: 0,
+			checkpointing: true,
 		}
 	}
 
 	///  Validate and import a state response.
+	///
+	/// The imported key-values are committed to the store unless they complete the state, in
+	/// which case the import of the state is expected to clear the checkpoint.
 	pub fn import(&mut self, response: StateResponse) -> ImportResult<B> {
+		let (chunk, complete) = match self.import_response(response) {
+			Some(imported) => imported,
+			None => return ImportResult::BadResponse,
+		};
+		if complete {
+			self.apply_chunk(chunk);
+			self.complete = true;
+			ImportResult::Import(
+				self.target_block,
+				self.target_header.clone(),
+				ImportedState {
+					block: self.target_block,
+					state: std::mem::take(&mut self.state).into(),
+				},
+			)
+		} else {
+			if self.checkpointing {
+				if let Err(e) = self.commit_chunk(&chunk) {
+					warn!(target: "sync", "Failed to checkpoint state chunk: {}", e);
+					self.checkpointing = false;
+				}
+			}
+			self.apply_chunk(chunk);
+			ImportResult::Continue
+		}
+	}
+
+	fn checkpoint(&self) -> StateSyncProgress<B::Header> {
+		StateSyncProgress {
+			target: self.target_header.clone(),
+			skip_proof: self.skip_proof,
+			last_key: self.last_key.to_vec(),
+			chunks: self.chunks,
+			imported_bytes: self.imported_bytes,
+		}
+	}
+
+	fn commit_chunk(&mut self, chunk: &StateChunk) -> sp_blockchain::Result<()> {
+		self.client.insert_state_sync_chunk(self.chunks, &chunk.encode())?;
+		self.chunks += 1;
+		aux_schema::write_state_sync(&*self.client, &self.checkpoint())
+	}
+
+	fn apply_chunk(&mut self, chunk: StateChunk) {
+		for (root, (key_values, storage_keys)) in chunk {
+			let entry = self.state.entry(root).or_default();
+			entry.0.extend(key_values);
+			entry.1.extend(storage_keys);
+		}
+	}
+
+	/// Whether the child trie with the given root was already imported under another storage key.
+	fn is_imported_child_trie(&self, chunk: &StateChunk, root: &[u8]) -> bool {
+		let (key_values, storage_keys) = [self.state.get(root), chunk.get(root)]
+			.into_iter()
+			.flatten()
+			.fold((0, 0), |(key_values, storage_keys), entry| {
+				(key_values + entry.0.len(), storage_keys + entry.1.len())
+			});
+		key_values > 0 && storage_keys > 1
+	}
+
+	/// Validate a state response, returning the key-values it adds and whether the state is
+	/// complete.
+	fn import_response(&mut self, response: StateResponse) -> Option<(StateChunk, bool)> {
 		if response.entries.is_empty() && response.proof.is_empty() {
 			debug!(target: "sync", "Bad state response");
-			return ImportResult::BadResponse
+			return None
 		}
 		if !self.skip_proof && response.proof.is_empty() {
 			debug!(target: "sync", "Missing proof");
-			return ImportResult::BadResponse
+			return None
 		}
+		let mut chunk = StateChunk::new();
 		let complete = if !self.skip_proof {
 			debug!(target: "sync", "Importing state from {} trie nodes", response.proof.len());
 			let proof_size = response.proof.len() as u64;
@@ -90,7 +237,7 @@ where
 				Ok(proof) => proof,
 				Err(e) => {
 					debug!(target: "sync", "Error decoding proof: {:?}", e);
-					return ImportResult::BadResponse
+					return None
 				},
 			};
 			let (values, completed) = match self.client.verify_range_proof(
@@ -104,7 +251,7 @@ where
 						"StateResponse failed proof verification: {}",
 						e,
 					);
-					return ImportResult::BadResponse
+					return None
 				},
 				Ok(values) => values,
 			};
@@ -118,40 +265,26 @@ where
 			for values in values.0 {
 				let key_values = if values.state_root.is_empty() {
 					// Read child trie roots.
-					values
-						.key_values
-						.into_iter()
-						.filter(|key_value| {
-							if well_known_keys::is_child_storage_key(key_value.0.as_slice()) {
-								self.state
-									.entry(key_value.1.clone())
-									.or_default()
-									.1
-									.push(key_value.0.clone());
-								false
-							} else {
-								true
-							}
-						})
-						.collect()
+					let mut key_values = Vec::new();
+					for (key, value) in values.key_values {
+						if well_known_keys::is_child_storage_key(key.as_slice()) {
+							chunk.entry(value).or_default().1.push(key);
+						} else {
+							key_values.push((key, value));
+						}
+					}
+					key_values
 				} else {
 					values.key_values
 				};
-				let mut entry = self.state.entry(values.state_root).or_default();
-				if entry.0.len() > 0 && entry.1.len() > 1 {
+				if self.is_imported_child_trie(&chunk, &values.state_root) {
 					// Already imported child_trie with same root.
 					// Warning this will not work with parallel download.
-				} else if entry.0.is_empty() {
-					for (key, _value) in key_values.iter() {
-						self.imported_bytes += key.len() as u64;
-					}
-
-					entry.0 = key_values;
 				} else {
-					for (key, value) in key_values {
+					for (key, _value) in key_values.iter() {
 						self.imported_bytes += key.len() as u64;
-						entry.0.push((key, value))
 					}
+					chunk.entry(values.state_root).or_default().0.extend(key_values);
 				}
 			}
 			self.imported_bytes += proof_size;
@@ -183,10 +316,10 @@ where
 					complete = false;
 				}
 				let is_top = state.state_root.is_empty();
-				let entry = self.state.entry(state.state_root).or_default();
-				if entry.0.len() > 0 && entry.1.len() > 1 {
+				if self.is_imported_child_trie(&chunk, &state.state_root) {
 					// Already imported child trie with same root.
 				} else {
+					let mut key_values = Vec::new();
 					let mut child_roots = Vec::new();
 					for StateEntry { key, value } in state.entries {
 						// Skip all child key root (will be recalculated on import).
@@ -194,29 +327,18 @@ where
 							child_roots.push((value, key));
 						} else {
 							self.imported_bytes += key.len() as u64;
-							entry.0.push((key, value))
+							key_values.push((key, value))
 						}
 					}
+					chunk.entry(state.state_root).or_default().0.extend(key_values);
 					for (root, storage_key) in child_roots {
-						self.state.entry(root).or_default().1.push(storage_key);
+						chunk.entry(root).or_default().1.push(storage_key);
 					}
 				}
 			}
 			complete
 		};
-		if complete {
-			self.complete = true;
-			ImportResult::Import(
-				self.target_block,
-				self.target_header.clone(),
-				ImportedState {
-					block: self.target_block,
-					state: std::mem::take(&mut self.state).into(),
-				},
-			)
-		} else {
-			ImportResult::Continue
-		}
+		Some((chunk, complete))
 	}
 
 	/// Produce next state request.
@@ -250,3 +372,63 @@ where
 		StateDownloadProgress { percentage: percent_done, size: self.imported_bytes }
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::schema::v1::KeyValueStateEntry;
+	use sp_runtime::generic::BlockId;
+	use substrate_test_runtime_client::{
+		runtime::Block, DefaultTestClientBuilderExt, TestClientBuilder, TestClientBuilderExt,
+	};
+
+	fn response(key: &[u8], complete: bool) -> StateResponse {
+		StateResponse {
+			entries: vec![KeyValueStateEntry {
+				state_root: Vec::new(),
+				entries: vec![StateEntry { key: key.to_vec(), value: vec![1] }],
+				complete,
+			}],
+			proof: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn resumes_from_checkpointed_chunks() {
+		let client = Arc::new(TestClientBuilder::new().build());
+		let header = client.header(&BlockId::Number(0)).unwrap().unwrap();
+		assert!(StateSync::<Block, _>::resume(client.clone(), true).is_none());
+
+		let mut sync = StateSync::<Block, _>::new(client.clone(), header.clone(), true);
+		assert!(matches!(sync.import(response(b"a", false)), ImportResult::Continue));
+		assert!(matches!(sync.import(response(b"b", false)), ImportResult::Continue));
+		let request = sync.next_request();
+		let size = sync.progress().size;
+		drop(sync);
+		assert!(client.state_sync_chunk(1).unwrap().is_some());
+
+		// A checkpoint made without proofs can't resume a sync with proofs.
+		assert!(StateSync::<Block, _>::resume(client.clone(), false).is_none());
+		let mut sync = StateSync::<Block, _>::resume(client.clone(), true).unwrap();
+		assert_eq!(sync.target(), header.hash());
+		assert_eq!(sync.next_request(), request);
+		assert_eq!(sync.progress().size, size);
+		match sync.import(response(b"c", true)) {
+			ImportResult::Import(_, _, imported) => {
+				let keys: Vec<_> =
+					imported.state.0[0].key_values.iter().map(|(key, _)| key.clone()).collect();
+				assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+			},
+			_ => panic!("Expected the state to be complete"),
+		}
+
+		// Starting a new sync forgets the previous chunks.
+		StateSync::<Block, _>::new(client.clone(), header, true);
+		assert!(client.state_sync_chunk(0).unwrap().is_none());
+		let sync = StateSync::<Block, _>::resume(client.clone(), true).unwrap();
+		assert_eq!(sync.next_request().start, Vec::<Vec<u8>>::new());
+
+		aux_schema::clear::<<Block as BlockT>::Header, _>(&*client).unwrap();
+		assert!(StateSync::<Block, _>::resume(client, true).is_none());
+	}
+}
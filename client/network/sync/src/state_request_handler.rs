@@ -30,7 +30,7 @@ use prost::Message;
 use sc_client_api::{BlockBackend, ProofProvider};
 use sc_network_common::{
 	config::ProtocolId,
-	request_responses::{IncomingRequest, OutgoingResponse, ProtocolConfig},
+	request_responses::{IncomingRequest, OutgoingResponse, ProtocolConfig, RequestQuota},
 };
 use sp_runtime::{generic::BlockId, traits::Block as BlockT};
 use std::{
@@ -43,6 +43,14 @@ const LOG_TARGET: &str = "sync";
 const MAX_RESPONSE_BYTES: usize = 2 * 1024 * 1024; // Actual reponse may be bigger.
 const MAX_NUMBER_OF_SAME_REQUESTS_PER_PEER: usize = 2;
 
+/// Version of the protocol on which the `StateResponse`s are compressed with zstd. The peers not
+/// supporting it fall back to the uncompressed version 2.
+const COMPRESSED_PROTOCOL_VERSION: u32 = 3;
+
+/// Quota of incoming state requests per peer. These are more expensive to answer than block
+/// requests, so the quota is lower.
+const INBOUND_QUOTA: RequestQuota = RequestQuota { burst: 16, per_second: 4 };
+
 mod rep {
 	use sc_peerset::ReputationChange as Rep;
 
@@ -57,22 +65,30 @@ pub fn generate_protocol_config<Hash: AsRef<[u8]>>(
 	fork_id: Option<&str>,
 ) -> ProtocolConfig {
 	ProtocolConfig {
-		name: generate_protocol_name(genesis_hash, fork_id).into(),
-		fallback_names: std::iter::once(generate_legacy_protocol_name(protocol_id).into())
-			.collect(),
+		name: generate_protocol_name(&genesis_hash, fork_id, COMPRESSED_PROTOCOL_VERSION).into(),
+		fallback_names: vec![
+			generate_protocol_name(&genesis_hash, fork_id, 2).into(),
+			generate_legacy_protocol_name(protocol_id).into(),
+		],
 		max_request_size: 1024 * 1024,
 		max_response_size: 16 * 1024 * 1024,
 		request_timeout: Duration::from_secs(40),
 		inbound_queue: None,
+		inbound_quota: None,
+		compress_responses: true,
 	}
 }
 
-/// Generate the state protocol name from the genesis hash and fork id.
-fn generate_protocol_name<Hash: AsRef<[u8]>>(genesis_hash: Hash, fork_id: Option<&str>) -> String {
+/// Generate the state protocol name from the genesis hash, fork id and protocol version.
+fn generate_protocol_name<Hash: AsRef<[u8]>>(
+	genesis_hash: Hash,
+	fork_id: Option<&str>,
+	version: u32,
+) -> String {
 	if let Some(fork_id) = fork_id {
-		format!("/{}/{}/state/2", hex::encode(genesis_hash), fork_id)
+		format!("/{}/{}/state/{}", hex::encode(genesis_hash), fork_id, version)
 	} else {
-		format!("/{}/state/2", hex::encode(genesis_hash))
+		format!("/{}/state/{}", hex::encode(genesis_hash), version)
 	}
 }
 
@@ -142,6 +158,7 @@ where
 			fork_id,
 		);
 		protocol_config.inbound_queue = Some(tx);
+		protocol_config.inbound_quota = Some(INBOUND_QUOTA);
 
 		let seen_requests = LruCache::new(num_peer_hint * 2);
 
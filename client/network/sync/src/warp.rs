@@ -19,10 +19,14 @@
 //! Warp sync support.
 
 use crate::{
+	aux_schema,
 	schema::v1::{StateRequest, StateResponse},
 	state::{ImportResult, StateSync},
 };
-use sc_client_api::ProofProvider;
+use sc_client_api::{
+	backend::{AuxStore, StateSyncStore},
+	ProofProvider,
+};
 use sc_network_common::sync::warp::{
 	EncodedProof, VerificationResult, WarpProofRequest, WarpSyncPhase, WarpSyncProgress,
 	WarpSyncProvider,
@@ -56,15 +60,44 @@ pub struct WarpSync<B: BlockT, Client> {
 impl<B, Client> WarpSync<B, Client>
 where
 	B: BlockT,
-	Client: HeaderBackend<B> + ProofProvider<B> + 'static,
+	Client: HeaderBackend<B> + ProofProvider<B> + AuxStore + StateSyncStore + Send + Sync + 'static,
 {
-	///  Create a new instance.
+	///  Create a new instance, resuming the warp sync checkpointed by a previous run if any.
 	pub fn new(client: Arc<Client>, warp_sync_provider: Arc<dyn WarpSyncProvider<B>>) -> Self {
-		let last_hash = client.hash(Zero::zero()).unwrap().expect("Genesis header always exists");
-		let phase = Phase::WarpProof {
-			set_id: 0,
-			authorities: warp_sync_provider.current_authorities(),
-			last_hash,
+		if let Some(state_sync) = StateSync::resume(client.clone(), false) {
+			return Self {
+				client,
+				warp_sync_provider,
+				phase: Phase::State(state_sync),
+				total_proof_bytes: 0,
+			}
+		}
+		let phase = match aux_schema::load_warp_sync::<B, _>(&*client) {
+			Ok(Some(progress)) => {
+				log::debug!(
+					target: "sync",
+					"Resuming warp sync from {}, set_id={:?}",
+					progress.last_hash,
+					progress.set_id,
+				);
+				Phase::WarpProof {
+					set_id: progress.set_id,
+					authorities: progress.authorities,
+					last_hash: progress.last_hash,
+				}
+			},
+			result => {
+				if let Err(e) = result {
+					log::warn!(target: "sync", "Failed to load warp sync checkpoint: {}", e);
+				}
+				let last_hash =
+					client.hash(Zero::zero()).unwrap().expect("Genesis header always exists");
+				Phase::WarpProof {
+					set_id: 0,
+					authorities: warp_sync_provider.current_authorities(),
+					last_hash,
+				}
+			},
 		};
 		Self { client, warp_sync_provider, phase, total_proof_bytes: 0 }
 	}
@@ -99,6 +132,14 @@ where
 						*authorities = new_authorities;
 						*last_hash = new_last_hash;
 						self.total_proof_bytes += response.0.len() as u64;
+						let progress = aux_schema::WarpSyncProgress {
+							set_id: *set_id,
+							authorities: authorities.clone(),
+							last_hash: *last_hash,
+						};
+						if let Err(e) = aux_schema::write_warp_sync(&*self.client, &progress) {
+							log::warn!(target: "sync", "Failed to checkpoint warp sync: {}", e);
+						}
 						WarpProofImportResult::Success
 					},
 					Ok(VerificationResult::Complete(new_set_id, _, header)) => {
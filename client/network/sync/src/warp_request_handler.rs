@@ -49,6 +49,8 @@ pub fn generate_request_response_config<Hash: AsRef<[u8]>>(
 		max_response_size: MAX_RESPONSE_SIZE,
 		request_timeout: Duration::from_secs(10),
 		inbound_queue: None,
+		inbound_quota: None,
+		compress_responses: false,
 	}
 }
 
@@ -54,6 +54,7 @@ use sc_network::{
 		NetworkConfiguration, NonDefaultSetConfig, NonReservedPeerMode, Role, SyncMode,
 		TransportConfig,
 	},
+	simulator::{LinkConditions, NetworkSimulator},
 	Multiaddr, NetworkService, NetworkWorker,
 };
 use sc_network_common::{
@@ -236,6 +237,7 @@ pub struct Peer<D, BlockImport> {
 	imported_blocks_stream: Pin<Box<dyn Stream<Item = BlockImportNotification<Block>> + Send>>,
 	finality_notification_stream: Pin<Box<dyn Stream<Item = FinalityNotification<Block>> + Send>>,
 	listen_addr: Multiaddr,
+	network_simulator: Option<NetworkSimulator>,
 }
 
 impl<D, B> Peer<D, B>
@@ -695,6 +697,8 @@ pub struct FullPeerConfig {
 	pub extra_storage: Option<sp_core::storage::Storage>,
 	/// Enable transaction indexing.
 	pub storage_chain: bool,
+	/// Simulated network conditions applied to the connections of the peer.
+	pub network_simulator: Option<NetworkSimulator>,
 }
 
 pub trait TestNetFactory: Default + Sized
@@ -738,6 +742,21 @@ where
 		net
 	}
 
+	/// Create new test network with this many peers, whose connections go through `simulator`.
+	///
+	/// Polling the network advances the clock of `simulator` from one delivery to the next.
+	fn new_simulated(n: usize, simulator: &NetworkSimulator) -> Self {
+		let mut net = Self::default();
+
+		for _ in 0..n {
+			net.add_full_peer_with_config(FullPeerConfig {
+				network_simulator: Some(simulator.clone()),
+				..Default::default()
+			});
+		}
+		net
+	}
+
 	fn add_full_peer(&mut self) {
 		self.add_full_peer_with_config(Default::default())
 	}
@@ -862,6 +881,7 @@ where
 						storage_chain_mode,
 					},
 				SyncMode::Warp => sc_network_common::sync::SyncMode::Warp,
+				SyncMode::Light => sc_network_common::sync::SyncMode::Light,
 			},
 			client.clone(),
 			block_announce_validator,
@@ -869,7 +889,7 @@ where
 			Some(warp_sync),
 		)
 		.unwrap();
-		let network = NetworkWorker::new(sc_network::config::Params {
+		let params = sc_network::config::Params {
 			role: if config.is_authority { Role::Authority } else { Role::Full },
 			executor: None,
 			transactions_handler_executor: Box::new(|task| {
@@ -887,7 +907,11 @@ where
 			state_request_protocol_config,
 			light_client_request_protocol_config,
 			warp_sync_protocol_config: Some(warp_protocol_config),
-		})
+		};
+		let network = match config.network_simulator.clone() {
+			Some(simulator) => NetworkWorker::new_simulated(params, simulator),
+			None => NetworkWorker::new(params),
+		}
 		.unwrap();
 
 		trace!(target: "test_network", "Peer identifier: {}", network.service().local_peer_id());
@@ -913,6 +937,7 @@ where
 				verifier,
 				network,
 				listen_addr,
+				network_simulator: config.network_simulator,
 			});
 		});
 	}
@@ -1007,6 +1032,27 @@ where
 		}));
 	}
 
+	/// Split the peers with the given indices into groups unable to reach each other.
+	///
+	/// See [`NetworkSimulator::partition`].
+	fn partition_peers(&self, simulator: &NetworkSimulator, groups: &[&[usize]]) {
+		simulator.partition(groups.iter().map(|group| group.iter().map(|i| self.peers()[*i].id())));
+	}
+
+	/// Set the conditions of the link between the peers with the given indices, in both
+	/// directions.
+	fn set_peers_link(
+		&self,
+		simulator: &NetworkSimulator,
+		a: usize,
+		b: usize,
+		conditions: LinkConditions,
+	) {
+		let (a, b) = (self.peers()[a].id(), self.peers()[b].id());
+		simulator.set_link(a, b, conditions);
+		simulator.set_link(b, a, conditions);
+	}
+
 	/// Polls the testnet. Processes all the pending actions.
 	fn poll(&mut self, cx: &mut FutureContext) {
 		self.mut_peers(|peers| {
@@ -1031,6 +1077,12 @@ where
 					peer.network.on_block_finalized(notification.hash, notification.header);
 				}
 			}
+
+			// Move the simulated clock to the next delivery, once the data already delivered is
+			// consumed.
+			for simulator in peers.iter().filter_map(|peer| peer.network_simulator.as_ref()) {
+				simulator.advance_to_next_delivery();
+			}
 		});
 	}
 }
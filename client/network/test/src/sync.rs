@@ -1239,3 +1239,96 @@ fn syncs_huge_blocks() {
 	assert_eq!(net.peer(0).client.info().best_number, 33);
 	assert_eq!(net.peer(1).client.info().best_number, 33);
 }
+
+#[test]
+fn light_peer_queries_state_through_remote_backend() {
+	use sc_client_api::StorageProvider;
+	use sc_network_light::light_client_requests::sender::LightClientRequestSender;
+	use sc_rpc_api::{state::StateApiServer, DenyUnsafe};
+	use sp_core::storage::{well_known_keys, StorageKey};
+
+	sp_tracing::try_init_simple();
+	let mut net = TestNet::new(1);
+	net.add_full_peer_with_config(FullPeerConfig {
+		sync_mode: SyncMode::Light,
+		..Default::default()
+	});
+	let client = net.peer(1).client().as_client();
+	let (remote_backend, track_peers) = LightClientRequestSender::new(
+		&ProtocolId::from("test-protocol-name"),
+		None,
+		client.clone(),
+		net.peer(1).network_service().clone(),
+	)
+	.unwrap();
+	let mut track_peers = Box::pin(track_peers);
+
+	// Wait for the light peer to track the full peer.
+	block_on(futures::future::poll_fn::<(), _>(|cx| {
+		net.poll(cx);
+		let _ = track_peers.poll_unpin(cx);
+		if net.peer(1).num_peers() == 1 {
+			Poll::Ready(())
+		} else {
+			Poll::Pending
+		}
+	}));
+
+	// The state RPCs wait on the remote requests, which need the network to be polled.
+	let (state, _) = sc_rpc::state::new_light(
+		client,
+		Arc::new(sp_core::testing::TaskExecutor::new()),
+		Arc::new(remote_backend),
+		DenyUnsafe::No,
+	);
+	let mut storage = state.storage(StorageKey(well_known_keys::CODE.to_vec()), None);
+	let value = block_on(futures::future::poll_fn(|cx| {
+		net.poll(cx);
+		let _ = track_peers.poll_unpin(cx);
+		storage.poll_unpin(cx)
+	}))
+	.unwrap();
+
+	let code = net
+		.peer(0)
+		.client()
+		.as_client()
+		.storage(&BlockId::Number(0), &StorageKey(well_known_keys::CODE.to_vec()))
+		.unwrap();
+	assert!(code.is_some());
+	assert_eq!(value, code);
+}
+
+#[test]
+fn syncs_after_partition_heals() {
+	sp_tracing::try_init_simple();
+	let simulator = NetworkSimulator::new(0);
+	simulator.set_default_link(LinkConditions {
+		latency: Duration::from_millis(10),
+		loss: 0.01,
+		..Default::default()
+	});
+	let mut net = TestNet::new_simulated(3, &simulator);
+	net.block_until_connected();
+
+	net.partition_peers(&simulator, &[&[0], &[1, 2]]);
+	net.peer(0).push_blocks(10, false);
+	// Wait for the peers to notice the partition.
+	block_on(futures::future::poll_fn::<(), _>(|cx| {
+		net.poll(cx);
+		if (0..3).map(|i| net.peer(i).num_peers()).eq([0, 1, 1]) {
+			Poll::Ready(())
+		} else {
+			Poll::Pending
+		}
+	}));
+
+	assert_eq!(net.peer(1).client.info().best_number, 0);
+	assert_eq!(net.peer(2).client.info().best_number, 0);
+
+	simulator.heal();
+	net.block_until_sync();
+
+	assert_eq!(net.peer(1).client.info().best_number, 10);
+	assert_eq!(net.peer(2).client.info().best_number, 10);
+}
@@ -55,6 +55,8 @@ const DISCONNECT_REPUTATION_CHANGE: i32 = -256;
 /// Amount of time between the moment we disconnect from a node and the moment we remove it from
 /// the list.
 const FORGET_AFTER: Duration = Duration::from_secs(3600);
+/// Number of latest reputation changes of each node kept for [`Peerset::peer_scores`].
+const MAX_REPUTATION_CHANGES_HISTORY: usize = 8;
 
 #[derive(Debug)]
 enum Action {
@@ -218,6 +220,10 @@ impl From<u64> for IncomingIndex {
 pub struct PeersetConfig {
 	/// List of sets of nodes the peerset manages.
 	pub sets: Vec<SetConfig>,
+
+	/// Reputations of nodes known from a previous run, restored before the first slots are
+	/// allocated. See [`Peerset::reputations`].
+	pub reputations: Vec<(PeerId, i32)>,
 }
 
 /// Configuration for a single set of nodes.
@@ -245,6 +251,80 @@ pub struct SetConfig {
 	pub reserved_only: bool,
 }
 
+/// Moves a reputation towards zero by one second worth of decay.
+///
+/// We use `k = 0.98`, so we divide by `50`. With that value, it takes 34.3 seconds to reduce the
+/// reputation by half.
+fn reput_tick(reput: i32) -> i32 {
+	let mut diff = reput / 50;
+	if diff == 0 && reput < 0 {
+		diff = -1;
+	} else if diff == 0 && reput > 0 {
+		diff = 1;
+	}
+	reput.saturating_sub(diff)
+}
+
+/// Returns for how long a node with the given reputation is banned, that is how long it takes its
+/// reputation to decay back above [`BANNED_THRESHOLD`]. Returns `None` if it isn't banned.
+fn ban_duration(mut reputation: i32) -> Option<Duration> {
+	let mut secs = 0;
+	while reputation < BANNED_THRESHOLD {
+		reputation = reput_tick(reputation);
+		secs += 1;
+	}
+	(secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Returns the reputation that `reputation` decays to after `elapsed` time, at the same pace as
+/// the reputations of the nodes of a running [`Peerset`].
+pub fn decay_reputation(mut reputation: i32, elapsed: Duration) -> i32 {
+	for _ in 0..elapsed.as_secs() {
+		if reputation == 0 {
+			break
+		}
+		reputation = reput_tick(reputation);
+	}
+	reputation
+}
+
+/// State of the connection to a node in the context of a set.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+	/// The node is part of the set, but we are not connected to it.
+	NotConnected,
+	/// We are connected to the node through a connection it opened.
+	Inbound,
+	/// We are connected to the node through a connection we opened.
+	Outbound,
+}
+
+/// Membership of a node in a set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetMembership {
+	/// The set the node is part of.
+	pub set_id: SetId,
+	/// Whether we are connected to the node in this set.
+	pub state: ConnectionState,
+	/// Whether the node is a reserved node of this set.
+	pub reserved: bool,
+}
+
+/// Snapshot of what the peerset knows about a node. See [`Peerset::peer_scores`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerScore {
+	/// Identity of the node.
+	pub peer_id: PeerId,
+	/// Current reputation of the node.
+	pub reputation: i32,
+	/// Sets the node is part of.
+	pub sets: Vec<SetMembership>,
+	/// Latest reputation changes reported for the node, the most recent last.
+	pub reputation_changes: Vec<ReputationChange>,
+	/// For how long the node remains banned, if it is.
+	pub banned_for: Option<Duration>,
+}
+
 /// Side of the peer set manager owned by the network. In other words, the "receiving" side.
 ///
 /// Implements the `Stream` trait and can be polled for messages. The `Stream` never ends and never
@@ -270,6 +350,8 @@ pub struct Peerset {
 	/// Next time to do a periodic call to `alloc_slots` with all sets. This is done once per
 	/// second, to match the period of the reputation updates.
 	next_periodic_alloc_slots: Delay,
+	/// Latest reputation changes of the known nodes, the most recent last.
+	reputation_changes: HashMap<PeerId, VecDeque<ReputationChange>>,
 }
 
 impl Peerset {
@@ -297,9 +379,14 @@ impl Peerset {
 				created: now,
 				latest_time_update: now,
 				next_periodic_alloc_slots: Delay::new(Duration::new(0, 0)),
+				reputation_changes: HashMap::new(),
 			}
 		};
 
+		for (peer_id, reputation) in config.reputations {
+			peerset.data.peer_reputation(peer_id).set_reputation(reputation);
+		}
+
 		for (set, set_config) in config.sets.into_iter().enumerate() {
 			for node in set_config.reserved_nodes {
 				peerset.data.add_no_slot_node(set, node);
@@ -436,6 +523,12 @@ impl Peerset {
 		// We want reputations to be up-to-date before adjusting them.
 		self.update_time();
 
+		let changes = self.reputation_changes.entry(peer_id).or_default();
+		if changes.len() == MAX_REPUTATION_CHANGES_HISTORY {
+			changes.pop_front();
+		}
+		changes.push_back(change);
+
 		let mut reputation = self.data.peer_reputation(peer_id);
 		reputation.add_reputation(change.value);
 		if reputation.reputation() >= BANNED_THRESHOLD {
@@ -489,18 +582,6 @@ impl Peerset {
 		// empirically determine a value of `k` that looks correct.
 		for _ in 0..secs_diff {
 			for peer_id in self.data.peers().cloned().collect::<Vec<_>>() {
-				// We use `k = 0.98`, so we divide by `50`. With that value, it takes 34.3 seconds
-				// to reduce the reputation by half.
-				fn reput_tick(reput: i32) -> i32 {
-					let mut diff = reput / 50;
-					if diff == 0 && reput < 0 {
-						diff = -1;
-					} else if diff == 0 && reput > 0 {
-						diff = 1;
-					}
-					reput.saturating_sub(diff)
-				}
-
 				let mut peer_reputation = self.data.peer_reputation(peer_id);
 
 				let before = peer_reputation.reputation();
@@ -531,6 +612,11 @@ impl Peerset {
 				}
 			}
 		}
+
+		if secs_diff != 0 {
+			let data = &self.data;
+			self.reputation_changes.retain(|peer_id, _| data.contains(peer_id));
+		}
 	}
 
 	/// Try to fill available out slots with nodes for the given set.
@@ -713,10 +799,70 @@ impl Peerset {
 		})
 	}
 
+	/// Returns a snapshot of the reputation and set memberships of each known node.
+	pub fn peer_scores(&mut self) -> Vec<PeerScore> {
+		self.update_time();
+
+		self.data
+			.peers()
+			.cloned()
+			.collect::<Vec<_>>()
+			.into_iter()
+			.map(|peer_id| {
+				let reputation = self.data.peer_reputation(peer_id).reputation();
+				let sets = (0..self.data.num_sets())
+					.filter_map(|set_index| {
+						let state = match self.data.peer(set_index, &peer_id) {
+							peersstate::Peer::Connected(entry) if entry.is_inbound() =>
+								ConnectionState::Inbound,
+							peersstate::Peer::Connected(_) => ConnectionState::Outbound,
+							peersstate::Peer::NotConnected(_) => ConnectionState::NotConnected,
+							peersstate::Peer::Unknown(_) => return None,
+						};
+						Some(SetMembership {
+							set_id: SetId(set_index),
+							state,
+							reserved: self.reserved_nodes[set_index].0.contains(&peer_id),
+						})
+					})
+					.collect();
+
+				PeerScore {
+					peer_id,
+					reputation,
+					sets,
+					reputation_changes: self
+						.reputation_changes
+						.get(&peer_id)
+						.map(|changes| changes.iter().copied().collect())
+						.unwrap_or_default(),
+					banned_for: ban_duration(reputation),
+				}
+			})
+			.collect()
+	}
+
 	/// Returns the number of peers that we have discovered.
 	pub fn num_discovered_peers(&self) -> usize {
 		self.data.peers().len()
 	}
+
+	/// Returns the non-zero reputations of the known nodes, for example to restore them through
+	/// [`PeersetConfig::reputations`] after a restart.
+	pub fn reputations(&mut self) -> Vec<(PeerId, i32)> {
+		self.update_time();
+
+		self.data
+			.peers()
+			.cloned()
+			.collect::<Vec<_>>()
+			.into_iter()
+			.filter_map(|peer_id| {
+				let reputation = self.data.peer_reputation(peer_id).reputation();
+				(reputation != 0).then(|| (peer_id, reputation))
+			})
+			.collect()
+	}
 }
 
 impl Stream for Peerset {
@@ -777,8 +923,8 @@ pub enum DropReason {
 #[cfg(test)]
 mod tests {
 	use super::{
-		IncomingIndex, Message, Peerset, PeersetConfig, ReputationChange, SetConfig, SetId,
-		BANNED_THRESHOLD,
+		decay_reputation, ConnectionState, IncomingIndex, Message, Peerset, PeersetConfig,
+		ReputationChange, SetConfig, SetId, SetMembership, BANNED_THRESHOLD,
 	};
 	use futures::prelude::*;
 	use libp2p::PeerId;
@@ -812,6 +958,7 @@ mod tests {
 				reserved_nodes: Default::default(),
 				reserved_only: true,
 			}],
+			reputations: Vec::new(),
 		};
 
 		let (peerset, handle) = Peerset::from_config(config);
@@ -845,6 +992,7 @@ mod tests {
 				reserved_nodes: Default::default(),
 				reserved_only: false,
 			}],
+			reputations: Vec::new(),
 		};
 
 		let (mut peerset, _handle) = Peerset::from_config(config);
@@ -876,6 +1024,7 @@ mod tests {
 				reserved_nodes: Default::default(),
 				reserved_only: true,
 			}],
+			reputations: Vec::new(),
 		};
 
 		let (mut peerset, _) = Peerset::from_config(config);
@@ -897,6 +1046,7 @@ mod tests {
 				reserved_nodes: Default::default(),
 				reserved_only: false,
 			}],
+			reputations: Vec::new(),
 		};
 
 		let (mut peerset, _handle) = Peerset::from_config(config);
@@ -923,6 +1073,7 @@ mod tests {
 				reserved_nodes: Default::default(),
 				reserved_only: false,
 			}],
+			reputations: Vec::new(),
 		});
 
 		// We ban a node by setting its reputation under the threshold.
@@ -966,6 +1117,7 @@ mod tests {
 				reserved_nodes: Default::default(),
 				reserved_only: false,
 			}],
+			reputations: Vec::new(),
 		});
 
 		// We ban a node by setting its reputation under the threshold.
@@ -996,4 +1148,75 @@ mod tests {
 
 		futures::executor::block_on(fut);
 	}
+
+	#[test]
+	fn test_decay_reputation() {
+		assert_eq!(decay_reputation(1000, Duration::from_secs(0)), 1000);
+		assert_eq!(decay_reputation(1000, Duration::from_secs(1)), 980);
+		assert_eq!(decay_reputation(-1000, Duration::from_secs(1)), -980);
+		assert_eq!(decay_reputation(BANNED_THRESHOLD - 1, Duration::from_secs(3600)), 0);
+		assert_eq!(decay_reputation(i32::MAX, Duration::from_secs(3600)), 0);
+	}
+
+	#[test]
+	fn test_restored_reputations() {
+		let banned = PeerId::random();
+		let (mut peerset, _handle) = Peerset::from_config(PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 25,
+				out_peers: 25,
+				bootnodes: vec![banned],
+				reserved_nodes: Default::default(),
+				reserved_only: false,
+			}],
+			reputations: vec![(banned, BANNED_THRESHOLD - 1)],
+		});
+
+		assert_eq!(peerset.reputations(), vec![(banned, BANNED_THRESHOLD - 1)]);
+
+		// The restored reputation prevents connecting to the bootnode, and rejects its incoming
+		// connections.
+		peerset.incoming(SetId::from(0), banned, IncomingIndex(1));
+		assert_messages(peerset, vec![Message::Reject(IncomingIndex(1))]);
+	}
+
+	#[test]
+	fn test_peer_scores() {
+		let reserved = PeerId::random();
+		let banned = PeerId::random();
+		let (mut peerset, _handle) = Peerset::from_config(PeersetConfig {
+			sets: vec![SetConfig {
+				in_peers: 25,
+				out_peers: 25,
+				bootnodes: vec![],
+				reserved_nodes: vec![reserved].into_iter().collect(),
+				reserved_only: false,
+			}],
+			reputations: Vec::new(),
+		});
+
+		peerset.on_report_peer(banned, ReputationChange::new(-10, "Bad"));
+		peerset.on_report_peer(banned, ReputationChange::new_fatal("Very bad"));
+
+		let scores = peerset.peer_scores();
+		let reserved_score = scores.iter().find(|score| score.peer_id == reserved).unwrap();
+		assert_eq!(
+			reserved_score.sets,
+			vec![SetMembership {
+				set_id: SetId::from(0),
+				state: ConnectionState::Outbound,
+				reserved: true
+			}],
+		);
+		assert!(reserved_score.banned_for.is_none());
+
+		let banned_score = scores.iter().find(|score| score.peer_id == banned).unwrap();
+		assert!(banned_score.sets.is_empty());
+		assert_eq!(banned_score.reputation, i32::MIN);
+		assert_eq!(
+			banned_score.reputation_changes,
+			vec![ReputationChange::new(-10, "Bad"), ReputationChange::new_fatal("Very bad")],
+		);
+		assert!(banned_score.banned_for.is_some());
+	}
 }
@@ -210,6 +210,11 @@ impl PeersState {
 		self.nodes.keys()
 	}
 
+	/// Returns `true` if we know of the given peer.
+	pub fn contains(&self, peer_id: &PeerId) -> bool {
+		self.nodes.contains_key(peer_id)
+	}
+
 	/// Returns the list of peers we are connected to in the context of a specific set.
 	///
 	/// # Panic
@@ -362,6 +367,14 @@ impl<'a> ConnectedPeer<'a> {
 		self.peer_id.into_owned()
 	}
 
+	/// Returns `true` if the connection was opened by the remote.
+	pub fn is_inbound(&self) -> bool {
+		self.state
+			.nodes
+			.get(&*self.peer_id)
+			.map_or(false, |node| matches!(node.sets[self.set], MembershipState::In))
+	}
+
 	/// Switches the peer to "not connected".
 	pub fn disconnect(self) -> NotConnectedPeer<'a> {
 		let is_no_slot_occupy = self.state.sets[self.set].no_slot_nodes.contains(&*self.peer_id);
@@ -0,0 +1,104 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Events of the `archive_storageDiff` subscription.
+
+use serde::{Deserialize, Serialize};
+use sp_core::storage::{StorageData, StorageKey};
+
+/// How a storage entry differs between the two compared blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageDiffType {
+	/// The entry only exists at the second block.
+	Added,
+	/// The entry exists at both blocks, with different values.
+	Modified,
+	/// The entry only exists at the first block.
+	Removed,
+}
+
+/// A storage entry which differs between the two compared blocks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageDiffItem {
+	/// Key of the entry.
+	pub key: StorageKey,
+	/// How the entry differs.
+	#[serde(rename = "type")]
+	pub diff_type: StorageDiffType,
+	/// Value at the second block, `None` if the entry was removed.
+	pub value: Option<StorageData>,
+}
+
+/// The subscription failed. No further event is generated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorEvent {
+	/// Reason of the failure.
+	pub error: String,
+}
+
+/// Event generated by the `archive_storageDiff` subscription.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "event")]
+pub enum StorageDiffEvent {
+	/// Entries which differ, following the ones of the previous events in key order.
+	Items {
+		/// The entries.
+		items: Vec<StorageDiffItem>,
+	},
+	/// All the entries were reported. No further event is generated.
+	Done,
+	/// The comparison failed.
+	Error(ErrorEvent),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn storage_diff_event_serialization() {
+		let event = StorageDiffEvent::Items {
+			items: vec![
+				StorageDiffItem {
+					key: StorageKey(vec![1]),
+					diff_type: StorageDiffType::Added,
+					value: Some(StorageData(vec![2])),
+				},
+				StorageDiffItem {
+					key: StorageKey(vec![3]),
+					diff_type: StorageDiffType::Removed,
+					value: None,
+				},
+			],
+		};
+		assert_eq!(
+			serde_json::to_string(&event).unwrap(),
+			r#"{"event":"items","items":[{"key":"0x01","type":"added","value":"0x02"},{"key":"0x03","type":"removed","value":null}]}"#,
+		);
+
+		let event = StorageDiffEvent::Error(ErrorEvent { error: "failed".into() });
+		assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"event":"error","error":"failed"}"#);
+
+		let decoded: StorageDiffEvent = serde_json::from_str(r#"{"event":"done"}"#).unwrap();
+		assert_eq!(decoded, StorageDiffEvent::Done);
+	}
+}
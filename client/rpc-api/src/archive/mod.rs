@@ -0,0 +1,58 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Substrate archive API.
+//!
+//! Queries of any block of the chain, available only on the nodes which keep every block and
+//! every state. The methods fail on the other nodes rather than returning partial results.
+
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use sp_core::{storage::StorageKey, Bytes};
+
+pub mod error;
+pub mod event;
+
+use event::StorageDiffEvent;
+
+#[rpc(client, server)]
+pub trait ArchiveApi<Hash> {
+	/// Get the SCALE-encoded header of a block.
+	#[method(name = "archive_header", blocking)]
+	fn header(&self, hash: Hash) -> RpcResult<Option<Bytes>>;
+
+	/// Get the SCALE-encoded extrinsics of a block.
+	#[method(name = "archive_body", blocking)]
+	fn body(&self, hash: Hash) -> RpcResult<Option<Vec<Bytes>>>;
+
+	/// Call a runtime function at a block.
+	#[method(name = "archive_call", blocking)]
+	fn call(&self, hash: Hash, function: String, call_parameters: Bytes) -> RpcResult<Bytes>;
+
+	/// Stream the storage entries which differ between the states of two blocks.
+	///
+	/// Only the keys starting with `prefix` are compared, if given. The entries are reported in
+	/// lexicographic order of their keys, with their value at `hash_b`. The child tries are not
+	/// compared entry by entry: a change in a child trie is reported as a change of its root,
+	/// under its `:child_storage:` key.
+	#[subscription(
+		name = "archive_storageDiff" => "archive_storageDiffEvent",
+		unsubscribe = "archive_unsubscribeStorageDiff",
+		item = StorageDiffEvent
+	)]
+	fn storage_diff(&self, hash_a: Hash, hash_b: Hash, prefix: Option<StorageKey>);
+}
@@ -18,10 +18,8 @@
 
 //! Error helpers for Chain RPC module.
 
-use jsonrpsee::{
-	core::Error as JsonRpseeError,
-	types::error::{CallError, ErrorObject},
-};
+use jsonrpsee::types::error::{ErrorObject, ErrorObjectOwned, CALL_EXECUTION_FAILED_CODE};
+
 /// Chain RPC Result type.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -39,12 +37,11 @@ pub enum Error {
 /// Base error code for all chain errors.
 const BASE_ERROR: i32 = 3000;
 
-impl From<Error> for JsonRpseeError {
+impl From<Error> for ErrorObjectOwned {
 	fn from(e: Error) -> Self {
 		match e {
-			Error::Other(message) =>
-				CallError::Custom(ErrorObject::owned(BASE_ERROR + 1, message, None::<()>)).into(),
-			e => e.into(),
+			Error::Other(message) => ErrorObject::owned(BASE_ERROR + 1, message, None::<()>),
+			e => ErrorObject::owned(CALL_EXECUTION_FAILED_CODE, e.to_string(), None::<()>),
 		}
 	}
 }
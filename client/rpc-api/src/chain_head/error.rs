@@ -0,0 +1,52 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Error helpers for the `chainHead` RPC module.
+
+use jsonrpsee::types::error::{ErrorObject, ErrorObjectOwned, CALL_EXECUTION_FAILED_CODE};
+
+/// ChainHead RPC Result type.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// ChainHead RPC errors.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	/// The follow subscription doesn't exist, or has been stopped.
+	#[error("Invalid follow subscription")]
+	InvalidSubscription,
+	/// The block isn't pinned by the follow subscription.
+	#[error("Block {0} isn't pinned by the follow subscription")]
+	InvalidBlock(String),
+	/// Client error.
+	#[error("Client error: {}", .0)]
+	Client(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Base error code for all chainHead errors.
+const BASE_ERROR: i32 = 7000;
+
+impl From<Error> for ErrorObjectOwned {
+	fn from(e: Error) -> Self {
+		match e {
+			Error::InvalidSubscription =>
+				ErrorObject::owned(BASE_ERROR + 1, e.to_string(), None::<()>),
+			Error::InvalidBlock(_) => ErrorObject::owned(BASE_ERROR + 2, e.to_string(), None::<()>),
+			e => ErrorObject::owned(CALL_EXECUTION_FAILED_CODE, e.to_string(), None::<()>),
+		}
+	}
+}
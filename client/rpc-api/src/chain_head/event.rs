@@ -0,0 +1,111 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Events of the `chainHead_follow` subscription.
+
+use serde::{Deserialize, Serialize};
+
+/// The first event of a follow subscription.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Initialized<Hash> {
+	/// Identifier of the follow subscription, to pass to the methods scoped to it.
+	pub subscription_id: String,
+	/// The finalized block the subscription starts from. It is pinned.
+	pub finalized_block_hash: Hash,
+}
+
+/// A block was imported. It is pinned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewBlock<Hash> {
+	/// Hash of the block.
+	pub block_hash: Hash,
+	/// Hash of the parent of the block.
+	pub parent_block_hash: Hash,
+}
+
+/// The best block changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BestBlockChanged<Hash> {
+	/// Hash of the new best block.
+	pub best_block_hash: Hash,
+}
+
+/// Blocks were finalized.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finalized<Hash> {
+	/// Hashes of the newly finalized blocks, in ascending order.
+	pub finalized_block_hashes: Vec<Hash>,
+	/// Hashes of the blocks which can't be finalized any more, and which are no longer reported.
+	///
+	/// These blocks, like the finalized ones, stay pinned until `chainHead_unpin` is called.
+	pub pruned_block_hashes: Vec<Hash>,
+}
+
+/// Event generated by the `chainHead_follow` subscription.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "event")]
+pub enum FollowEvent<Hash> {
+	/// The subscription started.
+	Initialized(Initialized<Hash>),
+	/// A block was imported.
+	NewBlock(NewBlock<Hash>),
+	/// The best block changed.
+	BestBlockChanged(BestBlockChanged<Hash>),
+	/// Blocks were finalized.
+	Finalized(Finalized<Hash>),
+	/// The subscription was stopped by the node, for example because too many blocks are pinned.
+	///
+	/// All its blocks are unpinned, and no further event is generated.
+	Stop,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn follow_event_serialization() {
+		let event: FollowEvent<String> = FollowEvent::Initialized(Initialized {
+			subscription_id: "sub".into(),
+			finalized_block_hash: "0x1".into(),
+		});
+		assert_eq!(
+			serde_json::to_string(&event).unwrap(),
+			r#"{"event":"initialized","subscriptionId":"sub","finalizedBlockHash":"0x1"}"#,
+		);
+
+		let event: FollowEvent<String> = FollowEvent::Finalized(Finalized {
+			finalized_block_hashes: vec!["0x1".into()],
+			pruned_block_hashes: vec![],
+		});
+		assert_eq!(
+			serde_json::to_string(&event).unwrap(),
+			r#"{"event":"finalized","finalizedBlockHashes":["0x1"],"prunedBlockHashes":[]}"#,
+		);
+
+		let event: FollowEvent<String> = FollowEvent::Stop;
+		assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"event":"stop"}"#);
+		let decoded: FollowEvent<String> = serde_json::from_str(r#"{"event":"stop"}"#).unwrap();
+		assert_eq!(decoded, event);
+	}
+}
@@ -0,0 +1,84 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Substrate chain head API.
+//!
+//! Unlike the `chain_subscribe*Heads` subscriptions, the blocks reported by `chainHead_follow`
+//! are pinned until the client unpins them, so that their body and state can still be queried
+//! after they have been finalized or discarded.
+
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use sp_core::{
+	storage::{PrefixedStorageKey, StorageData, StorageKey},
+	Bytes,
+};
+
+pub mod error;
+pub mod event;
+
+use event::FollowEvent;
+
+#[rpc(client, server)]
+pub trait ChainHeadApi<Hash> {
+	/// Follow the chain, starting from the last finalized block.
+	///
+	/// The first event reports the identifier to pass to the other `chainHead` methods, which
+	/// only accept the blocks reported by this subscription and not unpinned yet.
+	///
+	/// This subscription is unsafe, as its pinned blocks can't be pruned until they are unpinned.
+	#[subscription(
+		name = "chainHead_follow" => "chainHead_followEvent",
+		unsubscribe = "chainHead_unfollow",
+		item = FollowEvent<Hash>
+	)]
+	fn follow(&self);
+
+	/// Get the SCALE-encoded header of a pinned block.
+	#[method(name = "chainHead_header", blocking)]
+	fn header(&self, follow_subscription: String, hash: Hash) -> RpcResult<Option<Bytes>>;
+
+	/// Get the SCALE-encoded extrinsics of a pinned block.
+	///
+	/// Returns `None` if the body was already pruned when the block got pinned.
+	#[method(name = "chainHead_body", blocking)]
+	fn body(&self, follow_subscription: String, hash: Hash) -> RpcResult<Option<Vec<Bytes>>>;
+
+	/// Read a storage value at a pinned block, in the child trie `child_key` if given.
+	#[method(name = "chainHead_storage", blocking)]
+	fn storage(
+		&self,
+		follow_subscription: String,
+		hash: Hash,
+		key: StorageKey,
+		child_key: Option<PrefixedStorageKey>,
+	) -> RpcResult<Option<StorageData>>;
+
+	/// Call a runtime function at a pinned block.
+	#[method(name = "chainHead_call", blocking)]
+	fn call(
+		&self,
+		follow_subscription: String,
+		hash: Hash,
+		function: String,
+		call_parameters: Bytes,
+	) -> RpcResult<Bytes>;
+
+	/// Unpin a block reported by the follow subscription.
+	#[method(name = "chainHead_unpin", blocking)]
+	fn unpin(&self, follow_subscription: String, hash: Hash) -> RpcResult<()>;
+}
@@ -26,8 +26,10 @@ mod policy;
 
 pub use policy::DenyUnsafe;
 
+pub mod archive;
 pub mod author;
 pub mod chain;
+pub mod chain_head;
 pub mod child_state;
 pub mod dev;
 pub mod offchain;
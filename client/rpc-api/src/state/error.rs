@@ -18,10 +18,7 @@
 
 //! State RPC errors.
 
-use jsonrpsee::{
-	core::Error as JsonRpseeError,
-	types::error::{CallError, ErrorObject},
-};
+use jsonrpsee::types::error::{ErrorObject, ErrorObjectOwned, CALL_EXECUTION_FAILED_CODE};
 /// State RPC Result type.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -49,6 +46,15 @@ pub enum Error {
 		/// Maximum allowed value
 		max: u32,
 	},
+	/// The storage changes of the requested blocks are not indexed.
+	#[error("Storage changes are not indexed: {}", .0)]
+	ChangesNotIndexed(String),
+	/// The response would exceed the maximum response size.
+	#[error("Response exceeds the maximum size of {} bytes, query less data at once", .max)]
+	ResponseTooLarge {
+		/// Maximum size of a response, in bytes.
+		max: usize,
+	},
 	/// Call to an unsafe RPC was denied.
 	#[error(transparent)]
 	UnsafeRpcCalled(#[from] crate::policy::UnsafeRpcError),
@@ -57,16 +63,18 @@ pub enum Error {
 /// Base code for all state errors.
 const BASE_ERROR: i32 = 4000;
 
-impl From<Error> for JsonRpseeError {
+impl From<Error> for ErrorObjectOwned {
 	fn from(e: Error) -> Self {
 		match e {
 			Error::InvalidBlockRange { .. } =>
-				CallError::Custom(ErrorObject::owned(BASE_ERROR + 1, e.to_string(), None::<()>))
-					.into(),
+				ErrorObject::owned(BASE_ERROR + 1, e.to_string(), None::<()>),
 			Error::InvalidCount { .. } =>
-				CallError::Custom(ErrorObject::owned(BASE_ERROR + 2, e.to_string(), None::<()>))
-					.into(),
-			e => Self::to_call_error(e),
+				ErrorObject::owned(BASE_ERROR + 2, e.to_string(), None::<()>),
+			Error::ChangesNotIndexed(_) =>
+				ErrorObject::owned(BASE_ERROR + 3, e.to_string(), None::<()>),
+			Error::ResponseTooLarge { .. } =>
+				ErrorObject::owned(BASE_ERROR + 4, e.to_string(), None::<()>),
+			e => ErrorObject::owned(CALL_EXECUTION_FAILED_CODE, e.to_string(), None::<()>),
 		}
 	}
 }
@@ -19,10 +19,13 @@
 //! Substrate state API helpers.
 
 use serde::{Deserialize, Serialize};
-use sp_core::Bytes;
+use sp_core::{
+	storage::{StorageData, StorageKey},
+	Bytes,
+};
 
 /// ReadProof struct returned by the RPC
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ReadProof<Hash> {
 	/// Block hash used to generate the proof
@@ -30,3 +33,13 @@ pub struct ReadProof<Hash> {
 	/// A proof used to prove that storage entries are included in the storage trie
 	pub proof: Vec<Bytes>,
 }
+
+/// Page of storage entries returned by `state_getStoragePaged`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoragePage {
+	/// Keys and values of the entries, in lexicographic order of the keys.
+	pub entries: Vec<(StorageKey, StorageData)>,
+	/// Key to pass as `start_key` to get the next page, `None` if this is the last page.
+	pub next_start_key: Option<StorageKey>,
+}
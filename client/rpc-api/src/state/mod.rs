@@ -28,21 +28,25 @@ use sp_version::RuntimeVersion;
 pub mod error;
 pub mod helpers;
 
-pub use self::helpers::ReadProof;
+pub use self::helpers::{ReadProof, StoragePage};
 
 /// Substrate state API
 #[rpc(client, server)]
 pub trait StateApi<Hash> {
 	/// Call a contract at a block's state.
-	#[method(name = "state_call", aliases = ["state_callAt"], blocking)]
-	fn call(&self, name: String, bytes: Bytes, hash: Option<Hash>) -> RpcResult<Bytes>;
+	#[method(name = "state_call", aliases = ["state_callAt"])]
+	async fn call(&self, name: String, bytes: Bytes, hash: Option<Hash>) -> RpcResult<Bytes>;
 
 	/// Returns the keys with prefix, leave empty to get all the keys.
+	///
+	/// Fails if the keys exceed the maximum response size.
 	#[method(name = "state_getKeys", blocking)]
 	#[deprecated(since = "2.0.0", note = "Please use `getKeysPaged` with proper paging support")]
 	fn storage_keys(&self, prefix: StorageKey, hash: Option<Hash>) -> RpcResult<Vec<StorageKey>>;
 
-	/// Returns the keys with prefix, leave empty to get all the keys
+	/// Returns the keys with prefix along with their values, leave empty to get all the pairs.
+	///
+	/// Fails if the pairs exceed the maximum response size, see `state_getStoragePaged`.
 	#[method(name = "state_getPairs", blocking)]
 	fn storage_pairs(
 		&self,
@@ -62,31 +66,46 @@ pub trait StateApi<Hash> {
 		hash: Option<Hash>,
 	) -> RpcResult<Vec<StorageKey>>;
 
+	/// Returns the keys with prefix along with their values, with pagination support.
+	/// Up to `count` entries will be returned, fewer if they would exceed the maximum response
+	/// size. If `start_key` is passed, return the next entries in lexicographic order of the keys.
+	/// The `next_start_key` of the page is the `start_key` of the next one.
+	#[method(name = "state_getStoragePaged", blocking)]
+	fn storage_paged(
+		&self,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+		hash: Option<Hash>,
+	) -> RpcResult<StoragePage>;
+
 	/// Returns a storage entry at a specific block's state.
-	#[method(name = "state_getStorage", aliases = ["state_getStorageAt"], blocking)]
-	fn storage(&self, key: StorageKey, hash: Option<Hash>) -> RpcResult<Option<StorageData>>;
+	#[method(name = "state_getStorage", aliases = ["state_getStorageAt"])]
+	async fn storage(&self, key: StorageKey, hash: Option<Hash>) -> RpcResult<Option<StorageData>>;
 
 	/// Returns the hash of a storage entry at a block's state.
-	#[method(name = "state_getStorageHash", aliases = ["state_getStorageHashAt"], blocking)]
-	fn storage_hash(&self, key: StorageKey, hash: Option<Hash>) -> RpcResult<Option<Hash>>;
+	#[method(name = "state_getStorageHash", aliases = ["state_getStorageHashAt"])]
+	async fn storage_hash(&self, key: StorageKey, hash: Option<Hash>) -> RpcResult<Option<Hash>>;
 
 	/// Returns the size of a storage entry at a block's state.
-	#[method(name = "state_getStorageSize", aliases = ["state_getStorageSizeAt"], blocking)]
-	fn storage_size(&self, key: StorageKey, hash: Option<Hash>) -> RpcResult<Option<u64>>;
+	#[method(name = "state_getStorageSize", aliases = ["state_getStorageSizeAt"])]
+	async fn storage_size(&self, key: StorageKey, hash: Option<Hash>) -> RpcResult<Option<u64>>;
 
 	/// Returns the runtime metadata as an opaque blob.
-	#[method(name = "state_getMetadata", blocking)]
-	fn metadata(&self, hash: Option<Hash>) -> RpcResult<Bytes>;
+	#[method(name = "state_getMetadata")]
+	async fn metadata(&self, hash: Option<Hash>) -> RpcResult<Bytes>;
 
 	/// Get the runtime version.
-	#[method(name = "state_getRuntimeVersion", aliases = ["chain_getRuntimeVersion"], blocking)]
-	fn runtime_version(&self, hash: Option<Hash>) -> RpcResult<RuntimeVersion>;
+	#[method(name = "state_getRuntimeVersion", aliases = ["chain_getRuntimeVersion"])]
+	async fn runtime_version(&self, hash: Option<Hash>) -> RpcResult<RuntimeVersion>;
 
 	/// Query historical storage entries (by key) starting from a block given as the second
 	/// parameter.
 	///
 	/// NOTE This first returned result contains the initial state of storage for all keys.
 	/// Subsequent values in the vector represent changes to the previous state (diffs).
+	///
+	/// Fails if the changes exceed the maximum response size.
 	#[method(name = "state_queryStorage", blocking)]
 	fn query_storage(
 		&self,
@@ -95,9 +114,18 @@ pub trait StateApi<Hash> {
 		hash: Option<Hash>,
 	) -> RpcResult<Vec<StorageChangeSet<Hash>>>;
 
+	/// Returns the numbers of the blocks after the block given as the second parameter, up to the
+	/// block given as the third parameter or the best block, which changed the value of `key`.
+	///
+	/// NOTE Requires the node to index the storage changes of the blocks in the range.
+	#[method(name = "state_keyHistory", blocking)]
+	fn key_history(&self, key: StorageKey, block: Hash, hash: Option<Hash>) -> RpcResult<Vec<u64>>;
+
 	/// Query storage entries (by key) starting at block hash given as the second parameter.
-	#[method(name = "state_queryStorageAt", blocking)]
-	fn query_storage_at(
+	///
+	/// Fails if the entries exceed the maximum response size.
+	#[method(name = "state_queryStorageAt")]
+	async fn query_storage_at(
 		&self,
 		keys: Vec<StorageKey>,
 		at: Option<Hash>,
@@ -19,10 +19,7 @@
 //! System RPC module errors.
 
 use crate::system::helpers::Health;
-use jsonrpsee::{
-	core::Error as JsonRpseeError,
-	types::error::{CallError, ErrorObject},
-};
+use jsonrpsee::types::error::{ErrorObject, ErrorObjectOwned};
 
 /// System RPC Result type.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -45,17 +42,13 @@ const NOT_HEALTHY_ERROR: i32 = BASE_ERROR + 1;
 // Peer argument is malformatted.
 const MALFORMATTED_PEER_ARG_ERROR: i32 = BASE_ERROR + 2;
 
-impl From<Error> for JsonRpseeError {
+impl From<Error> for ErrorObjectOwned {
 	fn from(e: Error) -> Self {
 		match e {
 			Error::NotHealthy(ref h) =>
-				CallError::Custom(ErrorObject::owned(NOT_HEALTHY_ERROR, e.to_string(), Some(h))),
-			Error::MalformattedPeerArg(e) => CallError::Custom(ErrorObject::owned(
-				MALFORMATTED_PEER_ARG_ERROR + 2,
-				e,
-				None::<()>,
-			)),
+				ErrorObject::owned(NOT_HEALTHY_ERROR, e.to_string(), Some(h)),
+			Error::MalformattedPeerArg(e) =>
+				ErrorObject::owned(MALFORMATTED_PEER_ARG_ERROR + 2, e, None::<()>),
 		}
-		.into()
 	}
 }
@@ -38,7 +38,7 @@ pub struct SystemInfo {
 }
 
 /// Health struct returned by the RPC
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Health {
 	/// Number of connected peers
@@ -58,7 +58,7 @@ impl fmt::Display for Health {
 }
 
 /// Network Peer information
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PeerInfo<Hash, Number> {
 	/// Peer ID
@@ -71,8 +71,72 @@ pub struct PeerInfo<Hash, Number> {
 	pub best_number: Number,
 }
 
+/// Reputation and connection state of a peer known to the peer set manager.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerScore {
+	/// Peer ID
+	pub peer_id: String,
+	/// Current reputation of the peer
+	pub reputation: i32,
+	/// Peer sets the peer is part of
+	pub sets: Vec<PeerSetMembership>,
+	/// Latest reputation changes reported for the peer, the most recent last
+	pub reputation_changes: Vec<PeerReputationChange>,
+	/// Number of seconds until the peer is no longer banned, if it is banned
+	pub ban_expires_in: Option<u64>,
+}
+
+/// Block download throughput of a peer we sync with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPeerThroughput {
+	/// Peer ID
+	pub peer_id: String,
+	/// Average number of blocks downloaded per second
+	pub blocks_per_second: f64,
+	/// Average number of bytes of block data downloaded per second
+	pub bytes_per_second: f64,
+	/// Average time between a block request and its response, in milliseconds
+	pub latency_ms: u64,
+	/// Number of blocks requested at once from the peer
+	pub request_size: u32,
+}
+
+/// Membership of a peer in a peer set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerSetMembership {
+	/// Name of the notifications protocol of the set
+	pub protocol: String,
+	/// Direction of the connection to the peer, if we are connected to it
+	pub direction: Option<ConnectionDirection>,
+	/// Is the peer a reserved peer of the set
+	pub reserved: bool,
+}
+
+/// Direction of a connection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionDirection {
+	/// The connection was opened by the peer
+	Inbound,
+	/// The connection was opened by us
+	Outbound,
+}
+
+/// Reputation change reported for a peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerReputationChange {
+	/// Reputation delta
+	pub value: i32,
+	/// Reason for the change
+	pub reason: String,
+}
+
 /// The role the node is running as
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum NodeRole {
 	/// The node is a full node
 	Full,
@@ -81,7 +145,7 @@ pub enum NodeRole {
 }
 
 /// The state of the syncing of the node.
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SyncState<Number> {
 	/// Height of the block at which syncing started.
@@ -123,6 +187,25 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn should_serialize_peer_score() {
+		assert_eq!(
+			::serde_json::to_string(&PeerScore {
+				peer_id: "2".into(),
+				reputation: -10,
+				sets: vec![PeerSetMembership {
+					protocol: "/foo".into(),
+					direction: Some(ConnectionDirection::Inbound),
+					reserved: false,
+				}],
+				reputation_changes: vec![PeerReputationChange { value: -10, reason: "a".into() }],
+				ban_expires_in: None,
+			})
+			.unwrap(),
+			r#"{"peerId":"2","reputation":-10,"sets":[{"protocol":"/foo","direction":"inbound","reserved":false}],"reputationChanges":[{"value":-10,"reason":"a"}],"banExpiresIn":null}"#,
+		);
+	}
+
 	#[test]
 	fn should_serialize_sync_state() {
 		assert_eq!(
@@ -23,7 +23,10 @@ use jsonrpsee::{
 	proc_macros::rpc,
 };
 
-pub use self::helpers::{Health, NodeRole, PeerInfo, SyncState, SystemInfo};
+pub use self::helpers::{
+	ConnectionDirection, Health, NodeRole, PeerInfo, PeerReputationChange, PeerScore,
+	PeerSetMembership, SyncPeerThroughput, SyncState, SystemInfo,
+};
 
 pub mod error;
 pub mod helpers;
@@ -83,6 +86,24 @@ pub trait SystemApi<Hash, Number> {
 	#[method(name = "system_unstable_networkState")]
 	async fn system_network_state(&self) -> RpcResult<JsonValue>;
 
+	/// Returns the reputation, peer set memberships, latest reputation changes and ban expiry of
+	/// every peer known to the peer set manager.
+	#[method(name = "system_peerScores")]
+	async fn system_peer_scores(&self) -> RpcResult<Vec<PeerScore>>;
+
+	/// Returns the block download throughput of the peers we sync with, once blocks were
+	/// downloaded from them.
+	#[method(name = "system_syncPeerThroughput")]
+	async fn system_sync_peer_throughput(&self) -> RpcResult<Vec<SyncPeerThroughput>>;
+
+	/// Peer scores subscription. Sends the scores of `system_peerScores` every few seconds.
+	#[subscription(
+		name = "system_subscribePeerScores" => "system_peerScoresUpdate",
+		unsubscribe = "system_unsubscribePeerScores",
+		item = Vec<PeerScore>
+	)]
+	fn system_subscribe_peer_scores(&self);
+
 	/// Adds a reserved peer. Returns the empty string or an error. The string
 	/// parameter should encode a `p2p` multiaddr.
 	///
@@ -17,20 +17,42 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 //! Substrate RPC servers.
+//!
+//! A single server answers both HTTP and WebSocket requests on each of its addresses.
 
 #![warn(missing_docs)]
 
+use crate::rate_limit::{Limits, RateLimitLayer};
+use http::{header, HeaderMap, HeaderValue, Method};
 use jsonrpsee::{
-	http_server::{AccessControlBuilder, HttpServerBuilder, HttpServerHandle},
-	ws_server::{WsServerBuilder, WsServerHandle},
-	RpcModule,
+	server::{
+		http::response,
+		middleware::{
+			http::{HostFilterLayer, ProxyGetRequestLayer},
+			rpc::RpcServiceBuilder,
+		},
+		serve_with_graceful_shutdown, stop_channel, ws, BatchRequestConfig, PingConfig,
+		ServerConfig, ServerConfigBuilder, ServerHandle,
+	},
+	Methods, RpcModule,
 };
-use std::{error::Error as StdError, net::SocketAddr};
+use std::{
+	error::Error as StdError, future::Future, net::SocketAddr, pin::Pin, sync::Arc, time::Duration,
+};
+use tokio::net::TcpListener;
+use tower::Service;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
-pub use crate::middleware::{RpcMetrics, RpcMiddleware};
-pub use jsonrpsee::core::{
-	id_providers::{RandomIntegerIdProvider, RandomStringIdProvider},
-	traits::IdProvider,
+pub use crate::{
+	middleware::{RpcMetrics, RpcMiddleware},
+	rate_limit::{RateLimitConfig, RATE_LIMITED_ERROR_CODE},
+};
+pub use jsonrpsee::{
+	core::{
+		id_providers::{RandomIntegerIdProvider, RandomStringIdProvider},
+		traits::IdProvider,
+	},
+	types::error::TOO_BIG_BATCH_REQUEST_CODE as BATCH_TOO_LARGE_ERROR_CODE,
 };
 
 const MEGABYTE: usize = 1024 * 1024;
@@ -45,11 +67,32 @@ const WS_MAX_CONNECTIONS: usize = 100;
 const WS_MAX_SUBS_PER_CONN: usize = 1024;
 
 pub mod middleware;
+mod rate_limit;
 
 /// Type alias for http server
-pub type HttpServer = HttpServerHandle;
+pub type HttpServer = Server;
 /// Type alias for ws server
-pub type WsServer = WsServerHandle;
+pub type WsServer = Server;
+
+/// RPC server, stopped when dropped.
+pub struct Server {
+	handle: ServerHandle,
+	local_addrs: Vec<SocketAddr>,
+}
+
+impl Server {
+	/// Addresses the server listens on.
+	pub fn local_addrs(&self) -> &[SocketAddr] {
+		&self.local_addrs
+	}
+}
+
+impl Drop for Server {
+	fn drop(&mut self) {
+		// The server can only be stopped here.
+		let _ = self.handle.stop();
+	}
+}
 
 /// WebSocket specific settings on the server.
 pub struct WsConfig {
@@ -63,18 +106,32 @@ pub struct WsConfig {
 	pub max_payload_out_mb: Option<usize>,
 }
 
-impl WsConfig {
-	// Deconstructs the config to get the finalized inner values.
-	//
-	// `Payload size` or `max subs per connection` bigger than u32::MAX will be truncated.
-	fn deconstruct(self) -> (u32, u32, u64, u32) {
-		let max_conns = self.max_connections.unwrap_or(WS_MAX_CONNECTIONS) as u64;
-		let max_payload_in_mb = payload_size_or_default(self.max_payload_in_mb) as u32;
-		let max_payload_out_mb = payload_size_or_default(self.max_payload_out_mb) as u32;
-		let max_subs_per_conn = self.max_subs_per_conn.unwrap_or(WS_MAX_SUBS_PER_CONN) as u32;
-
-		(max_payload_in_mb, max_payload_out_mb, max_conns, max_subs_per_conn)
-	}
+/// Settings of an RPC server serving both HTTP and WebSocket.
+pub struct Config<'a, M: Send + Sync + 'static> {
+	/// Addresses to listen on.
+	pub addrs: &'a [SocketAddr],
+	/// Allowed origins, all of them if `None`.
+	pub cors: Option<&'a Vec<String>>,
+	/// Maximum number of WebSocket connections and HTTP requests being served together.
+	pub max_connections: Option<usize>,
+	/// Maximum subscriptions per connection.
+	pub max_subs_per_conn: Option<usize>,
+	/// Maximum rpc request payload size.
+	pub max_payload_in_mb: Option<usize>,
+	/// Maximum rpc response payload size.
+	pub max_payload_out_mb: Option<usize>,
+	/// Maximum number of calls in a batch.
+	pub max_batch_len: Option<usize>,
+	/// Call-rate limits.
+	pub rate_limit: RateLimitConfig,
+	/// Metrics.
+	pub metrics: Option<RpcMetrics>,
+	/// Subscription ID provider, random strings of 16 characters if `None`.
+	pub id_provider: Option<Box<dyn IdProvider>>,
+	/// RPC API.
+	pub rpc_api: RpcModule<M>,
+	/// Tokio runtime handle.
+	pub tokio_handle: tokio::runtime::Handle,
 }
 
 /// Start HTTP server listening on given address.
@@ -86,46 +143,30 @@ pub async fn start_http<M: Send + Sync + 'static>(
 	metrics: Option<RpcMetrics>,
 	rpc_api: RpcModule<M>,
 	rt: tokio::runtime::Handle,
-) -> Result<HttpServerHandle, Box<dyn StdError + Send + Sync>> {
-	let max_payload_in = payload_size_or_default(max_payload_in_mb);
-	let max_payload_out = payload_size_or_default(max_payload_out_mb);
-
-	let mut acl = AccessControlBuilder::new();
-
-	if let Some(cors) = cors {
-		// Whitelist listening address.
-		// NOTE: set_allowed_hosts will whitelist both ports but only one will used.
-		acl = acl.set_allowed_hosts(format_allowed_hosts(&addrs[..]))?;
-		acl = acl.set_allowed_origins(cors)?;
-	};
-
-	let builder = HttpServerBuilder::new()
-		.max_request_body_size(max_payload_in as u32)
-		.max_response_body_size(max_payload_out as u32)
-		.set_access_control(acl.build())
-		.health_api("/health", "system_health")?
-		.custom_tokio_runtime(rt);
-
-	let rpc_api = build_rpc_api(rpc_api);
-	let (handle, addr) = if let Some(metrics) = metrics {
-		let middleware = RpcMiddleware::new(metrics, "http".into());
-		let builder = builder.set_middleware(middleware);
-		let server = builder.build(&addrs[..]).await?;
-		let addr = server.local_addr();
-		(server.start(rpc_api)?, addr)
-	} else {
-		let server = builder.build(&addrs[..]).await?;
-		let addr = server.local_addr();
-		(server.start(rpc_api)?, addr)
+) -> Result<HttpServer, Box<dyn StdError + Send + Sync>> {
+	let config = Config {
+		addrs: &addrs[..],
+		cors,
+		max_connections: None,
+		max_subs_per_conn: None,
+		max_payload_in_mb,
+		max_payload_out_mb,
+		max_batch_len: None,
+		rate_limit: Default::default(),
+		metrics,
+		id_provider: None,
+		rpc_api,
+		tokio_handle: rt,
 	};
+	let server = start(config, ServerConfig::builder().http_only()).await?;
 
 	log::info!(
 		"Running JSON-RPC HTTP server: addr={}, allowed origins={:?}",
-		addr.map_or_else(|_| "unknown".to_string(), |a| a.to_string()),
+		format_addrs(server.local_addrs()),
 		cors
 	);
 
-	Ok(handle)
+	Ok(server)
 }
 
 /// Start WS server listening on given address.
@@ -137,54 +178,246 @@ pub async fn start_ws<M: Send + Sync + 'static>(
 	rpc_api: RpcModule<M>,
 	rt: tokio::runtime::Handle,
 	id_provider: Option<Box<dyn IdProvider>>,
-) -> Result<WsServerHandle, Box<dyn StdError + Send + Sync>> {
-	let (max_payload_in, max_payload_out, max_connections, max_subs_per_conn) =
-		ws_config.deconstruct();
+) -> Result<WsServer, Box<dyn StdError + Send + Sync>> {
+	let config = Config {
+		addrs: &addrs[..],
+		cors,
+		max_connections: ws_config.max_connections,
+		max_subs_per_conn: ws_config.max_subs_per_conn,
+		max_payload_in_mb: ws_config.max_payload_in_mb,
+		max_payload_out_mb: ws_config.max_payload_out_mb,
+		max_batch_len: None,
+		rate_limit: Default::default(),
+		metrics,
+		id_provider,
+		rpc_api,
+		tokio_handle: rt,
+	};
+	let server = start(config, ServerConfig::builder().ws_only()).await?;
 
-	let mut acl = AccessControlBuilder::new();
+	log::info!(
+		"Running JSON-RPC WS server: addr={}, allowed origins={:?}",
+		format_addrs(server.local_addrs()),
+		cors
+	);
 
-	if let Some(cors) = cors {
-		// Whitelist listening address.
-		// NOTE: set_allowed_hosts will whitelist both ports but only one will used.
-		acl = acl.set_allowed_hosts(format_allowed_hosts(&addrs[..]))?;
-		acl = acl.set_allowed_origins(cors)?;
+	Ok(server)
+}
+
+/// Start the RPC server, serving both HTTP and WebSocket on each of the given addresses.
+///
+/// An address already in use is replaced by a random port on the same interface. The batches of
+/// more than `max_batch_len` calls are answered with a [`BATCH_TOO_LARGE_ERROR_CODE`] error, and
+/// the calls exceeding the `rate_limit` with a [`RATE_LIMITED_ERROR_CODE`] error.
+pub async fn start_server<M: Send + Sync + 'static>(
+	config: Config<'_, M>,
+) -> Result<Server, Box<dyn StdError + Send + Sync>> {
+	let cors = config.cors;
+	let server = start(config, ServerConfig::builder()).await?;
+
+	log::info!(
+		"Running JSON-RPC server: addr={}, allowed origins={:?}",
+		format_addrs(server.local_addrs()),
+		cors
+	);
+
+	Ok(server)
+}
+
+async fn start<M: Send + Sync + 'static>(
+	config: Config<'_, M>,
+	server_config: ServerConfigBuilder,
+) -> Result<Server, Box<dyn StdError + Send + Sync>> {
+	let Config {
+		addrs,
+		cors,
+		max_connections,
+		max_subs_per_conn,
+		max_payload_in_mb,
+		max_payload_out_mb,
+		max_batch_len,
+		rate_limit,
+		metrics,
+		id_provider,
+		rpc_api,
+		tokio_handle,
+	} = config;
+
+	let mut listeners = Vec::with_capacity(addrs.len());
+	let mut local_addrs = Vec::with_capacity(addrs.len());
+	for addr in addrs {
+		let listener = bind(*addr).await?;
+		local_addrs.push(listener.local_addr()?);
+		listeners.push(listener);
+	}
+
+	// `Payload size` or `max subs per connection` bigger than u32::MAX will be truncated.
+	let max_payload_out = payload_size_or_default(max_payload_out_mb);
+	let batch_config = match max_batch_len {
+		Some(len) => BatchRequestConfig::Limit(len as u32),
+		None => BatchRequestConfig::Unlimited,
+	};
+	let mut server_config = server_config
+		.max_request_body_size(payload_size_or_default(max_payload_in_mb) as u32)
+		.max_response_body_size(max_payload_out as u32)
+		.max_connections(max_connections.unwrap_or(WS_MAX_CONNECTIONS) as u32)
+		.max_subscriptions_per_connection(max_subs_per_conn.unwrap_or(WS_MAX_SUBS_PER_CONN) as u32)
+		.set_batch_request_config(batch_config)
+		.enable_ws_ping(PingConfig::new().ping_interval(Duration::from_secs(30)))
+		.custom_tokio_runtime(tokio_handle.clone());
+	server_config = match id_provider {
+		Some(provider) => server_config.set_id_provider(provider),
+		None => server_config.set_id_provider(RandomStringIdProvider::new(16)),
 	};
 
-	let mut builder = WsServerBuilder::new()
-		.max_request_body_size(max_payload_in)
-		.max_response_body_size(max_payload_out)
-		.max_connections(max_connections)
-		.max_subscriptions_per_connection(max_subs_per_conn)
-		.ping_interval(std::time::Duration::from_secs(30))
-		.custom_tokio_runtime(rt)
-		.set_access_control(acl.build());
-
-	if let Some(provider) = id_provider {
-		builder = builder.set_id_provider(provider);
-	} else {
-		builder = builder.set_id_provider(RandomStringIdProvider::new(16));
+	// Whitelist listening address.
+	let host_filter = match cors {
+		Some(_) => Some(HostFilterLayer::new(format_allowed_hosts(&local_addrs))?),
+		None => None,
 	};
+	let http_middleware = tower::ServiceBuilder::new()
+		.option_layer(host_filter)
+		// Proxy `GET /health` requests to the internal `system_health` method.
+		.layer(ProxyGetRequestLayer::new([("/health", "system_health")])?)
+		.layer(try_into_cors(cors)?);
+	let service_builder = jsonrpsee::server::Server::builder()
+		.set_config(server_config.build())
+		.set_http_middleware(http_middleware)
+		.to_service_builder();
 
 	let rpc_api = build_rpc_api(rpc_api);
-	let (handle, addr) = if let Some(metrics) = metrics {
-		let middleware = RpcMiddleware::new(metrics, "ws".into());
-		let builder = builder.set_middleware(middleware);
-		let server = builder.build(&addrs[..]).await?;
-		let addr = server.local_addr();
-		(server.start(rpc_api)?, addr)
-	} else {
-		let server = builder.build(&addrs[..]).await?;
-		let addr = server.local_addr();
-		(server.start(rpc_api)?, addr)
-	};
+	let limits = Arc::new(Limits::new(
+		rate_limit,
+		metrics.clone(),
+		rpc_api.method_names().map(ToOwned::to_owned).collect(),
+	));
+	let methods = Methods::from(rpc_api);
+	let allowed_origins = cors.cloned().map(Arc::new);
+	let (stop_handle, handle) = stop_channel();
+
+	for listener in listeners {
+		let service_builder = service_builder.clone();
+		let methods = methods.clone();
+		let stop_handle = stop_handle.clone();
+		let limits = limits.clone();
+		let metrics = metrics.clone();
+		let allowed_origins = allowed_origins.clone();
+
+		tokio_handle.spawn(async move {
+			loop {
+				let (socket, remote_addr) = tokio::select! {
+					connection = listener.accept() => match connection {
+						Ok(connection) => connection,
+						Err(e) => {
+							log::debug!(target: "rpc", "Failed to accept RPC connection: {}", e);
+							continue
+						},
+					},
+					_ = stop_handle.clone().shutdown() => break,
+				};
+
+				let service_builder = service_builder.clone();
+				let methods = methods.clone();
+				let stop_handle2 = stop_handle.clone();
+				let limits = limits.clone();
+				let metrics = metrics.clone();
+				let allowed_origins = allowed_origins.clone();
+
+				let service = tower::service_fn(move |request: http::Request<_>| {
+					let is_websocket = ws::is_upgrade_request(&request);
+					if !is_origin_allowed(allowed_origins.as_deref(), request.headers()) {
+						log::debug!(target: "rpc", "Denied RPC request from {}: origin not allowed", remote_addr);
+						let denied: ResponseFuture = Box::pin(async { Ok(response::denied()) });
+						return denied
+					}
+
+					let transport_label = if is_websocket { "ws" } else { "http" };
+					let ip = limits.client_ip(remote_addr, request.headers());
+					let middleware = metrics.clone().map(|metrics| {
+						RpcMiddleware::new(metrics, transport_label, max_payload_out)
+					});
+					let rpc_middleware = RpcServiceBuilder::new()
+						.option_layer(middleware.clone())
+						.layer(RateLimitLayer::new(
+							limits.clone(),
+							ip,
+							transport_label,
+							max_payload_out,
+						));
+					let mut service = service_builder
+						.clone()
+						.set_rpc_middleware(rpc_middleware)
+						.build(methods.clone(), stop_handle2.clone());
+
+					if let Some(middleware) = middleware.filter(|_| is_websocket) {
+						middleware.on_connect();
+						let session_closed = service.on_session_closed();
+						tokio::spawn(async move {
+							session_closed.await;
+							middleware.on_disconnect();
+						});
+					}
+
+					service.call(request)
+				});
+
+				tokio::spawn(serve_with_graceful_shutdown(
+					socket,
+					service,
+					stop_handle.clone().shutdown(),
+				));
+			}
+		});
+	}
 
-	log::info!(
-		"Running JSON-RPC WS server: addr={}, allowed origins={:?}",
-		addr.map_or_else(|_| "unknown".to_string(), |a| a.to_string()),
-		cors
-	);
+	Ok(Server { handle, local_addrs })
+}
+
+type ResponseFuture =
+	Pin<Box<dyn Future<Output = Result<jsonrpsee::server::HttpResponse, tower::BoxError>> + Send>>;
+
+/// Bind a listener to `addr`, or to a random port of the same interface if it is in use.
+async fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+	match TcpListener::bind(addr).await {
+		Ok(listener) => Ok(listener),
+		Err(e) if addr.port() != 0 => {
+			log::warn!(target: "rpc", "Failed to bind RPC server to {}: {}, using a random port", addr, e);
+			TcpListener::bind(SocketAddr::new(addr.ip(), 0)).await
+		},
+		Err(e) => Err(e),
+	}
+}
+
+/// Whether the `Origin` of a request, if any, is one of the allowed origins.
+///
+/// Browsers don't apply the CORS rules to WebSocket, the server has to reject the handshakes
+/// coming from the other origins.
+fn is_origin_allowed(allowed_origins: Option<&Vec<String>>, headers: &HeaderMap) -> bool {
+	match (allowed_origins, headers.get(header::ORIGIN)) {
+		(Some(allowed), Some(origin)) =>
+			allowed.iter().any(|allowed| allowed.as_bytes() == origin.as_bytes()),
+		_ => true,
+	}
+}
+
+fn try_into_cors(cors: Option<&Vec<String>>) -> Result<CorsLayer, Box<dyn StdError + Send + Sync>> {
+	match cors {
+		Some(cors) => {
+			let origins = cors
+				.iter()
+				.map(|origin| HeaderValue::from_str(origin))
+				.collect::<Result<Vec<_>, _>>()?;
+			Ok(CorsLayer::new()
+				.allow_origin(AllowOrigin::list(origins))
+				.allow_methods([Method::POST])
+				.allow_headers([header::CONTENT_TYPE]))
+		},
+		None => Ok(CorsLayer::permissive()),
+	}
+}
 
-	Ok(handle)
+fn format_addrs(addrs: &[SocketAddr]) -> String {
+	addrs.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")
 }
 
 fn format_allowed_hosts(addrs: &[SocketAddr]) -> Vec<String> {
@@ -201,11 +434,11 @@ fn build_rpc_api<M: Send + Sync + 'static>(mut rpc_api: RpcModule<M>) -> RpcModu
 	available_methods.sort_unstable();
 
 	rpc_api
-		.register_method("rpc_methods", move |_, _| {
-			Ok(serde_json::json!({
+		.register_method("rpc_methods", move |_, _, _| {
+			serde_json::json!({
 				"version": 1,
 				"methods": available_methods,
-			}))
+			})
 		})
 		.expect("infallible all other methods have their own address space; qed");
 
@@ -215,3 +448,242 @@ fn build_rpc_api<M: Send + Sync + 'static>(mut rpc_api: RpcModule<M>) -> RpcModu
 fn payload_size_or_default(size_mb: Option<usize>) -> usize {
 	size_mb.map_or(RPC_MAX_PAYLOAD_DEFAULT, |mb| mb.saturating_mul(MEGABYTE))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::io::{BufReader, BufWriter};
+	use hyper::{header, Body, Client, Request, StatusCode};
+	use jsonrpsee::types::ErrorObjectOwned;
+	use serde_json::{json, Value};
+	use soketto::handshake::{
+		self,
+		client::{Header, ServerResponse},
+	};
+	use std::{
+		net::{IpAddr, Ipv4Addr},
+		num::NonZeroU32,
+	};
+	use tokio::{net::TcpStream, sync::Barrier, time::timeout};
+	use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+	type WsConnection = BufReader<BufWriter<Compat<TcpStream>>>;
+
+	const ADDRS: &[SocketAddr] = &[SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)];
+
+	fn config(module: RpcModule<Barrier>) -> Config<'static, Barrier> {
+		Config {
+			addrs: ADDRS,
+			cors: None,
+			max_connections: None,
+			max_subs_per_conn: None,
+			max_payload_in_mb: None,
+			max_payload_out_mb: None,
+			max_batch_len: None,
+			rate_limit: Default::default(),
+			metrics: None,
+			id_provider: None,
+			rpc_api: module,
+			tokio_handle: tokio::runtime::Handle::current(),
+		}
+	}
+
+	fn module() -> RpcModule<Barrier> {
+		// The calls of `test_wait` only complete once two of them are running.
+		let mut module = RpcModule::new(Barrier::new(2));
+		module.register_method("test_echo", |params, _, _| params.one::<u64>()).unwrap();
+		module
+			.register_async_method("test_wait", |_, barrier, _| async move {
+				barrier.wait().await;
+				Ok::<_, ErrorObjectOwned>(true)
+			})
+			.unwrap();
+		module
+			.register_subscription(
+				"test_subscribe",
+				"test_notification",
+				"test_unsubscribe",
+				|_, pending, _, _| async move {
+					if let Ok(sink) = pending.accept().await {
+						let _ = sink.send(serde_json::value::to_raw_value(&42u64).unwrap()).await;
+					}
+				},
+			)
+			.unwrap();
+		module
+	}
+
+	fn call(method: &str, params: Value, id: u64) -> Value {
+		json!({ "jsonrpc": "2.0", "method": method, "params": params, "id": id })
+	}
+
+	async fn http_request(addr: SocketAddr, request: Value) -> (StatusCode, Vec<u8>) {
+		let request = Request::post(format!("http://{}", addr))
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(Body::from(request.to_string()))
+			.unwrap();
+		let response = Client::new().request(request).await.unwrap();
+		let status = response.status();
+		(status, hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec())
+	}
+
+	async fn http_call(addr: SocketAddr, request: Value) -> Value {
+		let (_, body) = http_request(addr, request).await;
+		serde_json::from_slice(&body).unwrap()
+	}
+
+	async fn ws_connect(
+		addr: SocketAddr,
+	) -> (soketto::Sender<WsConnection>, soketto::Receiver<WsConnection>) {
+		let stream = TcpStream::connect(addr).await.unwrap();
+		let host = addr.to_string();
+		let mut client =
+			handshake::Client::new(BufReader::new(BufWriter::new(stream.compat())), &host, "/");
+		assert!(matches!(client.handshake().await.unwrap(), ServerResponse::Accepted { .. }));
+		client.into_builder().finish()
+	}
+
+	async fn ws_send(sender: &mut soketto::Sender<WsConnection>, request: Value) {
+		sender.send_text(request.to_string()).await.unwrap();
+		sender.flush().await.unwrap();
+	}
+
+	async fn ws_receive(receiver: &mut soketto::Receiver<WsConnection>) -> Value {
+		let mut message = Vec::new();
+		receiver.receive_data(&mut message).await.unwrap();
+		serde_json::from_slice(&message).unwrap()
+	}
+
+	#[tokio::test]
+	async fn serves_http_and_websocket_on_the_same_port() {
+		let server = start_server(config(module())).await.unwrap();
+		let addr = server.local_addrs()[0];
+
+		let response = http_call(addr, call("test_echo", json!([7]), 1)).await;
+		assert_eq!(response["result"], 7);
+
+		// Subscriptions are only served over WebSocket.
+		let response = http_call(addr, call("test_subscribe", json!([]), 2)).await;
+		assert!(response.get("error").is_some());
+
+		let (mut sender, mut receiver) = ws_connect(addr).await;
+		ws_send(&mut sender, call("test_echo", json!([8]), 3)).await;
+		assert_eq!(ws_receive(&mut receiver).await["result"], 8);
+
+		ws_send(&mut sender, call("test_subscribe", json!([]), 4)).await;
+		let subscription = ws_receive(&mut receiver).await;
+		assert_eq!(subscription["id"], 4);
+		let notification = ws_receive(&mut receiver).await;
+		assert_eq!(notification["method"], "test_notification");
+		assert_eq!(notification["params"]["subscription"], subscription["result"]);
+		assert_eq!(notification["params"]["result"], 42);
+	}
+
+	#[tokio::test]
+	async fn applies_the_rate_limits_across_transports() {
+		let config = Config {
+			rate_limit: RateLimitConfig { calls_per_ip: NonZeroU32::new(1), ..Default::default() },
+			..config(module())
+		};
+		let server = start_server(config).await.unwrap();
+		let addr = server.local_addrs()[0];
+
+		let response = http_call(addr, call("test_echo", json!([7]), 1)).await;
+		assert_eq!(response["result"], 7);
+
+		let (mut sender, mut receiver) = ws_connect(addr).await;
+		ws_send(&mut sender, call("test_echo", json!([8]), 2)).await;
+		let response = ws_receive(&mut receiver).await;
+		assert_eq!(response["error"]["code"], RATE_LIMITED_ERROR_CODE);
+		assert_eq!(response["id"], 2);
+	}
+
+	#[tokio::test]
+	async fn runs_the_calls_of_a_batch_concurrently() {
+		let server = start_server(config(module())).await.unwrap();
+		let addr = server.local_addrs()[0];
+
+		let batch = json!([call("test_wait", json!([]), 1), call("test_wait", json!([]), 2)]);
+		let response = timeout(Duration::from_secs(10), http_call(addr, batch)).await.unwrap();
+		assert_eq!(
+			response,
+			json!([
+				{ "jsonrpc": "2.0", "result": true, "id": 1 },
+				{ "jsonrpc": "2.0", "result": true, "id": 2 },
+			])
+		);
+	}
+
+	#[tokio::test]
+	async fn answers_a_subscription_in_a_batch_once() {
+		let server = start_server(config(module())).await.unwrap();
+		let addr = server.local_addrs()[0];
+
+		let (mut sender, mut receiver) = ws_connect(addr).await;
+		let batch = json!([call("test_subscribe", json!([]), 1), call("test_echo", json!([8]), 2)]);
+		ws_send(&mut sender, batch).await;
+
+		let mut messages = Vec::new();
+		for _ in 0..3 {
+			messages.push(ws_receive(&mut receiver).await);
+		}
+		let subscription = messages.iter().find(|message| message["id"] == 1).unwrap();
+		let notification = messages
+			.iter()
+			.find(|message| message["method"] == "test_notification")
+			.unwrap();
+		assert_eq!(notification["params"]["subscription"], subscription["result"]);
+		let batch = messages.iter().find(|message| message.is_array()).unwrap();
+		assert_eq!(batch, &json!([{ "jsonrpc": "2.0", "result": 8, "id": 2 }]));
+
+		// Nothing else is sent.
+		assert!(timeout(Duration::from_millis(200), ws_receive(&mut receiver)).await.is_err());
+	}
+
+	#[tokio::test]
+	async fn rejects_the_batches_longer_than_the_limit() {
+		let config = Config { max_batch_len: Some(1), ..config(module()) };
+		let server = start_server(config).await.unwrap();
+		let addr = server.local_addrs()[0];
+
+		let batch = json!([call("test_echo", json!([7]), 1)]);
+		assert_eq!(http_call(addr, batch).await[0]["result"], 7);
+
+		let batch = json!([call("test_echo", json!([7]), 1), call("test_echo", json!([8]), 2)]);
+		let response = http_call(addr, batch).await;
+		assert_eq!(response["error"]["code"], BATCH_TOO_LARGE_ERROR_CODE);
+	}
+
+	#[tokio::test]
+	async fn limits_the_http_requests_and_websocket_connections_together() {
+		let config = Config { max_connections: Some(1), ..config(module()) };
+		let server = start_server(config).await.unwrap();
+		let addr = server.local_addrs()[0];
+
+		let (mut sender, mut receiver) = ws_connect(addr).await;
+		let (status, _) = http_request(addr, call("test_echo", json!([7]), 1)).await;
+		assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+		ws_send(&mut sender, call("test_echo", json!([8]), 2)).await;
+		assert_eq!(ws_receive(&mut receiver).await["result"], 8);
+	}
+
+	#[tokio::test]
+	async fn rejects_the_websocket_handshakes_from_other_origins() {
+		let cors = vec!["http://localhost:3000".to_owned()];
+		let config = Config { cors: Some(&cors), ..config(module()) };
+		let server = start_server(config).await.unwrap();
+		let addr = server.local_addrs()[0];
+		let host = format!("localhost:{}", addr.port());
+
+		for (origin, accepted) in [("http://localhost:3000", true), ("http://evil.com", false)] {
+			let stream = TcpStream::connect(addr).await.unwrap();
+			let mut client =
+				handshake::Client::new(BufReader::new(BufWriter::new(stream.compat())), &host, "/");
+			let headers = [Header { name: "Origin", value: origin.as_bytes() }];
+			client.set_headers(&headers);
+			let response = client.handshake().await.unwrap();
+			assert_eq!(matches!(response, ServerResponse::Accepted { .. }), accepted);
+		}
+	}
+}
@@ -18,12 +18,18 @@
 
 //! RPC middlware to collect prometheus metrics on RPC calls.
 
-use jsonrpsee::core::middleware::{Headers, HttpMiddleware, MethodKind, Params, WsMiddleware};
+use crate::rate_limit::Limit;
+use futures::future::join_all;
+use jsonrpsee::server::{
+	middleware::rpc::{Batch, BatchEntry, Notification, Request, RpcServiceT},
+	BatchResponseBuilder, MethodResponse,
+};
 use prometheus_endpoint::{
 	register, Counter, CounterVec, HistogramOpts, HistogramVec, Opts, PrometheusError, Registry,
 	U64,
 };
-use std::net::SocketAddr;
+use std::{future::Future, time::Instant};
+use tower::Layer;
 
 /// Histogram time buckets in microseconds.
 const HISTOGRAM_BUCKETS: [f64; 11] = [
@@ -54,6 +60,8 @@ pub struct RpcMetrics {
 	calls_started: CounterVec<U64>,
 	/// Number of calls completed.
 	calls_finished: CounterVec<U64>,
+	/// Number of calls rejected for exceeding a rate limit.
+	calls_rate_limited: CounterVec<U64>,
 	/// Number of Websocket sessions opened (Websocket only).
 	ws_sessions_opened: Option<Counter<U64>>,
 	/// Number of Websocket sessions closed (Websocket only).
@@ -116,6 +124,16 @@ impl RpcMetrics {
 					)?,
 					metrics_registry,
 				)?,
+				calls_rate_limited: register(
+					CounterVec::new(
+						Opts::new(
+							"substrate_rpc_calls_rate_limited",
+							"Number of RPC calls rejected for exceeding a rate limit",
+						),
+						&["protocol", "method", "limit"],
+					)?,
+					metrics_registry,
+				)?,
 				ws_sessions_opened: register(
 					Counter::new(
 						"substrate_rpc_sessions_opened",
@@ -137,47 +155,75 @@ impl RpcMetrics {
 			Ok(None)
 		}
 	}
+
+	/// Called when a call is rejected for exceeding `limit`.
+	pub(crate) fn on_rate_limited(&self, protocol: &str, method: &str, limit: Limit) {
+		self.calls_rate_limited
+			.with_label_values(&[protocol, method, limit.as_str()])
+			.inc();
+	}
 }
 
+/// Middleware for RPC calls, collecting the [`RpcMetrics`] of the calls going through it.
 #[derive(Clone)]
-/// Middleware for RPC calls
 pub struct RpcMiddleware {
 	metrics: RpcMetrics,
 	transport_label: &'static str,
+	max_response_size: usize,
 }
 
 impl RpcMiddleware {
 	/// Create a new [`RpcMiddleware`] with the provided [`RpcMetrics`].
-	pub fn new(metrics: RpcMetrics, transport_label: &'static str) -> Self {
-		Self { metrics, transport_label }
+	///
+	/// The responses to batches larger than `max_response_size` bytes are replaced by an error.
+	pub fn new(
+		metrics: RpcMetrics,
+		transport_label: &'static str,
+		max_response_size: usize,
+	) -> Self {
+		Self { metrics, transport_label, max_response_size }
+	}
+
+	/// Called when a WebSocket connection is opened.
+	pub(crate) fn on_connect(&self) {
+		if let Some(counter) = &self.metrics.ws_sessions_opened {
+			counter.inc();
+		}
+	}
+
+	/// Called when a WebSocket connection is closed.
+	pub(crate) fn on_disconnect(&self) {
+		if let Some(counter) = &self.metrics.ws_sessions_closed {
+			counter.inc();
+		}
 	}
 
 	/// Called when a new JSON-RPC request comes to the server.
-	fn on_request(&self) -> std::time::Instant {
-		let now = std::time::Instant::now();
+	fn on_request(&self) -> Instant {
+		let now = Instant::now();
 		self.metrics.requests_started.with_label_values(&[self.transport_label]).inc();
 		now
 	}
 
 	/// Called on each JSON-RPC method call, batch requests will trigger `on_call` multiple times.
-	fn on_call(&self, name: &str, params: Params, kind: MethodKind) {
+	fn on_call(&self, request: &Request) -> Instant {
 		log::trace!(
 			target: "rpc_metrics",
-			"[{}] on_call name={} params={:?} kind={}",
+			"[{}] on_call name={} params={:?}",
 			self.transport_label,
-			name,
-			params,
-			kind,
+			request.method_name(),
+			request.params(),
 		);
 		self.metrics
 			.calls_started
-			.with_label_values(&[self.transport_label, name])
+			.with_label_values(&[self.transport_label, request.method_name()])
 			.inc();
+		Instant::now()
 	}
 
 	/// Called on each JSON-RPC method completion, batch requests will trigger `on_result` multiple
 	/// times.
-	fn on_result(&self, name: &str, success: bool, started_at: std::time::Instant) {
+	fn on_result(&self, name: &str, success: bool, started_at: Instant) {
 		let micros = started_at.elapsed().as_micros();
 		log::debug!(
 			target: "rpc_metrics",
@@ -204,56 +250,130 @@ impl RpcMiddleware {
 	}
 
 	/// Called once the JSON-RPC request is finished and response is sent to the output buffer.
-	fn on_response(&self, _result: &str, started_at: std::time::Instant) {
+	fn on_response(&self, started_at: Instant) {
 		log::trace!(target: "rpc_metrics", "[{}] on_response started_at={:?}", self.transport_label, started_at);
 		self.metrics.requests_finished.with_label_values(&[self.transport_label]).inc();
 	}
 }
 
-impl WsMiddleware for RpcMiddleware {
-	type Instant = std::time::Instant;
+impl<S> Layer<S> for RpcMiddleware {
+	type Service = RpcMiddlewareService<S>;
 
-	fn on_connect(&self, _remote_addr: SocketAddr, _headers: &Headers) {
-		self.metrics.ws_sessions_opened.as_ref().map(|counter| counter.inc());
+	fn layer(&self, service: S) -> Self::Service {
+		RpcMiddlewareService { service, middleware: self.clone() }
 	}
+}
 
-	fn on_request(&self) -> Self::Instant {
-		self.on_request()
-	}
+/// Service of the [`RpcMiddleware`], wrapping the service executing the calls.
+#[derive(Clone)]
+pub struct RpcMiddlewareService<S> {
+	service: S,
+	middleware: RpcMiddleware,
+}
 
-	fn on_call(&self, name: &str, params: Params, kind: MethodKind) {
-		self.on_call(name, params, kind)
+impl<S> RpcMiddlewareService<S>
+where
+	S: RpcServiceT<MethodResponse = MethodResponse, NotificationResponse = MethodResponse>
+		+ Send
+		+ Sync,
+{
+	async fn metered_call(&self, request: Request<'_>) -> MethodResponse {
+		let name = request.method_name().to_owned();
+		let started_at = self.middleware.on_call(&request);
+		let response = self.service.call(request).await;
+		self.middleware.on_result(&name, response.is_success(), started_at);
+		response
 	}
+}
 
-	fn on_result(&self, name: &str, success: bool, started_at: Self::Instant) {
-		self.on_result(name, success, started_at)
-	}
+impl<S> RpcServiceT for RpcMiddlewareService<S>
+where
+	S: RpcServiceT<MethodResponse = MethodResponse, NotificationResponse = MethodResponse>
+		+ Clone
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	type MethodResponse = MethodResponse;
+	type NotificationResponse = MethodResponse;
+	type BatchResponse = MethodResponse;
 
-	fn on_response(&self, _result: &str, started_at: Self::Instant) {
-		self.on_response(_result, started_at)
+	fn call<'a>(&self, request: Request<'a>) -> impl Future<Output = MethodResponse> + Send + 'a {
+		let this = self.clone();
+		async move {
+			let started_at = this.middleware.on_request();
+			let response = this.metered_call(request).await;
+			this.middleware.on_response(started_at);
+			response
+		}
 	}
 
-	fn on_disconnect(&self, _remote_addr: SocketAddr) {
-		self.metrics.ws_sessions_closed.as_ref().map(|counter| counter.inc());
+	fn batch<'a>(&self, batch: Batch<'a>) -> impl Future<Output = MethodResponse> + Send + 'a {
+		let this = self.clone();
+		async move {
+			let started_at = this.middleware.on_request();
+			let response = call_concurrently(
+				batch,
+				this.middleware.max_response_size,
+				|request| this.metered_call(request),
+				|notification| this.service.notification(notification),
+			)
+			.await;
+			this.middleware.on_response(started_at);
+			response
+		}
 	}
-}
 
-impl HttpMiddleware for RpcMiddleware {
-	type Instant = std::time::Instant;
-
-	fn on_request(&self, _remote_addr: SocketAddr, _headers: &Headers) -> Self::Instant {
-		self.on_request()
+	fn notification<'a>(
+		&self,
+		notification: Notification<'a>,
+	) -> impl Future<Output = MethodResponse> + Send + 'a {
+		self.service.notification(notification)
 	}
+}
 
-	fn on_call(&self, name: &str, params: Params, kind: MethodKind) {
-		self.on_call(name, params, kind)
-	}
+/// Execute the calls of a batch concurrently, and gather their responses in the order of the batch.
+///
+/// The response is replaced by an error if it is larger than `max_response_size` bytes.
+pub(crate) async fn call_concurrently<'a, C, CF, N, NF>(
+	batch: Batch<'a>,
+	max_response_size: usize,
+	call: C,
+	notify: N,
+) -> MethodResponse
+where
+	C: Fn(Request<'a>) -> CF,
+	CF: Future<Output = MethodResponse>,
+	N: Fn(Notification<'a>) -> NF,
+	NF: Future<Output = MethodResponse>,
+{
+	let (call, notify) = (&call, &notify);
+	let responses = join_all(batch.into_iter().map(|entry| async move {
+		match entry {
+			Ok(BatchEntry::Call(request)) => Some(call(request).await),
+			Ok(BatchEntry::Notification(notification)) => {
+				notify(notification).await;
+				None
+			},
+			Err(err) => {
+				let (err, id) = err.into_parts();
+				Some(MethodResponse::error(id, err))
+			},
+		}
+	}))
+	.await;
 
-	fn on_result(&self, name: &str, success: bool, started_at: Self::Instant) {
-		self.on_result(name, success, started_at)
+	let mut batch_response = BatchResponseBuilder::new_with_limit(max_response_size);
+	// The subscription responses are sent on their own as soon as the subscriptions are accepted
+	// or rejected, they must not be answered again in the batch.
+	for response in responses.into_iter().flatten().filter(|response| !response.is_subscription()) {
+		if let Err(err) = batch_response.append(response) {
+			return err
+		}
 	}
-
-	fn on_response(&self, _result: &str, started_at: Self::Instant) {
-		self.on_response(_result, started_at)
+	if batch_response.is_empty() {
+		MethodResponse::notification()
+	} else {
+		MethodResponse::from_batch(batch_response.finish())
 	}
 }
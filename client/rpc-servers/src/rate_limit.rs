@@ -0,0 +1,384 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Limits of the RPC server on the calls, checked before they are executed.
+//!
+//! The rate limits are token buckets refilled continuously: a limit of `n` calls per minute allows
+//! bursts of `n` calls, then one call every `60 / n` seconds.
+
+use crate::{middleware::call_concurrently, RpcMetrics};
+use http::HeaderMap;
+use jsonrpsee::{
+	server::{
+		middleware::rpc::{Batch, Notification, Request, ResponseFuture, RpcServiceT},
+		MethodResponse,
+	},
+	types::{ErrorObject, ErrorObjectOwned},
+};
+use lru::LruCache;
+use parking_lot::Mutex;
+use sc_utils::token_bucket::{Rate, TokenBucket};
+use std::{
+	collections::{HashMap, HashSet},
+	fmt,
+	future::Future,
+	net::{IpAddr, SocketAddr},
+	num::NonZeroU32,
+	sync::Arc,
+	time::Instant,
+};
+use tower::Layer;
+
+/// JSON-RPC error code of the calls exceeding a rate limit.
+pub const RATE_LIMITED_ERROR_CODE: i32 = -32005;
+
+/// Number of IP addresses tracked, the least recently seen ones are forgotten beyond it.
+const MAX_TRACKED_IPS: usize = 4096;
+
+/// Call-rate limits of the RPC server.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitConfig {
+	/// Maximum number of calls per minute from each IP address.
+	pub calls_per_ip: Option<NonZeroU32>,
+	/// Maximum number of calls per minute of each listed method, all clients together.
+	pub calls_per_method: HashMap<String, NonZeroU32>,
+	/// Identify the clients by the first address of the `X-Forwarded-For` header, if any.
+	///
+	/// Only enable it behind a proxy setting this header, otherwise the clients pick their own
+	/// address.
+	pub trust_proxy_headers: bool,
+}
+
+/// Rate limit exceeded by a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Limit {
+	/// Limit of calls per IP address.
+	Ip,
+	/// Limit of calls of the method.
+	Method,
+}
+
+impl Limit {
+	/// Label of the limit in the metrics.
+	pub(crate) fn as_str(&self) -> &'static str {
+		match self {
+			Limit::Ip => "ip",
+			Limit::Method => "method",
+		}
+	}
+}
+
+impl fmt::Display for Limit {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Limit::Ip => write!(f, "too many calls from this address"),
+			Limit::Method => write!(f, "too many calls of this method"),
+		}
+	}
+}
+
+/// Enforces a [`RateLimitConfig`].
+pub(crate) struct RateLimiter {
+	ip_rate: Option<Rate>,
+	method_rates: HashMap<String, Rate>,
+	trust_proxy_headers: bool,
+	/// Buckets of the most recently seen IP addresses. An address forgotten early gets a full
+	/// bucket back, which is at most one burst more than its limit.
+	ips: Mutex<LruCache<IpAddr, TokenBucket>>,
+	methods: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+	pub(crate) fn new(config: RateLimitConfig) -> Self {
+		Self {
+			ip_rate: config.calls_per_ip.map(|limit| Rate::per_minute(limit.get())),
+			method_rates: config
+				.calls_per_method
+				.into_iter()
+				.map(|(method, limit)| (method, Rate::per_minute(limit.get())))
+				.collect(),
+			trust_proxy_headers: config.trust_proxy_headers,
+			ips: Mutex::new(LruCache::new(MAX_TRACKED_IPS)),
+			methods: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Whether some calls can be limited.
+	pub(crate) fn is_enabled(&self) -> bool {
+		self.ip_rate.is_some() || !self.method_rates.is_empty()
+	}
+
+	/// Account for a call of `method` from `ip`, or return the limit it exceeds.
+	pub(crate) fn check(&self, ip: IpAddr, method: &str) -> Result<(), Limit> {
+		self.check_at(ip, method, Instant::now())
+	}
+
+	fn check_at(&self, ip: IpAddr, method: &str, now: Instant) -> Result<(), Limit> {
+		if let Some(rate) = &self.ip_rate {
+			let mut ips = self.ips.lock();
+			let allowed = match ips.get_mut(&ip) {
+				Some(bucket) => bucket.try_take(rate, now),
+				None => {
+					let mut bucket = TokenBucket::full(rate, now);
+					let allowed = bucket.try_take(rate, now);
+					ips.put(ip, bucket);
+					allowed
+				},
+			};
+			if !allowed {
+				return Err(Limit::Ip)
+			}
+		}
+
+		if let Some(rate) = self.method_rates.get(method) {
+			let mut methods = self.methods.lock();
+			let bucket =
+				methods.entry(method.to_owned()).or_insert_with(|| TokenBucket::full(rate, now));
+			if !bucket.try_take(rate, now) {
+				return Err(Limit::Method)
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Rate limits shared by all the clients of the server.
+pub(crate) struct Limits {
+	rate_limiter: RateLimiter,
+	metrics: Option<RpcMetrics>,
+	/// Methods of the RPC API, the others are reported as `unknown` in the metrics.
+	methods: HashSet<String>,
+}
+
+impl Limits {
+	pub(crate) fn new(
+		rate_limit: RateLimitConfig,
+		metrics: Option<RpcMetrics>,
+		methods: HashSet<String>,
+	) -> Self {
+		Self { rate_limiter: RateLimiter::new(rate_limit), metrics, methods }
+	}
+
+	/// IP address of the client, as reported by the proxy if it is trusted.
+	pub(crate) fn client_ip(&self, remote_addr: SocketAddr, headers: &HeaderMap) -> IpAddr {
+		if self.rate_limiter.trust_proxy_headers {
+			let forwarded_for = headers
+				.get("x-forwarded-for")
+				.and_then(|value| value.to_str().ok())
+				.and_then(|value| value.split(',').next())
+				.and_then(|ip| ip.trim().parse().ok());
+			if let Some(ip) = forwarded_for {
+				return ip
+			}
+		}
+		remote_addr.ip()
+	}
+
+	/// Check a call of `method` from `ip` against the rate limits, returning the error to answer
+	/// it with if it exceeds one.
+	fn check(&self, ip: IpAddr, protocol: &str, method: &str) -> Result<(), ErrorObjectOwned> {
+		if !self.rate_limiter.is_enabled() {
+			return Ok(())
+		}
+		self.rate_limiter.check(ip, method).map_err(|limit| {
+			log::debug!(target: "rpc", "Rejecting RPC call of {} from {}: {}", method, ip, limit);
+			if let Some(metrics) = &self.metrics {
+				let label = if self.methods.contains(method) { method } else { "unknown" };
+				metrics.on_rate_limited(protocol, label, limit);
+			}
+			rate_limited_error(limit)
+		})
+	}
+}
+
+/// Middleware rejecting the calls of a client exceeding the [`Limits`].
+#[derive(Clone)]
+pub(crate) struct RateLimitLayer {
+	limits: Arc<Limits>,
+	ip: IpAddr,
+	transport_label: &'static str,
+	max_response_size: usize,
+}
+
+impl RateLimitLayer {
+	pub(crate) fn new(
+		limits: Arc<Limits>,
+		ip: IpAddr,
+		transport_label: &'static str,
+		max_response_size: usize,
+	) -> Self {
+		Self { limits, ip, transport_label, max_response_size }
+	}
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+	type Service = RateLimit<S>;
+
+	fn layer(&self, service: S) -> Self::Service {
+		RateLimit { service, layer: self.clone() }
+	}
+}
+
+/// Service of the [`RateLimitLayer`], wrapping the service executing the calls.
+#[derive(Clone)]
+pub(crate) struct RateLimit<S> {
+	service: S,
+	layer: RateLimitLayer,
+}
+
+impl<S> RpcServiceT for RateLimit<S>
+where
+	S: RpcServiceT<MethodResponse = MethodResponse, NotificationResponse = MethodResponse>
+		+ Clone
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	type MethodResponse = MethodResponse;
+	type NotificationResponse = MethodResponse;
+	type BatchResponse = MethodResponse;
+
+	fn call<'a>(&self, request: Request<'a>) -> impl Future<Output = MethodResponse> + Send + 'a {
+		let RateLimitLayer { limits, ip, transport_label, .. } = &self.layer;
+		match limits.check(*ip, transport_label, request.method_name()) {
+			Ok(()) => ResponseFuture::future(self.service.call(request)),
+			Err(err) => ResponseFuture::ready(MethodResponse::error(request.id, err)),
+		}
+	}
+
+	fn batch<'a>(&self, batch: Batch<'a>) -> impl Future<Output = MethodResponse> + Send + 'a {
+		let this = self.clone();
+		async move {
+			call_concurrently(
+				batch,
+				this.layer.max_response_size,
+				|request| this.call(request),
+				|notification| this.service.notification(notification),
+			)
+			.await
+		}
+	}
+
+	fn notification<'a>(
+		&self,
+		notification: Notification<'a>,
+	) -> impl Future<Output = MethodResponse> + Send + 'a {
+		self.service.notification(notification)
+	}
+}
+
+fn rate_limited_error(limit: Limit) -> ErrorObjectOwned {
+	ErrorObject::owned(RATE_LIMITED_ERROR_CODE, format!("Too many requests: {}", limit), None::<()>)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	/// Time after which a call exceeding a limit of `per_minute` calls can be retried.
+	fn refill_period(per_minute: u32) -> Duration {
+		Duration::from_secs(60) / per_minute
+	}
+
+	fn config(calls_per_ip: Option<u32>, calls_per_method: &[(&str, u32)]) -> RateLimitConfig {
+		RateLimitConfig {
+			calls_per_ip: calls_per_ip.and_then(NonZeroU32::new),
+			calls_per_method: calls_per_method
+				.iter()
+				.map(|(method, limit)| (method.to_string(), NonZeroU32::new(*limit).unwrap()))
+				.collect(),
+			trust_proxy_headers: false,
+		}
+	}
+
+	fn limiter(calls_per_ip: Option<u32>, calls_per_method: &[(&str, u32)]) -> RateLimiter {
+		RateLimiter::new(config(calls_per_ip, calls_per_method))
+	}
+
+	#[test]
+	fn limits_calls_per_ip() {
+		let limiter = limiter(Some(2), &[]);
+		let (alice, bob) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+		let now = Instant::now();
+
+		assert_eq!(limiter.check_at(alice, "system_name", now), Ok(()));
+		assert_eq!(limiter.check_at(alice, "system_health", now), Ok(()));
+		assert_eq!(limiter.check_at(alice, "system_name", now), Err(Limit::Ip));
+		assert_eq!(limiter.check_at(bob, "system_name", now), Ok(()));
+
+		let later = now + refill_period(2);
+		assert_eq!(limiter.check_at(alice, "system_name", later), Ok(()));
+		assert_eq!(limiter.check_at(alice, "system_name", later), Err(Limit::Ip));
+	}
+
+	#[test]
+	fn limits_calls_per_method_across_clients() {
+		let limiter = limiter(None, &[("state_getKeysPaged", 1)]);
+		let (alice, bob) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+		let now = Instant::now();
+
+		assert!(limiter.is_enabled());
+		assert_eq!(limiter.check_at(alice, "state_getKeysPaged", now), Ok(()));
+		assert_eq!(limiter.check_at(bob, "state_getKeysPaged", now), Err(Limit::Method));
+		assert_eq!(limiter.check_at(bob, "system_name", now), Ok(()));
+		assert_eq!(limiter.check_at(bob, "state_getKeysPaged", now + refill_period(1)), Ok(()));
+	}
+
+	#[test]
+	fn tracks_a_bounded_number_of_ips() {
+		let limiter = limiter(Some(1), &[]);
+		let now = Instant::now();
+		let first = IpAddr::from(0u32.to_be_bytes());
+		for i in 0..=MAX_TRACKED_IPS as u32 {
+			assert_eq!(limiter.check_at(IpAddr::from(i.to_be_bytes()), "system_name", now), Ok(()));
+		}
+		assert_eq!(limiter.ips.lock().len(), MAX_TRACKED_IPS);
+
+		// The least recently seen address was forgotten, the others are still limited.
+		assert_eq!(limiter.check_at(first, "system_name", now), Ok(()));
+		let last = IpAddr::from((MAX_TRACKED_IPS as u32).to_be_bytes());
+		assert_eq!(limiter.check_at(last, "system_name", now), Err(Limit::Ip));
+	}
+
+	#[test]
+	fn rejects_the_calls_exceeding_a_limit_with_an_error() {
+		let limits = Limits::new(config(Some(1), &[]), None, HashSet::new());
+		let ip = IpAddr::from([10, 0, 0, 1]);
+
+		assert!(limits.check(ip, "ws", "system_name").is_ok());
+		let err = limits.check(ip, "ws", "system_name").unwrap_err();
+		assert_eq!(err.code(), RATE_LIMITED_ERROR_CODE);
+	}
+
+	#[test]
+	fn identifies_clients_by_the_forwarded_address_only_if_trusted() {
+		let remote_addr = SocketAddr::from(([10, 0, 0, 1], 30333));
+		let mut headers = HeaderMap::new();
+		headers.insert("x-forwarded-for", "192.168.0.1, 10.0.0.2".parse().unwrap());
+
+		let limits = Limits::new(Default::default(), None, HashSet::new());
+		assert_eq!(limits.client_ip(remote_addr, &headers), remote_addr.ip());
+
+		let config = RateLimitConfig { trust_proxy_headers: true, ..Default::default() };
+		let limits = Limits::new(config, None, HashSet::new());
+		assert_eq!(limits.client_ip(remote_addr, &headers), IpAddr::from([192, 168, 0, 1]));
+		assert_eq!(limits.client_ip(remote_addr, &HeaderMap::new()), remote_addr.ip());
+	}
+}
@@ -0,0 +1,313 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Substrate archive API.
+//!
+//! The storage diffs are computed by walking the tries of the two states together and skipping
+//! the subtries they share, so their cost depends on the size of the difference rather than on
+//! the size of the state.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+	marker::PhantomData,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+};
+
+use crate::{spawn_subscription_task, utils::pipe_from_stream, SubscriptionTaskExecutor};
+
+use codec::Encode;
+use futures::{channel::mpsc, executor::block_on, FutureExt, SinkExt};
+use jsonrpsee::{
+	core::{async_trait, RpcResult},
+	types::{error::CALL_EXECUTION_FAILED_CODE, ErrorObject, ErrorObjectOwned},
+	PendingSubscriptionSink,
+};
+use sc_client_api::{Backend, BlockBackend, CallExecutor, ExecutorProvider};
+use sp_blockchain::{Error as ClientError, HeaderBackend};
+use sp_core::{
+	storage::{StorageData, StorageKey},
+	Bytes,
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor},
+};
+use sp_state_machine::backend::AsTrieBackend;
+use sp_trie::{DiffEntry, LayoutV1};
+
+pub use sc_rpc_api::archive::*;
+use sc_rpc_api::archive::{
+	error::Error,
+	event::{ErrorEvent, StorageDiffEvent, StorageDiffItem, StorageDiffType},
+};
+
+/// Maximum number of entries reported by a single `items` event of a storage diff.
+const MAX_ITEMS_PER_EVENT: usize = 64;
+
+/// Number of storage diff events computed in advance of the subscriber.
+const EVENTS_BUFFER: usize = 4;
+
+/// Maximum number of storage diffs computed at the same time.
+const MAX_STORAGE_DIFFS: usize = 4;
+
+/// An API for any block of the chain, on the nodes which keep them all.
+pub struct Archive<BE, Block: BlockT, Client> {
+	client: Arc<Client>,
+	backend: Arc<BE>,
+	executor: SubscriptionTaskExecutor,
+	/// Whether the node keeps all blocks and states. The methods fail otherwise.
+	is_archive: bool,
+	/// Number of storage diffs being computed.
+	storage_diffs: Arc<AtomicUsize>,
+	_phantom: PhantomData<Block>,
+}
+
+impl<BE, Block, Client> Archive<BE, Block, Client>
+where
+	Block: BlockT,
+	BE: Backend<Block>,
+{
+	/// Create a new [`Archive`].
+	pub fn new(client: Arc<Client>, backend: Arc<BE>, executor: SubscriptionTaskExecutor) -> Self {
+		let is_archive = backend.is_archive();
+		Self {
+			client,
+			backend,
+			executor,
+			is_archive,
+			storage_diffs: Default::default(),
+			_phantom: PhantomData,
+		}
+	}
+
+	fn ensure_archive(&self) -> Result<(), Error> {
+		if self.is_archive {
+			Ok(())
+		} else {
+			Err(Error::PrunedNode)
+		}
+	}
+}
+
+#[async_trait]
+impl<BE, Block, Client> ArchiveApiServer<Block::Hash> for Archive<BE, Block, Client>
+where
+	Block: BlockT + 'static,
+	BE: Backend<Block> + 'static,
+	Client: BlockBackend<Block>
+		+ ExecutorProvider<Block>
+		+ HeaderBackend<Block>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	fn header(&self, hash: Block::Hash) -> RpcResult<Option<Bytes>> {
+		self.ensure_archive()?;
+		self.client
+			.header(BlockId::Hash(hash))
+			.map(|header| header.map(|header| header.encode().into()))
+			.map_err(|e| client_err(e).into())
+	}
+
+	fn body(&self, hash: Block::Hash) -> RpcResult<Option<Vec<Bytes>>> {
+		self.ensure_archive()?;
+		self.client
+			.block_body(&BlockId::Hash(hash))
+			.map(|body| body.map(|body| body.iter().map(|ex| ex.encode().into()).collect()))
+			.map_err(|e| client_err(e).into())
+	}
+
+	fn call(
+		&self,
+		hash: Block::Hash,
+		function: String,
+		call_parameters: Bytes,
+	) -> RpcResult<Bytes> {
+		self.ensure_archive()?;
+		self.client
+			.executor()
+			.call(
+				&BlockId::Hash(hash),
+				&function,
+				&call_parameters,
+				self.client.execution_extensions().strategies().other,
+				None,
+			)
+			.map(Into::into)
+			.map_err(|e| client_err(e).into())
+	}
+
+	fn storage_diff(
+		&self,
+		pending: PendingSubscriptionSink,
+		hash_a: Block::Hash,
+		hash_b: Block::Hash,
+		prefix: Option<StorageKey>,
+	) {
+		if let Err(e) = self.ensure_archive() {
+			spawn_subscription_task(&self.executor, pending.reject(ErrorObjectOwned::from(e)));
+			return
+		}
+		let slot = match StorageDiffSlot::acquire(&self.storage_diffs) {
+			Some(slot) => slot,
+			None => {
+				let err = ErrorObject::owned(
+					CALL_EXECUTION_FAILED_CODE,
+					"Too many storage diffs",
+					None::<()>,
+				);
+				spawn_subscription_task(&self.executor, pending.reject(err));
+				return
+			},
+		};
+		// The blocks stay pinned until the diff is computed.
+		let pinned = PinnedBlock::new(self.backend.clone(), hash_a)
+			.and_then(|a| Ok((a, PinnedBlock::new(self.backend.clone(), hash_b)?)));
+		let pinned = match pinned {
+			Ok(pinned) => pinned,
+			Err(ClientError::UnknownBlock(hash)) => {
+				let err = ErrorObjectOwned::from(Error::UnknownBlock(hash));
+				spawn_subscription_task(&self.executor, pending.reject(err));
+				return
+			},
+			Err(e) => {
+				let err = ErrorObjectOwned::from(client_err(e));
+				spawn_subscription_task(&self.executor, pending.reject(err));
+				return
+			},
+		};
+
+		// The diff is computed on a blocking task, which stops as soon as the subscriber is gone.
+		let (mut sender, receiver) = mpsc::channel(EVENTS_BUFFER);
+		let backend = self.backend.clone();
+		let prefix = prefix.map(|prefix| prefix.0).unwrap_or_default();
+		let diff = async move {
+			let _slot = slot;
+			let (pinned_a, pinned_b) = pinned;
+			let outcome = diff_states(&*backend, pinned_a.hash, pinned_b.hash, &prefix, |items| {
+				block_on(sender.send(StorageDiffEvent::Items { items })).is_ok()
+			});
+			let last_event = match outcome {
+				Ok(true) => StorageDiffEvent::Done,
+				Ok(false) => return,
+				Err(e) => StorageDiffEvent::Error(ErrorEvent { error: e.to_string() }),
+			};
+			let _ = block_on(sender.send(last_event));
+		};
+		self.executor
+			.spawn_blocking("substrate-rpc-storage-diff", Some("rpc"), diff.boxed());
+
+		spawn_subscription_task(&self.executor, pipe_from_stream(pending, receiver));
+	}
+}
+
+/// Pass the storage entries which differ between the states of `hash_a` and `hash_b` to `send`,
+/// by batches. Returns whether all of them were sent, `send` returning `false` to stop.
+fn diff_states<Block, BE>(
+	backend: &BE,
+	hash_a: Block::Hash,
+	hash_b: Block::Hash,
+	prefix: &[u8],
+	mut send: impl FnMut(Vec<StorageDiffItem>) -> bool,
+) -> sp_blockchain::Result<bool>
+where
+	Block: BlockT,
+	BE: Backend<Block>,
+{
+	let state_a = backend.state_at(BlockId::Hash(hash_a))?;
+	let state_b = backend.state_at(BlockId::Hash(hash_b))?;
+	let (trie_a, trie_b) = (state_a.as_trie_backend(), state_b.as_trie_backend());
+
+	let mut items = Vec::with_capacity(MAX_ITEMS_PER_EVENT);
+	let mut open = true;
+	sp_trie::diff::<LayoutV1<HashFor<Block>>, _>(
+		trie_a.essence(),
+		trie_a.root(),
+		trie_b.essence(),
+		trie_b.root(),
+		prefix,
+		|entry| {
+			items.push(diff_item(entry));
+			if items.len() == MAX_ITEMS_PER_EVENT {
+				open = send(std::mem::take(&mut items));
+			}
+			open
+		},
+	)
+	.map_err(|e| ClientError::Storage(e.to_string()))?;
+
+	Ok(open && (items.is_empty() || send(items)))
+}
+
+fn diff_item(entry: DiffEntry) -> StorageDiffItem {
+	let (key, diff_type, value) = match entry {
+		DiffEntry::Added { key, value } => (key, StorageDiffType::Added, Some(value)),
+		DiffEntry::Modified { key, value } => (key, StorageDiffType::Modified, Some(value)),
+		DiffEntry::Removed { key } => (key, StorageDiffType::Removed, None),
+	};
+	StorageDiffItem { key: StorageKey(key), diff_type, value: value.map(StorageData) }
+}
+
+/// One of the [`MAX_STORAGE_DIFFS`] storage diffs computed at the same time, released when
+/// dropped.
+struct StorageDiffSlot(Arc<AtomicUsize>);
+
+impl StorageDiffSlot {
+	fn acquire(storage_diffs: &Arc<AtomicUsize>) -> Option<Self> {
+		storage_diffs
+			.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+				(count < MAX_STORAGE_DIFFS).then(|| count + 1)
+			})
+			.ok()
+			.map(|_| Self(storage_diffs.clone()))
+	}
+}
+
+impl Drop for StorageDiffSlot {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+/// A block pinned in the backend until dropped.
+struct PinnedBlock<Block: BlockT, BE: Backend<Block>> {
+	backend: Arc<BE>,
+	hash: Block::Hash,
+}
+
+impl<Block: BlockT, BE: Backend<Block>> PinnedBlock<Block, BE> {
+	fn new(backend: Arc<BE>, hash: Block::Hash) -> sp_blockchain::Result<Self> {
+		backend.pin_block(hash)?;
+		Ok(Self { backend, hash })
+	}
+}
+
+impl<Block: BlockT, BE: Backend<Block>> Drop for PinnedBlock<Block, BE> {
+	fn drop(&mut self) {
+		self.backend.unpin_block(self.hash);
+	}
+}
+
+fn client_err(err: ClientError) -> Error {
+	Error::Client(Box::new(err))
+}
@@ -0,0 +1,203 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use crate::testing::{test_executor, timeout_secs};
+use jsonrpsee::core::server::Subscription;
+use sc_block_builder::BlockBuilderProvider;
+use sp_consensus::BlockOrigin;
+use substrate_test_runtime_client::{
+	prelude::*,
+	runtime::{Block, H256},
+	Backend,
+};
+
+fn setup(is_archive: bool) -> (Arc<TestClient>, Archive<Backend, Block, TestClient>) {
+	let builder =
+		if is_archive { TestClientBuilder::with_archive() } else { TestClientBuilder::new() };
+	let backend = builder.backend();
+	let client = Arc::new(builder.build());
+	let archive = Archive::new(client.clone(), backend, test_executor());
+	(client, archive)
+}
+
+async fn import_block(
+	client: &mut Arc<TestClient>,
+	changes: Vec<(&[u8], Option<Vec<u8>>)>,
+) -> H256 {
+	let mut builder = client.new_block(Default::default()).unwrap();
+	for (key, value) in changes {
+		builder.push_storage_change(key.to_vec(), value).unwrap();
+	}
+	let block = builder.build().unwrap().block;
+	let hash = block.header.hash();
+	client.import(BlockOrigin::Own, block).await.unwrap();
+	hash
+}
+
+/// Collect the items of a storage diff subscription, until it is done.
+async fn diff_items(sub: &mut Subscription) -> Vec<StorageDiffItem> {
+	let mut items = Vec::new();
+	loop {
+		let (event, _) = timeout_secs(10, sub.next::<StorageDiffEvent>())
+			.await
+			.expect("Event received in time")
+			.expect("Subscription open")
+			.expect("Valid event");
+		match event {
+			StorageDiffEvent::Items { items: new_items } => items.extend(new_items),
+			StorageDiffEvent::Done => return items,
+			event => panic!("Unexpected event {:?}", event),
+		}
+	}
+}
+
+fn item(key: &[u8], diff_type: StorageDiffType, value: Option<Vec<u8>>) -> StorageDiffItem {
+	StorageDiffItem { key: StorageKey(key.to_vec()), diff_type, value: value.map(StorageData) }
+}
+
+#[tokio::test]
+async fn archive_reads_any_block() {
+	let (mut client, archive) = setup(true);
+	let api = archive.into_rpc();
+	let genesis = client.genesis_hash();
+	let hash = import_block(&mut client, vec![]).await;
+
+	let res: Option<Bytes> = api.call("archive_header", [genesis]).await.unwrap();
+	assert_eq!(res, Some(client.header(&BlockId::Hash(genesis)).unwrap().unwrap().encode().into()));
+	let res: Option<Vec<Bytes>> = api.call("archive_body", [hash]).await.unwrap();
+	assert_eq!(res, Some(vec![]));
+	let res: Option<Bytes> = api.call("archive_header", [H256::repeat_byte(1)]).await.unwrap();
+	assert_eq!(res, None);
+
+	let res: Bytes = api
+		.call("archive_call", (genesis, "Core_version", Bytes(vec![])))
+		.await
+		.unwrap();
+	assert!(!res.is_empty());
+}
+
+#[tokio::test]
+async fn archive_storage_diff_reports_changed_entries() {
+	let (mut client, archive) = setup(true);
+	let api = archive.into_rpc();
+	let genesis = client.genesis_hash();
+	let large = vec![7; 100];
+	let block_1 = import_block(
+		&mut client,
+		vec![(b":archive:a", Some(vec![1])), (b":archive:b", Some(large.clone()))],
+	)
+	.await;
+	let block_2 = import_block(
+		&mut client,
+		vec![
+			(b":archive:a", Some(vec![2])),
+			(b":archive:b", None),
+			(b":archive:c", Some(large.clone())),
+		],
+	)
+	.await;
+	let prefix = StorageKey(b":archive:".to_vec());
+
+	let mut sub = api
+		.subscribe_unbounded("archive_storageDiff", (block_1, block_2, &prefix))
+		.await
+		.unwrap();
+	assert_eq!(
+		diff_items(&mut sub).await,
+		vec![
+			item(b":archive:a", StorageDiffType::Modified, Some(vec![2])),
+			item(b":archive:b", StorageDiffType::Removed, None),
+			item(b":archive:c", StorageDiffType::Added, Some(large.clone())),
+		],
+	);
+
+	let mut sub = api
+		.subscribe_unbounded("archive_storageDiff", (genesis, block_2, &prefix))
+		.await
+		.unwrap();
+	assert_eq!(
+		diff_items(&mut sub).await,
+		vec![
+			item(b":archive:a", StorageDiffType::Added, Some(vec![2])),
+			item(b":archive:c", StorageDiffType::Added, Some(large.clone())),
+		],
+	);
+
+	// Without prefix, the changes made by the runtime itself are reported as well.
+	let mut sub = api
+		.subscribe_unbounded("archive_storageDiff", (block_2, block_1, None::<StorageKey>))
+		.await
+		.unwrap();
+	let items = diff_items(&mut sub).await;
+	assert!(items.windows(2).all(|pair| pair[0].key < pair[1].key));
+	assert!(items.contains(&item(b":archive:b", StorageDiffType::Added, Some(large))));
+	assert!(items.contains(&item(b":archive:c", StorageDiffType::Removed, None)));
+
+	let mut sub = api
+		.subscribe_unbounded("archive_storageDiff", (block_1, block_1, None::<()>))
+		.await
+		.unwrap();
+	assert!(diff_items(&mut sub).await.is_empty());
+
+	assert!(api
+		.subscribe_unbounded("archive_storageDiff", (genesis, H256::repeat_byte(1), None::<()>))
+		.await
+		.is_err());
+}
+
+#[tokio::test]
+async fn archive_refuses_to_run_on_pruned_nodes() {
+	let (client, archive) = setup(false);
+	let api = archive.into_rpc();
+	let genesis = client.genesis_hash();
+
+	assert!(api.call::<_, Bytes>("archive_header", [genesis]).await.is_err());
+	assert!(api.call::<_, Vec<Bytes>>("archive_body", [genesis]).await.is_err());
+	assert!(api
+		.call::<_, Bytes>("archive_call", (genesis, "Core_version", Bytes(vec![])))
+		.await
+		.is_err());
+	assert!(api
+		.subscribe_unbounded("archive_storageDiff", (genesis, genesis, None::<()>))
+		.await
+		.is_err());
+}
+
+#[tokio::test]
+async fn archive_limits_concurrent_storage_diffs() {
+	let (client, archive) = setup(true);
+	let genesis = client.genesis_hash();
+	let storage_diffs = archive.storage_diffs.clone();
+	let api = archive.into_rpc();
+
+	let slots: Vec<_> = (0..MAX_STORAGE_DIFFS)
+		.map(|_| StorageDiffSlot::acquire(&storage_diffs).unwrap())
+		.collect();
+	assert!(api
+		.subscribe_unbounded("archive_storageDiff", (genesis, genesis, None::<()>))
+		.await
+		.is_err());
+
+	drop(slots);
+	let mut sub = api
+		.subscribe_unbounded("archive_storageDiff", (genesis, genesis, None::<()>))
+		.await
+		.unwrap();
+	assert!(diff_items(&mut sub).await.is_empty());
+}
@@ -19,14 +19,14 @@
 //! Blockchain API backend for full nodes.
 
 use super::{client_err, ChainBackend, Error};
-use crate::SubscriptionTaskExecutor;
+use crate::{spawn_subscription_task, utils::pipe_from_stream, SubscriptionTaskExecutor};
 use std::{marker::PhantomData, sync::Arc};
 
 use futures::{
-	future::{self, FutureExt},
+	future,
 	stream::{self, Stream, StreamExt},
 };
-use jsonrpsee::SubscriptionSink;
+use jsonrpsee::PendingSubscriptionSink;
 use sc_client_api::{BlockBackend, BlockchainEvents};
 use sp_blockchain::HeaderBackend;
 use sp_runtime::{
@@ -69,11 +69,11 @@ where
 		self.client.block(&BlockId::Hash(self.unwrap_or_best(hash))).map_err(client_err)
 	}
 
-	fn subscribe_all_heads(&self, sink: SubscriptionSink) {
+	fn subscribe_all_heads(&self, pending: PendingSubscriptionSink) {
 		subscribe_headers(
 			&self.client,
 			&self.executor,
-			sink,
+			pending,
 			|| self.client().info().best_hash,
 			|| {
 				self.client()
@@ -83,11 +83,11 @@ where
 		)
 	}
 
-	fn subscribe_new_heads(&self, sink: SubscriptionSink) {
+	fn subscribe_new_heads(&self, pending: PendingSubscriptionSink) {
 		subscribe_headers(
 			&self.client,
 			&self.executor,
-			sink,
+			pending,
 			|| self.client().info().best_hash,
 			|| {
 				self.client()
@@ -98,11 +98,11 @@ where
 		)
 	}
 
-	fn subscribe_finalized_heads(&self, sink: SubscriptionSink) {
+	fn subscribe_finalized_heads(&self, pending: PendingSubscriptionSink) {
 		subscribe_headers(
 			&self.client,
 			&self.executor,
-			sink,
+			pending,
 			|| self.client().info().finalized_hash,
 			|| {
 				self.client()
@@ -117,7 +117,7 @@ where
 fn subscribe_headers<Block, Client, F, G, S>(
 	client: &Arc<Client>,
 	executor: &SubscriptionTaskExecutor,
-	mut sink: SubscriptionSink,
+	pending: PendingSubscriptionSink,
 	best_block_hash: G,
 	stream: F,
 ) where
@@ -142,9 +142,5 @@ fn subscribe_headers<Block, Client, F, G, S>(
 	// duplicates at the beginning of the stream though.
 	let stream = stream::iter(maybe_header).chain(stream());
 
-	let fut = async move {
-		sink.pipe_from_stream(stream).await;
-	};
-
-	executor.spawn("substrate-rpc-subscription", Some("rpc"), fut.boxed());
+	spawn_subscription_task(executor, pipe_from_stream(pending, stream));
 }
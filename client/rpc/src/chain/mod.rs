@@ -27,7 +27,7 @@ use std::sync::Arc;
 
 use crate::SubscriptionTaskExecutor;
 
-use jsonrpsee::{core::RpcResult, types::SubscriptionResult, SubscriptionSink};
+use jsonrpsee::{core::RpcResult, PendingSubscriptionSink};
 use sc_client_api::BlockchainEvents;
 use sp_rpc::{list::ListOrValue, number::NumberOrHex};
 use sp_runtime::{
@@ -95,13 +95,13 @@ where
 	}
 
 	/// All new head subscription
-	fn subscribe_all_heads(&self, sink: SubscriptionSink);
+	fn subscribe_all_heads(&self, pending: PendingSubscriptionSink);
 
 	/// New best head subscription
-	fn subscribe_new_heads(&self, sink: SubscriptionSink);
+	fn subscribe_new_heads(&self, pending: PendingSubscriptionSink);
 
 	/// Finalized head subscription
-	fn subscribe_finalized_heads(&self, sink: SubscriptionSink);
+	fn subscribe_finalized_heads(&self, pending: PendingSubscriptionSink);
 }
 
 /// Create new state API that works on full node.
@@ -160,19 +160,16 @@ where
 		self.backend.finalized_head().map_err(Into::into)
 	}
 
-	fn subscribe_all_heads(&self, sink: SubscriptionSink) -> SubscriptionResult {
-		self.backend.subscribe_all_heads(sink);
-		Ok(())
+	fn subscribe_all_heads(&self, pending: PendingSubscriptionSink) {
+		self.backend.subscribe_all_heads(pending)
 	}
 
-	fn subscribe_new_heads(&self, sink: SubscriptionSink) -> SubscriptionResult {
-		self.backend.subscribe_new_heads(sink);
-		Ok(())
+	fn subscribe_new_heads(&self, pending: PendingSubscriptionSink) {
+		self.backend.subscribe_new_heads(pending)
 	}
 
-	fn subscribe_finalized_heads(&self, sink: SubscriptionSink) -> SubscriptionResult {
-		self.backend.subscribe_finalized_heads(sink);
-		Ok(())
+	fn subscribe_finalized_heads(&self, pending: PendingSubscriptionSink) {
+		self.backend.subscribe_finalized_heads(pending)
 	}
 }
 
@@ -19,7 +19,7 @@
 use super::*;
 use crate::testing::{test_executor, timeout_secs};
 use assert_matches::assert_matches;
-use jsonrpsee::types::EmptyParams;
+use jsonrpsee::core::EmptyServerParams;
 use sc_block_builder::BlockBuilderProvider;
 use sp_consensus::BlockOrigin;
 use sp_rpc::list::ListOrValue;
@@ -48,7 +48,7 @@ async fn should_return_header() {
 		}
 	);
 
-	let res: Header = api.call("chain_getHeader", EmptyParams::new()).await.unwrap();
+	let res: Header = api.call("chain_getHeader", EmptyServerParams::new()).await.unwrap();
 	assert_eq!(
 		res,
 		Header {
@@ -134,7 +134,7 @@ async fn should_return_block_hash() {
 	let api = new_full(client.clone(), test_executor()).into_rpc();
 
 	let res: ListOrValue<Option<H256>> =
-		api.call("chain_getBlockHash", EmptyParams::new()).await.unwrap();
+		api.call("chain_getBlockHash", EmptyServerParams::new()).await.unwrap();
 
 	assert_matches!(
 		res,
@@ -193,7 +193,7 @@ async fn should_return_finalized_hash() {
 	let mut client = Arc::new(substrate_test_runtime_client::new());
 	let api = new_full(client.clone(), test_executor()).into_rpc();
 
-	let res: H256 = api.call("chain_getFinalizedHead", EmptyParams::new()).await.unwrap();
+	let res: H256 = api.call("chain_getFinalizedHead", EmptyServerParams::new()).await.unwrap();
 	assert_eq!(res, client.genesis_hash());
 
 	// import new block
@@ -201,12 +201,12 @@ async fn should_return_finalized_hash() {
 	client.import(BlockOrigin::Own, block).await.unwrap();
 
 	// no finalization yet
-	let res: H256 = api.call("chain_getFinalizedHead", EmptyParams::new()).await.unwrap();
+	let res: H256 = api.call("chain_getFinalizedHead", EmptyServerParams::new()).await.unwrap();
 	assert_eq!(res, client.genesis_hash());
 
 	// finalize
 	client.finalize_block(BlockId::number(1), None).unwrap();
-	let res: H256 = api.call("chain_getFinalizedHead", EmptyParams::new()).await.unwrap();
+	let res: H256 = api.call("chain_getFinalizedHead", EmptyServerParams::new()).await.unwrap();
 	assert_eq!(res, client.block_hash(1).unwrap().unwrap());
 }
 
@@ -230,7 +230,7 @@ async fn test_head_subscription(method: &str) {
 
 	let mut sub = {
 		let api = new_full(client.clone(), test_executor()).into_rpc();
-		let sub = api.subscribe(method, EmptyParams::new()).await.unwrap();
+		let sub = api.subscribe_unbounded(method, EmptyServerParams::new()).await.unwrap();
 		let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
 		client.import(BlockOrigin::Own, block).await.unwrap();
 		client.finalize_block(BlockId::number(1), None).unwrap();
@@ -0,0 +1,447 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Substrate chain head API.
+//!
+//! Every `chainHead_follow` subscription pins the blocks it reports in the backend, so that their
+//! body and state stay available to the methods scoped to the subscription until the client
+//! unpins them or unsubscribes.
+//!
+//! Nothing forces a client to unpin its blocks, so a connection could keep every follow
+//! subscription open and prevent the pruning of up to `MAX_PINNED_BLOCKS` blocks each. The
+//! subscription is therefore unsafe to expose publicly.
+
+mod subscription;
+
+#[cfg(test)]
+mod tests;
+
+use std::{collections::VecDeque, marker::PhantomData, sync::Arc};
+
+use crate::{
+	spawn_subscription_task, utils::pipe_from_stream, DenyUnsafe, SubscriptionTaskExecutor,
+};
+
+use codec::Encode;
+use futures::{
+	stream::{self, BoxStream},
+	StreamExt,
+};
+use jsonrpsee::{
+	core::{async_trait, traits::IdProvider, RpcResult},
+	types::{error::CALL_EXECUTION_FAILED_CODE, ErrorObject, SubscriptionId},
+	PendingSubscriptionSink,
+};
+use sc_client_api::{
+	Backend, BlockBackend, BlockImportNotification, BlockchainEvents, CallExecutor,
+	ExecutorProvider, FinalityNotification, StorageProvider,
+};
+use sp_blockchain::{Backend as _, Error as ClientError, HeaderBackend};
+use sp_core::{
+	storage::{ChildInfo, ChildType, PrefixedStorageKey, StorageData, StorageKey},
+	Bytes,
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, NumberFor, Zero},
+};
+
+use self::subscription::SubscriptionManagement;
+pub use sc_rpc_api::chain_head::*;
+use sc_rpc_api::chain_head::{
+	error::Error,
+	event::{BestBlockChanged, Finalized, FollowEvent, Initialized, NewBlock},
+};
+
+/// Length of the identifiers of the follow subscriptions.
+const SUBSCRIPTION_ID_LEN: usize = 16;
+
+/// An API for the blocks at the head of the chain, pinned by the follow subscriptions.
+pub struct ChainHead<BE, Block: BlockT, Client> {
+	client: Arc<Client>,
+	backend: Arc<BE>,
+	executor: SubscriptionTaskExecutor,
+	subscriptions: Arc<SubscriptionManagement<Block, BE>>,
+	id_provider: crate::RandomStringSubscriptionId,
+	deny_unsafe: DenyUnsafe,
+	_phantom: PhantomData<Block>,
+}
+
+impl<BE, Block, Client> ChainHead<BE, Block, Client>
+where
+	Block: BlockT,
+	BE: Backend<Block>,
+{
+	/// Create a new [`ChainHead`].
+	pub fn new(
+		client: Arc<Client>,
+		backend: Arc<BE>,
+		executor: SubscriptionTaskExecutor,
+		deny_unsafe: DenyUnsafe,
+	) -> Self {
+		Self {
+			client,
+			subscriptions: Arc::new(SubscriptionManagement::new(backend.clone())),
+			backend,
+			executor,
+			id_provider: crate::RandomStringSubscriptionId::new(SUBSCRIPTION_ID_LEN),
+			deny_unsafe,
+			_phantom: PhantomData,
+		}
+	}
+
+	/// Generate the identifier of a new follow subscription.
+	fn next_subscription_id(&self) -> String {
+		match self.id_provider.next_id() {
+			SubscriptionId::Num(id) => id.to_string(),
+			SubscriptionId::Str(id) => id.into_owned(),
+		}
+	}
+}
+
+#[async_trait]
+impl<BE, Block, Client> ChainHeadApiServer<Block::Hash> for ChainHead<BE, Block, Client>
+where
+	Block: BlockT + 'static,
+	BE: Backend<Block> + 'static,
+	Client: BlockBackend<Block>
+		+ ExecutorProvider<Block>
+		+ HeaderBackend<Block>
+		+ BlockchainEvents<Block>
+		+ StorageProvider<Block, BE>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	fn follow(&self, pending: PendingSubscriptionSink) {
+		if let Err(err) = self.deny_unsafe.check_if_safe() {
+			spawn_subscription_task(&self.executor, pending.reject(err));
+			return
+		}
+
+		let subscription_id = self.next_subscription_id();
+		if !self.subscriptions.insert_subscription(subscription_id.clone()) {
+			let err = ErrorObject::owned(
+				CALL_EXECUTION_FAILED_CODE,
+				"Too many follow subscriptions",
+				None::<()>,
+			);
+			spawn_subscription_task(&self.executor, pending.reject(err));
+			return
+		}
+
+		// Subscribe to the notifications before reading the current blocks, so that none is
+		// missed. The blocks reported twice are filtered out by the pinning.
+		let notifications = stream::select(
+			self.client.import_notification_stream().map(Notification::Import),
+			self.client.finality_notification_stream().map(Notification::Finality),
+		)
+		.boxed();
+
+		let mut follow = Follow {
+			subscription_id: subscription_id.clone(),
+			client: self.client.clone(),
+			backend: self.backend.clone(),
+			subscriptions: self.subscriptions.clone(),
+			notifications,
+			pending: VecDeque::new(),
+			best_block: Default::default(),
+			finalized_number: Zero::zero(),
+			stopped: false,
+		};
+		follow.initialize();
+
+		let events = stream::unfold(follow, |mut follow| async move {
+			loop {
+				if let Some(event) = follow.pending.pop_front() {
+					return Some((event, follow))
+				}
+				if follow.stopped {
+					return None
+				}
+				let notification = follow.notifications.next().await?;
+				follow.on_notification(notification);
+			}
+		});
+
+		let subscriptions = self.subscriptions.clone();
+		let fut = async move {
+			pipe_from_stream(pending, events.boxed()).await;
+			subscriptions.remove_subscription(&subscription_id);
+		};
+
+		spawn_subscription_task(&self.executor, fut);
+	}
+
+	fn header(&self, follow_subscription: String, hash: Block::Hash) -> RpcResult<Option<Bytes>> {
+		self.subscriptions.ensure_pinned(&follow_subscription, &hash)?;
+		self.client
+			.header(BlockId::Hash(hash))
+			.map(|header| header.map(|header| header.encode().into()))
+			.map_err(|e| client_err(e).into())
+	}
+
+	fn body(
+		&self,
+		follow_subscription: String,
+		hash: Block::Hash,
+	) -> RpcResult<Option<Vec<Bytes>>> {
+		self.subscriptions.ensure_pinned(&follow_subscription, &hash)?;
+		self.client
+			.block_body(&BlockId::Hash(hash))
+			.map(|body| body.map(|body| body.iter().map(|ex| ex.encode().into()).collect()))
+			.map_err(|e| client_err(e).into())
+	}
+
+	fn storage(
+		&self,
+		follow_subscription: String,
+		hash: Block::Hash,
+		key: StorageKey,
+		child_key: Option<PrefixedStorageKey>,
+	) -> RpcResult<Option<StorageData>> {
+		self.subscriptions.ensure_pinned(&follow_subscription, &hash)?;
+		let block = BlockId::Hash(hash);
+		let value = match child_key {
+			Some(child_key) => {
+				let child_info = match ChildType::from_prefixed_key(&child_key) {
+					Some((ChildType::ParentKeyId, child_key)) => ChildInfo::new_default(child_key),
+					None => return Err(client_err(ClientError::InvalidChildStorageKey).into()),
+				};
+				self.client.child_storage(&block, &child_info, &key)
+			},
+			None => self.client.storage(&block, &key),
+		};
+		value.map_err(|e| client_err(e).into())
+	}
+
+	fn call(
+		&self,
+		follow_subscription: String,
+		hash: Block::Hash,
+		function: String,
+		call_parameters: Bytes,
+	) -> RpcResult<Bytes> {
+		self.subscriptions.ensure_pinned(&follow_subscription, &hash)?;
+		self.client
+			.executor()
+			.call(
+				&BlockId::Hash(hash),
+				&function,
+				&call_parameters,
+				self.client.execution_extensions().strategies().other,
+				None,
+			)
+			.map(Into::into)
+			.map_err(|e| client_err(e).into())
+	}
+
+	fn unpin(&self, follow_subscription: String, hash: Block::Hash) -> RpcResult<()> {
+		self.subscriptions.unpin_block(&follow_subscription, hash).map_err(Into::into)
+	}
+}
+
+/// Notification of the client followed by a subscription.
+enum Notification<Block: BlockT> {
+	Import(BlockImportNotification<Block>),
+	Finality(FinalityNotification<Block>),
+}
+
+/// State of a follow subscription, turning the notifications of the client into events.
+struct Follow<BE, Block: BlockT, Client> {
+	subscription_id: String,
+	client: Arc<Client>,
+	backend: Arc<BE>,
+	subscriptions: Arc<SubscriptionManagement<Block, BE>>,
+	notifications: BoxStream<'static, Notification<Block>>,
+	/// Events not sent yet.
+	pending: VecDeque<FollowEvent<Block::Hash>>,
+	/// Last best block reported.
+	best_block: Block::Hash,
+	/// Number of the last finalized block reported.
+	finalized_number: NumberFor<Block>,
+	/// Whether the subscription was stopped, once the pending events are sent.
+	stopped: bool,
+}
+
+impl<BE, Block, Client> Follow<BE, Block, Client>
+where
+	Block: BlockT,
+	BE: Backend<Block>,
+	Client: HeaderBackend<Block>,
+{
+	/// Report the last finalized block and all its descendants.
+	fn initialize(&mut self) {
+		let info = self.client.info();
+		if self.pin(info.finalized_hash).is_none() {
+			return
+		}
+		self.finalized_number = info.finalized_number;
+		self.pending.push_back(FollowEvent::Initialized(Initialized {
+			subscription_id: self.subscription_id.clone(),
+			finalized_block_hash: info.finalized_hash,
+		}));
+
+		let mut blocks = match self.descendants_of_finalized() {
+			Ok(blocks) => blocks,
+			Err(e) => {
+				log::warn!(target: "rpc", "Failed to read the unfinalized blocks: {}", e);
+				self.stop();
+				return
+			},
+		};
+		// Parents are reported before their children.
+		blocks.sort_by_key(|(number, ..)| *number);
+		for (_, hash, parent_hash) in blocks {
+			self.new_block(hash, parent_hash);
+		}
+		self.best_block_changed(info.best_hash);
+	}
+
+	/// The unfinalized blocks, as `(number, hash, parent_hash)`.
+	fn descendants_of_finalized(
+		&self,
+	) -> sp_blockchain::Result<Vec<(NumberFor<Block>, Block::Hash, Block::Hash)>> {
+		let mut blocks = Vec::new();
+		let mut seen = std::collections::HashSet::new();
+		for leaf in self.backend.blockchain().leaves()? {
+			let mut hash = leaf;
+			while seen.insert(hash) {
+				let header = match self.client.header(BlockId::Hash(hash))? {
+					Some(header) => header,
+					None => break,
+				};
+				if *header.number() <= self.finalized_number {
+					break
+				}
+				blocks.push((*header.number(), hash, *header.parent_hash()));
+				hash = *header.parent_hash();
+			}
+		}
+		Ok(blocks)
+	}
+
+	fn on_notification(&mut self, notification: Notification<Block>) {
+		match notification {
+			Notification::Import(notification) => {
+				// Blocks imported below the finalized one can't become part of the chain.
+				if *notification.header.number() <= self.finalized_number {
+					return
+				}
+				self.new_block(notification.hash, *notification.header.parent_hash());
+				if notification.is_new_best {
+					self.best_block_changed(notification.hash);
+				}
+			},
+			Notification::Finality(notification) => self.on_finality(notification),
+		}
+	}
+
+	fn on_finality(&mut self, notification: FinalityNotification<Block>) {
+		let finalized_block_hashes: Vec<_> =
+			notification.tree_route.iter().copied().chain(Some(notification.hash)).collect();
+
+		// The finality notification can overtake the import notifications of these blocks.
+		for hash in &finalized_block_hashes {
+			match self.client.header(BlockId::Hash(*hash)) {
+				Ok(Some(header)) => self.new_block(*hash, *header.parent_hash()),
+				Ok(None) => {},
+				Err(e) => log::warn!(
+					target: "rpc",
+					"Failed to read the header of finalized block {}: {}",
+					hash,
+					e
+				),
+			}
+		}
+		if self.stopped {
+			return
+		}
+
+		let mut pruned_block_hashes = Vec::new();
+		for head in notification.stale_heads.iter() {
+			// Walk the stale branch back down to the canonical chain.
+			let mut hash = *head;
+			while let Ok(Some(header)) = self.client.header(BlockId::Hash(hash)) {
+				if self.client.hash(*header.number()).ok().flatten() == Some(hash) ||
+					pruned_block_hashes.contains(&hash)
+				{
+					break
+				}
+				pruned_block_hashes.push(hash);
+				hash = *header.parent_hash();
+			}
+		}
+
+		self.finalized_number = *notification.header.number();
+		self.pending.push_back(FollowEvent::Finalized(Finalized {
+			finalized_block_hashes,
+			pruned_block_hashes,
+		}));
+
+		// The best block may have been on a pruned branch.
+		let best_hash = self.client.info().best_hash;
+		self.best_block_changed(best_hash);
+	}
+
+	/// Report a new block, unless it was already reported.
+	fn new_block(&mut self, hash: Block::Hash, parent_hash: Block::Hash) {
+		if self.stopped {
+			return
+		}
+		if self.pin(hash) == Some(true) {
+			self.pending.push_back(FollowEvent::NewBlock(NewBlock {
+				block_hash: hash,
+				parent_block_hash: parent_hash,
+			}));
+		}
+	}
+
+	fn best_block_changed(&mut self, hash: Block::Hash) {
+		if self.stopped || hash == self.best_block {
+			return
+		}
+		self.best_block = hash;
+		self.pending
+			.push_back(FollowEvent::BestBlockChanged(BestBlockChanged { best_block_hash: hash }));
+	}
+
+	/// Pin the block, stopping the subscription if it can't be. Returns whether the block was
+	/// newly pinned, or `None` if the subscription was stopped.
+	fn pin(&mut self, hash: Block::Hash) -> Option<bool> {
+		match self.subscriptions.pin_block(&self.subscription_id, hash) {
+			Ok(newly_pinned) => Some(newly_pinned),
+			Err(e) => {
+				log::debug!(target: "rpc", "Stopping follow subscription, failed to pin {}: {}", hash, e);
+				self.stop();
+				None
+			},
+		}
+	}
+
+	/// Stop the subscription, unpinning all its blocks.
+	fn stop(&mut self) {
+		self.subscriptions.remove_subscription(&self.subscription_id);
+		self.pending.push_back(FollowEvent::Stop);
+		self.stopped = true;
+	}
+}
+
+fn client_err(err: ClientError) -> Error {
+	Error::Client(Box::new(err))
+}
@@ -0,0 +1,127 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Bookkeeping of the blocks pinned by the follow subscriptions.
+
+use parking_lot::RwLock;
+use sc_client_api::Backend;
+use sc_rpc_api::chain_head::error::Error;
+use sp_runtime::traits::Block as BlockT;
+use std::{
+	collections::{HashMap, HashSet},
+	sync::Arc,
+};
+
+/// Maximum number of follow subscriptions at the same time.
+pub(crate) const MAX_FOLLOW_SUBSCRIPTIONS: usize = 32;
+
+/// Maximum number of blocks a follow subscription can pin. The subscription is stopped when it
+/// reaches this limit.
+pub(crate) const MAX_PINNED_BLOCKS: usize = 512;
+
+/// Reason a block can't be pinned by a follow subscription.
+#[derive(Debug)]
+pub(crate) enum PinError {
+	/// The subscription doesn't exist.
+	UnknownSubscription,
+	/// The subscription pins too many blocks.
+	TooManyPinnedBlocks,
+	/// The backend failed to pin the block.
+	Client(sp_blockchain::Error),
+}
+
+impl std::fmt::Display for PinError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::UnknownSubscription => write!(f, "unknown subscription"),
+			Self::TooManyPinnedBlocks => write!(f, "too many pinned blocks"),
+			Self::Client(e) => write!(f, "client error: {}", e),
+		}
+	}
+}
+
+/// Follow subscriptions and the blocks they pin in the backend.
+pub(crate) struct SubscriptionManagement<Block: BlockT, BE> {
+	subscriptions: RwLock<HashMap<String, HashSet<Block::Hash>>>,
+	backend: Arc<BE>,
+}
+
+impl<Block, BE> SubscriptionManagement<Block, BE>
+where
+	Block: BlockT,
+	BE: Backend<Block>,
+{
+	/// Create a new instance without any subscription.
+	pub fn new(backend: Arc<BE>) -> Self {
+		Self { subscriptions: Default::default(), backend }
+	}
+
+	/// Start tracking a subscription. Returns `false` if there are too many subscriptions.
+	pub fn insert_subscription(&self, id: String) -> bool {
+		let mut subscriptions = self.subscriptions.write();
+		if subscriptions.len() >= MAX_FOLLOW_SUBSCRIPTIONS {
+			return false
+		}
+		subscriptions.insert(id, Default::default());
+		true
+	}
+
+	/// Stop tracking a subscription, releasing all the blocks it pins.
+	pub fn remove_subscription(&self, id: &str) {
+		let blocks = self.subscriptions.write().remove(id);
+		for hash in blocks.into_iter().flatten() {
+			self.backend.unpin_block(hash);
+		}
+	}
+
+	/// Pin a block for the subscription. Returns `false` if the subscription already pins it.
+	pub fn pin_block(&self, id: &str, hash: Block::Hash) -> Result<bool, PinError> {
+		let mut subscriptions = self.subscriptions.write();
+		let blocks = subscriptions.get_mut(id).ok_or(PinError::UnknownSubscription)?;
+		if blocks.contains(&hash) {
+			return Ok(false)
+		}
+		if blocks.len() >= MAX_PINNED_BLOCKS {
+			return Err(PinError::TooManyPinnedBlocks)
+		}
+		self.backend.pin_block(hash).map_err(PinError::Client)?;
+		blocks.insert(hash);
+		Ok(true)
+	}
+
+	/// Unpin a block pinned by the subscription.
+	pub fn unpin_block(&self, id: &str, hash: Block::Hash) -> Result<(), Error> {
+		let mut subscriptions = self.subscriptions.write();
+		let blocks = subscriptions.get_mut(id).ok_or(Error::InvalidSubscription)?;
+		if !blocks.remove(&hash) {
+			return Err(Error::InvalidBlock(hash.to_string()))
+		}
+		self.backend.unpin_block(hash);
+		Ok(())
+	}
+
+	/// Check that the block is pinned by the subscription.
+	pub fn ensure_pinned(&self, id: &str, hash: &Block::Hash) -> Result<(), Error> {
+		let subscriptions = self.subscriptions.read();
+		let blocks = subscriptions.get(id).ok_or(Error::InvalidSubscription)?;
+		if !blocks.contains(hash) {
+			return Err(Error::InvalidBlock(hash.to_string()))
+		}
+		Ok(())
+	}
+}
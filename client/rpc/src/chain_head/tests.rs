@@ -0,0 +1,146 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use super::*;
+use crate::testing::{test_executor, timeout_secs};
+use assert_matches::assert_matches;
+use jsonrpsee::{
+	core::{
+		server::{MethodsError, Subscription},
+		EmptyServerParams,
+	},
+	RpcModule,
+};
+use sc_block_builder::BlockBuilderProvider;
+use sp_consensus::BlockOrigin;
+use sp_core::storage::well_known_keys;
+use substrate_test_runtime_client::{prelude::*, runtime::H256};
+
+async fn next_event(sub: &mut Subscription) -> FollowEvent<H256> {
+	let (event, _) = timeout_secs(10, sub.next::<FollowEvent<H256>>())
+		.await
+		.expect("Event received in time")
+		.expect("Subscription open")
+		.expect("Valid event");
+	event
+}
+
+async fn setup() -> (Arc<TestClient>, RpcModule<()>, Subscription, String) {
+	let builder = TestClientBuilder::new();
+	let backend = builder.backend();
+	let client = Arc::new(builder.build());
+
+	let api = ChainHead::new(client.clone(), backend, test_executor(), DenyUnsafe::No).into_rpc();
+	let mut sub = api
+		.subscribe_unbounded("chainHead_follow", EmptyServerParams::new())
+		.await
+		.unwrap();
+
+	let subscription_id = match next_event(&mut sub).await {
+		FollowEvent::Initialized(Initialized { subscription_id, finalized_block_hash }) => {
+			assert_eq!(finalized_block_hash, client.genesis_hash());
+			subscription_id
+		},
+		event => panic!("Unexpected event {:?}", event),
+	};
+	assert_eq!(
+		next_event(&mut sub).await,
+		FollowEvent::BestBlockChanged(BestBlockChanged { best_block_hash: client.genesis_hash() }),
+	);
+
+	let mut module = RpcModule::new(());
+	module.merge(api).unwrap();
+	(client, module, sub, subscription_id)
+}
+
+#[tokio::test]
+async fn follow_is_rpc_unsafe() {
+	let builder = TestClientBuilder::new();
+	let backend = builder.backend();
+	let client = Arc::new(builder.build());
+
+	let api = ChainHead::new(client, backend, test_executor(), DenyUnsafe::Yes).into_rpc();
+	let err = api.subscribe_unbounded("chainHead_follow", EmptyServerParams::new()).await;
+	assert_matches!(err, Err(MethodsError::JsonRpc(e)) if e.message() == "RPC call is unsafe to be called externally");
+}
+
+#[tokio::test]
+async fn follow_reports_and_pins_new_blocks() {
+	let (mut client, api, mut sub, subscription_id) = setup().await;
+
+	let block = client.new_block(Default::default()).unwrap().build().unwrap().block;
+	let hash = block.header.hash();
+	let header = block.header.clone();
+	client.import(BlockOrigin::Own, block).await.unwrap();
+
+	assert_eq!(
+		next_event(&mut sub).await,
+		FollowEvent::NewBlock(NewBlock {
+			block_hash: hash,
+			parent_block_hash: client.genesis_hash(),
+		}),
+	);
+	assert_eq!(
+		next_event(&mut sub).await,
+		FollowEvent::BestBlockChanged(BestBlockChanged { best_block_hash: hash }),
+	);
+
+	let res: Option<Bytes> = api.call("chainHead_header", (&subscription_id, hash)).await.unwrap();
+	assert_eq!(res, Some(header.encode().into()));
+
+	let res: Option<Vec<Bytes>> =
+		api.call("chainHead_body", (&subscription_id, hash)).await.unwrap();
+	assert_eq!(res, Some(vec![]));
+
+	let res: Option<StorageData> = api
+		.call(
+			"chainHead_storage",
+			(&subscription_id, hash, StorageKey(well_known_keys::CODE.to_vec()), None::<()>),
+		)
+		.await
+		.unwrap();
+	assert!(res.is_some());
+
+	client.finalize_block(BlockId::Hash(hash), None).unwrap();
+	assert_eq!(
+		next_event(&mut sub).await,
+		FollowEvent::Finalized(Finalized {
+			finalized_block_hashes: vec![hash],
+			pruned_block_hashes: vec![],
+		}),
+	);
+}
+
+#[tokio::test]
+async fn methods_only_accept_pinned_blocks() {
+	let (client, api, _sub, subscription_id) = setup().await;
+	let genesis = client.genesis_hash();
+
+	assert!(api.call::<_, Bytes>("chainHead_header", ("unknown", genesis)).await.is_err());
+	assert!(api
+		.call::<_, Bytes>("chainHead_header", (&subscription_id, H256::repeat_byte(1)))
+		.await
+		.is_err());
+
+	api.call::<_, ()>("chainHead_unpin", (&subscription_id, genesis)).await.unwrap();
+	assert!(api
+		.call::<_, Bytes>("chainHead_header", (&subscription_id, genesis))
+		.await
+		.is_err());
+	assert!(api.call::<_, ()>("chainHead_unpin", (&subscription_id, genesis)).await.is_err());
+}
@@ -22,6 +22,8 @@
 
 #![warn(missing_docs)]
 
+use futures::{Future, FutureExt};
+
 pub use jsonrpsee::core::{
 	id_providers::{
 		RandomIntegerIdProvider as RandomIntegerSubscriptionId,
@@ -31,15 +33,26 @@ pub use jsonrpsee::core::{
 };
 pub use sc_rpc_api::DenyUnsafe;
 
+pub mod archive;
 pub mod author;
 pub mod chain;
+pub mod chain_head;
 pub mod dev;
 pub mod offchain;
 pub mod state;
 pub mod system;
+pub mod utils;
 
 #[cfg(any(test, feature = "test-helpers"))]
 pub mod testing;
 
 /// Task executor that is being used by RPC subscriptions.
 pub type SubscriptionTaskExecutor = std::sync::Arc<dyn sp_core::traits::SpawnNamed>;
+
+/// Spawn a task of an RPC subscription on the [`SubscriptionTaskExecutor`].
+pub fn spawn_subscription_task(
+	executor: &SubscriptionTaskExecutor,
+	fut: impl Future<Output = ()> + Send + 'static,
+) {
+	executor.spawn("substrate-rpc-subscription", Some("rpc"), fut.boxed());
+}
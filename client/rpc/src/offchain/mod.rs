@@ -22,7 +22,10 @@
 mod tests;
 
 use self::error::Error;
-use jsonrpsee::core::{async_trait, Error as JsonRpseeError, RpcResult};
+use jsonrpsee::{
+	core::{async_trait, RpcResult},
+	types::ErrorObjectOwned,
+};
 use parking_lot::RwLock;
 /// Re-export the API for backward compatibility.
 pub use sc_rpc_api::offchain::*;
@@ -55,7 +58,7 @@ impl<T: OffchainStorage + 'static> OffchainApiServer for Offchain<T> {
 
 		let prefix = match kind {
 			StorageKind::PERSISTENT => sp_offchain::STORAGE_PREFIX,
-			StorageKind::LOCAL => return Err(JsonRpseeError::from(Error::UnavailableStorageKind)),
+			StorageKind::LOCAL => return Err(ErrorObjectOwned::from(Error::UnavailableStorageKind)),
 		};
 		self.storage.write().set(prefix, &key, &value);
 		Ok(())
@@ -66,7 +69,7 @@ impl<T: OffchainStorage + 'static> OffchainApiServer for Offchain<T> {
 
 		let prefix = match kind {
 			StorageKind::PERSISTENT => sp_offchain::STORAGE_PREFIX,
-			StorageKind::LOCAL => return Err(JsonRpseeError::from(Error::UnavailableStorageKind)),
+			StorageKind::LOCAL => return Err(ErrorObjectOwned::from(Error::UnavailableStorageKind)),
 		};
 
 		Ok(self.storage.read().get(prefix, &key).map(Into::into))
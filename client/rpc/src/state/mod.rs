@@ -19,18 +19,20 @@
 //! Substrate state API.
 
 mod state_full;
+mod state_light;
 
 #[cfg(test)]
 mod tests;
 
 use std::sync::Arc;
 
-use crate::SubscriptionTaskExecutor;
+use crate::{spawn_subscription_task, SubscriptionTaskExecutor};
 
+use futures::future::BoxFuture;
 use jsonrpsee::{
-	core::{Error as JsonRpseeError, RpcResult},
-	types::SubscriptionResult,
-	ws_server::SubscriptionSink,
+	core::{async_trait, RpcResult},
+	types::ErrorObjectOwned,
+	PendingSubscriptionSink,
 };
 
 use sc_rpc_api::{state::ReadProof, DenyUnsafe};
@@ -46,13 +48,17 @@ use sp_api::{CallApiAt, Metadata, ProvideRuntimeApi};
 use self::error::Error;
 
 use sc_client_api::{
-	Backend, BlockBackend, BlockchainEvents, ExecutorProvider, ProofProvider, StorageProvider,
+	light::RemoteBackend, Backend, BlockBackend, BlockchainEvents, ExecutorProvider, ProofProvider,
+	StorageProvider,
 };
 pub use sc_rpc_api::{child_state::*, state::*};
 use sp_blockchain::{HeaderBackend, HeaderMetadata};
 
 const STORAGE_KEYS_PAGED_MAX_COUNT: u32 = 1000;
 
+/// Result of a state backend call that may wait on other tasks, or on the network.
+pub type FutureResult<T> = BoxFuture<'static, Result<T, Error>>;
+
 /// State backend API.
 pub trait StateBackend<Block: BlockT, Client>: Send + Sync + 'static
 where
@@ -65,7 +71,7 @@ where
 		block: Option<Block::Hash>,
 		method: String,
 		call_data: Bytes,
-	) -> Result<Bytes, Error>;
+	) -> FutureResult<Bytes>;
 
 	/// Returns the keys with prefix, leave empty to get all the keys.
 	fn storage_keys(
@@ -90,19 +96,28 @@ where
 		start_key: Option<StorageKey>,
 	) -> Result<Vec<StorageKey>, Error>;
 
+	/// Returns the keys with prefix along with their values, with pagination support.
+	fn storage_paged(
+		&self,
+		block: Option<Block::Hash>,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+	) -> Result<StoragePage, Error>;
+
 	/// Returns a storage entry at a specific block's state.
 	fn storage(
 		&self,
 		block: Option<Block::Hash>,
 		key: StorageKey,
-	) -> Result<Option<StorageData>, Error>;
+	) -> FutureResult<Option<StorageData>>;
 
 	/// Returns the hash of a storage entry at a block's state.
 	fn storage_hash(
 		&self,
 		block: Option<Block::Hash>,
 		key: StorageKey,
-	) -> Result<Option<Block::Hash>, Error>;
+	) -> FutureResult<Option<Block::Hash>>;
 
 	/// Returns the size of a storage entry at a block's state.
 	///
@@ -112,13 +127,13 @@ where
 		&self,
 		block: Option<Block::Hash>,
 		key: StorageKey,
-	) -> Result<Option<u64>, Error>;
+	) -> FutureResult<Option<u64>>;
 
 	/// Returns the runtime metadata as an opaque blob.
-	fn metadata(&self, block: Option<Block::Hash>) -> Result<Bytes, Error>;
+	fn metadata(&self, block: Option<Block::Hash>) -> FutureResult<Bytes>;
 
 	/// Get the runtime version.
-	fn runtime_version(&self, block: Option<Block::Hash>) -> Result<RuntimeVersion, Error>;
+	fn runtime_version(&self, block: Option<Block::Hash>) -> FutureResult<RuntimeVersion>;
 
 	/// Query historical storage entries (by key) starting from a block given as the second
 	/// parameter.
@@ -132,12 +147,21 @@ where
 		keys: Vec<StorageKey>,
 	) -> Result<Vec<StorageChangeSet<Block::Hash>>, Error>;
 
+	/// Returns the numbers of the blocks after `from`, up to `to` or the best block, which
+	/// changed the value of `key`.
+	fn key_history(
+		&self,
+		from: Block::Hash,
+		to: Option<Block::Hash>,
+		key: StorageKey,
+	) -> Result<Vec<u64>, Error>;
+
 	/// Query storage entries (by key) starting at block hash given as the second parameter.
 	fn query_storage_at(
 		&self,
 		keys: Vec<StorageKey>,
 		at: Option<Block::Hash>,
-	) -> Result<Vec<StorageChangeSet<Block::Hash>>, Error>;
+	) -> FutureResult<Vec<StorageChangeSet<Block::Hash>>>;
 
 	/// Returns proof of storage entries at a specific block's state.
 	fn read_proof(
@@ -156,18 +180,24 @@ where
 	) -> Result<sp_rpc::tracing::TraceBlockResponse, Error>;
 
 	/// New runtime version subscription
-	fn subscribe_runtime_version(&self, sink: SubscriptionSink);
+	fn subscribe_runtime_version(&self, pending: PendingSubscriptionSink);
 
 	/// New storage subscription
-	fn subscribe_storage(&self, sink: SubscriptionSink, keys: Option<Vec<StorageKey>>);
+	fn subscribe_storage(&self, pending: PendingSubscriptionSink, keys: Option<Vec<StorageKey>>);
 }
 
 /// Create new state API that works on full node.
+///
+/// `max_response_size` bounds, in bytes, the storage returned by the calls whose responses grow
+/// with the state: `state_getKeys`, `state_getPairs`, `state_getStoragePaged` and
+/// `state_queryStorage*`. Those fail early rather than collecting more than can be sent, the other
+/// responses are only bounded by the RPC server once serialized.
 pub fn new_full<BE, Block: BlockT, Client>(
 	client: Arc<Client>,
 	executor: SubscriptionTaskExecutor,
 	deny_unsafe: DenyUnsafe,
 	rpc_max_payload: Option<usize>,
+	max_response_size: Option<usize>,
 ) -> (State<Block, Client>, ChildState<Block, Client>)
 where
 	Block: BlockT + 'static,
@@ -191,25 +221,61 @@ where
 		client.clone(),
 		executor.clone(),
 		rpc_max_payload,
+		max_response_size,
 	));
-	let backend = Box::new(self::state_full::FullState::new(client, executor, rpc_max_payload));
-	(State { backend, deny_unsafe }, ChildState { backend: child_backend })
+	let backend = Box::new(self::state_full::FullState::new(
+		client,
+		executor.clone(),
+		rpc_max_payload,
+		max_response_size,
+	));
+	(State { backend, executor, deny_unsafe }, ChildState { backend: child_backend })
+}
+
+/// Create new state API that works on light node, reading the state from full nodes through
+/// `remote_backend`.
+pub fn new_light<Block, Client>(
+	client: Arc<Client>,
+	executor: SubscriptionTaskExecutor,
+	remote_backend: Arc<dyn RemoteBackend<Block>>,
+	deny_unsafe: DenyUnsafe,
+) -> (State<Block, Client>, ChildState<Block, Client>)
+where
+	Block: BlockT + 'static,
+	Client: HeaderBackend<Block> + Send + Sync + 'static,
+{
+	let child_backend = Box::new(self::state_light::LightState::new(
+		client.clone(),
+		executor.clone(),
+		remote_backend.clone(),
+	));
+	let backend =
+		Box::new(self::state_light::LightState::new(client, executor.clone(), remote_backend));
+	(State { backend, executor, deny_unsafe }, ChildState { backend: child_backend })
 }
 
 /// State API with subscriptions support.
 pub struct State<Block, Client> {
 	backend: Box<dyn StateBackend<Block, Client>>,
+	/// Executor of the subscription tasks.
+	executor: SubscriptionTaskExecutor,
 	/// Whether to deny unsafe calls
 	deny_unsafe: DenyUnsafe,
 }
 
+#[async_trait]
 impl<Block, Client> StateApiServer<Block::Hash> for State<Block, Client>
 where
 	Block: BlockT + 'static,
 	Client: Send + Sync + 'static,
 {
-	fn call(&self, method: String, data: Bytes, block: Option<Block::Hash>) -> RpcResult<Bytes> {
-		self.backend.call(block, method, data).map_err(Into::into)
+	async fn call(
+		&self,
+		method: String,
+		data: Bytes,
+		block: Option<Block::Hash>,
+	) -> RpcResult<Bytes> {
+		self.backend.call(block, method, data).await.map_err(Into::into)
 	}
 
 	fn storage_keys(
@@ -237,7 +303,7 @@ where
 		block: Option<Block::Hash>,
 	) -> RpcResult<Vec<StorageKey>> {
 		if count > STORAGE_KEYS_PAGED_MAX_COUNT {
-			return Err(JsonRpseeError::from(Error::InvalidCount {
+			return Err(ErrorObjectOwned::from(Error::InvalidCount {
 				value: count,
 				max: STORAGE_KEYS_PAGED_MAX_COUNT,
 			}))
@@ -247,32 +313,52 @@ where
 			.map_err(Into::into)
 	}
 
-	fn storage(
+	fn storage_paged(
+		&self,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+		block: Option<Block::Hash>,
+	) -> RpcResult<StoragePage> {
+		if count > STORAGE_KEYS_PAGED_MAX_COUNT {
+			return Err(ErrorObjectOwned::from(Error::InvalidCount {
+				value: count,
+				max: STORAGE_KEYS_PAGED_MAX_COUNT,
+			}))
+		}
+		self.backend.storage_paged(block, prefix, count, start_key).map_err(Into::into)
+	}
+
+	async fn storage(
 		&self,
 		key: StorageKey,
 		block: Option<Block::Hash>,
 	) -> RpcResult<Option<StorageData>> {
-		self.backend.storage(block, key).map_err(Into::into)
+		self.backend.storage(block, key).await.map_err(Into::into)
 	}
 
-	fn storage_hash(
+	async fn storage_hash(
 		&self,
 		key: StorageKey,
 		block: Option<Block::Hash>,
 	) -> RpcResult<Option<Block::Hash>> {
-		self.backend.storage_hash(block, key).map_err(Into::into)
+		self.backend.storage_hash(block, key).await.map_err(Into::into)
 	}
 
-	fn storage_size(&self, key: StorageKey, block: Option<Block::Hash>) -> RpcResult<Option<u64>> {
-		self.backend.storage_size(block, key).map_err(Into::into)
+	async fn storage_size(
+		&self,
+		key: StorageKey,
+		block: Option<Block::Hash>,
+	) -> RpcResult<Option<u64>> {
+		self.backend.storage_size(block, key).await.map_err(Into::into)
 	}
 
-	fn metadata(&self, block: Option<Block::Hash>) -> RpcResult<Bytes> {
-		self.backend.metadata(block).map_err(Into::into)
+	async fn metadata(&self, block: Option<Block::Hash>) -> RpcResult<Bytes> {
+		self.backend.metadata(block).await.map_err(Into::into)
 	}
 
-	fn runtime_version(&self, at: Option<Block::Hash>) -> RpcResult<RuntimeVersion> {
-		self.backend.runtime_version(at).map_err(Into::into)
+	async fn runtime_version(&self, at: Option<Block::Hash>) -> RpcResult<RuntimeVersion> {
+		self.backend.runtime_version(at).await.map_err(Into::into)
 	}
 
 	fn query_storage(
@@ -285,12 +371,22 @@ where
 		self.backend.query_storage(from, to, keys).map_err(Into::into)
 	}
 
-	fn query_storage_at(
+	fn key_history(
+		&self,
+		key: StorageKey,
+		from: Block::Hash,
+		to: Option<Block::Hash>,
+	) -> RpcResult<Vec<u64>> {
+		self.deny_unsafe.check_if_safe()?;
+		self.backend.key_history(from, to, key).map_err(Into::into)
+	}
+
+	async fn query_storage_at(
 		&self,
 		keys: Vec<StorageKey>,
 		at: Option<Block::Hash>,
 	) -> RpcResult<Vec<StorageChangeSet<Block::Hash>>> {
-		self.backend.query_storage_at(keys, at).map_err(Into::into)
+		self.backend.query_storage_at(keys, at).await.map_err(Into::into)
 	}
 
 	fn read_proof(
@@ -319,25 +415,19 @@ where
 			.map_err(Into::into)
 	}
 
-	fn subscribe_runtime_version(&self, sink: SubscriptionSink) -> SubscriptionResult {
-		self.backend.subscribe_runtime_version(sink);
-		Ok(())
+	fn subscribe_runtime_version(&self, pending: PendingSubscriptionSink) {
+		self.backend.subscribe_runtime_version(pending)
 	}
 
-	fn subscribe_storage(
-		&self,
-		mut sink: SubscriptionSink,
-		keys: Option<Vec<StorageKey>>,
-	) -> SubscriptionResult {
+	fn subscribe_storage(&self, pending: PendingSubscriptionSink, keys: Option<Vec<StorageKey>>) {
 		if keys.is_none() {
 			if let Err(err) = self.deny_unsafe.check_if_safe() {
-				let _ = sink.reject(JsonRpseeError::from(err));
-				return Ok(())
+				spawn_subscription_task(&self.executor, pending.reject(err));
+				return
 			}
 		}
 
-		self.backend.subscribe_storage(sink, keys);
-		Ok(())
+		self.backend.subscribe_storage(pending, keys)
 	}
 }
 
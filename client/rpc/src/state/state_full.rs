@@ -18,22 +18,26 @@
 
 //! State API backend for full nodes.
 
-use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+use std::{
+	collections::{BTreeMap, HashMap},
+	marker::PhantomData,
+	sync::Arc,
+};
 
 use super::{
 	client_err,
 	error::{Error, Result},
-	ChildStateBackend, StateBackend,
+	ChildStateBackend, FutureResult, StateBackend,
 };
-use crate::SubscriptionTaskExecutor;
+use crate::{spawn_subscription_task, utils::pipe_from_stream, SubscriptionTaskExecutor};
 
-use futures::{future, stream, FutureExt, StreamExt};
-use jsonrpsee::{core::Error as JsonRpseeError, SubscriptionSink};
+use futures::{channel::oneshot, future, stream, FutureExt, StreamExt};
+use jsonrpsee::PendingSubscriptionSink;
 use sc_client_api::{
 	Backend, BlockBackend, BlockchainEvents, CallExecutor, ExecutorProvider, ProofProvider,
 	StorageProvider,
 };
-use sc_rpc_api::state::ReadProof;
+use sc_rpc_api::state::{ReadProof, StoragePage};
 use sp_api::{CallApiAt, Metadata, ProvideRuntimeApi};
 use sp_blockchain::{
 	CachedHeaderMetadata, Error as ClientError, HeaderBackend, HeaderMetadata,
@@ -45,13 +49,18 @@ use sp_core::{
 	},
 	Bytes,
 };
-use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor, One, SaturatedConversion},
+};
 use sp_version::RuntimeVersion;
 
 /// Ranges to query in state_queryStorage.
 struct QueryStorageRange<Block: BlockT> {
 	/// Hashes of all the blocks in the range.
 	pub hashes: Vec<Block::Hash>,
+	/// Number of the first block of the range.
+	pub first_number: NumberFor<Block>,
 }
 
 /// State API backend for full nodes.
@@ -60,6 +69,20 @@ pub struct FullState<BE, Block: BlockT, Client> {
 	executor: SubscriptionTaskExecutor,
 	_phantom: PhantomData<(BE, Block)>,
 	rpc_max_payload: Option<usize>,
+	/// Maximum size of the storage returned by a call, in bytes.
+	max_response_size: Option<usize>,
+}
+
+impl<BE, Block: BlockT, Client> Clone for FullState<BE, Block, Client> {
+	fn clone(&self) -> Self {
+		Self {
+			client: self.client.clone(),
+			executor: self.executor.clone(),
+			_phantom: PhantomData,
+			rpc_max_payload: self.rpc_max_payload,
+			max_response_size: self.max_response_size,
+		}
+	}
 }
 
 impl<BE, Block: BlockT, Client> FullState<BE, Block, Client>
@@ -76,8 +99,45 @@ where
 		client: Arc<Client>,
 		executor: SubscriptionTaskExecutor,
 		rpc_max_payload: Option<usize>,
+		max_response_size: Option<usize>,
 	) -> Self {
-		Self { client, executor, _phantom: PhantomData, rpc_max_payload }
+		Self { client, executor, _phantom: PhantomData, rpc_max_payload, max_response_size }
+	}
+
+	/// Account for `len` more bytes in a response of `size` bytes, failing if it exceeds the
+	/// maximum response size.
+	fn add_to_response(&self, size: &mut usize, len: usize) -> Result<()> {
+		*size += len;
+		match self.max_response_size {
+			Some(max) if *size > max => Err(Error::ResponseTooLarge { max }),
+			_ => Ok(()),
+		}
+	}
+
+	/// Run `f` on a blocking task, so that reading the state or calling the runtime doesn't hold up
+	/// the RPC server.
+	fn spawn_blocking<R, F>(&self, f: F) -> FutureResult<R>
+	where
+		Self: Send + 'static,
+		R: Send + 'static,
+		F: FnOnce(&Self) -> Result<R> + Send + 'static,
+	{
+		let state = self.clone();
+		let (tx, rx) = oneshot::channel();
+		self.executor.spawn_blocking(
+			"substrate-rpc-state",
+			Some("rpc"),
+			async move {
+				let _ = tx.send(f(&state));
+			}
+			.boxed(),
+		);
+		rx.map(|result| {
+			result.unwrap_or_else(|_| {
+				Err(client_err(ClientError::Backend("The state RPC task was cancelled".into())))
+			})
+		})
+		.boxed()
 	}
 
 	/// Returns given block hash or best block hash if None is passed.
@@ -132,7 +192,7 @@ where
 			hashes
 		};
 
-		Ok(QueryStorageRange { hashes })
+		Ok(QueryStorageRange { hashes, first_number: from_number })
 	}
 
 	/// Iterates through range.unfiltered_range and check each block for changes of keys' values.
@@ -143,6 +203,7 @@ where
 		last_values: &mut HashMap<StorageKey, Option<StorageData>>,
 		changes: &mut Vec<StorageChangeSet<Block::Hash>>,
 	) -> Result<()> {
+		let mut size = 0;
 		for block_hash in &range.hashes {
 			let mut block_changes = StorageChangeSet { block: *block_hash, changes: Vec::new() };
 			let id = BlockId::hash(*block_hash);
@@ -155,6 +216,7 @@ where
 					}
 				};
 				if has_changed {
+					self.add_to_response(&mut size, change_len(key, &data))?;
 					block_changes.changes.push((key.clone(), data.clone()));
 				}
 				last_values.insert(key.clone(), data);
@@ -165,6 +227,80 @@ where
 		}
 		Ok(())
 	}
+
+	/// Lists the changes of the values of `keys` over the range with the changes index, reading
+	/// the keys only at the blocks which modified them. Returns the position in the range of each
+	/// block changing them, or `None` if the blocks of the range are not indexed.
+	fn query_storage_indexed(
+		&self,
+		range: &QueryStorageRange<Block>,
+		keys: &[StorageKey],
+	) -> Result<Option<Vec<(usize, Vec<(StorageKey, Option<StorageData>)>)>>> {
+		let index = match self.client.changes_index() {
+			Some(index) => index,
+			None => return Ok(None),
+		};
+		let first = range.first_number;
+		match index.indexed_from() {
+			Ok(indexed_from) if indexed_from <= first + One::one() => (),
+			_ => return Ok(None),
+		}
+		let position = |number: NumberFor<Block>| (number - first).saturated_into::<usize>();
+
+		// Keys to read at each block, the first block being read in full.
+		let mut candidates: BTreeMap<usize, Vec<&StorageKey>> = BTreeMap::new();
+		candidates.insert(0, keys.iter().collect());
+
+		// The changes by finalized blocks are listed from the history of each key, provided the
+		// range follows the finalized chain.
+		let last = first + (range.hashes.len() - 1).saturated_into::<NumberFor<Block>>();
+		let finalized = std::cmp::min(self.client.info().finalized_number, last);
+		let mut unfinalized = 1;
+		if finalized > first &&
+			self.client.hash(finalized).map_err(client_err)? ==
+				Some(range.hashes[position(finalized)])
+		{
+			for key in keys {
+				for number in index
+					.key_changes(first + One::one(), finalized, None, &key.0)
+					.map_err(client_err)?
+				{
+					candidates.entry(position(number)).or_default().push(key);
+				}
+			}
+			unfinalized = position(finalized) + 1;
+		}
+		for (position, hash) in range.hashes.iter().enumerate().skip(unfinalized) {
+			let modified: Vec<_> = match index.block_changes(hash).map_err(client_err)? {
+				Some(block_changes) =>
+					keys.iter().filter(|key| block_changes.contains(None, &key.0)).collect(),
+				None => keys.iter().collect(),
+			};
+			if !modified.is_empty() {
+				candidates.entry(position).or_default().extend(modified);
+			}
+		}
+
+		let mut size = 0;
+		let mut changes = Vec::new();
+		let mut last_values = HashMap::new();
+		for (position, keys) in candidates {
+			let id = BlockId::hash(range.hashes[position]);
+			let mut block_changes = Vec::new();
+			for key in keys {
+				let data = self.client.storage(&id, key).map_err(client_err)?;
+				if last_values.get(key) != Some(&data) {
+					self.add_to_response(&mut size, change_len(key, &data))?;
+					block_changes.push((key.clone(), data.clone()));
+				}
+				last_values.insert(key, data);
+			}
+			if !block_changes.is_empty() {
+				changes.push((position, block_changes));
+			}
+		}
+		Ok(Some(changes))
+	}
 }
 
 impl<BE, Block, Client> StateBackend<Block, Client> for FullState<BE, Block, Client>
@@ -191,21 +327,25 @@ where
 		block: Option<Block::Hash>,
 		method: String,
 		call_data: Bytes,
-	) -> std::result::Result<Bytes, Error> {
-		self.block_or_best(block)
-			.and_then(|block| {
-				self.client
-					.executor()
-					.call(
-						&BlockId::Hash(block),
-						&method,
-						&call_data,
-						self.client.execution_extensions().strategies().other,
-						None,
-					)
-					.map(Into::into)
-			})
-			.map_err(client_err)
+	) -> FutureResult<Bytes> {
+		self.spawn_blocking(move |state| {
+			state
+				.block_or_best(block)
+				.and_then(|block| {
+					state
+						.client
+						.executor()
+						.call(
+							&BlockId::Hash(block),
+							&method,
+							&call_data,
+							state.client.execution_extensions().strategies().other,
+							None,
+						)
+						.map(Into::into)
+				})
+				.map_err(client_err)
+		})
 	}
 
 	fn storage_keys(
@@ -213,9 +353,19 @@ where
 		block: Option<Block::Hash>,
 		prefix: StorageKey,
 	) -> std::result::Result<Vec<StorageKey>, Error> {
-		self.block_or_best(block)
-			.and_then(|block| self.client.storage_keys(&BlockId::Hash(block), &prefix))
-			.map_err(client_err)
+		let id = BlockId::Hash(self.block_or_best(block).map_err(client_err)?);
+		// The iteration starts after the prefix, which can be a key itself.
+		let exact_match =
+			self.client.storage(&id, &prefix).map_err(client_err)?.map(|_| prefix.clone());
+		let iter = self.client.storage_keys_iter(&id, Some(&prefix), None).map_err(client_err)?;
+
+		let mut size = 0;
+		let mut keys = Vec::new();
+		for key in exact_match.into_iter().chain(iter) {
+			self.add_to_response(&mut size, encoded_len(&key.0))?;
+			keys.push(key);
+		}
+		Ok(keys)
 	}
 
 	fn storage_pairs(
@@ -223,9 +373,23 @@ where
 		block: Option<Block::Hash>,
 		prefix: StorageKey,
 	) -> std::result::Result<Vec<(StorageKey, StorageData)>, Error> {
-		self.block_or_best(block)
-			.and_then(|block| self.client.storage_pairs(&BlockId::Hash(block), &prefix))
-			.map_err(client_err)
+		let id = BlockId::Hash(self.block_or_best(block).map_err(client_err)?);
+		// The iteration starts after the prefix, which can be a key itself.
+		let exact_match =
+			self.client.storage(&id, &prefix).map_err(client_err)?.map(|_| prefix.clone());
+		let iter = self.client.storage_keys_iter(&id, Some(&prefix), None).map_err(client_err)?;
+
+		let mut size = 0;
+		let mut pairs = Vec::new();
+		for key in exact_match.into_iter().chain(iter) {
+			let value = match self.client.storage(&id, &key).map_err(client_err)? {
+				Some(value) => value,
+				None => continue,
+			};
+			self.add_to_response(&mut size, encoded_len(&key.0) + encoded_len(&value.0))?;
+			pairs.push((key, value));
+		}
+		Ok(pairs)
 	}
 
 	fn storage_keys_paged(
@@ -247,73 +411,130 @@ where
 			.map_err(client_err)
 	}
 
+	fn storage_paged(
+		&self,
+		block: Option<Block::Hash>,
+		prefix: Option<StorageKey>,
+		count: u32,
+		start_key: Option<StorageKey>,
+	) -> std::result::Result<StoragePage, Error> {
+		let id = BlockId::Hash(self.block_or_best(block).map_err(client_err)?);
+		let mut keys = self
+			.client
+			.storage_keys_iter(&id, prefix.as_ref(), start_key.as_ref())
+			.map_err(client_err)?;
+
+		let mut size = 0;
+		let mut entries = Vec::new();
+		while entries.len() < count as usize {
+			let key = match keys.next() {
+				Some(key) => key,
+				None => return Ok(StoragePage { entries, next_start_key: None }),
+			};
+			let value = match self.client.storage(&id, &key).map_err(client_err)? {
+				Some(value) => value,
+				None => continue,
+			};
+			if let Err(e) =
+				self.add_to_response(&mut size, encoded_len(&key.0) + encoded_len(&value.0))
+			{
+				// Return a shorter page, unless the entry doesn't fit on its own.
+				return match entries.last() {
+					Some((last_key, _)) => {
+						let next_start_key = Some(last_key.clone());
+						Ok(StoragePage { entries, next_start_key })
+					},
+					None => Err(e),
+				}
+			}
+			entries.push((key, value));
+		}
+
+		let next_start_key = keys
+			.next()
+			.and_then(|_| entries.last().map(|(key, _)| key.clone()).or(start_key));
+		Ok(StoragePage { entries, next_start_key })
+	}
+
 	fn storage(
 		&self,
 		block: Option<Block::Hash>,
 		key: StorageKey,
-	) -> std::result::Result<Option<StorageData>, Error> {
-		self.block_or_best(block)
-			.and_then(|block| self.client.storage(&BlockId::Hash(block), &key))
-			.map_err(client_err)
+	) -> FutureResult<Option<StorageData>> {
+		self.spawn_blocking(move |state| {
+			state
+				.block_or_best(block)
+				.and_then(|block| state.client.storage(&BlockId::Hash(block), &key))
+				.map_err(client_err)
+		})
 	}
 
 	fn storage_size(
 		&self,
 		block: Option<Block::Hash>,
 		key: StorageKey,
-	) -> std::result::Result<Option<u64>, Error> {
-		let block = match self.block_or_best(block) {
-			Ok(b) => b,
-			Err(e) => return Err(client_err(e)),
-		};
-
-		match self.client.storage(&BlockId::Hash(block), &key) {
-			Ok(Some(d)) => return Ok(Some(d.0.len() as u64)),
-			Err(e) => return Err(client_err(e)),
-			Ok(None) => {},
-		}
+	) -> FutureResult<Option<u64>> {
+		self.spawn_blocking(move |state| {
+			let block = match state.block_or_best(block) {
+				Ok(b) => b,
+				Err(e) => return Err(client_err(e)),
+			};
+
+			match state.client.storage(&BlockId::Hash(block), &key) {
+				Ok(Some(d)) => return Ok(Some(d.0.len() as u64)),
+				Err(e) => return Err(client_err(e)),
+				Ok(None) => {},
+			}
 
-		self.client
-			.storage_pairs(&BlockId::Hash(block), &key)
-			.map(|kv| {
-				let item_sum = kv.iter().map(|(_, v)| v.0.len() as u64).sum::<u64>();
-				if item_sum > 0 {
-					Some(item_sum)
-				} else {
-					None
-				}
-			})
-			.map_err(client_err)
+			state
+				.client
+				.storage_pairs(&BlockId::Hash(block), &key)
+				.map(|kv| {
+					let item_sum = kv.iter().map(|(_, v)| v.0.len() as u64).sum::<u64>();
+					if item_sum > 0 {
+						Some(item_sum)
+					} else {
+						None
+					}
+				})
+				.map_err(client_err)
+		})
 	}
 
 	fn storage_hash(
 		&self,
 		block: Option<Block::Hash>,
 		key: StorageKey,
-	) -> std::result::Result<Option<Block::Hash>, Error> {
-		self.block_or_best(block)
-			.and_then(|block| self.client.storage_hash(&BlockId::Hash(block), &key))
-			.map_err(client_err)
+	) -> FutureResult<Option<Block::Hash>> {
+		self.spawn_blocking(move |state| {
+			state
+				.block_or_best(block)
+				.and_then(|block| state.client.storage_hash(&BlockId::Hash(block), &key))
+				.map_err(client_err)
+		})
 	}
 
-	fn metadata(&self, block: Option<Block::Hash>) -> std::result::Result<Bytes, Error> {
-		self.block_or_best(block).map_err(client_err).and_then(|block| {
-			self.client
-				.runtime_api()
-				.metadata(&BlockId::Hash(block))
-				.map(Into::into)
-				.map_err(|e| Error::Client(Box::new(e)))
+	fn metadata(&self, block: Option<Block::Hash>) -> FutureResult<Bytes> {
+		self.spawn_blocking(move |state| {
+			state.block_or_best(block).map_err(client_err).and_then(|block| {
+				state
+					.client
+					.runtime_api()
+					.metadata(&BlockId::Hash(block))
+					.map(Into::into)
+					.map_err(|e| Error::Client(Box::new(e)))
+			})
 		})
 	}
 
-	fn runtime_version(
-		&self,
-		block: Option<Block::Hash>,
-	) -> std::result::Result<RuntimeVersion, Error> {
-		self.block_or_best(block).map_err(client_err).and_then(|block| {
-			self.client
-				.runtime_version_at(&BlockId::Hash(block))
-				.map_err(|e| Error::Client(Box::new(e)))
+	fn runtime_version(&self, block: Option<Block::Hash>) -> FutureResult<RuntimeVersion> {
+		self.spawn_blocking(move |state| {
+			state.block_or_best(block).map_err(client_err).and_then(|block| {
+				state
+					.client
+					.runtime_version_at(&BlockId::Hash(block))
+					.map_err(|e| Error::Client(Box::new(e)))
+			})
 		})
 	}
 
@@ -325,6 +546,15 @@ where
 	) -> std::result::Result<Vec<StorageChangeSet<Block::Hash>>, Error> {
 		let call_fn = move || {
 			let range = self.query_storage_range(from, to)?;
+			if let Some(changes) = self.query_storage_indexed(&range, &keys)? {
+				return Ok(changes
+					.into_iter()
+					.map(|(position, changes)| StorageChangeSet {
+						block: range.hashes[position],
+						changes,
+					})
+					.collect())
+			}
 			let mut changes = Vec::new();
 			let mut last_values = HashMap::new();
 			self.query_storage_unfiltered(&range, &keys, &mut last_values, &mut changes)?;
@@ -333,13 +563,105 @@ where
 		call_fn()
 	}
 
+	fn key_history(
+		&self,
+		from: Block::Hash,
+		to: Option<Block::Hash>,
+		key: StorageKey,
+	) -> std::result::Result<Vec<u64>, Error> {
+		let to = self
+			.block_or_best(to)
+			.map_err(|e| invalid_block::<Block>(from, to, e.to_string()))?;
+		let invalid_block_err =
+			|e: ClientError| invalid_block::<Block>(from, Some(to), e.to_string());
+		let from_meta = self.client.header_metadata(from).map_err(invalid_block_err)?;
+		let to_meta = self.client.header_metadata(to).map_err(invalid_block_err)?;
+		if from_meta.number > to_meta.number {
+			return Err(invalid_block_range(
+				&from_meta,
+				&to_meta,
+				"from number > to number".to_owned(),
+			))
+		}
+		let different_forks = || {
+			invalid_block_range(&from_meta, &to_meta, "from and to are on different forks".into())
+		};
+
+		let from_number = from_meta.number;
+		let index = self
+			.client
+			.changes_index()
+			.filter(|index| {
+				index
+					.indexed_from()
+					.map_or(false, |indexed_from| indexed_from <= from_number + One::one())
+			})
+			.ok_or_else(|| {
+				Error::ChangesNotIndexed(format!("blocks after #{} ({:?})", from_number, from))
+			})?;
+
+		// The unfinalized blocks of the range are checked one by one.
+		let finalized = std::cmp::min(self.client.info().finalized_number, to_meta.number);
+		let mut unfinalized = Vec::new();
+		let mut last = to_meta.clone();
+		while last.number > std::cmp::max(finalized, from_number) {
+			unfinalized.push((last.number, last.hash));
+			last = self
+				.client
+				.header_metadata(last.parent)
+				.map_err(|e| invalid_block_range::<Block>(&last, &to_meta, e.to_string()))?;
+		}
+
+		// The changes by finalized blocks are listed from the history of the key.
+		let mut candidates = Vec::new();
+		if last.number > from_number {
+			if self.client.hash(last.number).map_err(client_err)? != Some(last.hash) ||
+				self.client.hash(from_number).map_err(client_err)? != Some(from)
+			{
+				return Err(different_forks())
+			}
+			for number in index
+				.key_changes(from_number + One::one(), last.number, None, &key.0)
+				.map_err(client_err)?
+			{
+				let hash =
+					self.client.hash(number).map_err(client_err)?.ok_or_else(|| {
+						client_err(ClientError::UnknownBlock(format!("#{}", number)))
+					})?;
+				candidates.push((number, hash));
+			}
+		} else if last.hash != from {
+			return Err(different_forks())
+		}
+		for (number, hash) in unfinalized.into_iter().rev() {
+			match index.block_changes(&hash).map_err(client_err)? {
+				Some(block_changes) if !block_changes.contains(None, &key.0) => (),
+				_ => candidates.push((number, hash)),
+			}
+		}
+
+		// Keys written with their previous value may be reported as modified by the index.
+		let mut changes = Vec::new();
+		let mut last_value = self.client.storage(&BlockId::Hash(from), &key).map_err(client_err)?;
+		for (number, hash) in candidates {
+			let value = self.client.storage(&BlockId::Hash(hash), &key).map_err(client_err)?;
+			if value != last_value {
+				changes.push(number.saturated_into::<u64>());
+				last_value = value;
+			}
+		}
+		Ok(changes)
+	}
+
 	fn query_storage_at(
 		&self,
 		keys: Vec<StorageKey>,
 		at: Option<Block::Hash>,
-	) -> std::result::Result<Vec<StorageChangeSet<Block::Hash>>, Error> {
-		let at = at.unwrap_or_else(|| self.client.info().best_hash);
-		self.query_storage(at, Some(at), keys)
+	) -> FutureResult<Vec<StorageChangeSet<Block::Hash>>> {
+		self.spawn_blocking(move |state| {
+			let at = at.unwrap_or_else(|| state.client.info().best_hash);
+			state.query_storage(at, Some(at), keys)
+		})
 	}
 
 	fn read_proof(
@@ -357,7 +679,7 @@ where
 			.map_err(client_err)
 	}
 
-	fn subscribe_runtime_version(&self, mut sink: SubscriptionSink) {
+	fn subscribe_runtime_version(&self, pending: PendingSubscriptionSink) {
 		let client = self.client.clone();
 
 		let initial = match self
@@ -369,7 +691,7 @@ where
 		{
 			Ok(initial) => initial,
 			Err(e) => {
-				let _ = sink.reject(JsonRpseeError::from(e));
+				spawn_subscription_task(&self.executor, pending.reject(e));
 				return
 			},
 		};
@@ -396,18 +718,15 @@ where
 
 		let stream = futures::stream::once(future::ready(initial)).chain(version_stream);
 
-		let fut = async move {
-			sink.pipe_from_stream(stream).await;
-		};
-
-		self.executor.spawn("substrate-rpc-subscription", Some("rpc"), fut.boxed());
+		spawn_subscription_task(&self.executor, pipe_from_stream(pending, stream));
 	}
 
-	fn subscribe_storage(&self, mut sink: SubscriptionSink, keys: Option<Vec<StorageKey>>) {
+	fn subscribe_storage(&self, pending: PendingSubscriptionSink, keys: Option<Vec<StorageKey>>) {
 		let stream = match self.client.storage_changes_notification_stream(keys.as_deref(), None) {
 			Ok(stream) => stream,
 			Err(blockchain_err) => {
-				let _ = sink.reject(JsonRpseeError::from(Error::Client(Box::new(blockchain_err))));
+				let err = Error::Client(Box::new(blockchain_err));
+				spawn_subscription_task(&self.executor, pending.reject(err));
 				return
 			},
 		};
@@ -439,11 +758,7 @@ where
 			.chain(storage_stream)
 			.filter(|storage| future::ready(!storage.changes.is_empty()));
 
-		let fut = async move {
-			sink.pipe_from_stream(stream).await;
-		};
-
-		self.executor.spawn("substrate-rpc-subscription", Some("rpc"), fut.boxed());
+		spawn_subscription_task(&self.executor, pipe_from_stream(pending, stream));
 	}
 
 	fn trace_block(
@@ -629,3 +944,14 @@ fn invalid_block_range<B: BlockT>(
 fn invalid_block<B: BlockT>(from: B::Hash, to: Option<B::Hash>, details: String) -> Error {
 	Error::InvalidBlockRange { from: format!("{:?}", from), to: format!("{:?}", to), details }
 }
+
+/// Size of storage data in a JSON response: hex encoded with a `0x` prefix, quoted and followed by
+/// a separator.
+fn encoded_len(data: &[u8]) -> usize {
+	2 * data.len() + 5
+}
+
+/// Size of a change of the value of `key` in a response.
+fn change_len(key: &StorageKey, data: &Option<StorageData>) -> usize {
+	encoded_len(&key.0) + data.as_ref().map_or(4, |data| encoded_len(&data.0))
+}
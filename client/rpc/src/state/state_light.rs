@@ -0,0 +1,346 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! State API backend for light nodes.
+//!
+//! The storage is read and the runtime is called on full nodes through a [`RemoteBackend`], which
+//! checks the returned proofs against the locally imported headers. The methods which can't be
+//! answered this way fail with [`ClientError::NotAvailableOnLightClient`].
+
+use std::sync::Arc;
+
+use super::{client_err, error::Error, ChildStateBackend, FutureResult, StateBackend};
+use crate::{spawn_subscription_task, SubscriptionTaskExecutor};
+
+use codec::Decode;
+use futures::{
+	future::{self, BoxFuture},
+	FutureExt, TryFutureExt,
+};
+use jsonrpsee::PendingSubscriptionSink;
+use sc_client_api::light::{RemoteBackend, RemoteCallRequest, RemoteReadRequest};
+use sc_rpc_api::state::{ReadProof, StoragePage};
+use sp_blockchain::{Error as ClientError, HeaderBackend, Result as ClientResult};
+use sp_core::{
+	storage::{PrefixedStorageKey, StorageChangeSet, StorageData, StorageKey},
+	Bytes,
+};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Hash, Header as HeaderT},
+};
+use sp_version::RuntimeVersion;
+
+/// State API backend for light nodes.
+pub struct LightState<Block: BlockT, Client> {
+	client: Arc<Client>,
+	executor: SubscriptionTaskExecutor,
+	remote_backend: Arc<dyn RemoteBackend<Block>>,
+}
+
+impl<Block, Client> LightState<Block, Client>
+where
+	Block: BlockT + 'static,
+	Client: HeaderBackend<Block> + Send + Sync + 'static,
+{
+	/// Create new state API backend for light nodes.
+	pub fn new(
+		client: Arc<Client>,
+		executor: SubscriptionTaskExecutor,
+		remote_backend: Arc<dyn RemoteBackend<Block>>,
+	) -> Self {
+		Self { client, executor, remote_backend }
+	}
+
+	/// Returns the header of the given block, or of the best block if None is passed.
+	fn header(&self, block: Option<Block::Hash>) -> ClientResult<Block::Header> {
+		let block = block.unwrap_or_else(|| self.client.info().best_hash);
+		self.client
+			.header(BlockId::Hash(block))?
+			.ok_or_else(|| ClientError::UnknownBlock(format!("{}", block)))
+	}
+
+	/// Read the values of `keys` at the given block from a full node.
+	fn storage_values(
+		&self,
+		block: Option<Block::Hash>,
+		keys: Vec<StorageKey>,
+	) -> BoxFuture<'static, ClientResult<Vec<Option<StorageData>>>> {
+		let header = match self.header(block) {
+			Ok(header) => header,
+			Err(e) => return future::err(e).boxed(),
+		};
+		let keys: Vec<Vec<u8>> = keys.into_iter().map(|key| key.0).collect();
+		let read = self
+			.remote_backend
+			.remote_read(RemoteReadRequest { header, keys: keys.clone() });
+		read.map_ok(move |mut values| {
+			keys.iter().map(|key| values.remove(key).flatten().map(StorageData)).collect()
+		})
+		.boxed()
+	}
+
+	/// Call a runtime method at the given block on a full node.
+	fn remote_call(
+		&self,
+		block: Option<Block::Hash>,
+		method: &str,
+		call_data: Vec<u8>,
+	) -> BoxFuture<'static, ClientResult<Vec<u8>>> {
+		match self.header(block) {
+			Ok(header) => self.remote_backend.remote_call(RemoteCallRequest {
+				header,
+				method: method.into(),
+				call_data,
+			}),
+			Err(e) => future::err(e).boxed(),
+		}
+	}
+}
+
+impl<Block, Client> StateBackend<Block, Client> for LightState<Block, Client>
+where
+	Block: BlockT + 'static,
+	Client: HeaderBackend<Block> + Send + Sync + 'static,
+{
+	fn call(
+		&self,
+		block: Option<Block::Hash>,
+		method: String,
+		call_data: Bytes,
+	) -> FutureResult<Bytes> {
+		self.remote_call(block, &method, call_data.0)
+			.map_ok(Into::into)
+			.map_err(client_err)
+			.boxed()
+	}
+
+	fn storage_keys(
+		&self,
+		_block: Option<Block::Hash>,
+		_prefix: StorageKey,
+	) -> Result<Vec<StorageKey>, Error> {
+		Err(not_available())
+	}
+
+	fn storage_pairs(
+		&self,
+		_block: Option<Block::Hash>,
+		_prefix: StorageKey,
+	) -> Result<Vec<(StorageKey, StorageData)>, Error> {
+		Err(not_available())
+	}
+
+	fn storage_keys_paged(
+		&self,
+		_block: Option<Block::Hash>,
+		_prefix: Option<StorageKey>,
+		_count: u32,
+		_start_key: Option<StorageKey>,
+	) -> Result<Vec<StorageKey>, Error> {
+		Err(not_available())
+	}
+
+	fn storage_paged(
+		&self,
+		_block: Option<Block::Hash>,
+		_prefix: Option<StorageKey>,
+		_count: u32,
+		_start_key: Option<StorageKey>,
+	) -> Result<StoragePage, Error> {
+		Err(not_available())
+	}
+
+	fn storage(
+		&self,
+		block: Option<Block::Hash>,
+		key: StorageKey,
+	) -> FutureResult<Option<StorageData>> {
+		self.storage_values(block, vec![key])
+			.map_ok(|mut values| values.pop().flatten())
+			.map_err(client_err)
+			.boxed()
+	}
+
+	fn storage_hash(
+		&self,
+		block: Option<Block::Hash>,
+		key: StorageKey,
+	) -> FutureResult<Option<Block::Hash>> {
+		StateBackend::storage(self, block, key)
+			.map_ok(|value| {
+				value.map(|value| <<Block::Header as HeaderT>::Hashing as Hash>::hash(&value.0))
+			})
+			.boxed()
+	}
+
+	fn storage_size(
+		&self,
+		block: Option<Block::Hash>,
+		key: StorageKey,
+	) -> FutureResult<Option<u64>> {
+		StateBackend::storage(self, block, key)
+			.map_ok(|value| value.map(|value| value.0.len() as u64))
+			.boxed()
+	}
+
+	fn metadata(&self, block: Option<Block::Hash>) -> FutureResult<Bytes> {
+		self.remote_call(block, "Metadata_metadata", Vec::new())
+			.map(|metadata| {
+				Vec::<u8>::decode(&mut &metadata?[..])
+					.map_err(|e| ClientError::CallResultDecode("Metadata_metadata", e))
+			})
+			.map_ok(Into::into)
+			.map_err(client_err)
+			.boxed()
+	}
+
+	fn runtime_version(&self, block: Option<Block::Hash>) -> FutureResult<RuntimeVersion> {
+		self.remote_call(block, "Core_version", Vec::new())
+			.map(|version| {
+				RuntimeVersion::decode(&mut &version?[..])
+					.map_err(|e| ClientError::CallResultDecode("Core_version", e))
+			})
+			.map_err(client_err)
+			.boxed()
+	}
+
+	fn query_storage(
+		&self,
+		_from: Block::Hash,
+		_to: Option<Block::Hash>,
+		_keys: Vec<StorageKey>,
+	) -> Result<Vec<StorageChangeSet<Block::Hash>>, Error> {
+		Err(not_available())
+	}
+
+	fn key_history(
+		&self,
+		_from: Block::Hash,
+		_to: Option<Block::Hash>,
+		_key: StorageKey,
+	) -> Result<Vec<u64>, Error> {
+		Err(not_available())
+	}
+
+	fn query_storage_at(
+		&self,
+		keys: Vec<StorageKey>,
+		at: Option<Block::Hash>,
+	) -> FutureResult<Vec<StorageChangeSet<Block::Hash>>> {
+		let block = at.unwrap_or_else(|| self.client.info().best_hash);
+		self.storage_values(Some(block), keys.clone())
+			.map_ok(move |values| {
+				vec![StorageChangeSet { block, changes: keys.into_iter().zip(values).collect() }]
+			})
+			.map_err(client_err)
+			.boxed()
+	}
+
+	fn read_proof(
+		&self,
+		_block: Option<Block::Hash>,
+		_keys: Vec<StorageKey>,
+	) -> Result<ReadProof<Block::Hash>, Error> {
+		Err(not_available())
+	}
+
+	fn trace_block(
+		&self,
+		_block: Block::Hash,
+		_targets: Option<String>,
+		_storage_keys: Option<String>,
+		_methods: Option<String>,
+	) -> Result<sp_rpc::tracing::TraceBlockResponse, Error> {
+		Err(not_available())
+	}
+
+	fn subscribe_runtime_version(&self, pending: PendingSubscriptionSink) {
+		spawn_subscription_task(&self.executor, pending.reject(not_available()));
+	}
+
+	fn subscribe_storage(&self, pending: PendingSubscriptionSink, _keys: Option<Vec<StorageKey>>) {
+		spawn_subscription_task(&self.executor, pending.reject(not_available()));
+	}
+}
+
+impl<Block, Client> ChildStateBackend<Block, Client> for LightState<Block, Client>
+where
+	Block: BlockT + 'static,
+	Client: HeaderBackend<Block> + Send + Sync + 'static,
+{
+	fn read_child_proof(
+		&self,
+		_block: Option<Block::Hash>,
+		_storage_key: PrefixedStorageKey,
+		_keys: Vec<StorageKey>,
+	) -> Result<ReadProof<Block::Hash>, Error> {
+		Err(not_available())
+	}
+
+	fn storage_keys(
+		&self,
+		_block: Option<Block::Hash>,
+		_storage_key: PrefixedStorageKey,
+		_prefix: StorageKey,
+	) -> Result<Vec<StorageKey>, Error> {
+		Err(not_available())
+	}
+
+	fn storage_keys_paged(
+		&self,
+		_block: Option<Block::Hash>,
+		_storage_key: PrefixedStorageKey,
+		_prefix: Option<StorageKey>,
+		_count: u32,
+		_start_key: Option<StorageKey>,
+	) -> Result<Vec<StorageKey>, Error> {
+		Err(not_available())
+	}
+
+	fn storage(
+		&self,
+		_block: Option<Block::Hash>,
+		_storage_key: PrefixedStorageKey,
+		_key: StorageKey,
+	) -> Result<Option<StorageData>, Error> {
+		Err(not_available())
+	}
+
+	fn storage_entries(
+		&self,
+		_block: Option<Block::Hash>,
+		_storage_key: PrefixedStorageKey,
+		_keys: Vec<StorageKey>,
+	) -> Result<Vec<Option<StorageData>>, Error> {
+		Err(not_available())
+	}
+
+	fn storage_hash(
+		&self,
+		_block: Option<Block::Hash>,
+		_storage_key: PrefixedStorageKey,
+		_key: StorageKey,
+	) -> Result<Option<Block::Hash>, Error> {
+		Err(not_available())
+	}
+}
+
+fn not_available() -> Error {
+	client_err(ClientError::NotAvailableOnLightClient)
+}
@@ -20,17 +20,22 @@ use self::error::Error;
 use super::*;
 use crate::testing::{test_executor, timeout_secs};
 use assert_matches::assert_matches;
-use futures::executor;
+use futures::{executor, future, future::BoxFuture, FutureExt};
 use jsonrpsee::{
-	core::Error as RpcError,
-	types::{error::CallError as RpcCallError, EmptyParams, ErrorObject},
+	core::{server::MethodsError, EmptyServerParams},
+	types::ErrorObject,
 };
 use sc_block_builder::BlockBuilderProvider;
+use sc_client_api::{
+	light::{check_read_proof, RemoteCallRequest, RemoteReadRequest},
+	CallExecutor,
+};
 use sc_rpc_api::DenyUnsafe;
 use sp_consensus::BlockOrigin;
 use sp_core::{hash::H256, storage::ChildInfo};
 use sp_io::hashing::blake2_256;
-use std::sync::Arc;
+use sp_runtime::{generic::BlockId, traits::Header as HeaderT};
+use std::{collections::HashMap, sync::Arc};
 use substrate_test_runtime_client::{prelude::*, runtime};
 
 const STORAGE_KEY: &[u8] = b"child";
@@ -55,24 +60,31 @@ async fn should_return_storage() {
 		.add_extra_storage(b":map:acc2".to_vec(), vec![1, 2, 3])
 		.build();
 	let genesis_hash = client.genesis_hash();
-	let (client, child) = new_full(Arc::new(client), test_executor(), DenyUnsafe::No, None);
+	let (client, child) = new_full(Arc::new(client), test_executor(), DenyUnsafe::No, None, None);
 	let key = StorageKey(KEY.to_vec());
 
 	assert_eq!(
 		client
 			.storage(key.clone(), Some(genesis_hash).into())
+			.await
 			.map(|x| x.map(|x| x.0.len()))
 			.unwrap()
 			.unwrap() as usize,
 		VALUE.len(),
 	);
 	assert_matches!(
-		client.storage_hash(key.clone(), Some(genesis_hash).into()).map(|x| x.is_some()),
+		client
+			.storage_hash(key.clone(), Some(genesis_hash).into())
+			.await
+			.map(|x| x.is_some()),
 		Ok(true)
 	);
-	assert_eq!(client.storage_size(key.clone(), None).unwrap().unwrap() as usize, VALUE.len(),);
 	assert_eq!(
-		client.storage_size(StorageKey(b":map".to_vec()), None).unwrap().unwrap() as usize,
+		client.storage_size(key.clone(), None).await.unwrap().unwrap() as usize,
+		VALUE.len()
+	);
+	assert_eq!(
+		client.storage_size(StorageKey(b":map".to_vec()), None).await.unwrap().unwrap() as usize,
 		2 + 3,
 	);
 	assert_eq!(
@@ -100,7 +112,7 @@ async fn should_return_storage_entries() {
 		.add_extra_child_storage(&child_info, KEY2.to_vec(), CHILD_VALUE2.to_vec())
 		.build();
 	let genesis_hash = client.genesis_hash();
-	let (_client, child) = new_full(Arc::new(client), test_executor(), DenyUnsafe::No, None);
+	let (_client, child) = new_full(Arc::new(client), test_executor(), DenyUnsafe::No, None, None);
 
 	let keys = &[StorageKey(KEY1.to_vec()), StorageKey(KEY2.to_vec())];
 	assert_eq!(
@@ -122,6 +134,71 @@ async fn should_return_storage_entries() {
 	);
 }
 
+#[tokio::test]
+async fn should_return_storage_paged() {
+	let client = TestClientBuilder::new()
+		.add_extra_storage(b":map:acc1".to_vec(), vec![1, 2])
+		.add_extra_storage(b":map:acc2".to_vec(), vec![1, 2, 3])
+		.add_extra_storage(b":map:acc3".to_vec(), vec![1])
+		.build();
+	let (api, _child) = new_full(Arc::new(client), test_executor(), DenyUnsafe::No, None, None);
+	let prefix = Some(StorageKey(b":map".to_vec()));
+
+	let page = api.storage_paged(prefix.clone(), 2, None, None).unwrap();
+	assert_eq!(
+		page.entries,
+		vec![
+			(StorageKey(b":map:acc1".to_vec()), StorageData(vec![1, 2])),
+			(StorageKey(b":map:acc2".to_vec()), StorageData(vec![1, 2, 3])),
+		]
+	);
+	assert_eq!(page.next_start_key, Some(StorageKey(b":map:acc2".to_vec())));
+
+	let page = api.storage_paged(prefix.clone(), 2, page.next_start_key, None).unwrap();
+	assert_eq!(page.entries, vec![(StorageKey(b":map:acc3".to_vec()), StorageData(vec![1]))]);
+	assert_eq!(page.next_start_key, None);
+
+	assert_matches!(
+		api.storage_paged(prefix, STORAGE_KEYS_PAGED_MAX_COUNT + 1, None, None),
+		Err(e) if e.code() == 4002
+	);
+}
+
+#[tokio::test]
+async fn should_limit_storage_response_size() {
+	// Values are hex encoded in the responses, which are limited to 1 MiB.
+	let client = TestClientBuilder::new()
+		.add_extra_storage(b":big:1".to_vec(), vec![0; 300 * 1024])
+		.add_extra_storage(b":big:2".to_vec(), vec![0; 300 * 1024])
+		.add_extra_storage(b":huge".to_vec(), vec![0; 600 * 1024])
+		.build();
+	let (api, _child) =
+		new_full(Arc::new(client), test_executor(), DenyUnsafe::No, None, Some(1024 * 1024));
+
+	assert_matches!(
+		api.storage_pairs(StorageKey(b":big".to_vec()), None),
+		Err(e) if e.code() == 4004
+	);
+	assert_eq!(api.storage_keys(StorageKey(b":big".to_vec()), None).unwrap().len(), 2);
+
+	// Pages are shortened to fit.
+	let page = api.storage_paged(Some(StorageKey(b":big".to_vec())), 10, None, None).unwrap();
+	assert_eq!(page.entries.len(), 1);
+	assert_eq!(page.next_start_key, Some(StorageKey(b":big:1".to_vec())));
+
+	assert_matches!(
+		api.storage_paged(Some(StorageKey(b":hug".to_vec())), 10, None, None),
+		Err(e) if e.code() == 4004
+	);
+
+	let (big1, big2) = (StorageKey(b":big:1".to_vec()), StorageKey(b":big:2".to_vec()));
+	assert_eq!(api.query_storage_at(vec![big1.clone()], None).await.unwrap()[0].changes.len(), 1);
+	assert_matches!(
+		api.query_storage_at(vec![big1, big2], None).await,
+		Err(e) if e.code() == 4004
+	);
+}
+
 #[tokio::test]
 async fn should_return_child_storage() {
 	let child_info = ChildInfo::new_default(STORAGE_KEY);
@@ -131,7 +208,7 @@ async fn should_return_child_storage() {
 			.build(),
 	);
 	let genesis_hash = client.genesis_hash();
-	let (_client, child) = new_full(client, test_executor(), DenyUnsafe::No, None);
+	let (_client, child) = new_full(client, test_executor(), DenyUnsafe::No, None, None);
 	let child_key = prefixed_storage_key();
 	let key = StorageKey(b"key".to_vec());
 
@@ -162,7 +239,7 @@ async fn should_return_child_storage_entries() {
 			.build(),
 	);
 	let genesis_hash = client.genesis_hash();
-	let (_client, child) = new_full(client, test_executor(), DenyUnsafe::No, None);
+	let (_client, child) = new_full(client, test_executor(), DenyUnsafe::No, None, None);
 	let child_key = prefixed_storage_key();
 	let keys = vec![StorageKey(b"key1".to_vec()), StorageKey(b"key2".to_vec())];
 
@@ -193,13 +270,13 @@ async fn should_return_child_storage_entries() {
 async fn should_call_contract() {
 	let client = Arc::new(substrate_test_runtime_client::new());
 	let genesis_hash = client.genesis_hash();
-	let (client, _child) = new_full(client, test_executor(), DenyUnsafe::No, None);
-
-	use jsonrpsee::{core::Error, types::error::CallError};
+	let (client, _child) = new_full(client, test_executor(), DenyUnsafe::No, None, None);
 
 	assert_matches!(
-		client.call("balanceOf".into(), Bytes(vec![1, 2, 3]), Some(genesis_hash).into()),
-		Err(Error::Call(CallError::Failed(_)))
+		client
+			.call("balanceOf".into(), Bytes(vec![1, 2, 3]), Some(genesis_hash).into())
+			.await,
+		Err(err) if err.code() == jsonrpsee::types::error::CALL_EXECUTION_FAILED_CODE
 	)
 }
 
@@ -207,10 +284,13 @@ async fn should_call_contract() {
 async fn should_notify_about_storage_changes() {
 	let mut sub = {
 		let mut client = Arc::new(substrate_test_runtime_client::new());
-		let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None);
+		let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None, None);
 
 		let api_rpc = api.into_rpc();
-		let sub = api_rpc.subscribe("state_subscribeStorage", EmptyParams::new()).await.unwrap();
+		let sub = api_rpc
+			.subscribe_unbounded("state_subscribeStorage", EmptyServerParams::new())
+			.await
+			.unwrap();
 
 		// Cause a change:
 		let mut builder = client.new_block(Default::default()).unwrap();
@@ -239,14 +319,17 @@ async fn should_notify_about_storage_changes() {
 async fn should_send_initial_storage_changes_and_notifications() {
 	let mut sub = {
 		let mut client = Arc::new(substrate_test_runtime_client::new());
-		let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None);
+		let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None, None);
 
 		let alice_balance_key =
 			blake2_256(&runtime::system::balance_of_key(AccountKeyring::Alice.into()));
 
 		let api_rpc = api.into_rpc();
 		let sub = api_rpc
-			.subscribe("state_subscribeStorage", [[StorageKey(alice_balance_key.to_vec())]])
+			.subscribe_unbounded(
+				"state_subscribeStorage",
+				[[StorageKey(alice_balance_key.to_vec())]],
+			)
 			.await
 			.unwrap();
 
@@ -275,7 +358,7 @@ async fn should_send_initial_storage_changes_and_notifications() {
 #[tokio::test]
 async fn should_query_storage() {
 	async fn run_tests(mut client: Arc<TestClient>) {
-		let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None);
+		let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None, None);
 
 		let mut add_block = |nonce| {
 			let mut builder = client.new_block(Default::default()).unwrap();
@@ -352,7 +435,7 @@ async fn should_query_storage() {
 
 		assert_eq!(
 			result.map_err(|e| e.to_string()),
-			Err(RpcError::Call(RpcCallError::Custom(ErrorObject::owned(
+			Err(ErrorObject::owned(
 				4001,
 				Error::InvalidBlockRange {
 					from: format!("1 ({:?})", block1_hash),
@@ -361,7 +444,7 @@ async fn should_query_storage() {
 				}
 				.to_string(),
 				None::<()>,
-			))))
+			))
 			.map_err(|e| e.to_string())
 		);
 
@@ -373,7 +456,7 @@ async fn should_query_storage() {
 
 		assert_eq!(
 			result.map_err(|e| e.to_string()),
-			Err(RpcError::Call(RpcCallError::Custom(ErrorObject::owned(
+			Err(ErrorObject::owned(
 				4001,
 				Error::InvalidBlockRange {
 					from: format!("{:?}", genesis_hash),
@@ -385,7 +468,7 @@ async fn should_query_storage() {
 				}
 				.to_string(),
 				None::<()>,
-			))))
+			))
 			.map_err(|e| e.to_string())
 		);
 
@@ -394,7 +477,7 @@ async fn should_query_storage() {
 
 		assert_eq!(
 			result.map_err(|e| e.to_string()),
-			Err(RpcError::Call(RpcCallError::Custom(ErrorObject::owned(
+			Err(ErrorObject::owned(
 				4001,
 				Error::InvalidBlockRange {
 					from: format!("{:?}", random_hash1),
@@ -406,7 +489,7 @@ async fn should_query_storage() {
 				}
 				.to_string(),
 				None::<()>,
-			))))
+			))
 			.map_err(|e| e.to_string()),
 		);
 
@@ -415,7 +498,7 @@ async fn should_query_storage() {
 
 		assert_eq!(
 			result.map_err(|e| e.to_string()),
-			Err(RpcError::Call(RpcCallError::Custom(ErrorObject::owned(
+			Err(ErrorObject::owned(
 				4001,
 				Error::InvalidBlockRange {
 					from: format!("{:?}", random_hash1),
@@ -427,7 +510,7 @@ async fn should_query_storage() {
 				}
 				.to_string(),
 				None::<()>,
-			))))
+			))
 			.map_err(|e| e.to_string()),
 		);
 
@@ -436,7 +519,7 @@ async fn should_query_storage() {
 
 		assert_eq!(
 			result.map_err(|e| e.to_string()),
-			Err(RpcError::Call(RpcCallError::Custom(ErrorObject::owned(
+			Err(ErrorObject::owned(
 				4001,
 				Error::InvalidBlockRange {
 					from: format!("{:?}", random_hash1), // First hash not found.
@@ -448,12 +531,12 @@ async fn should_query_storage() {
 				}
 				.to_string(),
 				None::<()>
-			))))
+			))
 			.map_err(|e| e.to_string()),
 		);
 
 		// single block range
-		let result = api.query_storage_at(keys.clone(), Some(block1_hash));
+		let result = api.query_storage_at(keys.clone(), Some(block1_hash)).await;
 
 		assert_eq!(
 			result.unwrap(),
@@ -472,12 +555,64 @@ async fn should_query_storage() {
 
 	run_tests(Arc::new(substrate_test_runtime_client::new())).await;
 	run_tests(Arc::new(TestClientBuilder::new().build())).await;
+	run_tests(Arc::new(TestClientBuilder::with_changes_index().build())).await;
+}
+
+#[tokio::test]
+async fn should_return_key_history() {
+	let mut client = Arc::new(TestClientBuilder::with_changes_index().build());
+	let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None, None);
+
+	let mut hashes = vec![client.genesis_hash()];
+	for nonce in 0..4 {
+		let mut builder = client.new_block(Default::default()).unwrap();
+		// None -> Some(0) -> Some(0) -> Some(1) -> Some(1)
+		builder.push_storage_change(vec![1], Some(vec![nonce / 2])).unwrap();
+		let block = builder.build().unwrap().block;
+		hashes.push(block.header.hash());
+		client.import(BlockOrigin::Own, block).await.unwrap();
+	}
+	// The changes of the finalized blocks are read from the history of the key.
+	client.finalize_block(BlockId::hash(hashes[2]), None).unwrap();
+
+	let key = StorageKey(vec![1]);
+	assert_eq!(api.key_history(key.clone(), hashes[0], None).unwrap(), vec![1, 3]);
+	assert_eq!(api.key_history(key.clone(), hashes[1], Some(hashes[3])).unwrap(), vec![3]);
+	assert_eq!(api.key_history(key.clone(), hashes[3], None).unwrap(), Vec::<u64>::new());
+	assert_eq!(
+		api.query_storage(vec![key.clone()], hashes[0], None).unwrap(),
+		vec![
+			StorageChangeSet { block: hashes[0], changes: vec![(key.clone(), None)] },
+			StorageChangeSet {
+				block: hashes[1],
+				changes: vec![(key.clone(), Some(StorageData(vec![0])))],
+			},
+			StorageChangeSet {
+				block: hashes[3],
+				changes: vec![(key.clone(), Some(StorageData(vec![1])))],
+			},
+		]
+	);
+
+	// Without the index.
+	let client = Arc::new(substrate_test_runtime_client::new());
+	let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None, None);
+	let genesis_hash = client.genesis_hash();
+	assert_eq!(
+		api.key_history(key, genesis_hash, None).map_err(|e| e.to_string()),
+		Err(ErrorObject::owned(
+			4003,
+			Error::ChangesNotIndexed(format!("blocks after #0 ({:?})", genesis_hash)).to_string(),
+			None::<()>,
+		))
+		.map_err(|e| e.to_string())
+	);
 }
 
 #[tokio::test]
 async fn should_return_runtime_version() {
 	let client = Arc::new(substrate_test_runtime_client::new());
-	let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None);
+	let (api, _child) = new_full(client.clone(), test_executor(), DenyUnsafe::No, None, None);
 
 	let result = "{\"specName\":\"test\",\"implName\":\"parity-test\",\"authoringVersion\":1,\
 		\"specVersion\":2,\"implVersion\":2,\"apis\":[[\"0xdf6acb689907609b\",4],\
@@ -486,7 +621,7 @@ async fn should_return_runtime_version() {
 		[\"0xf78b278be53f454c\",2],[\"0xab3c0572291feb8b\",1],[\"0xbc9d89904f5b923f\",1]],\
 		\"transactionVersion\":1,\"stateVersion\":1}";
 
-	let runtime_version = api.runtime_version(None.into()).unwrap();
+	let runtime_version = api.runtime_version(None.into()).await.unwrap();
 	let serialized = serde_json::to_string(&runtime_version).unwrap();
 	assert_eq!(serialized, result);
 
@@ -498,11 +633,11 @@ async fn should_return_runtime_version() {
 async fn should_notify_on_runtime_version_initially() {
 	let mut sub = {
 		let client = Arc::new(substrate_test_runtime_client::new());
-		let (api, _child) = new_full(client, test_executor(), DenyUnsafe::No, None);
+		let (api, _child) = new_full(client, test_executor(), DenyUnsafe::No, None, None);
 
 		let api_rpc = api.into_rpc();
 		let sub = api_rpc
-			.subscribe("state_subscribeRuntimeVersion", EmptyParams::new())
+			.subscribe_unbounded("state_subscribeRuntimeVersion", EmptyServerParams::new())
 			.await
 			.unwrap();
 
@@ -527,21 +662,91 @@ fn should_deserialize_storage_key() {
 #[tokio::test]
 async fn wildcard_storage_subscriptions_are_rpc_unsafe() {
 	let client = Arc::new(substrate_test_runtime_client::new());
-	let (api, _child) = new_full(client, test_executor(), DenyUnsafe::Yes, None);
+	let (api, _child) = new_full(client, test_executor(), DenyUnsafe::Yes, None, None);
 
 	let api_rpc = api.into_rpc();
-	let err = api_rpc.subscribe("state_subscribeStorage", EmptyParams::new()).await;
-	assert_matches!(err, Err(RpcError::Call(RpcCallError::Custom(e))) if e.message() == "RPC call is unsafe to be called externally");
+	let err = api_rpc
+		.subscribe_unbounded("state_subscribeStorage", EmptyServerParams::new())
+		.await;
+	assert_matches!(err, Err(MethodsError::JsonRpc(e)) if e.message() == "RPC call is unsafe to be called externally");
 }
 
 #[tokio::test]
 async fn concrete_storage_subscriptions_are_rpc_safe() {
 	let client = Arc::new(substrate_test_runtime_client::new());
-	let (api, _child) = new_full(client, test_executor(), DenyUnsafe::Yes, None);
+	let (api, _child) = new_full(client, test_executor(), DenyUnsafe::Yes, None, None);
 	let api_rpc = api.into_rpc();
 
 	let key = StorageKey(STORAGE_KEY.to_vec());
-	let sub = api_rpc.subscribe("state_subscribeStorage", [[key]]).await;
+	let sub = api_rpc.subscribe_unbounded("state_subscribeStorage", [[key]]).await;
 
 	assert!(sub.is_ok());
 }
+
+/// Answers the requests of a light client with the proofs of a full client.
+struct ProvingRemoteBackend(Arc<TestClient>);
+
+impl RemoteBackend<runtime::Block> for ProvingRemoteBackend {
+	fn remote_read(
+		&self,
+		request: RemoteReadRequest<runtime::Header>,
+	) -> BoxFuture<'static, sp_blockchain::Result<HashMap<Vec<u8>, Option<Vec<u8>>>>> {
+		let result = self
+			.0
+			.read_proof(
+				&BlockId::Hash(request.header.hash()),
+				&mut request.keys.iter().map(AsRef::as_ref),
+			)
+			.and_then(|proof| check_read_proof(&request.header, &request.keys, proof));
+		future::ready(result).boxed()
+	}
+
+	fn remote_call(
+		&self,
+		request: RemoteCallRequest<runtime::Header>,
+	) -> BoxFuture<'static, sp_blockchain::Result<Vec<u8>>> {
+		let result = self
+			.0
+			.execution_proof(
+				&BlockId::Hash(request.header.hash()),
+				&request.method,
+				&request.call_data,
+			)
+			.and_then(|(_, proof)| {
+				self.0.executor().check_execution_proof(
+					*request.header.state_root(),
+					&request.method,
+					&request.call_data,
+					proof,
+				)
+			});
+		future::ready(result).boxed()
+	}
+}
+
+#[tokio::test]
+async fn should_read_state_from_remote_backend_on_light_client() {
+	const KEY: &[u8] = b":mock";
+	const VALUE: &[u8] = b"hello world";
+
+	let client =
+		Arc::new(TestClientBuilder::new().add_extra_storage(KEY.to_vec(), VALUE.to_vec()).build());
+	let genesis_hash = client.genesis_hash();
+	let remote_backend = Arc::new(ProvingRemoteBackend(client.clone()));
+	let (api, child) = new_light(client.clone(), test_executor(), remote_backend, DenyUnsafe::No);
+	let key = StorageKey(KEY.to_vec());
+
+	assert_eq!(
+		api.storage(key.clone(), Some(genesis_hash)).await.unwrap(),
+		Some(StorageData(VALUE.to_vec())),
+	);
+	assert_eq!(api.storage(StorageKey(b":missing".to_vec()), None).await.unwrap(), None);
+	assert_eq!(api.storage_size(key.clone(), None).await.unwrap(), Some(VALUE.len() as u64));
+	assert_eq!(
+		api.runtime_version(None).await.unwrap(),
+		client.runtime_version_at(&BlockId::Hash(genesis_hash)).unwrap(),
+	);
+
+	assert_matches!(api.storage_keys(StorageKey(Vec::new()), None), Err(_));
+	assert_matches!(child.storage(prefixed_storage_key(), key, None), Err(_));
+}
@@ -21,26 +21,42 @@
 #[cfg(test)]
 mod tests;
 
-use futures::channel::oneshot;
+use std::time::Duration;
+
+use futures::{channel::oneshot, stream, StreamExt};
+use futures_timer::Delay;
 use jsonrpsee::{
-	core::{async_trait, error::Error as JsonRpseeError, JsonValue, RpcResult},
-	types::error::{CallError, ErrorCode, ErrorObject},
+	core::{async_trait, JsonValue, RpcResult},
+	types::{
+		error::{ErrorCode, CALL_EXECUTION_FAILED_CODE},
+		ErrorObject, ErrorObjectOwned,
+	},
+	PendingSubscriptionSink,
 };
 use sc_rpc_api::DenyUnsafe;
 use sc_tracing::logging;
 use sc_utils::mpsc::TracingUnboundedSender;
 use sp_runtime::traits::{self, Header as HeaderT};
 
+use crate::{spawn_subscription_task, utils::pipe_from_stream, SubscriptionTaskExecutor};
+
 use self::error::Result;
 
-pub use self::helpers::{Health, NodeRole, PeerInfo, SyncState, SystemInfo};
+pub use self::helpers::{
+	ConnectionDirection, Health, NodeRole, PeerInfo, PeerReputationChange, PeerScore,
+	PeerSetMembership, SyncPeerThroughput, SyncState, SystemInfo,
+};
 pub use sc_rpc_api::system::*;
 
+/// Interval between the notifications of the peer scores subscription.
+const PEER_SCORES_INTERVAL: Duration = Duration::from_secs(5);
+
 /// System API implementation
 pub struct System<B: traits::Block> {
 	info: SystemInfo,
 	send_back: TracingUnboundedSender<Request<B>>,
 	deny_unsafe: DenyUnsafe,
+	executor: SubscriptionTaskExecutor,
 }
 
 /// Request to be processed.
@@ -56,6 +72,11 @@ pub enum Request<B: traits::Block> {
 	Peers(oneshot::Sender<Vec<PeerInfo<B::Hash, <B::Header as HeaderT>::Number>>>),
 	/// Must return the state of the network.
 	NetworkState(oneshot::Sender<serde_json::Value>),
+	/// Must return the reputation and connection state of the peers known to the peer set
+	/// manager.
+	PeerScores(oneshot::Sender<Vec<PeerScore>>),
+	/// Must return the block download throughput of the peers we sync with.
+	SyncPeerThroughput(oneshot::Sender<Vec<SyncPeerThroughput>>),
 	/// Must return any potential parse error.
 	NetworkAddReservedPeer(String, oneshot::Sender<Result<()>>),
 	/// Must return any potential parse error.
@@ -77,8 +98,9 @@ impl<B: traits::Block> System<B> {
 		info: SystemInfo,
 		send_back: TracingUnboundedSender<Request<B>>,
 		deny_unsafe: DenyUnsafe,
+		executor: SubscriptionTaskExecutor,
 	) -> Self {
-		System { info, send_back, deny_unsafe }
+		System { info, send_back, deny_unsafe, executor }
 	}
 }
 
@@ -107,19 +129,19 @@ impl<B: traits::Block> SystemApiServer<B::Hash, <B::Header as HeaderT>::Number>
 	async fn system_health(&self) -> RpcResult<Health> {
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::Health(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
 	}
 
 	async fn system_local_peer_id(&self) -> RpcResult<String> {
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::LocalPeerId(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
 	}
 
 	async fn system_local_listen_addresses(&self) -> RpcResult<Vec<String>> {
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::LocalListenAddresses(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
 	}
 
 	async fn system_peers(
@@ -128,14 +150,50 @@ impl<B: traits::Block> SystemApiServer<B::Hash, <B::Header as HeaderT>::Number>
 		self.deny_unsafe.check_if_safe()?;
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::Peers(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
 	}
 
 	async fn system_network_state(&self) -> RpcResult<JsonValue> {
 		self.deny_unsafe.check_if_safe()?;
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::NetworkState(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
+	}
+
+	async fn system_peer_scores(&self) -> RpcResult<Vec<PeerScore>> {
+		self.deny_unsafe.check_if_safe()?;
+		let (tx, rx) = oneshot::channel();
+		let _ = self.send_back.unbounded_send(Request::PeerScores(tx));
+		rx.await.map_err(call_failed)
+	}
+
+	async fn system_sync_peer_throughput(&self) -> RpcResult<Vec<SyncPeerThroughput>> {
+		self.deny_unsafe.check_if_safe()?;
+		let (tx, rx) = oneshot::channel();
+		let _ = self.send_back.unbounded_send(Request::SyncPeerThroughput(tx));
+		rx.await.map_err(call_failed)
+	}
+
+	fn system_subscribe_peer_scores(&self, pending: PendingSubscriptionSink) {
+		if let Err(err) = self.deny_unsafe.check_if_safe() {
+			spawn_subscription_task(&self.executor, pending.reject(err));
+			return
+		}
+
+		// The scores are sent right away, and then every `PEER_SCORES_INTERVAL` until either the
+		// subscription or the system RPC requests handler is closed.
+		let stream =
+			stream::unfold((self.send_back.clone(), true), |(send_back, first)| async move {
+				if !first {
+					Delay::new(PEER_SCORES_INTERVAL).await;
+				}
+				let (tx, rx) = oneshot::channel();
+				send_back.unbounded_send(Request::PeerScores(tx)).ok()?;
+				let scores = rx.await.ok()?;
+				Some((scores, (send_back, false)))
+			});
+
+		spawn_subscription_task(&self.executor, pipe_from_stream(pending, stream.boxed()));
 	}
 
 	async fn system_add_reserved_peer(&self, peer: String) -> RpcResult<()> {
@@ -144,8 +202,8 @@ impl<B: traits::Block> SystemApiServer<B::Hash, <B::Header as HeaderT>::Number>
 		let _ = self.send_back.unbounded_send(Request::NetworkAddReservedPeer(peer, tx));
 		match rx.await {
 			Ok(Ok(())) => Ok(()),
-			Ok(Err(e)) => Err(JsonRpseeError::from(e)),
-			Err(e) => Err(JsonRpseeError::to_call_error(e)),
+			Ok(Err(e)) => Err(e.into()),
+			Err(e) => Err(call_failed(e)),
 		}
 	}
 
@@ -155,50 +213,45 @@ impl<B: traits::Block> SystemApiServer<B::Hash, <B::Header as HeaderT>::Number>
 		let _ = self.send_back.unbounded_send(Request::NetworkRemoveReservedPeer(peer, tx));
 		match rx.await {
 			Ok(Ok(())) => Ok(()),
-			Ok(Err(e)) => Err(JsonRpseeError::from(e)),
-			Err(e) => Err(JsonRpseeError::to_call_error(e)),
+			Ok(Err(e)) => Err(e.into()),
+			Err(e) => Err(call_failed(e)),
 		}
 	}
 
 	async fn system_reserved_peers(&self) -> RpcResult<Vec<String>> {
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::NetworkReservedPeers(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
 	}
 
 	async fn system_node_roles(&self) -> RpcResult<Vec<NodeRole>> {
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::NodeRoles(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
 	}
 
 	async fn system_sync_state(&self) -> RpcResult<SyncState<<B::Header as HeaderT>::Number>> {
 		let (tx, rx) = oneshot::channel();
 		let _ = self.send_back.unbounded_send(Request::SyncState(tx));
-		rx.await.map_err(|e| JsonRpseeError::to_call_error(e))
+		rx.await.map_err(call_failed)
 	}
 
 	fn system_add_log_filter(&self, directives: String) -> RpcResult<()> {
 		self.deny_unsafe.check_if_safe()?;
 
 		logging::add_directives(&directives);
-		logging::reload_filter().map_err(|e| {
-			JsonRpseeError::Call(CallError::Custom(ErrorObject::owned(
-				ErrorCode::InternalError.code(),
-				e,
-				None::<()>,
-			)))
-		})
+		logging::reload_filter()
+			.map_err(|e| ErrorObject::owned(ErrorCode::InternalError.code(), e, None::<()>))
 	}
 
 	fn system_reset_log_filter(&self) -> RpcResult<()> {
 		self.deny_unsafe.check_if_safe()?;
-		logging::reset_log_filter().map_err(|e| {
-			JsonRpseeError::Call(CallError::Custom(ErrorObject::owned(
-				ErrorCode::InternalError.code(),
-				e,
-				None::<()>,
-			)))
-		})
+		logging::reset_log_filter()
+			.map_err(|e| ErrorObject::owned(ErrorCode::InternalError.code(), e, None::<()>))
 	}
 }
+
+/// Turns the failure of a call into the error returned to the client.
+fn call_failed(e: impl std::fmt::Display) -> ErrorObjectOwned {
+	ErrorObject::owned(CALL_EXECUTION_FAILED_CODE, e.to_string(), None::<()>)
+}
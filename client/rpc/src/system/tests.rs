@@ -17,11 +17,11 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use super::{helpers::SyncState, *};
+use crate::testing::{test_executor, timeout_secs};
 use assert_matches::assert_matches;
 use futures::prelude::*;
 use jsonrpsee::{
-	core::Error as RpcError,
-	types::{error::CallError, EmptyParams},
+	core::{server::MethodsError, EmptyServerParams},
 	RpcModule,
 };
 use sc_network::{self, config::Role, PeerId};
@@ -98,6 +98,31 @@ fn api<T: Into<Option<Status>>>(sync: T) -> RpcModule<System<Block>> {
 						.unwrap(),
 					);
 				},
+				Request::PeerScores(sender) => {
+					let _ = sender.send(vec![PeerScore {
+						peer_id: status.peer_id.to_base58(),
+						reputation: -10,
+						sets: vec![PeerSetMembership {
+							protocol: "/block-announces/1".into(),
+							direction: Some(ConnectionDirection::Outbound),
+							reserved: true,
+						}],
+						reputation_changes: vec![PeerReputationChange {
+							value: -10,
+							reason: "Duplicate gossip".into(),
+						}],
+						ban_expires_in: None,
+					}]);
+				},
+				Request::SyncPeerThroughput(sender) => {
+					let _ = sender.send(vec![SyncPeerThroughput {
+						peer_id: status.peer_id.to_base58(),
+						blocks_per_second: 8.0,
+						bytes_per_second: 8192.0,
+						latency_ms: 250,
+						request_size: 16,
+					}]);
+				},
 				Request::NetworkAddReservedPeer(peer, sender) => {
 					let _ = match sc_network_common::config::parse_str_addr(&peer) {
 						Ok(_) => sender.send(Ok(())),
@@ -141,6 +166,7 @@ fn api<T: Into<Option<Status>>>(sync: T) -> RpcModule<System<Block>> {
 		},
 		tx,
 		sc_rpc_api::DenyUnsafe::No,
+		test_executor(),
 	)
 	.into_rpc()
 }
@@ -148,7 +174,10 @@ fn api<T: Into<Option<Status>>>(sync: T) -> RpcModule<System<Block>> {
 #[tokio::test]
 async fn system_name_works() {
 	assert_eq!(
-		api(None).call::<_, String>("system_name", EmptyParams::new()).await.unwrap(),
+		api(None)
+			.call::<_, String>("system_name", EmptyServerParams::new())
+			.await
+			.unwrap(),
 		"testclient".to_string(),
 	);
 }
@@ -156,7 +185,10 @@ async fn system_name_works() {
 #[tokio::test]
 async fn system_version_works() {
 	assert_eq!(
-		api(None).call::<_, String>("system_version", EmptyParams::new()).await.unwrap(),
+		api(None)
+			.call::<_, String>("system_version", EmptyServerParams::new())
+			.await
+			.unwrap(),
 		"0.2.0".to_string(),
 	);
 }
@@ -164,7 +196,10 @@ async fn system_version_works() {
 #[tokio::test]
 async fn system_chain_works() {
 	assert_eq!(
-		api(None).call::<_, String>("system_chain", EmptyParams::new()).await.unwrap(),
+		api(None)
+			.call::<_, String>("system_chain", EmptyServerParams::new())
+			.await
+			.unwrap(),
 		"testchain".to_string(),
 	);
 }
@@ -174,7 +209,10 @@ async fn system_properties_works() {
 	type Map = serde_json::map::Map<String, serde_json::Value>;
 
 	assert_eq!(
-		api(None).call::<_, Map>("system_properties", EmptyParams::new()).await.unwrap(),
+		api(None)
+			.call::<_, Map>("system_properties", EmptyServerParams::new())
+			.await
+			.unwrap(),
 		Map::new()
 	);
 }
@@ -183,7 +221,7 @@ async fn system_properties_works() {
 async fn system_type_works() {
 	assert_eq!(
 		api(None)
-			.call::<_, String>("system_chainType", EmptyParams::new())
+			.call::<_, String>("system_chainType", EmptyServerParams::new())
 			.await
 			.unwrap(),
 		"Live".to_owned(),
@@ -193,13 +231,16 @@ async fn system_type_works() {
 #[tokio::test]
 async fn system_health() {
 	assert_eq!(
-		api(None).call::<_, Health>("system_health", EmptyParams::new()).await.unwrap(),
+		api(None)
+			.call::<_, Health>("system_health", EmptyServerParams::new())
+			.await
+			.unwrap(),
 		Health { peers: 0, is_syncing: false, should_have_peers: true },
 	);
 
 	assert_eq!(
 		api(Status { peer_id: PeerId::random(), peers: 5, is_syncing: true, is_dev: true })
-			.call::<_, Health>("system_health", EmptyParams::new())
+			.call::<_, Health>("system_health", EmptyServerParams::new())
 			.await
 			.unwrap(),
 		Health { peers: 5, is_syncing: true, should_have_peers: false },
@@ -207,7 +248,7 @@ async fn system_health() {
 
 	assert_eq!(
 		api(Status { peer_id: PeerId::random(), peers: 5, is_syncing: false, is_dev: false })
-			.call::<_, Health>("system_health", EmptyParams::new())
+			.call::<_, Health>("system_health", EmptyServerParams::new())
 			.await
 			.unwrap(),
 		Health { peers: 5, is_syncing: false, should_have_peers: true },
@@ -215,7 +256,7 @@ async fn system_health() {
 
 	assert_eq!(
 		api(Status { peer_id: PeerId::random(), peers: 0, is_syncing: false, is_dev: true })
-			.call::<_, Health>("system_health", EmptyParams::new())
+			.call::<_, Health>("system_health", EmptyServerParams::new())
 			.await
 			.unwrap(),
 		Health { peers: 0, is_syncing: false, should_have_peers: false },
@@ -226,7 +267,7 @@ async fn system_health() {
 async fn system_local_peer_id_works() {
 	assert_eq!(
 		api(None)
-			.call::<_, String>("system_localPeerId", EmptyParams::new())
+			.call::<_, String>("system_localPeerId", EmptyServerParams::new())
 			.await
 			.unwrap(),
 		"QmSk5HQbn6LhUwDiNMseVUjuRYhEtYj4aUZ6WfWoGURpdV".to_owned()
@@ -237,7 +278,7 @@ async fn system_local_peer_id_works() {
 async fn system_local_listen_addresses_works() {
 	assert_eq!(
 		api(None)
-			.call::<_, Vec<String>>("system_localListenAddresses", EmptyParams::new())
+			.call::<_, Vec<String>>("system_localListenAddresses", EmptyServerParams::new())
 			.await
 			.unwrap(),
 		vec![
@@ -252,7 +293,7 @@ async fn system_peers() {
 	let peer_id = PeerId::random();
 	let peer_info: Vec<PeerInfo<H256, u64>> =
 		api(Status { peer_id, peers: 1, is_syncing: false, is_dev: true })
-			.call("system_peers", EmptyParams::new())
+			.call("system_peers", EmptyServerParams::new())
 			.await
 			.unwrap();
 
@@ -271,7 +312,7 @@ async fn system_peers() {
 async fn system_network_state() {
 	use sc_network::network_state::NetworkState;
 	let network_state: NetworkState = api(None)
-		.call("system_unstable_networkState", EmptyParams::new())
+		.call("system_unstable_networkState", EmptyServerParams::new())
 		.await
 		.unwrap();
 	assert_eq!(
@@ -287,16 +328,49 @@ async fn system_network_state() {
 	);
 }
 
+#[tokio::test]
+async fn system_peer_scores() {
+	let peer_id = PeerId::random();
+	let api = api(Status { peer_id, ..Default::default() });
+
+	let scores: Vec<PeerScore> =
+		api.call("system_peerScores", EmptyServerParams::new()).await.unwrap();
+	assert_eq!(scores.len(), 1);
+	assert_eq!(scores[0].peer_id, peer_id.to_base58());
+	assert_eq!(scores[0].sets[0].direction, Some(ConnectionDirection::Outbound));
+
+	let mut sub = api
+		.subscribe_unbounded("system_subscribePeerScores", EmptyServerParams::new())
+		.await
+		.unwrap();
+	assert_matches!(
+		timeout_secs(10, sub.next::<Vec<PeerScore>>()).await,
+		Ok(Some(Ok((update, _)))) if update == scores
+	);
+}
+
+#[tokio::test]
+async fn system_sync_peer_throughput() {
+	let peer_id = PeerId::random();
+	let api = api(Status { peer_id, ..Default::default() });
+
+	let throughput: Vec<SyncPeerThroughput> =
+		api.call("system_syncPeerThroughput", EmptyServerParams::new()).await.unwrap();
+	assert_eq!(throughput.len(), 1);
+	assert_eq!(throughput[0].peer_id, peer_id.to_base58());
+	assert_eq!(throughput[0].request_size, 16);
+}
+
 #[tokio::test]
 async fn system_node_roles() {
 	let node_roles: Vec<NodeRole> =
-		api(None).call("system_nodeRoles", EmptyParams::new()).await.unwrap();
+		api(None).call("system_nodeRoles", EmptyServerParams::new()).await.unwrap();
 	assert_eq!(node_roles, vec![NodeRole::Authority]);
 }
 #[tokio::test]
 async fn system_sync_state() {
 	let sync_state: SyncState<i32> =
-		api(None).call("system_syncState", EmptyParams::new()).await.unwrap();
+		api(None).call("system_syncState", EmptyServerParams::new()).await.unwrap();
 	assert_eq!(sync_state, SyncState { starting_block: 1, current_block: 2, highest_block: 3 });
 }
 
@@ -312,7 +386,7 @@ async fn system_network_add_reserved() {
 	let bad_peer_id = ["/ip4/198.51.100.19/tcp/30333"];
 	assert_matches!(
 		api(None).call::<_, ()>("system_addReservedPeer", bad_peer_id).await,
-		Err(RpcError::Call(CallError::Custom(err))) if err.message().contains("Peer id is missing from the address")
+		Err(MethodsError::JsonRpc(err)) if err.message().contains("Peer id is missing from the address")
 	);
 }
 
@@ -328,13 +402,13 @@ async fn system_network_remove_reserved() {
 
 	assert_matches!(
 		api(None).call::<_, String>("system_removeReservedPeer", bad_peer_id).await,
-		Err(RpcError::Call(CallError::Custom(err))) if err.message().contains("base-58 decode error: provided string contained invalid character '/' at byte 0")
+		Err(MethodsError::JsonRpc(err)) if err.message().contains("base-58 decode error: provided string contained invalid character '/' at byte 0")
 	);
 }
 #[tokio::test]
 async fn system_network_reserved_peers() {
 	let reserved_peers: Vec<String> =
-		api(None).call("system_reservedPeers", EmptyParams::new()).await.unwrap();
+		api(None).call("system_reservedPeers", EmptyServerParams::new()).await.unwrap();
 	assert_eq!(reserved_peers, vec!["QmSk5HQbn6LhUwDiNMseVUjuRYhEtYj4aUZ6WfWoGURpdV".to_string()],);
 }
 
@@ -364,7 +438,7 @@ fn test_add_reset_log_filter() {
 				futures::executor::block_on(fut).expect("`system_addLogFilter (trace)` failed");
 			} else if line.contains("reset") {
 				let fut = async move {
-					api(None).call::<_, ()>("system_resetLogFilter", EmptyParams::new()).await
+					api(None).call::<_, ()>("system_resetLogFilter", EmptyServerParams::new()).await
 				};
 				futures::executor::block_on(fut).expect("`system_resetLogFilter` failed");
 			} else if line.contains("exit") {
@@ -0,0 +1,68 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers of the RPC subscriptions.
+
+use futures::{
+	future::{self, Either},
+	Stream, StreamExt,
+};
+use jsonrpsee::{PendingSubscriptionSink, SubscriptionMessage};
+use serde::Serialize;
+
+/// Accept the subscription and send it the items of `stream`, until either the stream ends or the
+/// subscription is closed.
+pub async fn pipe_from_stream<S, T>(pending: PendingSubscriptionSink, mut stream: S)
+where
+	S: Stream<Item = T> + Unpin,
+	T: Serialize,
+{
+	let sink = match pending.accept().await {
+		Ok(sink) => sink,
+		Err(_) => return,
+	};
+
+	let mut closed = Box::pin(sink.closed());
+	loop {
+		let item = match future::select(closed, stream.next()).await {
+			Either::Right((Some(item), still_open)) => {
+				closed = still_open;
+				item
+			},
+			Either::Right((None, _)) | Either::Left(_) => break,
+		};
+		let message = match to_message(&item) {
+			Some(message) => message,
+			None => break,
+		};
+		if sink.send(message).await.is_err() {
+			break
+		}
+	}
+}
+
+/// Serialize an item of a subscription.
+pub fn to_message<T: Serialize>(item: &T) -> Option<SubscriptionMessage> {
+	match serde_json::value::to_raw_value(item) {
+		Ok(raw) => Some(raw.into()),
+		Err(e) => {
+			log::error!(target: "rpc", "Failed to serialize a subscription item: {}", e);
+			None
+		},
+	}
+}
@@ -26,12 +26,16 @@ use crate::{
 };
 use futures::{channel::oneshot, future::ready, FutureExt, StreamExt};
 use jsonrpsee::RpcModule;
-use log::info;
+use log::{info, warn};
 use prometheus_endpoint::Registry;
 use sc_chain_spec::get_extension;
 use sc_client_api::{
-	execution_extensions::ExecutionExtensions, proof_provider::ProofProvider, BadBlocks,
-	BlockBackend, BlockchainEvents, ExecutorProvider, ForkBlocks, StorageProvider, UsageProvider,
+	backend::{AuxStore, StateSyncStore},
+	execution_extensions::ExecutionExtensions,
+	light::RemoteBackend,
+	proof_provider::ProofProvider,
+	BadBlocks, BlockBackend, BlockchainEvents, ExecutorProvider, ForkBlocks, StorageProvider,
+	UsageProvider,
 };
 use sc_client_db::{Backend, DatabaseSettings};
 use sc_consensus::import_queue::ImportQueue;
@@ -39,17 +43,24 @@ use sc_executor::RuntimeVersionOf;
 use sc_keystore::LocalKeystore;
 use sc_network::{config::SyncMode, NetworkService};
 use sc_network_common::{
-	service::{NetworkStateInfo, NetworkStatusProvider, NetworkTransaction},
+	service::{
+		NetworkEventStream, NetworkRequest, NetworkStateInfo, NetworkStatusProvider,
+		NetworkTransaction,
+	},
 	sync::warp::WarpSyncProvider,
 };
-use sc_network_light::light_client_requests::handler::LightClientRequestHandler;
+use sc_network_light::light_client_requests::{
+	handler::LightClientRequestHandler, sender::LightClientRequestSender,
+};
 use sc_network_sync::{
 	block_request_handler::BlockRequestHandler, state_request_handler::StateRequestHandler,
 	warp_request_handler::RequestHandler as WarpSyncRequestHandler, ChainSync,
 };
 use sc_rpc::{
+	archive::ArchiveApiServer,
 	author::AuthorApiServer,
 	chain::ChainApiServer,
+	chain_head::ChainHeadApiServer,
 	offchain::OffchainApiServer,
 	state::{ChildStateApiServer, StateApiServer},
 	system::SystemApiServer,
@@ -70,7 +81,11 @@ use sp_runtime::{
 	traits::{Block as BlockT, BlockIdTo, NumberFor, Zero},
 	BuildStorage,
 };
-use std::{str::FromStr, sync::Arc, time::SystemTime};
+use std::{
+	str::FromStr,
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
 
 /// Full client type.
 pub type TFullClient<TBl, TRtApi, TExec> =
@@ -210,9 +225,21 @@ where
 			state_pruning: config.state_pruning.clone(),
 			source: config.database.clone(),
 			blocks_pruning: config.blocks_pruning,
+			blocks_retention: config.blocks_retention.clone(),
+			changes_index: config.changes_index,
 		};
 
 		let backend = new_db_backend(db_config)?;
+		if let Some(registry) = config.prometheus_registry() {
+			backend.register_column_metrics(registry)?;
+		}
+		if backend.is_read_only() {
+			task_manager.spawn_handle().spawn_blocking(
+				"db-catch-up",
+				Some("db"),
+				catch_up_with_primary(backend.clone()),
+			);
+		}
 
 		let extensions = sc_client_api::execution_extensions::ExecutionExtensions::new(
 			config.execution_strategies.clone(),
@@ -253,7 +280,7 @@ where
 				wasm_runtime_overrides: config.wasm_runtime_overrides.clone(),
 				no_genesis: matches!(
 					config.network.sync_mode,
-					SyncMode::Fast { .. } | SyncMode::Warp { .. }
+					SyncMode::Fast { .. } | SyncMode::Warp { .. } | SyncMode::Light
 				),
 				wasm_runtime_substitutes,
 			},
@@ -265,6 +292,19 @@ where
 	Ok((client, backend, keystore_container, task_manager))
 }
 
+/// Interval at which a database opened read-only catches up with the node owning it.
+const DB_CATCH_UP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keep a database opened read-only up to date with the writes of the node owning it.
+async fn catch_up_with_primary<Block: BlockT>(backend: Arc<Backend<Block>>) {
+	loop {
+		futures_timer::Delay::new(DB_CATCH_UP_INTERVAL).await;
+		if let Err(e) = backend.catch_up_with_primary() {
+			warn!("{}", e);
+		}
+	}
+}
+
 /// Create an instance of default DB-backend backend.
 pub fn new_db_backend<Block>(
 	settings: DatabaseSettings,
@@ -327,6 +367,8 @@ pub trait SpawnTaskNetwork<Block: BlockT>:
 	+ NetworkStateInfo
 	+ NetworkTransaction<Block::Hash>
 	+ NetworkStatusProvider<Block>
+	+ NetworkRequest
+	+ NetworkEventStream
 	+ Send
 	+ Sync
 	+ 'static
@@ -340,6 +382,8 @@ where
 		+ NetworkStateInfo
 		+ NetworkTransaction<Block::Hash>
 		+ NetworkStatusProvider<Block>
+		+ NetworkRequest
+		+ NetworkEventStream
 		+ Send
 		+ Sync
 		+ 'static,
@@ -506,6 +550,20 @@ where
 		metrics_service.run(client.clone(), transaction_pool.clone(), network.clone()),
 	);
 
+	// In light sync mode, the state is read from the full nodes we are syncing from.
+	let remote_backend = if config.network.sync_mode.is_light() {
+		let (sender, track_peers) = LightClientRequestSender::new(
+			&config.protocol_id(),
+			config.chain_spec.fork_id(),
+			client.clone(),
+			Arc::new(network.clone()),
+		)?;
+		spawn_handle.spawn("light-client-request-sender", Some("networking"), track_peers);
+		Some(Arc::new(sender) as Arc<dyn RemoteBackend<TBl>>)
+	} else {
+		None
+	};
+
 	let rpc_id_provider = config.rpc_id_provider.take();
 
 	// jsonrpsee RPC
@@ -518,7 +576,8 @@ where
 			keystore.clone(),
 			system_rpc_tx.clone(),
 			&config,
-			backend.offchain_storage(),
+			backend.clone(),
+			remote_backend.clone(),
 			&*rpc_builder,
 		)
 	};
@@ -616,7 +675,8 @@ fn gen_rpc_module<TBl, TBackend, TCl, TRpc, TExPool>(
 	keystore: SyncCryptoStorePtr,
 	system_rpc_tx: TracingUnboundedSender<sc_rpc::system::Request<TBl>>,
 	config: &Configuration,
-	offchain_storage: Option<<TBackend as sc_client_api::backend::Backend<TBl>>::OffchainStorage>,
+	backend: Arc<TBackend>,
+	remote_backend: Option<Arc<dyn RemoteBackend<TBl>>>,
 	rpc_builder: &(dyn Fn(DenyUnsafe, SubscriptionTaskExecutor) -> Result<RpcModule<TRpc>, Error>),
 ) -> Result<RpcModule<()>, Error>
 where
@@ -652,12 +712,49 @@ where
 
 	let (chain, state, child_state) = {
 		let chain = sc_rpc::chain::new_full(client.clone(), task_executor.clone()).into_rpc();
-		let (state, child_state) = sc_rpc::state::new_full(
-			client.clone(),
-			task_executor.clone(),
-			deny_unsafe,
-			config.rpc_max_payload,
-		);
+		// Light nodes don't keep the bodies and states the follow subscriptions would pin.
+		if remote_backend.is_none() {
+			let chain_head = sc_rpc::chain_head::ChainHead::new(
+				client.clone(),
+				backend.clone(),
+				task_executor.clone(),
+				deny_unsafe,
+			)
+			.into_rpc();
+			rpc_api.merge(chain_head).map_err(|e| Error::Application(e.into()))?;
+
+			// The archive methods fail on the nodes which prune blocks or states.
+			let archive = sc_rpc::archive::Archive::new(
+				client.clone(),
+				backend.clone(),
+				task_executor.clone(),
+			)
+			.into_rpc();
+			rpc_api.merge(archive).map_err(|e| Error::Application(e.into()))?;
+		}
+		let (state, child_state) = match remote_backend {
+			Some(remote_backend) => sc_rpc::state::new_light(
+				client.clone(),
+				task_executor.clone(),
+				remote_backend,
+				deny_unsafe,
+			),
+			None => sc_rpc::state::new_full(
+				client.clone(),
+				task_executor.clone(),
+				deny_unsafe,
+				config.rpc_max_payload,
+				// The maximum size of the HTTP responses, see `legacy_cli_parsing`.
+				Some(
+					config
+						.rpc_max_payload
+						.max(config.rpc_max_response_size)
+						.map_or(sc_rpc_server::RPC_MAX_PAYLOAD_DEFAULT, |mb| {
+							mb.saturating_mul(1024 * 1024)
+						}),
+				),
+			),
+		};
 		let state = state.into_rpc();
 		let child_state = child_state.into_rpc();
 
@@ -673,9 +770,11 @@ where
 	)
 	.into_rpc();
 
-	let system = sc_rpc::system::System::new(system_info, system_rpc_tx, deny_unsafe).into_rpc();
+	let system =
+		sc_rpc::system::System::new(system_info, system_rpc_tx, deny_unsafe, task_executor.clone())
+			.into_rpc();
 
-	if let Some(storage) = offchain_storage {
+	if let Some(storage) = backend.offchain_storage() {
 		let offchain = sc_rpc::offchain::Offchain::new(storage, deny_unsafe).into_rpc();
 
 		rpc_api.merge(offchain).map_err(|e| Error::Application(e.into()))?;
@@ -733,6 +832,8 @@ where
 		+ ProofProvider<TBl>
 		+ HeaderBackend<TBl>
 		+ BlockchainEvents<TBl>
+		+ AuxStore
+		+ StateSyncStore
 		+ 'static,
 	TExPool: MaintainedTransactionPool<Block = TBl, Hash = <TBl as BlockT>::Hash> + 'static,
 	TImpQu: ImportQueue<TBl> + 'static,
@@ -755,6 +856,7 @@ where
 		match config.network.sync_mode {
 			SyncMode::Fast { .. } => return Err("Fast sync doesn't work for archive nodes".into()),
 			SyncMode::Warp => return Err("Warp sync doesn't work for archive nodes".into()),
+			SyncMode::Light => return Err("Light sync doesn't work for archive nodes".into()),
 			SyncMode::Full => {},
 		}
 	}
@@ -830,6 +932,7 @@ where
 			SyncMode::Fast { skip_proofs, storage_chain_mode } =>
 				sc_network_common::sync::SyncMode::LightState { skip_proofs, storage_chain_mode },
 			SyncMode::Warp => sc_network_common::sync::SyncMode::Warp,
+			SyncMode::Light => sc_network_common::sync::SyncMode::Light,
 		},
 		client.clone(),
 		block_announce_validator,
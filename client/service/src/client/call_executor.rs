@@ -26,7 +26,10 @@ use sp_core::{
 	NativeOrEncoded, NeverNativeValue,
 };
 use sp_externalities::Extensions;
-use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, HashFor},
+};
 use sp_state_machine::{
 	backend::AsTrieBackend, ExecutionManager, ExecutionStrategy, Ext, OverlayedChanges,
 	StateMachine, StorageProof,
@@ -308,6 +311,33 @@ where
 		)
 		.map_err(Into::into)
 	}
+
+	fn check_execution_proof(
+		&self,
+		state_root: Block::Hash,
+		method: &str,
+		call_data: &[u8],
+		proof: StorageProof,
+	) -> sp_blockchain::Result<Vec<u8>> {
+		let trie_backend =
+			sp_state_machine::create_proof_check_backend::<HashFor<Block>>(state_root, proof)
+				.map_err(sp_blockchain::Error::from_state)?;
+
+		let state_runtime_code = sp_state_machine::backend::BackendRuntimeCode::new(&trie_backend);
+		let runtime_code =
+			state_runtime_code.runtime_code().map_err(sp_blockchain::Error::RuntimeCode)?;
+
+		sp_state_machine::execution_proof_check_on_trie_backend(
+			&trie_backend,
+			&mut Default::default(),
+			&self.executor,
+			self.spawn_handle.clone(),
+			method,
+			call_data,
+			&runtime_code,
+		)
+		.map_err(Into::into)
+	}
 }
 
 impl<B, E, Block> RuntimeVersionOf for LocalCallExecutor<Block, B, E>
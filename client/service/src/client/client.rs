@@ -30,8 +30,8 @@ use rand::Rng;
 use sc_block_builder::{BlockBuilderApi, BlockBuilderProvider, RecordProof};
 use sc_client_api::{
 	backend::{
-		self, apply_aux, BlockImportOperation, ClientImportOperation, FinalizeSummary, Finalizer,
-		ImportSummary, LockImportRun, NewBlockState, StorageProvider,
+		self, apply_aux, BlockImportOperation, ChangesIndex, ClientImportOperation,
+		FinalizeSummary, Finalizer, ImportSummary, LockImportRun, NewBlockState, StorageProvider,
 	},
 	client::{
 		BadBlocks, BlockBackend, BlockImportNotification, BlockOf, BlockchainEvents, ClientInfo,
@@ -1179,7 +1179,15 @@ where
 		method: &str,
 		call_data: &[u8],
 	) -> sp_blockchain::Result<(Vec<u8>, StorageProof)> {
-		self.executor.prove_execution(id, method, call_data)
+		// The runtime code is read before the execution is recorded, but the light clients
+		// checking the proof need it too.
+		let code_proof = self.read_proof(
+			id,
+			&mut [well_known_keys::CODE, well_known_keys::HEAP_PAGES].iter().copied(),
+		)?;
+		self.executor
+			.prove_execution(id, method, call_data)
+			.map(|(result, proof)| (result, StorageProof::merge(vec![proof, code_proof])))
 	}
 
 	fn read_proof_collection(
@@ -1510,6 +1518,10 @@ where
 			.child_storage_hash(child_info, &key.0)
 			.map_err(|e| sp_blockchain::Error::from_state(Box::new(e)))
 	}
+
+	fn changes_index(&self) -> Option<&dyn ChangesIndex<Block>> {
+		self.backend.changes_index()
+	}
 }
 
 impl<B, E, Block, RA> HeaderMetadata<Block> for Client<B, E, Block, RA>
@@ -2031,6 +2043,25 @@ where
 	}
 }
 
+impl<B, E, Block, RA> backend::StateSyncStore for Client<B, E, Block, RA>
+where
+	B: backend::Backend<Block>,
+	E: CallExecutor<Block>,
+	Block: BlockT,
+{
+	fn insert_state_sync_chunk(&self, index: u32, chunk: &[u8]) -> sp_blockchain::Result<()> {
+		self.backend.insert_state_sync_chunk(index, chunk)
+	}
+
+	fn state_sync_chunk(&self, index: u32) -> sp_blockchain::Result<Option<Vec<u8>>> {
+		self.backend.state_sync_chunk(index)
+	}
+
+	fn remove_state_sync_chunks(&self, count: u32) -> sp_blockchain::Result<()> {
+		self.backend.remove_state_sync_chunks(count)
+	}
+}
+
 impl<B, E, Block, RA> backend::AuxStore for &Client<B, E, Block, RA>
 where
 	B: backend::Backend<Block>,
@@ -19,7 +19,7 @@
 //! Service configuration.
 
 pub use sc_client_api::execution_extensions::{ExecutionStrategies, ExecutionStrategy};
-pub use sc_client_db::{BlocksPruning, Database, DatabaseSource, PruningMode};
+pub use sc_client_db::{BlocksPruning, BlocksRetention, Database, DatabaseSource, PruningMode};
 pub use sc_executor::WasmExecutionMethod;
 #[cfg(feature = "wasmtime")]
 pub use sc_executor::WasmtimeInstantiationStrategy;
@@ -35,6 +35,7 @@ pub use sc_network_common::{
 		IncomingRequest, OutgoingResponse, ProtocolConfig as RequestResponseConfig,
 	},
 };
+pub use sc_rpc_server::RateLimitConfig;
 
 use prometheus_endpoint::Registry;
 use sc_chain_spec::ChainSpec;
@@ -79,6 +80,10 @@ pub struct Configuration {
 	///
 	/// NOTE: only finalized blocks are subject for removal!
 	pub blocks_pruning: BlocksPruning,
+	/// Retention of the indexed transactions and bodies beyond `blocks_pruning`.
+	pub blocks_retention: BlocksRetention,
+	/// Index the storage keys modified by each block.
+	pub changes_index: bool,
 	/// Chain configuration.
 	pub chain_spec: Box<dyn ChainSpec>,
 	/// Wasm execution method.
@@ -115,6 +120,10 @@ pub struct Configuration {
 	///
 	/// Default: 1024.
 	pub rpc_max_subs_per_conn: Option<usize>,
+	/// Maximum number of calls in a batch request. `None` if unlimited.
+	pub rpc_max_batch_len: Option<usize>,
+	/// Call-rate limits of the RPC server.
+	pub rpc_rate_limit: RateLimitConfig,
 	/// Maximum size of the output buffer capacity for websocket connections.
 	pub ws_max_out_buffer_capacity: Option<usize>,
 	/// Prometheus endpoint configuration. `None` if disabled.
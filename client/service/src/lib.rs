@@ -34,16 +34,15 @@ mod client;
 mod metrics;
 mod task_manager;
 
-use std::{collections::HashMap, net::SocketAddr};
+use std::{borrow::Cow, collections::HashMap};
 
 use codec::{Decode, Encode};
-use futures::{channel::mpsc, FutureExt, StreamExt};
-use jsonrpsee::{core::Error as JsonRpseeError, RpcModule};
+use futures::{FutureExt, StreamExt};
+use jsonrpsee::{core::JsonRawValue, RpcModule};
 use log::{debug, error, warn};
 use sc_client_api::{blockchain::HeaderBackend, BlockBackend, BlockchainEvents, ProofProvider};
 use sc_network::PeerId;
 use sc_network_common::service::NetworkBlock;
-use sc_rpc_server::WsConfig;
 use sc_utils::mpsc::TracingUnboundedReceiver;
 use sp_blockchain::HeaderMetadata;
 use sp_consensus::SyncOracle;
@@ -62,7 +61,8 @@ pub use self::{
 	error::Error,
 };
 pub use config::{
-	BasePath, BlocksPruning, Configuration, DatabaseSource, PruningMode, Role, RpcMethods, TaskType,
+	BasePath, BlocksPruning, BlocksRetention, Configuration, DatabaseSource, PruningMode, Role,
+	RpcMethods, TaskType,
 };
 pub use sc_chain_spec::{
 	ChainSpec, ChainType, Extension as ChainSpecExtension, GenericChainSpec, NoExtension,
@@ -102,11 +102,14 @@ impl RpcHandlers {
 	pub async fn rpc_query(
 		&self,
 		json_query: &str,
-	) -> Result<(String, mpsc::UnboundedReceiver<String>), JsonRpseeError> {
+	) -> Result<(String, tokio::sync::mpsc::Receiver<Box<JsonRawValue>>), serde_json::Error> {
+		// The notifications are buffered until they are read, like the replies of a connection.
+		const MAX_BUFFERED_NOTIFICATIONS: usize = tokio::sync::Semaphore::MAX_PERMITS;
+
 		self.0
-			.raw_json_request(json_query)
+			.raw_json_request(json_query, MAX_BUFFERED_NOTIFICATIONS)
 			.await
-			.map(|(method_res, recv)| (method_res.result, recv))
+			.map(|(method_res, recv)| (method_res.to_string(), recv))
 	}
 
 	/// Provides access to the underlying `RpcModule`
@@ -229,6 +232,47 @@ async fn build_network_future<
 							let _ = sender.send(network_state);
 						}
 					}
+					sc_rpc::system::Request::SyncPeerThroughput(sender) => {
+						let _ = sender.send(network.sync_peer_throughput().into_iter().map(
+							|(peer_id, throughput)| sc_rpc::system::SyncPeerThroughput {
+								peer_id: peer_id.to_base58(),
+								blocks_per_second: throughput.blocks_per_second,
+								bytes_per_second: throughput.bytes_per_second,
+								latency_ms: throughput.latency.as_millis() as u64,
+								request_size: throughput.request_size,
+							}
+						).collect());
+					}
+					sc_rpc::system::Request::PeerScores(sender) => {
+						use sc_rpc::system::{
+							ConnectionDirection, PeerReputationChange, PeerScore, PeerSetMembership,
+						};
+
+						let scores = network.peer_scores().into_iter().map(|score| PeerScore {
+							peer_id: score.peer_id.to_base58(),
+							reputation: score.reputation,
+							sets: score.sets.into_iter().map(|set| PeerSetMembership {
+								protocol: network.peerset_protocol_name(set.set_id)
+									.map_or_else(|| format!("{:?}", set.set_id), Cow::into_owned),
+								direction: match set.state {
+									sc_network::ConnectionState::NotConnected => None,
+									sc_network::ConnectionState::Inbound =>
+										Some(ConnectionDirection::Inbound),
+									sc_network::ConnectionState::Outbound =>
+										Some(ConnectionDirection::Outbound),
+								},
+								reserved: set.reserved,
+							}).collect(),
+							reputation_changes: score.reputation_changes.into_iter().map(|change|
+								PeerReputationChange {
+									value: change.value,
+									reason: change.reason.into(),
+								}
+							).collect(),
+							ban_expires_in: score.banned_for.map(|duration| duration.as_secs()),
+						}).collect();
+						let _ = sender.send(scores);
+					}
 					sc_rpc::system::Request::NetworkAddReservedPeer(peer_addr, sender) => {
 						let x = network.add_reserved_peer(peer_addr)
 							.map_err(sc_rpc::system::error::Error::MalformattedPeerArg);
@@ -285,32 +329,7 @@ async fn build_network_future<
 	}
 }
 
-// Wrapper for HTTP and WS servers that makes sure they are properly shut down.
-mod waiting {
-	pub struct HttpServer(pub Option<sc_rpc_server::HttpServer>);
-
-	impl Drop for HttpServer {
-		fn drop(&mut self) {
-			if let Some(server) = self.0.take() {
-				// This doesn't not wait for the server to be stopped but fires the signal.
-				let _ = server.stop();
-			}
-		}
-	}
-
-	pub struct WsServer(pub Option<sc_rpc_server::WsServer>);
-
-	impl Drop for WsServer {
-		fn drop(&mut self) {
-			if let Some(server) = self.0.take() {
-				// This doesn't not wait for the server to be stopped but fires the signal.
-				let _ = server.stop();
-			}
-		}
-	}
-}
-
-/// Starts RPC servers.
+/// Starts the RPC server.
 fn start_rpc_servers<R>(
 	config: &Configuration,
 	gen_rpc_module: R,
@@ -322,62 +341,51 @@ where
 	let (max_request_size, ws_max_response_size, http_max_response_size) =
 		legacy_cli_parsing(config);
 
-	fn deny_unsafe(addr: SocketAddr, methods: &RpcMethods) -> sc_rpc::DenyUnsafe {
-		let is_exposed_addr = !addr.ip().is_loopback();
-		match (is_exposed_addr, methods) {
-			| (_, RpcMethods::Unsafe) | (false, RpcMethods::Auto) => sc_rpc::DenyUnsafe::No,
-			_ => sc_rpc::DenyUnsafe::Yes,
-		}
-	}
-
-	let random_port = |mut addr: SocketAddr| {
-		addr.set_port(0);
-		addr
-	};
-
 	let ws_addr = config
 		.rpc_ws
 		.unwrap_or_else(|| "127.0.0.1:9944".parse().expect("valid sockaddr; qed"));
-	let ws_addr2 = random_port(ws_addr);
 	let http_addr = config
 		.rpc_http
 		.unwrap_or_else(|| "127.0.0.1:9933".parse().expect("valid sockaddr; qed"));
-	let http_addr2 = random_port(http_addr);
+	// Both addresses serve HTTP and WebSocket.
+	let mut addrs = vec![http_addr];
+	if ws_addr != http_addr {
+		addrs.push(ws_addr);
+	}
+
+	// The same API is served on all the addresses, so the unsafe methods are only allowed if
+	// none of them is exposed.
+	let is_exposed = addrs.iter().any(|addr| !addr.ip().is_loopback());
+	let deny_unsafe = match (is_exposed, &config.rpc_methods) {
+		| (_, RpcMethods::Unsafe) | (false, RpcMethods::Auto) => sc_rpc::DenyUnsafe::No,
+		_ => sc_rpc::DenyUnsafe::Yes,
+	};
 
 	let metrics = sc_rpc_server::RpcMetrics::new(config.prometheus_registry())?;
 
-	let http_fut = sc_rpc_server::start_http(
-		[http_addr, http_addr2],
-		config.rpc_cors.as_ref(),
-		max_request_size,
-		http_max_response_size,
-		metrics.clone(),
-		gen_rpc_module(deny_unsafe(ws_addr, &config.rpc_methods))?,
-		config.tokio_handle.clone(),
-	);
-
-	let ws_config = WsConfig {
-		max_connections: config.rpc_ws_max_connections,
-		max_payload_in_mb: max_request_size,
-		max_payload_out_mb: ws_max_response_size,
-		max_subs_per_conn: config.rpc_max_subs_per_conn,
+	// The HTTP and WebSocket responses are sent by the same server.
+	let max_payload_out_mb = match (ws_max_response_size, http_max_response_size) {
+		(Some(ws), Some(http)) => Some(std::cmp::max(ws, http)),
+		(ws, http) => ws.or(http),
 	};
 
-	let ws_fut = sc_rpc_server::start_ws(
-		[ws_addr, ws_addr2],
-		config.rpc_cors.as_ref(),
-		ws_config,
+	let server_fut = sc_rpc_server::start_server(sc_rpc_server::Config {
+		addrs: &addrs,
+		cors: config.rpc_cors.as_ref(),
+		max_connections: config.rpc_ws_max_connections,
+		max_subs_per_conn: config.rpc_max_subs_per_conn,
+		max_payload_in_mb: max_request_size,
+		max_payload_out_mb,
+		max_batch_len: config.rpc_max_batch_len,
+		rate_limit: config.rpc_rate_limit.clone(),
 		metrics,
-		gen_rpc_module(deny_unsafe(http_addr, &config.rpc_methods))?,
-		config.tokio_handle.clone(),
-		rpc_id_provider,
-	);
-
-	match tokio::task::block_in_place(|| {
-		config.tokio_handle.block_on(futures::future::try_join(http_fut, ws_fut))
-	}) {
-		Ok((http, ws)) =>
-			Ok(Box::new((waiting::HttpServer(Some(http)), waiting::WsServer(Some(ws))))),
+		id_provider: rpc_id_provider,
+		rpc_api: gen_rpc_module(deny_unsafe)?,
+		tokio_handle: config.tokio_handle.clone(),
+	});
+
+	match tokio::task::block_in_place(|| config.tokio_handle.block_on(server_fut)) {
+		Ok(server) => Ok(Box::new(server)),
 		Err(e) => Err(Error::Application(e)),
 	}
 }
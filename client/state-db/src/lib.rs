@@ -37,20 +37,44 @@
 //! number of blocks that can fit in the non-canonical overlay, so canonicalization of an
 //! unfinalized block may be forced.
 //!
+//! The journal records are checksummed. Records left corrupted by an interrupted write are
+//! discarded along with their descendants when the overlay is rebuilt, instead of failing to
+//! open the database. Use `inspect_non_canonical_journal` to list the records stored in the DB.
+//!
 //! # Pruning.
 //! See `RefWindow` for pruning algorithm details. `StateDb` prunes on each canonicalization until
 //! pruning constraints are satisfied.
+//!
+//! # Switching from archive.
+//! A database kept in an archive mode may be opened with a pruning mode using
+//! `StateDb::open_from_archive`. The last canonical block becomes the first block of the pruning
+//! window and the state of older blocks is considered pruned. On databases without reference
+//! counting the nodes of those states are then removed block by block by an archive garbage
+//! collection pass driven by the caller, see `StateDb::collect_archive_garbage`. Databases with
+//! reference counting can't tell the nodes of the archived states apart and are not switched.
+//!
+//! A constrained pruning window may be widened when the database is opened, but never shrunk. A
+//! wider window only fills up with the blocks canonicalized afterwards, pruned states are not
+//! restored.
+//!
+//! # Checkpoints.
+//! In the `Hybrid` pruning mode the state of every `checkpoint_interval`-th canonical block is
+//! kept in the DB after the block leaves the pruning window. This requires opening the state DB
+//! without reference counting (`ref_counting: false`), i.e. over a backing database that counts
+//! references itself.
 
 mod noncanonical;
 mod pruning;
 #[cfg(test)]
 mod test;
 
-use codec::Codec;
+use codec::{Codec, Decode, Encode};
 use log::trace;
 use noncanonical::NonCanonicalOverlay;
+pub use noncanonical::{fetch_last_canonical, JournalEntry, JournalEntryError};
 use parity_util_mem::{malloc_size, MallocSizeOf};
 use parking_lot::RwLock;
+pub use pruning::fetch_last_pruned;
 use pruning::RefWindow;
 use sc_client_api::{MemorySize, StateDbMemoryInfo};
 use std::{
@@ -62,6 +86,12 @@ const PRUNING_MODE: &[u8] = b"mode";
 const PRUNING_MODE_ARCHIVE: &[u8] = b"archive";
 const PRUNING_MODE_ARCHIVE_CANON: &[u8] = b"archive_canonical";
 const PRUNING_MODE_CONSTRAINED: &[u8] = b"constrained";
+const PRUNING_MODE_HYBRID: &[u8] = b"hybrid";
+const PRUNING_CHECKPOINT_INTERVAL: &[u8] = b"checkpoint_interval";
+const PRUNING_MAX_BLOCKS: &[u8] = b"max_blocks";
+
+/// Default interval between retained checkpoints in the `Hybrid` pruning mode.
+pub const DEFAULT_CHECKPOINT_INTERVAL: u32 = 10_000;
 
 /// Database value type.
 pub type DBValue = Vec<u8>;
@@ -138,6 +168,12 @@ pub enum StateDbError {
 	BlockAlreadyExists,
 	/// Invalid metadata
 	Metadata(String),
+	/// Retaining checkpoints is not supported when the state DB does reference counting.
+	CheckpointsUnsupportedWithRefCounting,
+	/// The requested pruning mode is not supported.
+	UnsupportedPruningMode(PruningMode),
+	/// The non-canonical journal was written in a newer format.
+	UnsupportedJournalVersion(u8),
 }
 
 impl<E> From<StateDbError> for Error<E> {
@@ -152,6 +188,17 @@ pub enum PinError {
 	InvalidBlock,
 }
 
+/// Result of checking whether the state of a block has been pruned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsPruned {
+	/// Definitely pruned.
+	Pruned,
+	/// Definitely not pruned.
+	NotPruned,
+	/// May or may not be pruned, the caller should check the backing DB for the state root.
+	MaybePruned,
+}
+
 impl<E: fmt::Debug> From<codec::Error> for Error<E> {
 	fn from(x: codec::Error) -> Self {
 		StateDbError::Decoding(x).into()
@@ -182,6 +229,11 @@ impl fmt::Debug for StateDbError {
 			Self::TooManySiblingBlocks => write!(f, "Too many sibling blocks inserted"),
 			Self::BlockAlreadyExists => write!(f, "Block already exists"),
 			Self::Metadata(message) => write!(f, "Invalid metadata: {}", message),
+			Self::CheckpointsUnsupportedWithRefCounting =>
+				write!(f, "Retaining checkpoints requires a state DB without reference counting"),
+			Self::UnsupportedPruningMode(mode) => write!(f, "Unsupported pruning mode {:?}", mode),
+			Self::UnsupportedJournalVersion(version) =>
+				write!(f, "Unsupported non-canonical journal version {}", version),
 		}
 	}
 }
@@ -223,6 +275,9 @@ pub enum PruningMode {
 	ArchiveAll,
 	/// Canonicalization discards non-canonical nodes. All the canonical nodes are kept in the DB.
 	ArchiveCanonical,
+	/// Maintain a pruning window and additionally keep the state of every
+	/// `checkpoint_interval`-th canonical block.
+	Hybrid { constraints: Constraints, checkpoint_interval: u32 },
 }
 
 impl PruningMode {
@@ -231,11 +286,46 @@ impl PruningMode {
 		PruningMode::Constrained(Constraints { max_blocks: Some(n), max_mem: None })
 	}
 
+	/// Create a mode that keeps given number of blocks and the state of every
+	/// `checkpoint_interval`-th canonical block.
+	pub fn hybrid(n: u32, checkpoint_interval: u32) -> PruningMode {
+		PruningMode::Hybrid {
+			constraints: Constraints { max_blocks: Some(n), max_mem: None },
+			checkpoint_interval,
+		}
+	}
+
 	/// Is this an archive (either ArchiveAll or ArchiveCanonical) pruning mode?
 	pub fn is_archive(&self) -> bool {
 		match *self {
 			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => true,
-			PruningMode::Constrained(_) => false,
+			PruningMode::Constrained(_) | PruningMode::Hybrid { .. } => false,
+		}
+	}
+
+	/// Returns `true` if the state of the canonical block with the given number is retained
+	/// after it leaves the pruning window.
+	pub fn is_checkpoint(&self, number: u64) -> bool {
+		match *self {
+			PruningMode::Hybrid { checkpoint_interval, .. } =>
+				checkpoint_interval != 0 && number % checkpoint_interval as u64 == 0,
+			_ => false,
+		}
+	}
+
+	fn constraints(&self) -> Option<&Constraints> {
+		match self {
+			PruningMode::Constrained(constraints) | PruningMode::Hybrid { constraints, .. } =>
+				Some(constraints),
+			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => None,
+		}
+	}
+
+	fn constraints_mut(&mut self) -> Option<&mut Constraints> {
+		match self {
+			PruningMode::Constrained(constraints) | PruningMode::Hybrid { constraints, .. } =>
+				Some(constraints),
+			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => None,
 		}
 	}
 
@@ -245,6 +335,7 @@ impl PruningMode {
 			PruningMode::ArchiveAll => PRUNING_MODE_ARCHIVE,
 			PruningMode::ArchiveCanonical => PRUNING_MODE_ARCHIVE_CANON,
 			PruningMode::Constrained(_) => PRUNING_MODE_CONSTRAINED,
+			PruningMode::Hybrid { .. } => PRUNING_MODE_HYBRID,
 		}
 	}
 
@@ -253,6 +344,10 @@ impl PruningMode {
 			PRUNING_MODE_ARCHIVE => Some(Self::ArchiveAll),
 			PRUNING_MODE_ARCHIVE_CANON => Some(Self::ArchiveCanonical),
 			PRUNING_MODE_CONSTRAINED => Some(Self::Constrained(Default::default())),
+			PRUNING_MODE_HYBRID => Some(Self::Hybrid {
+				constraints: Default::default(),
+				checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+			}),
 			_ => None,
 		}
 	}
@@ -270,6 +365,71 @@ impl Default for Constraints {
 	}
 }
 
+/// Trie node changes recorded by a state database journal entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalChanges<BlockHash> {
+	/// Hash of the block the changes belong to.
+	pub hash: BlockHash,
+	/// Hash of the parent block. Only recorded by non-canonical journal entries.
+	pub parent_hash: Option<BlockHash>,
+	/// Keys of the inserted nodes. Non-canonical journal entries also record the node values.
+	pub inserted: Vec<(Vec<u8>, Option<DBValue>)>,
+	/// Keys of the deleted nodes.
+	pub deleted: Vec<Vec<u8>>,
+}
+
+/// Returns the node changes recorded by a state database meta entry, or `None` if the entry
+/// does not contain any node keys and may be copied as is.
+pub fn journal_changes<BlockHash: Hash>(
+	key: &[u8],
+	value: &[u8],
+) -> Result<Option<JournalChanges<BlockHash>>, codec::Error> {
+	if let Some(changes) = pruning::journal_changes(key, value)? {
+		return Ok(Some(changes))
+	}
+	noncanonical::journal_changes(key, value)
+}
+
+/// Encode `changes` as the value of the journal entry stored under `key`. Returns `None` if
+/// `key` is not a journal key.
+pub fn replace_journal_changes<BlockHash: Hash>(
+	key: &[u8],
+	changes: JournalChanges<BlockHash>,
+) -> Option<DBValue> {
+	if changes.parent_hash.is_some() {
+		noncanonical::is_journal_key(key).then(|| noncanonical::encode_journal_changes(changes))
+	} else {
+		pruning::is_journal_key(key).then(|| pruning::encode_journal_changes(changes))
+	}
+}
+
+/// Rewrite the node keys stored in a state database meta entry with `f`.
+///
+/// Used when moving the state to a backing database with a different node key format. Returns
+/// `None` if the entry does not contain any node keys and may be copied as is.
+pub fn map_journal_keys<BlockHash: Hash>(
+	key: &[u8],
+	value: &[u8],
+	f: impl Fn(&[u8]) -> Vec<u8>,
+) -> Result<Option<DBValue>, codec::Error> {
+	Ok(journal_changes::<BlockHash>(key, value)?.and_then(|changes| {
+		let changes = JournalChanges {
+			inserted: changes.inserted.into_iter().map(|(k, v)| (f(&k), v)).collect(),
+			deleted: changes.deleted.iter().map(|k| f(k)).collect(),
+			..changes
+		};
+		replace_journal_changes(key, changes)
+	}))
+}
+
+/// List the records of the non-canonical overlay journal stored in `db`, along with the reason
+/// for discarding those that are not restored when the overlay is rebuilt.
+pub fn inspect_non_canonical_journal<BlockHash: Hash, Key: Hash, D: MetaDb>(
+	db: &D,
+) -> Result<Vec<JournalEntry<BlockHash>>, Error<D::Error>> {
+	noncanonical::inspect_journal::<BlockHash, Key, D>(db)
+}
+
 fn to_meta_key<S: Codec>(suffix: &[u8], data: &S) -> Vec<u8> {
 	let mut buffer = data.encode();
 	buffer.extend(suffix);
@@ -295,6 +455,11 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 		let pruning: Option<RefWindow<BlockHash, Key>> = match mode {
 			PruningMode::Constrained(Constraints { max_mem: Some(_), .. }) => unimplemented!(),
 			PruningMode::Constrained(_) => Some(RefWindow::new(db, ref_counting)?),
+			PruningMode::Hybrid { constraints: Constraints { max_mem: Some(_), .. }, .. } =>
+				return Err(StateDbError::UnsupportedPruningMode(mode).into()),
+			PruningMode::Hybrid { .. } if ref_counting =>
+				return Err(StateDbError::CheckpointsUnsupportedWithRefCounting.into()),
+			PruningMode::Hybrid { .. } => Some(RefWindow::with_checkpoints(db, ref_counting)?),
 			PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => None,
 		};
 
@@ -314,7 +479,9 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 				// write changes immediately
 				Ok(CommitSet { data: changeset, meta: Default::default() })
 			},
-			PruningMode::Constrained(_) | PruningMode::ArchiveCanonical => self
+			PruningMode::Constrained(_) |
+			PruningMode::Hybrid { .. } |
+			PruningMode::ArchiveCanonical => self
 				.non_canonical
 				.insert(hash, number, parent_hash, changeset)
 				.map_err(Into::into),
@@ -347,24 +514,82 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 		self.non_canonical.last_canonicalized_block_number()
 	}
 
-	fn is_pruned(&self, hash: &BlockHash, number: u64) -> bool {
+	/// Start pruning a database that was kept in the `stored` archive mode.
+	fn switch_from_archive<E: fmt::Debug>(
+		&mut self,
+		stored: &PruningMode,
+		ref_counting: bool,
+		last_canonical: (BlockHash, u64),
+		non_canonical: Vec<(BlockHash, u64, BlockHash)>,
+		commit: &mut CommitSet<Key>,
+	) -> Result<(), Error<E>> {
+		if *stored == PruningMode::ArchiveAll &&
+			self.non_canonical.last_canonicalized_hash().is_none()
+		{
+			let (hash, number) = last_canonical;
+			self.non_canonical.set_last_canonicalized(&hash, number, commit);
+			for (hash, number, parent_hash) in non_canonical {
+				let insert =
+					self.non_canonical.insert(&hash, number, &parent_hash, Default::default())?;
+				commit.meta.inserted.extend(insert.meta.inserted);
+			}
+		}
+		if let (Some(pruning), Some(hash), Some(number)) = (
+			&mut self.pruning,
+			self.non_canonical.last_canonicalized_hash(),
+			self.non_canonical.last_canonicalized_block_number(),
+		) {
+			pruning.start_from_archive(&hash, number, ref_counting, commit);
+		}
+		Ok(())
+	}
+
+	fn archive_gc_next(&self) -> Option<(u64, u64)> {
+		let pruning = self.pruning.as_ref()?;
+		pruning.archive_gc_next().map(|number| (number, pruning.pending()))
+	}
+
+	fn collect_archive_garbage(&mut self, number: u64, garbage: Vec<Key>) -> CommitSet<Key> {
+		let mut commit = CommitSet::default();
+		if let Some(pruning) = &mut self.pruning {
+			pruning.collect_archive_garbage(number, garbage, &mut commit);
+		}
+		commit
+	}
+
+	fn is_pruned(&self, hash: &BlockHash, number: u64) -> IsPruned {
 		match self.mode {
-			PruningMode::ArchiveAll => false,
-			PruningMode::ArchiveCanonical | PruningMode::Constrained(_) => {
+			PruningMode::ArchiveAll => IsPruned::NotPruned,
+			PruningMode::ArchiveCanonical |
+			PruningMode::Constrained(_) |
+			PruningMode::Hybrid { .. } => {
 				if self.best_canonical().map(|c| number > c).unwrap_or(true) {
-					!self.non_canonical.have_block(hash)
+					if self.non_canonical.have_block(hash) {
+						IsPruned::NotPruned
+					} else {
+						IsPruned::Pruned
+					}
 				} else {
-					self.pruning.as_ref().map_or(false, |pruning| {
-						number < pruning.pending() || !pruning.have_block(hash)
-					})
+					match self.pruning.as_ref() {
+						None => IsPruned::NotPruned,
+						Some(pruning)
+							if number >= pruning.pending() && pruning.have_block(hash) =>
+							IsPruned::NotPruned,
+						// Only canonical checkpoints are retained, which can't be told by number.
+						Some(pruning)
+							if number < pruning.pending() && self.mode.is_checkpoint(number) =>
+							IsPruned::MaybePruned,
+						Some(_) => IsPruned::Pruned,
+					}
 				}
 			},
 		}
 	}
 
 	fn prune(&mut self, commit: &mut CommitSet<Key>) {
-		if let (&mut Some(ref mut pruning), &PruningMode::Constrained(ref constraints)) =
-			(&mut self.pruning, &self.mode)
+		let best_canonical = self.best_canonical();
+		if let (&mut Some(ref mut pruning), Some(constraints)) =
+			(&mut self.pruning, self.mode.constraints())
 		{
 			loop {
 				if pruning.window_size() <= constraints.max_blocks.unwrap_or(0) as u64 {
@@ -379,7 +604,13 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 				if pruning.next_hash().map_or(false, |h| pinned.contains_key(&h)) {
 					break
 				}
-				pruning.prune_one(commit);
+				// The last block in the window is the last canonicalized one.
+				let number = best_canonical.map(|c| c + 1 - pruning.window_size());
+				if number.map_or(false, |n| self.mode.is_checkpoint(n)) {
+					pruning.prune_checkpoint(commit);
+				} else {
+					pruning.prune_one(commit);
+				}
 			}
 		}
 	}
@@ -390,23 +621,36 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDbSync<Block
 	fn revert_one(&mut self) -> Option<CommitSet<Key>> {
 		match self.mode {
 			PruningMode::ArchiveAll => Some(CommitSet::default()),
-			PruningMode::ArchiveCanonical | PruningMode::Constrained(_) =>
-				self.non_canonical.revert_one(),
+			PruningMode::ArchiveCanonical |
+			PruningMode::Constrained(_) |
+			PruningMode::Hybrid { .. } => self.non_canonical.revert_one(),
 		}
 	}
 
 	fn remove(&mut self, hash: &BlockHash) -> Option<CommitSet<Key>> {
 		match self.mode {
 			PruningMode::ArchiveAll => Some(CommitSet::default()),
-			PruningMode::ArchiveCanonical | PruningMode::Constrained(_) =>
-				self.non_canonical.remove(hash),
+			PruningMode::ArchiveCanonical |
+			PruningMode::Constrained(_) |
+			PruningMode::Hybrid { .. } => self.non_canonical.remove(hash),
 		}
 	}
 
-	fn pin(&mut self, hash: &BlockHash) -> Result<(), PinError> {
+	fn pin(&mut self, hash: &BlockHash, number: u64) -> Result<(), PinError> {
 		match self.mode {
 			PruningMode::ArchiveAll => Ok(()),
-			PruningMode::ArchiveCanonical | PruningMode::Constrained(_) => {
+			PruningMode::Hybrid { .. }
+				if self.mode.is_checkpoint(number) &&
+					self.pruning
+						.as_ref()
+						.map_or(false, |pruning| number < pruning.pending()) =>
+			{
+				// Retained checkpoints are never pruned.
+				Ok(())
+			},
+			PruningMode::ArchiveCanonical |
+			PruningMode::Constrained(_) |
+			PruningMode::Hybrid { .. } => {
 				if self.non_canonical.have_block(hash) ||
 					self.pruning.as_ref().map_or(false, |pruning| pruning.have_block(hash))
 				{
@@ -506,8 +750,14 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 		D: MetaDb,
 	{
 		let stored_mode = fetch_stored_pruning_mode(db)?;
-
-		let selected_mode = match (should_init, stored_mode, requested_mode) {
+		// Databases created before the pruning window was stored don't tell which window they
+		// were pruned with. The requested window is taken as is and recorded.
+		let unknown_window =
+			stored_mode.as_ref().map_or(false, |mode| mode.constraints().is_some()) &&
+				db.get_meta(&to_meta_key(PRUNING_MAX_BLOCKS, &())).map_err(Error::Db)?.is_none();
+		let record_window = unknown_window && requested_mode.is_some();
+
+		let selected_mode = match (should_init, stored_mode.clone(), requested_mode) {
 			(true, stored_mode, requested_mode) => {
 				assert!(stored_mode.is_none(), "The storage has just been initialized. No meta-data is expected to be found in it.");
 				requested_mode.unwrap_or_default()
@@ -521,16 +771,20 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 
 			(false, Some(stored), None) => stored,
 
-			(false, Some(stored), Some(requested)) => choose_pruning_mode(stored, requested)?,
+			(false, Some(mut stored), Some(requested)) => {
+				if let (true, Some(constraints)) = (unknown_window, stored.constraints_mut()) {
+					constraints.max_blocks = None;
+				}
+				choose_pruning_mode(stored, requested)?
+			},
 		};
 
-		let db_init_commit_set = if should_init {
+		// Record the mode of a new database, or the widened pruning window of an existing one.
+		let mode_changed = record_window || stored_mode.as_ref() != Some(&selected_mode);
+		let db_init_commit_set = if should_init || mode_changed {
 			let mut cs: CommitSet<Key> = Default::default();
 
-			let key = to_meta_key(PRUNING_MODE, &());
-			let value = selected_mode.id().to_owned();
-
-			cs.meta.inserted.push((key, value));
+			cs.meta.inserted.extend(pruning_mode_meta(&selected_mode));
 
 			cs
 		} else {
@@ -543,10 +797,65 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 		Ok((db_init_commit_set, state_db))
 	}
 
+	/// Open a database that was kept in an archive pruning mode with `requested_mode`, without
+	/// resyncing.
+	///
+	/// See the module documentation. An `ArchiveAll` database does not track canonicalization, so
+	/// the caller supplies its last finalized block as `last_canonical` and the blocks imported on
+	/// top of it as `(hash, number, parent_hash)` in `non_canonical`, ordered by number. Both are
+	/// ignored for other archive modes.
+	pub fn open_from_archive<D: MetaDb>(
+		db: &D,
+		requested_mode: PruningMode,
+		ref_counting: bool,
+		last_canonical: (BlockHash, u64),
+		non_canonical: Vec<(BlockHash, u64, BlockHash)>,
+	) -> Result<(CommitSet<Key>, StateDb<BlockHash, Key>), Error<D::Error>> {
+		let stored_mode = match fetch_stored_pruning_mode(db)? {
+			Some(stored) if stored.is_archive() => stored,
+			Some(stored) =>
+				return Err(StateDbError::IncompatiblePruningModes {
+					stored,
+					requested: requested_mode,
+				}
+				.into()),
+			None =>
+				return Err(StateDbError::Metadata(
+					"An existing StateDb does not have PRUNING_MODE stored in its meta-data".into(),
+				)
+				.into()),
+		};
+		if requested_mode.is_archive() {
+			return Self::open(db, Some(requested_mode), ref_counting, false)
+		}
+
+		let mut commit = CommitSet::default();
+		commit.meta.inserted.extend(pruning_mode_meta(&requested_mode));
+		let mut state_db = StateDbSync::new(requested_mode, ref_counting, db)?;
+		state_db.switch_from_archive(
+			&stored_mode,
+			ref_counting,
+			last_canonical,
+			non_canonical,
+			&mut commit,
+		)?;
+		state_db.apply_pending();
+
+		Ok((commit, StateDb { db: RwLock::new(state_db) }))
+	}
+
 	pub fn pruning_mode(&self) -> PruningMode {
 		self.db.read().mode.clone()
 	}
 
+	/// Reload the non-canonical overlay and the pruning window from the database, discarding the
+	/// in-memory state. Used to follow a database that is written to by another process.
+	pub fn reload<D: MetaDb>(&self, db: &D, ref_counting: bool) -> Result<(), Error<D::Error>> {
+		let mut state_db = self.db.write();
+		*state_db = StateDbSync::new(state_db.mode.clone(), ref_counting, db)?;
+		Ok(())
+	}
+
 	/// Add a new non-canonical block.
 	pub fn insert_block<E: fmt::Debug>(
 		&self,
@@ -567,8 +876,8 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 	}
 
 	/// Prevents pruning of specified block and its descendants.
-	pub fn pin(&self, hash: &BlockHash) -> Result<(), PinError> {
-		self.db.write().pin(hash)
+	pub fn pin(&self, hash: &BlockHash, number: u64) -> Result<(), PinError> {
+		self.db.write().pin(hash, number)
 	}
 
 	/// Allows pruning of specified block.
@@ -608,8 +917,22 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 		return self.db.read().best_canonical()
 	}
 
+	/// Returns the number of the next block canonicalized in an archive mode to collect the garbage
+	/// of, along with the number of the first block in the pruning window. The garbage of block `n`
+	/// are the nodes of the state of block `n - 1` that are neither part of the state of block `n`
+	/// nor of the first block in the window.
+	pub fn archive_gc_next(&self) -> Option<(u64, u64)> {
+		self.db.read().archive_gc_next()
+	}
+
+	/// Delete the `garbage` nodes of archived block `number`, returned by `archive_gc_next`. Nodes
+	/// inserted by the blocks in the pruning window are kept.
+	pub fn collect_archive_garbage(&self, number: u64, garbage: Vec<Key>) -> CommitSet<Key> {
+		self.db.write().collect_archive_garbage(number, garbage)
+	}
+
 	/// Check if block is pruned away.
-	pub fn is_pruned(&self, hash: &BlockHash, number: u64) -> bool {
+	pub fn is_pruned(&self, hash: &BlockHash, number: u64) -> IsPruned {
 		return self.db.read().is_pruned(hash, number)
 	}
 
@@ -629,10 +952,32 @@ impl<BlockHash: Hash + MallocSizeOf, Key: Hash + MallocSizeOf> StateDb<BlockHash
 	}
 }
 
-fn fetch_stored_pruning_mode<D: MetaDb>(db: &D) -> Result<Option<PruningMode>, Error<D::Error>> {
+/// Returns the pruning mode stored in the database, if any.
+pub fn fetch_stored_pruning_mode<D: MetaDb>(
+	db: &D,
+) -> Result<Option<PruningMode>, Error<D::Error>> {
 	let meta_key_mode = to_meta_key(PRUNING_MODE, &());
 	if let Some(stored_mode) = db.get_meta(&meta_key_mode).map_err(Error::Db)? {
-		if let Some(mode) = PruningMode::from_id(&stored_mode) {
+		if let Some(mut mode) = PruningMode::from_id(&stored_mode) {
+			// Databases created before the window was stored report the default one, see
+			// `StateDb::open`.
+			let key = to_meta_key(PRUNING_MAX_BLOCKS, &());
+			if let (Some(constraints), Some(max_blocks)) =
+				(mode.constraints_mut(), db.get_meta(&key).map_err(Error::Db)?)
+			{
+				constraints.max_blocks = Option::<u32>::decode(&mut max_blocks.as_slice())
+					.map_err(|e| Error::from(StateDbError::Decoding(e)))?;
+			}
+			if let PruningMode::Hybrid { checkpoint_interval, .. } = &mut mode {
+				let key = to_meta_key(PRUNING_CHECKPOINT_INTERVAL, &());
+				let stored_interval = db.get_meta(&key).map_err(Error::Db)?.ok_or_else(|| {
+					StateDbError::Metadata(
+						"A hybrid StateDb does not have its checkpoint interval stored".into(),
+					)
+				})?;
+				*checkpoint_interval = u32::decode(&mut stored_interval.as_slice())
+					.map_err(|e| Error::from(StateDbError::Decoding(e)))?;
+			}
 			Ok(Some(mode))
 		} else {
 			Err(StateDbError::Metadata(format!(
@@ -646,6 +991,18 @@ fn fetch_stored_pruning_mode<D: MetaDb>(db: &D) -> Result<Option<PruningMode>, E
 	}
 }
 
+/// The meta entries recording `mode`.
+fn pruning_mode_meta(mode: &PruningMode) -> Vec<(Vec<u8>, Vec<u8>)> {
+	let mut meta = vec![(to_meta_key(PRUNING_MODE, &()), mode.id().to_owned())];
+	if let Some(constraints) = mode.constraints() {
+		meta.push((to_meta_key(PRUNING_MAX_BLOCKS, &()), constraints.max_blocks.encode()));
+	}
+	if let PruningMode::Hybrid { checkpoint_interval, .. } = mode {
+		meta.push((to_meta_key(PRUNING_CHECKPOINT_INTERVAL, &()), checkpoint_interval.encode()));
+	}
+	meta
+}
+
 fn choose_pruning_mode(
 	stored: PruningMode,
 	requested: PruningMode,
@@ -654,8 +1011,17 @@ fn choose_pruning_mode(
 		(PruningMode::ArchiveAll, PruningMode::ArchiveAll) => Ok(PruningMode::ArchiveAll),
 		(PruningMode::ArchiveCanonical, PruningMode::ArchiveCanonical) =>
 			Ok(PruningMode::ArchiveCanonical),
-		(PruningMode::Constrained(_), PruningMode::Constrained(requested)) =>
+		// A pruning window can only be widened.
+		(PruningMode::Constrained(stored), PruningMode::Constrained(requested))
+			if requested.max_blocks.unwrap_or(0) >= stored.max_blocks.unwrap_or(0) =>
 			Ok(PruningMode::Constrained(requested)),
+		// The states already pruned can't be brought back as checkpoints of another interval.
+		(
+			PruningMode::Hybrid { constraints: stored, checkpoint_interval: stored_interval },
+			PruningMode::Hybrid { constraints, checkpoint_interval },
+		) if checkpoint_interval == stored_interval &&
+			constraints.max_blocks.unwrap_or(0) >= stored.max_blocks.unwrap_or(0) =>
+			Ok(PruningMode::Hybrid { constraints, checkpoint_interval }),
 		(stored, requested) => Err(StateDbError::IncompatiblePruningModes { requested, stored }),
 	}
 }
@@ -664,7 +1030,7 @@ fn choose_pruning_mode(
 mod tests {
 	use crate::{
 		test::{make_changeset, make_db, TestDb},
-		Constraints, Error, PruningMode, StateDb, StateDbError,
+		Constraints, Error, IsPruned, PruningMode, StateDb, StateDbError,
 	};
 	use sp_core::H256;
 	use std::io;
@@ -737,11 +1103,32 @@ mod tests {
 		(db, state_db)
 	}
 
+	#[test]
+	fn reload_follows_database() {
+		let (mut db, sdb) = make_test_db(PruningMode::blocks_pruning(1));
+		let (_, follower) = StateDb::<H256, H256>::open(&db, None, false, false).unwrap();
+		db.commit(
+			&sdb.insert_block::<io::Error>(
+				&H256::from_low_u64_be(5),
+				5,
+				&H256::from_low_u64_be(4),
+				make_changeset(&[5], &[]),
+			)
+			.unwrap(),
+		);
+		sdb.apply_pending();
+
+		let hash = H256::from_low_u64_be(5);
+		assert!(follower.pin(&hash, 5).is_err());
+		follower.reload(&db, false).unwrap();
+		assert!(follower.pin(&hash, 5).is_ok());
+	}
+
 	#[test]
 	fn full_archive_keeps_everything() {
 		let (db, sdb) = make_test_db(PruningMode::ArchiveAll);
 		assert!(db.data_eq(&make_db(&[1, 21, 22, 3, 4, 91, 921, 922, 93, 94])));
-		assert!(!sdb.is_pruned(&H256::from_low_u64_be(0), 0));
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(0), 0), IsPruned::NotPruned);
 	}
 
 	#[test]
@@ -765,10 +1152,10 @@ mod tests {
 			max_blocks: Some(1),
 			max_mem: None,
 		}));
-		assert!(sdb.is_pruned(&H256::from_low_u64_be(0), 0));
-		assert!(sdb.is_pruned(&H256::from_low_u64_be(1), 1));
-		assert!(sdb.is_pruned(&H256::from_low_u64_be(21), 2));
-		assert!(sdb.is_pruned(&H256::from_low_u64_be(22), 2));
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(0), 0), IsPruned::Pruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(1), 1), IsPruned::Pruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(21), 2), IsPruned::Pruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(22), 2), IsPruned::Pruned);
 		assert!(db.data_eq(&make_db(&[21, 3, 922, 93, 94])));
 	}
 
@@ -778,13 +1165,183 @@ mod tests {
 			max_blocks: Some(2),
 			max_mem: None,
 		}));
-		assert!(sdb.is_pruned(&H256::from_low_u64_be(0), 0));
-		assert!(sdb.is_pruned(&H256::from_low_u64_be(1), 1));
-		assert!(!sdb.is_pruned(&H256::from_low_u64_be(21), 2));
-		assert!(sdb.is_pruned(&H256::from_low_u64_be(22), 2));
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(0), 0), IsPruned::Pruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(1), 1), IsPruned::Pruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(21), 2), IsPruned::NotPruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(22), 2), IsPruned::Pruned);
 		assert!(db.data_eq(&make_db(&[1, 21, 3, 921, 922, 93, 94])));
 	}
 
+	#[test]
+	fn hybrid_keeps_checkpoints() {
+		let (db, sdb) = make_test_db(PruningMode::hybrid(0, 2));
+		assert!(db.data_eq(&make_db(&[21, 3, 91, 921, 922, 93, 94])));
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(1), 1), IsPruned::Pruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(21), 2), IsPruned::MaybePruned);
+		assert_eq!(sdb.is_pruned(&H256::from_low_u64_be(3), 3), IsPruned::Pruned);
+		assert!(sdb.pin(&H256::from_low_u64_be(21), 2).is_ok());
+		assert!(sdb.pin(&H256::from_low_u64_be(3), 3).is_err());
+	}
+
+	#[test]
+	fn hybrid_rejects_ref_counting() {
+		let mut db = make_db(&[]);
+		let state_db_open_result: Result<(_, StateDb<H256, H256>), _> =
+			StateDb::open(&mut db, Some(PruningMode::hybrid(256, 10)), true, true);
+		assert!(matches!(
+			state_db_open_result,
+			Err(Error::StateDb(StateDbError::CheckpointsUnsupportedWithRefCounting))
+		));
+	}
+
+	#[test]
+	fn hybrid_rejects_memory_constraints() {
+		let mut db = make_db(&[]);
+		let mode = PruningMode::Hybrid {
+			constraints: Constraints { max_blocks: Some(256), max_mem: Some(1024) },
+			checkpoint_interval: 10,
+		};
+		let state_db_open_result: Result<(_, StateDb<H256, H256>), _> =
+			StateDb::open(&mut db, Some(mode), false, true);
+		assert!(matches!(
+			state_db_open_result,
+			Err(Error::StateDb(StateDbError::UnsupportedPruningMode(_)))
+		));
+	}
+
+	#[test]
+	fn best_canonical_counts_from_genesis() {
+		for (mode, expected) in [
+			(PruningMode::blocks_pruning(256), Some(1)),
+			(PruningMode::hybrid(256, 10), Some(1)),
+			(PruningMode::ArchiveCanonical, Some(1)),
+			(PruningMode::ArchiveAll, None),
+		] {
+			let mut db = make_db(&[]);
+			let (state_db_init, state_db) =
+				StateDb::<H256, H256>::open(&mut db, Some(mode), false, true).unwrap();
+			db.commit(&state_db_init);
+			for (number, parent) in [(0, H256::default()), (1, H256::from_low_u64_be(0))] {
+				db.commit(
+					&state_db
+						.insert_block::<io::Error>(
+							&H256::from_low_u64_be(number),
+							number,
+							&parent,
+							make_changeset(&[], &[]),
+						)
+						.unwrap(),
+				);
+			}
+			state_db.apply_pending();
+
+			// Both canonicalizations are pending, the genesis block being the first one.
+			for number in [0, 1] {
+				db.commit(
+					&state_db
+						.canonicalize_block::<io::Error>(&H256::from_low_u64_be(number))
+						.unwrap(),
+				);
+			}
+			assert_eq!(state_db.best_canonical(), expected);
+			state_db.apply_pending();
+			assert_eq!(state_db.best_canonical(), expected);
+		}
+	}
+
+	#[test]
+	fn hybrid_checkpoint_interval_must_not_change() {
+		let mut db = make_db(&[]);
+		let (state_db_init, _) =
+			StateDb::<H256, H256>::open(&mut db, Some(PruningMode::hybrid(256, 10)), false, true)
+				.unwrap();
+		db.commit(&state_db_init);
+
+		let (_, state_db) = StateDb::<H256, H256>::open(&mut db, None, false, false).unwrap();
+		assert_eq!(state_db.pruning_mode(), PruningMode::hybrid(256, 10));
+		let (_, state_db) =
+			StateDb::<H256, H256>::open(&mut db, Some(PruningMode::hybrid(512, 10)), false, false)
+				.unwrap();
+		assert_eq!(state_db.pruning_mode(), PruningMode::hybrid(512, 10));
+		assert!(matches!(
+			StateDb::<H256, H256>::open(&mut db, Some(PruningMode::hybrid(256, 20)), false, false),
+			Err(Error::StateDb(StateDbError::IncompatiblePruningModes { .. }))
+		));
+	}
+
+	fn switch_from_archive(stored: PruningMode) -> (TestDb, StateDb<H256, H256>) {
+		let (mut db, _) = make_test_db(stored);
+		let (commit, state_db) = StateDb::open_from_archive(
+			&db,
+			PruningMode::blocks_pruning(1),
+			true,
+			(H256::from_low_u64_be(3), 3),
+			vec![(H256::from_low_u64_be(4), 4, H256::from_low_u64_be(3))],
+		)
+		.unwrap();
+		db.commit(&commit);
+		(db, state_db)
+	}
+
+	#[test]
+	fn switches_from_archive() {
+		for stored in [PruningMode::ArchiveAll, PruningMode::ArchiveCanonical] {
+			let (mut db, state_db) = switch_from_archive(stored);
+			assert_eq!(state_db.is_pruned(&H256::from_low_u64_be(21), 2), IsPruned::Pruned);
+			assert_eq!(state_db.is_pruned(&H256::from_low_u64_be(3), 3), IsPruned::NotPruned);
+			assert_eq!(state_db.is_pruned(&H256::from_low_u64_be(4), 4), IsPruned::NotPruned);
+
+			db.commit(
+				&state_db.canonicalize_block::<io::Error>(&H256::from_low_u64_be(4)).unwrap(),
+			);
+			state_db.apply_pending();
+			assert_eq!(state_db.is_pruned(&H256::from_low_u64_be(3), 3), IsPruned::Pruned);
+			assert_eq!(state_db.is_pruned(&H256::from_low_u64_be(4), 4), IsPruned::NotPruned);
+
+			let (_, state_db) = StateDb::<H256, H256>::open(&db, None, true, false).unwrap();
+			assert_eq!(state_db.pruning_mode(), PruningMode::blocks_pruning(1));
+			assert_eq!(state_db.is_pruned(&H256::from_low_u64_be(4), 4), IsPruned::NotPruned);
+		}
+	}
+
+	#[test]
+	fn collects_archive_garbage() {
+		let (mut db, state_db) = switch_from_archive(PruningMode::ArchiveCanonical);
+		assert_eq!(state_db.archive_gc_next(), Some((1, 3)));
+		db.commit(&state_db.canonicalize_block::<io::Error>(&H256::from_low_u64_be(4)).unwrap());
+		state_db.apply_pending();
+		assert_eq!(state_db.archive_gc_next(), Some((1, 4)));
+
+		// 4 is inserted by a block in the window and kept.
+		db.commit(
+			&state_db.collect_archive_garbage(
+				1,
+				vec![H256::from_low_u64_be(91), H256::from_low_u64_be(4)],
+			),
+		);
+		state_db.apply_pending();
+		assert!(db.data_eq(&make_db(&[1, 21, 3, 4, 921, 922, 93, 94])));
+
+		let (_, state_db) =
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(1)), true, false)
+				.unwrap();
+		assert_eq!(state_db.archive_gc_next(), Some((2, 4)));
+		db.commit(&state_db.collect_archive_garbage(2, vec![H256::from_low_u64_be(921)]));
+		state_db.revert_pending();
+		assert_eq!(state_db.archive_gc_next(), Some((2, 4)));
+		db.commit(&state_db.collect_archive_garbage(2, vec![H256::from_low_u64_be(921)]));
+		state_db.apply_pending();
+		db.commit(&state_db.collect_archive_garbage(3, vec![H256::from_low_u64_be(93)]));
+		state_db.apply_pending();
+		assert_eq!(state_db.archive_gc_next(), None);
+		assert!(db.data_eq(&make_db(&[1, 21, 3, 4, 922, 94])));
+
+		let (_, state_db) =
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(1)), true, false)
+				.unwrap();
+		assert_eq!(state_db.archive_gc_next(), None);
+	}
+
 	#[test]
 	fn detects_incompatible_mode() {
 		let mut db = make_db(&[]);
@@ -837,7 +1394,7 @@ mod tests {
 		for (created, reopened, expected) in [
 			(None, None, Ok(PruningMode::blocks_pruning(256))),
 			(None, Some(PruningMode::blocks_pruning(256)), Ok(PruningMode::blocks_pruning(256))),
-			(None, Some(PruningMode::blocks_pruning(128)), Ok(PruningMode::blocks_pruning(128))),
+			(None, Some(PruningMode::blocks_pruning(128)), Err(())),
 			(None, Some(PruningMode::blocks_pruning(512)), Ok(PruningMode::blocks_pruning(512))),
 			(None, Some(PruningMode::ArchiveAll), Err(())),
 			(None, Some(PruningMode::ArchiveCanonical), Err(())),
@@ -850,7 +1407,7 @@ mod tests {
 			(
 				Some(PruningMode::blocks_pruning(256)),
 				Some(PruningMode::blocks_pruning(128)),
-				Ok(PruningMode::blocks_pruning(128)),
+				Err(()),
 			),
 			(
 				Some(PruningMode::blocks_pruning(256)),
@@ -883,4 +1440,49 @@ mod tests {
 			check_stored_and_requested_mode_compatibility(created, reopened, expected);
 		}
 	}
+
+	#[test]
+	fn pruning_window_is_stored() {
+		let mut db = make_db(&[]);
+		let (commit, _) =
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(128)), false, true)
+				.unwrap();
+		db.commit(&commit);
+		let (commit, _) =
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(512)), false, false)
+				.unwrap();
+		db.commit(&commit);
+
+		let (_, state_db) = StateDb::<H256, H256>::open(&db, None, false, false).unwrap();
+		assert_eq!(state_db.pruning_mode(), PruningMode::blocks_pruning(512));
+		assert!(matches!(
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(256)), false, false),
+			Err(Error::StateDb(StateDbError::IncompatiblePruningModes { .. }))
+		));
+	}
+
+	#[test]
+	fn unknown_pruning_window_is_recorded_on_open() {
+		let mut db = make_db(&[]);
+		let (commit, _) =
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(1000)), false, true)
+				.unwrap();
+		db.commit(&commit);
+		// As written before the window was stored.
+		db.meta.remove(&crate::to_meta_key(crate::PRUNING_MAX_BLOCKS, &()));
+
+		let (commit, state_db) = StateDb::<H256, H256>::open(&db, None, false, false).unwrap();
+		assert!(commit.meta.inserted.is_empty());
+		assert_eq!(state_db.pruning_mode(), PruningMode::blocks_pruning(256));
+
+		let (commit, state_db) =
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(500)), false, false)
+				.unwrap();
+		db.commit(&commit);
+		assert_eq!(state_db.pruning_mode(), PruningMode::blocks_pruning(500));
+		assert!(matches!(
+			StateDb::<H256, H256>::open(&db, Some(PruningMode::blocks_pruning(256)), false, false),
+			Err(Error::StateDb(StateDbError::IncompatiblePruningModes { .. }))
+		));
+	}
 }
@@ -21,15 +21,32 @@
 //! The overlays are added in `insert` and removed in `canonicalize`.
 //! All pending changes are kept in memory until next call to `apply_pending` or
 //! `revert_pending`
-
-use super::{to_meta_key, ChangeSet, CommitSet, DBValue, Error, Hash, MetaDb, StateDbError};
-use codec::{Decode, Encode};
-use log::trace;
-use std::collections::{hash_map::Entry, HashMap, VecDeque};
+//!
+//! Each journal record starts with the format version and a checksum of the record. When the
+//! overlay is rebuilt, records that fail the checksum or can't be decoded, such as the ones left
+//! by an interrupted write, are discarded along with their descendants. The discarded records are
+//! removed from the database with the next inserted block.
+
+use super::{
+	to_meta_key, ChangeSet, CommitSet, DBValue, Error, Hash, JournalChanges, MetaDb, StateDbError,
+};
+use codec::{Decode, DecodeAll, Encode};
+use log::{trace, warn};
+use std::{
+	collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+	fmt,
+};
 
 const NON_CANONICAL_JOURNAL: &[u8] = b"noncanonical_journal";
 const LAST_CANONICAL: &[u8] = b"last_canonical";
 const MAX_BLOCKS_PER_LEVEL: u64 = 32;
+/// Start of a versioned journal record. Records written before the journal was versioned are
+/// plain encoded `JournalRecord`s.
+const JOURNAL_MAGIC: &[u8] = b"ncj";
+/// Version of the journal record format.
+const JOURNAL_VERSION: u8 = 1;
+/// Length of the checksum following the version of a record.
+const CHECKSUM_LEN: usize = 8;
 
 /// See module documentation.
 #[derive(parity_util_mem_derive::MallocSizeOf)]
@@ -43,6 +60,8 @@ pub struct NonCanonicalOverlay<BlockHash: Hash, Key: Hash> {
 	// would be deleted but kept around because block is pinned, ref counted.
 	pinned: HashMap<BlockHash, u32>,
 	pinned_insertions: HashMap<BlockHash, (Vec<Key>, u32)>,
+	// journal records discarded on startup, removed from the DB with the next insertion.
+	discarded_journals: Vec<Vec<u8>>,
 }
 
 #[derive(parity_util_mem_derive::MallocSizeOf)]
@@ -80,10 +99,210 @@ struct JournalRecord<BlockHash: Hash, Key: Hash> {
 	deleted: Vec<Key>,
 }
 
+/// Reason for discarding a journal record when the overlay is rebuilt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalEntryError {
+	/// The record was written in a newer format. Such records are never discarded.
+	UnsupportedVersion(u8),
+	/// The record does not match its checksum, as left by an interrupted write.
+	ChecksumMismatch,
+	/// The record can not be decoded.
+	Decoding(String),
+	/// The parent of the block is neither the last canonicalized block nor in the journal.
+	UnknownParent,
+}
+
+impl fmt::Display for JournalEntryError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::UnsupportedVersion(version) => write!(f, "Unsupported version {}", version),
+			Self::ChecksumMismatch => write!(f, "Checksum mismatch"),
+			Self::Decoding(e) => write!(f, "Error decoding record: {}", e),
+			Self::UnknownParent => write!(f, "Unknown parent block"),
+		}
+	}
+}
+
+/// An entry of the journal, see `inspect_journal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry<BlockHash> {
+	/// Number of the block.
+	pub number: u64,
+	/// Index of the entry among the entries of the blocks with the same number.
+	pub index: u64,
+	/// Meta key of the entry.
+	pub key: Vec<u8>,
+	/// Hash of the block, if the record could be decoded.
+	pub hash: Option<BlockHash>,
+	/// Number of inserted and deleted nodes.
+	pub changes: (usize, usize),
+	/// Why the entry is discarded when the overlay is rebuilt, if it is.
+	pub error: Option<JournalEntryError>,
+}
+
 fn to_journal_key(block: u64, index: u64) -> Vec<u8> {
 	to_meta_key(NON_CANONICAL_JOURNAL, &(block, index))
 }
 
+fn encode_record<BlockHash: Hash, Key: Hash>(record: &JournalRecord<BlockHash, Key>) -> DBValue {
+	let body = record.encode();
+	let mut value = Vec::with_capacity(JOURNAL_MAGIC.len() + 1 + CHECKSUM_LEN + body.len());
+	value.extend_from_slice(JOURNAL_MAGIC);
+	value.push(JOURNAL_VERSION);
+	value.extend_from_slice(&sp_core::hashing::twox_64(&body));
+	value.extend(body);
+	value
+}
+
+fn decode_record<BlockHash: Hash, Key: Hash>(
+	value: &[u8],
+) -> Result<JournalRecord<BlockHash, Key>, JournalEntryError> {
+	let decode = |mut body: &[u8]| {
+		JournalRecord::decode_all(&mut body).map_err(|e| JournalEntryError::Decoding(e.to_string()))
+	};
+	let versioned = match value.strip_prefix(JOURNAL_MAGIC) {
+		Some([version, ..]) if *version != JOURNAL_VERSION =>
+			Err(JournalEntryError::UnsupportedVersion(*version)),
+		Some([_, rest @ ..]) if rest.len() >= CHECKSUM_LEN => {
+			let (checksum, body) = rest.split_at(CHECKSUM_LEN);
+			if checksum == sp_core::hashing::twox_64(body) {
+				decode(body)
+			} else {
+				Err(JournalEntryError::ChecksumMismatch)
+			}
+		},
+		Some(_) => Err(JournalEntryError::ChecksumMismatch),
+		None => return decode(value),
+	};
+	// A record written before the journal was versioned may start with the magic by chance.
+	versioned.or_else(|e| decode(value).map_err(|_| e))
+}
+
+/// A journal record read from the database.
+struct JournalItem<BlockHash: Hash, Key: Hash> {
+	number: u64,
+	index: u64,
+	key: Vec<u8>,
+	hash: Option<BlockHash>,
+	record: Result<JournalRecord<BlockHash, Key>, JournalEntryError>,
+}
+
+/// Read the journal records of the blocks following the last canonicalized block, in order of
+/// block number. The records of a block with a record that can't be restored are returned with
+/// an error, and so are the ones of its descendants.
+fn read_journal<BlockHash: Hash, Key: Hash, D: MetaDb>(
+	db: &D,
+	last_canonicalized: &(BlockHash, u64),
+) -> Result<Vec<JournalItem<BlockHash, Key>>, Error<D::Error>> {
+	let mut items = Vec::new();
+	let mut parents: HashSet<BlockHash> = std::iter::once(last_canonicalized.0.clone()).collect();
+	let mut block = last_canonicalized.1 + 1;
+	loop {
+		let mut found = false;
+		let mut level = HashSet::new();
+		for index in 0..MAX_BLOCKS_PER_LEVEL {
+			let key = to_journal_key(block, index);
+			if let Some(value) = db.get_meta(&key).map_err(Error::Db)? {
+				found = true;
+				let record = decode_record::<BlockHash, Key>(&value);
+				let hash = record.as_ref().ok().map(|record| record.hash.clone());
+				let record = record.and_then(|record| {
+					if parents.contains(&record.parent_hash) {
+						level.insert(record.hash.clone());
+						Ok(record)
+					} else {
+						Err(JournalEntryError::UnknownParent)
+					}
+				});
+				items.push(JournalItem { number: block, index, key, hash, record });
+			}
+		}
+		if !found {
+			break
+		}
+		parents = level;
+		block += 1;
+	}
+	Ok(items)
+}
+
+/// List the journal entries stored in `db`.
+pub fn inspect_journal<BlockHash: Hash, Key: Hash, D: MetaDb>(
+	db: &D,
+) -> Result<Vec<JournalEntry<BlockHash>>, Error<D::Error>> {
+	let last_canonicalized =
+		match db.get_meta(&to_meta_key(LAST_CANONICAL, &())).map_err(Error::Db)? {
+			Some(buffer) => <(BlockHash, u64)>::decode(&mut buffer.as_slice())?,
+			None => return Ok(Vec::new()),
+		};
+	Ok(read_journal::<BlockHash, Key, D>(db, &last_canonicalized)?
+		.into_iter()
+		.map(|item| {
+			let (changes, error) = match item.record {
+				Ok(record) => ((record.inserted.len(), record.deleted.len()), None),
+				Err(e) => ((0, 0), Some(e)),
+			};
+			JournalEntry {
+				number: item.number,
+				index: item.index,
+				key: item.key,
+				hash: item.hash,
+				changes,
+				error,
+			}
+		})
+		.collect())
+}
+
+/// Returns the node changes recorded by a non-canonical journal entry, or `None` if `key` is not a
+/// journal key or the entry is discarded when the overlay is rebuilt.
+pub fn journal_changes<BlockHash: Hash>(
+	key: &[u8],
+	value: &[u8],
+) -> Result<Option<JournalChanges<BlockHash>>, codec::Error> {
+	if !is_journal_key(key) {
+		return Ok(None)
+	}
+	let record: JournalRecord<BlockHash, Vec<u8>> = match decode_record(value) {
+		Ok(record) => record,
+		Err(JournalEntryError::UnsupportedVersion(_)) =>
+			return Err("Unsupported non-canonical journal version".into()),
+		// Discarded when the overlay is rebuilt.
+		Err(_) => return Ok(None),
+	};
+	Ok(Some(JournalChanges {
+		hash: record.hash,
+		parent_hash: Some(record.parent_hash),
+		inserted: record.inserted.into_iter().map(|(k, v)| (k, Some(v))).collect(),
+		deleted: record.deleted,
+	}))
+}
+
+/// Encode `changes` as a non-canonical journal entry.
+pub fn encode_journal_changes<BlockHash: Hash>(changes: JournalChanges<BlockHash>) -> DBValue {
+	encode_record(&JournalRecord::<BlockHash, Vec<u8>> {
+		hash: changes.hash,
+		parent_hash: changes.parent_hash.unwrap_or_default(),
+		inserted: changes.inserted.into_iter().map(|(k, v)| (k, v.unwrap_or_default())).collect(),
+		deleted: changes.deleted,
+	})
+}
+
+/// Whether `key` is the key of a non-canonical journal entry.
+pub(crate) fn is_journal_key(key: &[u8]) -> bool {
+	key.len() == 16 + NON_CANONICAL_JOURNAL.len() && key.ends_with(NON_CANONICAL_JOURNAL)
+}
+
+/// Returns the last canonicalized block stored in `db`, if any.
+pub fn fetch_last_canonical<BlockHash: Hash, D: MetaDb>(
+	db: &D,
+) -> Result<Option<(BlockHash, u64)>, Error<D::Error>> {
+	match db.get_meta(&to_meta_key(LAST_CANONICAL, &())).map_err(Error::Db)? {
+		Some(buffer) => Ok(Some(Decode::decode(&mut buffer.as_slice())?)),
+		None => Ok(None),
+	}
+}
+
 #[cfg_attr(test, derive(PartialEq, Debug))]
 #[derive(parity_util_mem_derive::MallocSizeOf)]
 struct BlockOverlay<BlockHash: Hash, Key: Hash> {
@@ -182,46 +401,60 @@ impl<BlockHash: Hash, Key: Hash> NonCanonicalOverlay<BlockHash, Key> {
 		let mut levels = VecDeque::new();
 		let mut parents = HashMap::new();
 		let mut values = HashMap::new();
-		if let Some((ref hash, mut block)) = last_canonicalized {
+		let mut discarded_journals = Vec::new();
+		if let Some(ref last_canonicalized) = last_canonicalized {
 			// read the journal
-			trace!(target: "state-db", "Reading uncanonicalized journal. Last canonicalized #{} ({:?})", block, hash);
+			trace!(
+				target: "state-db",
+				"Reading uncanonicalized journal. Last canonicalized #{} ({:?})",
+				last_canonicalized.1,
+				last_canonicalized.0,
+			);
 			let mut total: u64 = 0;
-			block += 1;
-			loop {
-				let mut level = OverlayLevel::new();
-				for index in 0..MAX_BLOCKS_PER_LEVEL {
-					let journal_key = to_journal_key(block, index);
-					if let Some(record) = db.get_meta(&journal_key).map_err(Error::Db)? {
-						let record: JournalRecord<BlockHash, Key> =
-							Decode::decode(&mut record.as_slice())?;
-						let inserted = record.inserted.iter().map(|(k, _)| k.clone()).collect();
-						let overlay = BlockOverlay {
-							hash: record.hash.clone(),
-							journal_index: index,
-							journal_key,
-							inserted,
-							deleted: record.deleted,
-						};
-						insert_values(&mut values, record.inserted);
-						trace!(
+			for item in read_journal::<BlockHash, Key, D>(db, last_canonicalized)? {
+				let record = match item.record {
+					Ok(record) => record,
+					Err(JournalEntryError::UnsupportedVersion(version)) =>
+						return Err(StateDbError::UnsupportedJournalVersion(version).into()),
+					Err(e) => {
+						warn!(
 							target: "state-db",
-							"Uncanonicalized journal entry {}.{} ({:?}) ({} inserted, {} deleted)",
-							block,
-							index,
-							record.hash,
-							overlay.inserted.len(),
-							overlay.deleted.len()
+							"Discarding uncanonicalized journal entry {}.{} ({:?}): {}",
+							item.number,
+							item.index,
+							item.hash,
+							e,
 						);
-						level.push(overlay);
-						parents.insert(record.hash, record.parent_hash);
-						total += 1;
-					}
-				}
-				if level.blocks.is_empty() {
-					break
+						discarded_journals.push(item.key);
+						continue
+					},
+				};
+				// Blocks with a restored record have their parent restored on the previous level.
+				let level_index = (item.number - last_canonicalized.1 - 1) as usize;
+				if levels.len() == level_index {
+					levels.push_back(OverlayLevel::new());
 				}
-				levels.push_back(level);
-				block += 1;
+				let inserted = record.inserted.iter().map(|(k, _)| k.clone()).collect();
+				let overlay = BlockOverlay {
+					hash: record.hash.clone(),
+					journal_index: item.index,
+					journal_key: item.key,
+					inserted,
+					deleted: record.deleted,
+				};
+				insert_values(&mut values, record.inserted);
+				trace!(
+					target: "state-db",
+					"Uncanonicalized journal entry {}.{} ({:?}) ({} inserted, {} deleted)",
+					item.number,
+					item.index,
+					record.hash,
+					overlay.inserted.len(),
+					overlay.deleted.len()
+				);
+				levels[level_index].push(overlay);
+				parents.insert(record.hash, record.parent_hash);
+				total += 1;
 			}
 			trace!(target: "state-db", "Finished reading uncanonicalized journal, {} entries", total);
 		}
@@ -234,6 +467,7 @@ impl<BlockHash: Hash, Key: Hash> NonCanonicalOverlay<BlockHash, Key> {
 			pinned: Default::default(),
 			pinned_insertions: Default::default(),
 			values,
+			discarded_journals,
 		})
 	}
 
@@ -315,7 +549,11 @@ impl<BlockHash: Hash, Key: Hash> NonCanonicalOverlay<BlockHash, Key> {
 			inserted: changeset.inserted,
 			deleted: changeset.deleted,
 		};
-		commit.meta.inserted.push((journal_key, journal_record.encode()));
+		commit
+			.meta
+			.deleted
+			.extend(self.discarded_journals.drain(..).filter(|key| *key != journal_key));
+		commit.meta.inserted.push((journal_key, encode_record(&journal_record)));
 		trace!(target: "state-db", "Inserted uncanonicalized changeset {}.{} ({} inserted, {} deleted)", number, index, journal_record.inserted.len(), journal_record.deleted.len());
 		insert_values(&mut self.values, journal_record.inserted);
 		self.pending_insertions.push(hash.clone());
@@ -358,11 +596,28 @@ impl<BlockHash: Hash, Key: Hash> NonCanonicalOverlay<BlockHash, Key> {
 		match self.last_canonicalized.as_ref().map(|&(_, n)| n) {
 			Some(n) => Some(n + self.pending_canonicalizations.len() as u64),
 			None if !self.pending_canonicalizations.is_empty() =>
-				Some(self.pending_canonicalizations.len() as u64),
+				Some(self.pending_canonicalizations.len() as u64 - 1),
 			_ => None,
 		}
 	}
 
+	/// Set the last canonicalized block of an overlay that has never been used, such as the
+	/// overlay of a database kept in the `ArchiveAll` mode. Adds changes to `commit`.
+	pub fn set_last_canonicalized(
+		&mut self,
+		hash: &BlockHash,
+		number: u64,
+		commit: &mut CommitSet<Key>,
+	) {
+		debug_assert!(self.last_canonicalized.is_none() && self.levels.is_empty());
+		let last_canonicalized = (hash.clone(), number);
+		commit
+			.meta
+			.inserted
+			.push((to_meta_key(LAST_CANONICAL, &()), last_canonicalized.encode()));
+		self.last_canonicalized = Some(last_canonicalized);
+	}
+
 	pub fn last_canonicalized_hash(&self) -> Option<BlockHash> {
 		self.last_canonicalized.as_ref().map(|&(ref h, _)| h.clone())
 	}
@@ -634,11 +889,15 @@ impl<BlockHash: Hash, Key: Hash> NonCanonicalOverlay<BlockHash, Key> {
 
 #[cfg(test)]
 mod tests {
-	use super::{to_journal_key, NonCanonicalOverlay};
+	use super::{
+		decode_record, encode_journal_changes, encode_record, inspect_journal, journal_changes,
+		to_journal_key, JournalEntryError, JournalRecord, NonCanonicalOverlay, LAST_CANONICAL,
+	};
 	use crate::{
-		test::{make_changeset, make_db},
-		ChangeSet, CommitSet, MetaDb, StateDbError,
+		test::{make_changeset, make_db, FailingDb},
+		to_meta_key, ChangeSet, CommitSet, Error, MetaDb, StateDbError,
 	};
+	use codec::Encode;
 	use sp_core::H256;
 
 	fn contains(overlay: &NonCanonicalOverlay<H256, H256>, key: u64) -> bool {
@@ -1209,4 +1468,141 @@ mod tests {
 		db.commit(&overlay.remove(&h2).unwrap());
 		assert!(!contains(&overlay, 2));
 	}
+
+	#[test]
+	fn journal_changes_roundtrip() {
+		let record = JournalRecord::<H256, Vec<u8>> {
+			hash: H256::random(),
+			parent_hash: H256::random(),
+			inserted: vec![(vec![1, 2], vec![42])],
+			deleted: vec![vec![3]],
+		};
+		let changes = journal_changes::<H256>(&to_journal_key(5, 1), &encode_record(&record))
+			.unwrap()
+			.unwrap();
+		assert_eq!(changes.hash, record.hash);
+		assert_eq!(changes.parent_hash, Some(record.parent_hash));
+		assert_eq!(changes.inserted, vec![(vec![1, 2], Some(vec![42]))]);
+		assert_eq!(changes.deleted, vec![vec![3]]);
+		let encoded = encode_journal_changes(changes);
+		assert_eq!(decode_record::<H256, Vec<u8>>(&encoded).unwrap().inserted, record.inserted);
+
+		let last_canonical = to_meta_key(LAST_CANONICAL, &());
+		assert!(journal_changes::<H256>(&last_canonical, &[]).unwrap().is_none());
+	}
+
+	#[test]
+	fn restores_records_written_before_versioning() {
+		let h1 = H256::random();
+		let h2 = H256::random();
+		let mut db = make_db(&[]);
+		db.meta.insert(to_meta_key(LAST_CANONICAL, &()), (h1, 10u64).encode());
+		let record = JournalRecord::<H256, H256> {
+			hash: h2,
+			parent_hash: h1,
+			inserted: make_changeset(&[1], &[]).inserted,
+			deleted: Vec::new(),
+		};
+		db.meta.insert(to_journal_key(11, 0), record.encode());
+
+		let overlay = NonCanonicalOverlay::<H256, H256>::new(&db).unwrap();
+		assert_eq!(overlay.levels.len(), 1);
+		assert!(contains(&overlay, 1));
+		assert!(overlay.discarded_journals.is_empty());
+	}
+
+	#[test]
+	fn refuses_newer_journal_version() {
+		let mut db = make_db(&[]);
+		db.meta
+			.insert(to_meta_key(LAST_CANONICAL, &()), (H256::random(), 10u64).encode());
+		db.meta.insert(to_journal_key(11, 0), b"ncj\x02".to_vec());
+
+		assert!(matches!(
+			NonCanonicalOverlay::<H256, H256>::new(&db),
+			Err(Error::StateDb(StateDbError::UnsupportedJournalVersion(2)))
+		));
+		let entries = inspect_journal::<H256, H256, _>(&db).unwrap();
+		assert_eq!(entries[0].error, Some(JournalEntryError::UnsupportedVersion(2)));
+	}
+
+	#[test]
+	fn discards_corrupted_journal_tail() {
+		let h1 = H256::random();
+		let h2 = H256::random();
+		let h3 = H256::random();
+		let h4 = H256::random();
+		let db = FailingDb::default();
+		let mut overlay = NonCanonicalOverlay::<H256, H256>::new(&db).unwrap();
+		db.commit_meta(
+			&overlay.insert(&h1, 10, &H256::default(), make_changeset(&[1], &[])).unwrap(),
+		)
+		.unwrap();
+		db.commit_meta(&overlay.insert(&h2, 11, &h1, make_changeset(&[2], &[])).unwrap())
+			.unwrap();
+		// The journal record of `h3` is only partially written and `h4` is left without parent.
+		db.fail_after(Some(0));
+		assert!(db
+			.commit_meta(&overlay.insert(&h3, 12, &h2, make_changeset(&[3], &[])).unwrap())
+			.is_err());
+		db.fail_after(None);
+		db.commit_meta(&overlay.insert(&h4, 13, &h3, make_changeset(&[4], &[])).unwrap())
+			.unwrap();
+
+		let entries = inspect_journal::<H256, H256, _>(&db).unwrap();
+		let errors: Vec<_> = entries.iter().map(|e| (e.number, e.error.clone())).collect();
+		assert_eq!(
+			errors,
+			vec![
+				(10, None),
+				(11, None),
+				(12, Some(JournalEntryError::ChecksumMismatch)),
+				(13, Some(JournalEntryError::UnknownParent)),
+			]
+		);
+		assert_eq!(entries[3].hash, Some(h4));
+
+		let mut overlay = NonCanonicalOverlay::<H256, H256>::new(&db).unwrap();
+		assert_eq!(overlay.levels.len(), 2);
+		assert!(contains(&overlay, 1));
+		assert!(contains(&overlay, 2));
+		assert!(!contains(&overlay, 3));
+		assert!(!contains(&overlay, 4));
+
+		// The discarded records are removed with the next insertion, reusing the free index.
+		let commit = overlay.insert(&h3, 12, &h2, make_changeset(&[3], &[])).unwrap();
+		assert_eq!(commit.meta.deleted, vec![to_journal_key(13, 0)]);
+		db.commit_meta(&commit).unwrap();
+		assert!(db.get_meta(&to_journal_key(13, 0)).unwrap().is_none());
+
+		let overlay2 = NonCanonicalOverlay::<H256, H256>::new(&db).unwrap();
+		assert_eq!(overlay.levels, overlay2.levels);
+		assert_eq!(overlay.parents, overlay2.parents);
+		assert!(overlay2.discarded_journals.is_empty());
+	}
+
+	#[test]
+	fn restores_after_interrupted_canonicalization() {
+		let h1 = H256::random();
+		let h2 = H256::random();
+		let db = FailingDb::default();
+		let mut overlay = NonCanonicalOverlay::<H256, H256>::new(&db).unwrap();
+		db.commit_meta(
+			&overlay.insert(&h1, 10, &H256::default(), make_changeset(&[1], &[])).unwrap(),
+		)
+		.unwrap();
+		db.commit_meta(&overlay.insert(&h2, 11, &h1, make_changeset(&[2], &[])).unwrap())
+			.unwrap();
+		// The last canonicalized block is written, the journal record of `h1` is not removed.
+		let mut commit = CommitSet::default();
+		overlay.canonicalize(&h1, &mut commit).unwrap();
+		db.fail_after(Some(1));
+		assert!(db.commit_meta(&commit).is_err());
+
+		let overlay = NonCanonicalOverlay::<H256, H256>::new(&db).unwrap();
+		assert_eq!(overlay.last_canonicalized, Some((h1, 10)));
+		assert_eq!(overlay.levels.len(), 1);
+		assert!(contains(&overlay, 2));
+		assert!(overlay.discarded_journals.is_empty());
+	}
 }
@@ -23,14 +23,28 @@
 //! If a node is re-inserted into the window it gets removed from
 //! the death list.
 //! The changes are journaled in the DB.
-
-use crate::{to_meta_key, CommitSet, Error, Hash, MetaDb};
+//!
+//! Optionally the window may retain checkpoints: when a checkpoint block leaves the window, the
+//! nodes of its state are never deleted. Between checkpoints only the nodes that were inserted
+//! after the last checkpoint may be deleted. To support this, journal records of the blocks pruned
+//! since the last checkpoint are kept in the DB until the next checkpoint is reached.
+//!
+//! A window may also be started on a database that was previously kept in an archive mode. Nodes
+//! deleted by the blocks canonicalized before the switch were never journaled, so the window keeps
+//! track of an archive garbage collection pass: for each such block the caller supplies the nodes
+//! that are no longer part of any retained state, and the window deletes those that were not
+//! re-inserted by the blocks in the window. The pass is journaled and may be interrupted at any
+//! block.
+
+use crate::{to_meta_key, CommitSet, DBValue, Error, Hash, JournalChanges, MetaDb};
 use codec::{Decode, Encode};
 use log::{trace, warn};
 use std::collections::{HashMap, HashSet, VecDeque};
 
 const LAST_PRUNED: &[u8] = b"last_pruned";
 const PRUNING_JOURNAL: &[u8] = b"pruning_journal";
+const CHECKPOINT_START: &[u8] = b"checkpoint_start";
+const ARCHIVE_GC: &[u8] = b"archive_gc";
 
 /// See module documentation.
 #[derive(parity_util_mem_derive::MallocSizeOf)]
@@ -51,6 +65,10 @@ pub struct RefWindow<BlockHash: Hash, Key: Hash> {
 	/// Setting this to false requires backend that supports reference
 	/// counting.
 	count_insertions: bool,
+	/// Checkpoint tracking. `None` if no state is retained after leaving the window.
+	checkpoints: Option<Checkpoints<Key>>,
+	/// Archive garbage collection progress. `None` if there is nothing to collect.
+	archive_gc: Option<ArchiveGc>,
 }
 
 #[derive(Debug, PartialEq, Eq, parity_util_mem_derive::MallocSizeOf)]
@@ -58,6 +76,97 @@ struct DeathRow<BlockHash: Hash, Key: Hash> {
 	hash: BlockHash,
 	journal_key: Vec<u8>,
 	deleted: HashSet<Key>,
+	/// Inserted keys. Only tracked when checkpoints are retained or archive garbage is collected.
+	inserted: Vec<Key>,
+}
+
+#[derive(Debug, PartialEq, Eq, Encode, Decode, parity_util_mem_derive::MallocSizeOf)]
+struct ArchiveGc {
+	/// Number of the next block to collect the garbage of.
+	next: u64,
+	/// Number of the last block canonicalized in the archive mode.
+	end: u64,
+	/// New value of `next` if garbage has been collected since the last call to `apply_pending`
+	/// or `revert_pending`.
+	#[codec(skip)]
+	pending_next: Option<u64>,
+}
+
+impl ArchiveGc {
+	fn current_next(&self) -> u64 {
+		self.pending_next.unwrap_or(self.next)
+	}
+}
+
+#[derive(Debug, Default, PartialEq, Eq, parity_util_mem_derive::MallocSizeOf)]
+struct Checkpoints<Key: Hash> {
+	/// Journal index of the first block pruned after the last checkpoint.
+	start: u64,
+	/// Keys inserted by the blocks pruned after the last checkpoint, with insertion count. Only
+	/// these keys may be deleted, everything else belongs to the state of a checkpoint.
+	inserted: HashMap<Key, u32>,
+	/// New value of `start` if a checkpoint has been pruned since the last call to
+	/// `apply_pending` or `revert_pending`.
+	pending_start: Option<u64>,
+	/// Changes to `inserted` made by pending prunings. If `pending_start` is set these are
+	/// applied to an empty map.
+	pending_inserted: HashMap<Key, i64>,
+}
+
+impl<Key: Hash> Checkpoints<Key> {
+	fn count(&self, key: &Key) -> i64 {
+		let base = if self.pending_start.is_some() {
+			0
+		} else {
+			self.inserted.get(key).cloned().unwrap_or(0) as i64
+		};
+		base + self.pending_inserted.get(key).cloned().unwrap_or(0)
+	}
+
+	/// Returns `true` if the key was inserted after the last checkpoint and may be deleted.
+	fn take_inserted(&mut self, key: &Key) -> bool {
+		if self.count(key) > 0 {
+			*self.pending_inserted.entry(key.clone()).or_default() -= 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	fn note_inserted<'a>(&mut self, keys: impl IntoIterator<Item = &'a Key>) {
+		for k in keys {
+			*self.pending_inserted.entry(k.clone()).or_default() += 1;
+		}
+	}
+
+	fn note_checkpoint(&mut self, next_start: u64) {
+		self.pending_start = Some(next_start);
+		self.pending_inserted.clear();
+	}
+
+	fn current_start(&self) -> u64 {
+		self.pending_start.unwrap_or(self.start)
+	}
+
+	fn apply_pending(&mut self) {
+		if let Some(start) = self.pending_start.take() {
+			self.start = start;
+			self.inserted.clear();
+		}
+		for (k, delta) in self.pending_inserted.drain() {
+			let count = self.inserted.get(&k).cloned().unwrap_or(0) as i64 + delta;
+			if count > 0 {
+				self.inserted.insert(k, count as u32);
+			} else {
+				self.inserted.remove(&k);
+			}
+		}
+	}
+
+	fn revert_pending(&mut self) {
+		self.pending_start = None;
+		self.pending_inserted.clear();
+	}
 }
 
 #[derive(Encode, Decode)]
@@ -71,10 +180,67 @@ fn to_journal_key(block: u64) -> Vec<u8> {
 	to_meta_key(PRUNING_JOURNAL, &block)
 }
 
+/// Returns the node changes recorded by a pruning journal entry, or `None` if `key` is not a
+/// journal key.
+pub fn journal_changes<BlockHash: Hash>(
+	key: &[u8],
+	value: &[u8],
+) -> Result<Option<JournalChanges<BlockHash>>, codec::Error> {
+	if !is_journal_key(key) {
+		return Ok(None)
+	}
+	let record: JournalRecord<BlockHash, Vec<u8>> = Decode::decode(&mut &value[..])?;
+	Ok(Some(JournalChanges {
+		hash: record.hash,
+		parent_hash: None,
+		inserted: record.inserted.into_iter().map(|k| (k, None)).collect(),
+		deleted: record.deleted,
+	}))
+}
+
+/// Encode `changes` as a pruning journal entry.
+pub fn encode_journal_changes<BlockHash: Hash>(changes: JournalChanges<BlockHash>) -> DBValue {
+	JournalRecord::<BlockHash, Vec<u8>> {
+		hash: changes.hash,
+		inserted: changes.inserted.into_iter().map(|(k, _)| k).collect(),
+		deleted: changes.deleted,
+	}
+	.encode()
+}
+
+/// Whether `key` is the key of a pruning journal entry.
+pub(crate) fn is_journal_key(key: &[u8]) -> bool {
+	key.len() == 8 + PRUNING_JOURNAL.len() && key.ends_with(PRUNING_JOURNAL)
+}
+
+/// Returns the number of the last pruned block stored in `db`, if any.
+pub fn fetch_last_pruned<D: MetaDb>(db: &D) -> Result<Option<u64>, Error<D::Error>> {
+	match db.get_meta(&to_meta_key(LAST_PRUNED, &())).map_err(Error::Db)? {
+		Some(buffer) => Ok(Some(u64::decode(&mut buffer.as_slice())?)),
+		None => Ok(None),
+	}
+}
+
 impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 	pub fn new<D: MetaDb>(
 		db: &D,
 		count_insertions: bool,
+	) -> Result<RefWindow<BlockHash, Key>, Error<D::Error>> {
+		Self::open(db, count_insertions, false)
+	}
+
+	/// Create a window that retains the state of checkpoint blocks. See module documentation.
+	pub fn with_checkpoints<D: MetaDb>(
+		db: &D,
+		count_insertions: bool,
+	) -> Result<RefWindow<BlockHash, Key>, Error<D::Error>> {
+		Self::open(db, count_insertions, true)
+	}
+
+	fn open<D: MetaDb>(
+		db: &D,
+		count_insertions: bool,
+		checkpoints: bool,
 	) -> Result<RefWindow<BlockHash, Key>, Error<D::Error>> {
 		let last_pruned = db.get_meta(&to_meta_key(LAST_PRUNED, &())).map_err(Error::Db)?;
 		let pending_number: u64 = match last_pruned {
@@ -89,7 +255,15 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 			pending_canonicalizations: 0,
 			pending_prunings: 0,
 			count_insertions,
+			checkpoints: None,
+			archive_gc: None,
 		};
+		if checkpoints {
+			pruning.checkpoints = Some(Self::read_checkpoints(db, pending_number)?);
+		}
+		if let Some(buffer) = db.get_meta(&to_meta_key(ARCHIVE_GC, &())).map_err(Error::Db)? {
+			pruning.archive_gc = Some(Decode::decode(&mut buffer.as_slice())?);
+		}
 		// read the journal
 		trace!(target: "state-db", "Reading pruning journal. Pending #{}", pending_number);
 		loop {
@@ -113,6 +287,31 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		Ok(pruning)
 	}
 
+	/// Restore the checkpoint state by replaying journal records of the blocks pruned since the
+	/// last checkpoint.
+	fn read_checkpoints<D: MetaDb>(
+		db: &D,
+		pending_number: u64,
+	) -> Result<Checkpoints<Key>, Error<D::Error>> {
+		let start = match db.get_meta(&to_meta_key(CHECKPOINT_START, &())).map_err(Error::Db)? {
+			Some(buffer) => u64::decode(&mut buffer.as_slice())?,
+			None => pending_number,
+		};
+		let mut checkpoints = Checkpoints { start, ..Default::default() };
+		trace!(target: "state-db", "Reading checkpoint journal #{}..#{}", start, pending_number);
+		for block in start..pending_number {
+			if let Some(record) = db.get_meta(&to_journal_key(block)).map_err(Error::Db)? {
+				let record: JournalRecord<BlockHash, Key> = Decode::decode(&mut record.as_slice())?;
+				for k in record.deleted.iter() {
+					checkpoints.take_inserted(k);
+				}
+				checkpoints.note_inserted(record.inserted.iter());
+				checkpoints.apply_pending();
+			}
+		}
+		Ok(checkpoints)
+	}
+
 	fn import<I: IntoIterator<Item = Key>>(
 		&mut self,
 		hash: &BlockHash,
@@ -120,11 +319,12 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		inserted: I,
 		deleted: Vec<Key>,
 	) {
+		let inserted: Vec<Key> = inserted.into_iter().collect();
 		if self.count_insertions {
 			// remove all re-inserted keys from death rows
-			for k in inserted {
-				if let Some(block) = self.death_index.remove(&k) {
-					self.death_rows[(block - self.pending_number) as usize].deleted.remove(&k);
+			for k in inserted.iter() {
+				if let Some(block) = self.death_index.remove(k) {
+					self.death_rows[(block - self.pending_number) as usize].deleted.remove(k);
 				}
 			}
 
@@ -138,6 +338,11 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 			hash: hash.clone(),
 			deleted: deleted.into_iter().collect(),
 			journal_key,
+			inserted: if self.checkpoints.is_some() || self.archive_gc.is_some() {
+				inserted
+			} else {
+				Vec::new()
+			},
 		});
 	}
 
@@ -161,14 +366,122 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		self.death_rows.iter().skip(self.pending_prunings).any(|r| r.hash == *hash)
 	}
 
+	/// Start an empty window at the last canonical block of a database that was kept in an archive
+	/// mode. The state of that block is retained in the window. If `collect_garbage` is set, the
+	/// window tracks the collection of the nodes left by blocks `1..=number`. Adds changes to
+	/// `commit`.
+	pub fn start_from_archive(
+		&mut self,
+		hash: &BlockHash,
+		number: u64,
+		collect_garbage: bool,
+		commit: &mut CommitSet<Key>,
+	) {
+		debug_assert!(self.death_rows.is_empty() && self.pending_number == 0);
+		trace!(target: "state-db", "Starting pruning window at archived block #{} ({:?})", number, hash);
+		self.pending_number = number;
+		if let Some(last_pruned) = number.checked_sub(1) {
+			commit.meta.inserted.push((to_meta_key(LAST_PRUNED, &()), last_pruned.encode()));
+		}
+		if let Some(checkpoints) = &mut self.checkpoints {
+			checkpoints.start = number;
+		}
+		if collect_garbage && number > 0 {
+			let archive_gc = ArchiveGc { next: 1, end: number, pending_next: None };
+			commit.meta.inserted.push((to_meta_key(ARCHIVE_GC, &()), archive_gc.encode()));
+			self.archive_gc = Some(archive_gc);
+		}
+		let mut record = CommitSet::default();
+		self.note_canonical(hash, &mut record);
+		commit.meta.inserted.extend(record.meta.inserted);
+	}
+
+	/// Returns the number of the next archived block to collect the garbage of, if any.
+	pub fn archive_gc_next(&self) -> Option<u64> {
+		match &self.archive_gc {
+			Some(archive_gc) if archive_gc.current_next() <= archive_gc.end =>
+				Some(archive_gc.current_next()),
+			_ => None,
+		}
+	}
+
+	/// Delete the nodes left by archived block `number` that are not part of the state of the
+	/// first block in the window. Nodes inserted by the blocks in the window are kept. Adds changes
+	/// to `commit`.
+	pub fn collect_archive_garbage(
+		&mut self,
+		number: u64,
+		garbage: Vec<Key>,
+		commit: &mut CommitSet<Key>,
+	) {
+		let archive_gc = match &mut self.archive_gc {
+			Some(archive_gc) if archive_gc.current_next() == number => archive_gc,
+			_ => {
+				warn!(target: "state-db", "Trying to collect garbage of unexpected block #{}", number);
+				return
+			},
+		};
+		let inserted: HashSet<&Key> = self
+			.death_rows
+			.iter()
+			.skip(self.pending_prunings)
+			.flat_map(|row| row.inserted.iter())
+			.collect();
+		let len = commit.data.deleted.len();
+		commit
+			.data
+			.deleted
+			.extend(garbage.into_iter().filter(|k| !inserted.contains(k)));
+		trace!(target: "state-db", "Collecting garbage of archived block #{} ({} deleted)", number, commit.data.deleted.len() - len);
+		archive_gc.pending_next = Some(number + 1);
+		if number < archive_gc.end {
+			let next = ArchiveGc { next: number + 1, end: archive_gc.end, pending_next: None };
+			commit.meta.inserted.push((to_meta_key(ARCHIVE_GC, &()), next.encode()));
+		} else {
+			commit.meta.deleted.push(to_meta_key(ARCHIVE_GC, &()));
+		}
+	}
+
 	/// Prune next block. Expects at least one block in the window. Adds changes to `commit`.
 	pub fn prune_one(&mut self, commit: &mut CommitSet<Key>) {
+		self.prune_next(false, commit)
+	}
+
+	/// Prune next block, keeping its state in the DB if the window retains checkpoints. Expects at
+	/// least one block in the window. Adds changes to `commit`.
+	pub fn prune_checkpoint(&mut self, commit: &mut CommitSet<Key>) {
+		self.prune_next(true, commit)
+	}
+
+	fn prune_next(&mut self, checkpoint: bool, commit: &mut CommitSet<Key>) {
 		if let Some(pruned) = self.death_rows.get(self.pending_prunings) {
 			trace!(target: "state-db", "Pruning {:?} ({} deleted)", pruned.hash, pruned.deleted.len());
 			let index = self.pending_number + self.pending_prunings as u64;
-			commit.data.deleted.extend(pruned.deleted.iter().cloned());
 			commit.meta.inserted.push((to_meta_key(LAST_PRUNED, &()), index.encode()));
-			commit.meta.deleted.push(pruned.journal_key.clone());
+			match self.checkpoints {
+				Some(ref mut checkpoints) => {
+					commit.data.deleted.extend(
+						pruned.deleted.iter().filter(|k| checkpoints.take_inserted(k)).cloned(),
+					);
+					checkpoints.note_inserted(pruned.inserted.iter());
+					if checkpoint {
+						trace!(target: "state-db", "Retaining checkpoint {:?}", pruned.hash);
+						commit
+							.meta
+							.deleted
+							.extend((checkpoints.current_start()..=index).map(to_journal_key));
+						checkpoints.note_checkpoint(index + 1);
+					}
+					commit.meta.inserted.push((
+						to_meta_key(CHECKPOINT_START, &()),
+						checkpoints.current_start().encode(),
+					));
+				},
+				None => {
+					commit.data.deleted.extend(pruned.deleted.iter().cloned());
+					commit.meta.deleted.push(pruned.journal_key.clone());
+				},
+			}
 			self.pending_prunings += 1;
 		} else {
 			warn!(target: "state-db", "Trying to prune when there's nothing to prune");
@@ -178,7 +491,7 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 	/// Add a change set to the window. Creates a journal record and pushes it to `commit`
 	pub fn note_canonical(&mut self, hash: &BlockHash, commit: &mut CommitSet<Key>) {
 		trace!(target: "state-db", "Adding to pruning window: {:?} ({} inserted, {} deleted)", hash, commit.data.inserted.len(), commit.data.deleted.len());
-		let inserted = if self.count_insertions {
+		let inserted = if self.count_insertions || self.checkpoints.is_some() {
 			commit.data.inserted.iter().map(|(k, _)| k.clone()).collect()
 		} else {
 			Default::default()
@@ -214,6 +527,17 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 			self.pending_number += 1;
 		}
 		self.pending_prunings = 0;
+		if let Some(checkpoints) = &mut self.checkpoints {
+			checkpoints.apply_pending();
+		}
+		if let Some(archive_gc) = &mut self.archive_gc {
+			if let Some(next) = archive_gc.pending_next.take() {
+				archive_gc.next = next;
+			}
+			if archive_gc.next > archive_gc.end {
+				self.archive_gc = None;
+			}
+		}
 	}
 
 	/// Revert all pending changes
@@ -230,23 +554,38 @@ impl<BlockHash: Hash, Key: Hash> RefWindow<BlockHash, Key> {
 		}
 		self.pending_canonicalizations = 0;
 		self.pending_prunings = 0;
+		if let Some(checkpoints) = &mut self.checkpoints {
+			checkpoints.revert_pending();
+		}
+		if let Some(archive_gc) = &mut self.archive_gc {
+			archive_gc.pending_next = None;
+		}
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::RefWindow;
+	use super::{
+		encode_journal_changes, journal_changes, to_journal_key, JournalRecord, RefWindow,
+		LAST_PRUNED,
+	};
 	use crate::{
 		test::{make_commit, make_db, TestDb},
-		CommitSet,
+		to_meta_key, CommitSet,
 	};
+	use codec::Encode;
 	use sp_core::H256;
 
 	fn check_journal(pruning: &RefWindow<H256, H256>, db: &TestDb) {
-		let restored: RefWindow<H256, H256> = RefWindow::new(db, pruning.count_insertions).unwrap();
+		let restored: RefWindow<H256, H256> = if pruning.checkpoints.is_some() {
+			RefWindow::with_checkpoints(db, pruning.count_insertions).unwrap()
+		} else {
+			RefWindow::new(db, pruning.count_insertions).unwrap()
+		};
 		assert_eq!(pruning.pending_number, restored.pending_number);
 		assert_eq!(pruning.death_rows, restored.death_rows);
 		assert_eq!(pruning.death_index, restored.death_index);
+		assert_eq!(pruning.checkpoints, restored.checkpoints);
 	}
 
 	#[test]
@@ -438,4 +777,86 @@ mod tests {
 		assert!(db.data_eq(&make_db(&[1, 3])));
 		assert!(pruning.death_index.is_empty());
 	}
+
+	#[test]
+	fn checkpoint_state_survives() {
+		let mut db = make_db(&[1, 2, 3]);
+		let mut pruning: RefWindow<H256, H256> = RefWindow::with_checkpoints(&db, false).unwrap();
+		let mut commit = make_commit(&[4], &[1]);
+		pruning.note_canonical(&H256::random(), &mut commit);
+		db.commit(&commit);
+		let mut commit = make_commit(&[5], &[4]);
+		pruning.note_canonical(&H256::random(), &mut commit);
+		db.commit(&commit);
+		let mut commit = make_commit(&[6], &[2, 5]);
+		pruning.note_canonical(&H256::random(), &mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+		assert!(db.data_eq(&make_db(&[1, 2, 3, 4, 5, 6])));
+		check_journal(&pruning, &db);
+
+		// 1 was inserted before the window and is kept.
+		let mut commit = CommitSet::default();
+		pruning.prune_one(&mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+		assert!(db.data_eq(&make_db(&[1, 2, 3, 4, 5, 6])));
+		check_journal(&pruning, &db);
+
+		// 4 was inserted after the last checkpoint and is deleted.
+		let mut commit = CommitSet::default();
+		pruning.prune_checkpoint(&mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+		assert!(db.data_eq(&make_db(&[1, 2, 3, 5, 6])));
+		check_journal(&pruning, &db);
+
+		// 2 and 5 belong to the checkpoint state.
+		let mut commit = CommitSet::default();
+		pruning.prune_one(&mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+		assert!(db.data_eq(&make_db(&[1, 2, 3, 5, 6])));
+		assert_eq!(pruning.pending_number, 3);
+		check_journal(&pruning, &db);
+	}
+
+	#[test]
+	fn checkpoint_revert_pending() {
+		let mut db = make_db(&[1]);
+		let mut pruning: RefWindow<H256, H256> = RefWindow::with_checkpoints(&db, false).unwrap();
+		let mut commit = make_commit(&[2], &[1]);
+		pruning.note_canonical(&H256::random(), &mut commit);
+		db.commit(&commit);
+		let mut commit = make_commit(&[3], &[2]);
+		pruning.note_canonical(&H256::random(), &mut commit);
+		db.commit(&commit);
+		pruning.apply_pending();
+
+		let mut commit = CommitSet::default();
+		pruning.prune_one(&mut commit);
+		pruning.prune_checkpoint(&mut commit);
+		assert_eq!(commit.data.deleted, vec![H256::from_low_u64_be(2)]);
+		pruning.revert_pending();
+		check_journal(&pruning, &db);
+	}
+
+	#[test]
+	fn journal_changes_roundtrip() {
+		let record = JournalRecord::<H256, Vec<u8>> {
+			hash: H256::random(),
+			inserted: vec![vec![1, 2]],
+			deleted: vec![vec![3]],
+		};
+		let changes =
+			journal_changes::<H256>(&to_journal_key(5), &record.encode()).unwrap().unwrap();
+		assert_eq!(changes.hash, record.hash);
+		assert_eq!(changes.parent_hash, None);
+		assert_eq!(changes.inserted, vec![(vec![1, 2], None)]);
+		assert_eq!(changes.deleted, vec![vec![3]]);
+		assert_eq!(encode_journal_changes(changes), record.encode());
+
+		let last_pruned = to_meta_key(LAST_PRUNED, &());
+		assert!(journal_changes::<H256>(&last_pruned, &5u64.encode()).unwrap().is_none());
+	}
 }
@@ -19,7 +19,9 @@
 //! Test utils
 
 use crate::{ChangeSet, CommitSet, DBValue, MetaDb, NodeDb};
+use parking_lot::Mutex;
 use sp_core::H256;
+use sp_database::{error::DatabaseError, Change, ColumnId, Database, Transaction};
 use std::collections::HashMap;
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -63,6 +65,74 @@ impl TestDb {
 	}
 }
 
+/// In-memory meta database that stops writing after a given number of changes, leaving the value
+/// being written truncated, as if the node was killed in the middle of a commit.
+#[derive(Default)]
+pub struct FailingDb {
+	meta: Mutex<HashMap<Vec<u8>, DBValue>>,
+	writes_left: Mutex<Option<usize>>,
+}
+
+impl Database<H256> for FailingDb {
+	fn commit(&self, transaction: Transaction<H256>) -> sp_database::error::Result<()> {
+		let mut meta = self.meta.lock();
+		let mut writes_left = self.writes_left.lock();
+		for change in transaction.0 {
+			if *writes_left == Some(0) {
+				if let Change::Set(_, key, value) = change {
+					meta.insert(key, value[..value.len() / 2].to_vec());
+				}
+				return Err(DatabaseError(Box::new(std::io::Error::new(
+					std::io::ErrorKind::Other,
+					"Interrupted write",
+				))))
+			}
+			match change {
+				Change::Set(_, key, value) => {
+					meta.insert(key, value);
+				},
+				Change::Remove(_, key) => {
+					meta.remove(&key);
+				},
+				_ => unreachable!("Only meta changes are written"),
+			}
+			*writes_left = writes_left.map(|n| n - 1);
+		}
+		Ok(())
+	}
+
+	fn get(&self, _col: ColumnId, key: &[u8]) -> Option<Vec<u8>> {
+		self.meta.lock().get(key).cloned()
+	}
+}
+
+impl MetaDb for FailingDb {
+	type Error = ();
+
+	fn get_meta(&self, key: &[u8]) -> Result<Option<DBValue>, ()> {
+		Ok(self.get(0, key))
+	}
+}
+
+impl FailingDb {
+	/// Fail the write following the next `writes` ones, or never fail if `None`.
+	pub fn fail_after(&self, writes: Option<usize>) {
+		*self.writes_left.lock() = writes;
+	}
+
+	/// Commit the meta changes of `commit`.
+	pub fn commit_meta(&self, commit: &CommitSet<H256>) -> sp_database::error::Result<()> {
+		let mut transaction = Transaction::new();
+		for (key, value) in commit.meta.inserted.iter() {
+			transaction.set(0, key, value);
+		}
+		for key in commit.meta.deleted.iter() {
+			transaction.remove(0, key);
+		}
+		self.commit(transaction)
+	}
+}
+
 pub fn make_changeset(inserted: &[u64], deleted: &[u64]) -> ChangeSet<H256> {
 	ChangeSet {
 		inserted: inserted
@@ -42,9 +42,9 @@
 #![deny(unused_crate_dependencies)]
 
 use jsonrpsee::{
-	core::{Error as JsonRpseeError, RpcResult},
+	core::RpcResult,
 	proc_macros::rpc,
-	types::{error::CallError, ErrorObject},
+	types::{ErrorObject, ErrorObjectOwned},
 };
 use sc_client_api::StorageData;
 use sp_blockchain::HeaderBackend;
@@ -79,13 +79,13 @@ pub enum Error<Block: BlockT> {
 	LightSyncStateExtensionNotFound,
 }
 
-impl<Block: BlockT> From<Error<Block>> for JsonRpseeError {
+impl<Block: BlockT> From<Error<Block>> for ErrorObjectOwned {
 	fn from(error: Error<Block>) -> Self {
 		let message = match error {
 			Error::JsonRpc(s) => s,
 			_ => error.to_string(),
 		};
-		CallError::Custom(ErrorObject::owned(1, message, None::<()>)).into()
+		ErrorObject::owned(1, message, None::<()>)
 	}
 }
 
@@ -194,10 +194,11 @@ where
 		)
 		.ok_or(Error::<Block>::LightSyncStateExtensionNotFound)?;
 
-		let val = serde_json::to_value(&current_sync_state)?;
+		let val = serde_json::to_value(&current_sync_state)
+			.map_err(|e| Error::<Block>::JsonRpc(e.to_string()))?;
 		*extension = Some(val);
 
 		let json_str = chain_spec.as_json(raw).map_err(|e| Error::<Block>::JsonRpc(e))?;
-		serde_json::from_str(&json_str).map_err(Into::into)
+		serde_json::from_str(&json_str).map_err(|e| Error::<Block>::JsonRpc(e.to_string()).into())
 	}
 }
@@ -42,3 +42,4 @@ pub mod mpsc;
 pub mod notification;
 pub mod pubsub;
 pub mod status_sinks;
+pub mod token_bucket;
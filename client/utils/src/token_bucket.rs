@@ -0,0 +1,121 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Token bucket rate limiting.
+
+use std::time::Instant;
+
+/// Rate enforced by a [`TokenBucket`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+	/// Maximum number of tokens in the bucket, which is the number of events allowed at once.
+	pub burst: u32,
+	/// Number of tokens refilled per second, which is the number of events allowed per second in
+	/// the long run.
+	pub per_second: f64,
+}
+
+impl Rate {
+	/// `per_minute` events per minute, all of which are allowed at once.
+	pub fn per_minute(per_minute: u32) -> Self {
+		Self { burst: per_minute, per_second: per_minute as f64 / 60.0 }
+	}
+}
+
+/// Token bucket limiting the rate of some events.
+///
+/// The bucket starts full, and every event takes one of its tokens. The tokens are refilled at
+/// the [`Rate`] passed to every call, which is left to the owner of the bucket so that the buckets
+/// sharing a rate don't each store it.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+	tokens: f64,
+	refilled_at: Instant,
+}
+
+impl TokenBucket {
+	/// Create a full bucket.
+	pub fn full(rate: &Rate, now: Instant) -> Self {
+		Self { tokens: rate.burst as f64, refilled_at: now }
+	}
+
+	/// Refill the bucket for the time elapsed since the last refill.
+	pub fn refill(&mut self, rate: &Rate, now: Instant) {
+		let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+		self.tokens = (self.tokens + elapsed * rate.per_second).min(rate.burst as f64);
+		self.refilled_at = now;
+	}
+
+	/// Returns true if the bucket was full at the last refill.
+	pub fn is_full(&self, rate: &Rate) -> bool {
+		self.tokens >= rate.burst as f64
+	}
+
+	/// Take a token for a new event, after refilling the bucket. Returns `false` if the rate is
+	/// exceeded.
+	pub fn try_take(&mut self, rate: &Rate, now: Instant) -> bool {
+		self.refill(rate, now);
+		if self.tokens < 1.0 {
+			return false
+		}
+		self.tokens -= 1.0;
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn enforces_rate() {
+		let rate = Rate { burst: 2, per_second: 4.0 };
+		let start = Instant::now();
+		let mut bucket = TokenBucket::full(&rate, start);
+
+		assert!(bucket.try_take(&rate, start));
+		assert!(bucket.try_take(&rate, start));
+		assert!(!bucket.try_take(&rate, start));
+
+		// A token is refilled every 250ms.
+		assert!(bucket.try_take(&rate, start + Duration::from_millis(250)));
+		assert!(!bucket.try_take(&rate, start + Duration::from_millis(300)));
+
+		// The refill is capped to the burst.
+		let later = start + Duration::from_secs(10);
+		bucket.refill(&rate, later);
+		assert!(bucket.is_full(&rate));
+		assert!(bucket.try_take(&rate, later));
+		assert!(bucket.try_take(&rate, later));
+		assert!(!bucket.try_take(&rate, later));
+	}
+
+	#[test]
+	fn per_minute_refills_over_a_minute() {
+		let rate = Rate::per_minute(60);
+		let start = Instant::now();
+		let mut bucket = TokenBucket::full(&rate, start);
+
+		for _ in 0..60 {
+			assert!(bucket.try_take(&rate, start));
+		}
+		assert!(!bucket.try_take(&rate, start));
+		assert!(bucket.try_take(&rate, start + Duration::from_secs(1)));
+	}
+}
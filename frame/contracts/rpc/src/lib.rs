@@ -23,9 +23,12 @@ use std::{marker::PhantomData, sync::Arc};
 
 use codec::Codec;
 use jsonrpsee::{
-	core::{async_trait, Error as JsonRpseeError, RpcResult},
+	core::{async_trait, RpcResult},
 	proc_macros::rpc,
-	types::error::{CallError, ErrorCode, ErrorObject},
+	types::{
+		error::{ErrorCode, ErrorObject},
+		ErrorObjectOwned,
+	},
 };
 use pallet_contracts_primitives::{
 	Code, CodeUploadResult, ContractExecResult, ContractInstantiateResult,
@@ -65,22 +68,20 @@ const GAS_LIMIT: Weight = 5 * GAS_PER_SECOND;
 /// A private newtype for converting `ContractAccessError` into an RPC error.
 struct ContractAccessError(pallet_contracts_primitives::ContractAccessError);
 
-impl From<ContractAccessError> for JsonRpseeError {
+impl From<ContractAccessError> for ErrorObjectOwned {
 	fn from(e: ContractAccessError) -> Self {
 		use pallet_contracts_primitives::ContractAccessError::*;
 		match e.0 {
-			DoesntExist => CallError::Custom(ErrorObject::owned(
+			DoesntExist => ErrorObject::owned(
 				CONTRACT_DOESNT_EXIST,
 				"The specified contract doesn't exist.",
 				None::<()>,
-			))
-			.into(),
-			KeyDecodingFailed => CallError::Custom(ErrorObject::owned(
+			),
+			KeyDecodingFailed => ErrorObject::owned(
 				KEY_DECODING_FAILED,
 				"Failed to decode the specified storage key.",
 				None::<()>,
-			))
-			.into(),
+			),
 		}
 	}
 }
@@ -315,35 +316,30 @@ where
 }
 
 /// Converts a runtime trap into an RPC error.
-fn runtime_error_into_rpc_err(err: impl std::fmt::Debug) -> JsonRpseeError {
-	CallError::Custom(ErrorObject::owned(
-		RUNTIME_ERROR,
-		"Runtime error",
-		Some(format!("{:?}", err)),
-	))
-	.into()
+fn runtime_error_into_rpc_err(err: impl std::fmt::Debug) -> ErrorObjectOwned {
+	ErrorObject::owned(RUNTIME_ERROR, "Runtime error", Some(format!("{:?}", err)))
 }
 
 fn decode_hex<H: std::fmt::Debug + Copy, T: TryFrom<H>>(from: H, name: &str) -> RpcResult<T> {
 	from.try_into().map_err(|_| {
-		JsonRpseeError::Call(CallError::Custom(ErrorObject::owned(
+		ErrorObject::owned(
 			ErrorCode::InvalidParams.code(),
 			format!("{:?} does not fit into the {} type", from, name),
 			None::<()>,
-		)))
+		)
 	})
 }
 
 fn limit_gas(gas_limit: Weight) -> RpcResult<()> {
 	if gas_limit > GAS_LIMIT {
-		Err(JsonRpseeError::Call(CallError::Custom(ErrorObject::owned(
+		Err(ErrorObject::owned(
 			ErrorCode::InvalidParams.code(),
 			format!(
 				"Requested gas limit is greater than maximum allowed: {} > {}",
 				gas_limit, GAS_LIMIT
 			),
 			None::<()>,
-		))))
+		))
 	} else {
 		Ok(())
 	}
@@ -26,7 +26,7 @@ use codec::{Codec, Encode};
 use jsonrpsee::{
 	core::{async_trait, RpcResult},
 	proc_macros::rpc,
-	types::error::{CallError, ErrorObject},
+	types::{error::ErrorObject, ErrorObjectOwned},
 };
 use serde::{Deserialize, Serialize};
 
@@ -194,31 +194,21 @@ where
 	}
 }
 
-/// Converts a mmr-specific error into a [`CallError`].
-fn mmr_error_into_rpc_error(err: MmrError) -> CallError {
+/// Converts a mmr-specific error into an [`ErrorObjectOwned`].
+fn mmr_error_into_rpc_error(err: MmrError) -> ErrorObjectOwned {
 	let data = format!("{:?}", err);
 	match err {
-		MmrError::LeafNotFound => CallError::Custom(ErrorObject::owned(
-			LEAF_NOT_FOUND_ERROR,
-			"Leaf was not found",
-			Some(data),
-		)),
-		MmrError::GenerateProof => CallError::Custom(ErrorObject::owned(
-			GENERATE_PROOF_ERROR,
-			"Error while generating the proof",
-			Some(data),
-		)),
-		_ => CallError::Custom(ErrorObject::owned(MMR_ERROR, "Unexpected MMR error", Some(data))),
+		MmrError::LeafNotFound =>
+			ErrorObject::owned(LEAF_NOT_FOUND_ERROR, "Leaf was not found", Some(data)),
+		MmrError::GenerateProof =>
+			ErrorObject::owned(GENERATE_PROOF_ERROR, "Error while generating the proof", Some(data)),
+		_ => ErrorObject::owned(MMR_ERROR, "Unexpected MMR error", Some(data)),
 	}
 }
 
-/// Converts a runtime trap into a [`CallError`].
-fn runtime_error_into_rpc_error(err: impl std::fmt::Debug) -> CallError {
-	CallError::Custom(ErrorObject::owned(
-		RUNTIME_ERROR,
-		"Runtime trapped",
-		Some(format!("{:?}", err)),
-	))
+/// Converts a runtime trap into an [`ErrorObjectOwned`].
+fn runtime_error_into_rpc_error(err: impl std::fmt::Debug) -> ErrorObjectOwned {
+	ErrorObject::owned(RUNTIME_ERROR, "Runtime trapped", Some(format!("{:?}", err)))
 }
 
 #[cfg(test)]
@@ -158,7 +158,7 @@ impl crate::traits::SpawnNamed for TaskExecutor {
 		_group: Option<&'static str>,
 		future: futures::future::BoxFuture<'static, ()>,
 	) {
-		self.0.spawn_ok(future);
+		spawn_thread(future);
 	}
 	fn spawn(
 		&self,
@@ -178,7 +178,7 @@ impl crate::traits::SpawnEssentialNamed for TaskExecutor {
 		_: Option<&'static str>,
 		future: futures::future::BoxFuture<'static, ()>,
 	) {
-		self.0.spawn_ok(future);
+		spawn_thread(future);
 	}
 	fn spawn_essential(
 		&self,
@@ -189,3 +189,28 @@ impl crate::traits::SpawnEssentialNamed for TaskExecutor {
 		self.0.spawn_ok(future);
 	}
 }
+
+/// Run `future` on its own thread, like the blocking tasks of the nodes, so that it may block on
+/// other futures itself.
+#[cfg(feature = "std")]
+fn spawn_thread(future: futures::future::BoxFuture<'static, ()>) {
+	use futures::task::{waker, ArcWake};
+	use std::{sync::Arc, task::Context, thread::Thread};
+
+	struct Unpark(Thread);
+
+	impl ArcWake for Unpark {
+		fn wake_by_ref(arc_self: &Arc<Self>) {
+			arc_self.0.unpark();
+		}
+	}
+
+	std::thread::spawn(move || {
+		let mut future = future;
+		let waker = waker(Arc::new(Unpark(std::thread::current())));
+		let mut cx = Context::from_waker(&waker);
+		while future.as_mut().poll(&mut cx).is_pending() {
+			std::thread::park();
+		}
+	});
+}
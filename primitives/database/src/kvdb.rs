@@ -18,9 +18,9 @@
 /// A wrapper around `kvdb::Database` that implements `sp_database::Database` trait
 use ::kvdb::{DBTransaction, KeyValueDB};
 
-use crate::{error, Change, ColumnId, Database, Transaction};
+use crate::{error, Change, ColumnId, ColumnStats, Database, Transaction};
 
-struct DbAdapter<D: KeyValueDB + 'static>(D);
+struct DbAdapter<D: KeyValueDB + 'static>(D, Option<fn(&D, ColumnId) -> Option<u64>>);
 
 fn handle_err<T>(result: std::io::Result<T>) -> T {
 	match result {
@@ -37,7 +37,20 @@ where
 	D: KeyValueDB + 'static,
 	H: Clone + AsRef<[u8]>,
 {
-	std::sync::Arc::new(DbAdapter(db))
+	std::sync::Arc::new(DbAdapter(db, None))
+}
+
+/// Wrap a database into a trait object that implements `sp_database::Database`, estimating the
+/// number of keys of a column with `estimate_num_keys`.
+pub fn as_database_with_estimates<D, H>(
+	db: D,
+	estimate_num_keys: fn(&D, ColumnId) -> Option<u64>,
+) -> std::sync::Arc<dyn Database<H>>
+where
+	D: KeyValueDB + 'static,
+	H: Clone + AsRef<[u8]>,
+{
+	std::sync::Arc::new(DbAdapter(db, Some(estimate_num_keys)))
 }
 
 impl<D: KeyValueDB> DbAdapter<D> {
@@ -115,4 +128,26 @@ impl<D: KeyValueDB, H: Clone + AsRef<[u8]>> Database<H> for DbAdapter<D> {
 	fn contains(&self, col: ColumnId, key: &[u8]) -> bool {
 		handle_err(self.0.has_key(col, key))
 	}
+
+	fn column_stats(&self, col: ColumnId) -> Option<ColumnStats> {
+		let mut stats = ColumnStats { keys: Some(0), bytes: 0 };
+		for (key, value) in self.0.iter(col) {
+			stats.keys = stats.keys.map(|keys| keys + 1);
+			stats.bytes += (key.len() + value.len()) as u64;
+		}
+		Some(stats)
+	}
+
+	fn estimate_num_keys(&self, col: ColumnId) -> Option<u64> {
+		self.1.and_then(|estimate| estimate(&self.0, col))
+	}
+
+	fn iter_column(&self, col: ColumnId, f: &mut dyn FnMut(Option<&[u8]>, &[u8]) -> bool) -> bool {
+		for (key, value) in self.0.iter(col) {
+			if !f(Some(&key), &value) {
+				break
+			}
+		}
+		true
+	}
 }
@@ -21,7 +21,7 @@ pub mod error;
 mod kvdb;
 mod mem;
 
-pub use crate::kvdb::as_database;
+pub use crate::kvdb::{as_database, as_database_with_estimates};
 pub use mem::MemDb;
 
 /// An identifier for a column.
@@ -77,6 +77,15 @@ impl<H> Transaction<H> {
 	}
 }
 
+/// Size statistics of a database column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+	/// Number of keys, if known.
+	pub keys: Option<u64>,
+	/// Size of the column in bytes.
+	pub bytes: u64,
+}
+
 pub trait Database<H: Clone + AsRef<[u8]>>: Send + Sync {
 	/// Commit the `transaction` to the database atomically. Any further calls to `get` or `lookup`
 	/// will reflect the new state.
@@ -115,6 +124,33 @@ pub trait Database<H: Clone + AsRef<[u8]>>: Send + Sync {
 	///
 	/// Not all database implementations use a prefix for keys, so this function may be a noop.
 	fn sanitize_key(&self, _key: &mut Vec<u8>) {}
+
+	/// Compute the size statistics of the column `col`.
+	///
+	/// This may read the whole column. Returns `None` if the database does not support it.
+	fn column_stats(&self, _col: ColumnId) -> Option<ColumnStats> {
+		None
+	}
+
+	/// Estimate the number of keys of the column `col` without reading it.
+	///
+	/// Returns `None` if the database can't estimate it.
+	fn estimate_num_keys(&self, _col: ColumnId) -> Option<u64> {
+		None
+	}
+
+	/// Call `f` with the key and the value of the entries of the column `col`, in no particular
+	/// order, until it returns `false`. Databases storing the values of a column under their hash
+	/// may not keep the keys, in which case `f` gets `None`.
+	///
+	/// Returns `false` if the database does not support iterating the column.
+	fn iter_column(
+		&self,
+		_col: ColumnId,
+		_f: &mut dyn FnMut(Option<&[u8]>, &[u8]) -> bool,
+	) -> bool {
+		false
+	}
 }
 
 impl<H> std::fmt::Debug for dyn Database<H> {
@@ -17,7 +17,7 @@
 
 //! In-memory implementation of `Database`
 
-use crate::{error, Change, ColumnId, Database, Transaction};
+use crate::{error, Change, ColumnId, ColumnStats, Database, Transaction};
 use parking_lot::RwLock;
 use std::collections::{hash_map::Entry, HashMap};
 
@@ -73,6 +73,26 @@ where
 		let s = self.0.read();
 		s.get(&col).and_then(|c| c.get(key).map(|(_, v)| v.clone()))
 	}
+
+	fn column_stats(&self, col: ColumnId) -> Option<ColumnStats> {
+		let s = self.0.read();
+		let column = s.get(&col);
+		Some(ColumnStats {
+			keys: Some(column.map_or(0, |c| c.len() as u64)),
+			bytes: column
+				.map_or(0, |c| c.iter().map(|(k, (_, v))| (k.len() + v.len()) as u64).sum()),
+		})
+	}
+
+	fn iter_column(&self, col: ColumnId, f: &mut dyn FnMut(Option<&[u8]>, &[u8]) -> bool) -> bool {
+		let s = self.0.read();
+		for (key, (_, value)) in s.get(&col).into_iter().flatten() {
+			if !f(Some(key), value) {
+				break
+			}
+		}
+		true
+	}
 }
 
 impl MemDb {
@@ -0,0 +1,510 @@
+// This file is part of Substrate.
+
+// Copyright (C) 2022 Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Difference between two tries.
+//!
+//! The two tries are walked together from their roots, one nibble at a time. A subtrie whose
+//! node is the same in both tries is skipped without being read, so the number of nodes fetched
+//! from the database is proportional to the size of the difference rather than to the size of
+//! the tries.
+
+use crate::{nibble_ops, TrieError, TrieHash, TrieLayout, EMPTY_PREFIX};
+use hash_db::HashDBRef;
+use sp_std::{boxed::Box, rc::Rc, vec::Vec};
+use trie_db::{
+	node::{NodeHandlePlan, NodePlan, ValuePlan},
+	DBValue, NodeCodec as NodeCodecT,
+};
+
+/// A storage entry which differs between two tries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffEntry {
+	/// The key only exists in the second trie.
+	Added {
+		/// Key of the entry.
+		key: Vec<u8>,
+		/// Value in the second trie.
+		value: Vec<u8>,
+	},
+	/// The key exists in both tries with different values.
+	Modified {
+		/// Key of the entry.
+		key: Vec<u8>,
+		/// Value in the second trie.
+		value: Vec<u8>,
+	},
+	/// The key only exists in the first trie.
+	Removed {
+		/// Key of the entry.
+		key: Vec<u8>,
+	},
+}
+
+impl DiffEntry {
+	/// Key of the entry.
+	pub fn key(&self) -> &[u8] {
+		match self {
+			Self::Added { key, .. } | Self::Modified { key, .. } | Self::Removed { key } => key,
+		}
+	}
+}
+
+/// Compare the trie at `root_a` in `db_a` with the trie at `root_b` in `db_b`.
+///
+/// Only the keys starting with `prefix` are compared. The entries which differ are passed to
+/// `on_entry` in lexicographic order of their keys; the walk stops as soon as it returns `false`.
+/// Values are compared as stored, so a value stored inline in one trie and by hash in the other
+/// is only reported if its content differs.
+pub fn diff<L, F>(
+	db_a: &dyn HashDBRef<L::Hash, DBValue>,
+	root_a: &TrieHash<L>,
+	db_b: &dyn HashDBRef<L::Hash, DBValue>,
+	root_b: &TrieHash<L>,
+	prefix: &[u8],
+	on_entry: F,
+) -> Result<(), Box<TrieError<L>>>
+where
+	L: TrieLayout,
+	F: FnMut(DiffEntry) -> bool,
+{
+	let mut walker = Walker::<L, F> {
+		dbs: [db_a, db_b],
+		prefix: prefix
+			.iter()
+			.flat_map(|byte| [nibble_ops::at_left(0, *byte), nibble_ops::at_left(1, *byte)])
+			.collect(),
+		key: Vec::new(),
+		on_entry,
+		stopped: false,
+	};
+	let a = walker.root(Side::A, root_a)?;
+	let b = walker.root(Side::B, root_b)?;
+	walker.walk([a, b])
+}
+
+/// One of the two compared tries.
+#[derive(Clone, Copy)]
+enum Side {
+	A = 0,
+	B = 1,
+}
+
+/// A decoded trie node.
+struct Node<L: TrieLayout> {
+	/// Hash of the node, `None` if it is inlined in its parent.
+	hash: Option<TrieHash<L>>,
+	data: Vec<u8>,
+	plan: NodePlan,
+}
+
+/// Position in a node: the number of nibbles of its partial key already walked.
+struct Cursor<L: TrieLayout> {
+	node: Rc<Node<L>>,
+	offset: usize,
+}
+
+impl<L: TrieLayout> Clone for Cursor<L> {
+	fn clone(&self) -> Self {
+		Self { node: self.node.clone(), offset: self.offset }
+	}
+}
+
+/// A value stored in the trie.
+enum StoredValue<'a, H> {
+	Inline(&'a [u8]),
+	Hashed(H),
+}
+
+impl<L: TrieLayout> Cursor<L> {
+	/// Whether the subtries under both cursors are identical.
+	fn same_as(&self, other: &Self) -> bool {
+		self.offset == other.offset &&
+			match (&self.node.hash, &other.node.hash) {
+				(Some(a), Some(b)) => a == b,
+				(None, None) => self.node.data == other.node.data,
+				_ => false,
+			}
+	}
+
+	fn partial_len(&self) -> usize {
+		match &self.node.plan {
+			NodePlan::Leaf { partial, .. } |
+			NodePlan::Extension { partial, .. } |
+			NodePlan::NibbledBranch { partial, .. } => partial.len(),
+			NodePlan::Empty | NodePlan::Branch { .. } => 0,
+		}
+	}
+
+	/// Whether the whole partial key of the node has been walked.
+	fn at_node_key(&self) -> bool {
+		self.offset == self.partial_len()
+	}
+
+	/// Value stored at the current position.
+	fn value(&self) -> Option<StoredValue<'_, TrieHash<L>>> {
+		if !self.at_node_key() {
+			return None
+		}
+		let data = &self.node.data;
+		match self.node.plan.value_plan()? {
+			ValuePlan::Inline(range) => Some(StoredValue::Inline(&data[range.clone()])),
+			ValuePlan::Node(range) =>
+				Some(StoredValue::Hashed(decode_hash::<L>(&data[range.clone()]))),
+		}
+	}
+
+	/// The next nibble of the partial key, if the node key has not been walked entirely.
+	fn next_partial_nibble(&self) -> Option<u8> {
+		let data = &self.node.data;
+		match &self.node.plan {
+			NodePlan::Leaf { partial, .. } |
+			NodePlan::Extension { partial, .. } |
+			NodePlan::NibbledBranch { partial, .. }
+				if self.offset < partial.len() =>
+				Some(partial.build(data).at(self.offset)),
+			_ => None,
+		}
+	}
+
+	/// Handle of the child at `nibble`, if the node key has been walked entirely.
+	fn child_handle(&self, nibble: u8) -> Option<&NodeHandlePlan> {
+		match &self.node.plan {
+			NodePlan::Branch { children, .. } | NodePlan::NibbledBranch { children, .. }
+				if self.at_node_key() =>
+				children[nibble as usize].as_ref(),
+			_ => None,
+		}
+	}
+}
+
+struct Walker<'a, L: TrieLayout, F> {
+	dbs: [&'a dyn HashDBRef<L::Hash, DBValue>; 2],
+	/// Nibbles of the compared prefix.
+	prefix: Vec<u8>,
+	/// Nibbles of the current key.
+	key: Vec<u8>,
+	on_entry: F,
+	stopped: bool,
+}
+
+impl<'a, L, F> Walker<'a, L, F>
+where
+	L: TrieLayout,
+	F: FnMut(DiffEntry) -> bool,
+{
+	fn root(&self, side: Side, root: &TrieHash<L>) -> Result<Option<Cursor<L>>, Box<TrieError<L>>> {
+		let data = if *root == L::Codec::hashed_null_node() {
+			L::Codec::empty_node().to_vec()
+		} else {
+			self.dbs[side as usize]
+				.get(root, EMPTY_PREFIX)
+				.ok_or_else(|| Box::new(TrieError::<L>::InvalidStateRoot(*root)))?
+		};
+		self.normalize(side, Some(decode::<L>(Some(*root), data, *root)?))
+	}
+
+	/// Compare the subtries under the two cursors, positioned at `self.key`.
+	fn walk(&mut self, [a, b]: [Option<Cursor<L>>; 2]) -> Result<(), Box<TrieError<L>>> {
+		match (&a, &b) {
+			(None, None) => return Ok(()),
+			(Some(a), Some(b)) if a.same_as(b) => return Ok(()),
+			_ => (),
+		}
+
+		let depth = self.key.len();
+		if depth >= self.prefix.len() && depth % nibble_ops::NIBBLE_PER_BYTE == 0 {
+			self.compare_values(a.as_ref(), b.as_ref())?;
+			if self.stopped {
+				return Ok(())
+			}
+		}
+
+		for nibble in 0..nibble_ops::NIBBLE_LENGTH as u8 {
+			if matches!(self.prefix.get(depth), Some(expected) if *expected != nibble) {
+				continue
+			}
+			self.key.push(nibble);
+			let result = self.child(Side::A, a.as_ref(), nibble).and_then(|child_a| {
+				let child_b = self.child(Side::B, b.as_ref(), nibble)?;
+				self.walk([child_a, child_b])
+			});
+			self.key.pop();
+			result?;
+			if self.stopped {
+				break
+			}
+		}
+		Ok(())
+	}
+
+	/// Cursor following `cursor` at `nibble`, which has already been pushed to `self.key`.
+	fn child(
+		&self,
+		side: Side,
+		cursor: Option<&Cursor<L>>,
+		nibble: u8,
+	) -> Result<Option<Cursor<L>>, Box<TrieError<L>>> {
+		let cursor = match cursor {
+			Some(cursor) => cursor,
+			None => return Ok(None),
+		};
+		if let Some(next) = cursor.next_partial_nibble() {
+			return Ok((next == nibble)
+				.then(|| Cursor { node: cursor.node.clone(), offset: cursor.offset + 1 }))
+		}
+		match cursor.child_handle(nibble) {
+			Some(handle) => {
+				let child = self.resolve(side, cursor, handle)?;
+				self.normalize(side, Some(child))
+			},
+			None => Ok(None),
+		}
+	}
+
+	/// Skip the empty nodes and the extension nodes whose partial key has been walked.
+	fn normalize(
+		&self,
+		side: Side,
+		mut cursor: Option<Cursor<L>>,
+	) -> Result<Option<Cursor<L>>, Box<TrieError<L>>> {
+		while let Some(current) = cursor.take() {
+			match &current.node.plan {
+				NodePlan::Empty => return Ok(None),
+				NodePlan::Extension { child, .. } if current.at_node_key() =>
+					cursor = Some(self.resolve(side, &current, child)?),
+				_ => return Ok(Some(current)),
+			}
+		}
+		Ok(None)
+	}
+
+	/// Read the child node of `parent` referenced by `handle`.
+	fn resolve(
+		&self,
+		side: Side,
+		parent: &Cursor<L>,
+		handle: &NodeHandlePlan,
+	) -> Result<Cursor<L>, Box<TrieError<L>>> {
+		// Hash of the closest hashed ancestor, to report the decoding errors of inline nodes.
+		let context = parent.node.hash.unwrap_or_default();
+		match handle {
+			NodeHandlePlan::Hash(range) => {
+				let hash = decode_hash::<L>(&parent.node.data[range.clone()]);
+				let data = self.fetch(side, &hash)?;
+				decode::<L>(Some(hash), data, hash)
+			},
+			NodeHandlePlan::Inline(range) =>
+				decode::<L>(None, parent.node.data[range.clone()].to_vec(), context),
+		}
+	}
+
+	/// Read the node or value with hash `hash`, stored at `self.key`.
+	fn fetch(&self, side: Side, hash: &TrieHash<L>) -> Result<Vec<u8>, Box<TrieError<L>>> {
+		let (bytes, last) = self.key_prefix();
+		self.dbs[side as usize]
+			.get(hash, (&bytes, last))
+			.ok_or_else(|| Box::new(TrieError::<L>::IncompleteDatabase(*hash)))
+	}
+
+	fn load(
+		&self,
+		side: Side,
+		value: StoredValue<'_, TrieHash<L>>,
+	) -> Result<Vec<u8>, Box<TrieError<L>>> {
+		match value {
+			StoredValue::Inline(data) => Ok(data.to_vec()),
+			StoredValue::Hashed(hash) => self.fetch(side, &hash),
+		}
+	}
+
+	/// Report the difference between the values at the current key, if any.
+	fn compare_values(
+		&mut self,
+		a: Option<&Cursor<L>>,
+		b: Option<&Cursor<L>>,
+	) -> Result<(), Box<TrieError<L>>> {
+		let key = || self.key.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect();
+		let entry = match (a.and_then(Cursor::value), b.and_then(Cursor::value)) {
+			(None, None) => return Ok(()),
+			(Some(_), None) => DiffEntry::Removed { key: key() },
+			(None, Some(value)) =>
+				DiffEntry::Added { key: key(), value: self.load(Side::B, value)? },
+			(Some(StoredValue::Inline(a)), Some(StoredValue::Inline(b))) if a == b => return Ok(()),
+			(Some(StoredValue::Hashed(a)), Some(StoredValue::Hashed(b))) if a == b => return Ok(()),
+			(Some(a), Some(b)) => {
+				let value = self.load(Side::B, b)?;
+				if self.load(Side::A, a)? == value {
+					return Ok(())
+				}
+				DiffEntry::Modified { key: key(), value }
+			},
+		};
+		if !(self.on_entry)(entry) {
+			self.stopped = true;
+		}
+		Ok(())
+	}
+
+	/// Database prefix of the nodes and values stored at `self.key`.
+	fn key_prefix(&self) -> (Vec<u8>, Option<u8>) {
+		let mut pairs = self.key.chunks_exact(2);
+		let bytes = pairs.by_ref().map(|pair| pair[0] << 4 | pair[1]).collect();
+		(bytes, pairs.remainder().first().map(|nibble| nibble << 4))
+	}
+}
+
+fn decode_hash<L: TrieLayout>(data: &[u8]) -> TrieHash<L> {
+	let mut hash = TrieHash::<L>::default();
+	hash.as_mut().copy_from_slice(data);
+	hash
+}
+
+fn decode<L: TrieLayout>(
+	hash: Option<TrieHash<L>>,
+	data: Vec<u8>,
+	context: TrieHash<L>,
+) -> Result<Cursor<L>, Box<TrieError<L>>> {
+	let plan = L::Codec::decode_plan(&data)
+		.map_err(|e| Box::new(TrieError::<L>::DecoderError(context, e)))?;
+	Ok(Cursor { node: Rc::new(Node { hash, data, plan }), offset: 0 })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{LayoutV0, LayoutV1, PrefixedMemoryDB, TrieDBMutBuilder, TrieMut};
+	use sp_core::Blake2Hasher;
+	use std::collections::BTreeMap;
+
+	type Layout = LayoutV1<Blake2Hasher>;
+
+	fn build<L: TrieLayout<Hash = Blake2Hasher>>(
+		db: &mut PrefixedMemoryDB<Blake2Hasher>,
+		entries: &BTreeMap<Vec<u8>, Vec<u8>>,
+	) -> TrieHash<L> {
+		let mut root = Default::default();
+		{
+			let mut trie = TrieDBMutBuilder::<L>::new(db, &mut root).build();
+			for (key, value) in entries {
+				trie.insert(key, value).unwrap();
+			}
+		}
+		root
+	}
+
+	fn entries(seed: u8, count: u8) -> BTreeMap<Vec<u8>, Vec<u8>> {
+		(0..count)
+			.map(|i| {
+				let key = vec![i % 7, i.wrapping_mul(31), i];
+				let value = vec![seed.wrapping_add(i % 3); 1 + (i as usize % 5) * 10];
+				(key, value)
+			})
+			.collect()
+	}
+
+	fn expected(
+		a: &BTreeMap<Vec<u8>, Vec<u8>>,
+		b: &BTreeMap<Vec<u8>, Vec<u8>>,
+		prefix: &[u8],
+	) -> Vec<DiffEntry> {
+		let keys: std::collections::BTreeSet<_> = a.keys().chain(b.keys()).collect();
+		keys.into_iter()
+			.filter(|key| key.starts_with(prefix))
+			.filter_map(|key| match (a.get(key), b.get(key)) {
+				(Some(_), None) => Some(DiffEntry::Removed { key: key.clone() }),
+				(None, Some(value)) =>
+					Some(DiffEntry::Added { key: key.clone(), value: value.clone() }),
+				(Some(old), Some(new)) if old != new =>
+					Some(DiffEntry::Modified { key: key.clone(), value: new.clone() }),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn compute(
+		db: &PrefixedMemoryDB<Blake2Hasher>,
+		root_a: &TrieHash<Layout>,
+		root_b: &TrieHash<Layout>,
+		prefix: &[u8],
+	) -> Vec<DiffEntry> {
+		let mut result = Vec::new();
+		diff::<Layout, _>(db, root_a, db, root_b, prefix, |entry| {
+			result.push(entry);
+			true
+		})
+		.unwrap();
+		result
+	}
+
+	#[test]
+	fn diff_matches_comparison_of_entries() {
+		let mut db = PrefixedMemoryDB::default();
+		let a = entries(0, 200);
+		let mut b = entries(1, 150);
+		b.extend(entries(0, 200).into_iter().filter(|(key, _)| key[2] % 4 == 0));
+		b.insert(vec![1, 2], vec![3]);
+		b.insert(vec![1], vec![4; 40]);
+		let root_a = build::<Layout>(&mut db, &a);
+		let root_b = build::<Layout>(&mut db, &b);
+		let empty = build::<Layout>(&mut db, &BTreeMap::new());
+
+		for prefix in [&[][..], &[1], &[1, 2], &[3, 93], &[8]] {
+			assert_eq!(compute(&db, &root_a, &root_b, prefix), expected(&a, &b, prefix));
+			assert_eq!(compute(&db, &root_b, &root_a, prefix), expected(&b, &a, prefix));
+			assert_eq!(
+				compute(&db, &empty, &root_b, prefix),
+				expected(&BTreeMap::new(), &b, prefix)
+			);
+		}
+		assert!(compute(&db, &root_a, &root_a, &[]).is_empty());
+	}
+
+	#[test]
+	fn diff_ignores_change_of_value_storage() {
+		let mut db = PrefixedMemoryDB::default();
+		let a = entries(0, 50);
+		let root_a = build::<LayoutV0<Blake2Hasher>>(&mut db, &a);
+		let root_b = build::<Layout>(&mut db, &a);
+		assert_ne!(root_a, root_b);
+		assert!(compute(&db, &root_a, &root_b, &[]).is_empty());
+	}
+
+	#[test]
+	fn diff_stops_when_asked() {
+		let mut db = PrefixedMemoryDB::default();
+		let root_a = build::<Layout>(&mut db, &BTreeMap::new());
+		let root_b = build::<Layout>(&mut db, &entries(0, 50));
+		let mut count = 0;
+		diff::<Layout, _>(&db, &root_a, &db, &root_b, &[], |_| {
+			count += 1;
+			count < 3
+		})
+		.unwrap();
+		assert_eq!(count, 3);
+	}
+
+	#[test]
+	fn diff_reports_missing_nodes() {
+		let mut db = PrefixedMemoryDB::default();
+		let root_a = build::<Layout>(&mut db, &entries(0, 50));
+		let root_b = build::<Layout>(&mut PrefixedMemoryDB::default(), &entries(1, 50));
+		assert!(matches!(
+			*diff::<Layout, _>(&db, &root_a, &db, &root_b, &[], |_| true).unwrap_err(),
+			TrieError::<Layout>::InvalidStateRoot(root) if root == root_b,
+		));
+	}
+}
@@ -21,6 +21,7 @@
 
 #[cfg(feature = "std")]
 pub mod cache;
+mod diff;
 mod error;
 mod node_codec;
 mod node_header;
@@ -30,6 +31,8 @@ mod storage_proof;
 mod trie_codec;
 mod trie_stream;
 
+/// Difference between two tries.
+pub use diff::{diff, DiffEntry};
 /// Our `NodeCodec`-specific error.
 pub use error::Error;
 /// Various re-exports from the `hash-db` crate.
@@ -105,6 +105,18 @@ impl<Block: BlockT, ExecutorDispatch, G: GenesisInit>
 		let backend = Arc::new(Backend::new_test_with_tx_storage(blocks_pruning, 0));
 		Self::with_backend(backend)
 	}
+
+	/// Create new `TestClientBuilder` with default backend indexing the storage changes of blocks
+	pub fn with_changes_index() -> Self {
+		let backend = Arc::new(Backend::new_test_with_changes_index(std::u32::MAX, std::u64::MAX));
+		Self::with_backend(backend)
+	}
+
+	/// Create new `TestClientBuilder` with a backend keeping all blocks and states
+	pub fn with_archive() -> Self {
+		let backend = Arc::new(Backend::new_test_archive(std::u64::MAX));
+		Self::with_backend(backend)
+	}
 }
 
 impl<Block: BlockT, ExecutorDispatch, Backend, G: GenesisInit>
@@ -255,7 +267,8 @@ impl<Block: BlockT, D, Backend, G: GenesisInit>
 		client::LocalCallExecutor<Block, Backend, NativeElseWasmExecutor<D>>,
 		Backend,
 		G,
-	> where
+	>
+where
 	D: sc_executor::NativeExecutionDispatch,
 {
 	/// Build the test client with the given native executor.
@@ -296,7 +309,7 @@ pub struct RpcTransactionOutput {
 	/// The output string of the transaction if any.
 	pub result: String,
 	/// An async receiver if data will be returned via a callback.
-	pub receiver: futures::channel::mpsc::UnboundedReceiver<String>,
+	pub receiver: tokio::sync::mpsc::Receiver<Box<serde_json::value::RawValue>>,
 }
 
 impl std::fmt::Debug for RpcTransactionOutput {
@@ -356,7 +369,7 @@ impl RpcHandlersExt for RpcHandlers {
 
 pub(crate) fn parse_rpc_result(
 	result: String,
-	receiver: futures::channel::mpsc::UnboundedReceiver<String>,
+	receiver: tokio::sync::mpsc::Receiver<Box<serde_json::value::RawValue>>,
 ) -> Result<RpcTransactionOutput, RpcTransactionError> {
 	let json: serde_json::Value =
 		serde_json::from_str(&result).expect("the result can only be a JSONRPC string; qed");
@@ -410,7 +423,7 @@ where
 mod tests {
 	#[test]
 	fn parses_error_properly() {
-		let (_, rx) = futures::channel::mpsc::unbounded();
+		let (_, rx) = tokio::sync::mpsc::channel(1);
 		assert!(super::parse_rpc_result(
 			r#"{
 				"jsonrpc": "2.0",
@@ -422,7 +435,7 @@ mod tests {
 		)
 		.is_ok());
 
-		let (_, rx) = futures::channel::mpsc::unbounded();
+		let (_, rx) = tokio::sync::mpsc::channel(1);
 		let error = super::parse_rpc_result(
 			r#"{
 				"jsonrpc": "2.0",
@@ -440,7 +453,7 @@ mod tests {
 		assert_eq!(error.message, "Method not found");
 		assert!(error.data.is_none());
 
-		let (_, rx) = futures::channel::mpsc::unbounded();
+		let (_, rx) = tokio::sync::mpsc::channel(1);
 		let error = super::parse_rpc_result(
 			r#"{
 				"jsonrpc": "2.0",
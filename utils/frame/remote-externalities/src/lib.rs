@@ -23,7 +23,10 @@
 use codec::{Decode, Encode};
 
 use jsonrpsee::{
-	core::{client::ClientT, Error as RpcError},
+	core::{
+		client::{ClientT, Error as RpcError},
+		params::BatchRequestBuilder,
+	},
 	proc_macros::rpc,
 	rpc_params,
 	ws_client::{WsClient, WsClientBuilder},
@@ -92,6 +95,19 @@ pub trait RpcApi<Hash> {
 	fn finalized_head(&self) -> Result<Hash, RpcError>;
 }
 
+/// Sends `batch` and returns the results of its calls, in order, or the first error.
+async fn batch_request<R: DeserializeOwned + std::fmt::Debug>(
+	client: &WsClient,
+	batch: BatchRequestBuilder<'_>,
+) -> Result<Vec<R>, RpcError> {
+	client
+		.batch_request::<R>(batch)
+		.await?
+		.into_iter()
+		.map(|result| result.map_err(|e| RpcError::Call(e.into_owned())))
+		.collect()
+}
+
 /// The execution mode.
 #[derive(Clone)]
 pub enum Mode<B: BlockT> {
@@ -141,7 +157,7 @@ impl Transport {
 			log::debug!(target: LOG_TARGET, "initializing remote client to {:?}", uri);
 
 			let ws_client = WsClientBuilder::default()
-				.max_request_body_size(u32::MAX)
+				.max_request_size(u32::MAX)
 				.build(&uri)
 				.await
 				.map_err(|e| {
@@ -370,21 +386,23 @@ impl<B: BlockT + DeserializeOwned> Builder<B> {
 		let mut key_values: Vec<KeyValue> = vec![];
 		let client = self.as_online().rpc_client();
 		for chunk_keys in keys.chunks(BATCH_SIZE) {
-			let batch = chunk_keys
-				.iter()
-				.cloned()
-				.map(|key| ("state_getStorage", rpc_params![key, at]))
-				.collect::<Vec<_>>();
-
-			let values = client.batch_request::<Option<StorageData>>(batch).await.map_err(|e| {
-				log::error!(
-					target: LOG_TARGET,
-					"failed to execute batch: {:?}. Error: {:?}",
-					chunk_keys.iter().map(HexDisplay::from).collect::<Vec<_>>(),
-					e
-				);
-				"batch failed."
-			})?;
+			let mut batch = BatchRequestBuilder::new();
+			for key in chunk_keys {
+				batch
+					.insert("state_getStorage", rpc_params![key, at])
+					.map_err(|_| "invalid batch params.")?;
+			}
+
+			let values =
+				batch_request::<Option<StorageData>>(client, batch).await.map_err(|e| {
+					log::error!(
+						target: LOG_TARGET,
+						"failed to execute batch: {:?}. Error: {:?}",
+						chunk_keys.iter().map(HexDisplay::from).collect::<Vec<_>>(),
+						e
+					);
+					"batch failed."
+				})?;
 
 			assert_eq!(chunk_keys.len(), values.len());
 
@@ -420,11 +438,10 @@ impl<B: BlockT + DeserializeOwned> Builder<B> {
 	) -> Result<Vec<KeyValue>, &'static str> {
 		let mut child_kv_inner = vec![];
 		for batch_child_key in child_keys.chunks(BATCH_SIZE) {
-			let batch_request = batch_child_key
-				.iter()
-				.cloned()
-				.map(|key| {
-					(
+			let mut batch = BatchRequestBuilder::new();
+			for key in batch_child_key {
+				batch
+					.insert(
 						"childstate_getStorage",
 						rpc_params![
 							PrefixedStorageKey::new(prefixed_top_key.as_ref().to_vec()),
@@ -432,23 +449,21 @@ impl<B: BlockT + DeserializeOwned> Builder<B> {
 							at
 						],
 					)
-				})
-				.collect::<Vec<_>>();
+					.map_err(|_| "invalid batch params.")?;
+			}
 
-			let batch_response = self
-				.as_online()
-				.rpc_client()
-				.batch_request::<Option<StorageData>>(batch_request)
-				.await
-				.map_err(|e| {
-					log::error!(
-						target: LOG_TARGET,
-						"failed to execute batch: {:?}. Error: {:?}",
-						batch_child_key,
-						e
-					);
-					"batch failed."
-				})?;
+			let batch_response =
+				batch_request::<Option<StorageData>>(self.as_online().rpc_client(), batch)
+					.await
+					.map_err(|e| {
+						log::error!(
+							target: LOG_TARGET,
+							"failed to execute batch: {:?}. Error: {:?}",
+							batch_child_key,
+							e
+						);
+						"batch failed."
+					})?;
 
 			assert_eq!(batch_child_key.len(), batch_response.len());
 
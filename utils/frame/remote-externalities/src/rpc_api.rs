@@ -38,7 +38,7 @@ where
 	let client = build_client(from).await?;
 
 	client
-		.request::<Block::Header>("chain_getHeader", rpc_params!(at))
+		.request::<Block::Header, _>("chain_getHeader", rpc_params!(at))
 		.await
 		.map_err(|e| format!("chain_getHeader request failed: {:?}", e))
 }
@@ -52,7 +52,7 @@ where
 	let client = build_client(from).await?;
 
 	client
-		.request::<Block::Hash>("chain_getFinalizedHead", None)
+		.request::<Block::Hash, _>("chain_getFinalizedHead", rpc_params!())
 		.await
 		.map_err(|e| format!("chain_getFinalizedHead request failed: {:?}", e))
 }
@@ -66,7 +66,7 @@ where
 {
 	let client = build_client(from).await?;
 	let signed_block = client
-		.request::<SignedBlock<Block>>("chain_getBlock", rpc_params!(at))
+		.request::<SignedBlock<Block>, _>("chain_getBlock", rpc_params!(at))
 		.await
 		.map_err(|e| format!("chain_getBlock request failed: {:?}", e))?;
 
@@ -76,7 +76,7 @@ where
 /// Build a websocket client that connects to `from`.
 async fn build_client<S: AsRef<str>>(from: S) -> Result<WsClient, String> {
 	WsClientBuilder::default()
-		.max_request_body_size(u32::MAX)
+		.max_request_size(u32::MAX)
 		.build(from.as_ref())
 		.await
 		.map_err(|e| format!("`WsClientBuilder` failed to build: {:?}", e))
@@ -94,7 +94,7 @@ where
 {
 	let client = build_client(from).await?;
 	client
-		.request::<sp_version::RuntimeVersion>("state_getRuntimeVersion", rpc_params!(at))
+		.request::<sp_version::RuntimeVersion, _>("state_getRuntimeVersion", rpc_params!(at))
 		.await
 		.map_err(|e| format!("state_getRuntimeVersion request failed: {:?}", e))
 }
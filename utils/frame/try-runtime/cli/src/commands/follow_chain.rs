@@ -21,6 +21,7 @@ use crate::{
 };
 use jsonrpsee::{
 	core::client::{Subscription, SubscriptionClientT},
+	rpc_params,
 	ws_client::WsClientBuilder,
 };
 use parity_scale_codec::Decode;
@@ -60,15 +61,15 @@ where
 
 	let client = WsClientBuilder::default()
 		.connection_timeout(std::time::Duration::new(20, 0))
-		.max_notifs_per_subscription(1024)
-		.max_request_body_size(u32::MAX)
+		.max_buffer_capacity_per_subscription(1024)
+		.max_request_size(u32::MAX)
 		.build(&command.uri)
 		.await
 		.unwrap();
 
 	log::info!(target: LOG_TARGET, "subscribing to {:?} / {:?}", SUB, UN_SUB);
 	let mut subscription: Subscription<Block::Header> =
-		client.subscribe(SUB, None, UN_SUB).await.unwrap();
+		client.subscribe(SUB, rpc_params!(), UN_SUB).await.unwrap();
 
 	let (code_key, code) = extract_code(&config.chain_spec)?;
 	let executor = build_executor::<ExecDispatch>(&shared, &config);